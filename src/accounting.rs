@@ -0,0 +1,163 @@
+// src/accounting.rs — How long the box spends in each state
+//
+// The daemon adds up the time it's been in each state, e.g. how many
+// hours a day it sits Away with Sunshine waiting for a client. The totals
+// go to `$XDG_STATE_HOME/vitamink/times.json`, next to state.json, every
+// few minutes and on the way out, so a crash loses no more than the last
+// few minutes. They're shown by `vitamink status`, are in
+// status.json as `time_in_state` (seconds), and the metrics endpoint has
+// them as `vitamink_state_seconds_total`.
+//
+// Once a day, at the first poll after midnight, the day before is logged:
+//
+//   2026-10-15: Away 14h2m, AtDesk 9h58m
+//
+// Time is counted in whole seconds, and the time a poll or transition
+// covers goes to the day it ends on.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::daemon::State;
+use crate::duration;
+use crate::error::Result;
+use crate::logging;
+use crate::persist;
+use crate::process;
+use crate::status;
+
+// How often the totals are written out.
+const SAVE_EVERY: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Totals {
+    // Seconds in each state, ever.
+    pub total: BTreeMap<State, u64>,
+    // The same for `day` alone, for its summary.
+    #[serde(default)]
+    pub today: BTreeMap<State, u64>,
+    // "2026-10-16", local time.
+    #[serde(default)]
+    pub day: String,
+}
+
+impl Totals {
+    // Counts `seconds` in `state` on `day`. If that's a new day, the one
+    // before is returned first, with its date.
+    pub fn add(&mut self, state: State, seconds: u64, day: &str) -> Option<(String, BTreeMap<State, u64>)> {
+        let finished = if self.day != day {
+            let previous = std::mem::replace(&mut self.day, day.to_string());
+            let today = std::mem::take(&mut self.today);
+            Some((previous, today)).filter(|(previous, today)| !previous.is_empty() && !today.is_empty())
+        } else {
+            None
+        };
+        *self.total.entry(state).or_default() += seconds;
+        *self.today.entry(state).or_default() += seconds;
+        finished
+    }
+}
+
+// "Away 14h2m, AtDesk 9h58m": the longest first, to the minute.
+pub fn summary(times: &BTreeMap<State, u64>) -> String {
+    let mut times: Vec<(&State, &u64)> = times.iter().filter(|&(_, &seconds)| seconds >= 60).collect();
+    times.sort_by_key(|&(_, &seconds)| std::cmp::Reverse(seconds));
+    let times: Vec<String> =
+        times.into_iter().map(|(state, seconds)| format!("{state} {}", duration::format(Duration::from_secs(seconds / 60 * 60)))).collect();
+    if times.is_empty() { "under a minute in any state".to_string() } else { times.join(", ") }
+}
+
+// The daemon's running count.
+pub struct Accounting {
+    totals: Totals,
+    // Up to when time has been counted.
+    counted: Instant,
+    // Time that didn't make a whole second yet.
+    carry: Duration,
+    saved: Instant,
+}
+
+impl Accounting {
+    // Picks up the totals from the last run, if there are any.
+    pub fn load() -> Self {
+        let totals = match persist::read_checked(&path()) {
+            Ok(totals) => totals.unwrap_or_default(),
+            Err(e) => {
+                warn!("Couldn't read the time spent in each state: {e}");
+                Totals::default()
+            }
+        };
+        let now = Instant::now();
+        Self { totals, counted: now, carry: Duration::ZERO, saved: now }
+    }
+
+    // Counts the time since the last call as spent in `state`, logs the
+    // day before once a new one has begun, and saves now and then.
+    pub fn count(&mut self, state: State) {
+        let now = Instant::now();
+        let elapsed = now.saturating_duration_since(self.counted) + self.carry;
+        self.counted = now;
+        self.carry = Duration::from_nanos(elapsed.subsec_nanos().into());
+        let day = logging::format_local(status::unix_now() as libc::time_t)[..10].to_string();
+        if let Some((day, times)) = self.totals.add(state, elapsed.as_secs(), &day) {
+            info!("{day}: {}", summary(&times));
+        }
+        if now.duration_since(self.saved) >= SAVE_EVERY {
+            self.save();
+        }
+    }
+
+    pub fn total(&self) -> &BTreeMap<State, u64> {
+        &self.totals.total
+    }
+
+    // Not in dry-run mode, like state.json.
+    pub fn save(&mut self) {
+        self.saved = Instant::now();
+        if process::is_dry_run() {
+            return;
+        }
+        if let Err(e) = save(&self.totals) {
+            warn!("Couldn't save the time spent in each state: {e}");
+        }
+    }
+}
+
+// Next to state.json.
+pub fn path() -> PathBuf {
+    persist::path().with_file_name("times.json")
+}
+
+fn save(totals: &Totals) -> Result<()> {
+    persist::write_checked(&path(), totals)
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add() {
+        let mut totals = Totals::default();
+        // Nothing to report for the first day seen.
+        assert_eq!(totals.add(State::AtDesk, 3_600, "2026-10-15"), None);
+        assert_eq!(totals.add(State::Away, 50_520, "2026-10-15"), None);
+        assert_eq!(totals.add(State::AtDesk, 32_280, "2026-10-15"), None);
+
+        let (day, times) = totals.add(State::Away, 60, "2026-10-16").unwrap();
+        assert_eq!(day, "2026-10-15");
+        assert_eq!(summary(&times), "Away 14h2m, AtDesk 9h58m");
+        assert_eq!(totals.today, BTreeMap::from([(State::Away, 60)]));
+        assert_eq!(totals.total, BTreeMap::from([(State::AtDesk, 35_880), (State::Away, 50_580)]));
+
+        let json = serde_json::to_string(&totals).unwrap();
+        assert_eq!(serde_json::from_str::<Totals>(&json).unwrap(), totals);
+        assert_eq!(summary(&BTreeMap::from([(State::Standby, 59)])), "under a minute in any state");
+    }
+}
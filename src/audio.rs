@@ -0,0 +1,131 @@
+// src/audio.rs — which sink plays sound in each state
+//
+// While Away, sound belongs on the stream: the default sink is switched to
+// `away_sink` (Sunshine's virtual "sink-sunshine-stereo", say), and back at
+// the desk to `desk_sink` — or, if that's unset, to whatever was the
+// default before. Streams already playing are moved along too, since
+// changing the default alone only affects new ones.
+//
+// Everything goes through `pactl`, which PipeWire understands through
+// pipewire-pulse as well as PulseAudio itself does. Sink names are the
+// ones `pactl list short sinks` prints.
+
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::AudioConfig;
+use crate::error::{Result, VitaminkError};
+use crate::process;
+
+// The sink to go back to, like privacy::Restore.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Restore {
+    previous_sink: String,
+}
+
+pub fn engage(config: &AudioConfig) -> Result<Option<Restore>> {
+    let Some(sink) = &config.away_sink else {
+        return Ok(None);
+    };
+    let previous_sink = default_sink()?;
+    switch_to(sink, config.move_streams)?;
+    Ok(Some(Restore { previous_sink }))
+}
+
+pub fn restore(config: &AudioConfig, restore: &Restore) -> Result<()> {
+    let sink = config.desk_sink.as_ref().unwrap_or(&restore.previous_sink);
+    switch_to(sink, config.move_streams)
+}
+
+fn switch_to(sink: &str, move_streams: bool) -> Result<()> {
+    let sinks = run_pactl(&["list", "short", "sinks"])?;
+    if !column(&sinks, 1).any(|name| name == sink) {
+        return Err(VitaminkError::Parse(format!("No audio sink named \"{sink}\" (see `pactl list short sinks`)")));
+    }
+
+    apply_pactl(&["set-default-sink", sink])?;
+    if move_streams {
+        let inputs = run_pactl(&["list", "short", "sink-inputs"])?;
+        for id in column(&inputs, 0) {
+            apply_pactl(&["move-sink-input", id, sink])?;
+        }
+    }
+    Ok(())
+}
+
+fn default_sink() -> Result<String> {
+    let name = run_pactl(&["get-default-sink"])?.trim().to_string();
+    if name.is_empty() {
+        return Err(VitaminkError::Parse("pactl reports no default sink".to_string()));
+    }
+    Ok(name)
+}
+
+// The `index`th tab-separated field of each line of `pactl list short`.
+fn column(table: &str, index: usize) -> impl Iterator<Item = &str> {
+    table.lines().filter_map(move |line| line.split('\t').nth(index))
+}
+
+// Queries: always run, even in dry-run mode.
+fn run_pactl(args: &[&str]) -> Result<String> {
+    let output = process::output(Command::new("pactl").args(args))?;
+    if !output.status.success() {
+        return Err(VitaminkError::command_failed(format!("pactl {}", args.join(" ")), &output));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+// Changes: only logged in dry-run mode.
+fn apply_pactl(args: &[&str]) -> Result<()> {
+    let output = process::run(Command::new("pactl").args(args))?;
+    if !output.status.success() {
+        return Err(VitaminkError::command_failed(format!("pactl {}", args.join(" ")), &output));
+    }
+    Ok(())
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::mock::FakeRunner;
+
+    const SINKS: &str = "\
+55\talsa_output.usb-Schiit_Modi-00.analog-stereo\tPipeWire\ts24le 2ch 48000Hz\tRUNNING
+71\tsink-sunshine-stereo\tPipeWire\tfloat32le 2ch 48000Hz\tSUSPENDED
+";
+
+    #[test]
+    fn test_engage_and_restore() {
+        let config = AudioConfig { away_sink: Some("sink-sunshine-stereo".into()), desk_sink: None, move_streams: true };
+        let fake = Rc::new(FakeRunner::new());
+        fake.respond("pactl", 0, "");
+        fake.respond("pactl get-default-sink", 0, "alsa_output.usb-Schiit_Modi-00.analog-stereo\n");
+        fake.respond("pactl list short sinks", 0, SINKS);
+        fake.respond("pactl list short sink-inputs", 0, "102\t55\t101\tPipeWire\tfloat32le 2ch 48000Hz\n");
+
+        let restore = process::with_runner(fake.clone(), || engage(&config)).unwrap().unwrap();
+        assert_eq!(restore.previous_sink, "alsa_output.usb-Schiit_Modi-00.analog-stereo");
+        assert!(fake.calls().contains(&"pactl set-default-sink sink-sunshine-stereo".to_string()));
+        assert!(fake.calls().contains(&"pactl move-sink-input 102 sink-sunshine-stereo".to_string()));
+
+        process::with_runner(fake.clone(), || super::restore(&config, &restore)).unwrap();
+        assert_eq!(fake.calls().last().unwrap(), "pactl move-sink-input 102 alsa_output.usb-Schiit_Modi-00.analog-stereo");
+    }
+
+    #[test]
+    fn test_missing_sink() {
+        let config = AudioConfig { away_sink: Some("sink-sunshine-71".into()), desk_sink: None, move_streams: false };
+        let fake = Rc::new(FakeRunner::new());
+        fake.respond("pactl get-default-sink", 0, "alsa_output.usb-Schiit_Modi-00.analog-stereo\n");
+        fake.respond("pactl list short sinks", 0, SINKS);
+
+        let error = process::with_runner(fake.clone(), || engage(&config)).unwrap_err();
+        assert!(error.to_string().contains("sink-sunshine-71"));
+        assert!(!fake.calls().iter().any(|call| call.starts_with("pactl set-default-sink")));
+    }
+}
@@ -0,0 +1,104 @@
+// src/bluetooth.rs — Presence from a phone or watch in Bluetooth range
+//
+// For desks where the monitor never sleeps, the devices you carry can say
+// whether you're there:
+//
+//   presence_backend = "bluetooth"      # or, combined with DPMS:
+//   away_when = "dpms_off or bluetooth_away"
+//
+//   [bluetooth]
+//   devices = ["AA:BB:CC:DD:EE:FF"]
+//   min_rssi = -70
+//
+// Each device is looked up in BlueZ over the system bus (no scanning of
+// our own, so it must be paired). It counts as near while it's
+// connected; with `min_rssi`, a signal strength BlueZ has seen recently
+// (while discovering) decides instead, so a phone that's connected from
+// the next room doesn't count and a watch that only advertises does.
+// Away once none of the devices is near; a device BlueZ doesn't know
+// about is unknown, like an unreadable DPMS file.
+
+use std::collections::HashMap;
+
+use log::debug;
+use zbus::blocking::Connection;
+use zbus::zvariant::OwnedValue;
+
+use crate::config::BluetoothConfig;
+use crate::dbus;
+use crate::error::{Result, VitaminkError};
+use crate::presence::Aggregation;
+
+const BLUEZ: &str = "org.bluez";
+const DEVICE: &str = "org.bluez.Device1";
+
+// Some(true) if none of the devices is near, None if the unknown ones
+// would decide it (or BlueZ can't be reached).
+pub fn absent(config: &BluetoothConfig) -> Option<bool> {
+    let conn = Connection::system().inspect_err(|e| debug!("Bluetooth: no system bus: {e}")).ok()?;
+    let away = config.devices.iter().map(|address| match read(&conn, &config.adapter, address) {
+        Ok((connected, rssi)) => Some(!near(connected, rssi, config.min_rssi)),
+        Err(e) => {
+            debug!("Bluetooth: {address}: {e}");
+            None
+        }
+    });
+    // All of them gone, like every main display being off.
+    Aggregation::AllOff.combine(away)
+}
+
+// BlueZ's object path for `address` on `adapter`.
+pub fn device_path(adapter: &str, address: &str) -> String {
+    format!("/org/bluez/{adapter}/dev_{}", address.to_uppercase().replace(':', "_"))
+}
+
+// "AA:BB:CC:DD:EE:FF".
+pub fn is_address(text: &str) -> bool {
+    let parts: Vec<&str> = text.split(':').collect();
+    parts.len() == 6 && parts.iter().all(|p| p.len() == 2 && p.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+// (Connected, RSSI if BlueZ has one).
+fn read(conn: &Connection, adapter: &str, address: &str) -> Result<(bool, Option<i16>)> {
+    let properties: HashMap<String, OwnedValue> =
+        dbus::call(conn, BLUEZ, &device_path(adapter, address), "org.freedesktop.DBus.Properties", "GetAll", &(DEVICE,))?;
+    let connected = properties
+        .get("Connected")
+        .ok_or_else(|| VitaminkError::Parse("BlueZ reports no Connected property".to_string()))
+        .and_then(|v| bool::try_from(v).map_err(|e| VitaminkError::Parse(format!("Unexpected Connected property: {e}"))))?;
+    let rssi = properties.get("RSSI").and_then(|v| i16::try_from(v).ok());
+    Ok((connected, rssi))
+}
+
+fn near(connected: bool, rssi: Option<i16>, min_rssi: Option<i16>) -> bool {
+    match (rssi, min_rssi) {
+        (Some(rssi), Some(min)) => rssi >= min,
+        _ => connected,
+    }
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_near() {
+        assert!(near(true, None, None));
+        assert!(!near(false, None, Some(-70)));
+        // Connected, but from the next room.
+        assert!(!near(true, Some(-85), Some(-70)));
+        // Only advertising, but close.
+        assert!(near(false, Some(-60), Some(-70)));
+        assert!(near(true, Some(-85), None));
+    }
+
+    #[test]
+    fn test_addresses() {
+        assert_eq!(device_path("hci0", "aa:bb:cc:dd:ee:ff"), "/org/bluez/hci0/dev_AA_BB_CC_DD_EE_FF");
+        assert!(is_address("AA:BB:CC:DD:EE:FF"));
+        assert!(!is_address("AA:BB:CC:DD:EE"));
+        assert!(!is_address("AA:BB:CC:DD:EE:GG"));
+    }
+}
@@ -0,0 +1,177 @@
+// src/camera.rs — "Is anyone in front of the desk?" from a local camera
+//
+// Opt-in, and VitaminK itself never opens the camera: `[camera] command`
+// is a program *you* supply (a small OpenCV script, a Frigate/Home
+// Assistant query, ...) that looks and reports back through its exit
+// status:
+//
+//   0        someone is there
+//   1        nobody is there
+//   other    can't tell (camera busy, too dark, ...) — changes nothing
+//
+// Only that answer is used; no frames pass through VitaminK, nothing is
+// stored, and the command runs only while `presence_backend = "camera"`
+// or an `away_when` condition with `camera_empty` asks for it — at most
+// once per `interval`. If the camera has an activity LED, it will light
+// up while the helper runs; keep the helper from saving images.
+//
+// A single frame is a noisy signal (you leaned out of view, the dog walked
+// past), so readings are debounced: "nobody" has to hold for
+// `absent_after` and "someone" for `present_after` before the answer flips.
+
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{debug, warn};
+
+use crate::config::CameraConfig;
+use crate::environment;
+
+// Holds the answer steady until a new reading has lasted long enough.
+#[derive(Debug)]
+pub struct Debounce {
+    absent_after: Duration,
+    present_after: Duration,
+    // Some(true) = nobody there. None until the first reading settles.
+    current: Option<bool>,
+    // A reading that differs from `current`, and since when it's been seen.
+    pending: Option<(bool, Instant)>,
+}
+
+impl Debounce {
+    pub fn new(absent_after: Duration, present_after: Duration) -> Self {
+        Self { absent_after, present_after, current: None, pending: None }
+    }
+
+    // Feeds one reading (None = can't tell) and returns the settled answer.
+    pub fn observe(&mut self, absent: Option<bool>, now: Instant) -> Option<bool> {
+        let Some(absent) = absent else {
+            return self.current;
+        };
+        if self.current == Some(absent) {
+            self.pending = None;
+            return self.current;
+        }
+
+        let since = match self.pending {
+            Some((pending, since)) if pending == absent => since,
+            _ => {
+                self.pending = Some((absent, now));
+                now
+            }
+        };
+        let needed = if absent { self.absent_after } else { self.present_after };
+        if now.duration_since(since) >= needed {
+            self.current = Some(absent);
+            self.pending = None;
+        }
+        self.current
+    }
+}
+
+struct Camera {
+    last_run: Instant,
+    debounce: Debounce,
+}
+
+static CAMERA: Mutex<Option<Camera>> = Mutex::new(None);
+
+// Whether the camera says nobody is there, running the helper if
+// `interval` has passed. None if unconfigured or not settled yet.
+pub fn absent(config: &CameraConfig) -> Option<bool> {
+    let command = config.command.as_deref()?;
+    let mut camera = CAMERA.lock().unwrap_or_else(|e| e.into_inner());
+
+    if let Some(camera) = camera.as_ref()
+        && camera.last_run.elapsed() < config.interval
+    {
+        return camera.debounce.current;
+    }
+
+    let reading = run(command, config.timeout);
+    debug!("Camera helper: {}", match reading {
+        Some(true) => "nobody there",
+        Some(false) => "someone there",
+        None => "can't tell",
+    });
+
+    let camera = camera.get_or_insert_with(|| Camera {
+        last_run: Instant::now(),
+        debounce: Debounce::new(config.absent_after, config.present_after),
+    });
+    camera.last_run = Instant::now();
+    camera.debounce.observe(reading, Instant::now())
+}
+
+// Some(true) = nobody there, per the exit status protocol above.
+fn run(command: &str, timeout: Duration) -> Option<bool> {
+    let mut cmd = Command::new("sh");
+    cmd.args(["-c", command]);
+    environment::apply(&mut cmd);
+    let mut child = match cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::inherit())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            warn!("Camera helper failed to start: {e}");
+            return None;
+        }
+    };
+
+    let start = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                return match status.code() {
+                    Some(0) => Some(false),
+                    Some(1) => Some(true),
+                    _ => None,
+                };
+            }
+            Ok(None) if start.elapsed() < timeout => thread::sleep(Duration::from_millis(50)),
+            Ok(None) => {
+                warn!("Camera helper killed after {:.0}s", timeout.as_secs_f64());
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+            Err(e) => {
+                warn!("Camera helper: {e}");
+                return None;
+            }
+        }
+    }
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debounce() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut debounce = Debounce::new(Duration::from_secs(120), Duration::ZERO);
+
+        // "Someone" counts at once; "nobody" has to last two minutes.
+        assert_eq!(debounce.observe(Some(false), at(0)), Some(false));
+        assert_eq!(debounce.observe(Some(true), at(30)), Some(false));
+        assert_eq!(debounce.observe(None, at(60)), Some(false));
+        assert_eq!(debounce.observe(Some(true), at(120)), Some(false));
+        assert_eq!(debounce.observe(Some(true), at(150)), Some(true));
+
+        // Back for a moment: that's enough to count as present again,
+        // and the two-minute wait starts over.
+        assert_eq!(debounce.observe(Some(false), at(160)), Some(false));
+        assert_eq!(debounce.observe(Some(true), at(170)), Some(false));
+        assert_eq!(debounce.observe(Some(true), at(289)), Some(false));
+        assert_eq!(debounce.observe(Some(true), at(290)), Some(true));
+    }
+}
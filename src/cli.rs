@@ -0,0 +1,271 @@
+// src/cli.rs — The command line, described once for completions and `man`
+//
+// main.rs parses arguments by hand; this is the list of what it takes,
+// for the two things that need it as data:
+//
+//   vitamink completions bash > ~/.local/share/bash-completion/completions/vitamink
+//   vitamink completions zsh > ~/.local/share/zsh/site-functions/_vitamink
+//   vitamink completions fish > ~/.config/fish/completions/vitamink.fish
+//   vitamink man > ~/.local/share/man/man1/vitamink.1
+//
+// Packagers can run the same at build time. A command added to main.rs
+// goes here too; the test below checks they agree.
+
+use std::fmt::Write;
+use std::str::FromStr;
+
+pub struct Subcommand {
+    pub name: &'static str,
+    // What follows the name, as in a usage line.
+    pub usage: &'static str,
+    pub about: &'static str,
+    // What can come next: subcommands, and flags (the ones starting "--").
+    pub words: &'static [&'static str],
+}
+
+pub const COMMANDS: &[Subcommand] = &[
+    Subcommand {
+        name: "daemon",
+        usage: "[--dry-run] [--replace]",
+        about: "Run the polling loop. With --dry-run, changes are logged instead of made; --replace stops a daemon that's already running.",
+        words: &["--dry-run", "--replace"],
+    },
+    Subcommand {
+        name: "reconcile",
+        usage: "[--dry-run] [--force]",
+        about: "Set up the state presence calls for, once and without the grace period, print it and exit. --force goes Away even with the machine busy.",
+        words: &["--dry-run", "--force"],
+    },
+    Subcommand { name: "status", usage: "[--json] [--diff]", about: "Show the daemon, the outputs and the services. --diff also shows what's out of step with the daemon's state.", words: &["--json", "--diff"] },
+    Subcommand { name: "watch", usage: "", about: "Show the status live.", words: &[] },
+    Subcommand { name: "history", usage: "[--json] [--limit N]", about: "List past transitions.", words: &["--json", "--limit"] },
+    Subcommand {
+        name: "statusline",
+        usage: "[--format waybar|i3bar] [--follow]",
+        about: "Print the state as a status bar module.",
+        words: &["--format", "--follow"],
+    },
+    Subcommand {
+        name: "displays",
+        usage: "[--connected] [--wide] [--json] [--sort KEY]",
+        about: "List the outputs.",
+        words: &["--connected", "--wide", "--json", "--sort"],
+    },
+    Subcommand { name: "display", usage: "show NAME [--json]", about: "Show everything about one output.", words: &["show", "--json"] },
+    Subcommand { name: "modes", usage: "NAME [--json]", about: "List one output's modes.", words: &["--json"] },
+    Subcommand { name: "set-mode", usage: "NAME WIDTHxHEIGHT[@HZ]", about: "Switch an output's mode.", words: &[] },
+    Subcommand { name: "output", usage: "power-cycle NAME", about: "Turn an output off and on again.", words: &["power-cycle"] },
+    Subcommand { name: "layout", usage: "save|apply NAME | list", about: "Keep and restore named layouts.", words: &["save", "apply", "list"] },
+    Subcommand { name: "profile", usage: "[NAME|none]", about: "List the Away profiles, or switch to one.", words: &["none"] },
+    Subcommand { name: "away", usage: "", about: "Switch the running daemon to Away by hand.", words: &[] },
+    Subcommand { name: "desk", usage: "", about: "Switch the running daemon to AtDesk by hand.", words: &[] },
+    Subcommand { name: "fleet", usage: "status", about: "Show every [hosts] entry's state.", words: &["status"] },
+    #[cfg(feature = "sunshine-api")]
+    Subcommand { name: "sunshine", usage: "apps|clients|close", about: "Talk to Sunshine's web API.", words: &["apps", "clients", "close"] },
+    Subcommand {
+        name: "ctl",
+        usage: "diagnostics [--kscreen] | cancel | reload | client-mode [WxH@HZ|--reset]",
+        about: "Ask the running daemon for a debug bundle, to cancel a pending switch, to reload the config, or to switch to a client's mode (from a Sunshine prep command).",
+        words: &["diagnostics", "cancel", "reload", "client-mode", "--kscreen", "--reset"],
+    },
+    Subcommand {
+        name: "inhibit",
+        usage: "[--name NAME] [--duration 2h] REASON | --release NAME",
+        about: "Pause automatic switching.",
+        words: &["--name", "--duration", "--release"],
+    },
+    Subcommand { name: "tune", usage: "[--write]", about: "Measure how long switching takes on this machine.", words: &["--write"] },
+    Subcommand { name: "diagram", usage: "[--mermaid]", about: "Print the state machine.", words: &["--mermaid"] },
+    Subcommand {
+        name: "simulate",
+        usage: "SCRIPT [--grace-period 20s] [--cooldown 1m] [--poll-interval 5s]",
+        about: "Rehearse the state machine against a script of readings.",
+        words: &["--grace-period", "--cooldown", "--poll-interval"],
+    },
+    Subcommand { name: "doctor", usage: "", about: "Check the setup.", words: &[] },
+    Subcommand { name: "config", usage: "check", about: "Check the config against this machine.", words: &["check"] },
+    Subcommand { name: "install", usage: "[--force] [--linger]", about: "Set up the user service.", words: &["--force", "--linger"] },
+    Subcommand { name: "smoke-test", usage: "", about: "Check the build without hardware.", words: &[] },
+    Subcommand { name: "completions", usage: "bash|zsh|fish", about: "Print shell completions.", words: &["bash", "zsh", "fish"] },
+    Subcommand { name: "man", usage: "", about: "Print this man page.", words: &[] },
+];
+
+// Values for the flags that take one from a fixed set.
+const VALUES: &[(&str, &[&str])] = &[
+    ("--log-level", &["off", "error", "warn", "info", "debug", "trace"]),
+    ("--format", &["waybar", "i3bar"]),
+    ("--sort", &["name", "refresh", "resolution", "state", "index"]),
+];
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl FromStr for Shell {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            other => Err(format!("Unknown shell \"{other}\" (bash, zsh, fish)")),
+        }
+    }
+}
+
+pub fn completions(shell: Shell) -> String {
+    match shell {
+        Shell::Bash => bash(),
+        Shell::Zsh => zsh(),
+        Shell::Fish => fish(),
+    }
+}
+
+fn names() -> String {
+    COMMANDS.iter().map(|c| c.name).collect::<Vec<_>>().join(" ")
+}
+
+fn bash() -> String {
+    let mut out = String::from("# vitamink completions for bash\n_vitamink() {\n    local cur=${COMP_WORDS[COMP_CWORD]} prev=${COMP_WORDS[COMP_CWORD-1]} words\n");
+    out += "    case $prev in\n";
+    for (flag, values) in VALUES {
+        let _ = writeln!(out, "        {flag}) COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return ;;", values.join(" "));
+    }
+    out += "    esac\n";
+    let _ = writeln!(out, "    if [ \"$COMP_CWORD\" -eq 1 ]; then\n        words=\"{} --log-level --host\"\n    else\n        case ${{COMP_WORDS[1]}} in", names());
+    for c in COMMANDS.iter().filter(|c| !c.words.is_empty()) {
+        let _ = writeln!(out, "            {}) words=\"{}\" ;;", c.name, c.words.join(" "));
+    }
+    out += "        esac\n    fi\n    COMPREPLY=($(compgen -W \"$words\" -- \"$cur\"))\n}\ncomplete -F _vitamink vitamink\n";
+    out
+}
+
+// Single-quoted for zsh and fish.
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+// The first sentence, for a one-line description.
+fn summary(about: &str) -> &str {
+    about.split_once(". ").map_or(about.trim_end_matches('.'), |(first, _)| first)
+}
+
+fn zsh() -> String {
+    let mut out = String::from("#compdef vitamink\n\n_vitamink() {\n    local -a commands\n    commands=(\n");
+    for c in COMMANDS {
+        let _ = writeln!(out, "        {}", quote(&format!("{}:{}", c.name, summary(c.about))));
+    }
+    out += "    )\n    case $words[CURRENT-1] in\n";
+    for (flag, values) in VALUES {
+        let _ = writeln!(out, "        {flag}) compadd -- {}; return ;;", values.join(" "));
+    }
+    out += "    esac\n    if (( CURRENT == 2 )); then\n        _describe 'command' commands\n        compadd -- --log-level --host\n        return\n    fi\n    case $words[2] in\n";
+    for c in COMMANDS.iter().filter(|c| !c.words.is_empty()) {
+        let _ = writeln!(out, "        {}) compadd -- {} ;;", c.name, c.words.join(" "));
+    }
+    out += "    esac\n}\n\n_vitamink \"$@\"\n";
+    out
+}
+
+fn fish() -> String {
+    let mut out = String::from("# vitamink completions for fish\ncomplete -c vitamink -f\n");
+    for (flag, values) in VALUES {
+        let _ = writeln!(out, "complete -c vitamink -l {} -xa {}", &flag[2..], quote(&values.join(" ")));
+    }
+    out += "complete -c vitamink -l host -x\n";
+    for c in COMMANDS {
+        let _ = writeln!(out, "complete -c vitamink -n __fish_use_subcommand -a {} -d {}", c.name, quote(summary(c.about)));
+    }
+    for c in COMMANDS {
+        let condition = quote(&format!("__fish_seen_subcommand_from {}", c.name));
+        for word in c.words {
+            match word.strip_prefix("--") {
+                Some(long) if !VALUES.iter().any(|(flag, _)| flag == word) => {
+                    let _ = writeln!(out, "complete -c vitamink -n {condition} -l {long}");
+                }
+                Some(_) => {}
+                None => {
+                    let _ = writeln!(out, "complete -c vitamink -n {condition} -a {word}");
+                }
+            }
+        }
+    }
+    out
+}
+
+// ---- Man Page ----
+
+// Text for roff: backslashes and hyphens escaped, and no line starting
+// with a control character.
+fn roff(text: &str) -> String {
+    let text = text.replace('\\', "\\e").replace('-', "\\-");
+    if text.starts_with(['.', '\'']) { format!("\\&{text}") } else { text }
+}
+
+pub fn man() -> String {
+    let mut out = format!(".TH VITAMINK 1 \"\" \"vitamink {}\" \"User Commands\"\n", env!("CARGO_PKG_VERSION"));
+    out += ".SH NAME\nvitamink \\- switch the desk between local use and Sunshine game streaming\n";
+    out += ".SH SYNOPSIS\n.B vitamink\n[\\fB\\-\\-log\\-level\\fR \\fILEVEL\\fR] [\\fB\\-\\-host\\fR \\fINAME\\fR] \\fICOMMAND\\fR [\\fIARGS\\fR]\n";
+    out += ".SH DESCRIPTION\n";
+    out += "The daemon watches whether anyone is at the desk. When nobody is, it goes Away: it turns on a dummy plug and starts Sunshine, \
+so the machine can be streamed to with Moonlight. When someone is back, it puts the desk as it was.\n";
+    out += ".PP\nWithout a command, vitamink prints the status.\n";
+    out += ".SH OPTIONS\n.TP\n\\fB\\-\\-log\\-level\\fR \\fILEVEL\\fR\nOne of off, error, warn, info, debug or trace. Works with any command.\n";
+    out += ".TP\n\\fB\\-\\-host\\fR \\fINAME\\fR\nSend status, away, desk or inhibit to another PC's daemon over its HTTP API: a [hosts.NAME] entry, or HOST[:PORT].\n";
+    out += ".SH COMMANDS\n";
+    for c in COMMANDS {
+        let _ = writeln!(out, ".TP\n\\fB{}\\fR {}\n{}", roff(c.name), roff(c.usage), roff(c.about));
+    }
+    out += ".SH FILES\n.TP\n\\fI~/.config/vitamink/config.toml\\fR\nThe configuration; \\fBvitamink install\\fR writes a commented one.\n";
+    out += ".TP\n\\fI~/.local/state/vitamink/\\fR\nWhat the daemon remembers across restarts.\n";
+    out += ".TP\n\\fI$XDG_RUNTIME_DIR/vitamink/status.json\\fR\nThe running daemon's status.\n";
+    out += ".SH ENVIRONMENT\n.TP\n\\fBNO_COLOR\\fR\nSet, \\fBvitamink status\\fR prints without colors.\n";
+    out
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commands_match_main() {
+        let main = include_str!("main.rs");
+        let (_, main) = main.split_once("    match command {").unwrap();
+        let (main, _) = main.split_once("        _ => print_status").unwrap();
+        for c in COMMANDS {
+            assert!(main.contains(&format!("Some(\"{}\")", c.name)), "{} isn't a command in main.rs", c.name);
+        }
+        // `status` is matched twice, and `sunshine` is there either way.
+        let expected = COMMANDS.len() + 1 + usize::from(!cfg!(feature = "sunshine-api"));
+        assert_eq!(main.matches("        Some(\"").count(), expected, "a command in main.rs is missing here");
+    }
+
+    #[test]
+    fn test_completions() {
+        let bash = completions(Shell::Bash);
+        assert!(bash.contains("            reconcile) words=\"--dry-run --force\" ;;\n"));
+        assert!(bash.contains("        --format) COMPREPLY=($(compgen -W \"waybar i3bar\" -- \"$cur\")); return ;;\n"));
+        assert!(completions(Shell::Zsh).contains("        'status:Show the daemon, the outputs and the services'\n"));
+        let fish = completions(Shell::Fish);
+        assert!(fish.contains("complete -c vitamink -n '__fish_seen_subcommand_from daemon' -l dry-run\n"));
+        assert!(fish.contains("complete -c vitamink -n __fish_use_subcommand -a daemon -d 'Run the polling loop'\n"));
+        assert!(!fish.contains("-l format\n"));
+        assert_eq!(quote("what's"), "'what'\\''s'");
+        assert_eq!("tcsh".parse::<Shell>(), Err("Unknown shell \"tcsh\" (bash, zsh, fish)".to_string()));
+    }
+
+    #[test]
+    fn test_man() {
+        assert_eq!(roff("--dry-run"), "\\-\\-dry\\-run");
+        assert_eq!(roff(".hidden"), "\\&.hidden");
+        let page = man();
+        assert!(page.starts_with(".TH VITAMINK 1"));
+        assert!(page.contains(".TP\n\\fBreconcile\\fR [\\-\\-dry\\-run] [\\-\\-force]\n"));
+    }
+}
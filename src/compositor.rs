@@ -0,0 +1,110 @@
+// src/compositor.rs — KWin animation/effects tuning while streaming
+//
+// Every fade, slide, and blur is more work for the encoder and more
+// latency for the remote player. While Away we can set the global
+// animation speed to "instant" and unload chosen KWin effects, then put
+// the user's settings back on return.
+//
+// Animation speed lives in kdeglobals ([KDE] AnimationDurationFactor, 0 =
+// no animations); KWin picks it up after `org.kde.KWin.reconfigure`.
+// Effects are toggled live through KWin's `/Effects` object.
+
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use zbus::blocking::Connection;
+
+use crate::config::CompositorConfig;
+use crate::dbus;
+use crate::error::{Result, VitaminkError};
+use crate::process;
+
+// What Away changed, so AtDesk can restore it.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Restore {
+    // Some(previous value, or None if the key was unset) if we touched it.
+    animation_factor: Option<Option<String>>,
+    // Effects that were loaded before we unloaded them.
+    effects: Vec<String>,
+}
+
+pub fn engage(config: &CompositorConfig) -> Result<Restore> {
+    let conn = dbus::session()?;
+    let mut restore = Restore::default();
+
+    if config.disable_animations {
+        let previous = read_animation_factor()?;
+        write_animation_factor(Some("0"))?;
+        reconfigure(&conn)?;
+        restore.animation_factor = Some(previous);
+    }
+
+    for effect in &config.unload_effects {
+        let loaded: bool = effects_call(&conn, "isEffectLoaded", &(effect.as_str(),))?;
+        if loaded {
+            effects_call::<()>(&conn, "unloadEffect", &(effect.as_str(),))?;
+            restore.effects.push(effect.clone());
+        }
+    }
+
+    Ok(restore)
+}
+
+pub fn restore(restore: &Restore) -> Result<()> {
+    let conn = dbus::session()?;
+
+    for effect in &restore.effects {
+        let _: bool = effects_call(&conn, "loadEffect", &(effect.as_str(),))?;
+    }
+
+    if let Some(previous) = &restore.animation_factor {
+        write_animation_factor(previous.as_deref())?;
+        reconfigure(&conn)?;
+    }
+
+    Ok(())
+}
+
+// ---- Animation Speed ----
+
+fn run_kconfig(program: &str, args: &[&str]) -> Result<String> {
+    let output = process::output(
+        Command::new(program)
+            .args(["--file", "kdeglobals", "--group", "KDE", "--key", "AnimationDurationFactor"])
+            .args(args),
+    )?;
+
+    if !output.status.success() {
+        return Err(VitaminkError::command_failed(program, &output));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn read_animation_factor() -> Result<Option<String>> {
+    let value = run_kconfig("kreadconfig6", &[])?;
+    Ok(if value.is_empty() { None } else { Some(value) })
+}
+
+// `None` removes the key, which means "use the Plasma default".
+fn write_animation_factor(value: Option<&str>) -> Result<()> {
+    match value {
+        Some(v) => run_kconfig("kwriteconfig6", &[v])?,
+        None => run_kconfig("kwriteconfig6", &["--delete"])?,
+    };
+    Ok(())
+}
+
+fn reconfigure(conn: &Connection) -> Result<()> {
+    dbus::call(conn, "org.kde.KWin", "/KWin", "org.kde.KWin", "reconfigure", &())
+}
+
+// ---- Effects ----
+
+fn effects_call<T>(conn: &Connection, method: &str, body: &(&str,)) -> Result<T>
+where
+    T: for<'d> zbus::zvariant::DynamicDeserialize<'d>,
+{
+    dbus::call(conn, "org.kde.KWin", "/Effects", "org.kde.kwin.Effects", method, body)
+}
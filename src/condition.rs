@@ -0,0 +1,285 @@
+// src/condition.rs — `away_when` expressions: when does Away kick in?
+//
+// A single presence backend can't say "the screen is locked AND the
+// monitor is asleep", so a paused movie with the monitor dimmed would
+// start Sunshine. `away_when` combines the signals instead:
+//
+//   away_when = "locked and dpms_off"
+//   away_when = "dpms_off and (locked or idle > 10m)"
+//
+// Terms:
+//   locked      the session is locked (logind LockedHint / ScreenSaver)
+//   dpms_off    the main display is asleep
+//   idle        no input for `idle_timeout`
+//   idle > 5m   no input for the given duration
+//   camera_empty  the `[camera]` helper sees nobody (see camera.rs)
+//   process_running  one of `processes` is running (see processes.rs)
+//   bluetooth_away   none of the `[bluetooth]` devices is near (see bluetooth.rs)
+//   on_battery  running on battery rather than mains (see power.rs)
+//
+// combined with `and`, `or`, `not` and parentheses (`not` binds tightest,
+// then `and`, then `or`).
+//
+// A term the system can't answer (e.g. no DPMS file) is unknown, and
+// unknowns follow three-valued logic: `false and unknown` is false, but
+// `true and unknown` is unknown — which holds the current state. Terms
+// are looked up lazily, so `dpms_off and locked` never asks about the
+// lock while the monitor is on.
+//
+// New Rust concepts in this file:
+//
+// - `Box<T>` in an enum: a recursive type needs indirection, otherwise
+//   `Condition` would have to contain itself and have infinite size.
+//
+// - `Peekable`: an iterator adapter that lets the parser look at the next
+//   token without consuming it.
+
+use std::fmt;
+use std::iter::Peekable;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::duration;
+use crate::error::{Result, VitaminkError};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Term {
+    Locked,
+    DpmsOff,
+    // None = the configured `idle_timeout`.
+    Idle(Option<Duration>),
+    CameraEmpty,
+    ProcessRunning,
+    BluetoothAway,
+    OnBattery,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Condition {
+    Term(Term),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+impl Condition {
+    // True/false, or None if an unknown term decides the outcome.
+    // `lookup` is only called for terms that can still matter.
+    pub fn eval(&self, lookup: &mut impl FnMut(Term) -> Option<bool>) -> Option<bool> {
+        match self {
+            Self::Term(term) => lookup(*term),
+            Self::Not(inner) => inner.eval(lookup).map(|v| !v),
+            Self::And(a, b) => match a.eval(lookup) {
+                Some(false) => Some(false),
+                Some(true) => b.eval(lookup),
+                None => b.eval(lookup).and_then(|v| if v { None } else { Some(false) }),
+            },
+            Self::Or(a, b) => match a.eval(lookup) {
+                Some(true) => Some(true),
+                Some(false) => b.eval(lookup),
+                None => b.eval(lookup).and_then(|v| if v { Some(true) } else { None }),
+            },
+        }
+    }
+
+    // Whether `term` appears anywhere in the condition.
+    pub fn uses(&self, term: Term) -> bool {
+        match self {
+            Self::Term(t) => *t == term,
+            Self::Not(inner) => inner.uses(term),
+            Self::And(a, b) | Self::Or(a, b) => a.uses(term) || b.uses(term),
+        }
+    }
+}
+
+// ---- Parsing ----
+
+impl FromStr for Condition {
+    type Err = VitaminkError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let tokens = tokenize(s);
+        let mut tokens = tokens.iter().map(String::as_str).peekable();
+        let invalid = |why: String| VitaminkError::Parse(format!("Invalid condition \"{s}\": {why}"));
+
+        let condition = parse_or(&mut tokens).map_err(invalid)?;
+        match tokens.next() {
+            None => Ok(condition),
+            Some(extra) => Err(invalid(format!("unexpected \"{extra}\""))),
+        }
+    }
+}
+
+// The parse_* functions return the reason a condition is invalid; from_str
+// adds the input to it.
+type Parsed = std::result::Result<Condition, String>;
+
+fn tokenize(s: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    for ch in s.chars() {
+        if ch.is_alphanumeric() || ch == '_' {
+            word.push(ch);
+            continue;
+        }
+        if !word.is_empty() {
+            tokens.push(std::mem::take(&mut word));
+        }
+        if !ch.is_whitespace() {
+            tokens.push(ch.to_string());
+        }
+    }
+    if !word.is_empty() {
+        tokens.push(word);
+    }
+    tokens
+}
+
+fn parse_or<'a>(tokens: &mut Peekable<impl Iterator<Item = &'a str>>) -> Parsed {
+    let mut left = parse_and(tokens)?;
+    while tokens.next_if_eq(&"or").is_some() {
+        left = Condition::Or(Box::new(left), Box::new(parse_and(tokens)?));
+    }
+    Ok(left)
+}
+
+fn parse_and<'a>(tokens: &mut Peekable<impl Iterator<Item = &'a str>>) -> Parsed {
+    let mut left = parse_unary(tokens)?;
+    while tokens.next_if_eq(&"and").is_some() {
+        left = Condition::And(Box::new(left), Box::new(parse_unary(tokens)?));
+    }
+    Ok(left)
+}
+
+fn parse_unary<'a>(tokens: &mut Peekable<impl Iterator<Item = &'a str>>) -> Parsed {
+    match tokens.next() {
+        Some("not") => Ok(Condition::Not(Box::new(parse_unary(tokens)?))),
+        Some("(") => {
+            let inner = parse_or(tokens)?;
+            match tokens.next() {
+                Some(")") => Ok(inner),
+                _ => Err("missing \")\"".to_string()),
+            }
+        }
+        Some("locked") => Ok(Condition::Term(Term::Locked)),
+        Some("dpms_off") => Ok(Condition::Term(Term::DpmsOff)),
+        Some("idle") if tokens.next_if_eq(&">").is_some() => {
+            let limit = tokens.next().ok_or("expected a duration after \"idle >\"")?;
+            let limit = duration::parse(limit).map_err(|e| e.to_string())?;
+            Ok(Condition::Term(Term::Idle(Some(limit))))
+        }
+        Some("idle") => Ok(Condition::Term(Term::Idle(None))),
+        Some("camera_empty") => Ok(Condition::Term(Term::CameraEmpty)),
+        Some("process_running") => Ok(Condition::Term(Term::ProcessRunning)),
+        Some("bluetooth_away") => Ok(Condition::Term(Term::BluetoothAway)),
+        Some("on_battery") => Ok(Condition::Term(Term::OnBattery)),
+        Some(other) => Err(format!(
+            "unknown term \"{other}\" (locked, dpms_off, idle, idle > DURATION, camera_empty, process_running, bluetooth_away, on_battery)"
+        )),
+        None => Err("expected a term, found the end".to_string()),
+    }
+}
+
+// ---- Display ----
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Locked => write!(f, "locked"),
+            Self::DpmsOff => write!(f, "dpms_off"),
+            Self::Idle(None) => write!(f, "idle"),
+            Self::Idle(Some(limit)) => write!(f, "idle > {}", duration::format(*limit)),
+            Self::CameraEmpty => write!(f, "camera_empty"),
+            Self::ProcessRunning => write!(f, "process_running"),
+            Self::BluetoothAway => write!(f, "bluetooth_away"),
+            Self::OnBattery => write!(f, "on_battery"),
+        }
+    }
+}
+
+// Parenthesizes every `or` inside an `and` or `not`, so the text parses
+// back to the same tree.
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let grouped = |c: &Condition| match c {
+            Condition::Or(..) => format!("({c})"),
+            _ => c.to_string(),
+        };
+        match self {
+            Self::Term(term) => write!(f, "{term}"),
+            Self::Not(inner) => match **inner {
+                Condition::Term(_) | Condition::Not(_) => write!(f, "not {inner}"),
+                _ => write!(f, "not ({inner})"),
+            },
+            Self::And(a, b) => write!(f, "{} and {}", grouped(a), grouped(b)),
+            Self::Or(a, b) => write!(f, "{a} or {b}"),
+        }
+    }
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let c: Condition = "dpms_off and (locked or idle > 10m)".parse().unwrap();
+        assert_eq!(
+            c,
+            Condition::And(
+                Box::new(Condition::Term(Term::DpmsOff)),
+                Box::new(Condition::Or(
+                    Box::new(Condition::Term(Term::Locked)),
+                    Box::new(Condition::Term(Term::Idle(Some(Duration::from_secs(600))))),
+                )),
+            )
+        );
+        assert_eq!(c.to_string(), "dpms_off and (locked or idle > 10m)");
+
+        let c: Condition = "not locked or idle and dpms_off".parse().unwrap();
+        assert_eq!(c.to_string(), "not locked or idle and dpms_off");
+
+        assert!("locked and".parse::<Condition>().is_err());
+        assert!("(locked".parse::<Condition>().is_err());
+        assert!("asleep".parse::<Condition>().is_err());
+        assert!("locked dpms_off".parse::<Condition>().is_err());
+        assert!("idle > soon".parse::<Condition>().is_err());
+
+        let c: Condition = "dpms_off and not camera_empty".parse().unwrap();
+        assert!(c.uses(Term::CameraEmpty));
+        assert!(!c.uses(Term::Locked));
+
+        let c: Condition = "dpms_off and not process_running".parse().unwrap();
+        assert_eq!(c.to_string(), "dpms_off and not process_running");
+
+        let c: Condition = "dpms_off and not on_battery".parse().unwrap();
+        assert!(c.uses(Term::OnBattery));
+        assert_eq!(c.to_string(), "dpms_off and not on_battery");
+    }
+
+    #[test]
+    fn test_eval() {
+        let c: Condition = "locked and dpms_off".parse().unwrap();
+        let facts = |locked, dpms_off| {
+            move |term| match term {
+                Term::Locked => locked,
+                Term::DpmsOff => dpms_off,
+                Term::Idle(_) | Term::CameraEmpty | Term::ProcessRunning | Term::BluetoothAway | Term::OnBattery => None,
+            }
+        };
+        assert_eq!(c.eval(&mut facts(Some(true), Some(true))), Some(true));
+        assert_eq!(c.eval(&mut facts(Some(false), None)), Some(false));
+        assert_eq!(c.eval(&mut facts(None, Some(false))), Some(false));
+        assert_eq!(c.eval(&mut facts(None, Some(true))), None);
+
+        // `false and ...` never looks at the second term.
+        let mut asked = Vec::new();
+        c.eval(&mut |term| {
+            asked.push(term);
+            Some(false)
+        });
+        assert_eq!(asked, vec![Term::Locked]);
+    }
+}
@@ -0,0 +1,1859 @@
+// src/config.rs — Configuration file loading
+//
+// New Rust concepts in this file:
+//
+// - `#[derive(Deserialize)]`: serde generates the code that turns TOML into
+//   our structs. Field names in the file match the struct field names.
+//
+// - `#[serde(default)]`: any field missing from the file is filled in from
+//   the struct's `Default` impl, so an empty (or absent) file is valid.
+//
+// - `deserialize_with`: a custom function for one field. We use it to read
+//   strings like "10s" or "1h30m" into a `Duration` (see duration.rs).
+//
+// - `#[serde(untagged)]`: serde tries each variant in turn, which lets one
+//   field accept either a string or (for older config files) an integer.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::bluetooth;
+use crate::condition::{Condition, Term};
+use crate::display::{self, BackendKind, ModeTarget, OffStrategy};
+use crate::drift::OnDrift;
+use crate::dummy::UnplugAction;
+use crate::layout;
+use crate::logging::LogFormat;
+use crate::error::{Result, VitaminkError};
+use crate::notify::{Event as NotifyEvent, Urgency};
+use crate::presence::{Aggregation, PresenceBackend};
+use crate::schedule::{Action as ScheduleAction, Days, TimeOfDay};
+use crate::pipeline::{self, Stage};
+use crate::power;
+use crate::seat;
+use crate::services::{IoClass, Manager, SchedPolicy, Scope};
+use crate::shutdown::ShutdownAction;
+use crate::sunshine::encoder::EncoderCheck;
+use crate::sunshine::journal::{self, OnError};
+use crate::virtual_output;
+use crate::webhook::Format as WebhookFormat;
+
+// ---- Configuration ----
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // Output names here and in `[profiles]` can also be globs ("DP-*") or
+    // "edid:PATTERN", matched against the connected outputs (see pattern.rs).
+    // One output, or a list for several monitors at the desk; the first
+    // is the one that's turned back on if the layout can't be restored.
+    #[serde(deserialize_with = "one_or_many")]
+    pub main_display: Vec<String>,
+    // With several main displays: "all_off" (Away once every one is off)
+    // or "any_off" (Away as soon as one is).
+    pub main_display_logic: Aggregation,
+    // Connector name, or "auto" to pick one of the other connected outputs
+    // (see dummy.rs), narrowed down by `dummy_priority` and `dummy_edid`.
+    pub dummy_plug: String,
+    pub dummy_priority: Vec<String>,
+    pub dummy_edid: Option<String>,
+    // How outputs are controlled: "auto" (from XDG_CURRENT_DESKTOP and
+    // XDG_SESSION_TYPE), "kscreen", "wlr-randr", "mutter", "xrandr" (X11),
+    // or "wlr-output" (wlr-randr's protocol spoken directly, with the
+    // `wlr-output` feature).
+    pub display_backend: BackendKind,
+    // Override the detected session sockets, e.g. "wayland-1" / ":1".
+    pub wayland_display: Option<String>,
+    pub x11_display: Option<String>,
+    // Outputs that may be absent when the daemon starts (see
+    // `check_outputs`), e.g. a plug that's only attached for streaming.
+    pub allow_missing: Vec<String>,
+    // Mode for the dummy plug, e.g. "3840x2160@60". Unset = preferred mode.
+    #[serde(deserialize_with = "parsed")]
+    pub dummy_mode: Option<ModeTarget>,
+    // Scale for the dummy plug, e.g. 1.0 so a 4K plug isn't captured at
+    // the desktop's 1.5. Unset = whatever the compositor picks.
+    pub dummy_scale: Option<f64>,
+    // `[[custom_modes]]` — modes an Away output doesn't advertise, added
+    // for Away and removed at the desk (see custom_mode.rs).
+    pub custom_modes: Vec<CustomMode>,
+    // What pulling the dummy plug while Away does: "wait" (report it, and
+    // enable it again once it's back) or "virtual" (also stream from
+    // `[virtual_output]` meanwhile).
+    pub on_dummy_unplug: UnplugAction,
+    // What an output or service changed by hand while Away does: "adopt"
+    // (leave it so), "reapply" (set Away up again) or "pause" (leave it so
+    // and stop switching until released). See drift.rs.
+    pub on_drift: OnDrift,
+    // How AtDesk turns off the outputs Away turned on: "disable", or
+    // "dpms-off" to only blank them, so they stay in the layout and KWin
+    // doesn't move windows around. Blanking one output takes
+    // kscreen-doctor, Sway or Hyprland; elsewhere they're disabled.
+    pub away_outputs_off: OffStrategy,
+    // The output that's primary (priority 1) while Away, so KWin doesn't
+    // hand that to the dummy plug and move the panels onto it. Unset = the
+    // desk's primary while Away leaves it on, otherwise the first output
+    // Away turns on.
+    pub away_primary: Option<String>,
+    // A layout preset (`vitamink layout save NAME`, see layout.rs) to come
+    // back to instead of the layout from before going Away.
+    pub desk_layout: Option<String>,
+    // Name of a `[profiles.NAME]` entry to use for Away instead of the
+    // dummy plug settings above. Switchable at runtime over D-Bus.
+    pub profile: Option<String>,
+    pub profiles: BTreeMap<String, ProfileConfig>,
+    #[serde(deserialize_with = "duration")]
+    pub poll_interval: Duration,
+    // Polling slows down towards this while nothing changes, and snaps
+    // back to `poll_interval` as soon as something does.
+    #[serde(deserialize_with = "duration")]
+    pub max_poll_interval: Duration,
+    #[serde(deserialize_with = "duration")]
+    pub grace_period: Duration,
+    // The least time between two switches, on top of the grace period, so
+    // DPMS bouncing on a flaky cable doesn't start and stop Sunshine over
+    // and over. "0s" = no limit.
+    #[serde(deserialize_with = "duration")]
+    pub cooldown: Duration,
+    // How long a switch by hand (D-Bus, SIGUSR1/SIGUSR2, ...), a timed
+    // switch or a wake holds against the presence reading, at most. It
+    // always ends once the reading changes. "0s" = only then.
+    #[serde(deserialize_with = "duration")]
+    pub override_timeout: Duration,
+    // How long to wait for the dummy plug's DRM framebuffer after enabling
+    // it, and again for its first frame where debugfs is readable.
+    #[serde(deserialize_with = "duration")]
+    pub drm_timeout: Duration,
+    // How long an external command (kscreen-doctor, systemctl, ...) may
+    // run before it's killed and counted as failed. "0s" = no limit.
+    #[serde(deserialize_with = "duration")]
+    pub command_timeout: Duration,
+    // How "away" is detected: "dpms" (main display asleep), "logind"
+    // (session IdleHint), "idle" (no input for `idle_timeout`), or
+    // "camera" (the `[camera]` helper sees nobody), or "bluetooth" (none of
+    // the `[bluetooth]` devices in range).
+    pub presence_backend: PresenceBackend,
+    // Overrides `presence_backend` with a combination of signals, e.g.
+    // "locked and dpms_off" (see condition.rs).
+    #[serde(deserialize_with = "parsed")]
+    pub away_when: Option<Condition>,
+    #[serde(deserialize_with = "duration")]
+    pub idle_timeout: Duration,
+    // Input this recent (KDE's idle time) vetoes going Away, whatever the
+    // reading: some monitors report DPMS Off for a moment while in use.
+    // "0s", the default, doesn't check; "30s" suits most.
+    #[serde(deserialize_with = "duration")]
+    pub recent_input: Duration,
+    // Program names for the `process_running` term, e.g. ["steam", "wine"]
+    // (see processes.rs).
+    pub processes: Vec<String>,
+    // Pause after two or more outputs appear/disappear at once (a dock).
+    // "0s" turns dock detection off.
+    #[serde(deserialize_with = "duration")]
+    pub dock_settle: Duration,
+    // How long after a resume from suspend to only watch, while DPMS and
+    // connector state catch up. "0s" decides on the first poll.
+    #[serde(deserialize_with = "duration")]
+    pub resume_settle: Duration,
+    // How long the daemon waits at startup for the compositor to be up
+    // and listing outputs (see startup.rs). "0s" doesn't wait.
+    #[serde(deserialize_with = "duration")]
+    pub startup_timeout: Duration,
+    pub camera: CameraConfig,
+    pub bluetooth: BluetoothConfig,
+    pub seat: SeatConfig,
+    pub power: PowerConfig,
+    pub input_gating: InputGatingConfig,
+    pub privacy: PrivacyConfig,
+    pub audio: AudioConfig,
+    pub ddc: DdcConfig,
+    pub desktop: DesktopConfig,
+    pub compositor: CompositorConfig,
+    pub windows: WindowsConfig,
+    pub sunshine: SunshineConfig,
+    pub apps: AppsConfig,
+    // `[[services]]` — systemd units to run while Away (see services.rs).
+    // Defaults to Sunshine alone; list it too when adding others.
+    pub services: Vec<ServiceConfig>,
+    pub slice: SliceConfig,
+    // `[[schedule]]` — quiet hours and timed switches (see schedule.rs).
+    pub schedule: Vec<ScheduleRule>,
+    pub hooks: HooksConfig,
+    pub transition: TransitionConfig,
+    pub notifications: NotificationsConfig,
+    // `[[webhooks]]` — the same events posted to a URL (see webhook.rs).
+    pub webhooks: Vec<WebhookConfig>,
+    pub metrics: MetricsConfig,
+    pub http: HttpConfig,
+    // `[hosts.NAME]` — other PCs' daemons, for `--host NAME` and `vitamink
+    // fleet status` (see remote.rs).
+    pub hosts: BTreeMap<String, HostConfig>,
+    pub mqtt: MqttConfig,
+    pub wake: WakeConfig,
+    pub standby: StandbyConfig,
+    pub virtual_output: VirtualOutputConfig,
+    pub gamescope: GamescopeConfig,
+    pub display_retry: RetryConfig,
+    pub recovery: RecoveryConfig,
+    pub load: LoadConfig,
+    pub environment: EnvironmentConfig,
+    // What SIGTERM/SIGINT does while Away: "persist" (stay Away, pick up
+    // again on the next start) or "restore" (go back to AtDesk first).
+    pub on_shutdown: ShutdownAction,
+    // Transitions kept in history.jsonl for `vitamink history`; 0 keeps
+    // none.
+    pub history_size: usize,
+    // The daemon's log on stderr: "text", or "json" for one object per
+    // line (see logging.rs).
+    pub log_format: LogFormat,
+    // A file to append the same JSON lines to, whatever `log_format` is.
+    pub log_file: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            main_display: vec!["DP-2".to_string()],
+            main_display_logic: Aggregation::AllOff,
+            dummy_plug: "HDMI-A-1".to_string(),
+            dummy_priority: Vec::new(),
+            dummy_edid: None,
+            display_backend: BackendKind::Auto,
+            wayland_display: None,
+            x11_display: None,
+            allow_missing: Vec::new(),
+            dummy_mode: None,
+            dummy_scale: None,
+            custom_modes: Vec::new(),
+            on_dummy_unplug: UnplugAction::Wait,
+            on_drift: OnDrift::Adopt,
+            away_outputs_off: OffStrategy::Disable,
+            away_primary: None,
+            desk_layout: None,
+            profile: None,
+            profiles: BTreeMap::new(),
+            poll_interval: Duration::from_secs(5),
+            max_poll_interval: Duration::from_secs(20),
+            grace_period: Duration::from_secs(10),
+            cooldown: Duration::ZERO,
+            override_timeout: Duration::ZERO,
+            drm_timeout: Duration::from_secs(10),
+            command_timeout: Duration::from_secs(30),
+            presence_backend: PresenceBackend::Dpms,
+            away_when: None,
+            idle_timeout: Duration::from_secs(300),
+            recent_input: Duration::ZERO,
+            processes: Vec::new(),
+            dock_settle: Duration::from_secs(15),
+            resume_settle: Duration::from_secs(10),
+            startup_timeout: Duration::from_secs(60),
+            camera: CameraConfig::default(),
+            bluetooth: BluetoothConfig::default(),
+            seat: SeatConfig::default(),
+            power: PowerConfig::default(),
+            input_gating: InputGatingConfig::default(),
+            privacy: PrivacyConfig::default(),
+            audio: AudioConfig::default(),
+            ddc: DdcConfig::default(),
+            desktop: DesktopConfig::default(),
+            compositor: CompositorConfig::default(),
+            windows: WindowsConfig::default(),
+            sunshine: SunshineConfig::default(),
+            apps: AppsConfig::default(),
+            services: vec![ServiceConfig {
+                unit: "sunshine".to_string(),
+                scope: Scope::User,
+                manager: Manager::Systemd,
+                command: None,
+                order: 0,
+                tuning: ServiceTuning::default(),
+            }],
+            slice: SliceConfig::default(),
+            schedule: Vec::new(),
+            hooks: HooksConfig::default(),
+            transition: TransitionConfig::default(),
+            notifications: NotificationsConfig::default(),
+            webhooks: Vec::new(),
+            metrics: MetricsConfig::default(),
+            http: HttpConfig::default(),
+            hosts: BTreeMap::new(),
+            mqtt: MqttConfig::default(),
+            wake: WakeConfig::default(),
+            standby: StandbyConfig::default(),
+            virtual_output: VirtualOutputConfig::default(),
+            gamescope: GamescopeConfig::default(),
+            display_retry: RetryConfig::default(),
+            recovery: RecoveryConfig::default(),
+            load: LoadConfig::default(),
+            environment: EnvironmentConfig::default(),
+            on_shutdown: ShutdownAction::Persist,
+            history_size: 1000,
+            log_format: LogFormat::Text,
+            log_file: None,
+        }
+    }
+}
+
+// `[profiles.NAME]` — an alternative Away setup, e.g. a 4K plug for the
+// TV and a 1080p one for the phone:
+//
+//   [profiles.tv]
+//   enable = [{ name = "HDMI-A-1", mode = "3840x2160@60" }]
+//   disable = ["HDMI-A-2"]
+//
+// The first `enable` output takes the dummy plug's role (the DRM wait,
+// VITAMINK_DUMMY_PLUG for hooks).
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileConfig {
+    pub enable: Vec<ProfileOutput>,
+    #[serde(default)]
+    pub disable: Vec<String>,
+    // Replaces `[sunshine] config_template` while this profile is applied.
+    #[serde(default)]
+    pub sunshine_template: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileOutput {
+    pub name: String,
+    // Unset = preferred mode.
+    #[serde(default, deserialize_with = "parsed")]
+    pub mode: Option<ModeTarget>,
+    // Like `dummy_scale`.
+    #[serde(default)]
+    pub scale: Option<f64>,
+}
+
+// One of `custom_modes`, e.g. 120 Hz on a plug whose EDID stops at 60:
+// `{ output = "HDMI-A-1", mode = "1920x1080@120" }`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomMode {
+    pub output: String,
+    // The refresh rate is required.
+    pub mode: ModeTarget,
+    // CVT reduced blanking: less bandwidth, for a plug or cable that
+    // can't take the full timings at that rate.
+    #[serde(default)]
+    pub reduced_blanking: bool,
+}
+
+// `[bluetooth]` — paired devices that mean someone's at the desk (see
+// bluetooth.rs). Used by `presence_backend = "bluetooth"` and the
+// `bluetooth_away` term in `away_when`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BluetoothConfig {
+    // Addresses like "AA:BB:CC:DD:EE:FF" (`bluetoothctl devices`).
+    pub devices: Vec<String>,
+    pub adapter: String,
+    // e.g. -70: a weaker signal counts as out of range. Unset, being
+    // connected is enough.
+    pub min_rssi: Option<i16>,
+}
+
+impl Default for BluetoothConfig {
+    fn default() -> Self {
+        Self { devices: Vec::new(), adapter: "hci0".to_string(), min_rssi: None }
+    }
+}
+
+// `[seat]` — which logind session is ours to automate when a machine has
+// several (see seat.rs).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SeatConfig {
+    // e.g. "seat0": only go Away while our session is on it.
+    pub seat: Option<String>,
+    // e.g. ["wayland"]: only go Away from these session types. Empty
+    // allows any.
+    pub session_types: Vec<String>,
+    // Don't go Away while another user is active at our seat.
+    pub yield_to_other_users: bool,
+}
+
+impl Default for SeatConfig {
+    fn default() -> Self {
+        Self { seat: None, session_types: Vec::new(), yield_to_other_users: true }
+    }
+}
+
+// `[power]` — laptops: the power source, and power-profiles-daemon
+// (see power.rs).
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PowerConfig {
+    // false: don't go Away while running on battery.
+    pub away_on_battery: bool,
+    // e.g. "performance": the profile while Away, the previous one back
+    // at the desk. Unset leaves it alone.
+    pub profile: Option<String>,
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        Self { away_on_battery: true, profile: None }
+    }
+}
+
+// `[input_gating]` — grab local keyboards/mice while Away so nobody at the
+// desk can interfere with the remote session.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputGatingConfig {
+    pub enabled: bool,
+    pub keyboards: bool,
+    pub pointers: bool,
+    // Holding all of these on any grabbed keyboard releases the grab.
+    // Names from linux/input-event-codes.h, e.g. "KEY_LEFTCTRL".
+    pub panic_keys: Vec<String>,
+}
+
+impl Default for InputGatingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            keyboards: true,
+            pointers: true,
+            panic_keys: vec![
+                "KEY_LEFTCTRL".to_string(),
+                "KEY_LEFTALT".to_string(),
+                "KEY_BACKSPACE".to_string(),
+            ],
+        }
+    }
+}
+
+// `[privacy]` — silence the mic and cut off webcams while Away, and lock
+// the screen at the desk.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrivacyConfig {
+    pub mute_microphone: bool,
+    // USB device ids as named in /sys/bus/usb/devices, e.g. "1-4".
+    pub webcam_usb_devices: Vec<String>,
+    // Lock the session before Sunshine starts, so nobody at the desk can
+    // use the streamed desktop. Going Away fails if logind doesn't report
+    // it locked within `lock_timeout`.
+    pub lock_session: bool,
+    #[serde(deserialize_with = "duration")]
+    pub lock_timeout: Duration,
+}
+
+impl Default for PrivacyConfig {
+    fn default() -> Self {
+        Self { mute_microphone: false, webcam_usb_devices: Vec::new(), lock_session: false, lock_timeout: Duration::from_secs(5) }
+    }
+}
+
+impl PrivacyConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.mute_microphone || !self.webcam_usb_devices.is_empty() || self.lock_session
+    }
+}
+
+// `[audio]` — the default sink per state (see audio.rs).
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioConfig {
+    // e.g. "sink-sunshine-stereo". Unset leaves audio alone.
+    pub away_sink: Option<String>,
+    // Where sound goes back to; the default from before Away when unset.
+    pub desk_sink: Option<String>,
+    // Also move streams that are already playing.
+    pub move_streams: bool,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self { away_sink: None, desk_sink: None, move_streams: true }
+    }
+}
+
+impl AudioConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.away_sink.is_some()
+    }
+}
+
+// `[ddc]` — the main display's settings put back over DDC/CI at the desk
+// (see ddc.rs). Unset ones are left alone.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DdcConfig {
+    // 0–100.
+    pub brightness: Option<u8>,
+    pub contrast: Option<u8>,
+    // The monitor's VCP 0x60 value, e.g. 15 for DisplayPort 1.
+    pub input_source: Option<u8>,
+    // The I2C bus from `ddcutil detect`; looked up from the connector when
+    // unset.
+    pub bus: Option<u32>,
+}
+
+impl DdcConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.brightness.is_some() || self.contrast.is_some() || self.input_source.is_some()
+    }
+}
+
+// `[desktop]` — what the streamed desktop looks like while Away.
+// Set either a wallpaper image or a solid color; the image wins if both are.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DesktopConfig {
+    // Path or URL of an image, e.g. "/usr/share/wallpapers/black.png".
+    pub away_wallpaper: Option<String>,
+    // Solid color for the org.kde.color plugin, e.g. "#000000".
+    pub away_wallpaper_color: Option<String>,
+    // Activity name or id to switch to, e.g. "Streaming".
+    pub away_activity: Option<String>,
+}
+
+impl DesktopConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.away_wallpaper.is_some() || self.away_wallpaper_color.is_some() || self.away_activity.is_some()
+    }
+}
+
+// `[compositor]` — trade eye candy for encode latency while Away.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompositorConfig {
+    pub disable_animations: bool,
+    // KWin effect ids, e.g. ["blur", "wobblywindows", "slide"].
+    pub unload_effects: Vec<String>,
+}
+
+impl CompositorConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.disable_animations || !self.unload_effects.is_empty()
+    }
+}
+
+// `[windows]` — put KWin's windows back where they were after Away (see
+// windows.rs).
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowsConfig {
+    pub restore: bool,
+    // How long after the desk layout is back to wait for KWin to stop
+    // moving windows itself.
+    #[serde(deserialize_with = "duration")]
+    pub settle: Duration,
+}
+
+impl Default for WindowsConfig {
+    fn default() -> Self {
+        Self { restore: false, settle: Duration::from_secs(2) }
+    }
+}
+
+// `[sunshine]` — how we treat the streaming server.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SunshineConfig {
+    // Stay Away while a Moonlight client is connected, even if the user is
+    // back at the desk. Manual ForceDesk always goes through.
+    pub block_desk_while_streaming: bool,
+    // Sunshine's default ports (base 47989). Change if you moved `port`
+    // in sunshine.conf.
+    pub session_ports: Vec<u16>,
+    // Switch the dummy plug to the resolution and frame rate the Moonlight
+    // client asks for, as reported by `vitamink ctl client-mode` from a
+    // Sunshine prep command (see sunshine.rs), and back once no client is
+    // connected.
+    pub match_client_mode: bool,
+    // Sunshine's web API (see sunshine/api.rs). With credentials set, the
+    // running app is closed cleanly before Sunshine is stopped.
+    pub api_url: String,
+    pub api_username: Option<String>,
+    pub api_password: Option<String>,
+    // How long going Away waits for the Away outputs' framebuffers and
+    // Sunshine's web port before giving up on it (see sunshine.rs). "0s"
+    // skips the wait.
+    #[serde(deserialize_with = "duration")]
+    pub ready_timeout: Duration,
+    // A sunshine.conf template to render over `config_file` while Away,
+    // with "{width}", "{height}" and "{fps}" from the dummy plug's mode
+    // (see sunshine/conf.rs). Unset leaves sunshine.conf alone.
+    pub config_template: Option<String>,
+    pub config_file: String,
+    // Before starting Sunshine for Away, whether a hardware encoder is
+    // free: "warn", "abort" (fail going Away) or "off" (see
+    // sunshine/encoder.rs). `nvenc_sessions` is the NVIDIA driver's limit
+    // on concurrent sessions.
+    pub encoder_check: EncoderCheck,
+    pub nvenc_sessions: u32,
+    // Back at the desk while a client is still streaming (with
+    // `block_desk_while_streaming` off, or a forced AtDesk), leave the
+    // services running until the stream ends, or at most this long. "0s"
+    // stops them right away.
+    #[serde(deserialize_with = "duration")]
+    pub drain_timeout: Duration,
+    // What a line in Sunshine's journal matching one of `error_patterns`
+    // (case aside) does while Away: "notify", "restart", "reapply" or
+    // "off" (see sunshine/journal.rs).
+    pub on_error: OnError,
+    pub error_patterns: Vec<String>,
+}
+
+impl Default for SunshineConfig {
+    fn default() -> Self {
+        Self {
+            block_desk_while_streaming: true,
+            session_ports: vec![47984, 47989, 47998, 47999, 48000, 48010],
+            match_client_mode: false,
+            api_url: "https://localhost:47990".to_string(),
+            api_username: None,
+            api_password: None,
+            ready_timeout: Duration::from_secs(30),
+            config_template: None,
+            config_file: "~/.config/sunshine/sunshine.conf".to_string(),
+            encoder_check: EncoderCheck::Warn,
+            nvenc_sessions: 8,
+            drain_timeout: Duration::ZERO,
+            on_error: OnError::Notify,
+            error_patterns: journal::DEFAULT_PATTERNS.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+}
+
+// `[apps]` — one of Sunshine's apps to start when going Away (see
+// sunshine/apps.rs). Off by default.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppsConfig {
+    // The app's name in Sunshine.
+    pub launch: Option<String>,
+    // Stop it and undo its prep commands at the desk.
+    pub close_on_desk: bool,
+}
+
+impl Default for AppsConfig {
+    fn default() -> Self {
+        Self { launch: None, close_on_desk: true }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServiceConfig {
+    pub unit: String,
+    // "user" (systemctl --user) or "system".
+    #[serde(default = "user_scope")]
+    pub scope: Scope,
+    // What runs it: "systemd", "runit", "s6" or "process" (see services.rs).
+    #[serde(default = "systemd")]
+    pub manager: Manager,
+    // The command line for manager = "process", run through `sh -c`.
+    #[serde(default)]
+    pub command: Option<String>,
+    // Lower starts first and stops last.
+    #[serde(default)]
+    pub order: i32,
+    // Scheduling while Away, e.g. `tuning = { nice = -5, cpu_affinity = "0-3" }`.
+    #[serde(default)]
+    pub tuning: ServiceTuning,
+}
+
+// Applied as a runtime drop-in while the unit runs for Away (see
+// services.rs). Unset fields leave the unit's own settings alone.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServiceTuning {
+    // CPUs in systemd's syntax, e.g. "0-3" or "2 4 6".
+    pub cpu_affinity: Option<String>,
+    // -20 (highest) to 19. Below 0 needs CAP_SYS_NICE for user units.
+    pub nice: Option<i8>,
+    pub io_class: Option<IoClass>,
+    // 0 (highest) to 7.
+    pub io_priority: Option<u8>,
+    pub cpu_scheduling: Option<SchedPolicy>,
+    // 1 to 99, for "fifo" and "rr" only.
+    pub cpu_scheduling_priority: Option<u8>,
+}
+
+impl ServiceTuning {
+    pub fn is_enabled(&self) -> bool {
+        self.cpu_affinity.is_some()
+            || self.nice.is_some()
+            || self.io_class.is_some()
+            || self.io_priority.is_some()
+            || self.cpu_scheduling.is_some()
+            || self.cpu_scheduling_priority.is_some()
+    }
+}
+
+fn user_scope() -> Scope {
+    Scope::User
+}
+
+fn systemd() -> Manager {
+    Manager::Systemd
+}
+
+// `[slice]` — a systemd slice for streaming services while Away, weighted
+// against the rest of the session (see services.rs).
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SliceConfig {
+    // e.g. "vitamink-stream.slice". Unset = no slice.
+    pub name: Option<String>,
+    // Which `[[services]]` units run in it.
+    pub units: Vec<String>,
+    // 1 to 10000; the session's other slices have 100.
+    pub cpu_weight: Option<u32>,
+    pub io_weight: Option<u32>,
+    // Memory kept from reclaim / where throttling starts, e.g. "4G".
+    pub memory_low: Option<String>,
+    pub memory_high: Option<String>,
+}
+
+impl Default for SliceConfig {
+    fn default() -> Self {
+        Self {
+            name: None,
+            units: vec!["sunshine".to_string()],
+            cpu_weight: None,
+            io_weight: None,
+            memory_low: None,
+            memory_high: None,
+        }
+    }
+}
+
+impl SliceConfig {
+    // Whether `unit` runs in the slice ("sunshine" and "sunshine.service"
+    // are the same unit).
+    pub fn contains(&self, unit: &str) -> bool {
+        let base = |u: &str| u.trim_end_matches(".service").to_string();
+        self.name.is_some() && self.units.iter().any(|u| base(u) == base(unit))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleRule {
+    // "suppress" or "away".
+    pub action: ScheduleAction,
+    // "daily", "weekdays", "weekends", "mon-fri", "sat,sun", ...
+    #[serde(default = "every_day", deserialize_with = "from_str")]
+    pub days: Days,
+    // "HH:MM", local time.
+    #[serde(deserialize_with = "from_str")]
+    pub from: TimeOfDay,
+    // Unset for a one-off switch at `from`.
+    #[serde(default, deserialize_with = "parsed")]
+    pub to: Option<TimeOfDay>,
+}
+
+fn every_day() -> Days {
+    Days::ALL
+}
+
+// `[camera]` — opt-in presence from a local camera, through a helper
+// command you supply (see camera.rs). Used by `presence_backend = "camera"`
+// and the `camera_empty` term in `away_when`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraConfig {
+    // Exits 0 if someone is there, 1 if nobody is, anything else if unsure.
+    pub command: Option<String>,
+    // How often to run it; between runs the last answer stands.
+    #[serde(deserialize_with = "duration")]
+    pub interval: Duration,
+    // How long "nobody" / "someone" must hold before the answer flips.
+    #[serde(deserialize_with = "duration")]
+    pub absent_after: Duration,
+    #[serde(deserialize_with = "duration")]
+    pub present_after: Duration,
+    // A helper that runs longer is killed and counts as unsure.
+    #[serde(deserialize_with = "duration")]
+    pub timeout: Duration,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        Self {
+            command: None,
+            interval: Duration::from_secs(30),
+            absent_after: Duration::from_secs(120),
+            present_after: Duration::ZERO,
+            timeout: Duration::from_secs(15),
+        }
+    }
+}
+
+// `[hooks]` — shell commands run around transitions (see hooks.rs).
+// `pre_*` run before the first step, `on_*` after the last one succeeded.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HooksConfig {
+    pub pre_away: Vec<String>,
+    pub on_away: Vec<String>,
+    pub pre_desk: Vec<String>,
+    pub on_desk: Vec<String>,
+    // Per command; a hook that runs longer is killed.
+    #[serde(deserialize_with = "duration")]
+    pub timeout: Duration,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            pre_away: Vec::new(),
+            on_away: Vec::new(),
+            pre_desk: Vec::new(),
+            on_desk: Vec::new(),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+// `[transition]` — the order of a transition's steps, with commands and
+// pauses in between (see pipeline.rs). Unset, the built-in order.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransitionConfig {
+    #[serde(deserialize_with = "parsed_list")]
+    pub away: Option<Vec<Stage>>,
+    // Unset: `away`'s steps backwards.
+    #[serde(deserialize_with = "parsed_list")]
+    pub desk: Option<Vec<Stage>>,
+    // Per `run` command; one that runs longer is killed and fails the
+    // transition.
+    #[serde(deserialize_with = "duration")]
+    pub timeout: Duration,
+}
+
+impl Default for TransitionConfig {
+    fn default() -> Self {
+        Self { away: None, desk: None, timeout: Duration::from_secs(30) }
+    }
+}
+
+impl TransitionConfig {
+    pub fn away(&self) -> Vec<Stage> {
+        self.away.clone().unwrap_or_else(pipeline::default_away)
+    }
+
+    pub fn desk(&self) -> Vec<Stage> {
+        self.desk.clone().unwrap_or_else(|| pipeline::default_desk(&self.away()))
+    }
+}
+
+// `[environment]` — what the commands we run get in their environment
+// (see environment.rs).
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnvironmentConfig {
+    // Fill in XDG_RUNTIME_DIR, DBUS_SESSION_BUS_ADDRESS, WAYLAND_DISPLAY
+    // and DISPLAY from the user's session where ours lacks them.
+    pub detect: bool,
+    // The only variables passed through from ours. Unset passes them all.
+    pub inherit: Option<Vec<String>>,
+    pub set: BTreeMap<String, String>,
+}
+
+impl Default for EnvironmentConfig {
+    fn default() -> Self {
+        Self { detect: true, inherit: None, set: BTreeMap::new() }
+    }
+}
+
+// `[notifications]` — desktop popups (see notify.rs).
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotificationsConfig {
+    pub enabled: bool,
+    // Which of "transition", "error", "holding", "pending" and "summary"
+    // to show.
+    pub events: Vec<NotifyEvent>,
+    // "low", "normal" or "critical".
+    pub urgency: Urgency,
+    pub error_urgency: Urgency,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            events: vec![NotifyEvent::Transition, NotifyEvent::Error, NotifyEvent::Holding, NotifyEvent::Pending],
+            urgency: Urgency::Low,
+            error_urgency: Urgency::Critical,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub url: String,
+    // "generic", "telegram" or "discord".
+    #[serde(default)]
+    pub format: WebhookFormat,
+    // Where a "telegram" webhook posts.
+    #[serde(default)]
+    pub chat_id: Option<String>,
+    // As in [notifications], but "pending" isn't posted.
+    #[serde(default = "webhook_events")]
+    pub events: Vec<NotifyEvent>,
+}
+
+fn webhook_events() -> Vec<NotifyEvent> {
+    vec![NotifyEvent::Transition, NotifyEvent::Error, NotifyEvent::Holding]
+}
+
+// `[metrics]` — a Prometheus endpoint (see metrics.rs). Off by default.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    // Address to listen on; served at `http://LISTEN/metrics`.
+    pub listen: SocketAddr,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self { enabled: false, listen: SocketAddr::from(([127, 0, 0, 1], 9188)) }
+    }
+}
+
+// `[http]` — the state and switching over HTTP, for phones and
+// shortcuts (see http.rs). Off by default.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    pub enabled: bool,
+    // Anything but loopback needs a `token`.
+    pub listen: SocketAddr,
+    // Sent as `Authorization: Bearer TOKEN` or `?token=TOKEN`.
+    pub token: Option<String>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self { enabled: false, listen: SocketAddr::from(([127, 0, 0, 1], 9189)), token: None }
+    }
+}
+
+// `[hosts.NAME]` — another PC's `[http]` API:
+//
+//   [hosts.den-pc]
+//   url = "http://den-pc:9189"
+//   token = "a-long-random-string"
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HostConfig {
+    pub url: String,
+    // That PC's `[http] token`.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+// `[mqtt]` — state and commands over MQTT, with Home Assistant discovery
+// (see mqtt.rs). Off by default.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttConfig {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    // Base topic; `vitamink/NODE` when unset.
+    pub topic: Option<String>,
+    pub discovery_prefix: String,
+    // Names this PC in topics and in Home Assistant; the hostname when unset.
+    pub node_id: Option<String>,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: "localhost".to_string(),
+            port: 1883,
+            topic: None,
+            discovery_prefix: "homeassistant".to_string(),
+            node_id: None,
+        }
+    }
+}
+
+// `[wake]` — go Away as soon as a client tries to wake this PC (see
+// wake.rs). Off by default.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WakeConfig {
+    pub enabled: bool,
+    // UDP ports to watch for Wake-on-LAN magic packets. Below 1024 needs
+    // CAP_NET_BIND_SERVICE; Moonlight sends to 47009 as well as 9.
+    pub ports: Vec<u16>,
+    // Also treat a connection to Sunshine's HTTP port (47989) as a wake,
+    // which is what Moonlight does whenever it lists this PC.
+    pub moonlight_probe: bool,
+}
+
+impl Default for WakeConfig {
+    fn default() -> Self {
+        Self { enabled: false, ports: vec![9, 47009], moonlight_probe: false }
+    }
+}
+
+// `[standby]` — after a while Away with nobody streaming, stop Sunshine
+// and turn the Away outputs off until a client tries to connect. Off by
+// default.
+//
+// Coming back listens the way `[wake]` does, whether or not that's
+// enabled, and always for Moonlight's probe.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StandbyConfig {
+    pub enabled: bool,
+    // How long Away has to go without a stream first.
+    #[serde(deserialize_with = "duration")]
+    pub after: Duration,
+}
+
+impl Default for StandbyConfig {
+    fn default() -> Self {
+        Self { enabled: false, after: Duration::from_secs(30 * 60) }
+    }
+}
+
+// `[virtual_output]` — what `dummy_plug = "virtual"` runs to get a
+// headless output from KWin (see virtual_output.rs).
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VirtualOutputConfig {
+    // Holds the output open while it runs; "{width}", "{height}" and
+    // "{refresh}" are filled in from `dummy_mode`.
+    pub command: Option<String>,
+    // The output name the command makes KWin create.
+    pub output: String,
+}
+
+impl Default for VirtualOutputConfig {
+    fn default() -> Self {
+        Self { command: None, output: "Virtual-vitamink".to_string() }
+    }
+}
+
+// `[gamescope]` — a nested gamescope session launched when going Away
+// (see gamescope.rs).
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GamescopeConfig {
+    pub enabled: bool,
+    // "{width}", "{height}" and "{refresh}" are filled in from the
+    // streaming mode.
+    pub command: String,
+}
+
+impl Default for GamescopeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            command: "gamescope -W {width} -H {height} -r {refresh} -f -e -- steam -gamepadui".to_string(),
+        }
+    }
+}
+
+// `[display_retry]` — kscreen-doctor (and friends) can fail for a moment
+// right after the compositor wakes from DPMS off. Failed display commands
+// are retried with exponential backoff: `initial_delay`, doubling up to
+// `max_delay`, each randomly stretched or shrunk by up to `jitter`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    // Total tries, including the first. 1 turns retrying off.
+    pub attempts: u32,
+    #[serde(deserialize_with = "duration")]
+    pub initial_delay: Duration,
+    #[serde(deserialize_with = "duration")]
+    pub max_delay: Duration,
+    // Fraction of the delay, 0.0–1.0.
+    pub jitter: f64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            initial_delay: Duration::from_millis(250),
+            max_delay: Duration::from_secs(4),
+            jitter: 0.25,
+        }
+    }
+}
+
+// `[recovery]` — a poll that fails over and over (see recovery.rs).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecoveryConfig {
+    // Failed polls in a row before the session's variables, the DRM
+    // connectors and the display backend are looked up afresh, and again
+    // after as many more. 0 never does.
+    pub after: u32,
+    // Failed polls in a row before the daemon exits nonzero, for systemd
+    // to restart it. 0 keeps it running.
+    pub exit_after: u32,
+    // How often the same error is logged again while it keeps coming.
+    #[serde(deserialize_with = "duration")]
+    pub log_every: Duration,
+}
+
+impl Default for RecoveryConfig {
+    fn default() -> Self {
+        Self { after: 10, exit_after: 0, log_every: Duration::from_secs(300) }
+    }
+}
+
+// `[load]` — what holds off Away while the machine is busy (see
+// load.rs). 0 turns each check off.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoadConfig {
+    // The 1-minute load average per CPU, in percent.
+    pub cpu_above: u32,
+    // The busiest GPU, in percent.
+    pub gpu_above: u32,
+    // Free space on the filesystem holding `disk_path`, in GB.
+    pub min_free_gb: f64,
+    pub disk_path: String,
+}
+
+impl LoadConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.cpu_above != 0 || self.gpu_above != 0 || self.min_free_gb != 0.0
+    }
+}
+
+impl Default for LoadConfig {
+    fn default() -> Self {
+        Self { cpu_above: 0, gpu_above: 0, min_free_gb: 0.0, disk_path: "~".to_string() }
+    }
+}
+
+// `"DP-2"` or `["DP-1", "DP-2"]`.
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match Raw::deserialize(deserializer)? {
+        Raw::One(name) => vec![name],
+        Raw::Many(names) => names,
+    })
+}
+
+// "10s", "2m", "1h30m". Bare integers are still read as seconds so
+// config files written before durations had units keep working. Nothing
+// here needs more than a year, and the daemon adds these to the clock
+// (and doubles `max_poll_interval`'s base), so more is a typo.
+fn duration<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Duration, D::Error> {
+    const MAX: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Seconds(u64),
+        Text(String),
+    }
+
+    let duration = match Raw::deserialize(deserializer)? {
+        Raw::Seconds(secs) => Duration::from_secs(secs),
+        Raw::Text(text) => crate::duration::parse(&text).map_err(serde::de::Error::custom)?,
+    };
+    if duration > MAX {
+        return Err(serde::de::Error::custom(format!("{} is out of range (365d at most)", crate::duration::format(duration))));
+    }
+    Ok(duration)
+}
+
+// For fields whose type implements `FromStr` — the TOML value is a string,
+// and parse errors show up as config errors with the line number.
+fn parsed<'de, D, T>(deserializer: D) -> std::result::Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    from_str(deserializer).map(Some)
+}
+
+// `parsed` for a list of strings.
+fn parsed_list<'de, D, T>(deserializer: D) -> std::result::Result<Option<Vec<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    let raw = Vec::<String>::deserialize(deserializer)?;
+    raw.iter().map(|s| s.parse().map_err(serde::de::Error::custom)).collect::<std::result::Result<Vec<T>, _>>().map(Some)
+}
+
+// `parsed` for fields that must be set.
+fn from_str<'de, D, T>(deserializer: D) -> std::result::Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    let raw = String::deserialize(deserializer)?;
+    raw.parse().map_err(serde::de::Error::custom)
+}
+
+// ---- Loading ----
+
+// `$XDG_CONFIG_HOME/vitamink/config.toml`, falling back to `~/.config`.
+pub fn default_path() -> PathBuf {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_else(|| PathBuf::from("."));
+
+    base.join("vitamink").join("config.toml")
+}
+
+// A missing file is not an error — everything has a sensible default.
+pub fn load() -> Result<Config> {
+    parse(&read_text()?).map_err(|message| VitaminkError::Config { path: default_path(), message })
+}
+
+// The config file's text; "" if there isn't one.
+pub fn read_text() -> Result<String> {
+    let path = default_path();
+
+    match fs::read_to_string(&path) {
+        Ok(text) => Ok(text),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(VitaminkError::io(path, e)),
+    }
+}
+
+// When the config file last changed, to notice edits; None if it's missing.
+pub fn modified() -> Option<SystemTime> {
+    fs::metadata(default_path()).and_then(|m| m.modified()).ok()
+}
+
+// Returns the bare TOML message; `load` attaches the path.
+pub fn parse(text: &str) -> std::result::Result<Config, String> {
+    let config: Config = toml::from_str(text).map_err(|e| e.to_string())?;
+
+    if config.main_display.is_empty() {
+        return Err("main_display lists no outputs".to_string());
+    }
+    if config.poll_interval.is_zero() {
+        return Err("poll_interval must be longer than 0s".to_string());
+    }
+    if config.on_dummy_unplug == UnplugAction::Virtual && config.virtual_output.command.is_none() {
+        return Err("on_dummy_unplug = \"virtual\" needs [virtual_output] command".to_string());
+    }
+    if config.gamescope.enabled && config.gamescope.command.trim().is_empty() {
+        return Err("[gamescope] enabled needs a command".to_string());
+    }
+    if [config.ddc.brightness, config.ddc.contrast].iter().flatten().any(|&v| v > 100) {
+        return Err("ddc brightness and contrast must be between 0 and 100".to_string());
+    }
+    if config.dummy_plug == virtual_output::VIRTUAL && config.virtual_output.command.is_none() {
+        return Err("dummy_plug = \"virtual\" needs [virtual_output] command".to_string());
+    }
+    if config.main_display.contains(&config.dummy_plug) {
+        return Err(format!("main_display and dummy_plug are both \"{}\"", config.dummy_plug));
+    }
+    let scales = config.profiles.values().flat_map(|p| &p.enable).map(|o| o.scale);
+    if [config.dummy_scale].into_iter().chain(scales).flatten().any(|s| !(s > 0.0 && s <= 4.0)) {
+        return Err("scales must be between 0 and 4".to_string());
+    }
+    for (i, custom) in config.custom_modes.iter().enumerate() {
+        let mode = &custom.mode;
+        if !mode.refresh.is_some_and(|hz| hz > 0.0 && hz <= 1000.0) {
+            return Err(format!("custom mode {mode} for {} needs a refresh rate, like \"{}x{}@120\"", custom.output, mode.width, mode.height));
+        }
+        if !(1..=16384).contains(&mode.width) || !(1..=16384).contains(&mode.height) {
+            return Err(format!("custom mode {mode} for {} is no size a display has", custom.output));
+        }
+        if config.custom_modes[..i].iter().any(|other| other.output == custom.output && other.mode == custom.mode) {
+            return Err(format!("custom mode {mode} for {} is listed twice", custom.output));
+        }
+    }
+    if config.wake.enabled && config.wake.ports.is_empty() && !config.wake.moonlight_probe {
+        return Err("[wake] needs ports or moonlight_probe".to_string());
+    }
+    if config.standby.enabled && config.standby.after.is_zero() {
+        return Err("[standby] after must be longer than 0s".to_string());
+    }
+    if config.sunshine.nvenc_sessions == 0 {
+        return Err("[sunshine] nvenc_sessions must be at least 1".to_string());
+    }
+    for hook in &config.webhooks {
+        let url = &hook.url;
+        if !url.starts_with("https://") && !url.starts_with("http://") {
+            return Err(format!("webhook \"{url}\" must be an http:// or https:// URL"));
+        }
+        match (hook.format, &hook.chat_id) {
+            (WebhookFormat::Telegram, None) => return Err("a format = \"telegram\" webhook needs a chat_id".to_string()),
+            (WebhookFormat::Generic | WebhookFormat::Discord, Some(_)) => {
+                return Err("chat_id is only used with format = \"telegram\"".to_string());
+            }
+            _ => {}
+        }
+        if hook.events.contains(&NotifyEvent::Pending) {
+            return Err("webhooks can't post \"pending\" (it's the desktop countdown)".to_string());
+        }
+    }
+    if let Some((name, _)) = config.profiles.iter().find(|(_, p)| p.enable.is_empty()) {
+        return Err(format!("profile \"{name}\" enables no outputs"));
+    }
+    if let Some(name) = &config.profile
+        && !config.profiles.contains_key(name)
+    {
+        return Err(format!("profile \"{name}\" is not defined under [profiles]"));
+    }
+    let mut units: Vec<&str> = config.services.iter().map(|s| s.unit.as_str()).collect();
+    units.sort_unstable();
+    if let Some(unit) = units.windows(2).find(|w| w[0] == w[1]).map(|w| w[0]) {
+        return Err(format!("service \"{unit}\" is listed twice"));
+    }
+    for service in &config.services {
+        let (unit, tuning) = (&service.unit, &service.tuning);
+        if tuning.is_enabled() && service.scope == Scope::System {
+            return Err(format!("service \"{unit}\": tuning needs scope = \"user\""));
+        }
+        if tuning.is_enabled() && service.manager != Manager::Systemd {
+            return Err(format!("service \"{unit}\": tuning needs manager = \"systemd\""));
+        }
+        match (service.manager, &service.command) {
+            (Manager::Process, None) => return Err(format!("service \"{unit}\": manager = \"process\" needs a command")),
+            (Manager::Process, Some(_)) if unit.contains('/') => {
+                return Err(format!("service \"{unit}\": manager = \"process\" needs a plain name as unit"));
+            }
+            (Manager::Process, Some(_)) => {}
+            (_, Some(_)) => return Err(format!("service \"{unit}\": command is only used with manager = \"process\"")),
+            (Manager::S6, None) if !unit.starts_with('/') => {
+                return Err(format!("service \"{unit}\": manager = \"s6\" needs the service directory's path as unit"));
+            }
+            (_, None) => {}
+        }
+        if tuning.nice.is_some_and(|n| !(-20..=19).contains(&n)) {
+            return Err(format!("service \"{unit}\": nice must be between -20 and 19"));
+        }
+        if tuning.io_priority.is_some_and(|p| p > 7) {
+            return Err(format!("service \"{unit}\": io_priority must be between 0 and 7"));
+        }
+        if let Some(priority) = tuning.cpu_scheduling_priority {
+            if !matches!(tuning.cpu_scheduling, Some(SchedPolicy::Fifo | SchedPolicy::Rr)) {
+                return Err(format!("service \"{unit}\": cpu_scheduling_priority needs cpu_scheduling = \"fifo\" or \"rr\""));
+            }
+            if !(1..=99).contains(&priority) {
+                return Err(format!("service \"{unit}\": cpu_scheduling_priority must be between 1 and 99"));
+            }
+        }
+    }
+    if let Some(name) = &config.slice.name {
+        if !name.ends_with(".slice") {
+            return Err(format!("slice.name \"{name}\" must end in \".slice\""));
+        }
+        if [config.slice.cpu_weight, config.slice.io_weight].iter().flatten().any(|w| !(1..=10000).contains(w)) {
+            return Err("slice weights must be between 1 and 10000".to_string());
+        }
+        for unit in &config.slice.units {
+            let base = |u: &str| u.trim_end_matches(".service").to_string();
+            match config.services.iter().find(|s| base(&s.unit) == base(unit)) {
+                None => return Err(format!("slice.units: \"{unit}\" is not one of the [[services]]")),
+                Some(s) if s.scope == Scope::System => return Err(format!("slice.units: \"{unit}\" needs scope = \"user\"")),
+                Some(s) if s.manager != Manager::Systemd => {
+                    return Err(format!("slice.units: \"{unit}\" needs manager = \"systemd\""));
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    let wants_camera = match &config.away_when {
+        Some(condition) => condition.uses(Term::CameraEmpty),
+        None => config.presence_backend == PresenceBackend::Camera,
+    };
+    if wants_camera && config.camera.command.is_none() {
+        return Err("camera presence needs [camera] command".to_string());
+    }
+    let wants_bluetooth = match &config.away_when {
+        Some(condition) => condition.uses(Term::BluetoothAway),
+        None => config.presence_backend == PresenceBackend::Bluetooth,
+    };
+    if wants_bluetooth && config.bluetooth.devices.is_empty() {
+        return Err("bluetooth presence needs [bluetooth] devices".to_string());
+    }
+    if let Some(address) = config.bluetooth.devices.iter().find(|a| !bluetooth::is_address(a)) {
+        return Err(format!("[bluetooth] devices: \"{address}\" isn't an address like AA:BB:CC:DD:EE:FF"));
+    }
+    if config.sunshine.on_error != OnError::Off && config.sunshine.error_patterns.iter().all(|p| p.trim().is_empty()) {
+        return Err("[sunshine] on_error needs error_patterns, or set it to \"off\"".to_string());
+    }
+    let names = config.environment.set.keys().chain(config.environment.inherit.iter().flatten());
+    if let Some(name) = names.into_iter().find(|name| name.is_empty() || name.contains(['=', '\0'])) {
+        return Err(format!("[environment]: \"{name}\" isn't a variable name"));
+    }
+    if let Some(seat) = &config.seat.seat
+        && !seat.starts_with("seat")
+    {
+        return Err(format!("[seat] seat: \"{seat}\" isn't a seat name like seat0 (`loginctl list-seats`)"));
+    }
+    if let Some(kind) = config.seat.session_types.iter().find(|t| !seat::SESSION_TYPES.contains(&t.as_str())) {
+        return Err(format!("[seat] session_types: \"{kind}\" isn't one of {}", seat::SESSION_TYPES.join(", ")));
+    }
+    if let Some(profile) = config.power.profile.as_ref().filter(|p| !power::PROFILES.contains(&p.as_str())) {
+        return Err(format!("[power] profile: \"{profile}\" isn't one of {}", power::PROFILES.join(", ")));
+    }
+    if let Some(name) = config.desk_layout.as_ref().filter(|name| !layout::is_preset_name(name)) {
+        return Err(format!("desk_layout: \"{name}\" isn't a layout name; use letters, digits, '-', '_' and '.'"));
+    }
+    if let Some(away) = &config.transition.away {
+        pipeline::check(away, false).map_err(|e| format!("[transition] away {e}"))?;
+    }
+    if let Some(desk) = &config.transition.desk {
+        pipeline::check(desk, true).map_err(|e| format!("[transition] desk {e}"))?;
+    }
+    if config.apps.launch.is_some() && (config.sunshine.api_username.is_none() || config.sunshine.api_password.is_none()) {
+        return Err("[apps] launch needs [sunshine] api_username and api_password".to_string());
+    }
+    if config.http.token.as_ref().is_some_and(|t| t.trim().is_empty() || t.contains(char::is_whitespace)) {
+        return Err("[http] token can't be empty or contain spaces".to_string());
+    }
+    if config.http.enabled && config.http.token.is_none() && !config.http.listen.ip().is_loopback() {
+        return Err(format!("[http] listen = \"{}\" is reachable from other machines, so it needs a token", config.http.listen));
+    }
+    for (name, host) in &config.hosts {
+        if !host.url.starts_with("http://") && !host.url.starts_with("https://") {
+            return Err(format!("[hosts.{name}] url = \"{}\" must be an http:// or https:// URL", host.url));
+        }
+    }
+    if config.away_when.as_ref().is_some_and(|c| c.uses(Term::ProcessRunning)) && config.processes.is_empty() {
+        return Err("process_running needs `processes`".to_string());
+    }
+    if let Some(rule) = config.schedule.iter().find(|r| r.action == ScheduleAction::Suppress && r.to.is_none()) {
+        return Err(format!("schedule: the \"suppress\" rule from {} needs a `to`", rule.from));
+    }
+    if let Some(rule) = config.schedule.iter().find(|r| r.to == Some(r.from)) {
+        return Err(format!("schedule: the rule from {} ends when it starts", rule.from));
+    }
+    if config.display_retry.attempts == 0 {
+        return Err("display_retry.attempts must be at least 1".to_string());
+    }
+    if !(0.0..=1.0).contains(&config.display_retry.jitter) {
+        return Err("display_retry.jitter must be between 0.0 and 1.0".to_string());
+    }
+    let recovery = &config.recovery;
+    if recovery.exit_after != 0 && recovery.after != 0 && recovery.exit_after <= recovery.after {
+        return Err(format!("recovery.exit_after ({}) must be more than recovery.after ({}), or recovering never gets a try", recovery.exit_after, recovery.after));
+    }
+    if config.load.min_free_gb < 0.0 {
+        return Err("load.min_free_gb can't be negative".to_string());
+    }
+    Ok(config)
+}
+
+// ---- Checking Against the Hardware ----
+
+impl Config {
+    // The first `main_display`; `parse` makes sure there is one.
+    pub fn primary_display(&self) -> &str {
+        &self.main_display[0]
+    }
+
+    // What's wrong with the configured outputs given what the compositor
+    // reports right now: each must exist (unless in `allow_missing`), and
+    // each output Away turns on must offer at least one mode. Run at daemon
+    // startup so a typo fails there rather than halfway through going Away.
+    pub fn check_outputs(&self, displays: &[display::Display]) -> Vec<String> {
+        // (where it's configured, output, whether Away turns it on)
+        let mut outputs: Vec<(String, &str, bool)> =
+            self.main_display.iter().map(|name| ("main_display".to_string(), name.as_str(), false)).collect();
+        if self.dummy_plug != crate::dummy::AUTO && self.dummy_plug != virtual_output::VIRTUAL {
+            outputs.push(("dummy_plug".to_string(), &self.dummy_plug, true));
+        }
+        if let Some(name) = &self.away_primary {
+            outputs.push(("away_primary".to_string(), name, false));
+        }
+        for (name, profile) in &self.profiles {
+            let setting = format!("profile \"{name}\"");
+            outputs.extend(profile.enable.iter().map(|o| (setting.clone(), o.name.as_str(), true)));
+            outputs.extend(profile.disable.iter().map(|o| (setting.clone(), o.as_str(), false)));
+        }
+
+        let known: Vec<&str> = displays.iter().map(|d| d.name.as_str()).collect();
+        let mut problems = Vec::new();
+        for (setting, output, turned_on) in outputs {
+            if self.allow_missing.iter().any(|m| m == output) {
+                continue;
+            }
+            match displays.iter().find(|d| d.name == output) {
+                None => match known.iter().find(|name| loosely(name) == loosely(output)) {
+                    Some(name) => problems.push(format!("{setting}: no output named \"{output}\" (did you mean \"{name}\"?)")),
+                    None => problems.push(format!("{setting}: no output named \"{output}\" (found: {})", known.join(", "))),
+                },
+                Some(d) if turned_on && d.modes.is_empty() => {
+                    problems.push(format!("{setting}: {output} reports no modes — is the plug seated?"))
+                }
+                Some(_) => {}
+            }
+        }
+        problems
+    }
+}
+
+// An output name without case or punctuation, so "HDMI-A1" and "hdmi-a-1"
+// can be pointed at HDMI-A-1.
+fn loosely(name: &str) -> String {
+    name.chars().filter(|c| c.is_ascii_alphanumeric()).map(|c| c.to_ascii_lowercase()).collect()
+}
+
+// ---- Reloading ----
+//
+// The daemon re-reads the file on SIGHUP or when it changes on disk (see
+// daemon.rs). Most settings are looked up as they're needed and take
+// effect on the next poll; these are set up once at startup.
+pub const RESTART_SETTINGS: &[&str] =
+    &["display_backend", "wayland_display", "x11_display", "display_retry", "environment", "max_poll_interval", "metrics", "http", "mqtt", "startup_timeout"];
+
+// The settings that differ between two versions of the file, as
+// "section.key: old → new" lines. Compares what's written, so a setting
+// that's taken out shows as going back to its default.
+pub fn diff(old: &str, new: &str) -> Vec<String> {
+    let (old, new) = (flatten(old), flatten(new));
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    keys.into_iter()
+        .filter_map(|key| match (old.get(key), new.get(key)) {
+            (Some(a), Some(b)) if a == b => None,
+            (a, b) => {
+                let show = |v: Option<&String>| v.cloned().unwrap_or_else(|| "(default)".to_string());
+                Some(format!("{key}: {} → {}", show(a), show(b)))
+            }
+        })
+        .collect()
+}
+
+// Whether a `diff` line is about a setting in RESTART_SETTINGS.
+pub fn needs_restart(change: &str) -> bool {
+    let key = change.split(':').next().unwrap_or_default();
+    RESTART_SETTINGS.iter().any(|setting| key == *setting || key.starts_with(&format!("{setting}.")))
+}
+
+// Dotted key → value. Arrays (like `[[services]]`) count as one value.
+fn flatten(text: &str) -> BTreeMap<String, String> {
+    fn walk(prefix: &str, table: &toml::Table, out: &mut BTreeMap<String, String>) {
+        for (key, value) in table {
+            let key = if prefix.is_empty() { key.clone() } else { format!("{prefix}.{key}") };
+            match value {
+                toml::Value::Table(table) => walk(&key, table, out),
+                value => {
+                    out.insert(key, value.to_string());
+                }
+            }
+        }
+    }
+
+    let mut out = BTreeMap::new();
+    if let Ok(table) = text.parse::<toml::Table>() {
+        walk("", &table, &mut out);
+    }
+    out
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let old = "grace_period = \"10s\"\n[mqtt]\nenabled = true\n[sunshine]\nready_timeout = \"30s\"\n";
+        let new = "grace_period = \"30s\"\ndock_settle = \"5s\"\n[mqtt]\nenabled = false\n";
+        let changes = diff(old, new);
+        assert_eq!(
+            changes,
+            [
+                "dock_settle: (default) → \"5s\"",
+                "grace_period: \"10s\" → \"30s\"",
+                "mqtt.enabled: true → false",
+                "sunshine.ready_timeout: \"30s\" → (default)",
+            ]
+        );
+        assert_eq!(changes.iter().filter(|c| needs_restart(c)).count(), 1);
+        assert!(!needs_restart("mqtt_extra: 1 → 2"));
+        assert!(diff(old, old).is_empty());
+    }
+
+    #[test]
+    fn test_default_config() {
+        let config = Config::default();
+        assert_eq!(config.main_display, ["DP-2"]);
+        assert_eq!(config.dummy_plug, "HDMI-A-1");
+        assert_eq!(config.poll_interval, Duration::from_secs(5));
+        assert_eq!(config.grace_period, Duration::from_secs(10));
+        assert!(!config.input_gating.enabled);
+    }
+
+    #[test]
+    fn test_parse_partial() {
+        let config = parse(
+            "dummy_plug = \"HDMI-A-2\"\n\
+             grace_period = \"1m30s\"\n\
+             poll_interval = 3\n\
+             dummy_mode = \"3840x2160@60\"\n\
+             presence_backend = \"logind\"\n\
+             on_shutdown = \"restore\"\n\
+             away_when = \"locked and dpms_off\"\n\
+             [input_gating]\n\
+             enabled = true\n\
+             pointers = false\n\
+             [notifications]\n\
+             enabled = true\n\
+             events = [\"error\"]\n\
+             error_urgency = \"normal\"\n\
+             [display_retry]\n\
+             attempts = 5\n\
+             initial_delay = \"100ms\"\n\
+             [[services]]\n\
+             unit = \"sunshine\"\n\
+             tuning = { nice = -5, cpu_affinity = \"0-3\", cpu_scheduling = \"rr\", cpu_scheduling_priority = 20 }\n\
+             [[services]]\n\
+             unit = \"wayvnc.service\"\n\
+             scope = \"system\"\n\
+             order = 10\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.main_display, ["DP-2"]);
+        assert_eq!(config.main_display_logic, Aggregation::AllOff);
+        assert_eq!(config.dummy_plug, "HDMI-A-2");
+        assert_eq!(config.grace_period, Duration::from_secs(90));
+        assert_eq!(config.poll_interval, Duration::from_secs(3));
+        assert_eq!(config.dummy_mode.unwrap().to_string(), "3840x2160@60");
+        assert_eq!(config.presence_backend, PresenceBackend::Logind);
+        assert_eq!(config.on_shutdown, ShutdownAction::Restore);
+        assert_eq!(config.away_when.unwrap().to_string(), "locked and dpms_off");
+        assert!(config.input_gating.enabled);
+        assert!(config.input_gating.keyboards);
+        assert!(!config.input_gating.pointers);
+        assert!(config.notifications.enabled);
+        assert_eq!(config.notifications.events, vec![NotifyEvent::Error]);
+        assert_eq!(config.notifications.error_urgency, Urgency::Normal);
+        assert_eq!(config.display_retry.attempts, 5);
+        assert_eq!(config.display_retry.initial_delay, Duration::from_millis(100));
+        assert_eq!(config.display_retry.max_delay, Duration::from_secs(4));
+        assert_eq!(config.services.len(), 2);
+        assert_eq!(config.services[0].scope, Scope::User);
+        assert_eq!(config.services[0].tuning.nice, Some(-5));
+        assert_eq!(config.services[0].tuning.cpu_scheduling, Some(SchedPolicy::Rr));
+        assert!(!config.services[1].tuning.is_enabled());
+        assert_eq!(config.services[1].scope, Scope::System);
+        assert_eq!(config.services[1].order, 10);
+    }
+
+    #[test]
+    fn test_parse_profiles() {
+        let config = parse(
+            "profile = \"tv\"\n\
+             [profiles.tv]\n\
+             enable = [{ name = \"HDMI-A-1\", mode = \"3840x2160@60\" }]\n\
+             disable = [\"HDMI-A-2\"]\n\
+             [profiles.phone]\n\
+             enable = [{ name = \"HDMI-A-2\" }]\n",
+        )
+        .unwrap();
+
+        let tv = &config.profiles["tv"];
+        assert_eq!(tv.enable[0].name, "HDMI-A-1");
+        assert_eq!(tv.enable[0].mode.unwrap().to_string(), "3840x2160@60");
+        assert_eq!(tv.disable, vec!["HDMI-A-2"]);
+        assert_eq!(config.profiles["phone"].enable[0].mode, None);
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown_fields() {
+        assert!(parse("grace_perod = 30").is_err());
+        assert!(parse("dummy_mode = \"4k\"").is_err());
+        assert!(parse("presence_backend = \"webcam\"").is_err());
+        assert!(parse("grace_period = \"10 seconds\"").is_err());
+        assert!(parse("[notifications]\nurgency = \"urgent\"").is_err());
+        assert!(parse("profile = \"tv\"").is_err());
+        assert!(parse("[profiles.tv]\nenable = []").is_err());
+        assert!(parse("main_display = \"HDMI-A-1\"").is_err());
+        assert!(parse("main_display = [\"DP-1\", \"HDMI-A-1\"]").is_err());
+        assert!(parse("main_display = []").is_err());
+        assert!(parse("dummy_plug = \"virtual\"").is_err());
+        assert!(parse("dummy_plug = \"virtual\"\n[virtual_output]\ncommand = \"krfb-virtualmonitor\"").is_ok());
+        assert!(parse("[gamescope]\nenabled = true\ncommand = \"\"").is_err());
+        assert!(parse("[gamescope]\nenabled = true").is_ok());
+        assert!(parse("[ddc]\nbrightness = 101").is_err());
+        assert!(parse("[ddc]\nbrightness = 70\ninput_source = 15").is_ok());
+        assert!(parse("on_dummy_unplug = \"virtual\"").is_err());
+        assert!(parse("on_dummy_unplug = \"virtual\"\n[virtual_output]\ncommand = \"krfb-virtualmonitor\"").is_ok());
+        assert_eq!(parse("on_drift = \"pause\"").unwrap().on_drift, OnDrift::Pause);
+        assert!(parse("on_drift = \"ignore\"").is_err());
+        assert_eq!(parse("away_outputs_off = \"dpms-off\"").unwrap().away_outputs_off, OffStrategy::DpmsOff);
+        assert!(parse("main_display_logic = \"most_off\"").is_err());
+        let config = parse("main_display = [\"DP-1\", \"DP-2\", \"DP-3\"]\nmain_display_logic = \"any_off\"").unwrap();
+        assert_eq!(config.main_display, ["DP-1", "DP-2", "DP-3"]);
+        assert_eq!(config.primary_display(), "DP-1");
+        assert_eq!(config.main_display_logic, Aggregation::AnyOff);
+        assert!(parse("presence_backend = \"camera\"").is_err());
+        assert!(parse("[[services]]\nunit = \"wayvnc\"\n[[services]]\nunit = \"wayvnc\"").is_err());
+        assert!(parse("[[services]]\nscope = \"system\"").is_err());
+        assert!(parse("[[services]]\nunit = \"sunshine\"\ntuning = { nice = -30 }").is_err());
+        assert!(parse("[[services]]\nunit = \"sunshine\"\ntuning = { cpu_scheduling_priority = 10 }").is_err());
+        assert!(parse("[[services]]\nunit = \"sunshine\"\nscope = \"system\"\ntuning = { nice = -5 }").is_err());
+        assert!(parse("[slice]\nname = \"stream\"").is_err());
+        assert!(parse("[[schedule]]\naction = \"suppress\"\nfrom = \"02:00\"").is_err());
+        assert!(parse("[[schedule]]\naction = \"away\"\nfrom = \"9am\"").is_err());
+        assert!(parse("[[schedule]]\naction = \"away\"\ndays = \"mon-fri\"\nfrom = \"09:00\"").is_ok());
+        assert!(parse("[slice]\nname = \"stream.slice\"\nunits = [\"wayvnc\"]").is_err());
+        assert!(parse("[slice]\nname = \"stream.slice\"\ncpu_weight = 0").is_err());
+        assert!(parse("[slice]\nname = \"stream.slice\"\ncpu_weight = 1000").is_ok());
+        assert!(parse("away_when = \"locked or camera_empty\"").is_err());
+        assert!(parse("presence_backend = \"camera\"\n[camera]\ncommand = \"person-check\"").is_ok());
+        assert!(parse("away_when = \"dpms_off and not process_running\"").is_err());
+        assert!(parse("presence_backend = \"bluetooth\"").is_err());
+        assert!(parse("presence_backend = \"bluetooth\"\n[bluetooth]\ndevices = [\"AA:BB:CC\"]").is_err());
+        assert!(parse("away_when = \"dpms_off or bluetooth_away\"\n[bluetooth]\ndevices = [\"AA:BB:CC:DD:EE:FF\"]").is_ok());
+        assert!(parse("[seat]\nseat = \"seat1\"\nsession_types = [\"wayland\", \"x11\"]").is_ok());
+        assert!(parse("[seat]\nseat = \"HDMI-A-1\"").is_err());
+        assert!(parse("[environment]\ninherit = [\"PATH\"]\nset = { XDG_RUNTIME_DIR = \"/run/user/1000\" }").is_ok());
+        assert!(parse("[environment]\nset = { \"A=B\" = \"C\" }").is_err());
+        assert!(parse("[seat]\nsession_types = [\"kde\"]").is_err());
+        assert!(parse("away_when = \"dpms_off and not on_battery\"\n[power]\nprofile = \"performance\"").is_ok());
+        assert!(parse("[power]\nprofile = \"turbo\"").is_err());
+        let steps = "\"privacy\", \"layout\", \"outputs\", \"wait 2s\", \"services\", \"run lights dim\", \"gamescope\", \"app\", \"desktop\", \"compositor\", \"power\", \"audio\", \"input\"";
+        let config = parse(&format!("[transition]\naway = [{steps}]")).unwrap();
+        assert_eq!(config.transition.away().len(), 13);
+        assert_eq!(config.transition.desk().first(), Some(&Stage::Step(pipeline::Step::Input)));
+        assert!(parse(&format!("[transition]\naway = [{}]", steps.replace("\"layout\", ", ""))).is_err());
+        assert!(parse(&format!("[transition]\ndesk = [{steps}]")).is_err());
+        assert!(parse("[transition]\naway = [\"sunshine\"]").is_err());
+        let late = steps.replace("\"privacy\", ", "").replace("\"input\"", "\"input\", \"privacy\"");
+        assert!(parse(&format!("[transition]\naway = [{late}]")).unwrap_err().contains("the session has to be locked before Sunshine starts"));
+        assert!(parse(&format!("[privacy]\nlock_session = true\n[transition]\naway = [{steps}]")).is_ok());
+        let privacy = parse("[privacy]\nlock_session = true\nlock_timeout = \"10s\"").unwrap().privacy;
+        assert!(privacy.is_enabled());
+        assert_eq!(privacy.lock_timeout, Duration::from_secs(10));
+        assert_eq!(parse("").unwrap().privacy.lock_timeout, Duration::from_secs(5));
+        assert!(parse("[privacy]\nlock_timeout = \"soon\"").is_err());
+        assert_eq!(parse("[windows]\nrestore = true\nsettle = \"3s\"").unwrap().windows.settle, Duration::from_secs(3));
+        assert!(parse("[windows]\nsettle = \"soon\"").is_err());
+
+        assert_eq!(parse("recent_input = \"30s\"").unwrap().recent_input, Duration::from_secs(30));
+        assert_eq!(Config::default().recent_input, Duration::ZERO);
+        assert_eq!(parse("log_format = \"json\"").unwrap().log_format, LogFormat::Json);
+        let custom = parse("[[custom_modes]]\noutput = \"HDMI-A-1\"\nmode = \"1920x1080@120\"\nreduced_blanking = true").unwrap().custom_modes;
+        assert_eq!(custom[0].mode, ModeTarget { width: 1920, height: 1080, refresh: Some(120.0) });
+        assert!(custom[0].reduced_blanking);
+        assert!(parse("custom_modes = [{ output = \"HDMI-A-1\", mode = \"1920x1080\" }]").unwrap_err().contains("needs a refresh rate"));
+        assert!(parse("custom_modes = [{ output = \"HDMI-A-1\", mode = \"0x1080@60\" }]").is_err());
+        assert!(parse("custom_modes = [{ output = \"HDMI-A-1\", mode = \"fast\" }]").is_err());
+        let twice = "custom_modes = [{ output = \"HDMI-A-1\", mode = \"1920x1080@120\" }, { output = \"HDMI-A-1\", mode = \"1920x1080@120\" }]";
+        assert!(parse(twice).unwrap_err().contains("twice"));
+        assert!(parse("log_format = \"logfmt\"").is_err());
+
+        let recovery = parse("[recovery]\nafter = 5\nexit_after = 20\nlog_every = \"1m\"").unwrap().recovery;
+        assert_eq!((recovery.after, recovery.exit_after, recovery.log_every), (5, 20, Duration::from_secs(60)));
+        assert!(parse("[recovery]\nafter = 5\nexit_after = 5").is_err());
+        assert!(parse("[recovery]\nafter = 0\nexit_after = 5").is_ok());
+        let load = parse("[load]\ncpu_above = 90\nmin_free_gb = 20\ndisk_path = \"~/Videos\"").unwrap().load;
+        assert!(load.is_enabled() && load.gpu_above == 0);
+        assert!(!Config::default().load.is_enabled());
+        assert!(parse("[load]\nmin_free_gb = -1.0").is_err());
+        assert!(parse("[apps]\nlaunch = \"Steam Big Picture\"").is_err());
+        assert!(parse("[apps]\nlaunch = \"Steam Big Picture\"\n[sunshine]\napi_username = \"admin\"\napi_password = \"pw\"").is_ok());
+        assert!(parse("desk_layout = \"docked\"").is_ok());
+        assert!(parse("desk_layout = \"../state\"").is_err());
+        assert!(parse("[http]\nenabled = true").is_ok());
+        assert!(parse("[http]\nenabled = true\nlisten = \"0.0.0.0:9189\"").is_err());
+        assert!(parse("[http]\nenabled = true\nlisten = \"0.0.0.0:9189\"\ntoken = \"s3cret\"").is_ok());
+        assert!(parse("[http]\ntoken = \"\"").is_err());
+        assert!(parse("poll_interval = \"0s\"").is_err());
+        assert!(parse("grace_period = \"999999999999999999d\"").unwrap_err().contains("out of range"));
+        assert!(parse("max_poll_interval = \"400d\"").unwrap_err().contains("365d at most"));
+        assert!(parse("max_poll_interval = 18446744073709551615").is_err());
+        let hosts = parse("[hosts.den-pc]\nurl = \"http://den-pc:9189\"\ntoken = \"s3cret\"").unwrap().hosts;
+        assert_eq!(hosts["den-pc"].token.as_deref(), Some("s3cret"));
+        assert!(parse("[hosts.den-pc]\nurl = \"den-pc:9189\"").is_err());
+        assert!(parse("processes = [\"steam\"]\naway_when = \"dpms_off and not process_running\"").is_ok());
+        assert!(parse("[standby]\nenabled = true\nafter = \"0s\"").is_err());
+        assert!(parse("[standby]\nenabled = true\nafter = \"45m\"").is_ok());
+        assert!(parse("[sunshine]\nencoder_check = \"abort\"\nnvenc_sessions = 3").is_ok());
+        assert!(parse("[sunshine]\nnvenc_sessions = 0").is_err());
+        assert!(parse("[sunshine]\nencoder_check = \"sometimes\"").is_err());
+        assert!(parse("[sunshine]\non_error = \"restart\"\nerror_patterns = [\"Error: NvEnc\"]").is_ok());
+        assert!(parse("[sunshine]\nerror_patterns = []").is_err());
+        assert!(parse("[sunshine]\non_error = \"off\"\nerror_patterns = []").is_ok());
+        assert!(parse("[[services]]\nunit = \"sunshine\"\nmanager = \"process\"").is_err());
+        assert!(parse("[[services]]\nunit = \"sunshine\"\nmanager = \"process\"\ncommand = \"sunshine\"").is_ok());
+        assert!(parse("[[services]]\nunit = \"sunshine\"\ncommand = \"sunshine\"").is_err());
+        assert!(parse("[[services]]\nunit = \"sunshine\"\nmanager = \"s6\"").is_err());
+        assert!(parse("[[services]]\nunit = \"/run/service/sunshine\"\nmanager = \"s6\"").is_ok());
+        assert!(parse("[[services]]\nunit = \"sunshine\"\nmanager = \"runit\"\ntuning = { nice = -5 }").is_err());
+        assert!(parse("[[webhooks]]\nurl = \"https://example.com/hook\"").is_ok());
+        assert!(parse("[[webhooks]]\nurl = \"example.com/hook\"").is_err());
+        assert!(parse("[[webhooks]]\nurl = \"https://api.telegram.org/botX/sendMessage\"\nformat = \"telegram\"").is_err());
+        assert!(parse("[[webhooks]]\nurl = \"https://api.telegram.org/botX/sendMessage\"\nformat = \"telegram\"\nchat_id = \"42\"").is_ok());
+        assert!(parse("[[webhooks]]\nurl = \"https://example.com/hook\"\nevents = [\"pending\"]").is_err());
+    }
+
+    #[test]
+    fn test_check_outputs() {
+        use crate::display::{Color, ConnectionState, Display, DisplayState, Mode};
+
+        let display = |name: &str, modes: usize| Display {
+            index: 0,
+            name: name.into(),
+            uuid: None,
+            state: DisplayState::Disabled,
+            connection: ConnectionState::Connected,
+            modes: (0..modes as u32)
+                .map(|id| Mode { id, width: 1920, height: 1080, refresh: 60.0, preferred: false, current: false })
+                .collect(),
+            geometry: None,
+            scale: None,
+            priority: None,
+            color: Color::default(),
+            vrr: None,
+            rotation: None,
+            overscan: None,
+        };
+
+        let config = parse("[profiles.tv]\nenable = [{ name = \"HDMI-A-2\" }]\ndisable = [\"DP-3\"]").unwrap();
+        assert!(config.check_outputs(&[display("DP-2", 1), display("HDMI-A-1", 1), display("HDMI-A-2", 1), display("DP-3", 0)]).is_empty());
+
+        let problems = config.check_outputs(&[display("DP-2", 1), display("HDMI-A-1", 0)]);
+        assert_eq!(
+            problems,
+            vec![
+                "dummy_plug: HDMI-A-1 reports no modes — is the plug seated?",
+                "profile \"tv\": no output named \"HDMI-A-2\" (found: DP-2, HDMI-A-1)",
+                "profile \"tv\": no output named \"DP-3\" (found: DP-2, HDMI-A-1)",
+            ]
+        );
+
+        let config = parse("allow_missing = [\"HDMI-A-1\"]").unwrap();
+        assert!(config.check_outputs(&[display("DP-2", 1)]).is_empty());
+
+        let config = parse("dummy_plug = \"HDMI-A1\"").unwrap();
+        assert_eq!(config.check_outputs(&[display("DP-2", 1), display("HDMI-A-1", 1)]), ["dummy_plug: no output named \"HDMI-A1\" (did you mean \"HDMI-A-1\"?)"]);
+    }
+}
@@ -0,0 +1,263 @@
+// src/control.rs — Line-based JSON control over a Unix socket
+//
+// For scripts and tools that don't speak D-Bus (a phone's SSH shortcut,
+// say), the daemon also listens on `$XDG_RUNTIME_DIR/vitamink.sock`. Each
+// request is one JSON object on a line, and gets one line back:
+//
+//   {"command":"status"}                        → {"ok":true,"status":{...}}
+//   {"command":"force-state","state":"away"}    → {"ok":true}   ("desk" too)
+//   {"command":"inhibit","name":"game","reason":"Playing","seconds":3600}
+//   {"command":"release","name":"game"}
+//   {"command":"client-mode","mode":"1280x720@60"}  ("" once the stream is over)
+//   {"command":"subscribe"}                     → {"ok":true,"state":"AtDesk"}
+//
+// After `subscribe` the connection also gets {"event":"state","state":...}
+// whenever the state changes, and {"event":"countdown","pending":...}
+// (status.json's `pending`, null once it's over) during a grace period,
+// until the client hangs up. Anything that
+// can't be handled gets {"ok":false,"error":"..."}. From a shell:
+//
+//   echo '{"command":"force-state","state":"away"}' | socat - UNIX-CONNECT:$XDG_RUNTIME_DIR/vitamink.sock
+//
+// Like the D-Bus service, requests are turned into `Command`s for the main
+// loop, and `status` answers from status.json, so it works even while the
+// loop is busy. The socket is only accessible to its owner.
+
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use log::{debug, warn};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::daemon::{Command, State};
+use crate::error::{Result, VitaminkError};
+use crate::status::{self, Pending};
+
+// A subscriber that stops reading gets dropped rather than holding up
+// the main loop.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum Request {
+    Status,
+    ForceState {
+        state: Target,
+    },
+    Inhibit {
+        name: String,
+        #[serde(default)]
+        reason: String,
+        // 0 holds until released.
+        #[serde(default)]
+        seconds: u64,
+    },
+    Release {
+        name: String,
+    },
+    // Like `vitamink ctl client-mode`.
+    ClientMode {
+        #[serde(default)]
+        mode: String,
+    },
+    Subscribe,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    Away,
+    Desk,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    subscribers: Mutex<Vec<UnixStream>>,
+    commands: Sender<Command>,
+}
+
+// Handle to the listening socket. Dropping it removes the socket file.
+pub struct Server {
+    path: PathBuf,
+    shared: Arc<Shared>,
+}
+
+// `$XDG_RUNTIME_DIR/vitamink.sock`, like status.json falling back to /tmp.
+pub fn path() -> PathBuf {
+    env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from).unwrap_or_else(env::temp_dir).join("vitamink.sock")
+}
+
+impl Server {
+    pub fn start(state: State, commands: Sender<Command>) -> Result<Self> {
+        let path = path();
+        // Only one daemon runs at a time (see instance.rs), so a socket
+        // that's already there was left behind by one that crashed.
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).map_err(|e| VitaminkError::io(&path, e))?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).map_err(|e| VitaminkError::io(&path, e))?;
+
+        let shared = Arc::new(Shared { state: Mutex::new(state), subscribers: Mutex::new(Vec::new()), commands });
+        let accepting = shared.clone();
+        thread::Builder::new()
+            .name("control".into())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    let stream = match stream {
+                        Ok(stream) => stream,
+                        Err(e) => {
+                            warn!("Control socket: {e}");
+                            continue;
+                        }
+                    };
+                    let shared = accepting.clone();
+                    if let Err(e) = thread::Builder::new().name("control client".into()).spawn(move || serve(stream, &shared)) {
+                        warn!("Control socket: {e}");
+                    }
+                }
+            })
+            .map_err(|e| VitaminkError::spawn("control socket", e))?;
+
+        debug!("Listening on {}", path.display());
+        Ok(Self { path, shared })
+    }
+
+    // Tells subscribers, if the state really changed.
+    pub fn publish_state(&self, state: State) {
+        {
+            let mut current = self.shared.state.lock().unwrap_or_else(|e| e.into_inner());
+            if *current == state {
+                return;
+            }
+            *current = state;
+        }
+        self.broadcast(json!({ "event": "state", "state": state }));
+    }
+
+    pub fn publish_countdown(&self, pending: Option<&Pending>) {
+        self.broadcast(json!({ "event": "countdown", "pending": pending }));
+    }
+
+    fn broadcast(&self, event: Value) {
+        let line = format!("{event}\n");
+        let mut subscribers = self.shared.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        subscribers.retain_mut(|stream| stream.write_all(line.as_bytes()).is_ok());
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// Answers requests until the client hangs up.
+fn serve(stream: UnixStream, shared: &Shared) {
+    let Ok(reader) = stream.try_clone() else {
+        return;
+    };
+    let mut writer = stream;
+    for line in BufReader::new(reader).lines().map_while(|line| line.ok()) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request = serde_json::from_str::<Request>(&line).map_err(|e| e.to_string());
+        debug!("Control socket: {request:?}");
+        let subscribe = request == Ok(Request::Subscribe);
+        let reply = match request {
+            Ok(Request::Subscribe) => {
+                let state = *shared.state.lock().unwrap_or_else(|e| e.into_inner());
+                json!({ "ok": true, "state": state })
+            }
+            Ok(request) => reply(handle(request, &shared.commands)),
+            Err(e) => reply(Err(e)),
+        };
+        if writer.write_all(format!("{reply}\n").as_bytes()).is_err() {
+            return;
+        }
+        if subscribe
+            && let Ok(subscriber) = writer.try_clone()
+            && subscriber.set_write_timeout(Some(WRITE_TIMEOUT)).is_ok()
+        {
+            shared.subscribers.lock().unwrap_or_else(|e| e.into_inner()).push(subscriber);
+        }
+    }
+}
+
+// The reply's fields besides "ok", or what went wrong. http.rs answers
+// with the same.
+pub fn handle(request: Request, commands: &Sender<Command>) -> std::result::Result<Value, String> {
+    let command = match request {
+        Request::Status => {
+            let status = status::read().map_err(|e| e.to_string())?;
+            return Ok(json!({ "status": status }));
+        }
+        Request::ForceState { state: Target::Away } => Command::ForceAway,
+        Request::ForceState { state: Target::Desk } => Command::ForceDesk,
+        Request::Inhibit { name, .. } if name.is_empty() => return Err("Inhibitor name is empty".to_string()),
+        Request::Inhibit { name, reason, seconds } => {
+            Command::Inhibit { name, reason, duration: (seconds > 0).then(|| Duration::from_secs(seconds)) }
+        }
+        Request::Release { name } => Command::Release(name),
+        Request::ClientMode { mode } if mode.is_empty() => Command::SetClientMode(None),
+        Request::ClientMode { mode } => Command::SetClientMode(Some(mode.parse().map_err(|e: VitaminkError| e.to_string())?)),
+        Request::Subscribe => unreachable!("handled by serve"),
+    };
+    commands.send(command).map_err(|e| format!("Daemon is not accepting commands: {e}"))?;
+    Ok(json!({}))
+}
+
+pub fn reply(result: std::result::Result<Value, String>) -> Value {
+    match result {
+        Ok(mut fields) => {
+            fields["ok"] = json!(true);
+            fields
+        }
+        Err(e) => json!({ "ok": false, "error": e }),
+    }
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        let parse = |line: &str| serde_json::from_str::<Request>(line).ok();
+        assert_eq!(parse(r#"{"command":"force-state","state":"away"}"#), Some(Request::ForceState { state: Target::Away }));
+        assert_eq!(
+            parse(r#"{"command":"inhibit","name":"game"}"#),
+            Some(Request::Inhibit { name: "game".to_string(), reason: String::new(), seconds: 0 })
+        );
+        assert_eq!(parse(r#"{"command":"subscribe"}"#), Some(Request::Subscribe));
+        assert_eq!(parse(r#"{"command":"client-mode"}"#), Some(Request::ClientMode { mode: String::new() }));
+        assert_eq!(parse(r#"{"command":"force-state","state":"sideways"}"#), None);
+        assert_eq!(parse(r#"{"command":"reboot"}"#), None);
+    }
+
+    #[test]
+    fn test_handle() {
+        let (tx, rx) = mpsc::channel();
+        let result = handle(Request::Inhibit { name: "game".to_string(), reason: "Playing".to_string(), seconds: 60 }, &tx);
+        assert_eq!(reply(result), json!({ "ok": true }));
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            Command::Inhibit { name: "game".to_string(), reason: "Playing".to_string(), duration: Some(Duration::from_secs(60)) }
+        );
+
+        let result = handle(Request::Inhibit { name: String::new(), reason: String::new(), seconds: 0 }, &tx);
+        assert_eq!(reply(result), json!({ "ok": false, "error": "Inhibitor name is empty" }));
+    }
+}
@@ -0,0 +1,165 @@
+// src/crash.rs — A crash report when the daemon panics
+//
+// A panic used to leave nothing but a one-line message in the journal,
+// with no hint of what the daemon was doing. `install` adds a panic hook
+// that, after the usual message, writes a report to
+// `$XDG_STATE_HOME/vitamink/crash/crash-UNIXTIME.txt`:
+//
+//   - the panic message, where it happened, and a backtrace
+//   - the last status the daemon wrote (state, profile, pending switch, ...)
+//   - the main display's recent DPMS readings
+//   - the config, secrets blanked out as in diagnostics bundles
+//   - the last log lines
+//
+// The hook can't reach into the daemon, which may be halfway through a
+// change when it panics, so the daemon hands over copies as it goes
+// (`note_status`, `note_config`). Only the newest few reports are kept.
+
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs;
+use std::panic::{self, PanicHookInfo};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::thread;
+
+use log::error;
+
+use crate::diagnostics;
+use crate::display::DpmsState;
+use crate::error::{Result, VitaminkError};
+use crate::logging::{self, format_local};
+use crate::persist;
+use crate::status::{self, Status};
+
+const KEPT_REPORTS: usize = 10;
+const LOG_LINES: usize = 200;
+const DPMS_READINGS: usize = 20;
+
+#[derive(Default)]
+struct Context {
+    status: Option<String>,
+    // (Unix seconds, reading), one entry per change.
+    dpms: VecDeque<(u64, DpmsState)>,
+    config: Option<String>,
+}
+
+static CONTEXT: Mutex<Context> = Mutex::new(Context { status: None, dpms: VecDeque::new(), config: None });
+
+pub fn dir() -> PathBuf {
+    persist::path().with_file_name("crash")
+}
+
+// Chains onto the default hook, which still prints the message first.
+pub fn install() {
+    let default = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default(info);
+        match write_report(&render(info)) {
+            Ok(path) => error!("Crash report written to {}", path.display()),
+            Err(e) => error!("Couldn't write a crash report: {e}"),
+        }
+    }));
+}
+
+// What the daemon last wrote to status.json.
+pub fn note_status(status: &Status) {
+    let mut context = CONTEXT.lock().unwrap_or_else(|e| e.into_inner());
+    context.status = serde_json::to_string_pretty(status).ok();
+    if context.dpms.back().map(|&(_, dpms)| dpms) != Some(status.dpms) {
+        if context.dpms.len() == DPMS_READINGS {
+            context.dpms.pop_front();
+        }
+        context.dpms.push_back((status.updated, status.dpms));
+    }
+}
+
+// The config file as loaded.
+pub fn note_config(text: &str) {
+    CONTEXT.lock().unwrap_or_else(|e| e.into_inner()).config = Some(diagnostics::redact(text));
+}
+
+fn render(info: &PanicHookInfo) -> String {
+    let message = match (info.payload().downcast_ref::<&str>(), info.payload().downcast_ref::<String>()) {
+        (Some(text), _) => text.to_string(),
+        (_, Some(text)) => text.clone(),
+        _ => "(no message)".to_string(),
+    };
+    let location = info.location().map_or("unknown".to_string(), |l| l.to_string());
+    let thread = thread::current().name().unwrap_or("unnamed").to_string();
+    let backtrace = std::backtrace::Backtrace::force_capture().to_string();
+
+    // A panic while the lock was held mustn't deadlock the hook.
+    let context = match CONTEXT.try_lock() {
+        Ok(context) => Some(context),
+        Err(std::sync::TryLockError::Poisoned(e)) => Some(e.into_inner()),
+        Err(std::sync::TryLockError::WouldBlock) => None,
+    };
+    let recent = logging::recent();
+    let recent = &recent[recent.len().saturating_sub(LOG_LINES)..];
+    format_report(&message, &location, &thread, &backtrace, context.as_deref(), recent)
+}
+
+fn format_report(message: &str, location: &str, thread: &str, backtrace: &str, context: Option<&Context>, log: &[String]) -> String {
+    let now = status::unix_now();
+    let mut report = format!("vitamink {} panicked at {}\n\n", env!("CARGO_PKG_VERSION"), format_local(now as libc::time_t));
+    let _ = writeln!(report, "Thread: {thread}\nLocation: {location}\nMessage: {message}\n");
+    let _ = writeln!(report, "---- Backtrace ----\n{backtrace}");
+
+    let unknown = "(not recorded)\n".to_string();
+    let (status, dpms, config) = match context {
+        Some(context) => (
+            context.status.as_ref().map_or(unknown.clone(), |s| format!("{s}\n")),
+            context.dpms.iter().map(|&(at, dpms)| format!("{} {dpms:?}\n", format_local(at as libc::time_t))).collect(),
+            context.config.clone().unwrap_or(unknown),
+        ),
+        None => (unknown.clone(), unknown.clone(), unknown),
+    };
+    let _ = writeln!(report, "---- Status ----\n{status}");
+    let _ = writeln!(report, "---- DPMS readings (changes, oldest first) ----\n{dpms}");
+    let _ = writeln!(report, "---- Config ----\n{config}");
+    let _ = write!(report, "---- Last {} log lines ----\n{}", log.len(), log.concat());
+    report
+}
+
+// Also drops all but the newest KEPT_REPORTS.
+fn write_report(text: &str) -> Result<PathBuf> {
+    let dir = dir();
+    fs::create_dir_all(&dir).map_err(|e| VitaminkError::io(&dir, e))?;
+    let path = dir.join(format!("crash-{}.txt", status::unix_now()));
+    fs::write(&path, text).map_err(|e| VitaminkError::io(&path, e))?;
+
+    let mut reports: Vec<PathBuf> = fs::read_dir(&dir)
+        .map_err(|e| VitaminkError::io(&dir, e))?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.file_name().is_some_and(|name| name.to_string_lossy().starts_with("crash-")))
+        .collect();
+    // Same-length names up to 2286, so this is oldest first.
+    reports.sort();
+    for old in &reports[..reports.len().saturating_sub(KEPT_REPORTS)] {
+        let _ = fs::remove_file(old);
+    }
+    Ok(path)
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_report() {
+        let mut context = Context { status: Some("{\"state\": \"Away\"}".to_string()), config: None, ..Context::default() };
+        context.dpms.push_back((0, DpmsState::Off));
+        let log = ["first\n".to_string(), "second\n".to_string()];
+        let report = format_report("boom", "src/daemon.rs:1:1", "main", "<frames>", Some(&context), &log);
+
+        assert!(report.contains("Message: boom\n"));
+        assert!(report.contains("---- Status ----\n{\"state\": \"Away\"}\n"));
+        assert!(report.contains(" Off\n"));
+        assert!(report.contains("---- Config ----\n(not recorded)\n"));
+        assert!(report.ends_with("---- Last 2 log lines ----\nfirst\nsecond\n"));
+    }
+}
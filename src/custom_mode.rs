@@ -0,0 +1,126 @@
+// src/custom_mode.rs — Modes an output doesn't advertise, for Away
+//
+// Plenty of dummy plugs only carry a 60 Hz EDID but take 120 Hz just
+// fine. Listed in the config,
+//
+//   [[custom_modes]]
+//   output = "HDMI-A-1"
+//   mode = "1920x1080@120"
+//
+//   dummy_mode = "1920x1080@120"
+//
+// going Away adds the mode to the output before it's enabled, so
+// `dummy_mode`, a profile's `mode` or a client's (`match_client_mode`)
+// can pick it like any other, and AtDesk takes it away again once the
+// output is off. On Plasma 6.2 and later that's kscreen-doctor's
+// `addCustomMode`; on X11, a `cvt` modeline handed to `xrandr --newmode`
+// (see xrandr.rs). Other compositors can't, and say so.
+//
+// A mode is only counted as added once the output lists it: a driver
+// that refuses the timings fails the Away transition instead of leaving
+// the plug at whatever it had. A mode the output already has is left
+// alone, and never removed. What was added is kept in state.json, so a
+// restart in between still removes it.
+
+use log::{info, warn};
+
+use crate::config::CustomMode;
+use crate::display::{self, Display};
+use crate::error::{Result, VitaminkError};
+
+// Adds those of `modes` for `outputs` that they don't list yet. Returns
+// the ones it added; on an error, those are removed again first, since
+// the caller only keeps what it gets back.
+pub fn add(modes: &[CustomMode], outputs: &[String]) -> Result<Vec<CustomMode>> {
+    let wanted: Vec<&CustomMode> = modes.iter().filter(|m| outputs.contains(&m.output)).collect();
+    if wanted.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut added = Vec::new();
+    if let Err(e) = add_missing(&wanted, &mut added) {
+        remove(&added);
+        return Err(e);
+    }
+    Ok(added)
+}
+
+// Pushes each mode onto `added` as soon as it's in.
+fn add_missing(wanted: &[&CustomMode], added: &mut Vec<CustomMode>) -> Result<()> {
+    let displays = display::get_displays()?;
+    for &custom in wanted {
+        if displays.iter().any(|d| offers(d, custom)) {
+            continue;
+        }
+        info!("→ Adding custom mode {} to {}", custom.mode, custom.output);
+        display::add_mode(custom)?;
+        added.push(custom.clone());
+    }
+    if added.is_empty() {
+        return Ok(());
+    }
+    let displays = display::refresh_displays()?;
+    if let Some(missing) = added.iter().find(|custom| !displays.iter().any(|d| offers(d, custom))) {
+        return Err(VitaminkError::Unavailable(format!(
+            "{} doesn't list custom mode {} after adding it — the driver may not take those timings",
+            missing.output, missing.mode
+        )));
+    }
+    Ok(())
+}
+
+// Takes away what `add` added. A failure is only logged: the mode does no
+// harm, and AtDesk has more to put back.
+pub fn remove(added: &[CustomMode]) {
+    for custom in added {
+        info!("→ Removing custom mode {} from {}", custom.mode, custom.output);
+        if let Err(e) = display::remove_mode(custom) {
+            warn!("Couldn't remove custom mode {} from {}: {e}", custom.mode, custom.output);
+        }
+    }
+}
+
+// Whether `display` is the output and lists the mode, to within half a
+// hertz: drivers round the rate they got.
+fn offers(display: &Display, custom: &CustomMode) -> bool {
+    let mode = &custom.mode;
+    display.name == custom.output
+        && display.modes.iter().any(|m| {
+            m.width == mode.width && m.height == mode.height && mode.refresh.is_some_and(|hz| (m.refresh - hz).abs() < 0.5)
+        })
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::{Color, ConnectionState, DisplayState, Mode, ModeTarget};
+
+    #[test]
+    fn test_offers() {
+        let mode = |id, refresh| Mode { id, width: 1920, height: 1080, refresh, preferred: false, current: false };
+        let plug = Display {
+            index: 0,
+            name: "HDMI-A-1".into(),
+            uuid: None,
+            state: DisplayState::Disabled,
+            connection: ConnectionState::Connected,
+            modes: vec![mode(0, 60.0), mode(1, 119.88)],
+            geometry: None,
+            scale: None,
+            priority: None,
+            color: Color::default(),
+            vrr: None,
+            rotation: None,
+            overscan: None,
+        };
+        let custom = |output: &str, refresh| CustomMode {
+            output: output.into(),
+            mode: ModeTarget { width: 1920, height: 1080, refresh: Some(refresh) },
+            reduced_blanking: false,
+        };
+        assert!(offers(&plug, &custom("HDMI-A-1", 120.0)));
+        assert!(!offers(&plug, &custom("HDMI-A-1", 144.0)));
+        assert!(!offers(&plug, &custom("DP-1", 60.0)));
+    }
+}
@@ -1,4 +1,5 @@
-// src/daemon.rs — State machine and polling daemon
+// src/daemon.rs — Polling daemon: feeds the state machine (machine.rs) and
+// carries out what it decides
 //
 // New Rust concepts in this file:
 //
@@ -11,43 +12,100 @@
 //
 // - `std::thread::sleep`: pauses the current thread. Simple polling.
 //
-// - `eprintln!`: prints to stderr (good for daemon logging alongside journald).
+// - `info!` / `warn!` / ...: the `log` crate's macros; logging.rs decides
+//   where the lines go and what they look like.
+//
+// - `Receiver::recv_timeout`: waits for a message on a channel, but gives up
+//   after a deadline. It replaces a plain `sleep` so D-Bus commands are handled
+//   immediately instead of at the next poll.
+//
+// Everything that can happen reaches `run` as a `Command` on that one
+// channel, sent by a thread per source: signals (shutdown.rs), D-Bus,
+// the control socket, HTTP and MQTT, hotplug uevents (topology.rs), logind
+// and the screensaver (presence.rs), Sunshine's journal and wake packets.
+// The timeout is the earliest timer: the next poll, the end of a grace
+// period, an inhibitor running out, or systemd's keepalive. The threads
+// block in plain zbus and socket calls, so there's no async runtime to
+// start; the CLI commands use the same calls without any of it.
 
+use std::collections::BTreeSet;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::thread;
-use std::time::{Duration, Instant};
-
-use crate::display::{self, DpmsState};
-use crate::sunshine;
-
-// ---- Configuration ----
+use std::time::{Duration, Instant, SystemTime};
 
-pub struct Config {
-    pub main_display: String,
-    pub dummy_plug: String,
-    pub poll_interval: Duration,
-    pub grace_period: Duration,
-}
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
 
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            main_display: "DP-2".to_string(),
-            dummy_plug: "HDMI-A-1".to_string(),
-            poll_interval: Duration::from_secs(5),
-            grace_period: Duration::from_secs(10),
-        }
-    }
-}
+use crate::accounting::Accounting;
+use crate::audio;
+use crate::compositor;
+use crate::config::{self, Config, CustomMode, ProfileConfig, ProfileOutput, WakeConfig};
+use crate::control;
+use crate::crash;
+use crate::custom_mode;
+use crate::dbus;
+use crate::ddc;
+use crate::desktop;
+use crate::digest::Digest;
+use crate::display::{self, DisplayState, ModeTarget, OffStrategy};
+use crate::duration;
+use crate::gamescope;
+use crate::dock::{DockEvent, DockTracker};
+use crate::drift::{self, Drift, OnDrift};
+use crate::drm::{self, Connector, ConnectorStatus, GpuWatch, HotplugCounter};
+use crate::dummy::{self, UnplugAction};
+use crate::error::{Result, VitaminkError};
+use crate::history::{self, Cause};
+use crate::http;
+use crate::hooks::{self, Transition};
+use crate::inhibit::Inhibitors;
+use crate::metrics;
+use crate::mqtt;
+use crate::input::InputGate;
+use crate::layout::{self, Layout};
+use crate::load;
+use crate::logging;
+use crate::machine::{Action, Event, StateMachine};
+use crate::notify;
+use crate::pattern;
+use crate::power;
+use crate::presence::{self, Presence};
+use crate::persist::{self, Persisted};
+use crate::pipeline::{STANDBY_STEPS, Stage, Step};
+use crate::privacy;
+use crate::process;
+use crate::recovery::{self, Escalation, Failures};
+use crate::shutdown::{self, ShutdownAction};
+use crate::sleep::SleepWatch;
+use crate::status::{self, Pending, Progress, Status, StepTime};
+use crate::schedule::{self, Action as ScheduleAction};
+use crate::services;
+use crate::sunshine::{self, compat::{self, Feature}, encoder::{self, EncoderCheck}, journal::{self, OnError}};
+use crate::systemd;
+use crate::topology;
+use crate::virtual_output;
+use crate::wake;
+use crate::watchdog::{self, Heartbeat};
+use crate::webhook;
+use crate::windows;
 
-// ---- State Machine ----
+// ---- States ----
 
-// The two states VitaminK can be in.
+// The states VitaminK can be in.
 // `AtDesk`: user is present, main monitor on, Sunshine stopped.
 // `Away`: user is away, dummy plug on, Sunshine running.
-#[derive(Debug, PartialEq, Clone, Copy)]
-enum State {
+// `Standby`: Away, but nobody has streamed for `[standby] after` — Sunshine
+// and the Away outputs are off until a client knocks.
+// `HoldingPattern`: no outputs at all (GPU reset, driver reload) — nothing
+// is started or stopped until they come back.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Serialize, Deserialize)]
+pub enum State {
     AtDesk,
     Away,
+    HoldingPattern,
+    Standby,
 }
 
 // `impl` attaches methods to a type. This gives State a human-readable label.
@@ -56,146 +114,2397 @@ impl std::fmt::Display for State {
         match self {
             State::AtDesk => write!(f, "AtDesk"),
             State::Away => write!(f, "Away"),
+            State::HoldingPattern => write!(f, "HoldingPattern"),
+            State::Standby => write!(f, "Standby"),
         }
     }
 }
 
+// One failure tends to log several matching lines; `on_error` acts on
+// the first, then not again for this long.
+const SUNSHINE_ERROR_BACKOFF: Duration = Duration::from_secs(60);
+
+// Requests that arrive from outside the poll loop (D-Bus, the control
+// socket, the HTTP API and signals).
+#[derive(Debug, PartialEq, Clone)]
+pub enum Command {
+    ForceAway,
+    ForceDesk,
+    // A `[profiles]` name, or None for the plain dummy plug.
+    SetProfile(Option<String>),
+    // What the Moonlight client asked for (see sunshine.rs), or None once
+    // its stream ends.
+    SetClientMode(Option<ModeTarget>),
+    // Calls off a switch that's waiting out the grace period, and holds
+    // the current state until the presence reading changes again.
+    CancelPending,
+    // Holds the current state against presence and the schedule until
+    // released or `duration` is up (see inhibit.rs).
+    Inhibit { name: String, reason: String, duration: Option<Duration> },
+    Release(String),
+    // Re-read the config file (SIGHUP, or `vitamink ctl reload`).
+    Reload,
+    // A hotplug changed the outputs (see topology.rs): poll now rather
+    // than at the next interval.
+    OutputsChanged,
+    // The session locked or unlocked, or went idle (see presence.rs):
+    // also a reason to poll now.
+    PresenceChanged,
+    // A client trying to wake this PC (see wake.rs), and who it was.
+    RemoteWake(String),
+    // A line from Sunshine's journal that matched `error_patterns` (see
+    // sunshine/journal.rs).
+    SunshineError(String),
+    // SIGTERM or SIGINT; `run` returns once it's handled.
+    Shutdown,
+}
+
 pub struct Daemon {
     config: Config,
-    state: State,
-    // Tracks when we first saw a DPMS change.
-    // `Option<Instant>` is either Some(timestamp) or None.
-    // We use this to implement the grace period: only transition
-    // after the new DPMS state has been stable for `grace_period`.
-    transition_started: Option<Instant>,
+    // The config file as loaded, and when it was last changed, so a reload
+    // can tell what's different.
+    config_text: String,
+    config_modified: Option<SystemTime>,
+    // The state, and what decides it (see machine.rs).
+    machine: StateMachine,
+    // Whether the grace period countdown notification is up.
+    pending_notice: bool,
+    // Streams and errors since going Away, when the summary notification
+    // is on.
+    away_digest: Option<Digest>,
+    // Time spent in each state (see accounting.rs).
+    times: Accounting,
+    // When the schedule was last looked at (Unix time), and the window in
+    // force then.
+    schedule_checked: Option<i64>,
+    schedule_window: Option<ScheduleAction>,
+    inhibitors: Inhibitors,
+    commands: Receiver<Command>,
+    // Kept so the channel never disconnects, even if D-Bus is unavailable.
+    commands_tx: Sender<Command>,
+    bus: Option<dbus::Service>,
+    mqtt: Option<mqtt::Client>,
+    control: Option<control::Server>,
+    // Listening for wake packets while AtDesk with Sunshine stopped.
+    wake: Option<wake::Listener>,
+    // Follows logind's suspend/resume signals (see sleep.rs).
+    sleep: Option<SleepWatch>,
+    // Resumes handled so far, and when the last one was.
+    resumes_seen: u32,
+    resumed_at: Option<Instant>,
+    // Present while local keyboards/mice are grabbed (Away + input_gating).
+    input_gate: Option<InputGate>,
+    // What the privacy step changed, so AtDesk can undo exactly that.
+    privacy: Option<privacy::Restore>,
+    // Wallpaper/Activity to switch back to on AtDesk.
+    desktop: Option<desktop::Restore>,
+    // KWin animation/effect settings to put back on AtDesk.
+    compositor: Option<compositor::Restore>,
+    // The sink to go back to on AtDesk.
+    audio: Option<audio::Restore>,
+    // The power profile to go back to on AtDesk.
+    power: Option<power::Restore>,
+    // The gamescope session's PID while it runs.
+    gamescope: Option<u32>,
+    // How to close the `[apps]` app on AtDesk.
+    app: Option<sunshine::apps::Restore>,
+    // sunshine.conf from before a template was rendered over it.
+    sunshine_conf: Option<sunshine::conf::Restore>,
+    // Back at the desk with a stream still going: when the services get
+    // stopped regardless (see `drain`).
+    draining: Option<Instant>,
+    // Following Sunshine's journal while Away, and when `on_error` last
+    // acted on it.
+    sunshine_log: Option<journal::Watcher>,
+    sunshine_error_at: Option<Instant>,
+    // Output layout from just before going Away.
+    saved_layout: Option<Layout>,
+    // Where KWin's windows were then (see windows.rs).
+    windows: Option<Vec<windows::Placement>>,
+    // The dummy plug's connector, resolved when first needed and kept
+    // until AtDesk has turned it off (with "auto" it can change between runs).
+    dummy_plug: Option<String>,
+    // The `[profiles]` entry Away uses. While Away, it's the one applied.
+    profile: Option<String>,
+    // Set while the dummy plug is pulled out during Away, to what was done
+    // about it (see `watch_dummy_plug`).
+    unplugged: Option<UnplugAction>,
+    // Outputs and services changed by hand while Away that `on_drift` went
+    // along with, until the next transition (see drift.rs).
+    adopted: Drift,
+    // Away outputs AtDesk blanked rather than disabled (`away_outputs_off =
+    // "dpms-off"`), which the layout restore leaves as they are.
+    blanked: Vec<String>,
+    // The streaming client's mode, used for the dummy plug instead of
+    // `dummy_mode` while `match_client_mode` is on.
+    client_mode: Option<ModeTarget>,
+    // Why the machine is too busy for Away, while a switch there is
+    // pending and it is (see load.rs).
+    busy: Option<String>,
+    // `custom_modes` that Away added to its outputs, for AtDesk to remove.
+    custom_modes: Vec<CustomMode>,
+    dock: DockTracker,
+    // Kernel-level connect/disconnect flips per connector.
+    hotplug: HotplugCounter,
+    gpu: GpuWatch,
+    // Hash of the last poll's inputs (presence + connected outputs), and how
+    // many polls in a row have seen the same thing. Quiet polls stretch the
+    // poll interval towards `max_poll_interval`.
+    last_fingerprint: Option<u64>,
+    quiet_polls: u32,
+    next_poll: Instant,
+    // When the last poll started: timers due before it have been seen to.
+    polled: Instant,
+    // Set once a poll has succeeded (systemd's READY=1 waits for it).
+    ready: bool,
+    // For status.json.
+    last_transition: Option<SystemTime>,
+    last_error: Option<String>,
+    // The polls that failed in a row (see recovery.rs), and whether there
+    // were enough for the daemon to give up.
+    failures: Failures,
+    gave_up: bool,
+    // The steps of the transition under way (see `apply_steps`).
+    progress: Option<Progress>,
+    // The grace period countdown as last published (see `publish_countdown`).
+    countdown: Option<Pending>,
+    heartbeat: Arc<Heartbeat>,
 }
 
 impl Daemon {
     pub fn new(config: Config) -> Self {
-        // Start by checking current DPMS to set initial state correctly
-        let dpms = display::read_dpms(&config.main_display);
-        let initial_state = match dpms {
-            DpmsState::Off => State::Away,
+        // Start by checking presence to set initial state correctly
+        let presence = presence::detect(&config).unwrap_or_else(|e| {
+            warn!("Presence check failed: {e}");
+            Presence::Unknown
+        });
+        let initial_state = match presence {
+            Presence::Absent => State::Away,
             _ => State::AtDesk,
         };
 
-        eprintln!("[vitamink] Starting in state: {initial_state} (DPMS: {dpms:?})");
+        info!(
+            "Starting in state: {initial_state} ({:?} backend: {presence:?})",
+            config.presence_backend
+        );
+        info!("Display backend: {}", display::backend().name());
+        let session: Vec<String> = display::wayland_env().iter().map(|(k, v)| format!("{k}={v}")).collect();
+        info!("Session: {}", session.join(" "));
+
+        let (commands_tx, commands) = mpsc::channel();
+        let dock = DockTracker::new(config.dock_settle);
+
+        // Whatever a previous run left engaged. Loading it means the first
+        // apply_state undoes it (AtDesk) or doesn't redo it (Away).
+        let previous = persist::load().unwrap_or_else(|e| {
+            warn!("Ignoring unreadable state file: {e}");
+            None
+        });
+        if let Some(previous) = &previous
+            && matches!(previous.state, State::Away | State::Standby)
+        {
+            info!("Previous run ended while Away, reconciling from {}", persist::path().display());
+        }
+        let previous = previous.unwrap_or_default();
+        // An Away run must be undone with the profile it applied; otherwise
+        // the config decides.
+        let profile = match previous.state {
+            State::Away | State::Standby => previous.profile.clone(),
+            _ => config.profile.clone(),
+        };
+        let profile = profile.filter(|name| {
+            let known = config.profiles.contains_key(name);
+            if !known {
+                warn!("Profile \"{name}\" no longer exists, using the dummy plug");
+            }
+            known
+        });
 
+        let machine = StateMachine::new(initial_state, &config);
+        let config_text = config::read_text().unwrap_or_default();
+        crash::note_config(&config_text);
         Self {
             config,
-            state: initial_state,
-            transition_started: None,
+            config_text,
+            config_modified: config::modified(),
+            machine,
+            pending_notice: false,
+            away_digest: None,
+            times: Accounting::load(),
+            schedule_checked: None,
+            schedule_window: None,
+            inhibitors: Inhibitors::default(),
+            commands,
+            commands_tx,
+            bus: None,
+            mqtt: None,
+            control: None,
+            wake: None,
+            sleep: None,
+            resumes_seen: 0,
+            resumed_at: None,
+            draining: None,
+            sunshine_log: None,
+            sunshine_error_at: None,
+            input_gate: None,
+            privacy: previous.privacy,
+            desktop: previous.desktop,
+            compositor: previous.compositor,
+            audio: previous.audio,
+            power: previous.power,
+            gamescope: previous.gamescope,
+            app: previous.app,
+            sunshine_conf: previous.sunshine_conf,
+            saved_layout: previous.saved_layout,
+            windows: previous.windows,
+            dummy_plug: previous.dummy_plug,
+            profile,
+            unplugged: None,
+            adopted: Drift::default(),
+            blanked: Vec::new(),
+            client_mode: None,
+            busy: None,
+            custom_modes: previous.custom_modes,
+            dock,
+            hotplug: HotplugCounter::new(),
+            gpu: GpuWatch::new(),
+            last_fingerprint: None,
+            quiet_polls: 0,
+            next_poll: Instant::now(),
+            polled: Instant::now(),
+            ready: false,
+            last_transition: None,
+            progress: None,
+            countdown: None,
+            last_error: None,
+            failures: Failures::default(),
+            gave_up: false,
+            heartbeat: Heartbeat::new(),
         }
     }
 
-    // Main loop — runs forever, polling DPMS and managing state transitions.
+    // Main loop — polls presence and manages state transitions until a
+    // shutdown signal arrives.
     pub fn run(&mut self) {
-        // Apply the initial state so hardware matches
-        if let Err(e) = self.apply_state() {
-            eprintln!("[vitamink] Error applying initial state: {e}");
+        if let Err(e) = shutdown::forward_signals(self.commands_tx.clone()) {
+            warn!("Couldn't install signal handlers ({e}), SIGTERM will skip cleanup");
         }
+        self.heartbeat.beat();
+        // The loop legitimately sleeps for the longest adaptive interval.
+        let longest_sleep = self.config.poll_interval.max(self.config.max_poll_interval);
+        watchdog::spawn(Arc::clone(&self.heartbeat), longest_sleep);
+
+        self.start();
+
+        // systemd keepalives come from this loop (not the watchdog thread),
+        // so a loop that stops cycling gets the service restarted.
+        let keepalive = systemd::watchdog_interval();
+        let mut next_keepalive = Instant::now();
 
         loop {
-            thread::sleep(self.config.poll_interval);
+            if let Some(interval) = keepalive
+                && Instant::now() >= next_keepalive
+            {
+                systemd::watchdog_keepalive();
+                next_keepalive = Instant::now() + interval;
+            }
+
+            let deadline = if keepalive.is_some() { self.next_poll().min(next_keepalive) } else { self.next_poll() };
+            let timeout = deadline.saturating_duration_since(Instant::now());
+
+            self.heartbeat.beat();
+            self.heartbeat.set_phase("idle");
+
+            match self.commands.recv_timeout(timeout) {
+                Ok(command) => {
+                    if !self.dispatch(command) {
+                        return;
+                    }
+                }
+                // We hold a Sender ourselves, so the only error is a timeout —
+                // possibly just the keepalive's, with the poll not yet due.
+                Err(_) if Instant::now() < self.next_poll() => {}
+                Err(_) => self.poll_and_report(),
+            }
+            if self.gave_up {
+                self.shutdown();
+                return;
+            }
+        }
+    }
+
+    // ---- Embedding ----
+    //
+    // For programs that drive the daemon from their own loop instead of
+    // `run`: call `start` once, then `step` whenever convenient (at the
+    // latest by `next_poll`). There are no signal handlers, systemd
+    // keepalives or watchdog thread in this mode.
+
+    // Applies the initial state and registers on D-Bus.
+    pub fn start(&mut self) {
+        compat::probe(&self.config.sunshine);
+
+        self.heartbeat.set_phase("applying initial state");
+        if let Err(e) = self.apply_state(None) {
+            error!("Error applying initial state: {e}");
+            self.last_error = Some(e.to_string());
+        }
+
+        if self.config.metrics.enabled
+            && let Err(e) = metrics::serve(self.config.metrics.listen)
+        {
+            warn!("Couldn't serve metrics on {}: {e}", self.config.metrics.listen);
+        }
+        if self.config.http.enabled
+            && let Err(e) = http::serve(&self.config.http, self.commands_tx.clone())
+        {
+            warn!("Couldn't serve the HTTP API on {}: {e}", self.config.http.listen);
+        }
+
+        let profiles = self.config.profiles.keys().cloned().collect();
+        match dbus::Service::start(self.machine.state(), self.profile.clone(), profiles, self.commands_tx.clone()) {
+            Ok(service) => self.bus = Some(service),
+            Err(e) => warn!("{e} — continuing without D-Bus control"),
+        }
+        match control::Server::start(self.machine.state(), self.commands_tx.clone()) {
+            Ok(server) => self.control = Some(server),
+            Err(e) => warn!("{e} — continuing without the control socket"),
+        }
+        if self.config.mqtt.enabled {
+            match mqtt::Client::start(&self.config.mqtt, self.commands_tx.clone()) {
+                Ok(client) => {
+                    if let Err(e) = client.publish_state(self.machine.state()) {
+                        warn!("MQTT: {e}");
+                    }
+                    self.mqtt = Some(client);
+                }
+                Err(e) => warn!("{e} — continuing without MQTT"),
+            }
+        }
+        match SleepWatch::start() {
+            Ok(watch) => self.sleep = Some(watch),
+            Err(e) => warn!("{e} — suspend won't pause switching"),
+        }
+        match topology::manager().watch() {
+            Ok(()) => {
+                let commands = self.commands_tx.clone();
+                topology::manager().on_change(move |_| {
+                    let _ = commands.send(Command::OutputsChanged);
+                });
+            }
+            Err(e) => warn!("{e} — hotplugs are only noticed at the next poll"),
+        }
+        let commands = self.commands_tx.clone();
+        if let Err(e) = presence::watch(move || {
+            let _ = commands.send(Command::PresenceChanged);
+        }) {
+            warn!("{e} — the screen locking is only noticed at the next poll");
+        }
+        if let Err(e) = display::backend().watch_changes() {
+            warn!("{e} — output changes made in the desktop's settings are noticed late");
+        }
+        let notifications = &self.config.notifications;
+        if notifications.enabled && notifications.events.contains(&notify::Event::Pending) {
+            let commands = self.commands_tx.clone();
+            if let Err(e) = notify::on_cancel(move || {
+                let _ = commands.send(Command::CancelPending);
+            }) {
+                warn!("{e} — the countdown notification can't be cancelled");
+            }
+        }
+        self.report_status();
+        self.write_status();
+        self.next_poll = Instant::now() + self.config.poll_interval;
+    }
+
+    // Handles queued commands, then polls if a poll is due. Never blocks
+    // beyond the work itself. Returns false once a `Command::Shutdown` has
+    // been handled, or the daemon gave up on a poll that kept failing; it
+    // shouldn't be stepped after that.
+    pub fn step(&mut self) -> bool {
+        while let Ok(command) = self.commands.try_recv() {
+            if !self.dispatch(command) {
+                return false;
+            }
+        }
+        if Instant::now() >= self.next_poll() {
+            self.poll_and_report();
+        }
+        if self.gave_up {
+            self.shutdown();
+            return false;
+        }
+        true
+    }
+
+    // Whether `run` returned because polls kept failing (`[recovery]
+    // exit_after`) rather than for a signal, which is worth exiting
+    // nonzero for.
+    pub fn gave_up(&self) -> bool {
+        self.gave_up
+    }
+
+    // When `step` next has a poll to do: at the poll interval, or sooner
+    // for a grace period, a manual override or an inhibitor that ends
+    // before then.
+    pub fn next_poll(&self) -> Instant {
+        [self.machine.due(), self.machine.override_due(), self.inhibitors.next_expiry()].into_iter().flatten().filter(|&at| at > self.polled).fold(self.next_poll, Instant::min)
+    }
+
+    pub fn state(&self) -> State {
+        self.machine.state()
+    }
+
+    // For sending `Command`s from other threads, like the D-Bus service does.
+    pub fn commands(&self) -> Sender<Command> {
+        self.commands_tx.clone()
+    }
+
+    // ---- One Shot ----
+    //
+    // `vitamink reconcile` (see main.rs) does a single poll's work without
+    // the loop: no grace period, no cooldown, nothing to wait for.
 
-            if let Err(e) = self.poll() {
-                eprintln!("[vitamink] Poll error: {e}");
+    // Sets up the state the presence reading (and `[[schedule]]`) calls for
+    // now, starting from what state.json says is engaged, and returns it.
+    // Hooks run and history is kept only if that changed the state; if
+    // not, whatever of it is missing is put back.
+    pub fn reconcile(&mut self, force: bool) -> Result<State> {
+        let previous = persist::load()?.map_or(State::AtDesk, |p| p.state);
+        self.machine.set_state(previous);
+        let detected = presence::detect(&self.config)?;
+        let presence = self.scheduled(detected, schedule::WeekTime::at(schedule::now(), &schedule::Local));
+        let busy = || (previous == State::AtDesk && !force).then(|| load::check(&self.config.load)).flatten();
+        let target = match presence {
+            // Standby is Away waiting for a client.
+            Presence::Absent if previous == State::Standby => State::Standby,
+            Presence::Absent => match busy() {
+                Some(reason) => {
+                    warn!("Not going Away, the machine is busy: {reason} (--force goes anyway)");
+                    State::AtDesk
+                }
+                None => State::Away,
+            },
+            _ => State::AtDesk,
+        };
+        self.machine.set_state(target);
+        let result = if target == previous {
+            info!("Already {target}, checking it's all in place");
+            let result = self.apply_steps();
+            self.persist();
+            result
+        } else {
+            info!("{previous} → {target}");
+            self.transition_to(previous, Cause::presence(&self.config))
+        };
+        self.times.count(self.machine.state());
+        self.times.save();
+        result.map(|()| self.machine.state())
+    }
+
+    // Runs one command. False if it was Shutdown.
+    fn dispatch(&mut self, command: Command) -> bool {
+        if command == Command::Shutdown {
+            self.shutdown();
+            return false;
+        }
+        if command == Command::OutputsChanged || command == Command::PresenceChanged {
+            debug!("{command:?}, polling now");
+            self.next_poll = Instant::now();
+            return true;
+        }
+        self.heartbeat.set_phase("handling command");
+        if let Err(e) = self.handle_command(command, Cause::Command) {
+            error!("Command error: {e}");
+            self.notify(notify::Event::Error, "VitaminK error", &e.to_string());
+            self.last_error = Some(e.to_string());
+            if let Some(digest) = &mut self.away_digest {
+                digest.record_error();
+            }
+        }
+        self.update_pending_notice();
+        self.publish_countdown();
+        self.write_status();
+        true
+    }
+
+    fn poll_and_report(&mut self) {
+        if config::modified() != self.config_modified {
+            info!("{} changed, reloading", config::default_path().display());
+            self.reload();
+        }
+        self.heartbeat.set_phase("polling");
+        self.polled = Instant::now();
+        match self.poll() {
+            Ok(()) => {
+                let failed = self.failures.succeeded();
+                if failed > 0 {
+                    info!("Polling works again, after {failed} failed poll{}", if failed == 1 { "" } else { "s" });
+                }
+                if !self.ready {
+                    systemd::ready();
+                    self.ready = true;
+                }
+            }
+            Err(e) => self.poll_failed(&e),
+        }
+        if let Some(digest) = &mut self.away_digest {
+            digest.observe_stream(sunshine::has_active_session(&self.config.sunshine.session_ports), Instant::now());
+        }
+        self.drain();
+        self.update_wake_listener();
+        self.update_sunshine_log(status::unix_now());
+        self.update_pending_notice();
+        self.publish_countdown();
+        self.write_status();
+        self.next_poll = Instant::now() + self.poll_interval();
+    }
+
+    // Logs and notifies about a failed poll, as often as recovery.rs says,
+    // and sets things up afresh or gives up once enough failed in a row.
+    fn poll_failed(&mut self, e: &VitaminkError) {
+        let message = e.to_string();
+        self.last_error = Some(message.clone());
+        if let Some(digest) = &mut self.away_digest {
+            digest.record_error();
+        }
+        let escalation = self.failures.failed(&message, &self.config.recovery, Instant::now());
+        let count = self.failures.count();
+        match escalation {
+            Escalation::New => {
+                error!("Poll error: {e}");
+                self.notify(notify::Event::Error, "VitaminK error", &message);
+            }
+            Escalation::Repeated => error!("Poll error, {count} in a row now: {e}"),
+            Escalation::Quiet => debug!("Poll error: {e}"),
+            Escalation::Recover => {
+                error!("Poll error, {count} in a row now: {e} — looking up the session and outputs afresh");
+                recovery::recover(&self.config);
+                self.notify(notify::Event::Error, "VitaminK keeps failing", &format!("{count} polls in a row failed ({message}), trying to recover"));
+            }
+            Escalation::Exit => {
+                error!("Poll error, {count} in a row now: {e} — giving up");
+                self.notify(notify::Event::Error, "VitaminK is exiting", &format!("{count} polls in a row failed ({message})"));
+                systemd::status(&format!("Exiting after {count} failed polls"));
+                self.gave_up = true;
+            }
+        }
+    }
+
+    // Leaves the hardware the way `on_shutdown` asks. Either way the state
+    // file is up to date afterwards, so the next start knows what's engaged.
+    fn shutdown(&mut self) {
+        self.heartbeat.set_phase("shutting down");
+        systemd::notify("STOPPING=1");
+
+        let state = self.machine.state();
+        if self.config.on_shutdown == ShutdownAction::Restore && matches!(state, State::Away | State::Standby) {
+            info!("Going back to AtDesk before exiting");
+            self.machine.set_state(State::AtDesk);
+            if let Err(e) = self.apply_state(Some(state)) {
+                error!("Couldn't fully restore AtDesk: {e} — the next start will retry");
             }
+        } else {
+            info!("Exiting in {}, the next start picks up from here", self.machine.state());
         }
+        // Nobody would be left to stop them.
+        self.finish_draining("the daemon is exiting");
+        self.persist();
+        self.times.count(self.machine.state());
+        self.times.save();
     }
 
-    fn poll(&mut self) -> Result<(), String> {
-        let dpms = display::read_dpms(&self.config.main_display);
-        let desired = match dpms {
-            DpmsState::Off => State::Away,
-            DpmsState::On => State::AtDesk,
-            DpmsState::Unknown => {
-                eprintln!("[vitamink] DPMS unknown, holding current state");
+    // Manual overrides skip the grace period and apply immediately.
+    fn handle_command(&mut self, command: Command, cause: Cause) -> Result<()> {
+        if self.machine.state() == State::HoldingPattern {
+            warn!("Ignoring {command:?}: no outputs to work with");
+            return Ok(());
+        }
+
+        let target = match command {
+            Command::ForceAway => State::Away,
+            Command::ForceDesk => State::AtDesk,
+            Command::SetProfile(profile) => return self.switch_profile(profile),
+            Command::SetClientMode(mode) => return self.set_client_mode(mode),
+            Command::CancelPending => {
+                self.cancel_pending();
+                return Ok(());
+            }
+            Command::Inhibit { name, reason, duration } => {
+                match duration {
+                    Some(d) => info!("Inhibited by {name} for {}: {reason}", duration::format(d)),
+                    None => info!("Inhibited by {name} until released: {reason}"),
+                }
+                self.inhibitors.hold(&name, &reason, duration, Instant::now());
+                self.machine.handle(Event::Pause, Instant::now());
+                return Ok(());
+            }
+            Command::Reload => {
+                self.reload();
+                return Ok(());
+            }
+            Command::RemoteWake(from) => {
+                match self.machine.state() {
+                    State::AtDesk if presence::detect(&self.config).is_ok_and(|p| p == Presence::Present) => {
+                        info!("Ignoring {from}: someone is at the desk");
+                        return Ok(());
+                    }
+                    State::AtDesk => info!("Woken by {from}, going Away"),
+                    State::Standby => info!("Woken by {from}, leaving Standby"),
+                    _ => return Ok(()),
+                }
+                return self.handle_command(Command::ForceAway, Cause::Wake);
+            }
+            Command::SunshineError(line) => return self.on_sunshine_error(&line),
+            Command::Release(name) => {
+                if self.inhibitors.release(&name) {
+                    info!("Inhibitor {name} released");
+                    self.resume_if_uninhibited();
+                } else {
+                    warn!("No inhibitor named {name}");
+                }
                 return Ok(());
             }
+            // Handled by `dispatch` before it gets here.
+            Command::OutputsChanged | Command::PresenceChanged | Command::Shutdown => return Ok(()),
         };
 
-        if desired == self.state {
-            // Already in the right state — clear any pending transition
-            self.transition_started = None;
+        info!("Manual override: {} → {target}", self.machine.state());
+        self.last_fingerprint = None;
+        self.quiet_polls = 0;
+        let detected = presence::detect(&self.config).unwrap_or(Presence::Unknown);
+        let actions = self.machine.handle(Event::ManualOverride { target, detected }, Instant::now());
+        self.carry_out(actions, cause)
+    }
+
+    // Does what the state machine decided.
+    fn carry_out(&mut self, actions: Vec<Action>, cause: Cause) -> Result<()> {
+        for action in actions {
+            match action {
+                Action::StartGrace { to } => {
+                    notify::send_pending(
+                        &self.config.notifications,
+                        &format!("Switching to {to} in {}", duration::format(self.config.grace_period)),
+                        "Click to cancel",
+                        self.config.grace_period,
+                    );
+                    self.pending_notice = true;
+                }
+                Action::Transition { from: State::HoldingPattern, to } => {
+                    self.notify(notify::Event::Holding, "Displays are back", &format!("Re-applying {to}"));
+                    self.last_fingerprint = None;
+                    self.transition_to(State::HoldingPattern, Cause::Outputs)?;
+                }
+                Action::Transition { from, .. } => self.transition_to(from, cause)?,
+                Action::HoldForStream => self.report_status(),
+                Action::HoldForLoad => {
+                    info!("→ Holding off Away: {}", self.busy.as_deref().unwrap_or("busy"));
+                    self.report_status();
+                }
+                Action::HoldForCooldown { .. } => {
+                    metrics::record_cooldown_hold();
+                    self.report_status();
+                }
+                Action::Hold { .. } => {
+                    self.publish_state();
+                    self.notify(notify::Event::Holding, "No displays found", "Waiting for outputs to return");
+                }
+                Action::Cancelled => self.last_fingerprint = None,
+            }
+        }
+        Ok(())
+    }
+
+    fn poll(&mut self) -> Result<()> {
+        if let Some(sleep) = &self.sleep {
+            if sleep.is_asleep() {
+                self.machine.handle(Event::Pause, Instant::now());
+                return Ok(());
+            }
+            // Whatever was read before the suspend is stale, including the
+            // kernel's hotplug baseline.
+            if sleep.resumes() != self.resumes_seen {
+                self.resumes_seen = sleep.resumes();
+                info!("Resumed from suspend, re-reading outputs for {}", duration::format(self.config.resume_settle));
+                self.resumed_at = Some(Instant::now());
+                self.last_fingerprint = None;
+                self.hotplug = HotplugCounter::new();
+                self.machine.handle(Event::Pause, Instant::now());
+            }
+        }
+
+        let connectors = drm::scan();
+        // Only take readings until the settle time is up: outputs can be
+        // missing or off for a moment after waking.
+        if let Some(resumed) = self.resumed_at {
+            if resumed.elapsed() < self.config.resume_settle {
+                self.hotplug.observe(&connectors);
+                return Ok(());
+            }
+            info!("Outputs settled after resume");
+            self.resumed_at = None;
+        }
+        let connected = drm::connected(&connectors);
+        self.observe_hotplug(&connectors);
+        self.watch_dummy_plug();
+
+        // An empty sysfs list alone could just mean an unusual DRM layout,
+        // so the compositor has to agree before we stop acting.
+        let no_outputs = connected.is_empty() && display::get_displays().map_or(true, |d| d.is_empty());
+        if no_outputs {
+            let actions = self.machine.handle(Event::OutputsLost, Instant::now());
+            return self.carry_out(actions, Cause::Outputs);
+        }
+        if self.machine.state() == State::HoldingPattern {
+            let actions = self.machine.handle(Event::OutputsReturned, Instant::now());
+            return self.carry_out(actions, Cause::Outputs);
+        }
+        if let Some(reason) = self.gpu.observe(drm::cards(), drm::suspended_time()) {
+            self.recover_gpu(&reason)?;
+        }
+        match self.dock.observe(connected.clone(), Instant::now()) {
+            DockEvent::Stable => {}
+            DockEvent::Started { added, removed } => {
+                info!(
+                    "Dock event (connected: {added:?}, disconnected: {removed:?}), pausing for {}",
+                    duration::format(self.config.dock_settle)
+                );
+                self.machine.handle(Event::Pause, Instant::now());
+                return Ok(());
+            }
+            DockEvent::Settling => return Ok(()),
+            DockEvent::Settled => {
+                info!("Outputs settled, resuming");
+                // Connector names may have moved; an active dummy plug keeps
+                // its name until AtDesk turns it off.
+                if self.machine.state() == State::AtDesk {
+                    self.dummy_plug = None;
+                    self.resolve_outputs();
+                }
+            }
+        }
+        self.check_drift()?;
+
+        for name in self.inhibitors.expire(Instant::now()) {
+            info!("Inhibitor {name} expired");
+            self.resume_if_uninhibited();
+        }
+
+        // A timed switch works like a manual ForceAway. One that comes due
+        // while inhibited is skipped, not saved up.
+        let now = schedule::now();
+        let since = self.schedule_checked.replace(now);
+        if !self.inhibitors.is_empty() {
+            debug!("Inhibited ({}), not switching", self.inhibitors.reasons());
+            self.machine.handle(Event::Pause, Instant::now());
+            return Ok(());
+        }
+        if let Some(since) = since
+            && schedule::away_due(&self.config.schedule, since, now, &schedule::Local)
+        {
+            info!("Schedule: time to switch to Away");
+            return self.handle_command(Command::ForceAway, Cause::Schedule);
+        }
+
+        let detected = presence::detect(&self.config)?;
+        let presence = self.scheduled(detected, schedule::WeekTime::at(now, &schedule::Local));
+
+        self.observe_stream();
+        self.observe_load();
+
+        // Same readings as last time: nothing to decide unless a switch is
+        // waiting out the grace period, a manual override ran out, or
+        // Standby is due.
+        let standby_due = self.machine.standby_due(Instant::now());
+        let override_over = self.machine.override_due().is_some_and(|at| Instant::now() >= at);
+        let fingerprint = fingerprint(detected, presence, &connected);
+        let event = if self.last_fingerprint.replace(fingerprint) != Some(fingerprint) {
+            self.quiet_polls = 0;
+            Event::Presence { detected, effective: presence }
+        } else if self.machine.pending().is_some() || standby_due || override_over {
+            self.quiet_polls = 0;
+            Event::Tick
+        } else {
+            self.quiet_polls = self.quiet_polls.saturating_add(1);
             return Ok(());
+        };
+        let actions = self.machine.handle(event, Instant::now());
+        let cause = if standby_due { Cause::Standby } else { Cause::presence(&self.config) };
+        self.carry_out(actions, cause)
+    }
+
+    // Only matters while a stream could hold off AtDesk, Standby is
+    // counting the time without one, or the dummy plug is in a client's
+    // mode: once the last client has gone, it goes back to the configured
+    // one instead of waiting for the prep command's undo, which Sunshine
+    // only runs when the app is quit.
+    fn observe_stream(&mut self) {
+        let client_mode = self.config.sunshine.match_client_mode && self.client_mode.is_some();
+        if !(self.config.sunshine.block_desk_while_streaming || self.config.standby.enabled || client_mode) || self.machine.state() != State::Away {
+            return;
+        }
+        let streaming = sunshine::has_active_session(&self.config.sunshine.session_ports);
+        if streaming != self.machine.is_streaming() {
+            debug!("Stream {}", if streaming { "started" } else { "ended" });
+            let event = if streaming { Event::StreamStarted } else { Event::StreamEnded };
+            self.machine.handle(event, Instant::now());
+            if !streaming
+                && client_mode
+                && let Err(e) = self.set_client_mode(None)
+            {
+                error!("Couldn't switch the dummy plug back: {e}");
+            }
         }
+    }
 
-        // We want to transition, but we wait for the grace period first.
-        // This avoids flapping if the monitor briefly blinks off/on.
-        match self.transition_started {
-            None => {
-                eprintln!("[vitamink] DPMS changed to {dpms:?}, waiting grace period...");
-                self.transition_started = Some(Instant::now());
+    // Only looked at while a switch to Away is pending: nvidia-smi isn't
+    // free, and there's nothing to hold otherwise.
+    fn observe_load(&mut self) {
+        let away_pending = self.machine.pending().is_some_and(|(to, _)| to == State::Away);
+        let busy = if away_pending && self.config.load.is_enabled() { load::check(&self.config.load) } else { None };
+        if busy.is_none() && self.machine.is_busy() {
+            if self.machine.held_by_load() {
+                info!("Machine no longer busy");
             }
-            Some(started) if started.elapsed() >= self.config.grace_period => {
-                eprintln!("[vitamink] Grace period elapsed, transitioning: {} → {desired}", self.state);
-                self.state = desired;
-                self.transition_started = None;
-                self.apply_state()?;
+            self.machine.handle(Event::NotBusy, Instant::now());
+        } else if busy.is_some() && !self.machine.is_busy() {
+            self.machine.handle(Event::Busy, Instant::now());
+        }
+        self.busy = busy;
+    }
+
+    // The kernel sees a link drop or return seconds before kscreen does. An
+    // Away output that comes back (a KVM or flaky dongle bouncing) is turned
+    // on again right away instead of leaving Sunshine without a screen.
+    // The dummy plug itself is watch_dummy_plug's.
+    fn observe_hotplug(&mut self, connectors: &[Connector]) {
+        let away_outputs: Vec<String> = match self.active_profile() {
+            Some(profile) => profile.enable.iter().map(|o| o.name.clone()).collect(),
+            None => Vec::new(),
+        };
+        let away_outputs: Vec<String> =
+            away_outputs.into_iter().filter(|name| self.dummy_plug.as_ref() != Some(name)).collect();
+
+        let mut reapply = false;
+        for plug in self.hotplug.observe(connectors) {
+            info!("Kernel: {} {:?} (hotplug #{})", plug.name, plug.status, plug.count);
+            if self.machine.state() != State::Away || !away_outputs.contains(&plug.name) || self.adopted.outputs.contains(&plug.name) {
+                continue;
             }
-            Some(started) => {
-                let remaining = self.config.grace_period - started.elapsed();
-                eprintln!("[vitamink] Waiting... {:.0}s remaining", remaining.as_secs_f64());
+            match plug.status {
+                ConnectorStatus::Connected => reapply = true,
+                _ => self.notify(notify::Event::Error, "Stream output lost", &format!("{} disconnected", plug.name)),
             }
         }
 
-        Ok(())
+        if reapply {
+            info!("Away output reconnected, enabling it again");
+            if let Err(e) = self.enable_away_outputs() {
+                error!("Couldn't re-enable Away outputs: {e}");
+            }
+        }
     }
 
-    // Makes the hardware match the current state.
-    fn apply_state(&self) -> Result<(), String> {
-        match self.state {
-            State::Away => {
-                eprintln!("[vitamink] → Enabling dummy plug");
-                display::enable_dummy_plug(&self.config.dummy_plug)?;
+    // Pulling the dummy plug while Away leaves Sunshine capturing nothing,
+    // and enabling a disconnected output again can't help. This reports it
+    // once, leaves the plug alone until it's reconnected (streaming from a
+    // virtual output meanwhile with `on_dummy_unplug = "virtual"`), and
+    // then enables it again.
+    fn watch_dummy_plug(&mut self) {
+        if self.machine.state() != State::Away {
+            return;
+        }
+        let Some(name) = self.dummy_plug.clone().filter(|name| !self.is_virtual(name)) else {
+            return;
+        };
+        // Nothing in sysfs to go by.
+        let Some(connector) = drm::connector(&name) else {
+            return;
+        };
 
-                eprintln!("[vitamink] → Waiting for DRM framebuffer...");
-                display::wait_for_drm_active(
-                    &self.config.dummy_plug,
-                    Duration::from_secs(10),
-                )?;
+        match (connector.status, self.unplugged) {
+            (ConnectorStatus::Disconnected, None) => {
+                let action = self.config.on_dummy_unplug;
+                error!("Dummy plug {name} was unplugged");
+                let body = match action {
+                    UnplugAction::Wait => format!("{name} disconnected — waiting for it to come back"),
+                    UnplugAction::Virtual => {
+                        format!("{name} disconnected — streaming from {} meanwhile", self.config.virtual_output.output)
+                    }
+                };
+                self.notify(notify::Event::Error, "Dummy plug unplugged", &body);
+                self.unplugged = Some(action);
+                if action == UnplugAction::Virtual {
+                    let mode = self.away_outputs().ok().and_then(|outputs| outputs[0].mode);
+                    if let Err(e) = virtual_output::create(&self.config.virtual_output, mode.as_ref(), self.config.drm_timeout) {
+                        error!("Couldn't create a virtual output to stream from: {e}");
+                    }
+                }
+            }
+            (ConnectorStatus::Connected, Some(action)) => {
+                info!("Dummy plug {name} is back, enabling it again");
+                self.unplugged = None;
+                if action == UnplugAction::Virtual
+                    && let Err(e) = virtual_output::destroy()
+                {
+                    warn!("Couldn't remove the virtual output: {e}");
+                }
+                if let Err(e) = self.enable_away_outputs() {
+                    error!("Couldn't re-enable Away outputs: {e}");
+                }
+            }
+            _ => {}
+        }
+    }
 
-                eprintln!("[vitamink] → Starting Sunshine");
-                sunshine::start()?;
+    // Something Away set up was changed by hand, e.g. the dummy plug turned
+    // off with kscreen-doctor or Sunshine stopped: do what `on_drift` says
+    // rather than put it back regardless. Not in dry-run mode, where Away
+    // never set anything up.
+    fn check_drift(&mut self) -> Result<()> {
+        if self.machine.state() != State::Away || process::is_dry_run() {
+            return Ok(());
+        }
+        let Ok(mut enable) = self.away_outputs() else {
+            return Ok(());
+        };
+        // watch_dummy_plug looks after an unplugged one.
+        if self.unplugged.is_some() {
+            enable.retain(|o| self.dummy_plug.as_ref() != Some(&o.name));
+        }
+        let disable = self.active_profile().map(|p| p.disable.clone()).unwrap_or_default();
+        let services: Vec<(String, bool)> = self.config.services.iter().map(|s| (s.unit.clone(), services::is_active(s))).collect();
+        let displays = match display::get_displays() {
+            Ok(displays) => displays,
+            Err(e) => {
+                debug!("Not checking for changes made by hand: {e}");
+                return Ok(());
+            }
+        };
+        let drift = drift::find(&displays, &enable, &disable, &services, &self.adopted);
+        if drift.is_empty() {
+            return Ok(());
+        }
 
-                eprintln!("[vitamink] Away mode active");
+        match self.config.on_drift {
+            OnDrift::Adopt => {
+                warn!("Changed by hand while Away: {drift} — leaving it so");
+                self.adopted.adopt(&drift);
+                Ok(())
             }
-            State::AtDesk => {
-                if sunshine::is_running() {
-                    eprintln!("[vitamink] → Stopping Sunshine");
-                    sunshine::stop()?;
+            OnDrift::Pause => {
+                warn!("Changed by hand while Away: {drift} — not switching until `vitamink inhibit --release {}`", drift::INHIBITOR);
+                self.adopted.adopt(&drift);
+                self.inhibitors.hold(drift::INHIBITOR, &format!("changed by hand: {drift}"), None, Instant::now());
+                self.machine.handle(Event::Pause, Instant::now());
+                self.notify(notify::Event::Error, "Changed by hand", &format!("{drift} — automatic switching paused"));
+                Ok(())
+            }
+            OnDrift::Reapply => {
+                warn!("Changed by hand while Away: {drift} — setting Away up again");
+                let result = self.apply_steps();
+                self.persist();
+                if let Err(e) = &result {
+                    self.publish_state();
+                    self.notify(notify::Event::Error, "Couldn't set Away up again", &e.to_string());
                 }
+                result
+            }
+        }
+    }
+
+    // After a driver reload or resume, mode ids and connector state may have
+    // changed under us: drop what was derived from them and drive the
+    // outputs again. Only Away has outputs of ours to put back; the saved
+    // layout copes with renumbered modes when it's restored.
+    fn recover_gpu(&mut self, reason: &str) -> Result<()> {
+        warn!("GPU re-initialized ({reason}), re-scanning outputs");
+        self.last_fingerprint = None;
+        if self.machine.state() != State::Away {
+            return Ok(());
+        }
+
+        info!("Re-applying {}", self.machine.state());
+        let result = self.apply_steps();
+        self.persist();
+        if let Err(e) = &result {
+            self.publish_state();
+            self.notify(notify::Event::Error, "Couldn't recover from GPU reset", &e.to_string());
+        }
+        result
+    }
+
+    // The machine has already moved to its new state; this makes it so.
+    fn transition_to(&mut self, previous: State, cause: Cause) -> Result<()> {
+        let target = self.machine.state();
+        self.publish_state();
+
+        self.heartbeat.set_phase(match target {
+            State::Away => "applying Away",
+            State::AtDesk => "applying AtDesk",
+            State::HoldingPattern => "holding",
+            State::Standby => "entering Standby",
+        });
+        let (started, started_at) = (Instant::now(), status::unix_now());
+        self.times.count(previous);
+        self.adopted = Drift::default();
+        let result = self.apply_state(Some(previous));
+        if result.is_ok() {
+            metrics::record_transition(previous, target, started.elapsed());
+        }
+        let entry = history::Entry {
+            at: started_at,
+            from: previous,
+            to: target,
+            cause,
+            duration_ms: started.elapsed().as_millis() as u64,
+            error: result.as_ref().err().map(|e| e.to_string()),
+        };
+        // Like state.json, not written for transitions that only logged.
+        if !process::is_dry_run()
+            && let Err(e) = history::record(&entry, self.config.history_size)
+        {
+            warn!("Couldn't record history: {e}");
+        }
+        // A failed Away rolls back to AtDesk.
+        if self.machine.state() != target {
+            self.publish_state();
+        }
 
-                eprintln!("[vitamink] → Disabling dummy plug");
-                display::disable_dummy_plug(&self.config.dummy_plug)?;
+        self.last_transition = Some(SystemTime::now());
+        match &result {
+            Ok(()) => {
+                notify::clear_error();
+                webhook::clear_error();
+                let summary = match target {
+                    State::Away => "Switched to Away mode — Sunshine started",
+                    State::AtDesk if self.draining.is_some() => "Switched to AtDesk mode — Sunshine stops when the stream ends",
+                    State::AtDesk => "Switched to AtDesk mode — Sunshine stopped",
+                    State::HoldingPattern => "Holding",
+                    State::Standby => "Standby — Sunshine stopped until a client connects",
+                };
+                self.notify(notify::Event::Transition, summary, &format!("{previous} → {target}"));
+                self.update_digest(target);
+                self.update_sunshine_log(started_at);
+            }
+            Err(e) => self.notify(notify::Event::Error, &format!("Switching to {target} failed"), &e.to_string()),
+        }
+        result
+    }
 
-                eprintln!("[vitamink] At desk mode active");
+    // Presence as the schedule's windows see it: Absent throughout an
+    // `away` window, and during quiet hours an Absent that can't take us
+    // Away — the current state is held instead.
+    fn scheduled(&mut self, presence: Presence, now: schedule::WeekTime) -> Presence {
+        let window = schedule::active(&self.config.schedule, now);
+        if window != self.schedule_window {
+            match window.or(self.schedule_window) {
+                Some(ScheduleAction::Suppress) if window.is_some() => info!("Schedule: quiet hours, no automatic Away"),
+                Some(ScheduleAction::Suppress) => info!("Schedule: quiet hours over"),
+                Some(ScheduleAction::Away) if window.is_some() => info!("Schedule: Away window started"),
+                Some(ScheduleAction::Away) => info!("Schedule: Away window over"),
+                None => {}
             }
+            self.schedule_window = window;
         }
 
-        Ok(())
+        match (window, presence) {
+            (Some(ScheduleAction::Away), _) => Presence::Absent,
+            (Some(ScheduleAction::Suppress), Presence::Absent) if !matches!(self.machine.state(), State::Away | State::Standby) => {
+                Presence::Present
+            }
+            _ => presence,
+        }
     }
-}
 
-// ---- Tests ----
+    fn cancel_pending(&mut self) {
+        if self.machine.pending().is_none() {
+            return;
+        }
+        let detected = presence::detect(&self.config).unwrap_or(Presence::Unknown);
+        self.machine.handle(Event::CancelPending { detected }, Instant::now());
+        self.last_fingerprint = None;
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    // Matches the config's output patterns (globs, `edid:`) against the
+    // outputs there are now, e.g. after a dock came back with its
+    // connectors renumbered.
+    fn resolve_outputs(&mut self) {
+        let Ok(mut fresh) = config::parse(&self.config_text) else {
+            return;
+        };
+        pattern::resolve_config(&mut fresh);
+        if fresh.main_display != self.config.main_display {
+            info!("main_display is now {}", fresh.main_display.join(", "));
+            self.config.main_display = fresh.main_display;
+            self.last_fingerprint = None;
+        }
+        if fresh.dummy_plug != self.config.dummy_plug {
+            info!("dummy_plug is now {}", fresh.dummy_plug);
+            self.config.dummy_plug = fresh.dummy_plug;
+        }
+        self.config.profiles = fresh.profiles;
+    }
+
+    // Swaps in the config file's current contents. One that doesn't parse,
+    // or drops the profile that's applied right now, leaves the running
+    // config as it is.
+    fn reload(&mut self) {
+        self.config_modified = config::modified();
+        let path = config::default_path();
+        let loaded = config::read_text().and_then(|text| match config::parse(&text) {
+            Ok(config) => Ok((text, config)),
+            Err(message) => Err(VitaminkError::Config { path: path.clone(), message }),
+        });
+        let (text, mut config) = match loaded {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                error!("Not reloading: {e}");
+                self.notify(notify::Event::Error, "Config not reloaded", &e.to_string());
+                return;
+            }
+        };
+        pattern::resolve_config(&mut config);
+        if matches!(self.machine.state(), State::Away | State::Standby)
+            && let Some(name) = &self.profile
+            && !config.profiles.contains_key(name)
+        {
+            error!("Not reloading: profile \"{name}\" is applied and the new config drops it");
+            return;
+        }
+
+        let changes = config::diff(&self.config_text, &text);
+        if changes.is_empty() {
+            info!("Config reloaded, nothing changed");
+        }
+        for change in &changes {
+            if config::needs_restart(change) {
+                warn!("Config: {change} (takes effect after a restart)");
+            } else {
+                info!("Config: {change}");
+            }
+        }
+
+        if config.dock_settle != self.config.dock_settle {
+            self.dock = DockTracker::new(config.dock_settle);
+        }
+        self.machine.configure(&config);
+        process::set_timeout(config.command_timeout);
+        logging::configure(config.log_format, config.log_file.as_deref());
+        if let Some(bus) = &self.bus
+            && let Err(e) = bus.set_profiles(config.profiles.keys().cloned().collect())
+        {
+            info!("{e}");
+        }
+        crash::note_config(&text);
+        self.config = config;
+        self.config_text = text;
+        self.last_fingerprint = None;
+        // Picks up changed ports and patterns on the way back up.
+        self.wake = None;
+        self.update_wake_listener();
+        self.sunshine_log = None;
+        self.update_sunshine_log(status::unix_now());
+    }
+
+    // The last inhibitor is gone: look at presence afresh on the next poll
+    // rather than trusting what was seen before.
+    fn resume_if_uninhibited(&mut self) {
+        if self.inhibitors.is_empty() {
+            info!("No inhibitors left, resuming automatic switching");
+            self.last_fingerprint = None;
+        }
+    }
+
+    // Takes the countdown down once nothing is pending any more — the
+    // switch happened, was cancelled, or presence went back.
+    fn update_pending_notice(&mut self) {
+        if self.pending_notice && self.machine.pending().is_none() {
+            notify::close(notify::Event::Pending);
+            self.pending_notice = false;
+        }
+    }
+
+    // Tells D-Bus and control socket listeners how long is left of the
+    // grace period, each time that changes, and once it's over.
+    fn publish_countdown(&mut self) {
+        let countdown = self.pending();
+        if countdown == self.countdown {
+            return;
+        }
+        if let Some(bus) = &self.bus
+            && let Err(e) = bus.set_countdown(countdown.as_ref())
+        {
+            info!("{e}");
+        }
+        if let Some(control) = &self.control {
+            control.publish_countdown(countdown.as_ref());
+        }
+        self.countdown = countdown;
+    }
+
+    // The switch waiting out the grace period, for status.json and
+    // `publish_countdown`.
+    fn pending(&self) -> Option<Pending> {
+        let ((to, _), due) = self.machine.pending().zip(self.machine.due())?;
+        let remaining = due.saturating_duration_since(Instant::now());
+        Some(Pending {
+            to,
+            due: status::unix_secs(SystemTime::now() + remaining),
+            remaining: remaining.as_secs_f64().round() as u64,
+            held_by_stream: self.machine.held_by_stream(),
+            held_by_load: self.machine.held_by_load().then(|| self.busy.clone()).flatten(),
+        })
+    }
+
+    fn notify(&self, event: notify::Event, summary: &str, body: &str) {
+        notify::send(&self.config.notifications, event, summary, body);
+        webhook::send(&self.config.webhooks, event, summary, body);
+    }
+
+    // Starts the Away digest, or sends it once we're back at the desk.
+    fn update_digest(&mut self, target: State) {
+        let notifications = &self.config.notifications;
+        let wanted = (notifications.enabled && notifications.events.contains(&notify::Event::Summary))
+            || self.config.webhooks.iter().any(|hook| hook.events.contains(&notify::Event::Summary));
+        match target {
+            State::Away if wanted => {
+                self.away_digest = Some(Digest::new(Instant::now()));
+            }
+            State::AtDesk => {
+                if let Some(digest) = self.away_digest.take() {
+                    self.notify(notify::Event::Summary, "Welcome back", &digest.finish(Instant::now()));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Tells D-Bus listeners and systemd about the current state.
+    fn publish_state(&mut self) {
+        logging::set_state(self.machine.state());
+        self.update_wake_listener();
+        if let Some(bus) = &self.bus
+            && let Err(e) = bus.set_state(self.machine.state())
+        {
+            info!("{e}");
+        }
+        if let Some(mqtt) = &self.mqtt
+            && let Err(e) = mqtt.publish_state(self.machine.state())
+        {
+            warn!("MQTT: {e}");
+        }
+        if let Some(control) = &self.control {
+            control.publish_state(self.machine.state());
+        }
+        self.report_status();
+    }
+
+    // Listens only while AtDesk with Sunshine stopped, and stops before
+    // going Away starts Sunshine on some of the same ports. Standby always
+    // listens, Moonlight's probe included: it's how a client gets back in.
+    fn update_wake_listener(&mut self) {
+        let standby = self.machine.state() == State::Standby;
+        let wanted = (standby || self.config.wake.enabled && self.machine.state() == State::AtDesk)
+            && !process::is_dry_run()
+            && !sunshine::is_running(&self.config.services);
+        if !wanted {
+            self.wake = None;
+        } else if self.wake.is_none() {
+            let config = WakeConfig {
+                enabled: true,
+                ports: self.config.wake.ports.clone(),
+                moonlight_probe: self.config.wake.moonlight_probe || standby,
+            };
+            match wake::Listener::start(&config, self.commands_tx.clone()) {
+                Ok(listener) => self.wake = Some(listener),
+                Err(e) => warn!("Wake: {e} — not listening for wake packets"),
+            }
+        }
+    }
+
+    // Follows Sunshine's journal while Away, from `since` (Unix time) if
+    // it isn't already. The journal has no news for a dry run.
+    fn update_sunshine_log(&mut self, since: u64) {
+        let wanted = self.machine.state() == State::Away && self.config.sunshine.on_error != OnError::Off && !process::is_dry_run();
+        if !wanted {
+            self.sunshine_log = None;
+            return;
+        }
+        if self.sunshine_log.is_some() {
+            return;
+        }
+        let Some(service) = services::sunshine(&self.config.services) else {
+            return;
+        };
+        match journal::Watcher::start(service, &self.config.sunshine.error_patterns, since, self.commands_tx.clone()) {
+            Ok(watcher) => self.sunshine_log = Some(watcher),
+            Err(e) => {
+                warn!("Not watching Sunshine's log: {e}");
+                // Not again every poll.
+                self.config.sunshine.on_error = OnError::Off;
+            }
+        }
+    }
+
+    // Sunshine logged an error it won't stream past: do what `on_error`
+    // says about it.
+    fn on_sunshine_error(&mut self, line: &str) -> Result<()> {
+        if self.machine.state() != State::Away {
+            return Ok(());
+        }
+        error!("Sunshine: {line}");
+        if self.sunshine_error_at.is_some_and(|at| at.elapsed() < SUNSHINE_ERROR_BACKOFF) {
+            return Ok(());
+        }
+        self.sunshine_error_at = Some(Instant::now());
+        let body = match self.config.sunshine.on_error {
+            OnError::Off => return Ok(()),
+            OnError::Notify => line.to_string(),
+            OnError::Restart => format!("{line} — restarting it"),
+            OnError::Reapply => format!("{line} — setting up Away again"),
+        };
+        self.notify(notify::Event::Error, "Sunshine error", &body);
+        if let Some(digest) = &mut self.away_digest {
+            digest.record_error();
+        }
+        match self.config.sunshine.on_error {
+            OnError::Restart => match services::sunshine(&self.config.services) {
+                Some(service) => services::restart(service),
+                None => Ok(()),
+            },
+            OnError::Reapply => {
+                info!("Re-applying {}", self.machine.state());
+                let result = self.apply_steps();
+                self.persist();
+                result
+            }
+            OnError::Off | OnError::Notify => Ok(()),
+        }
+    }
+
+    fn poll_interval(&self) -> Duration {
+        if self.machine.pending().is_some() {
+            return self.config.poll_interval;
+        }
+        adaptive_interval(self.config.poll_interval, self.config.max_poll_interval, self.quiet_polls)
+    }
+
+    // The line `systemctl --user status vitamink` shows.
+    fn report_status(&self) {
+        let note = if self.machine.held_by_stream() {
+            " (stream in progress, holding)"
+        } else if self.machine.held_by_load() {
+            " (machine busy, holding)"
+        } else {
+            ""
+        };
+        systemd::status(&format!("{}{note}", self.machine.state()));
+    }
+
+    // status.json, for bars and scripts (see status.rs). Written after
+    // every poll and command, which is when the time in the current state
+    // is counted too.
+    fn write_status(&mut self) {
+        self.times.count(self.machine.state());
+        let sunshine_running = sunshine::is_running(&self.config.services);
+        metrics::set_status(self.machine.state(), sunshine_running);
+        logging::set_state(self.machine.state());
+        metrics::set_state_seconds(self.times.total());
+        let status = Status {
+            state: self.machine.state(),
+            pid: std::process::id(),
+            main_display: self.config.primary_display().to_string(),
+            dpms: display::read_dpms(self.config.primary_display()),
+            sunshine_running,
+            services: self.config.services.iter().map(|s| (s.unit.clone(), services::is_active(s))).collect(),
+            profile: self.profile.clone(),
+            last_transition: self.last_transition.map(status::unix_secs),
+            last_error: self.last_error.clone(),
+            hotplugs: self.hotplug.counts().clone(),
+            updated: status::unix_now(),
+            pending: self.pending(),
+            inhibitors: self.inhibitors.list(Instant::now()),
+            progress: self.progress.clone(),
+            time_in_state: self.times.total().clone(),
+        };
+        crash::note_status(&status);
+        if let Err(e) = status::write(&status) {
+            warn!("Couldn't write status file: {e}");
+        }
+    }
+
+    fn dummy_plug(&mut self) -> Result<String> {
+        if let Some(name) = &self.dummy_plug {
+            return Ok(name.clone());
+        }
+        let name = match self.active_profile() {
+            // Never empty; config::parse rejects that.
+            Some(profile) => profile.enable[0].name.clone(),
+            None => dummy::resolve(&self.config)?,
+        };
+        self.dummy_plug = Some(name.clone());
+        Ok(name)
+    }
+
+    fn active_profile(&self) -> Option<&ProfileConfig> {
+        self.profile.as_ref().and_then(|name| self.config.profiles.get(name))
+    }
+
+    // Outside Away this only changes what the next Away uses. While Away,
+    // the current outputs are undone, the saved layout put back, and the
+    // new profile applied on top of it; Sunshine keeps running.
+    fn switch_profile(&mut self, profile: Option<String>) -> Result<()> {
+        let show = |p: &Option<String>| p.clone().unwrap_or_else(|| "none".to_string());
+        info!("Profile: {} → {}", show(&self.profile), show(&profile));
+
+        if self.machine.state() != State::Away {
+            self.profile = profile;
+        } else {
+            self.disable_away_outputs()?;
+            if let Some(layout) = &self.saved_layout {
+                info!("→ Restoring display layout");
+                layout.restore(self.config.primary_display())?;
+            }
+            self.profile = profile;
+            let result = self.enable_away_outputs().and_then(|()| {
+                // Sunshine only reads its config at startup.
+                if self.sunshine_template().is_some() && !process::is_dry_run() {
+                    self.render_sunshine_conf()?;
+                    if let Some(sunshine) = services::sunshine(&self.config.services) {
+                        services::restart(sunshine)?;
+                    }
+                }
+                Ok(())
+            });
+            self.persist();
+            result?;
+        }
+
+        if let Some(bus) = &self.bus
+            && let Err(e) = bus.set_profile(self.profile.as_deref())
+        {
+            info!("{e}");
+        }
+        Ok(())
+    }
+
+    // Outside Away this is kept for the next Away; while Away, the dummy
+    // plug switches modes right away.
+    fn set_client_mode(&mut self, mode: Option<ModeTarget>) -> Result<()> {
+        if !self.config.sunshine.match_client_mode {
+            debug!("Ignoring the client's mode, match_client_mode is off");
+            return Ok(());
+        }
+        // Warned about at startup.
+        if !compat::supports(Feature::ClientMode) {
+            return Ok(());
+        }
+        if mode == self.client_mode {
+            return Ok(());
+        }
+        match &mode {
+            Some(mode) => info!("Moonlight client wants {mode}"),
+            None => info!("Stream ended, back to the configured mode"),
+        }
+        self.client_mode = mode;
+
+        if self.machine.state() != State::Away {
+            return Ok(());
+        }
+        let name = self.dummy_plug()?;
+        let output = self.away_outputs()?.into_iter().find(|o| o.name == name);
+        let output = output.unwrap_or(ProfileOutput { name: name.clone(), mode: None, scale: None });
+        info!("→ Switching {name} to {}", output.mode.map_or("its preferred mode".to_string(), |t| t.to_string()));
+        display::enable_output(&name, output.mode.as_ref(), output.scale)
+    }
+
+    // What Away turns on, and in which mode. The client's mode, if any,
+    // goes to the dummy plug (a profile's first output).
+    fn away_outputs(&mut self) -> Result<Vec<ProfileOutput>> {
+        let mut enable: Vec<ProfileOutput> = match self.active_profile() {
+            Some(profile) => profile.enable.clone(),
+            None => vec![ProfileOutput {
+                name: self.dummy_plug()?,
+                mode: self.config.dummy_mode,
+                scale: self.config.dummy_scale,
+            }],
+        };
+        if let Some(mode) = self.client_mode {
+            enable[0].mode = Some(mode);
+        }
+        Ok(enable)
+    }
+
+    // The dummy plug, or everything the active profile turns on and off.
+    fn enable_away_outputs(&mut self) -> Result<()> {
+        let enable = self.away_outputs()?;
+
+        // An unplugged dummy plug can't be enabled (see watch_dummy_plug).
+        let enable: Vec<ProfileOutput> = match self.unplugged {
+            Some(_) => enable.into_iter().filter(|o| self.dummy_plug.as_ref() != Some(&o.name)).collect(),
+            None => enable,
+        };
+
+        for ProfileOutput { name, mode, .. } in &enable {
+            if self.is_virtual(name) {
+                virtual_output::create(&self.config.virtual_output, mode.as_ref(), self.config.drm_timeout)?;
+            }
+        }
+        // In dry-run mode a virtual output was never created.
+        let enable: Vec<ProfileOutput> =
+            enable.into_iter().filter(|o| !(process::is_dry_run() && self.is_virtual(&o.name))).collect();
+        let disable = self.active_profile().map(|p| p.disable.clone()).unwrap_or_default();
+
+        // Before anything picks a mode (see custom_mode.rs).
+        if !process::is_dry_run() {
+            let names: Vec<String> = enable.iter().map(|o| o.name.clone()).collect();
+            let added = custom_mode::add(&self.config.custom_modes, &names)?;
+            if !added.is_empty() {
+                self.custom_modes.extend(added);
+                self.persist();
+            }
+        }
+
+        let displays = display::get_displays()?;
+        // Blanked at the desk with `away_outputs_off = "dpms-off"`, so still
+        // enabled: they only need waking.
+        if self.config.away_outputs_off == OffStrategy::DpmsOff {
+            let blanked = enable.iter().filter(|o| {
+                !self.is_virtual(&o.name) && displays.iter().any(|d| d.name == o.name && d.state == DisplayState::Enabled)
+            });
+            for output in blanked {
+                info!("→ Waking {}", output.name);
+                if let Err(e) = display::set_dpms(&output.name, true) {
+                    warn!("Couldn't wake {}: {e}", output.name);
+                }
+            }
+        }
+
+        // Only what isn't set up already, e.g. after a restart while Away.
+        let (changed, off) = display::outstanding(&displays, &enable, &disable);
+        if changed.is_empty() && off.is_empty() {
+            info!("→ Away outputs already set up");
+            self.wait_for_away_outputs(&enable)?;
+            self.keep_away_primary(&enable, &disable);
+            return Ok(());
+        }
+
+        // A profile's outputs all in one go, each kscreen-doctor call
+        // being slow. If that fails, they're changed one at a time.
+        if changed.len() + off.len() > 1 {
+            let names: Vec<&str> = changed.iter().map(|o| o.name.as_str()).chain(off.iter().map(String::as_str)).collect();
+            info!("→ Changing {} at once", names.join(", "));
+            match display::apply_outputs(&changed, &off) {
+                Ok(()) => {
+                    self.wait_for_away_outputs(&enable)?;
+                    self.keep_away_primary(&enable, &disable);
+                    return Ok(());
+                }
+                Err(e) => warn!("{e} — changing the outputs one at a time instead"),
+            }
+        }
+
+        for ProfileOutput { name, mode, scale } in &changed {
+            info!("→ Enabling {name}");
+            display::enable_output(name, mode.as_ref(), *scale)?;
+        }
+        self.wait_for_away_outputs(&enable)?;
+        for name in &off {
+            info!("→ Disabling {name}");
+            display::disable_output(name)?;
+        }
+        self.keep_away_primary(&enable, &disable);
+        Ok(())
+    }
+
+    // KWin sometimes makes a newly enabled dummy plug primary, and moves
+    // the panels onto it.
+    fn keep_away_primary(&self, enable: &[ProfileOutput], disable: &[String]) {
+        let desk = self.saved_layout.as_ref().and_then(Layout::primary);
+        if let Some(name) = away_primary(self.config.away_primary.as_deref(), desk, enable, disable) {
+            make_primary(&name);
+        }
+    }
+
+    fn wait_for_away_outputs(&self, enabled: &[ProfileOutput]) -> Result<()> {
+        info!("→ Waiting for DRM framebuffer...");
+        if !process::is_dry_run() {
+            // A virtual output has no connector; it's ready once KWin lists it.
+            for output in enabled.iter().filter(|o| !self.is_virtual(&o.name)) {
+                display::wait_for_drm_active(&output.name, self.config.drm_timeout)?;
+                display::wait_for_framebuffer(&output.name, self.config.drm_timeout)?;
+            }
+        }
+        Ok(())
+    }
+
+    // A stream can't work without a hardware encoder, and Sunshine won't
+    // say so until a client tries (see sunshine/encoder.rs).
+    fn check_encoder(&mut self) -> Result<()> {
+        match encoder::check(self.config.sunshine.nvenc_sessions) {
+            None => debug!("No hardware encoder to check"),
+            Some(Ok(found)) => info!("→ Hardware encoder free: {found}"),
+            Some(Err(problem)) => self.encoder_problem("No hardware encoder free", problem)?,
+        }
+        // The GPU it captures from, on a machine with more than one.
+        let output = self.away_outputs()?.into_iter().map(|o| o.name).find(|name| !self.is_virtual(name));
+        if let Some(output) = output
+            && let Some(result) = encoder::check_adapter(&self.config.sunshine.config_file, &output)
+        {
+            match result {
+                Ok(found) => info!("→ Capture adapter: {found}"),
+                Err(problem) => self.encoder_problem("Sunshine is on the wrong GPU", problem)?,
+            }
+        }
+        Ok(())
+    }
+
+    // Fails going Away with `encoder_check = "abort"`, else warns.
+    fn encoder_problem(&mut self, title: &str, problem: String) -> Result<()> {
+        if self.config.sunshine.encoder_check == EncoderCheck::Abort {
+            return Err(VitaminkError::Unavailable(format!("Sunshine couldn't stream: {problem}")));
+        }
+        warn!("Sunshine may not be able to stream: {problem}");
+        self.notify(notify::Event::Error, title, &problem);
+        Ok(())
+    }
+
+    // Holds the Away transition until a client could actually stream.
+    // Sunshine being slow isn't worth undoing Away for: a timeout is
+    // reported, and the client can still connect once it's up.
+    fn wait_for_sunshine(&mut self) -> Result<()> {
+        let timeout = self.config.sunshine.ready_timeout;
+        if timeout.is_zero() || services::sunshine(&self.config.services).is_none() {
+            return Ok(());
+        }
+        let outputs: Vec<String> =
+            self.away_outputs()?.into_iter().map(|o| o.name).filter(|name| !self.is_virtual(name)).collect();
+
+        self.heartbeat.set_phase("waiting for Sunshine");
+        let ready = sunshine::wait_until_ready(&outputs, &self.config.services, &self.config.sunshine.api_url, timeout, |stage| {
+            info!("→ Readiness: {stage}");
+            systemd::status(&format!("Away ({stage})"));
+        });
+        match ready {
+            Ok(took) => info!("→ Sunshine ready after {:.1}s", took.as_secs_f64()),
+            Err(e) => {
+                warn!("{e}");
+                self.notify(notify::Event::Error, "Sunshine isn't ready", &e.to_string());
+            }
+        }
+        self.report_status();
+        Ok(())
+    }
+
+    // Turns off what enable_away_outputs turned on; the layout restore
+    // brings back anything a profile disabled.
+    fn disable_away_outputs(&mut self) -> Result<()> {
+        let names: Vec<String> = match self.active_profile() {
+            Some(profile) => profile.enable.iter().map(|o| o.name.clone()).collect(),
+            None => vec![self.dummy_plug()?],
+        };
+        if self.unplugged == Some(UnplugAction::Virtual) {
+            info!("→ Removing virtual output {}", self.config.virtual_output.output);
+            virtual_output::destroy()?;
+        }
+        let mut disable = Vec::new();
+        for name in names {
+            if self.unplugged.is_some() && self.dummy_plug.as_ref() == Some(&name) {
+                info!("→ {name} is unplugged, nothing to disable");
+            } else if self.is_virtual(&name) {
+                info!("→ Removing virtual output {name}");
+                virtual_output::destroy()?;
+            } else {
+                disable.push(name);
+            }
+        }
+        // Those that are off already, at the desk or after a restart there,
+        // are left alone.
+        let (_, mut disable) = display::outstanding(&display::get_displays()?, &[], &disable);
+        if self.config.away_outputs_off == OffStrategy::DpmsOff {
+            disable.retain(|name| {
+                info!("→ Blanking {name}");
+                match display::set_dpms(name, false) {
+                    Ok(()) => {
+                        self.blanked.push(name.clone());
+                        false
+                    }
+                    Err(e) => {
+                        warn!("{e} — disabling {name} instead");
+                        true
+                    }
+                }
+            });
+        }
+        if disable.len() > 1 {
+            info!("→ Disabling {} at once", disable.join(", "));
+            match display::apply_outputs(&[], &disable) {
+                Ok(()) => disable.clear(),
+                Err(e) => warn!("{e} — disabling the outputs one at a time instead"),
+            }
+        }
+        for name in &disable {
+            info!("→ Disabling {name}");
+            display::disable_output(name)?;
+        }
+        // Blanked outputs still show the mode.
+        let (keep, remove): (Vec<CustomMode>, Vec<CustomMode>) = std::mem::take(&mut self.custom_modes).into_iter().partition(|m| self.blanked.contains(&m.output));
+        custom_mode::remove(&remove);
+        self.custom_modes = keep;
+        self.dummy_plug = None;
+        self.unplugged = None;
+        Ok(())
+    }
+
+    // The active profile's sunshine.conf template, or the global one.
+    fn sunshine_template(&self) -> Option<String> {
+        self.active_profile()
+            .and_then(|p| p.sunshine_template.clone())
+            .or_else(|| self.config.sunshine.config_template.clone())
+    }
+
+    // With the mode the dummy plug was asked for, or failing that the one
+    // it ended up in.
+    fn render_sunshine_conf(&mut self) -> Result<()> {
+        let Some(template) = self.sunshine_template() else {
+            return Ok(());
+        };
+        let name = self.dummy_plug()?;
+        let mode = match self.away_outputs()?.into_iter().next().and_then(|o| o.mode) {
+            Some(mode) => mode,
+            None => display::get_displays()?
+                .into_iter()
+                .find(|d| d.name == name)
+                .and_then(|d| d.modes.into_iter().find(|m| m.current))
+                .map(|m| ModeTarget { width: m.width, height: m.height, refresh: Some(m.refresh) })
+                .ok_or_else(|| VitaminkError::Parse(format!("{name} has no current mode to render sunshine.conf with")))?,
+        };
+        self.sunshine_conf =
+            Some(sunshine::conf::apply(&template, &self.config.sunshine.config_file, &mode, self.sunshine_conf.clone())?);
+        self.persist();
+        Ok(())
+    }
+
+    // Whether `name` is the output `dummy_plug = "virtual"` creates.
+    fn is_virtual(&self, name: &str) -> bool {
+        self.config.dummy_plug == virtual_output::VIRTUAL && name == self.config.virtual_output.output
+    }
+
+    // Makes the hardware match the current state, then records what it
+    // engaged — also on failure, since some steps may have gone through.
+    // User hooks run before the first step and after the last one.
+    fn apply_state(&mut self, previous: Option<State>) -> Result<()> {
+        let hooks = &self.config.hooks;
+        let (pre, post) = match self.machine.state() {
+            State::Away => (("pre_away", hooks.pre_away.clone()), ("on_away", hooks.on_away.clone())),
+            State::AtDesk => (("pre_desk", hooks.pre_desk.clone()), ("on_desk", hooks.on_desk.clone())),
+            // Neither side's hooks: Away is only paused.
+            State::Standby => (("pre_standby", Vec::new()), ("on_standby", Vec::new())),
+            State::HoldingPattern => return Ok(()),
+        };
+
+        // So pre_away hooks can see VITAMINK_DUMMY_PLUG; a failure here
+        // is reported by the dummy plug step itself.
+        if self.machine.state() == State::Away {
+            let _ = self.dummy_plug();
+        }
+
+        self.run_hooks(pre.0, &pre.1, previous);
+        let result = self.apply_steps();
+        self.persist();
+        if result.is_ok() {
+            self.run_hooks(post.0, &post.1, previous);
+        }
+        result
+    }
+
+    fn run_hooks(&self, hook: &str, commands: &[String], previous: Option<State>) {
+        let transition = Transition {
+            hook,
+            state: self.machine.state().to_string(),
+            previous: previous.map(|s| s.to_string()),
+            main_display: self.config.primary_display(),
+            dummy_plug: self.dummy_plug.as_deref(),
+        };
+        hooks::run_all(commands, &transition, self.config.hooks.timeout);
+    }
+
+    fn persist(&self) {
+        if process::is_dry_run() {
+            return;
+        }
+        let persisted = Persisted {
+            state: self.machine.state(),
+            dummy_plug: self.dummy_plug.clone(),
+            profile: self.profile.clone(),
+            saved_layout: self.saved_layout.clone(),
+            windows: self.windows.clone(),
+            custom_modes: self.custom_modes.clone(),
+            privacy: self.privacy.clone(),
+            desktop: self.desktop.clone(),
+            compositor: self.compositor.clone(),
+            audio: self.audio.clone(),
+            gamescope: self.gamescope,
+            app: self.app.clone(),
+            sunshine_conf: self.sunshine_conf.clone(),
+            power: self.power.clone(),
+        };
+        if let Err(e) = persist::save(&persisted) {
+            error!("Couldn't save state: {e}");
+        }
+    }
+
+    // Away engages each step in `[transition] away` order (`AWAY_STEPS`
+    // unless configured, see pipeline.rs); AtDesk undoes them in `desk`
+    // order, by default the reverse. If an Away step fails, everything begun so far — including
+    // the failed step, which may have half happened — is undone again and
+    // the daemon falls back to AtDesk, so a retry starts from a clean slate
+    // instead of a half-transitioned desk.
+    //
+    // AtDesk isn't rolled back: each of its steps forgets what it undid, so
+    // the next attempt picks up where this one stopped, and going back to
+    // Away would take the screen from someone sitting in front of it.
+    //
+    // In dry-run mode, external commands are only logged (see process::run),
+    // and steps that work through D-Bus, sysfs or input grabs are announced
+    // but skipped. Nothing is engaged, so AtDesk has nothing of theirs to undo.
+    fn apply_steps(&mut self) -> Result<()> {
+        let result = self.run_steps();
+        if let Some(progress) = self.progress.take() {
+            let total: u64 = progress.done.iter().map(|s| s.ms).sum();
+            let slow: Vec<String> =
+                progress.done.iter().filter(|s| s.ms >= 100).map(|s| format!("{} {:.1}s", s.step, s.ms as f64 / 1000.0)).collect();
+            if slow.is_empty() {
+                debug!("Steps took {:.1}s", total as f64 / 1000.0);
+            } else {
+                info!("Steps took {:.1}s: {}", total as f64 / 1000.0, slow.join(", "));
+            }
+            if !process::is_dry_run()
+                && let Err(e) = status::write_progress(None)
+            {
+                debug!("Couldn't update the status file: {e}");
+            }
+        }
+        result
+    }
+
+    fn run_steps(&mut self) -> Result<()> {
+        match self.machine.state() {
+            State::Away => {
+                let stages = self.config.transition.away();
+                for (i, stage) in stages.iter().enumerate() {
+                    if let Err(e) = self.run_stage(stage, false, i + 1, stages.len()) {
+                        error!("Couldn't {stage}: {e} — rolling back");
+                        let begun: Vec<Step> = stages[..=i]
+                            .iter()
+                            .filter_map(|stage| match stage {
+                                Stage::Step(step) => Some(*step),
+                                _ => None,
+                            })
+                            .collect();
+                        let rollback = self.roll_back(&begun);
+                        self.machine.set_state(State::AtDesk);
+                        return Err(VitaminkError::Transition {
+                            step: stage.to_string(),
+                            source: Box::new(e),
+                            rollback: rollback.map(Box::new),
+                        });
+                    }
+                }
+                info!("Away mode active");
+            }
+            State::AtDesk => {
+                let stages = self.config.transition.desk();
+                for (i, stage) in stages.iter().enumerate() {
+                    self.run_stage(stage, true, i + 1, stages.len())?;
+                }
+                // Cosmetic, and monitors fresh out of DPMS off don't always
+                // answer: not worth failing the transition over.
+                if self.config.ddc.is_enabled() {
+                    info!("→ Setting monitor brightness/input over DDC");
+                    if let Err(e) = ddc::restore(&self.config.ddc, self.config.primary_display()) {
+                        warn!("DDC: {e}");
+                    }
+                }
+                info!("At desk mode active");
+            }
+            // What draws power while nobody streams. The rest of Away stays,
+            // the saved layout above all, and going Away again starts these
+            // back up.
+            State::Standby => {
+                for (i, step) in STANDBY_STEPS.into_iter().enumerate() {
+                    self.run_stage(&Stage::Step(step), true, i + 1, STANDBY_STEPS.len())?;
+                }
+                info!("Standby active");
+            }
+            // Deliberately hands-off: there's nothing to drive.
+            State::HoldingPattern => {}
+        }
+
+        Ok(())
+    }
+
+    // Engages or undoes a step, or runs a `[transition]` command or pause,
+    // the `number`th of `total`, first telling status.json and D-Bus
+    // listeners, then noting how long it took.
+    fn run_stage(&mut self, stage: &Stage, undo: bool, number: usize, total: usize) -> Result<()> {
+        let name = match stage {
+            Stage::Step(step) if undo => format!("undo \"{step}\""),
+            stage => stage.to_string(),
+        };
+        debug!("Step {number}/{total}: {name}");
+        let to = self.machine.state();
+        let progress = self.progress.get_or_insert_with(|| Progress {
+            to,
+            step: String::new(),
+            number: 0,
+            total,
+            started: status::unix_now(),
+            done: Vec::new(),
+        });
+        progress.step = name.clone();
+        progress.number = number;
+        progress.total = total;
+        if !process::is_dry_run()
+            && let Err(e) = status::write_progress(Some(progress))
+        {
+            debug!("Couldn't update the status file: {e}");
+        }
+        if let Some(bus) = &self.bus
+            && let Err(e) = bus.set_progress(progress)
+        {
+            debug!("{e}");
+        }
+
+        let started = Instant::now();
+        let result = match stage {
+            Stage::Step(step) if undo => self.undo(*step),
+            Stage::Step(step) => self.engage(*step),
+            Stage::Wait(wait) => {
+                info!("→ Waiting {}", duration::format(*wait));
+                if !process::is_dry_run() {
+                    thread::sleep(*wait);
+                }
+                Ok(())
+            }
+            Stage::Run(command) => self.run_command(command),
+        };
+        if let Some(progress) = &mut self.progress {
+            progress.done.push(StepTime { step: name, ms: started.elapsed().as_millis() as u64 });
+        }
+        result
+    }
+
+    // A `[transition]` command: part of the transition, so unlike a hook
+    // its failure is the transition's.
+    fn run_command(&self, command: &str) -> Result<()> {
+        info!("→ Running {command}");
+        if process::is_dry_run() {
+            return Ok(());
+        }
+        let transition = Transition {
+            hook: "transition",
+            state: self.machine.state().to_string(),
+            previous: None,
+            main_display: self.config.primary_display(),
+            dummy_plug: self.dummy_plug.as_deref(),
+        };
+        hooks::run(command, &transition, self.config.transition.timeout)
+            .map_err(|stderr| VitaminkError::CommandFailed { command: command.to_string(), stderr })
+    }
+
+    // Undoes `steps` in reverse, carrying on past failures. Returns the
+    // first one.
+    fn roll_back(&mut self, steps: &[Step]) -> Option<VitaminkError> {
+        let mut first = None;
+        for &step in steps.iter().rev() {
+            if let Err(e) = self.undo(step) {
+                error!("Rollback: couldn't undo \"{step}\": {e}");
+                first.get_or_insert(e);
+            }
+        }
+        first
+    }
+
+    fn engage(&mut self, step: Step) -> Result<()> {
+        let dry_run = process::is_dry_run();
+
+        match step {
+            Step::Privacy => {
+                if self.config.privacy.is_enabled() && self.privacy.is_none() {
+                    if self.config.privacy.lock_session {
+                        info!("→ Locking the session");
+                    }
+                    if self.config.privacy.mute_microphone || !self.config.privacy.webcam_usb_devices.is_empty() {
+                        info!("→ Applying mic/webcam privacy");
+                    }
+                    if !dry_run {
+                        self.privacy = Some(privacy::engage(&self.config.privacy)?);
+                    }
+                }
+            }
+            Step::Layout => {
+                if self.saved_layout.is_none() {
+                    info!("→ Saving display layout");
+                    self.saved_layout = Some(Layout::capture()?);
+                    // Not worth failing Away for: the windows just stay
+                    // where KWin puts them.
+                    if self.config.windows.restore && self.windows.is_none() && !dry_run {
+                        match windows::record() {
+                            Ok(placements) => {
+                                info!("→ Recorded where {} windows are", placements.len());
+                                self.windows = Some(placements);
+                            }
+                            Err(e) => warn!("Couldn't record window placements: {e}"),
+                        }
+                    }
+                    // On disk before the dummy plug changes anything.
+                    self.persist();
+                }
+            }
+            Step::Outputs => self.enable_away_outputs()?,
+            Step::Desktop => {
+                if self.config.desktop.is_enabled() && self.desktop.is_none() {
+                    info!("→ Switching wallpaper/activity");
+                    if !dry_run {
+                        self.desktop = Some(desktop::engage(&self.config.desktop)?);
+                    }
+                }
+            }
+            Step::Compositor => {
+                if self.config.compositor.is_enabled() && self.compositor.is_none() {
+                    info!("→ Reducing compositor effects");
+                    if !dry_run {
+                        self.compositor = Some(compositor::engage(&self.config.compositor)?);
+                    }
+                }
+            }
+            Step::Power => {
+                if self.config.power.profile.is_some() && self.power.is_none() {
+                    info!("→ Switching the power profile");
+                    self.power = power::engage(&self.config.power)?;
+                }
+            }
+            Step::Services => {
+                // Still up from the last Away, stream and all.
+                if self.draining.take().is_some() {
+                    info!("→ Services still running, no restart needed");
+                    return Ok(());
+                }
+                if self.sunshine_template().is_some() {
+                    info!("→ Rendering sunshine.conf");
+                    if !dry_run {
+                        self.render_sunshine_conf()?;
+                    }
+                }
+                if self.config.sunshine.encoder_check != EncoderCheck::Off && services::sunshine(&self.config.services).is_some() {
+                    self.check_encoder()?;
+                }
+                services::start_all(&self.config.services, &self.config.slice)?;
+                if !dry_run {
+                    self.wait_for_sunshine()?;
+                }
+            }
+            Step::Gamescope => {
+                if self.config.gamescope.enabled && self.gamescope.is_none() {
+                    // The session runs at the dummy plug's (or client's) mode.
+                    let mode = self.away_outputs()?.first().and_then(|o| o.mode);
+                    self.gamescope = gamescope::launch(&self.config.gamescope, mode.as_ref())?;
+                }
+            }
+            Step::App => {
+                if let Some(name) = &self.config.apps.launch
+                    && self.app.is_none()
+                {
+                    info!("→ Launching {name} from Sunshine");
+                    if !dry_run {
+                        self.app = sunshine::apps::launch(&self.config.apps, &self.config.sunshine)?;
+                    }
+                }
+            }
+            Step::Audio => {
+                if self.config.audio.is_enabled() && self.audio.is_none() {
+                    info!("→ Switching audio to the Away sink");
+                    self.audio = audio::engage(&self.config.audio)?;
+                }
+            }
+            Step::Input => {
+                if self.config.input_gating.enabled && self.input_gate.is_none() {
+                    info!("→ Grabbing local input");
+                    if !dry_run {
+                        self.input_gate = Some(InputGate::engage(&self.config.input_gating)?);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn stop_services(&mut self) -> Result<()> {
+        // Quitting the app first ends the stream cleanly on the client and
+        // runs the app's own undo commands.
+        #[cfg(feature = "sunshine-api")]
+        if let Some(api) = sunshine::api::Client::from_config(&self.config.sunshine)
+            && compat::supports(Feature::CloseApp)
+            && sunshine::is_running(&self.config.services)
+        {
+            info!("→ Closing the streamed app");
+            if let Err(e) = api.close_app() {
+                warn!("Couldn't close the app through Sunshine's API: {e}");
+            }
+        }
+        services::stop_all(&self.config.services, &self.config.slice)?;
+        if let Some(restore) = &self.sunshine_conf {
+            info!("→ Restoring sunshine.conf");
+            sunshine::conf::restore(restore)?;
+            self.sunshine_conf = None;
+        }
+        Ok(())
+    }
+
+    // Back at the desk with a client still streaming, e.g. someone else
+    // on the couch: with `drain_timeout` set, the services are left
+    // running for `drain` to stop once the stream ends. False if they
+    // should stop now.
+    fn start_draining(&mut self) -> bool {
+        let timeout = self.config.sunshine.drain_timeout;
+        if timeout.is_zero() || !sunshine::has_active_session(&self.config.sunshine.session_ports) {
+            return false;
+        }
+        info!("→ A client is still streaming, leaving services running for up to {}", duration::format(timeout));
+        self.draining = Some(Instant::now() + timeout);
+        true
+    }
+
+    // Stops the services once the stream draining at the desk has ended,
+    // or drain_timeout is up.
+    fn drain(&mut self) {
+        let Some(deadline) = self.draining else {
+            return;
+        };
+        let streaming = sunshine::has_active_session(&self.config.sunshine.session_ports);
+        if streaming && Instant::now() < deadline {
+            return;
+        }
+        self.finish_draining(if streaming { "drain_timeout is up" } else { "the stream ended" });
+    }
+
+    fn finish_draining(&mut self, why: &str) {
+        if self.draining.take().is_none() {
+            return;
+        }
+        info!("Stopping services, {why}");
+        match self.stop_services() {
+            Ok(()) => self.notify(notify::Event::Transition, "Sunshine stopped", &format!("Stopped after the stream, {why}")),
+            Err(e) => {
+                error!("Couldn't stop services after the stream: {e}");
+                self.notify(notify::Event::Error, "Couldn't stop Sunshine", &e.to_string());
+                self.last_error = Some(e.to_string());
+            }
+        }
+    }
+
+    fn undo(&mut self, step: Step) -> Result<()> {
+        match step {
+            // Give the keyboard back first — it's the one step that must
+            // never be held up by a slow command below.
+            Step::Input => {
+                if self.input_gate.take().is_some() {
+                    info!("→ Released local input");
+                }
+            }
+            Step::Audio => {
+                if let Some(restore) = &self.audio {
+                    info!("→ Switching audio back");
+                    audio::restore(&self.config.audio, restore)?;
+                    self.audio = None;
+                }
+            }
+            Step::Power => {
+                if let Some(restore) = &self.power {
+                    info!("→ Switching the power profile back");
+                    power::restore(restore)?;
+                    self.power = None;
+                }
+            }
+            Step::Services => {
+                if self.machine.state() == State::AtDesk && self.start_draining() {
+                    return Ok(());
+                }
+                self.stop_services()?;
+            }
+            // Also when the config no longer enables it: a session a
+            // previous run started is still ours to end.
+            Step::Gamescope => {
+                if self.config.gamescope.enabled || self.gamescope.is_some() {
+                    match self.gamescope {
+                        Some(pid) => info!("→ Stopping gamescope (pid {pid})"),
+                        None => info!("→ Stopping gamescope"),
+                    }
+                    gamescope::stop()?;
+                    self.gamescope = None;
+                }
+            }
+            Step::App => {
+                if let Some(restore) = &self.app {
+                    info!("→ Closing {}", restore.app);
+                    sunshine::apps::close(restore)?;
+                    self.app = None;
+                }
+            }
+            Step::Compositor => {
+                if let Some(restore) = self.compositor.take() {
+                    info!("→ Restoring compositor effects");
+                    compositor::restore(&restore)?;
+                }
+            }
+            Step::Desktop => {
+                if let Some(restore) = self.desktop.take() {
+                    info!("→ Restoring wallpaper/activity");
+                    desktop::restore(&restore)?;
+                }
+            }
+            Step::Outputs => {
+                self.disable_away_outputs()?;
+                self.client_mode = None;
+            }
+            Step::Layout => {
+                // Only forget the snapshot once it has been applied, so a
+                // failed restore is retried on the next attempt.
+                if let Some(layout) = &mut self.saved_layout {
+                    if let Some(preset) = self.config.desk_layout.as_deref().and_then(desk_preset) {
+                        *layout = preset;
+                    }
+                    // A forced AtDesk with the lid shut mustn't light up the
+                    // panel behind it.
+                    for main in &self.config.main_display {
+                        if display::is_internal_panel(main) && presence::lid_closed().unwrap_or(false) {
+                            info!("→ Lid closed, leaving {main} off");
+                            layout.disable(main);
+                        }
+                    }
+                    for name in &self.blanked {
+                        layout.leave(name);
+                    }
+                    info!("→ Restoring display layout");
+                    layout.restore(self.config.primary_display())?;
+                    // The layout sets priorities too, but KWin doesn't
+                    // always take them from the same change that turns
+                    // outputs off.
+                    if let Some(name) = layout.primary().map(str::to_string) {
+                        make_primary(&name);
+                    }
+                    self.saved_layout = None;
+                }
+                self.blanked.clear();
+                if let Some(placements) = self.windows.take()
+                    && !process::is_dry_run()
+                {
+                    thread::sleep(self.config.windows.settle);
+                    match windows::restore(&placements) {
+                        Ok(moved) => info!("→ Put {moved} of {} windows back", placements.len()),
+                        Err(e) => warn!("Couldn't put the windows back: {e}"),
+                    }
+                }
+            }
+            Step::Privacy => {
+                if let Some(restore) = self.privacy.take() {
+                    info!("→ Restoring mic/webcam");
+                    privacy::restore(&restore)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn fingerprint(detected: Presence, presence: Presence, connected: &BTreeSet<String>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    detected.hash(&mut hasher);
+    presence.hash(&mut hasher);
+    connected.hash(&mut hasher);
+    hasher.finish()
+}
+
+// Doubles every 6 quiet polls (30s at the default 5s), up to `max`.
+pub fn adaptive_interval(base: Duration, max: Duration, quiet_polls: u32) -> Duration {
+    let factor = 1u32 << (quiet_polls / 6).min(4);
+    (base * factor).min(max.max(base))
+}
+
+// The `desk_layout` preset called `name`, or None to use the snapshot
+// after all.
+fn desk_preset(name: &str) -> Option<Layout> {
+    match layout::load_preset(name) {
+        Ok(preset) => Some(preset),
+        Err(e) => {
+            warn!("desk_layout \"{name}\": {e} — restoring the layout from before going Away");
+            None
+        }
+    }
+}
+
+// Which output should be primary while Away: `configured`, or else the
+// desk's primary if Away leaves it on, or else the first output turned on.
+fn away_primary(configured: Option<&str>, desk: Option<&str>, enable: &[ProfileOutput], disable: &[String]) -> Option<String> {
+    let desk = desk.filter(|name| !disable.iter().any(|d| d == name));
+    configured.or(desk).or(enable.first().map(|o| o.name.as_str())).map(str::to_string)
+}
+
+// Makes `name` primary unless it already is. Failing only warns: the
+// outputs are right, the panels may just be on the wrong one.
+fn make_primary(name: &str) {
+    let result = display::primary_output().and_then(|current| {
+        if current.as_deref() == Some(name) {
+            debug!("{name} is already the primary output");
+            return Ok(());
+        }
+        info!("→ Making {name} the primary output");
+        display::set_primary(name)
+    });
+    match result {
+        Ok(()) => {}
+        Err(VitaminkError::Unavailable(e)) => debug!("{e}"),
+        Err(e) => warn!("Can't make {name} the primary output: {e}"),
+    }
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_away_primary() {
+        let dummy = [ProfileOutput { name: "HDMI-A-1".to_string(), mode: None, scale: None }];
+        let off = ["DP-2".to_string()];
+        // The desk's primary stays primary while it's on.
+        assert_eq!(away_primary(None, Some("DP-2"), &dummy, &[]).as_deref(), Some("DP-2"));
+        // Once it's off, the dummy plug takes over.
+        assert_eq!(away_primary(None, Some("DP-2"), &dummy, &off).as_deref(), Some("HDMI-A-1"));
+        assert_eq!(away_primary(Some("DP-3"), Some("DP-2"), &dummy, &[]).as_deref(), Some("DP-3"));
+        assert_eq!(away_primary(None, None, &[], &[]), None);
+    }
 
     #[test]
     fn test_state_display() {
         assert_eq!(format!("{}", State::AtDesk), "AtDesk");
         assert_eq!(format!("{}", State::Away), "Away");
+        assert_eq!(format!("{}", State::HoldingPattern), "HoldingPattern");
+        assert_eq!(format!("{}", State::Standby), "Standby");
     }
 
     #[test]
-    fn test_default_config() {
-        let config = Config::default();
-        assert_eq!(config.main_display, "DP-2");
-        assert_eq!(config.dummy_plug, "HDMI-A-1");
-        assert_eq!(config.poll_interval, Duration::from_secs(5));
-        assert_eq!(config.grace_period, Duration::from_secs(10));
+    fn test_adaptive_interval() {
+        let secs = Duration::from_secs;
+        assert_eq!(adaptive_interval(secs(5), secs(20), 0), secs(5));
+        assert_eq!(adaptive_interval(secs(5), secs(20), 5), secs(5));
+        assert_eq!(adaptive_interval(secs(5), secs(20), 6), secs(10));
+        assert_eq!(adaptive_interval(secs(5), secs(20), 12), secs(20));
+        assert_eq!(adaptive_interval(secs(5), secs(20), 1000), secs(20));
+        // A max below the base interval turns adaptation off.
+        assert_eq!(adaptive_interval(secs(5), secs(1), 1000), secs(5));
     }
 }
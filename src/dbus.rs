@@ -0,0 +1,253 @@
+// src/dbus.rs — Session bus control interface for the running daemon
+//
+// New Rust concepts in this file:
+//
+// - Procedural macros: `#[zbus::interface]` reads the `impl` block at compile
+//   time and generates the D-Bus plumbing (introspection XML, method dispatch).
+//   `force_away` becomes the D-Bus method `ForceAway`, and so on.
+//
+// - `std::sync::mpsc`: a multi-producer, single-consumer channel. zbus runs
+//   method handlers on its own thread, so instead of touching the daemon
+//   directly they send a `Command` down the channel and the main loop picks
+//   it up. `Sender` can be cloned and shared between threads; `Receiver` can't.
+//
+// The bottom half of the file has small helpers for calling *other*
+// services (plasmashell, KWin, ...) as a client.
+//
+// Try it with:
+//   busctl --user call org.vitamink.Daemon /org/vitamink/Daemon org.vitamink.Daemon GetState
+
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+use serde::Serialize;
+use zbus::zvariant::{DynamicDeserialize, DynamicType};
+
+use zbus::blocking::Connection;
+use zbus::object_server::SignalEmitter;
+
+use crate::daemon::{Command, State};
+use crate::diagnostics;
+use crate::error::{Result, VitaminkError};
+use crate::status::{Pending, Progress};
+
+const BUS_NAME: &str = "org.vitamink.Daemon";
+const OBJECT_PATH: &str = "/org/vitamink/Daemon";
+
+// The object zbus serves at OBJECT_PATH. It keeps its own copy of the state
+// so GetState can answer without waiting on the main loop.
+struct DaemonInterface {
+    state: State,
+    profile: Option<String>,
+    // Configured profile names, so SetProfile can reject typos right away.
+    profiles: Vec<String>,
+    commands: Sender<Command>,
+}
+
+impl DaemonInterface {
+    fn send(&self, command: Command) -> zbus::fdo::Result<()> {
+        self.commands
+            .send(command)
+            .map_err(|e| zbus::fdo::Error::Failed(format!("Daemon is not accepting commands: {e}")))
+    }
+}
+
+#[zbus::interface(name = "org.vitamink.Daemon")]
+impl DaemonInterface {
+    fn force_away(&self) -> zbus::fdo::Result<()> {
+        self.send(Command::ForceAway)
+    }
+
+    fn force_desk(&self) -> zbus::fdo::Result<()> {
+        self.send(Command::ForceDesk)
+    }
+
+    // Calls off an automatic switch that's still in its grace period.
+    fn cancel_pending(&self) -> zbus::fdo::Result<()> {
+        self.send(Command::CancelPending)
+    }
+
+    // Pauses automatic switching under `name` until Release or, if
+    // `seconds` isn't 0, until that long has passed. Holding a name again
+    // renews it.
+    fn inhibit(&self, name: String, reason: String, seconds: u64) -> zbus::fdo::Result<()> {
+        if name.is_empty() {
+            return Err(zbus::fdo::Error::InvalidArgs("Inhibitor name is empty".to_string()));
+        }
+        let duration = (seconds > 0).then(|| Duration::from_secs(seconds));
+        self.send(Command::Inhibit { name, reason, duration })
+    }
+
+    fn release(&self, name: String) -> zbus::fdo::Result<()> {
+        self.send(Command::Release(name))
+    }
+
+    // Re-reads the config file, like SIGHUP.
+    fn reload(&self) -> zbus::fdo::Result<()> {
+        self.send(Command::Reload)
+    }
+
+    fn get_state(&self) -> String {
+        self.state.to_string()
+    }
+
+    // "" goes back to the plain dummy plug.
+    fn set_profile(&self, name: String) -> zbus::fdo::Result<()> {
+        if name.is_empty() {
+            return self.send(Command::SetProfile(None));
+        }
+        if !self.profiles.contains(&name) {
+            return Err(zbus::fdo::Error::InvalidArgs(format!(
+                "No profile \"{name}\" (configured: {})",
+                self.profiles.join(", ")
+            )));
+        }
+        self.send(Command::SetProfile(Some(name)))
+    }
+
+    // "WIDTHxHEIGHT[@HZ]" the streaming client asked for; "" once it's gone.
+    fn set_client_mode(&self, mode: String) -> zbus::fdo::Result<()> {
+        if mode.is_empty() {
+            return self.send(Command::SetClientMode(None));
+        }
+        let mode = mode.parse().map_err(|e: VitaminkError| zbus::fdo::Error::InvalidArgs(e.to_string()))?;
+        self.send(Command::SetClientMode(Some(mode)))
+    }
+
+    // "" when no profile is active.
+    fn get_profile(&self) -> String {
+        self.profile.clone().unwrap_or_default()
+    }
+
+    fn list_profiles(&self) -> Vec<String> {
+        self.profiles.clone()
+    }
+
+    // Writes a diagnostics bundle and returns its path. Runs right here on
+    // the D-Bus thread, so it answers even if the main loop is stuck.
+    // `include_kscreen` adds kscreen-console's report and KScreen's configs.
+    fn diagnostics(&self, include_kscreen: bool) -> zbus::fdo::Result<String> {
+        diagnostics::collect(include_kscreen)
+            .map(|path| path.display().to_string())
+            .map_err(|e| zbus::fdo::Error::Failed(e.to_string()))
+    }
+
+    // Declared here so it shows up in introspection; emitted from
+    // `Service::set_state` via the connection.
+    #[zbus(signal)]
+    async fn state_changed(emitter: &SignalEmitter<'_>, state: &str) -> zbus::Result<()>;
+
+    // Before each step of a transition to `state`: which one, counting
+    // from 1 up to `total`. From `Service::set_progress`.
+    #[zbus(signal)]
+    async fn transition_progress(emitter: &SignalEmitter<'_>, state: &str, step: &str, number: u32, total: u32) -> zbus::Result<()>;
+
+    // While a switch to `state` waits out the grace period: the seconds
+    // left, and whether a stream is holding it. `state` is "" once nothing
+    // is pending any more. From `Service::set_countdown`.
+    #[zbus(signal)]
+    async fn countdown(emitter: &SignalEmitter<'_>, state: &str, seconds: u32, held_by_stream: bool) -> zbus::Result<()>;
+}
+
+// Handle to the running service. Dropping it releases the bus name.
+pub struct Service {
+    conn: Connection,
+}
+
+impl Service {
+    pub fn start(state: State, profile: Option<String>, profiles: Vec<String>, commands: Sender<Command>) -> Result<Self> {
+        let iface = DaemonInterface { state, profile, profiles, commands };
+
+        let conn = zbus::blocking::connection::Builder::session()
+            .and_then(|b| b.name(BUS_NAME))
+            .and_then(|b| b.serve_at(OBJECT_PATH, iface))
+            .and_then(|b| b.build())
+            .map_err(|e| VitaminkError::dbus(format!("Failed to register {BUS_NAME} on the session bus"), e))?;
+
+        Ok(Self { conn })
+    }
+
+    // Updates the state GetState reports and broadcasts StateChanged.
+    pub fn set_state(&self, state: State) -> Result<()> {
+        self.interface()?.get_mut().state = state;
+
+        self.conn
+            .emit_signal(None::<()>, OBJECT_PATH, BUS_NAME, "StateChanged", &(state.to_string(),))
+            .map_err(|e| VitaminkError::dbus("Failed to emit StateChanged", e))
+    }
+
+    // Broadcasts TransitionProgress.
+    pub fn set_progress(&self, progress: &Progress) -> Result<()> {
+        let body = (progress.to.to_string(), progress.step.as_str(), progress.number as u32, progress.total as u32);
+        self.conn
+            .emit_signal(None::<()>, OBJECT_PATH, BUS_NAME, "TransitionProgress", &body)
+            .map_err(|e| VitaminkError::dbus("Failed to emit TransitionProgress", e))
+    }
+
+    // Broadcasts Countdown; None when the switch happened or was called off.
+    pub fn set_countdown(&self, pending: Option<&Pending>) -> Result<()> {
+        let body = match pending {
+            Some(p) => (p.to.to_string(), p.remaining.min(u32::MAX as u64) as u32, p.held_by_stream),
+            None => (String::new(), 0, false),
+        };
+        self.conn
+            .emit_signal(None::<()>, OBJECT_PATH, BUS_NAME, "Countdown", &body)
+            .map_err(|e| VitaminkError::dbus("Failed to emit Countdown", e))
+    }
+
+    // Updates what GetProfile reports.
+    pub fn set_profile(&self, profile: Option<&str>) -> Result<()> {
+        self.interface()?.get_mut().profile = profile.map(String::from);
+        Ok(())
+    }
+
+    // Updates the profile names SetProfile accepts, after a reload.
+    pub fn set_profiles(&self, profiles: Vec<String>) -> Result<()> {
+        self.interface()?.get_mut().profiles = profiles;
+        Ok(())
+    }
+
+    fn interface(&self) -> Result<zbus::blocking::object_server::InterfaceRef<DaemonInterface>> {
+        self.conn
+            .object_server()
+            .interface::<_, DaemonInterface>(OBJECT_PATH)
+            .map_err(|e| VitaminkError::dbus("D-Bus interface lookup failed", e))
+    }
+}
+
+// ---- Client Helpers ----
+
+// Calls one of our own methods on the running daemon.
+pub fn call_daemon<T>(method: &str, body: &(impl Serialize + DynamicType)) -> Result<T>
+where
+    T: for<'d> DynamicDeserialize<'d>,
+{
+    call(&session()?, BUS_NAME, OBJECT_PATH, BUS_NAME, method, body)
+}
+
+pub fn session() -> Result<Connection> {
+    Connection::session().map_err(|e| VitaminkError::dbus("Session bus unavailable", e))
+}
+
+// Calls `interface.method` on `destination` and deserializes the reply body.
+// Use `()` for `T` when the method returns nothing.
+pub fn call<T>(
+    conn: &Connection,
+    destination: &str,
+    path: &str,
+    interface: &str,
+    method: &str,
+    body: &(impl Serialize + DynamicType),
+) -> Result<T>
+where
+    T: for<'d> DynamicDeserialize<'d>,
+{
+    let reply = conn
+        .call_method(Some(destination), path, Some(interface), method, body)
+        .map_err(|e| VitaminkError::dbus(format!("{interface}.{method} failed"), e))?;
+
+    reply
+        .body()
+        .deserialize::<T>()
+        .map_err(|e| VitaminkError::dbus(format!("Unexpected {interface}.{method} reply"), e))
+}
@@ -0,0 +1,98 @@
+// src/ddc.rs — Monitor settings put back over DDC/CI at the desk
+//
+// Some monitors come out of DPMS off at minimum brightness, or on another
+// input. With
+//
+//   [ddc]
+//   brightness = 70
+//   input_source = 15
+//
+// the AtDesk transition ends by setting them on the main display through
+// `ddcutil`: VCP feature 0x10 is brightness, 0x12 contrast and 0x60 the
+// input source (the values a monitor accepts are in `ddcutil
+// capabilities`). The display is addressed by its I2C bus, which the
+// kernel links from the connector's sysfs directory as `ddc`; `bus` in
+// the config overrides that for adapters that don't expose it.
+//
+// `ddcutil` needs read/write access to /dev/i2c-* (the `i2c` group on
+// most distributions, with the i2c-dev module loaded).
+
+use std::fs;
+use std::process::Command;
+
+use log::debug;
+
+use crate::config::DdcConfig;
+use crate::drm;
+use crate::error::{Result, VitaminkError};
+use crate::process;
+
+const BRIGHTNESS: &str = "10";
+const CONTRAST: &str = "12";
+const INPUT_SOURCE: &str = "60";
+
+// Sets everything configured on `output`. Stops at the first failure.
+pub fn restore(config: &DdcConfig, output: &str) -> Result<()> {
+    let bus = match config.bus {
+        Some(bus) => bus,
+        None => bus_of(output).ok_or_else(|| {
+            VitaminkError::Parse(format!("Can't tell which I2C bus {output} is on; set `bus` under [ddc] (see `ddcutil detect`)"))
+        })?,
+    };
+    let features = [(BRIGHTNESS, config.brightness), (CONTRAST, config.contrast), (INPUT_SOURCE, config.input_source)];
+    for (code, value) in features {
+        if let Some(value) = value {
+            set_vcp(bus, code, value)?;
+        }
+    }
+    Ok(())
+}
+
+fn set_vcp(bus: u32, code: &str, value: u8) -> Result<()> {
+    let bus = bus.to_string();
+    let value = value.to_string();
+    let args = ["--bus", &bus, "setvcp", code, &value];
+    debug!("ddcutil {}", args.join(" "));
+    let output = process::run(Command::new("ddcutil").args(args))?;
+    if !output.status.success() {
+        return Err(VitaminkError::command_failed(format!("ddcutil {}", args.join(" ")), &output));
+    }
+    Ok(())
+}
+
+// The bus number from the connector's `ddc` link, e.g.
+// /sys/class/drm/card1-DP-2/ddc → .../i2c-5.
+fn bus_of(output: &str) -> Option<u32> {
+    let target = fs::read_link(drm::connector_dir(output)?.join("ddc")).ok()?;
+    parse_bus(target.file_name()?.to_str()?)
+}
+
+fn parse_bus(name: &str) -> Option<u32> {
+    name.strip_prefix("i2c-")?.parse().ok()
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::mock::FakeRunner;
+
+    #[test]
+    fn test_parse_bus() {
+        assert_eq!(parse_bus("i2c-5"), Some(5));
+        assert_eq!(parse_bus("i2c-"), None);
+        assert_eq!(parse_bus("card1-DP-2"), None);
+    }
+
+    #[test]
+    fn test_restore() {
+        let fake = Rc::new(FakeRunner::new());
+        fake.respond("ddcutil", 0, "");
+        let config = DdcConfig { brightness: Some(70), input_source: Some(15), bus: Some(5), ..DdcConfig::default() };
+        process::with_runner(fake.clone(), || restore(&config, "DP-2")).unwrap();
+        assert_eq!(fake.calls(), vec!["ddcutil --bus 5 setvcp 10 70", "ddcutil --bus 5 setvcp 60 15"]);
+    }
+}
@@ -0,0 +1,224 @@
+// src/desktop.rs — Per-state wallpaper and KDE Activity switching
+//
+// The streamed desktop is what Moonlight clients see, so a busy photo
+// wallpaper costs encoder bitrate and shows personal stuff on the TV.
+// Going Away can switch to a plain wallpaper and/or a dedicated Activity;
+// returning puts back whatever was there before.
+//
+// Plasma exposes a JavaScript API through `org.kde.PlasmaShell.evaluateScript`,
+// which is what `plasma-apply-wallpaperimage` uses under the hood. Anything
+// the script `print()`s comes back as the reply string.
+
+use serde::{Deserialize, Serialize};
+use zbus::blocking::Connection;
+
+use crate::config::DesktopConfig;
+use crate::dbus;
+use crate::error::{Result, VitaminkError};
+
+// What Away changed, so AtDesk can restore it.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Restore {
+    wallpapers: Vec<Wallpaper>,
+    activity: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+struct Wallpaper {
+    desktop_id: u32,
+    plugin: String,
+    image: String,
+}
+
+pub fn engage(config: &DesktopConfig) -> Result<Restore> {
+    let conn = dbus::session()?;
+    let mut restore = Restore::default();
+
+    if let Some(name) = &config.away_activity {
+        let id = find_activity(&conn, name)?;
+        restore.activity = Some(current_activity(&conn)?);
+        set_activity(&conn, &id)?;
+    }
+
+    // Switch activity first: each activity has its own desktop containments,
+    // and the wallpaper should land on the ones that are about to be shown.
+    if config.away_wallpaper.is_some() || config.away_wallpaper_color.is_some() {
+        restore.wallpapers = parse_wallpapers(&evaluate_script(&conn, SNAPSHOT_SCRIPT)?)?;
+
+        let script = match (&config.away_wallpaper, &config.away_wallpaper_color) {
+            (Some(image), _) => set_image_script(image),
+            (None, Some(color)) => set_color_script(color),
+            (None, None) => unreachable!(),
+        };
+        evaluate_script(&conn, &script)?;
+    }
+
+    Ok(restore)
+}
+
+pub fn restore(restore: &Restore) -> Result<()> {
+    let conn = dbus::session()?;
+
+    if !restore.wallpapers.is_empty() {
+        evaluate_script(&conn, &restore_script(&restore.wallpapers))?;
+    }
+
+    if let Some(id) = &restore.activity {
+        set_activity(&conn, id)?;
+    }
+
+    Ok(())
+}
+
+// ---- Wallpaper ----
+
+const SNAPSHOT_SCRIPT: &str = "\
+desktops().forEach(function (d) {
+    d.currentConfigGroup = ['Wallpaper', 'org.kde.image', 'General'];
+    print(d.id + '\\t' + d.wallpaperPlugin + '\\t' + d.readConfig('Image', '') + '\\n');
+});";
+
+fn evaluate_script(conn: &Connection, script: &str) -> Result<String> {
+    dbus::call(conn, "org.kde.plasmashell", "/PlasmaShell", "org.kde.PlasmaShell", "evaluateScript", &(script,))
+}
+
+// One "id<TAB>plugin<TAB>image" line per desktop containment.
+fn parse_wallpapers(output: &str) -> Result<Vec<Wallpaper>> {
+    let mut wallpapers = Vec::new();
+
+    for line in output.lines().filter(|l| !l.trim().is_empty()) {
+        let mut fields = line.splitn(3, '\t');
+        let (Some(id), Some(plugin)) = (fields.next(), fields.next()) else {
+            return Err(VitaminkError::Parse(format!("Invalid wallpaper snapshot line: {line}")));
+        };
+        let desktop_id = id
+            .trim()
+            .parse()
+            .map_err(|_| VitaminkError::Parse(format!("Invalid desktop id: {id}")))?;
+
+        wallpapers.push(Wallpaper {
+            desktop_id,
+            plugin: plugin.to_string(),
+            image: fields.next().unwrap_or("").to_string(),
+        });
+    }
+
+    Ok(wallpapers)
+}
+
+fn set_image_script(path: &str) -> String {
+    let url = if path.contains("://") { path.to_string() } else { format!("file://{path}") };
+    format!(
+        "desktops().forEach(function (d) {{
+    d.wallpaperPlugin = 'org.kde.image';
+    d.currentConfigGroup = ['Wallpaper', 'org.kde.image', 'General'];
+    d.writeConfig('Image', {});
+}});",
+        js_string(&url)
+    )
+}
+
+fn set_color_script(color: &str) -> String {
+    format!(
+        "desktops().forEach(function (d) {{
+    d.wallpaperPlugin = 'org.kde.color';
+    d.currentConfigGroup = ['Wallpaper', 'org.kde.color', 'General'];
+    d.writeConfig('Color', {});
+}});",
+        js_string(color)
+    )
+}
+
+// Other plugins (slideshow, etc.) keep their own config group, which we
+// never touch — restoring the plugin name is enough for them.
+fn restore_script(wallpapers: &[Wallpaper]) -> String {
+    let mut script = String::from("var d;\n");
+    for w in wallpapers {
+        script.push_str(&format!("d = desktopById({});\nif (d) {{\n", w.desktop_id));
+        script.push_str(&format!("    d.wallpaperPlugin = {};\n", js_string(&w.plugin)));
+        if !w.image.is_empty() {
+            script.push_str("    d.currentConfigGroup = ['Wallpaper', 'org.kde.image', 'General'];\n");
+            script.push_str(&format!("    d.writeConfig('Image', {});\n", js_string(&w.image)));
+        }
+        script.push_str("}\n");
+    }
+    script
+}
+
+fn js_string(value: &str) -> String {
+    let escaped = value.replace('\\', "\\\\").replace('\'', "\\'").replace('\n', "\\n");
+    format!("'{escaped}'")
+}
+
+// ---- Activities ----
+
+fn activities_call<T>(conn: &Connection, method: &str, body: &(impl serde::Serialize + zbus::zvariant::DynamicType)) -> Result<T>
+where
+    T: for<'d> zbus::zvariant::DynamicDeserialize<'d>,
+{
+    dbus::call(
+        conn,
+        "org.kde.ActivityManager",
+        "/ActivityManager/Activities",
+        "org.kde.ActivityManager.Activities",
+        method,
+        body,
+    )
+}
+
+fn current_activity(conn: &Connection) -> Result<String> {
+    activities_call(conn, "CurrentActivity", &())
+}
+
+fn set_activity(conn: &Connection, id: &str) -> Result<()> {
+    let switched: bool = activities_call(conn, "SetCurrentActivity", &(id,))?;
+    if !switched {
+        return Err(VitaminkError::Parse(format!("ActivityManager refused to switch to {id}")));
+    }
+    Ok(())
+}
+
+// Accepts either an activity id or its display name.
+fn find_activity(conn: &Connection, name: &str) -> Result<String> {
+    let ids: Vec<String> = activities_call(conn, "ListActivities", &())?;
+
+    for id in ids {
+        if id == name {
+            return Ok(id);
+        }
+        let activity_name: String = activities_call(conn, "ActivityName", &(id.as_str(),))?;
+        if activity_name == name {
+            return Ok(id);
+        }
+    }
+
+    Err(VitaminkError::Parse(format!("No KDE Activity named {name}")))
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_wallpapers() {
+        let output = "1\torg.kde.image\tfile:///home/me/cat.jpg\n\
+                      7\torg.kde.slideshow\t\n";
+        let wallpapers = parse_wallpapers(output).unwrap();
+        assert_eq!(
+            wallpapers,
+            vec![
+                Wallpaper { desktop_id: 1, plugin: "org.kde.image".into(), image: "file:///home/me/cat.jpg".into() },
+                Wallpaper { desktop_id: 7, plugin: "org.kde.slideshow".into(), image: String::new() },
+            ]
+        );
+        assert!(parse_wallpapers("garbage").is_err());
+    }
+
+    #[test]
+    fn test_js_string() {
+        assert_eq!(js_string("/home/me/it's.png"), "'/home/me/it\\'s.png'");
+        assert_eq!(js_string("C:\\x"), "'C:\\\\x'");
+    }
+}
@@ -0,0 +1,174 @@
+// src/detail.rs — `vitamink display show NAME [--json]`: one output, in full
+//
+// Everything the backend parsed, what sysfs and the EDID add, and the
+// backend's own lines for the output under `raw`. When a field comes out
+// wrong, `raw` shows what the parser was looking at.
+//
+// New Rust concepts in this file:
+//
+// - `#[serde(flatten)]`: the display's fields are written inline next to
+//   ours instead of under a nested "display" key.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::display::{Display, DpmsState, Mode};
+use crate::drm::ConnectorStatus;
+use crate::edid::Edid;
+
+#[derive(Debug, Serialize)]
+pub struct Detail<'a> {
+    #[serde(flatten)]
+    pub display: &'a Display,
+    pub capabilities: Capabilities,
+    pub edid: Option<Edid>,
+    pub raw: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Capabilities {
+    pub internal_panel: bool,
+    pub dpms: DpmsState,
+    pub drm_active: bool,
+    // The kernel's connector status, None without sysfs.
+    pub link: Option<ConnectorStatus>,
+    // The card driving it, e.g. "card1 (nvidia, 0000:01:00.0)".
+    pub gpu: Option<String>,
+    // "Key: value" lines the display parser doesn't use, e.g. "Vrr", "HDR".
+    pub reported: BTreeMap<String, String>,
+}
+
+// Keys parse_single_display already turns into fields.
+const PARSED_KEYS: [&str; 5] = ["Modes", "Geometry", "Scale", "Rotation", "Overscan"];
+
+pub fn reported_capabilities(raw: &[String]) -> BTreeMap<String, String> {
+    raw.iter()
+        .filter(|line| !line.starts_with("Output:"))
+        .filter_map(|line| line.trim().split_once(':'))
+        .filter(|(key, _)| !PARSED_KEYS.contains(key))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+pub fn to_json(detail: &Detail) -> String {
+    // Only plain data in here; serialization can't fail.
+    serde_json::to_string_pretty(detail).expect("Detail serializes")
+}
+
+pub fn to_text(detail: &Detail) -> String {
+    let d = detail.display;
+    let caps = &detail.capabilities;
+    let mut out = format!("{} (output {})\n", d.name, d.index);
+
+    let mut field = |label: &str, value: String| out.push_str(&format!("  {label:<12}{value}\n"));
+    field("State:", format!("{:?}, {:?}", d.state, d.connection).to_lowercase());
+    if let Some(edid) = &detail.edid {
+        let name = edid.name.as_deref().unwrap_or("");
+        field("Model:", format!("{} {name} ({}x{} cm)", edid.manufacturer, edid.width_cm, edid.height_cm));
+    }
+    if let Some(g) = d.geometry {
+        field("Geometry:", format!("{},{} {}x{}", g.x, g.y, g.width, g.height));
+    }
+    if let Some(scale) = d.scale {
+        field("Scale:", scale.to_string());
+    }
+    if let Some(rotation) = d.rotation {
+        field("Rotation:", rotation.setting().to_string());
+    }
+    if let Some(overscan) = d.overscan {
+        field("Overscan:", format!("{overscan}%"));
+    }
+    if let Some(priority) = d.priority {
+        field("Priority:", priority.to_string());
+    }
+    field("DPMS:", format!("{:?}", caps.dpms));
+    field("DRM active:", if caps.drm_active { "yes" } else { "no" }.to_string());
+    if let Some(link) = caps.link {
+        field("Link:", format!("{link:?}").to_lowercase());
+    }
+    if let Some(gpu) = &caps.gpu {
+        field("GPU:", gpu.clone());
+    }
+    for (key, value) in &caps.reported {
+        field(&format!("{key}:"), value.clone());
+    }
+
+    out.push_str("  Modes:\n");
+    for mode in &d.modes {
+        out.push_str(&format!("    {}\n", describe_mode(mode)));
+    }
+    out
+}
+
+// "3: 3840x2160@60.00 (current, preferred)"
+fn describe_mode(mode: &Mode) -> String {
+    let flags: Vec<&str> = [(mode.current, "current"), (mode.preferred, "preferred")]
+        .into_iter()
+        .filter_map(|(set, flag)| set.then_some(flag))
+        .collect();
+    let flags = if flags.is_empty() { String::new() } else { format!(" ({})", flags.join(", ")) };
+    format!("{}: {}x{}@{:.2}{flags}", mode.id, mode.width, mode.height, mode.refresh)
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::{Color, ConnectionState, DisplayState};
+
+    #[test]
+    fn test_reported_capabilities() {
+        let raw: Vec<String> = ["Output: 2 DP-2 uuid", "\tenabled", "\tModes:  1:1920x1080@60.00*", "\tVrr: incapable", "\tHDR: capable"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let caps = reported_capabilities(&raw);
+        assert_eq!(caps.len(), 2);
+        assert_eq!(caps["Vrr"], "incapable");
+        assert_eq!(caps["HDR"], "capable");
+    }
+
+    #[test]
+    fn test_to_json() {
+        let display = Display {
+            index: 2,
+            name: "DP-2".into(),
+            uuid: None,
+            state: DisplayState::Enabled,
+            connection: ConnectionState::Connected,
+            modes: vec![Mode { id: 1, width: 1920, height: 1080, refresh: 60.0, preferred: true, current: true }],
+            geometry: None,
+            scale: Some(1.0),
+            priority: Some(1),
+            color: Color::default(),
+            vrr: None,
+            rotation: None,
+            overscan: None,
+        };
+        let detail = Detail {
+            display: &display,
+            capabilities: Capabilities {
+                internal_panel: false,
+                dpms: DpmsState::On,
+                drm_active: true,
+                link: Some(ConnectorStatus::Connected),
+                gpu: Some("card1 (amdgpu, 0000:03:00.0)".into()),
+                reported: BTreeMap::new(),
+            },
+            edid: None,
+            raw: vec!["Output: 2 DP-2 uuid".into()],
+        };
+
+        let value: serde_json::Value = serde_json::from_str(&to_json(&detail)).unwrap();
+        assert_eq!(value["name"], "DP-2");
+        assert_eq!(value["state"], "enabled");
+        assert_eq!(value["modes"][0]["preferred"], true);
+        assert_eq!(value["capabilities"]["dpms"], "on");
+        assert_eq!(value["capabilities"]["link"], "connected");
+        assert_eq!(value["raw"][0], "Output: 2 DP-2 uuid");
+        assert!(to_text(&detail).contains("1: 1920x1080@60.00 (current, preferred)"));
+        assert!(to_text(&detail).contains("GPU:        card1 (amdgpu, 0000:03:00.0)"));
+    }
+}
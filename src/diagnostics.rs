@@ -0,0 +1,144 @@
+// src/diagnostics.rs — A debug bundle the daemon can put together on request
+//
+// `vitamink ctl diagnostics` asks the running daemon (over D-Bus) for a
+// tarball of everything we'd otherwise ask a bug reporter for: recent log
+// lines, the last raw kscreen-doctor dumps, status.json, the config with
+// secrets blanked out, and version info. It's built on the D-Bus thread
+// from data that doesn't belong to the main loop, so it works even when
+// the main loop is stuck — which is often exactly when it's wanted.
+//
+// With `--kscreen`, the bundle also gets KScreen's side of the story for
+// issues deeper in KDE: `kscreen-console bug` and the per-setup configs
+// KScreen keeps in ~/.local/share/kscreen.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config;
+use crate::display;
+use crate::error::{Result, VitaminkError};
+use crate::logging;
+use crate::persist;
+use crate::process;
+use crate::status;
+
+// Config keys whose values never leave the machine.
+const SECRET_KEYS: &[&str] = &["password", "token", "secret"];
+
+// Writes the bundle and returns the path of the .tar.gz.
+pub fn collect(include_kscreen: bool) -> Result<PathBuf> {
+    let base = persist::path().with_file_name("diagnostics");
+    let name = format!("vitamink-diagnostics-{}", status::unix_now());
+    let dir = base.join(&name);
+    fs::create_dir_all(&dir).map_err(|e| VitaminkError::io(&dir, e))?;
+
+    write(&dir, "version.txt", &version_info())?;
+    write(&dir, "events.log", &logging::recent().concat())?;
+    for (i, (command, output)) in display::recent_dumps().iter().enumerate() {
+        write(&dir, &format!("kscreen-{i}.txt"), &format!("$ {command}\n{output}"))?;
+    }
+    if let Ok(text) = fs::read_to_string(status::path()) {
+        write(&dir, "status.json", &text)?;
+    }
+    if let Ok(text) = fs::read_to_string(config::default_path()) {
+        write(&dir, "config.toml", &redact(&text))?;
+    }
+    let children: Vec<String> = process::running()
+        .iter()
+        .map(|c| format!("{} {:?} {}\n", c.pid, c.started.elapsed(), c.command))
+        .collect();
+    write(&dir, "children.txt", &children.concat())?;
+    if include_kscreen {
+        collect_kscreen(&dir)?;
+    }
+
+    let archive = base.join(format!("{name}.tar.gz"));
+    let output = process::output(Command::new("tar").arg("-czf").arg(&archive).arg("-C").arg(&base).arg(&name))?;
+    let _ = fs::remove_dir_all(&dir);
+    if !output.status.success() {
+        return Err(VitaminkError::command_failed("tar", &output));
+    }
+    Ok(archive)
+}
+
+// Best effort: a missing kscreen-console or data directory is noted in
+// the bundle rather than failing it.
+fn collect_kscreen(dir: &Path) -> Result<()> {
+    let mut cmd = Command::new("kscreen-console");
+    cmd.arg("bug").envs(display::wayland_env());
+    let report = match process::output(&mut cmd) {
+        Ok(output) => format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr)),
+        Err(e) => format!("{e}\n"),
+    };
+    write(dir, "kscreen-console.txt", &report)?;
+
+    let data = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .map(|base| base.join("kscreen"));
+    match data {
+        Some(source) if source.is_dir() => copy_tree(&source, &dir.join("kscreen")),
+        _ => write(dir, "kscreen-data.txt", "No ~/.local/share/kscreen directory\n"),
+    }
+}
+
+fn copy_tree(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to).map_err(|e| VitaminkError::io(to, e))?;
+    for entry in fs::read_dir(from).map_err(|e| VitaminkError::io(from, e))?.flatten() {
+        let target = to.join(entry.file_name());
+        if entry.path().is_dir() {
+            copy_tree(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target).map_err(|e| VitaminkError::io(entry.path(), e))?;
+        }
+    }
+    Ok(())
+}
+
+fn write(dir: &Path, file: &str, contents: &str) -> Result<()> {
+    let path = dir.join(file);
+    fs::write(&path, contents).map_err(|e| VitaminkError::io(&path, e))
+}
+
+fn version_info() -> String {
+    let mut info = format!("vitamink {}\n", env!("CARGO_PKG_VERSION"));
+    for var in ["XDG_CURRENT_DESKTOP", "XDG_SESSION_TYPE"] {
+        info += &format!("{var}={}\n", env::var(var).unwrap_or_default());
+    }
+    for (program, arg) in [("uname", "-a"), ("kscreen-doctor", "--version")] {
+        if let Ok(output) = process::output(Command::new(program).arg(arg)) {
+            info += &String::from_utf8_lossy(&output.stdout);
+        }
+    }
+    info
+}
+
+// Blanks the value of any `key = value` line whose key looks secret.
+pub fn redact(toml: &str) -> String {
+    toml.lines()
+        .map(|line| match line.split_once('=') {
+            Some((key, _)) if SECRET_KEYS.iter().any(|s| key.to_lowercase().contains(s)) => {
+                format!("{key}= \"<redacted>\"\n")
+            }
+            _ => format!("{line}\n"),
+        })
+        .collect()
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        let toml = "[sunshine]\napi_username = \"me\"\napi_password = \"hunter2\"\n# token = x\n";
+        assert_eq!(
+            redact(toml),
+            "[sunshine]\napi_username = \"me\"\napi_password = \"<redacted>\"\n# token = \"<redacted>\"\n"
+        );
+    }
+}
@@ -0,0 +1,282 @@
+// src/diagram.rs — `vitamink diagram`: the configured state machine as a graph
+//
+// Builds the states, the triggers between them, and the steps each state
+// runs from the *current config*, so what you see is what the daemon will
+// do — a disabled privacy section disappears from the Away box, a logind
+// backend changes the trigger labels. Output is Graphviz DOT (pipe it to
+// `dot -Tsvg`) or Mermaid (paste it into a Markdown file).
+
+use crate::config::{Config, ServiceConfig};
+use crate::display;
+use crate::duration;
+use crate::presence::{Aggregation, PresenceBackend};
+use crate::services;
+use crate::virtual_output;
+
+pub struct Machine {
+    pub states: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+pub struct Node {
+    pub name: &'static str,
+    pub actions: Vec<String>,
+}
+
+pub struct Edge {
+    pub from: &'static str,
+    pub to: &'static str,
+    pub label: String,
+}
+
+pub fn build(config: &Config) -> Machine {
+    let grace = duration::format(config.grace_period);
+    let (all, any) = match config.main_display_logic {
+        Aggregation::AllOff => ("all of ", "any of "),
+        Aggregation::AnyOff => ("any of ", "all of "),
+    };
+    let (off, on) = match config.main_display.as_slice() {
+        [main] => (main.clone(), main.clone()),
+        mains => (format!("{all}{}", mains.join(", ")), format!("{any}{}", mains.join(", "))),
+    };
+    let (absent, present) = match (&config.away_when, config.presence_backend) {
+        (Some(condition), _) => (condition.to_string(), format!("not ({condition})")),
+        (None, PresenceBackend::Dpms) => (format!("{off} DPMS off"), format!("{on} DPMS on")),
+        (None, PresenceBackend::Logind) => ("logind IdleHint set".to_string(), "logind IdleHint cleared".to_string()),
+        (None, PresenceBackend::Idle) => (
+            format!("no input for {}", duration::format(config.idle_timeout)),
+            "input activity".to_string(),
+        ),
+        (None, PresenceBackend::Camera) => (
+            format!("camera sees nobody for {}", duration::format(config.camera.absent_after)),
+            "camera sees someone".to_string(),
+        ),
+        (None, PresenceBackend::Bluetooth) => (
+            format!("{} out of Bluetooth range", config.bluetooth.devices.join(", ")),
+            "a Bluetooth device in range".to_string(),
+        ),
+    };
+    let absent = if config.main_display.iter().any(|name| display::is_internal_panel(name)) { format!("{absent} or lid closed") } else { absent };
+    let present = if config.sunshine.block_desk_while_streaming { format!("{present}, no stream active") } else { present };
+
+    let mut away = Vec::new();
+    let mut desk = Vec::new();
+    let hooks = &config.hooks;
+
+    if !hooks.pre_away.is_empty() {
+        away.push(format!("pre_away hooks ({})", hooks.pre_away.len()));
+    }
+    if config.privacy.is_enabled() {
+        away.push("mute mic / disable webcams".to_string());
+    }
+    away.push("save layout".to_string());
+    match &config.profile {
+        Some(name) => away.push(format!("apply profile {name}")),
+        None if config.dummy_plug == virtual_output::VIRTUAL => {
+            away.push(format!("create virtual output {}", config.virtual_output.output))
+        }
+        None => {
+            let scale = config.dummy_scale.map(|s| format!(", scale {s}")).unwrap_or_default();
+            away.push(match &config.dummy_mode {
+                Some(mode) => format!("enable {} at {mode}{scale}", config.dummy_plug),
+                None => format!("enable {}{scale}", config.dummy_plug),
+            })
+        }
+    }
+    away.push(format!("wait for DRM (≤ {})", duration::format(config.drm_timeout)));
+    if config.desktop.is_enabled() {
+        away.push("switch wallpaper/activity".to_string());
+    }
+    if config.compositor.is_enabled() {
+        away.push("reduce compositor effects".to_string());
+    }
+    let mut units: Vec<&ServiceConfig> = config.services.iter().collect();
+    units.sort_by_key(|s| s.order);
+    let units: Vec<&str> = units.iter().map(|s| s.unit.as_str()).collect();
+    if config.sunshine.config_template.is_some() {
+        away.push("render sunshine.conf".to_string());
+    }
+    if !units.is_empty() {
+        away.push(format!("start {}", units.join(", ")));
+    }
+    if !config.sunshine.ready_timeout.is_zero() && services::sunshine(&config.services).is_some() {
+        away.push(format!("wait for Sunshine (≤ {})", duration::format(config.sunshine.ready_timeout)));
+    }
+    if config.gamescope.enabled {
+        away.push("launch gamescope".to_string());
+    }
+    if let Some(sink) = &config.audio.away_sink {
+        away.push(format!("switch audio to {sink}"));
+    }
+    if config.input_gating.enabled {
+        away.push("grab local input".to_string());
+    }
+    if !hooks.on_away.is_empty() {
+        away.push(format!("on_away hooks ({})", hooks.on_away.len()));
+    }
+
+    if !hooks.pre_desk.is_empty() {
+        desk.push(format!("pre_desk hooks ({})", hooks.pre_desk.len()));
+    }
+    if config.input_gating.enabled {
+        desk.push("release local input".to_string());
+    }
+    if config.audio.is_enabled() {
+        desk.push(match &config.audio.desk_sink {
+            Some(sink) => format!("switch audio to {sink}"),
+            None => "switch audio back".to_string(),
+        });
+    }
+    if config.gamescope.enabled {
+        desk.push("stop gamescope".to_string());
+    }
+    if !units.is_empty() {
+        desk.push(format!("stop {}", units.iter().rev().copied().collect::<Vec<_>>().join(", ")));
+    }
+    if config.sunshine.config_template.is_some() {
+        desk.push("restore sunshine.conf".to_string());
+    }
+    if config.compositor.is_enabled() {
+        desk.push("restore compositor effects".to_string());
+    }
+    if config.desktop.is_enabled() {
+        desk.push("restore wallpaper/activity".to_string());
+    }
+    desk.push(match &config.profile {
+        Some(name) => format!("undo profile {name}"),
+        None if config.dummy_plug == virtual_output::VIRTUAL => "remove virtual output".to_string(),
+        None => format!("disable {}", config.dummy_plug),
+    });
+    desk.push("restore layout".to_string());
+    if config.privacy.is_enabled() {
+        desk.push("restore mic / webcams".to_string());
+    }
+    if config.ddc.is_enabled() {
+        desk.push("set monitor brightness/input (DDC)".to_string());
+    }
+    if !hooks.on_desk.is_empty() {
+        desk.push(format!("on_desk hooks ({})", hooks.on_desk.len()));
+    }
+
+    let mut edges = vec![
+        Edge { from: "AtDesk", to: "Away", label: format!("{absent} for {grace}") },
+        Edge { from: "Away", to: "AtDesk", label: format!("{present} for {grace}") },
+        Edge { from: "AtDesk", to: "Away", label: "ForceAway (D-Bus)".to_string() },
+        Edge { from: "Away", to: "AtDesk", label: "ForceDesk (D-Bus)".to_string() },
+    ];
+    if config.wake.enabled {
+        let label = if config.wake.moonlight_probe { "wake packet or Moonlight probe" } else { "wake packet" };
+        edges.push(Edge { from: "AtDesk", to: "Away", label: label.to_string() });
+    }
+    edges.extend([
+        Edge { from: "AtDesk", to: "HoldingPattern", label: "no outputs".to_string() },
+        Edge { from: "Away", to: "HoldingPattern", label: "no outputs".to_string() },
+        Edge { from: "HoldingPattern", to: "AtDesk", label: "outputs return (was AtDesk)".to_string() },
+        Edge { from: "HoldingPattern", to: "Away", label: "outputs return (was Away)".to_string() },
+    ]);
+
+    let mut states = vec![
+        Node { name: "AtDesk", actions: desk },
+        Node { name: "Away", actions: away },
+        Node { name: "HoldingPattern", actions: vec!["nothing (waits for outputs)".to_string()] },
+    ];
+    if config.standby.enabled {
+        let mut standby = Vec::new();
+        if config.gamescope.enabled {
+            standby.push("stop gamescope".to_string());
+        }
+        if !units.is_empty() {
+            standby.push(format!("stop {}", units.iter().rev().copied().collect::<Vec<_>>().join(", ")));
+        }
+        standby.push(match &config.profile {
+            Some(name) => format!("turn off profile {name}'s outputs"),
+            None if config.dummy_plug == virtual_output::VIRTUAL => "remove virtual output".to_string(),
+            None => format!("disable {}", config.dummy_plug),
+        });
+        standby.push("listen for wake packets and Moonlight probes".to_string());
+        states.push(Node { name: "Standby", actions: standby });
+        edges.extend([
+            Edge { from: "Away", to: "Standby", label: format!("no stream for {}", duration::format(config.standby.after)) },
+            Edge { from: "Standby", to: "Away", label: "wake packet, Moonlight probe or ForceAway".to_string() },
+            Edge { from: "Standby", to: "AtDesk", label: format!("{present} for {grace}") },
+        ]);
+    }
+
+    Machine { states, edges }
+}
+
+pub fn to_dot(machine: &Machine) -> String {
+    let mut out = String::from("digraph vitamink {\n    rankdir=LR;\n    node [shape=box, style=rounded];\n");
+
+    for state in &machine.states {
+        // `\l` left-aligns each line in Graphviz record-less labels.
+        let mut label = format!("{}\\n", state.name);
+        for action in &state.actions {
+            label.push_str(&format!("• {}\\l", escape(action)));
+        }
+        out.push_str(&format!("    {} [label=\"{label}\"];\n", state.name));
+    }
+    for edge in &machine.edges {
+        out.push_str(&format!("    {} -> {} [label=\"{}\"];\n", edge.from, edge.to, escape(&edge.label)));
+    }
+
+    out.push_str("}\n");
+    out
+}
+
+pub fn to_mermaid(machine: &Machine) -> String {
+    let mut out = String::from("stateDiagram-v2\n    [*] --> AtDesk\n");
+
+    for state in &machine.states {
+        for action in &state.actions {
+            out.push_str(&format!("    {} : {}\n", state.name, action.replace(':', "")));
+        }
+    }
+    for edge in &machine.edges {
+        out.push_str(&format!("    {} --> {} : {}\n", edge.from, edge.to, edge.label.replace(':', "")));
+    }
+
+    out
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_machine() {
+        let machine = build(&Config::default());
+
+        let dot = to_dot(&machine);
+        assert!(dot.starts_with("digraph vitamink {"));
+        assert!(dot.contains("AtDesk -> Away [label=\"DP-2 DPMS off for 10s\"];"));
+        assert!(dot.contains("• enable HDMI-A-1\\l"));
+        assert!(!dot.contains("grab local input"));
+
+        let mermaid = to_mermaid(&machine);
+        assert!(mermaid.contains("    Away --> AtDesk : DP-2 DPMS on, no stream active for 10s\n"));
+        assert!(mermaid.contains("    Away : start sunshine\n"));
+
+        let config = Config { main_display: vec!["DP-1".into(), "DP-2".into()], ..Config::default() };
+        let dot = to_dot(&build(&config));
+        assert!(dot.contains("AtDesk -> Away [label=\"all of DP-1, DP-2 DPMS off for 10s\"];"));
+        assert!(dot.contains("Away -> AtDesk [label=\"any of DP-1, DP-2 DPMS on, no stream active for 10s\"];"));
+        assert!(!dot.contains("Standby"));
+    }
+
+    #[test]
+    fn test_standby() {
+        let mut config = Config::default();
+        config.standby.enabled = true;
+        let mermaid = to_mermaid(&build(&config));
+        assert!(mermaid.contains("    Away --> Standby : no stream for 30m\n"));
+        assert!(mermaid.contains("    Standby : stop sunshine\n"));
+        assert!(mermaid.contains("    Standby : disable HDMI-A-1\n"));
+    }
+}
@@ -0,0 +1,108 @@
+// src/digest.rs — what happened while Away, for the welcome-back notice
+//
+// With "summary" in `[notifications] events`, the daemon keeps one of
+// these from the moment it goes Away and turns it into a single line when
+// it comes back:
+//
+//   Away for 5h12m, 2 streaming sessions totaling 3h40m, no errors
+//
+// Streams are sampled on every poll, so their lengths are only as precise
+// as the poll interval.
+
+use std::time::{Duration, Instant};
+
+use crate::duration;
+
+pub struct Digest {
+    started: Instant,
+    // When the stream in progress (if any) was first seen.
+    streaming_since: Option<Instant>,
+    sessions: u32,
+    streamed: Duration,
+    errors: u32,
+}
+
+impl Digest {
+    pub fn new(now: Instant) -> Self {
+        Self { started: now, streaming_since: None, sessions: 0, streamed: Duration::ZERO, errors: 0 }
+    }
+
+    // One poll's look at whether a client is connected.
+    pub fn observe_stream(&mut self, active: bool, now: Instant) {
+        match (self.streaming_since, active) {
+            (None, true) => {
+                self.streaming_since = Some(now);
+                self.sessions += 1;
+            }
+            (Some(since), false) => {
+                self.streamed += now.saturating_duration_since(since);
+                self.streaming_since = None;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn record_error(&mut self) {
+        self.errors += 1;
+    }
+
+    // The notification body. A stream still going counts up to `now`.
+    pub fn finish(mut self, now: Instant) -> String {
+        self.observe_stream(false, now);
+        summarize(now.saturating_duration_since(self.started), self.sessions, self.streamed, self.errors)
+    }
+}
+
+fn summarize(away: Duration, sessions: u32, streamed: Duration, errors: u32) -> String {
+    let streams = match sessions {
+        0 => "no streaming".to_string(),
+        1 => format!("1 streaming session of {}", minutes(streamed)),
+        n => format!("{n} streaming sessions totaling {}", minutes(streamed)),
+    };
+    let errors = match errors {
+        0 => "no errors".to_string(),
+        1 => "1 error".to_string(),
+        n => format!("{n} errors"),
+    };
+    format!("Away for {}, {streams}, {errors}", minutes(away))
+}
+
+// Seconds are noise at this scale; under a minute shows as such.
+fn minutes(d: Duration) -> String {
+    match d.as_secs() {
+        0..60 => "under a minute".to_string(),
+        secs => duration::format(Duration::from_secs(secs / 60 * 60)),
+    }
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_digest() {
+        let start = Instant::now();
+        let at = |mins: u64| start + Duration::from_secs(mins * 60);
+
+        let mut digest = Digest::new(start);
+        digest.observe_stream(false, at(10));
+        digest.observe_stream(true, at(20));
+        digest.observe_stream(true, at(60));
+        digest.observe_stream(false, at(200));
+        digest.record_error();
+        digest.observe_stream(true, at(272));
+        // Still streaming when we get back: counted up to now.
+        assert_eq!(digest.finish(at(312)), "Away for 5h12m, 2 streaming sessions totaling 3h40m, 1 error");
+
+        assert_eq!(Digest::new(start).finish(at(90)), "Away for 1h30m, no streaming, no errors");
+    }
+
+    #[test]
+    fn test_summarize() {
+        let mins = |m: u64| Duration::from_secs(m * 60 + 17);
+        assert_eq!(summarize(mins(45), 1, mins(30), 3), "Away for 45m, 1 streaming session of 30m, 3 errors");
+        assert_eq!(summarize(Duration::from_secs(20), 0, Duration::ZERO, 0), "Away for under a minute, no streaming, no errors");
+    }
+}
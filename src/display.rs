@@ -3,19 +3,56 @@
 // Rust module system: each .rs file in src/ is a module.
 // main.rs uses `mod display;` to include it, then accesses items with `display::`.
 // Items need `pub` to be visible outside the module.
+//
+// The free functions at the bottom (`get_displays`, `enable_output`, ...)
+// go through whichever `CompositorBackend` matches the desktop: kscreen-doctor
+// on Plasma, wlr-randr on wlroots compositors, Mutter's D-Bus API on GNOME.
 
+use std::collections::VecDeque;
+use std::env;
+use std::fmt;
 use std::fs;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::str::FromStr;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::config::{Config, CustomMode, ProfileOutput, RetryConfig};
+use crate::drm;
+use crate::error::{Result, VitaminkError};
+use crate::kscreen_json;
+use crate::layout::Layout;
+use crate::metrics;
+#[cfg(feature = "mutter")]
+use crate::mutter::MutterBackend;
+use crate::presence;
+use crate::process;
+use crate::topology;
+#[cfg(feature = "wlr-output")]
+use crate::wlr_output::WlrOutputBackend;
+#[cfg(feature = "wlr-randr")]
+use crate::wlr_randr::WlrRandrBackend;
+#[cfg(feature = "xrandr")]
+use crate::xrandr::XrandrBackend;
 
 // ---- Data Types ----
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum DisplayState {
     Enabled,
     Disabled,
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ConnectionState {
     Connected,
     Disconnected,
@@ -24,14 +61,15 @@ pub enum ConnectionState {
 // Clone + Copy: these are small enums (just a tag, no heap data).
 // Clone lets you call .clone(), Copy makes assignment automatically copy
 // instead of "move" (Rust's default ownership transfer).
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum DpmsState {
     On,
     Off,
     Unknown,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Clone, Serialize)]
 pub struct Mode {
     pub id: u32,
     pub width: u32,
@@ -41,45 +79,335 @@ pub struct Mode {
     pub current: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Clone, Serialize)]
 pub struct Display {
     pub index: u32,
     pub name: String,
-    pub uuid: String,
+    // Only the text output includes it.
+    pub uuid: Option<String>,
     pub state: DisplayState,
     pub connection: ConnectionState,
     pub modes: Vec<Mode>,
+    // The fields below are absent for disabled outputs on some Plasma versions.
+    pub geometry: Option<Geometry>,
+    pub scale: Option<f64>,
+    // 1 = primary. Plasma 6 replaced the "primary" flag with priorities.
+    pub priority: Option<u32>,
+    pub color: Color,
+    // None if the output can't do adaptive sync, or the backend doesn't say.
+    pub vrr: Option<Vrr>,
+    pub rotation: Option<Rotation>,
+    // Percent; TVs that crop the picture edges need it.
+    pub overscan: Option<u32>,
+}
+
+// Position in the global desktop plus logical (post-scaling) size.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct Geometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// HDR and friends. KWin forgets them when an output is disabled, so the
+// layout saves them with everything else. None = the output can't, or the
+// backend doesn't say.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct Color {
+    pub hdr: Option<bool>,
+    pub wide_gamut: Option<bool>,
+    // Max bits per color channel; None is also "automatic".
+    pub bit_depth: Option<u32>,
+}
+
+// Adaptive sync policy, as KWin names it. Like the color settings it
+// falls back to Never when an output is re-enabled, so it's saved with
+// the layout.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Vrr {
+    Never,
+    Always,
+    Automatic,
+}
+
+impl Vrr {
+    // "Vrr: Automatic"; "incapable" (or anything new) is None.
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "never" => Some(Vrr::Never),
+            "always" => Some(Vrr::Always),
+            "automatic" => Some(Vrr::Automatic),
+            _ => None,
+        }
+    }
+
+    // The value kscreen-doctor's `output.N.vrrpolicy.` takes.
+    pub fn setting(self) -> &'static str {
+        match self {
+            Vrr::Never => "never",
+            Vrr::Always => "always",
+            Vrr::Automatic => "automatic",
+        }
+    }
+}
+
+// Which way the picture is turned, as KScreen names it.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rotation {
+    Normal,
+    Left,
+    Inverted,
+    Right,
+}
+
+impl Rotation {
+    // KScreen's flag values: "Rotation: 2", or `"rotation": 2` in JSON.
+    pub fn from_kscreen(value: u32) -> Option<Self> {
+        match value {
+            1 => Some(Rotation::Normal),
+            2 => Some(Rotation::Left),
+            4 => Some(Rotation::Inverted),
+            8 => Some(Rotation::Right),
+            _ => None,
+        }
+    }
+
+    // The value kscreen-doctor's `output.N.rotation.` takes.
+    pub fn setting(self) -> &'static str {
+        match self {
+            Rotation::Normal => "normal",
+            Rotation::Left => "left",
+            Rotation::Inverted => "inverted",
+            Rotation::Right => "right",
+        }
+    }
+
+    // Turned on its side, so the logical size is the mode's swapped.
+    pub fn is_sideways(self) -> bool {
+        matches!(self, Rotation::Left | Rotation::Right)
+    }
+}
+
+// A resolution (and optionally refresh rate) asked for in the config,
+// e.g. "3840x2160@60" or just "1920x1080".
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ModeTarget {
+    pub width: u32,
+    pub height: u32,
+    pub refresh: Option<f64>,
+}
+
+// `FromStr` is what makes `"3840x2160@60".parse::<ModeTarget>()` work.
+impl FromStr for ModeTarget {
+    type Err = VitaminkError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || VitaminkError::Parse(format!("Invalid mode \"{s}\", expected WIDTHxHEIGHT[@HZ]"));
+
+        let (res, refresh) = match s.trim().split_once('@') {
+            Some((res, hz)) => (res, Some(hz.parse::<f64>().map_err(|_| invalid())?)),
+            None => (s.trim(), None),
+        };
+        let (w, h) = res.split_once('x').ok_or_else(invalid)?;
+
+        Ok(Self {
+            width: w.parse().map_err(|_| invalid())?,
+            height: h.parse().map_err(|_| invalid())?,
+            refresh,
+        })
+    }
+}
+
+impl fmt::Display for ModeTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)?;
+        if let Some(hz) = self.refresh {
+            write!(f, "@{hz}")?;
+        }
+        Ok(())
+    }
+}
+
+// As the same "3840x2160@60" in state.json.
+impl Serialize for ModeTarget {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ModeTarget {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
 }
 
 // ---- Wayland Environment ----
 
-fn wayland_env() -> Vec<(&'static str, &'static str)> {
+// WAYLAND_DISPLAY / DISPLAY for the commands we run. A systemd user
+// service often starts before (or without) the session's variables, so
+// each is resolved from, in order: the config, our own environment, the
+// sockets in $XDG_RUNTIME_DIR (Wayland) or logind's session (X11), and
+// finally the old hard-coded defaults.
+#[derive(Debug, Clone)]
+struct SessionEnv {
+    wayland_display: String,
+    x11_display: String,
+}
+
+static SESSION_ENV: Mutex<Option<SessionEnv>> = Mutex::new(None);
+
+pub fn wayland_env() -> Vec<(&'static str, String)> {
+    let mut session = SESSION_ENV.lock().unwrap_or_else(|e| e.into_inner());
+    let session = session.get_or_insert_with(|| detect_session_env(None, None));
     vec![
-        ("WAYLAND_DISPLAY", "wayland-0"),
-        ("DISPLAY", ":0"),
+        ("WAYLAND_DISPLAY", session.wayland_display.clone()),
+        ("DISPLAY", session.x11_display.clone()),
     ]
 }
 
+fn detect_session_env(wayland_override: Option<&str>, x11_override: Option<&str>) -> SessionEnv {
+    let wayland_display = wayland_override
+        .map(str::to_string)
+        .or_else(|| env::var("WAYLAND_DISPLAY").ok().filter(|v| !v.is_empty()))
+        .or_else(|| {
+            let dir = env::var_os("XDG_RUNTIME_DIR")?;
+            let names: Vec<String> =
+                fs::read_dir(dir).ok()?.flatten().map(|e| e.file_name().to_string_lossy().to_string()).collect();
+            pick_wayland_socket(&names)
+        })
+        .unwrap_or_else(|| "wayland-0".to_string());
+
+    let x11_display = x11_override
+        .map(str::to_string)
+        .or_else(|| env::var("DISPLAY").ok().filter(|v| !v.is_empty()))
+        .or_else(|| presence::session_x11_display().ok().filter(|v| !v.is_empty()))
+        .unwrap_or_else(|| ":0".to_string());
+
+    SessionEnv { wayland_display, x11_display }
+}
+
+// Settles the variables afresh, e.g. once the compositor has come back
+// on another socket (see recovery.rs). WAYLAND_DISPLAY in our own
+// environment then names one that's gone, so any `wayland-N` there is
+// does instead. Returns whether anything changed.
+pub fn redetect_session_env(config: &Config) -> bool {
+    let mut fresh = detect_session_env(config.wayland_display.as_deref(), config.x11_display.as_deref());
+    if config.wayland_display.is_none()
+        && wayland_socket(None).is_none()
+        && let Some(dir) = env::var_os("XDG_RUNTIME_DIR")
+        && let Some(socket) = socket_in(Path::new(&dir), None)
+    {
+        fresh.wayland_display = socket.file_name().map_or_else(|| socket.display().to_string(), |name| name.to_string_lossy().to_string());
+    }
+    let mut session = SESSION_ENV.lock().unwrap_or_else(|e| e.into_inner());
+    let changed = session.as_ref().is_none_or(|old| old.wayland_display != fresh.wayland_display || old.x11_display != fresh.x11_display);
+    if changed {
+        info!("Running display commands with WAYLAND_DISPLAY={} DISPLAY={}", fresh.wayland_display, fresh.x11_display);
+    }
+    *session = Some(fresh);
+    changed
+}
+
+// The socket `detect_session_env` would pick, once it's there: the one
+// named by `wayland_override` or $WAYLAND_DISPLAY, or else any `wayland-N`.
+pub fn wayland_socket(wayland_override: Option<&str>) -> Option<PathBuf> {
+    let dir = PathBuf::from(env::var_os("XDG_RUNTIME_DIR")?);
+    let named = wayland_override.map(str::to_string).or_else(|| env::var("WAYLAND_DISPLAY").ok().filter(|v| !v.is_empty()));
+    socket_in(&dir, named.as_deref())
+}
+
+fn socket_in(dir: &Path, name: Option<&str>) -> Option<PathBuf> {
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => {
+            let names: Vec<String> = fs::read_dir(dir).ok()?.flatten().map(|e| e.file_name().to_string_lossy().to_string()).collect();
+            pick_wayland_socket(&names)?
+        }
+    };
+    // WAYLAND_DISPLAY may be a full path, which `join` keeps as it is.
+    let path = dir.join(name);
+    path.exists().then_some(path)
+}
+
+// `wayland-N` sockets (ignoring their `.lock` files); the lowest N wins,
+// since nested compositors take the higher numbers.
+fn pick_wayland_socket(names: &[String]) -> Option<String> {
+    names
+        .iter()
+        .filter_map(|name| Some((name.strip_prefix("wayland-")?.parse::<u32>().ok()?, name)))
+        .min()
+        .map(|(_, name)| name.clone())
+}
+
 // ---- Shell Commands ----
 
-fn run_kscreen_doctor(args: &[&str]) -> Result<String, String> {
+// Untranslated and with '.' for decimals whatever the session's locale
+// is: the parser below only knows the English words.
+fn kscreen_doctor(args: &[&str]) -> Command {
     let mut cmd = Command::new("kscreen-doctor");
     for (key, val) in wayland_env() {
         cmd.env(key, val);
     }
+    cmd.env("LC_ALL", "C").env("LANG", "C").env_remove("LANGUAGE");
     for arg in args {
         cmd.arg(arg);
     }
+    cmd
+}
 
-    let output = cmd.output().map_err(|e| format!("Failed to run kscreen-doctor: {e}"))?;
+// How to name `mode` to kscreen-doctor: "WxH@R" (refresh rounded, as
+// KScreen names modes) survives replugs and reboots, where mode ids get
+// renumbered. If another of the output's modes rounds to the same name,
+// the id from `modes` — which must be a fresh lookup — is used instead.
+pub fn kscreen_mode(modes: &[Mode], mode: &Mode) -> String {
+    let name = |m: &Mode| format!("{}x{}@{}", m.width, m.height, m.refresh.round());
+    let wanted = name(mode);
+    if modes.iter().filter(|m| name(m) == wanted).count() == 1 { wanted } else { mode.id.to_string() }
+}
+
+// Queries: always run, even in dry-run mode.
+fn run_kscreen_doctor(args: &[&str]) -> Result<String> {
+    let output = process::output(&mut kscreen_doctor(args)).inspect_err(|_| metrics::record_kscreen_failure())?;
 
     if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("kscreen-doctor failed: {stderr}"));
+        metrics::record_kscreen_failure();
+        return Err(VitaminkError::command_failed("kscreen-doctor", &output));
     }
 
-    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-    Ok(strip_ansi(&stdout))
+    let stdout = strip_ansi(&String::from_utf8_lossy(&output.stdout));
+    remember_dump(&format!("kscreen-doctor {}", args.join(" ")), &stdout);
+    Ok(stdout)
+}
+
+// The last few raw kscreen-doctor outputs, for diagnostics bundles.
+const KEPT_DUMPS: usize = 5;
+static RECENT_DUMPS: Mutex<VecDeque<(String, String)>> = Mutex::new(VecDeque::new());
+
+fn remember_dump(command: &str, output: &str) {
+    let mut dumps = RECENT_DUMPS.lock().unwrap_or_else(|e| e.into_inner());
+    if dumps.len() == KEPT_DUMPS {
+        dumps.pop_front();
+    }
+    dumps.push_back((command.to_string(), output.to_string()));
+}
+
+// Oldest first, as (command line, output).
+pub fn recent_dumps() -> Vec<(String, String)> {
+    RECENT_DUMPS.lock().unwrap_or_else(|e| e.into_inner()).iter().cloned().collect()
+}
+
+// Changes: only logged in dry-run mode.
+fn apply_kscreen_doctor(args: &[&str]) -> Result<()> {
+    let output = process::run(&mut kscreen_doctor(args)).inspect_err(|_| metrics::record_kscreen_failure())?;
+
+    if !output.status.success() {
+        metrics::record_kscreen_failure();
+        return Err(VitaminkError::command_failed("kscreen-doctor", &output));
+    }
+    Ok(())
 }
 
 fn strip_ansi(input: &str) -> String {
@@ -99,177 +427,1027 @@ fn strip_ansi(input: &str) -> String {
     result
 }
 
-// ---- Parsing ----
+// ---- kscreen-doctor ----
 
-pub fn get_displays() -> Result<Vec<Display>, String> {
-    let raw = run_kscreen_doctor(&["-o"])?;
-    parse_displays(&raw)
+// Something that can list the outputs. `dyn DisplayBackend` lets callers
+// hold "some backend" without caring which one it is.
+pub trait DisplayBackend {
+    fn name(&self) -> &'static str;
+    fn get_displays(&self) -> Result<Vec<Display>>;
 }
 
-fn parse_displays(output: &str) -> Result<Vec<Display>, String> {
-    let mut displays = Vec::new();
-    let mut current_lines: Vec<&str> = Vec::new();
-    let mut header_line: Option<&str> = None;
+// `kscreen-doctor -j` — structured, preferred when available.
+pub struct KscreenJsonBackend;
+
+impl DisplayBackend for KscreenJsonBackend {
+    fn name(&self) -> &'static str {
+        "kscreen-doctor -j"
+    }
+
+    fn get_displays(&self) -> Result<Vec<Display>> {
+        parse_cached(&run_kscreen_doctor(&["-j"])?, kscreen_json::parse)
+    }
+}
+
+// `kscreen-doctor -o` — human-readable, works on every Plasma version.
+pub struct KscreenTextBackend;
+
+impl DisplayBackend for KscreenTextBackend {
+    fn name(&self) -> &'static str {
+        "kscreen-doctor -o"
+    }
+
+    fn get_displays(&self) -> Result<Vec<Display>> {
+        parse_cached(&run_kscreen_doctor(&["-o"])?, parse_installed)
+    }
+}
+
+// Hash of the last raw output and what it parsed to. Between transitions
+// kscreen-doctor prints the same thing every time, so we skip the parse.
+static LAST_PARSE: Mutex<Option<(u64, Vec<Display>)>> = Mutex::new(None);
+
+fn parse_cached(raw: &str, parse: fn(&str) -> Result<Vec<Display>>) -> Result<Vec<Display>> {
+    let mut hasher = DefaultHasher::new();
+    raw.hash(&mut hasher);
+    let hash = hasher.finish();
+
+    let mut last = LAST_PARSE.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((last_hash, displays)) = last.as_ref()
+        && *last_hash == hash
+    {
+        return Ok(displays.clone());
+    }
+
+    let displays = parse(raw)?;
+    *last = Some((hash, displays.clone()));
+    Ok(displays)
+}
+
+// Set once JSON has failed where text worked, so older Plasma versions
+// don't pay for a doomed `-j` call on every poll.
+static JSON_UNSUPPORTED: AtomicBool = AtomicBool::new(false);
+
+// Tries JSON first and falls back to the text parser.
+fn kscreen_displays() -> Result<Vec<Display>> {
+    if JSON_UNSUPPORTED.load(Ordering::Relaxed) {
+        return KscreenTextBackend.get_displays();
+    }
+
+    match KscreenJsonBackend.get_displays() {
+        Ok(displays) => Ok(displays),
+        Err(json_err) => {
+            let displays = KscreenTextBackend.get_displays()?;
+            warn!(
+                "{} unusable ({json_err}), using {} from now on",
+                KscreenJsonBackend.name(),
+                KscreenTextBackend.name()
+            );
+            JSON_UNSUPPORTED.store(true, Ordering::Relaxed);
+            Ok(displays)
+        }
+    }
+}
+
+// Plasma, through kscreen-doctor.
+pub struct KscreenBackend;
+
+impl CompositorBackend for KscreenBackend {
+    fn name(&self) -> &'static str {
+        "kscreen-doctor"
+    }
+
+    fn get_displays(&self) -> Result<Vec<Display>> {
+        kscreen_displays()
+    }
+
+    fn enable_output(&self, display: &Display, mode: &Mode, scale: Option<f64>) -> Result<()> {
+        let args = kscreen_enable_args(display, mode, scale);
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        apply_kscreen_doctor(&args)
+    }
+
+    fn disable_output(&self, name: &str) -> Result<()> {
+        let disable_arg = format!("output.{name}.disable");
+        apply_kscreen_doctor(&[&disable_arg])
+    }
+
+    // One kscreen-doctor call, so KWin sees it as one configuration change.
+    fn apply_layout(&self, layout: &Layout) -> Result<()> {
+        let settings = layout.settings(&self.get_displays()?);
+        if settings.is_empty() {
+            return Ok(());
+        }
+        let args: Vec<&str> = settings.iter().map(String::as_str).collect();
+        apply_kscreen_doctor(&args)
+    }
+
+    // kscreen-doctor takes any number of settings: one call for them all.
+    fn apply_changes(&self, changes: &[OutputChange]) -> Result<()> {
+        let args: Vec<String> = changes
+            .iter()
+            .flat_map(|change| match change {
+                OutputChange::Enable { display, mode, scale } => kscreen_enable_args(display, mode, *scale),
+                OutputChange::Disable(name) => vec![format!("output.{name}.disable")],
+            })
+            .collect();
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        apply_kscreen_doctor(&args)
+    }
+
+    fn set_all_dpms(&self, on: bool) -> Result<()> {
+        apply_kscreen_doctor(&["--dpms", if on { "on" } else { "off" }])
+    }
+
+    fn set_dpms(&self, name: &str, on: bool) -> Result<()> {
+        let args = kscreen_dpms_args(&self.get_displays()?, name, on);
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        apply_kscreen_doctor(&args)
+    }
+
+    // KWin moves the others down itself.
+    fn set_primary(&self, name: &str) -> Result<()> {
+        apply_kscreen_doctor(&[&format!("output.{name}.priority.1")])
+    }
+
+    // The text output's block for `name`, even when JSON is in use —
+    // it's the one users can compare against their own terminal.
+    fn raw_output(&self, name: &str) -> Result<Vec<String>> {
+        Ok(output_block(&run_kscreen_doctor(&["-o"])?, name))
+    }
+
+    // Plasma 6.2 and later.
+    fn add_mode(&self, custom: &CustomMode) -> Result<()> {
+        let blanking = if custom.reduced_blanking { "reduced" } else { "full" };
+        apply_kscreen_doctor(&[&format!("output.{}.addCustomMode.{}.{blanking}", custom.output, kscreen_custom_mode(&custom.mode))])
+    }
+
+    fn remove_mode(&self, custom: &CustomMode) -> Result<()> {
+        apply_kscreen_doctor(&[&format!("output.{}.removeCustomMode.{}", custom.output, kscreen_custom_mode(&custom.mode))])
+    }
+
+    // A failing `-j` may have been KWin going away, not an old Plasma.
+    fn reset(&self) {
+        *LAST_PARSE.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        JSON_UNSUPPORTED.store(false, Ordering::Relaxed);
+    }
+}
+
+fn kscreen_enable_args(display: &Display, mode: &Mode, scale: Option<f64>) -> Vec<String> {
+    let mut args = vec![
+        format!("output.{}.enable", display.name),
+        format!("output.{}.mode.{}", display.name, kscreen_mode(&display.modes, mode)),
+    ];
+    if let Some(scale) = scale {
+        args.push(format!("output.{}.scale.{scale}", display.name));
+    }
+    args
+}
+
+// "1920.1080.120000": the refresh rate in mHz.
+fn kscreen_custom_mode(mode: &ModeTarget) -> String {
+    format!("{}.{}.{}", mode.width, mode.height, (mode.refresh.unwrap_or(60.0) * 1000.0).round())
+}
+
+// kscreen-doctor has no DPMS setting per output, only outputs to leave
+// out of `--dpms`: every other enabled one.
+fn kscreen_dpms_args(displays: &[Display], name: &str, on: bool) -> Vec<String> {
+    let mut args = vec!["--dpms".to_string(), if on { "on" } else { "off" }.to_string()];
+    for display in displays.iter().filter(|d| d.name != name && d.state == DisplayState::Enabled) {
+        args.extend(["--dpms-excluded".to_string(), display.name.clone()]);
+    }
+    args
+}
+
+// The "Output: N NAME ..." line and everything up to the next one.
+fn output_block(output: &str, name: &str) -> Vec<String> {
+    let mut block = Vec::new();
+    let mut inside = false;
 
     for line in output.lines() {
         if line.starts_with("Output:") {
-            if let Some(header) = header_line {
-                displays.push(parse_single_display(header, &current_lines)?);
+            inside = line.split_whitespace().nth(2) == Some(name);
+        }
+        if inside {
+            block.push(line.to_string());
+        }
+    }
+    block
+}
+
+// ---- Compositor Backends ----
+
+// Everything the daemon needs from the compositor. `Send + Sync` because
+// the chosen backend lives in a static shared by every thread.
+pub trait CompositorBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn get_displays(&self) -> Result<Vec<Display>>;
+    // `display` and `mode` come from a fresh `get_displays` on this backend.
+    // `scale` None leaves whatever the compositor picks.
+    fn enable_output(&self, display: &Display, mode: &Mode, scale: Option<f64>) -> Result<()>;
+    fn disable_output(&self, name: &str) -> Result<()>;
+    fn apply_layout(&self, layout: &Layout) -> Result<()>;
+    fn set_all_dpms(&self, on: bool) -> Result<()>;
+    // Blanks or wakes `name` alone, leaving it enabled. Not every
+    // compositor can single one out.
+    fn set_dpms(&self, _name: &str, _on: bool) -> Result<()> {
+        Err(VitaminkError::Unavailable(format!("{} can't blank one output on its own", self.name())))
+    }
+    // Makes `name` the primary output. wlroots has no such thing.
+    fn set_primary(&self, _name: &str) -> Result<()> {
+        Err(VitaminkError::Unavailable(format!("{} has no primary output", self.name())))
+    }
+    // Several outputs changed together. Backends that can should do it in
+    // one go; by default it's one change after another.
+    fn apply_changes(&self, changes: &[OutputChange]) -> Result<()> {
+        for change in changes {
+            match change {
+                OutputChange::Enable { display, mode, scale } => self.enable_output(display, mode, *scale)?,
+                OutputChange::Disable(name) => self.disable_output(name)?,
+            }
+        }
+        Ok(())
+    }
+    // What the compositor says about `name`'s DPMS, for backends that know
+    // better than /sys/class/drm. None leaves it to the kernel.
+    fn dpms(&self, _name: &str) -> Option<DpmsState> {
+        None
+    }
+    // What the backend printed about one output, for debugging the parser.
+    fn raw_output(&self, _name: &str) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+    // Tells topology.rs about output changes the compositor announces, for
+    // backends that hear about them. The kernel's hotplugs are heard anyway.
+    fn watch_changes(&self) -> Result<()> {
+        Ok(())
+    }
+    // Forgets whatever the backend has cached or concluded about the
+    // compositor, so the next call finds out afresh (see recovery.rs).
+    fn reset(&self) {}
+    // Adds a mode the output doesn't advertise, and takes it away again
+    // (see custom_mode.rs).
+    fn add_mode(&self, _custom: &CustomMode) -> Result<()> {
+        Err(VitaminkError::Unavailable(format!("{} can't add custom modes", self.name())))
+    }
+    fn remove_mode(&self, _custom: &CustomMode) -> Result<()> {
+        Ok(())
+    }
+}
+
+// One output's part of `apply_changes`.
+pub enum OutputChange<'a> {
+    Enable { display: &'a Display, mode: &'a Mode, scale: Option<f64> },
+    Disable(&'a str),
+}
+
+// The `display_backend` config setting.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackendKind {
+    #[default]
+    Auto,
+    Kscreen,
+    WlrRandr,
+    Mutter,
+    // wlr-output-management without wlr-randr; never picked by "auto".
+    WlrOutput,
+    Xrandr,
+}
+
+// The `away_outputs_off` config setting: how AtDesk turns off what Away
+// turned on.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OffStrategy {
+    #[default]
+    Disable,
+    DpmsOff,
+}
+
+static BACKEND: OnceLock<Box<dyn CompositorBackend>> = OnceLock::new();
+static RETRY: OnceLock<RetryConfig> = OnceLock::new();
+
+// Chooses the session environment and backend for the rest of the process.
+// Only the first call counts; anything that runs before it gets auto-detection.
+pub fn init(config: &Config) {
+    SESSION_ENV
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get_or_insert_with(|| detect_session_env(config.wayland_display.as_deref(), config.x11_display.as_deref()));
+    let _ = RETRY.set(config.display_retry.clone());
+
+    let kind = match config.display_backend {
+        BackendKind::Auto => detect_backend(
+            &env::var("XDG_CURRENT_DESKTOP").unwrap_or_default(),
+            &env::var("XDG_SESSION_TYPE").unwrap_or_default(),
+            env::var_os("WAYLAND_DISPLAY").is_some() || env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some(),
+        ),
+        kind => kind,
+    };
+    let _ = BACKEND.set(match kind {
+        #[cfg(feature = "wlr-randr")]
+        BackendKind::WlrRandr => Box::new(WlrRandrBackend),
+        #[cfg(feature = "mutter")]
+        BackendKind::Mutter => Box::new(MutterBackend),
+        #[cfg(feature = "wlr-output")]
+        BackendKind::WlrOutput => Box::new(WlrOutputBackend),
+        #[cfg(feature = "xrandr")]
+        BackendKind::Xrandr => Box::new(XrandrBackend),
+        BackendKind::Kscreen | BackendKind::Auto => Box::new(KscreenBackend),
+        // Only reachable with a backend's feature turned off.
+        #[allow(unreachable_patterns)]
+        missing => {
+            warn!("This build has no {missing:?} support, using kscreen-doctor");
+            Box::new(KscreenBackend)
+        }
+    });
+}
+
+// Uses `backend` instead of the configured one, e.g. the in-memory one in
+// mock.rs. Call it before anything lists outputs; like `init`, only the
+// first call counts.
+pub fn use_backend(backend: Box<dyn CompositorBackend>) {
+    let _ = BACKEND.set(backend);
+}
+
+pub fn backend() -> &'static dyn CompositorBackend {
+    if BACKEND.get().is_none() {
+        init(&Config::default());
+    }
+    BACKEND.get().expect("backend initialized above").as_ref()
+}
+
+// XDG_CURRENT_DESKTOP is a colon-separated list, e.g. "ubuntu:GNOME".
+// An X11 session gets xrandr whatever the desktop, Plasma included. Any
+// other Wayland session is assumed to be wlroots-based; without one we
+// stay with kscreen-doctor, the original backend.
+fn detect_backend(current_desktop: &str, session_type: &str, wayland: bool) -> BackendKind {
+    let desktops: Vec<String> = current_desktop.split(':').map(str::to_uppercase).collect();
+
+    if session_type == "x11" {
+        BackendKind::Xrandr
+    } else if desktops.iter().any(|d| d == "KDE") {
+        BackendKind::Kscreen
+    } else if desktops.iter().any(|d| d == "GNOME") {
+        BackendKind::Mutter
+    } else if wayland {
+        BackendKind::WlrRandr
+    } else {
+        BackendKind::Kscreen
+    }
+}
+
+// The outputs as last listed, while that's still current (see topology.rs).
+pub fn get_displays() -> Result<Vec<Display>> {
+    topology::manager().displays()
+}
+
+// Lists them again regardless, for when a stale list would do harm.
+pub fn refresh_displays() -> Result<Vec<Display>> {
+    topology::manager().refresh()
+}
+
+// Asks the backend, bypassing the cache.
+pub fn list_displays() -> Result<Vec<Display>> {
+    with_retry("Listing outputs", || backend().get_displays())
+}
+
+// ---- Retrying ----
+
+// Runs `f` until it succeeds, fails with a non-transient error, or runs
+// out of `[display_retry]` attempts. Returns the last error.
+fn with_retry<T>(what: &str, mut f: impl FnMut() -> Result<T>) -> Result<T> {
+    let policy = RETRY.get().cloned().unwrap_or_default();
+    let mut attempt = 0;
+    loop {
+        match f() {
+            Err(e) if e.is_transient() && attempt + 1 < policy.attempts => {
+                let delay = backoff(&policy, attempt, noise());
+                warn!("{what} failed ({e}), retrying in {delay:?}");
+                thread::sleep(delay);
+                attempt += 1;
             }
-            header_line = Some(line);
-            current_lines.clear();
-        } else if header_line.is_some() {
-            current_lines.push(line);
+            result => return result,
         }
     }
+}
+
+// The delay before retry number `attempt + 1`. `noise` is in [0, 1) and
+// picks where in the ±jitter band the delay lands.
+fn backoff(policy: &RetryConfig, attempt: u32, noise: f64) -> Duration {
+    let base = policy.initial_delay.saturating_mul(2u32.saturating_pow(attempt)).min(policy.max_delay);
+    base.mul_f64(1.0 + policy.jitter * (2.0 * noise - 1.0))
+}
 
-    if let Some(header) = header_line {
-        displays.push(parse_single_display(header, &current_lines)?);
+// Good enough to keep several retrying processes from lining up; no need
+// for a random number crate.
+fn noise() -> f64 {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    f64::from(nanos % 1_000_000) / 1_000_000.0
+}
+
+// ---- kscreen-doctor Versions ----
+//
+// `kscreen-doctor -o` changed shape with Plasma 6:
+//
+//   Plasma 5.27   Output: 1 DP-2 enabled connected priority 1 DisplayPort Modes: 1:2560x1440@144*! ... Geometry: 0,0 2560x1440 Scale: 1 ...
+//   Plasma 6      Output: 1 DP-2 0b3f7c8e-...
+//                     enabled
+//                     connected
+//                     priority 1
+//                     Modes: 1:2560x1440@144*! ...
+//
+// The installed version (`kscreen-doctor --version`, asked once) picks the
+// rules. If it can't be told, the first header line decides.
+
+// The oldest Plasma whose output we know how to read.
+const OLDEST_KSCREEN: (u32, u32) = (5, 27);
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum TextFormat {
+    // Plasma 5: everything on the header line.
+    SingleLine,
+    // Plasma 6: a UUID on the header line, one attribute per line below.
+    MultiLine,
+}
+
+static KSCREEN_VERSION: OnceLock<Option<(u32, u32)>> = OnceLock::new();
+
+// (major, minor); None if kscreen-doctor doesn't say.
+fn kscreen_version() -> Option<(u32, u32)> {
+    *KSCREEN_VERSION.get_or_init(|| {
+        let output = process::output(&mut kscreen_doctor(&["--version"])).ok()?;
+        let version = parse_version(&String::from_utf8_lossy(&output.stdout));
+        match version {
+            Some((major, minor)) => info!("kscreen-doctor {major}.{minor}"),
+            None => debug!("Couldn't tell which kscreen-doctor is installed"),
+        }
+        version
+    })
+}
+
+// "kscreen-doctor 6.1.4" → (6, 1). Anything before Plasma 5 isn't a
+// Plasma version (some builds report their own "1.0").
+fn parse_version(text: &str) -> Option<(u32, u32)> {
+    let version = text.split_whitespace().find(|word| word.starts_with(|c: char| c.is_ascii_digit()))?;
+    let mut numbers = version.split('.').map(|n| n.parse::<u32>().ok());
+    let (major, minor) = (numbers.next()??, numbers.next().flatten().unwrap_or(0));
+    (major >= 5).then_some((major, minor))
+}
+
+fn text_format(version: Option<(u32, u32)>, output: &str) -> Result<TextFormat> {
+    match version {
+        Some(version) if version < OLDEST_KSCREEN => Err(parse_error(format!(
+            "kscreen-doctor {}.{} isn't supported (Plasma {}.{} or newer is needed)",
+            version.0, version.1, OLDEST_KSCREEN.0, OLDEST_KSCREEN.1
+        ))),
+        Some((5, _)) => Ok(TextFormat::SingleLine),
+        Some(_) => Ok(TextFormat::MultiLine),
+        None => Ok(sniff_format(output)),
     }
+}
 
-    Ok(displays)
+// Plasma 5 puts "enabled"/"disabled" on the header line itself.
+fn sniff_format(output: &str) -> TextFormat {
+    let header = output.lines().find(|line| line.starts_with("Output:")).unwrap_or_default();
+    if header.split_whitespace().skip(3).any(|word| word == "enabled" || word == "disabled") {
+        TextFormat::SingleLine
+    } else {
+        TextFormat::MultiLine
+    }
 }
 
-fn parse_single_display(header: &str, body: &[&str]) -> Result<Display, String> {
-    let parts: Vec<&str> = header.split_whitespace().collect();
-    if parts.len() < 4 {
-        return Err(format!("Invalid display header: {header}"));
+// With the installed version's rules; errors say which version it was.
+fn parse_installed(output: &str) -> Result<Vec<Display>> {
+    let version = kscreen_version();
+    let format = text_format(version, output)?;
+    parse_text(output, format).map_err(|e| match version {
+        Some((major, minor)) => parse_error(format!("kscreen-doctor {major}.{minor} output not understood ({format:?}): {e}")),
+        None => e,
+    })
+}
+
+// ---- Parsing ----
+//
+// One odd output mustn't hide the others: an output whose header can't be
+// read is skipped, and a field or mode that can't be read is left out,
+// each with a warning. It's only an error when no output can be read.
+//
+// kscreen-doctor runs with LC_ALL=C, but should its output be translated
+// anyway, that mustn't pass for outputs that are off and unplugged: an
+// output that says neither "enabled" nor "disabled" gets a warning, and
+// text without a single "Output:" line is an error.
+
+fn parse_text(output: &str, format: TextFormat) -> Result<Vec<Display>> {
+    let (displays, warnings) = parse_text_lenient(output, format);
+    for warning in &warnings {
+        warn!("kscreen-doctor: {warning}");
     }
+    displays
+}
 
-    let index: u32 = parts[1].parse().map_err(|_| format!("Invalid index: {}", parts[1]))?;
+// What could be read, and what couldn't.
+fn parse_text_lenient(output: &str, format: TextFormat) -> (Result<Vec<Display>>, Vec<String>) {
+    // Each "Output:" line and the lines below it.
+    let mut blocks: Vec<(&str, Vec<String>)> = Vec::new();
+    for line in output.lines() {
+        if line.starts_with("Output:") {
+            let body = match format {
+                TextFormat::SingleLine => unfold_single_line(line),
+                TextFormat::MultiLine => Vec::new(),
+            };
+            blocks.push((line, body));
+        } else if let Some((_, body)) = blocks.last_mut() {
+            body.push(line.to_string());
+        }
+    }
+
+    let mut displays = Vec::new();
+    let mut warnings = Vec::new();
+    if blocks.is_empty() && !output.trim().is_empty() {
+        return (Err(parse_error("no \"Output:\" lines; is kscreen-doctor's output translated?".to_string())), warnings);
+    }
+    let mut skipped = Vec::new();
+    for (position, (header, body)) in blocks.iter().enumerate() {
+        match parse_single_display(header, body, format, position, &mut warnings) {
+            Ok(display) => displays.push(display),
+            Err(e) => skipped.push(e),
+        }
+    }
+    if displays.is_empty() && !skipped.is_empty() {
+        return (Err(skipped.swap_remove(0)), warnings);
+    }
+    warnings.extend(skipped.iter().map(|e| format!("skipped an output: {e}")));
+    (Ok(displays), warnings)
+}
+
+// A Plasma 5 header line as the lines Plasma 6 would print: "enabled",
+// "priority 1", "Modes: 1:... 2:...", "Geometry: 0,0 2560x1440" and so on.
+fn unfold_single_line(header: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    // Whether the last line is a "Key:" still taking values.
+    let mut open = false;
+    let mut words = header.split_whitespace().skip(3);
+    while let Some(word) = words.next() {
+        if word == "priority" {
+            lines.push(format!("priority {}", words.next().unwrap_or_default()));
+            open = false;
+        } else if word.ends_with(':') {
+            lines.push(word.to_string());
+            open = true;
+        } else if open && let Some(last) = lines.last_mut() {
+            last.push(' ');
+            last.push_str(word);
+        } else {
+            lines.push(word.to_string());
+        }
+    }
+    lines
+}
+
+// `position` among the outputs stands in for an index that can't be read.
+// What can't be read of the rest goes to `warnings`.
+fn parse_single_display(header: &str, body: &[String], format: TextFormat, position: usize, warnings: &mut Vec<String>) -> Result<Display> {
+    let parts: Vec<&str> = header.split_whitespace().collect();
+    if parts.len() < 3 {
+        return Err(VitaminkError::Parse(format!("Invalid display header: {header}")));
+    }
     let name = parts[2].to_string();
-    let uuid = parts[3].to_string();
+    let mut problems = Vec::new();
 
-    let mut state = DisplayState::Disabled;
-    let mut connection = ConnectionState::Disconnected;
+    let index = parts[1].parse().unwrap_or_else(|_| {
+        problems.push(format!("invalid index {}", parts[1]));
+        position as u32 + 1
+    });
+    // Plasma 5 prints none; some builds print none at all, or several words.
+    let uuid = match format {
+        TextFormat::SingleLine => None,
+        TextFormat::MultiLine => Some(parts[3..].join(" ")).filter(|uuid| !uuid.is_empty()),
+    };
+
+    let mut state = None;
+    let mut connection = None;
     let mut modes = Vec::new();
+    let mut geometry = None;
+    let mut scale = None;
+    let mut priority = None;
+    let mut color = Color::default();
+    let mut vrr = None;
+    let mut rotation = None;
+    let mut overscan = None;
 
     for line in body {
         let trimmed = line.trim();
         match trimmed {
-            "enabled" => state = DisplayState::Enabled,
-            "disabled" => state = DisplayState::Disabled,
-            "connected" => connection = ConnectionState::Connected,
-            "disconnected" => connection = ConnectionState::Disconnected,
+            "enabled" => state = Some(DisplayState::Enabled),
+            "disabled" => state = Some(DisplayState::Disabled),
+            "connected" => connection = Some(ConnectionState::Connected),
+            "disconnected" => connection = Some(ConnectionState::Disconnected),
             _ if trimmed.starts_with("Modes:") => {
-                modes = parse_modes(trimmed)?;
+                modes = parse_modes(trimmed, &mut problems);
+            }
+            _ if trimmed.starts_with("Geometry:") => {
+                geometry = parse_geometry(trimmed).map_err(|e| problems.push(e.to_string())).ok();
+            }
+            _ if trimmed.starts_with("Scale:") => {
+                let value = trimmed["Scale:".len()..].trim();
+                scale = decimal(value).filter(|s| s.is_finite() && *s > 0.0);
+                if scale.is_none() {
+                    problems.push(format!("Invalid scale: {value}"));
+                }
+            }
+            _ if trimmed.starts_with("priority ") => {
+                let value = trimmed["priority ".len()..].trim();
+                priority = value.parse().map_err(|_| problems.push(format!("Invalid priority: {value}"))).ok();
+            }
+            // "HDR: enabled", "Wide Color Gamut: incapable", "Max bits per color: 10"
+            _ if trimmed.starts_with("HDR:") => color.hdr = switch(&trimmed["HDR:".len()..]),
+            _ if trimmed.starts_with("Wide Color Gamut:") => color.wide_gamut = switch(&trimmed["Wide Color Gamut:".len()..]),
+            _ if trimmed.starts_with("Max bits per color:") => {
+                color.bit_depth = trimmed["Max bits per color:".len()..].trim().parse().ok();
+            }
+            _ if trimmed.starts_with("Vrr:") => vrr = Vrr::parse(&trimmed["Vrr:".len()..]),
+            _ if trimmed.starts_with("Rotation:") => {
+                rotation = trimmed["Rotation:".len()..].trim().parse().ok().and_then(Rotation::from_kscreen);
             }
+            _ if trimmed.starts_with("Overscan:") => overscan = trimmed["Overscan:".len()..].trim().parse().ok(),
             _ => {}
         }
     }
 
-    Ok(Display { index, name, uuid, state, connection, modes })
+    if state.is_none() {
+        problems.push("neither enabled nor disabled, taking it as disabled".to_string());
+    }
+    if connection.is_none() {
+        problems.push("neither connected nor disconnected, taking it as disconnected".to_string());
+    }
+    let state = state.unwrap_or(DisplayState::Disabled);
+    let connection = connection.unwrap_or(ConnectionState::Disconnected);
+
+    warnings.extend(problems.into_iter().map(|problem| format!("{name}: {problem}")));
+    Ok(Display { index, name, uuid, state, connection, modes, geometry, scale, priority, color, vrr, rotation, overscan })
 }
 
-fn parse_modes(line: &str) -> Result<Vec<Mode>, String> {
+// "enabled"/"disabled"; "incapable" (or anything new) is None.
+fn switch(value: &str) -> Option<bool> {
+    match value.trim() {
+        "enabled" => Some(true),
+        "disabled" => Some(false),
+        _ => None,
+    }
+}
+
+// "Geometry: 1920,0 2560x1440"
+fn parse_geometry(line: &str) -> Result<Geometry> {
+    let value = line.strip_prefix("Geometry:").unwrap_or(line).trim();
+    let invalid = || parse_error(format!("Invalid geometry: {value}"));
+
+    let (pos, size) = value.split_once(' ').ok_or_else(invalid)?;
+    let (x, y) = pos.split_once(',').ok_or_else(invalid)?;
+    let (w, h) = size.trim().split_once('x').ok_or_else(invalid)?;
+
+    Ok(Geometry {
+        x: x.parse().map_err(|_| invalid())?,
+        y: y.parse().map_err(|_| invalid())?,
+        width: w.parse().map_err(|_| invalid())?,
+        height: h.parse().map_err(|_| invalid())?,
+    })
+}
+
+// A mode token that can't be read is left out, with a note in `problems`.
+fn parse_modes(line: &str, problems: &mut Vec<String>) -> Vec<Mode> {
     let modes_str = line.strip_prefix("Modes:").unwrap_or(line).trim();
     let mut modes = Vec::new();
 
     for token in modes_str.split_whitespace() {
-        let (id_str, spec) = token.split_once(':')
-            .ok_or_else(|| format!("Invalid mode token: {token}"))?;
+        match parse_mode(token) {
+            Ok(mode) => modes.push(mode),
+            Err(e) => problems.push(e.to_string()),
+        }
+    }
+    modes
+}
 
-        let id: u32 = id_str.parse()
-            .map_err(|_| format!("Invalid mode id: {id_str}"))?;
+// "3:2560x1440@144.00*!"
+fn parse_mode(token: &str) -> Result<Mode> {
+    let (id_str, spec) = token.split_once(':')
+        .ok_or_else(|| parse_error(format!("Invalid mode token: {token}")))?;
 
-        let current = spec.contains('*');
-        let preferred = spec.contains('!');
-        let clean = spec.replace(['*', '!'], "");
+    let id: u32 = id_str.parse()
+        .map_err(|_| parse_error(format!("Invalid mode id: {id_str}")))?;
 
-        let (res, refresh_str) = clean.split_once('@')
-            .ok_or_else(|| format!("Invalid mode spec: {clean}"))?;
+    let current = spec.contains('*');
+    let preferred = spec.contains('!');
+    let clean = spec.replace(['*', '!'], "");
 
-        let (w_str, h_str) = res.split_once('x')
-            .ok_or_else(|| format!("Invalid resolution: {res}"))?;
+    let (res, refresh_str) = clean.split_once('@')
+        .ok_or_else(|| parse_error(format!("Invalid mode spec: {clean}")))?;
 
-        let width: u32 = w_str.parse().map_err(|_| format!("Invalid width: {w_str}"))?;
-        let height: u32 = h_str.parse().map_err(|_| format!("Invalid height: {h_str}"))?;
-        let refresh: f64 = refresh_str.parse().map_err(|_| format!("Invalid refresh: {refresh_str}"))?;
+    let (w_str, h_str) = res.split_once('x')
+        .ok_or_else(|| parse_error(format!("Invalid resolution: {res}")))?;
 
-        modes.push(Mode { id, width, height, refresh, preferred, current });
-    }
+    let width: u32 = w_str.parse().map_err(|_| parse_error(format!("Invalid width: {w_str}")))?;
+    let height: u32 = h_str.parse().map_err(|_| parse_error(format!("Invalid height: {h_str}")))?;
+    // f64 would take "inf" and "NaN" too.
+    let refresh: f64 = decimal(refresh_str)
+        .filter(|hz| hz.is_finite() && *hz >= 0.0)
+        .ok_or_else(|| parse_error(format!("Invalid refresh: {refresh_str}")))?;
+
+    Ok(Mode { id, width, height, refresh, preferred, current })
+}
+
+// "1.25", or "1,25" from a kscreen-doctor that used the locale's decimal
+// comma after all.
+fn decimal(text: &str) -> Option<f64> {
+    text.replacen(',', ".", 1).parse().ok()
+}
 
-    Ok(modes)
+fn parse_error(message: String) -> VitaminkError {
+    VitaminkError::Parse(message)
+}
+
+// ---- Mode Selection ----
+
+// Without a target: the display's preferred mode (what its EDID says it
+// wants), then the current one, then whatever comes first.
+// With a target: the closest resolution, then the closest refresh rate.
+// Exact matches naturally win since their distance is zero.
+pub fn select_mode<'a>(modes: &'a [Mode], target: Option<&ModeTarget>) -> Option<&'a Mode> {
+    let Some(target) = target else {
+        return modes
+            .iter()
+            .find(|m| m.preferred)
+            .or_else(|| modes.iter().find(|m| m.current))
+            .or_else(|| modes.first());
+    };
+
+    let resolution_distance = |m: &Mode| m.width.abs_diff(target.width) + m.height.abs_diff(target.height);
+    let refresh_distance = |m: &Mode| match target.refresh {
+        Some(hz) => (m.refresh - hz).abs(),
+        // No refresh asked for: prefer the fastest mode at that resolution.
+        None => -m.refresh,
+    };
+
+    modes.iter().min_by(|a, b| {
+        resolution_distance(a)
+            .cmp(&resolution_distance(b))
+            .then(refresh_distance(a).total_cmp(&refresh_distance(b)))
+    })
 }
 
 // ---- DPMS ----
 
+// Laptop panels. Closing the lid makes the compositor switch these off
+// entirely rather than blank them, so DPMS alone misreads them.
+pub fn is_internal_panel(name: &str) -> bool {
+    ["eDP", "LVDS", "DSI"].iter().any(|prefix| name.starts_with(prefix))
+}
+
 pub fn read_dpms(display_name: &str) -> DpmsState {
-    let paths = [
-        format!("/sys/class/drm/card1-{display_name}/dpms"),
-        format!("/sys/class/drm/card0-{display_name}/dpms"),
-    ];
+    if let Some(dpms) = backend().dpms(display_name) {
+        return dpms;
+    }
+    drm::connector(display_name).map_or(DpmsState::Unknown, |c| c.dpms)
+}
 
-    for path in &paths {
-        if let Ok(content) = fs::read_to_string(path) {
-            return match content.trim() {
-                "On" => DpmsState::On,
-                "Off" => DpmsState::Off,
-                _ => DpmsState::Unknown,
-            };
-        }
+// ---- Display Control ----
+
+// Enables the output in the mode `select_mode` picks for `target`, at
+// `scale` if one is given (a 4K dummy plug next to a 1.5× desktop would
+// otherwise be scaled too, and Sunshine would capture that surface).
+// Mode ids differ between dongles, so we look them up instead of guessing.
+pub fn enable_output(name: &str, target: Option<&ModeTarget>, scale: Option<f64>) -> Result<()> {
+    let displays = get_displays()?;
+    let (display, mode) = pick_mode(&displays, name, target)?;
+    let result = with_retry(&format!("Enabling {name}"), || backend().enable_output(display, mode, scale));
+    topology::manager().invalidate();
+    result
+}
+
+// Enables `enable` and disables `disable` in one backend call — a single
+// kscreen-doctor run instead of one per output. Not retried: on failure
+// the caller can fall back to changing them one at a time.
+pub fn apply_outputs(enable: &[ProfileOutput], disable: &[String]) -> Result<()> {
+    let displays = get_displays()?;
+    let mut changes = Vec::new();
+    for output in enable {
+        let (display, mode) = pick_mode(&displays, &output.name, output.mode.as_ref())?;
+        changes.push(OutputChange::Enable { display, mode, scale: output.scale });
     }
+    changes.extend(disable.iter().map(|name| OutputChange::Disable(name)));
+    let result = backend().apply_changes(&changes);
+    topology::manager().invalidate();
+    result
+}
+
+// `name`'s entry in `displays` and the mode closest to `target`.
+fn pick_mode<'a>(displays: &'a [Display], name: &str, target: Option<&ModeTarget>) -> Result<(&'a Display, &'a Mode)> {
+    let display = displays
+        .iter()
+        .find(|d| d.name == name)
+        .ok_or_else(|| VitaminkError::Parse(format!("Output {name} not found in {} output", backend().name())))?;
+
+    let mode = select_mode(&display.modes, target)
+        .ok_or_else(|| VitaminkError::Parse(format!("Output {name} reports no modes")))?;
 
-    DpmsState::Unknown
+    if let Some(target) = target
+        && (mode.width != target.width || mode.height != target.height)
+    {
+        warn!("{name} has no {target} mode, using closest match");
+    }
+    info!("Using {name} mode {}: {}x{}@{:.2}Hz", mode.id, mode.width, mode.height, mode.refresh);
+    Ok((display, mode))
 }
 
-// ---- Display Control ----
+// What of `enable` and `disable` isn't so in `displays` already: outputs
+// to enable that are off, or on in another mode or scale than they'd be
+// set to, and outputs to disable that are on. Changing an output that's
+// right as it is still makes KWin reconfigure it, which flickers.
+pub fn outstanding(displays: &[Display], enable: &[ProfileOutput], disable: &[String]) -> (Vec<ProfileOutput>, Vec<String>) {
+    let find = |name: &str| displays.iter().find(|d| d.name == name);
+    let enable = enable
+        .iter()
+        .filter(|output| {
+            let Some(display) = find(&output.name).filter(|d| d.state == DisplayState::Enabled) else {
+                return true;
+            };
+            let current = display.modes.iter().find(|m| m.current).map(|m| m.id);
+            let wanted = select_mode(&display.modes, output.mode.as_ref()).map(|m| m.id);
+            let scaled = output.scale.is_none_or(|scale| display.scale.is_some_and(|s| (s - scale).abs() < 0.01));
+            current.is_none() || current != wanted || !scaled
+        })
+        .cloned()
+        .collect();
+    let disable = disable.iter().filter(|name| find(name).is_some_and(|d| d.state == DisplayState::Enabled)).cloned().collect();
+    (enable, disable)
+}
 
-pub fn enable_dummy_plug(name: &str) -> Result<(), String> {
-    let enable_arg = format!("output.{name}.enable");
-    let mode_arg = format!("output.{name}.mode.1");
-    run_kscreen_doctor(&[&enable_arg, &mode_arg])?;
-    Ok(())
+pub fn add_mode(custom: &CustomMode) -> Result<()> {
+    let result = backend().add_mode(custom);
+    topology::manager().invalidate();
+    result
 }
 
-pub fn disable_dummy_plug(name: &str) -> Result<(), String> {
-    let disable_arg = format!("output.{name}.disable");
-    run_kscreen_doctor(&[&disable_arg])?;
-    Ok(())
+pub fn remove_mode(custom: &CustomMode) -> Result<()> {
+    let result = backend().remove_mode(custom);
+    topology::manager().invalidate();
+    result
+}
+
+pub fn disable_output(name: &str) -> Result<()> {
+    let result = with_retry(&format!("Disabling {name}"), || backend().disable_output(name));
+    topology::manager().invalidate();
+    result
+}
+
+// The enabled output with priority 1, which KWin puts the panels on.
+pub fn primary_output() -> Result<Option<String>> {
+    Ok(get_displays()?.into_iter().find(|d| d.state == DisplayState::Enabled && d.priority == Some(1)).map(|d| d.name))
+}
+
+pub fn set_primary(name: &str) -> Result<()> {
+    let result = with_retry(&format!("Making {name} primary"), || backend().set_primary(name));
+    topology::manager().invalidate();
+    result
+}
+
+pub fn apply_layout(layout: &Layout) -> Result<()> {
+    let result = backend().apply_layout(layout);
+    topology::manager().invalidate();
+    result
+}
+
+// Blanks or wakes every output at once, like the power-saving timeout does.
+pub fn set_all_dpms(on: bool) -> Result<()> {
+    let result = backend().set_all_dpms(on);
+    topology::manager().invalidate();
+    result
+}
+
+// Blanks or wakes one output; unlike disabling it, the compositor keeps
+// it in the layout.
+pub fn set_dpms(name: &str, on: bool) -> Result<()> {
+    let result = with_retry(&format!("Turning {name} {}", if on { "on" } else { "off" }), || backend().set_dpms(name, on));
+    topology::manager().invalidate();
+    result
 }
 
 // Checks that a display has an active DRM framebuffer by reading sysfs.
 // Sunshine uses KMS/DRM to capture — it needs `enabled` to be "enabled"
 // at the kernel level, not just in KDE.
 pub fn is_drm_active(name: &str) -> bool {
-    let paths = [
-        format!("/sys/class/drm/card1-{name}/enabled"),
-        format!("/sys/class/drm/card0-{name}/enabled"),
-    ];
-
-    for path in &paths {
-        if let Ok(content) = std::fs::read_to_string(path) {
-            return content.trim() == "enabled";
-        }
-    }
-
-    false
+    drm::connector(name).is_some_and(|c| c.enabled)
 }
 
 // Waits up to `timeout` for DRM to report the display as active.
 // KDE's kscreen-doctor enables the display asynchronously — there's a
 // brief delay before the kernel DRM layer reflects the change.
-pub fn wait_for_drm_active(name: &str, timeout: std::time::Duration) -> Result<(), String> {
-    use std::time::Instant;
+pub fn wait_for_drm_active(name: &str, timeout: Duration) -> Result<()> {
+    wait_for_drm(name, true, timeout)
+}
 
+// The other way round: until the kernel has torn the framebuffer down.
+pub fn wait_for_drm_inactive(name: &str, timeout: Duration) -> Result<()> {
+    wait_for_drm(name, false, timeout)
+}
+
+fn wait_for_drm(name: &str, active: bool, timeout: Duration) -> Result<()> {
     let start = Instant::now();
-    let poll = std::time::Duration::from_millis(500);
+    let poll = Duration::from_millis(500);
 
     while start.elapsed() < timeout {
-        if is_drm_active(name) {
+        if is_drm_active(name) == active {
             return Ok(());
         }
-        std::thread::sleep(poll);
+        thread::sleep(poll);
+    }
+
+    let wanted = if active { "become active" } else { "go away" };
+    Err(VitaminkError::Timeout(format!("Timed out waiting for {name} DRM framebuffer to {wanted}")))
+}
+
+// Set once `wait_for_framebuffer` has said it can't check, so it says it
+// once rather than on every transition.
+static FRAMEBUFFER_UNCHECKED: AtomicBool = AtomicBool::new(false);
+
+// After `wait_for_drm_active`: waits for a frame to be committed to the
+// output, which is when Sunshine stops capturing black. Skipped (Ok) when
+// the kernel's state dump isn't readable (see drm::has_framebuffer).
+pub fn wait_for_framebuffer(name: &str, timeout: Duration) -> Result<()> {
+    let start = Instant::now();
+    loop {
+        match drm::has_framebuffer(name) {
+            None => {
+                if !FRAMEBUFFER_UNCHECKED.swap(true, Ordering::Relaxed) {
+                    info!("Can't read the kernel's DRM state (debugfs), so frames on the Away outputs aren't verified");
+                }
+                debug!("Can't read the DRM state for {name}, not checking its framebuffer");
+                return Ok(());
+            }
+            Some(true) => return Ok(()),
+            Some(false) if start.elapsed() >= timeout => {
+                return Err(VitaminkError::Timeout(format!("Timed out waiting for a frame on {name}")));
+            }
+            Some(false) => thread::sleep(Duration::from_millis(200)),
+        }
+    }
+}
+
+// `vitamink output power-cycle NAME`: turns the output off, waits for the
+// kernel to let go of it, and puts the whole layout back — the software
+// version of replugging a dongle or monitor that's stuck on a black
+// screen. Returns the mode it came back in, checked against the one it
+// had.
+pub fn power_cycle(name: &str, timeout: Duration) -> Result<Mode> {
+    let displays = get_displays()?;
+    let display = displays
+        .iter()
+        .find(|d| d.name == name)
+        .ok_or_else(|| VitaminkError::Parse(format!("No output named {name}")))?;
+    if display.state != DisplayState::Enabled {
+        return Err(VitaminkError::Parse(format!("{name} is off; enable it instead of power-cycling it")));
     }
+    let before = display.modes.iter().find(|m| m.current).cloned();
+    let layout = Layout::from_displays(&displays);
 
-    Err(format!("Timed out waiting for {name} DRM framebuffer to become active"))
+    info!("→ Disabling {name}");
+    disable_output(name)?;
+    info!("→ Waiting for the DRM framebuffer to go away");
+    let teardown = wait_for_drm_inactive(name, timeout);
+    // Even if the kernel never let go, the output must come back on.
+    info!("→ Restoring the layout");
+    layout.restore(name)?;
+    teardown?;
+    info!("→ Waiting for the DRM framebuffer");
+    wait_for_drm_active(name, timeout)?;
+
+    let after = get_displays()?
+        .into_iter()
+        .find(|d| d.name == name && d.state == DisplayState::Enabled)
+        .and_then(|d| d.modes.into_iter().find(|m| m.current))
+        .ok_or_else(|| VitaminkError::Parse(format!("{name} didn't come back on")))?;
+    if let Some(before) = before
+        && (before.width, before.height, before.refresh.round()) != (after.width, after.height, after.refresh.round())
+    {
+        return Err(VitaminkError::Parse(format!(
+            "{name} came back at {}x{}@{:.2} instead of {}x{}@{:.2}",
+            after.width, after.height, after.refresh, before.width, before.height, before.refresh
+        )));
+    }
+    Ok(after)
 }
 
 // ---- Tests ----
 
 #[cfg(test)]
 mod tests {
+    use std::rc::Rc;
+
     use super::*;
+    use crate::mock::FakeRunner;
 
     #[test]
     fn test_strip_ansi() {
@@ -281,7 +1459,7 @@ mod tests {
     #[test]
     fn test_parse_modes() {
         let line = "Modes:  1:1920x1080@60.00*!  2:4096x2160@59.94";
-        let modes = parse_modes(line).unwrap();
+        let modes = parse_modes(line, &mut Vec::new());
         assert_eq!(modes.len(), 2);
         assert_eq!(modes[0].width, 1920);
         assert_eq!(modes[0].height, 1080);
@@ -292,6 +1470,77 @@ mod tests {
         assert!(!modes[1].preferred);
     }
 
+    #[test]
+    fn test_mode_target_from_str() {
+        let target: ModeTarget = "3840x2160@60".parse().unwrap();
+        assert_eq!(target, ModeTarget { width: 3840, height: 2160, refresh: Some(60.0) });
+
+        let target: ModeTarget = "1920x1080".parse().unwrap();
+        assert_eq!(target.refresh, None);
+
+        assert!("4k".parse::<ModeTarget>().is_err());
+        assert!("1920x1080@fast".parse::<ModeTarget>().is_err());
+    }
+
+    #[test]
+    fn test_select_mode() {
+        let modes = parse_modes("Modes:  1:640x480@60.00  2:1920x1080@60.00!  3:3840x2160@30.00  4:3840x2160@60.00  5:2560x1440@120.00", &mut Vec::new());
+
+        // Preferred by default
+        assert_eq!(select_mode(&modes, None).unwrap().id, 2);
+
+        // Exact match
+        let target: ModeTarget = "3840x2160@60".parse().unwrap();
+        assert_eq!(select_mode(&modes, Some(&target)).unwrap().id, 4);
+
+        // Resolution only: fastest refresh
+        let target: ModeTarget = "3840x2160".parse().unwrap();
+        assert_eq!(select_mode(&modes, Some(&target)).unwrap().id, 4);
+
+        // No such resolution: closest one
+        let target: ModeTarget = "2560x1600@120".parse().unwrap();
+        assert_eq!(select_mode(&modes, Some(&target)).unwrap().id, 5);
+
+        assert!(select_mode(&[], None).is_none());
+    }
+
+    #[test]
+    fn test_outstanding() {
+        let output = |name: &str, modes: &str, enabled: bool| Display {
+            index: 0,
+            name: name.into(),
+            uuid: None,
+            state: if enabled { DisplayState::Enabled } else { DisplayState::Disabled },
+            connection: ConnectionState::Connected,
+            modes: parse_modes(modes, &mut Vec::new()),
+            geometry: None,
+            scale: Some(1.0),
+            priority: None,
+            color: Color::default(),
+            vrr: None,
+            rotation: None,
+            overscan: None,
+        };
+        let displays = [
+            output("HDMI-A-1", "Modes:  1:1920x1080@60.00!*  2:3840x2160@60.00", true),
+            output("DP-2", "Modes:  1:2560x1440@144.00!*", true),
+            output("DP-3", "Modes:  1:1920x1080@60.00!", false),
+        ];
+        let away = |mode: Option<&str>| ProfileOutput { name: "HDMI-A-1".into(), mode: mode.map(|m| m.parse().unwrap()), scale: None };
+
+        // On in the mode asked for, and DP-3 off already: nothing to do.
+        let (enable, disable) = outstanding(&displays, &[away(Some("1920x1080@60"))], &["DP-3".to_string()]);
+        assert!(enable.is_empty() && disable.is_empty());
+
+        // Another mode or scale, an output that's on, one that's unknown.
+        let (enable, _) = outstanding(&displays, &[away(Some("3840x2160"))], &[]);
+        assert_eq!(enable.len(), 1);
+        let scaled = ProfileOutput { scale: Some(2.0), ..away(None) };
+        assert_eq!(outstanding(&displays, &[scaled], &[]).0.len(), 1);
+        let (_, disable) = outstanding(&displays, &[], &["DP-2".to_string(), "DP-9".to_string()]);
+        assert_eq!(disable, ["DP-2"]);
+    }
+
     #[test]
     fn test_parse_displays() {
         let input = "\
@@ -308,9 +1557,18 @@ Output: 2 DP-2 other-uuid-here
 \tpriority 1
 \tDisplayPort
 \tModes:  3:3840x2160@240.02*  4:1920x1080@60.00!
-\tGeometry: 0,0 3200x1800";
+\tGeometry: -1920,0 3200x1800
+\tScale: 1.2
+\tRotation: 8
+\tOverscan: 3
+\tHDR: enabled
+\t\tSDR brightness: 200 nits
+\tWide Color Gamut: disabled
+\tMax bits per color: 10
+\tVrr: Automatic";
 
-        let displays = parse_displays(input).unwrap();
+        assert_eq!(sniff_format(input), TextFormat::MultiLine);
+        let displays = parse_text(input, TextFormat::MultiLine).unwrap();
         assert_eq!(displays.len(), 2);
 
         assert_eq!(displays[0].name, "HDMI-A-1");
@@ -323,5 +1581,445 @@ Output: 2 DP-2 other-uuid-here
         assert_eq!(displays[1].connection, ConnectionState::Connected);
         assert_eq!(displays[1].modes.len(), 2);
         assert_eq!(displays[1].modes[0].refresh, 240.02);
+        assert_eq!(displays[1].geometry, Some(Geometry { x: -1920, y: 0, width: 3200, height: 1800 }));
+        assert_eq!(displays[1].scale, Some(1.2));
+        assert_eq!(displays[1].priority, Some(1));
+        assert_eq!(displays[0].scale, None);
+        assert_eq!(displays[1].color, Color { hdr: Some(true), wide_gamut: Some(false), bit_depth: Some(10) });
+        assert_eq!(displays[0].color, Color::default());
+        assert_eq!(displays[1].vrr, Some(Vrr::Automatic));
+        assert_eq!(displays[0].vrr, None);
+        assert_eq!(displays[1].rotation, Some(Rotation::Right));
+        assert_eq!(displays[1].overscan, Some(3));
+        assert_eq!(displays[0].rotation, None);
+    }
+
+    #[test]
+    fn test_parse_plasma5_displays() {
+        let input = "\
+Output: 1 HDMI-A-1 enabled connected priority 2 HDMI Modes: 1:1920x1080@60*! 2:3840x2160@60 Geometry: 2560,0 1920x1080 Scale: 1 Rotation: 1 Overscan: 0 Vrr: incapable RgbRange: unknown
+Output: 2 DP-2 disabled connected priority 1 DisplayPort Modes: 3:2560x1440@144! Geometry: 0,0 2560x1440 Scale: 1.25 Rotation: 1 Overscan: 0 Vrr: Automatic RgbRange: unknown
+";
+        assert_eq!(sniff_format(input), TextFormat::SingleLine);
+        let displays = parse_text(input, TextFormat::SingleLine).unwrap();
+        assert_eq!(displays.len(), 2);
+
+        assert_eq!(displays[0].name, "HDMI-A-1");
+        assert_eq!(displays[0].uuid, None);
+        assert_eq!(displays[0].state, DisplayState::Enabled);
+        assert_eq!(displays[0].connection, ConnectionState::Connected);
+        assert_eq!(displays[0].priority, Some(2));
+        assert_eq!(displays[0].modes.len(), 2);
+        assert!(displays[0].modes[0].current);
+        assert_eq!(displays[0].geometry, Some(Geometry { x: 2560, y: 0, width: 1920, height: 1080 }));
+
+        assert_eq!(displays[1].state, DisplayState::Disabled);
+        assert_eq!(displays[1].scale, Some(1.25));
+        assert_eq!(displays[1].vrr, Some(Vrr::Automatic));
+    }
+
+    #[test]
+    fn test_parse_skips_what_it_cannot_read() {
+        let input = "\
+Output: 1 HDMI-A-1
+\tenabled
+\tconnected
+\tModes:  1:1920x1080@60.00*!  2:3840x2160@fast  3:1280x720  4:1024x768@NaN
+\tScale: sideways
+Output: one DP-2 uuid with spaces
+\tdisconnected
+Output:
+\tenabled";
+        let (displays, warnings) = parse_text_lenient(input, TextFormat::MultiLine);
+        let displays = displays.unwrap();
+        assert_eq!(displays.len(), 2);
+        assert_eq!(displays[0].uuid, None);
+        assert_eq!(displays[0].modes.len(), 1);
+        assert_eq!(displays[0].scale, None);
+        assert_eq!(displays[1].index, 2);
+        assert_eq!(displays[1].uuid.as_deref(), Some("uuid with spaces"));
+        assert_eq!(
+            warnings,
+            [
+                "HDMI-A-1: Invalid refresh: fast",
+                "HDMI-A-1: Invalid mode spec: 1280x720",
+                "HDMI-A-1: Invalid refresh: NaN",
+                "HDMI-A-1: Invalid scale: sideways",
+                "DP-2: invalid index one",
+                "DP-2: neither enabled nor disabled, taking it as disabled",
+                "skipped an output: Invalid display header: Output:",
+            ]
+        );
+
+        // Nothing readable at all is still an error.
+        assert!(parse_text("Output: 1\n\tenabled\n", TextFormat::MultiLine).is_err());
+        assert_eq!(parse_text("", TextFormat::MultiLine).unwrap(), []);
+    }
+
+    #[test]
+    fn test_parse_localized() {
+        // Translated throughout: nothing to go on.
+        let german = "\
+Ausgabe: 1 DP-2 uuid-2
+\taktiviert
+\tverbunden
+\tPriorität 1
+\tModi:  1:2560x1440@143,97*!
+\tSkalierung: 1,25";
+        assert!(parse_text(german, TextFormat::MultiLine).is_err());
+
+        // Only the states translated, and decimal commas.
+        let mixed = "\
+Output: 1 DP-2 uuid-2
+\taktiviert
+\tverbunden
+\tpriority 1
+\tModes:  1:2560x1440@143,97*!  2:1920x1080@60,00
+\tGeometry: 0,0 2560x1440
+\tScale: 1,25";
+        let (displays, warnings) = parse_text_lenient(mixed, TextFormat::MultiLine);
+        let displays = displays.unwrap();
+        assert_eq!(displays[0].state, DisplayState::Disabled);
+        assert_eq!(displays[0].modes[0].refresh, 143.97);
+        assert_eq!(displays[0].scale, Some(1.25));
+        assert_eq!(displays[0].geometry, Some(Geometry { x: 0, y: 0, width: 2560, height: 1440 }));
+        assert_eq!(
+            warnings,
+            ["DP-2: neither enabled nor disabled, taking it as disabled", "DP-2: neither connected nor disconnected, taking it as disconnected"]
+        );
+
+        // Which is why it runs in the C locale.
+        let cmd = kscreen_doctor(&["-o"]);
+        let envs: Vec<(String, Option<String>)> =
+            cmd.get_envs().map(|(k, v)| (k.to_string_lossy().to_string(), v.map(|v| v.to_string_lossy().to_string()))).collect();
+        assert!(envs.contains(&("LC_ALL".to_string(), Some("C".to_string()))));
+        assert!(envs.contains(&("LANGUAGE".to_string(), None)));
+    }
+
+    // ---- Generated Cases ----
+    //
+    // No property-testing crate: a seeded generator makes the same cases
+    // on every run, so a failure can be reproduced.
+
+    // xorshift64*.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+            items[self.below(items.len())]
+        }
+
+        fn maybe<T>(&mut self, value: impl FnOnce(&mut Self) -> T) -> Option<T> {
+            if self.next().is_multiple_of(2) { Some(value(self)) } else { None }
+        }
+    }
+
+    fn random_displays(rng: &mut Rng, format: TextFormat) -> Vec<Display> {
+        let multi_line = format == TextFormat::MultiLine;
+        (0..1 + rng.below(4))
+            .map(|i| Display {
+                index: i as u32 + 1,
+                name: format!("{}-{i}", rng.pick(&["DP", "HDMI-A", "eDP", "DVI-D"])),
+                uuid: multi_line.then(|| rng.pick(&["b5f0c9a2-2c4e-4d6e-9a51-0f8e3c1d2b7a", "0", "two words"]).to_string()),
+                state: rng.pick(&[DisplayState::Enabled, DisplayState::Disabled]),
+                connection: rng.pick(&[ConnectionState::Connected, ConnectionState::Disconnected]),
+                modes: (0..rng.below(5))
+                    .map(|id| Mode {
+                        id: id as u32 + 1,
+                        width: rng.pick(&[640, 1920, 2560, 3840]),
+                        height: rng.pick(&[480, 1080, 1440, 2160]),
+                        refresh: rng.pick(&[23.976, 59.94, 60.0, 144.0, 240.02]),
+                        preferred: rng.below(3) == 0,
+                        current: rng.below(3) == 0,
+                    })
+                    .collect(),
+                geometry: rng.maybe(|rng| Geometry {
+                    x: rng.pick(&[-1920, 0, 2560]),
+                    y: rng.pick(&[-1080, 0, 1440]),
+                    width: rng.pick(&[1920, 2560]),
+                    height: rng.pick(&[1080, 1440]),
+                }),
+                scale: rng.maybe(|rng| rng.pick(&[1.0, 1.25, 1.5, 2.0])),
+                priority: rng.maybe(|rng| rng.pick(&[0, 1, 2, 3])),
+                // Plasma 5 printed none of these.
+                color: match multi_line {
+                    true => Color {
+                        hdr: rng.maybe(|rng| rng.pick(&[true, false])),
+                        wide_gamut: rng.maybe(|rng| rng.pick(&[true, false])),
+                        bit_depth: rng.maybe(|rng| rng.pick(&[8, 10, 12])),
+                    },
+                    false => Color::default(),
+                },
+                vrr: rng.maybe(|rng| rng.pick(&[Vrr::Never, Vrr::Always, Vrr::Automatic])),
+                rotation: rng.maybe(|rng| rng.pick(&[Rotation::Normal, Rotation::Left, Rotation::Inverted, Rotation::Right])),
+                overscan: rng.maybe(|rng| rng.pick(&[0, 3, 10])),
+            })
+            .collect()
+    }
+
+    // The way kscreen-doctor prints `displays`.
+    fn render_text(displays: &[Display], format: TextFormat) -> String {
+        let mut out = String::new();
+        for d in displays {
+            let switch = |on: bool| if on { "enabled" } else { "disabled" };
+            let mut fields = vec![
+                switch(d.state == DisplayState::Enabled).to_string(),
+                if d.connection == ConnectionState::Connected { "connected" } else { "disconnected" }.to_string(),
+            ];
+            fields.extend(d.priority.map(|p| format!("priority {p}")));
+            let modes: Vec<String> = d
+                .modes
+                .iter()
+                .map(|m| {
+                    let flags = format!("{}{}", if m.current { "*" } else { "" }, if m.preferred { "!" } else { "" });
+                    format!("{}:{}x{}@{}{flags}", m.id, m.width, m.height, m.refresh)
+                })
+                .collect();
+            fields.push(format!("Modes: {}", modes.join(" ")).trim_end().to_string());
+            fields.extend(d.geometry.map(|g| format!("Geometry: {},{} {}x{}", g.x, g.y, g.width, g.height)));
+            fields.extend(d.scale.map(|s| format!("Scale: {s}")));
+            fields.extend(d.rotation.map(|r| format!("Rotation: {}", [1, 2, 4, 8][r as usize])));
+            fields.extend(d.overscan.map(|o| format!("Overscan: {o}")));
+            fields.extend(d.vrr.map(|v| format!("Vrr: {}", v.setting())));
+            fields.extend(d.color.hdr.map(|on| format!("HDR: {}", switch(on))));
+            fields.extend(d.color.wide_gamut.map(|on| format!("Wide Color Gamut: {}", switch(on))));
+            fields.extend(d.color.bit_depth.map(|bits| format!("Max bits per color: {bits}")));
+            match format {
+                TextFormat::SingleLine => out += &format!("Output: {} {} {}\n", d.index, d.name, fields.join(" ")),
+                TextFormat::MultiLine => {
+                    out += &format!("Output: {} {} {}\n", d.index, d.name, d.uuid.as_deref().unwrap_or_default());
+                    for field in fields {
+                        out += &format!("\t{field}\n");
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_parse_generated() {
+        let mut rng = Rng(0x5eed);
+        for format in [TextFormat::SingleLine, TextFormat::MultiLine] {
+            for _ in 0..300 {
+                let displays = random_displays(&mut rng, format);
+                let text = render_text(&displays, format);
+                let (parsed, warnings) = parse_text_lenient(&text, format);
+                assert_eq!(parsed.unwrap(), displays, "{text}");
+                assert!(warnings.is_empty(), "{text}: {warnings:?}");
+
+                // A broken mode list costs its own output those modes and
+                // leaves the others alone.
+                let broken = rng.below(displays.len());
+                let mut nth = 0;
+                let text: String = text
+                    .lines()
+                    .map(|line| {
+                        let mut line = line.to_string();
+                        if line.starts_with("Output:") {
+                            nth += 1;
+                        }
+                        if nth == broken + 1 {
+                            line = line.replace("Modes: ", "Modes: 9:x@ 10:1x1@inf ");
+                        }
+                        line + "\n"
+                    })
+                    .collect();
+                let parsed = parse_text(&text, format).unwrap();
+                for (i, (got, want)) in parsed.iter().zip(&displays).enumerate() {
+                    assert_eq!(got.modes, want.modes, "{text}");
+                    if i != broken {
+                        assert_eq!(got, want, "{text}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_mangled() {
+        const JUNK: &[&str] = &["x", "@", ":", "*", "!", ",", " ", "\t", "\n", "Output: ", "Modes: ", "priority ", "-1", "NaN", "inf", "4294967296", "é"];
+        let mut rng = Rng(0xfeed);
+        for format in [TextFormat::SingleLine, TextFormat::MultiLine] {
+            for _ in 0..2000 {
+                let mut text: Vec<char> = render_text(&random_displays(&mut rng, format), format).chars().collect();
+                for _ in 0..1 + rng.below(6) {
+                    let at = rng.below(text.len() + 1);
+                    match rng.below(3) {
+                        0 => {
+                            let end = (at + rng.below(8)).min(text.len());
+                            text.drain(at..end);
+                        }
+                        1 => {
+                            let junk = rng.pick(JUNK);
+                            text.splice(at..at, junk.chars());
+                        }
+                        _ => {
+                            let end = (at + rng.below(20)).min(text.len());
+                            let copy: Vec<char> = text[at..end].to_vec();
+                            text.splice(at..at, copy);
+                        }
+                    }
+                }
+                let text: String = text.into_iter().collect();
+                // Never a panic; whatever comes out is usable.
+                let (parsed, _) = parse_text_lenient(&text, format);
+                for display in parsed.unwrap_or_default() {
+                    assert!(!display.name.is_empty(), "{text}");
+                    assert!(display.modes.iter().all(|m| m.refresh.is_finite()), "{text}");
+                    assert!(display.scale.is_none_or(|s| s.is_finite() && s > 0.0), "{text}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_kscreen_version() {
+        assert_eq!(parse_version("kscreen-doctor 6.1.4\n"), Some((6, 1)));
+        assert_eq!(parse_version("kscreen-doctor 5.27.11\n"), Some((5, 27)));
+        assert_eq!(parse_version("kscreen-doctor 1.0\n"), None);
+        assert_eq!(parse_version(""), None);
+
+        assert_eq!(text_format(Some((6, 2)), "").unwrap(), TextFormat::MultiLine);
+        assert_eq!(text_format(Some((5, 27)), "").unwrap(), TextFormat::SingleLine);
+        let e = text_format(Some((5, 18)), "").unwrap_err().to_string();
+        assert!(e.contains("kscreen-doctor 5.18 isn't supported"), "{e}");
+    }
+
+    #[test]
+    fn test_kscreen_backend() {
+        let json = r#"{"outputs": [{
+            "id": 1, "name": "HDMI-A-1", "enabled": false, "connected": true,
+            "modes": [
+                {"id": "1", "refreshRate": 60.0, "size": {"width": 3840, "height": 2160}},
+                {"id": "2", "refreshRate": 60.0, "size": {"width": 1920, "height": 1080}}
+            ]
+        }]}"#;
+        let fake = Rc::new(FakeRunner::new());
+        fake.respond("kscreen-doctor -j", 0, json);
+        fake.respond("kscreen-doctor output.", 0, "");
+
+        process::with_runner(fake.clone(), || {
+            let displays = KscreenBackend.get_displays().unwrap();
+            assert_eq!(displays[0].name, "HDMI-A-1");
+            KscreenBackend.enable_output(&displays[0], &displays[0].modes[1], None).unwrap();
+            KscreenBackend.enable_output(&displays[0], &displays[0].modes[0], Some(1.0)).unwrap();
+            KscreenBackend.disable_output("HDMI-A-1").unwrap();
+            let batch = [
+                OutputChange::Enable { display: &displays[0], mode: &displays[0].modes[1], scale: None },
+                OutputChange::Disable("DP-2"),
+            ];
+            KscreenBackend.apply_changes(&batch).unwrap();
+        });
+        // The session lookup may have asked loginctl first.
+        let calls: Vec<String> = fake.calls().into_iter().filter(|c| c.starts_with("kscreen-doctor")).collect();
+        assert_eq!(
+            calls,
+            [
+                "kscreen-doctor -j",
+                "kscreen-doctor output.HDMI-A-1.enable output.HDMI-A-1.mode.1920x1080@60",
+                "kscreen-doctor output.HDMI-A-1.enable output.HDMI-A-1.mode.3840x2160@60 output.HDMI-A-1.scale.1",
+                "kscreen-doctor output.HDMI-A-1.disable",
+                "kscreen-doctor output.HDMI-A-1.enable output.HDMI-A-1.mode.1920x1080@60 output.DP-2.disable",
+            ]
+        );
+
+        fake.respond("kscreen-doctor output.", 1, "");
+        assert!(process::with_runner(fake, || KscreenBackend.disable_output("HDMI-A-1")).is_err());
+    }
+
+    #[test]
+    fn test_socket_in() {
+        let dir = std::env::temp_dir().join(format!("vitamink-runtime-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        assert_eq!(socket_in(&dir, None), None);
+        assert_eq!(socket_in(&dir, Some("wayland-1")), None);
+
+        fs::write(dir.join("wayland-1"), "").unwrap();
+        fs::write(dir.join("wayland-1.lock"), "").unwrap();
+        assert_eq!(socket_in(&dir, None), Some(dir.join("wayland-1")));
+        assert_eq!(socket_in(&dir, Some("wayland-0")), None);
+        assert_eq!(socket_in(&dir, Some(dir.join("wayland-1").to_str().unwrap())), Some(dir.join("wayland-1")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_kscreen_dpms_args() {
+        let input = "Output: 1 HDMI-A-1 uuid-1\n\tenabled\n\tconnected\n\tModes:  1:1920x1080@60.00*!\nOutput: 2 DP-2 uuid-2\n\tenabled\n\tconnected\n\tModes:  1:3840x2160@60.00*!\nOutput: 3 DP-3 uuid-3\n\tdisabled\n\tconnected\n";
+        let displays = parse_text(input, TextFormat::MultiLine).unwrap();
+        assert_eq!(kscreen_dpms_args(&displays, "HDMI-A-1", false), ["--dpms", "off", "--dpms-excluded", "DP-2"]);
+        assert_eq!(kscreen_dpms_args(&displays, "DP-2", true), ["--dpms", "on", "--dpms-excluded", "HDMI-A-1"]);
+    }
+
+    #[test]
+    fn test_output_block() {
+        let input = "Output: 1 HDMI-A-1 uuid-1\n\tenabled\nOutput: 2 DP-2 uuid-2\n\tdisabled\n\tVrr: incapable\n";
+        assert_eq!(output_block(input, "DP-2"), vec!["Output: 2 DP-2 uuid-2", "\tdisabled", "\tVrr: incapable"]);
+        assert_eq!(output_block(input, "HDMI-A-1").len(), 2);
+        assert!(output_block(input, "DP-1").is_empty());
+    }
+
+    #[test]
+    fn test_parse_geometry_invalid() {
+        assert!(parse_geometry("Geometry: 0,0").is_err());
+        assert!(parse_geometry("Geometry: a,b 1x1").is_err());
+    }
+
+    #[test]
+    fn test_is_internal_panel() {
+        assert!(is_internal_panel("eDP-1"));
+        assert!(is_internal_panel("LVDS-1"));
+        assert!(!is_internal_panel("DP-2"));
+        assert!(!is_internal_panel("HDMI-A-1"));
+    }
+
+    #[test]
+    fn test_pick_wayland_socket() {
+        let names: Vec<String> =
+            ["wayland-1.lock", "wayland-1", "wayland-3", "pipewire-0", "bus"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(pick_wayland_socket(&names).as_deref(), Some("wayland-1"));
+        assert_eq!(pick_wayland_socket(&[]), None);
+    }
+
+    #[test]
+    fn test_detect_backend() {
+        assert_eq!(detect_backend("KDE", "wayland", true), BackendKind::Kscreen);
+        assert_eq!(detect_backend("ubuntu:GNOME", "wayland", true), BackendKind::Mutter);
+        assert_eq!(detect_backend("Hyprland", "", true), BackendKind::WlrRandr);
+        assert_eq!(detect_backend("sway", "wayland", true), BackendKind::WlrRandr);
+        assert_eq!(detect_backend("", "", false), BackendKind::Kscreen);
+        assert_eq!(detect_backend("KDE", "x11", false), BackendKind::Xrandr);
+    }
+
+    #[test]
+    fn test_kscreen_mode() {
+        let mode = |id, width, height, refresh| Mode { id, width, height, refresh, preferred: false, current: false };
+        let modes = vec![mode(1, 3840, 2160, 60.0), mode(2, 1920, 1080, 60.0), mode(3, 1920, 1080, 59.94)];
+        assert_eq!(kscreen_mode(&modes, &modes[0]), "3840x2160@60");
+        // 60 and 59.94 both round to "1920x1080@60"
+        assert_eq!(kscreen_mode(&modes, &modes[2]), "3");
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryConfig { jitter: 0.0, ..RetryConfig::default() };
+        assert_eq!(backoff(&policy, 0, 0.5), Duration::from_millis(250));
+        assert_eq!(backoff(&policy, 2, 0.5), Duration::from_secs(1));
+        assert_eq!(backoff(&policy, 10, 0.5), Duration::from_secs(4));
+
+        let policy = RetryConfig { jitter: 0.2, ..policy };
+        assert_eq!(backoff(&policy, 0, 0.0), Duration::from_millis(200));
+        assert_eq!(backoff(&policy, 0, 0.5), Duration::from_millis(250));
     }
 }
@@ -0,0 +1,108 @@
+// src/dock.rs — Holding still while a dock connects or disconnects
+//
+// Plugging a laptop into a dock brings two or three monitors up at once,
+// and the compositor spends several seconds shuffling them: DPMS flickers,
+// outputs get renamed, the "main display" briefly disappears. Polling
+// through that produced a burst of Away/AtDesk transitions.
+//
+// The tracker compares the set of connected connectors on each poll. A
+// change touching two or more outputs at once is treated as a dock event:
+// automation pauses until nothing has changed for `dock_settle`, then the
+// daemon re-resolves its displays and carries on.
+
+use std::collections::BTreeSet;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, PartialEq)]
+pub enum DockEvent {
+    // Nothing unusual; carry on polling.
+    Stable,
+    // A dock event just started — the changes are included for the log.
+    Started { added: Vec<String>, removed: Vec<String> },
+    // Still inside the settle window.
+    Settling,
+    // The window passed without further changes.
+    Settled,
+}
+
+pub struct DockTracker {
+    settle: Duration,
+    connected: Option<BTreeSet<String>>,
+    settling_until: Option<Instant>,
+}
+
+impl DockTracker {
+    pub fn new(settle: Duration) -> Self {
+        Self { settle, connected: None, settling_until: None }
+    }
+
+    pub fn observe(&mut self, connected: BTreeSet<String>, now: Instant) -> DockEvent {
+        let Some(previous) = self.connected.replace(connected.clone()) else {
+            return DockEvent::Stable;
+        };
+
+        let added: Vec<String> = connected.difference(&previous).cloned().collect();
+        let removed: Vec<String> = previous.difference(&connected).cloned().collect();
+        let changed = !added.is_empty() || !removed.is_empty();
+
+        if let Some(until) = self.settling_until {
+            // Any change inside the window restarts it.
+            if changed {
+                self.settling_until = Some(now + self.settle);
+                return DockEvent::Settling;
+            }
+            if now < until {
+                return DockEvent::Settling;
+            }
+            self.settling_until = None;
+            return DockEvent::Settled;
+        }
+
+        if !self.settle.is_zero() && added.len() + removed.len() >= 2 {
+            self.settling_until = Some(now + self.settle);
+            return DockEvent::Started { added, removed };
+        }
+
+        DockEvent::Stable
+    }
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_observe() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut tracker = DockTracker::new(Duration::from_secs(10));
+
+        assert_eq!(tracker.observe(set(&["eDP-1"]), at(0)), DockEvent::Stable);
+        // A single monitor coming and going is not a dock.
+        assert_eq!(tracker.observe(set(&["eDP-1", "HDMI-A-1"]), at(5)), DockEvent::Stable);
+
+        assert_eq!(
+            tracker.observe(set(&["eDP-1", "HDMI-A-1", "DP-3", "DP-4"]), at(10)),
+            DockEvent::Started { added: vec!["DP-3".into(), "DP-4".into()], removed: vec![] }
+        );
+        assert_eq!(tracker.observe(set(&["eDP-1", "HDMI-A-1", "DP-3", "DP-5"]), at(15)), DockEvent::Settling);
+        // Restarted at 15s, so still settling at 20s.
+        assert_eq!(tracker.observe(set(&["eDP-1", "HDMI-A-1", "DP-3", "DP-5"]), at(20)), DockEvent::Settling);
+        assert_eq!(tracker.observe(set(&["eDP-1", "HDMI-A-1", "DP-3", "DP-5"]), at(25)), DockEvent::Settled);
+        assert_eq!(tracker.observe(set(&["eDP-1", "HDMI-A-1", "DP-3", "DP-5"]), at(30)), DockEvent::Stable);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut tracker = DockTracker::new(Duration::ZERO);
+        let now = Instant::now();
+        tracker.observe(set(&["eDP-1"]), now);
+        assert_eq!(tracker.observe(set(&["DP-3", "DP-4"]), now), DockEvent::Stable);
+    }
+}
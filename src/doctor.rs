@@ -0,0 +1,277 @@
+// src/doctor.rs — `vitamink doctor`: is this machine set up to work?
+//
+// Runs the things the daemon depends on once, in the order they'd fail,
+// and says what to do about each one that doesn't work:
+//
+//   ✓ kscreen-doctor lists outputs — 3 outputs
+//   ✗ Dummy plug HDMI-A-1 connected — the kernel reports it disconnected
+//       → Seat the plug (or its adapter) firmly; `vitamink displays` shows what's connected
+//
+// The command exits nonzero if any check failed, so it also works in
+// scripts.
+
+use std::fs;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+
+use crate::config::{Config, SeatConfig};
+use crate::display::{self, Display};
+use crate::drm::{self, ConnectorStatus};
+use crate::dummy;
+use crate::power;
+use crate::error::VitaminkError;
+use crate::layout;
+use crate::presence;
+use crate::seat;
+use crate::services::{self, Manager};
+use crate::sunshine::encoder;
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Pass(String),
+    Fail { problem: String, hint: String },
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Check {
+    pub name: String,
+    pub outcome: Outcome,
+}
+
+impl Check {
+    pub fn pass(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self { name: name.into(), outcome: Outcome::Pass(detail.into()) }
+    }
+
+    pub fn fail(name: impl Into<String>, problem: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self { name: name.into(), outcome: Outcome::Fail { problem: problem.into(), hint: hint.into() } }
+    }
+
+    pub fn failed(&self) -> bool {
+        matches!(self.outcome, Outcome::Fail { .. })
+    }
+}
+
+pub fn run(config: &Config) -> Vec<Check> {
+    let mut checks = vec![wayland_socket()];
+
+    let displays = display::get_displays();
+    checks.push(list_outputs(&displays));
+    if let Ok(displays) = &displays
+        && !displays.is_empty()
+    {
+        checks.push(configured_outputs(config, displays));
+    }
+
+    checks.push(drm_sysfs());
+    checks.push(dummy_plug(config));
+    if services::sunshine(&config.services).is_some()
+        && let Ok(name) = dummy::resolve(config)
+        && let Some(result) = encoder::check_adapter(&config.sunshine.config_file, &name)
+    {
+        checks.push(match result {
+            Ok(found) => Check::pass("Sunshine encodes on the dummy plug's GPU", found),
+            Err(problem) => Check::fail("Sunshine encodes on the dummy plug's GPU", problem, "Point adapter_name in sunshine.conf at the GPU the plug is on"),
+        });
+    }
+    if config.seat.seat.is_some() || !config.seat.session_types.is_empty() {
+        checks.push(login_session(config));
+    }
+    if config.power.profile.is_some() {
+        checks.push(power_profiles());
+    }
+    if let Some(name) = &config.desk_layout {
+        checks.push(desk_layout(name));
+    }
+    checks.extend(service_units(config));
+    checks
+}
+
+// Each `[[services]]` unit is known to its manager.
+pub fn service_units(config: &Config) -> Vec<Check> {
+    config
+        .services
+        .iter()
+        .map(|service| {
+            let name = format!("{} unit installed", service.unit);
+            if services::is_installed(service) {
+                Check::pass(name, "loaded")
+            } else if service.manager == Manager::Process {
+                Check::fail(name, "its command can't be found", "Install it or fix `command` under [[services]]")
+            } else if service.manager == Manager::Systemd {
+                Check::fail(
+                    name,
+                    "systemd doesn't know it",
+                    "Install it or fix `unit` under [[services]]; `systemctl --user list-unit-files` shows what exists",
+                )
+            } else {
+                Check::fail(name, format!("{} doesn't know it", service.manager), "Set up the service or fix `unit` under [[services]]")
+            }
+        })
+        .collect()
+}
+
+pub fn render(checks: &[Check]) -> String {
+    let mut out = String::new();
+    for check in checks {
+        match &check.outcome {
+            Outcome::Pass(detail) => out += &format!("✓ {} — {detail}\n", check.name),
+            Outcome::Fail { problem, hint } => out += &format!("✗ {} — {problem}\n    → {hint}\n", check.name),
+        }
+    }
+    let failed = checks.iter().filter(|c| c.failed()).count();
+    out += &match failed {
+        0 => format!("\nAll {} checks passed\n", checks.len()),
+        n => format!("\n{n} of {} checks failed\n", checks.len()),
+    };
+    out
+}
+
+// ---- Checks ----
+
+fn wayland_socket() -> Check {
+    let name = "Wayland socket reachable";
+    let socket = display::wayland_env().into_iter().find(|(key, _)| *key == "WAYLAND_DISPLAY").map(|(_, v)| v).unwrap_or_default();
+    let path = match std::env::var_os("XDG_RUNTIME_DIR") {
+        _ if socket.starts_with('/') => PathBuf::from(&socket),
+        Some(dir) => PathBuf::from(dir).join(&socket),
+        None => {
+            return Check::fail(name, "XDG_RUNTIME_DIR is not set", "Run inside your login session (a systemd user service is fine)");
+        }
+    };
+    match UnixStream::connect(&path) {
+        Ok(_) => Check::pass(name, path.display().to_string()),
+        Err(e) => Check::fail(
+            name,
+            format!("{}: {e}", path.display()),
+            "Set wayland_display in the config, or run `systemctl --user import-environment WAYLAND_DISPLAY` in the session",
+        ),
+    }
+}
+
+fn list_outputs(displays: &Result<Vec<Display>, VitaminkError>) -> Check {
+    let backend = display::backend().name();
+    let name = format!("{backend} lists outputs");
+    match displays {
+        Ok(displays) if displays.is_empty() => {
+            Check::fail(name, "no outputs reported", "Is the compositor running and the GPU driver loaded?")
+        }
+        Ok(displays) => Check::pass(name, format!("{} outputs", displays.len())),
+        Err(e @ VitaminkError::CommandNotFound { .. }) => {
+            Check::fail(name, e.to_string(), format!("Install {backend}, or pick another display_backend in the config"))
+        }
+        Err(e @ VitaminkError::Parse(_)) => Check::fail(
+            name,
+            e.to_string(),
+            "Its output changed format; `vitamink ctl diagnostics` collects what's needed for a bug report",
+        ),
+        Err(e) => Check::fail(name, e.to_string(), format!("Try running {backend} yourself from the same session")),
+    }
+}
+
+pub fn configured_outputs(config: &Config, displays: &[Display]) -> Check {
+    let name = "Configured outputs exist";
+    let problems = config.check_outputs(displays);
+    if problems.is_empty() {
+        return Check::pass(name, "all found");
+    }
+    Check::fail(
+        name,
+        problems.join("; "),
+        "Fix the names in the config (see `vitamink displays`) or add them to allow_missing",
+    )
+}
+
+fn drm_sysfs() -> Check {
+    let name = "DRM connectors readable";
+    if let Err(e) = fs::read_dir("/sys/class/drm") {
+        return Check::fail(name, format!("/sys/class/drm: {e}"), "Is sysfs mounted and a DRM driver loaded?");
+    }
+    match drm::scan().len() {
+        0 => Check::fail(name, "no connectors under /sys/class/drm", "Is the GPU driver loaded? `lsmod | grep drm` should list it"),
+        n => Check::pass(name, format!("{n} connectors")),
+    }
+}
+
+fn dummy_plug(config: &Config) -> Check {
+    let name = match dummy::resolve(config) {
+        Ok(name) => name,
+        Err(e) => {
+            return Check::fail(
+                "Dummy plug connected",
+                e.to_string(),
+                "Connect the plug, or set dummy_plug to its connector name",
+            );
+        }
+    };
+    let check = format!("Dummy plug {name} connected");
+    match drm::connector(&name).map(|c| c.status) {
+        Some(ConnectorStatus::Connected) => match drm::gpu_of(&name) {
+            Some(gpu) => Check::pass(check, format!("connected, on {gpu}")),
+            None => Check::pass(check, "connected"),
+        },
+        Some(_) => Check::fail(
+            check,
+            "the kernel reports it disconnected",
+            "Seat the plug (or its adapter) firmly; `vitamink displays` shows what's connected",
+        ),
+        None => Check::fail(
+            check,
+            format!("no connector named {name} under /sys/class/drm"),
+            "Fix dummy_plug in the config; `vitamink displays` lists the names",
+        ),
+    }
+}
+
+// Whether [seat] lets our session go Away; other users aside, since
+// they come and go.
+fn login_session(config: &Config) -> Check {
+    let name = "Login session matches [seat]";
+    let restrictions = SeatConfig { yield_to_other_users: false, ..config.seat.clone() };
+    let found = presence::system_bus().and_then(|conn| Ok((seat::sessions(&conn)?, presence::display_session(&conn)?.0)));
+    match found {
+        Ok((sessions, ours)) => match seat::hold_reason(&restrictions, &sessions, Some(&ours), 0) {
+            None => Check::pass(name, format!("session {ours}")),
+            Some(reason) => Check::fail(name, reason, "Fix [seat] in the config; `loginctl list-sessions` shows each session's seat and type"),
+        },
+        Err(e) => Check::fail(name, e.to_string(), "Run inside your graphical session; [seat] needs logind to find it"),
+    }
+}
+
+fn desk_layout(name: &str) -> Check {
+    let check = format!("Layout \"{name}\" saved");
+    match layout::load_preset(name) {
+        Ok(preset) => Check::pass(check, format!("{} outputs", preset.outputs.len())),
+        Err(e) => Check::fail(check, e.to_string(), format!("Run `vitamink layout save {name}` with the desk set up, or unset desk_layout")),
+    }
+}
+
+fn power_profiles() -> Check {
+    let name = "power-profiles-daemon reachable";
+    match power::active_profile() {
+        Ok(profile) => Check::pass(name, format!("{profile} now")),
+        Err(e) => Check::fail(name, e.to_string(), "Install power-profiles-daemon, or unset [power] profile"),
+    }
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let checks = vec![
+            Check::pass("DRM connectors readable", "4 connectors"),
+            Check::fail("Dummy plug HDMI-A-1 connected", "the kernel reports it disconnected", "Seat the plug"),
+        ];
+        assert_eq!(
+            render(&checks),
+            "✓ DRM connectors readable — 4 connectors\n\
+             ✗ Dummy plug HDMI-A-1 connected — the kernel reports it disconnected\n    → Seat the plug\n\
+             \n1 of 2 checks failed\n"
+        );
+        assert!(render(&checks[..1]).ends_with("\nAll 1 checks passed\n"));
+    }
+}
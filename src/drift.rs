@@ -0,0 +1,150 @@
+// src/drift.rs — Changes made behind the daemon's back while Away
+//
+// Each poll while Away compares what going Away set up with what's so:
+// the Away outputs on in their mode and scale, the outputs a profile
+// turns off still off, and the services running. Turning the dummy plug
+// off with kscreen-doctor or stopping Sunshine by hand makes them differ,
+// and `on_drift` says what to do then:
+//
+//   on_drift = "adopt"     # go along with it, and say so in the log
+//   on_drift = "reapply"   # set Away up again, like `on_error = "reapply"`
+//   on_drift = "pause"     # go along with it, notify, and stop switching
+//
+// What's adopted is left alone until the next transition: it isn't
+// reported again, and a reconnect doesn't turn an adopted output back on.
+// "pause" also holds the inhibitor "drift" (see inhibit.rs), so presence
+// doesn't switch on top of whatever is being done by hand;
+// `vitamink inhibit --release drift` lets it carry on.
+//
+// At the desk there's nothing of Away's to keep up, so nothing to drift
+// from: undoing a step is the same whether or not it was undone by hand.
+
+use std::fmt;
+
+use serde::Deserialize;
+
+use crate::config::ProfileOutput;
+use crate::display::{self, Display};
+
+// The name "pause" holds its inhibitor under.
+pub const INHIBITOR: &str = "drift";
+
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnDrift {
+    #[default]
+    Adopt,
+    Reapply,
+    Pause,
+}
+
+// What isn't the way going Away left it, by name.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Drift {
+    // Away outputs that are off, or in another mode or scale.
+    pub outputs: Vec<String>,
+    // Outputs Away turned off that are on again.
+    pub enabled: Vec<String>,
+    // Services that aren't running.
+    pub stopped: Vec<String>,
+}
+
+impl Drift {
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty() && self.enabled.is_empty() && self.stopped.is_empty()
+    }
+
+    // Takes `other` on, so it's left out of later comparisons.
+    pub fn adopt(&mut self, other: &Drift) {
+        for (mine, theirs) in [(&mut self.outputs, &other.outputs), (&mut self.enabled, &other.enabled), (&mut self.stopped, &other.stopped)] {
+            for name in theirs {
+                if !mine.contains(name) {
+                    mine.push(name.clone());
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let changes: Vec<String> = self
+            .outputs
+            .iter()
+            .map(|name| format!("{name} changed"))
+            .chain(self.enabled.iter().map(|name| format!("{name} back on")))
+            .chain(self.stopped.iter().map(|unit| format!("{unit} stopped")))
+            .collect();
+        write!(f, "{}", changes.join(", "))
+    }
+}
+
+// Compares the outputs Away turns on (`enable`) and off (`disable`), and
+// each service with whether it's running, against what's so. Whatever is
+// in `adopted` already doesn't count.
+pub fn find(displays: &[Display], enable: &[ProfileOutput], disable: &[String], services: &[(String, bool)], adopted: &Drift) -> Drift {
+    let (outputs, enabled) = display::outstanding(displays, enable, disable);
+    Drift {
+        outputs: outputs.into_iter().map(|o| o.name).filter(|name| !adopted.outputs.contains(name)).collect(),
+        enabled: enabled.into_iter().filter(|name| !adopted.enabled.contains(name)).collect(),
+        stopped: services
+            .iter()
+            .filter(|(unit, active)| !active && !adopted.stopped.contains(unit))
+            .map(|(unit, _)| unit.clone())
+            .collect(),
+    }
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::{Color, ConnectionState, DisplayState, Mode};
+
+    fn output(name: &str, enabled: bool) -> Display {
+        Display {
+            index: 0,
+            name: name.into(),
+            uuid: None,
+            state: if enabled { DisplayState::Enabled } else { DisplayState::Disabled },
+            connection: ConnectionState::Connected,
+            modes: vec![Mode { id: 1, width: 1920, height: 1080, refresh: 60.0, preferred: true, current: enabled }],
+            geometry: None,
+            scale: Some(1.0),
+            priority: None,
+            color: Color::default(),
+            vrr: None,
+            rotation: None,
+            overscan: None,
+        }
+    }
+
+    #[test]
+    fn test_find() {
+        let enable = [ProfileOutput { name: "HDMI-A-1".into(), mode: None, scale: None }];
+        let disable = ["DP-2".to_string()];
+        let services = [("sunshine.service".to_string(), true)];
+
+        // As Away left it.
+        let away = [output("HDMI-A-1", true), output("DP-2", false)];
+        assert!(find(&away, &enable, &disable, &services, &Drift::default()).is_empty());
+
+        // The dummy plug turned off and the desk monitor on by hand, and
+        // Sunshine stopped.
+        let by_hand = [output("HDMI-A-1", false), output("DP-2", true)];
+        let stopped = [("sunshine.service".to_string(), false)];
+        let drift = find(&by_hand, &enable, &disable, &stopped, &Drift::default());
+        assert_eq!(drift.outputs, ["HDMI-A-1"]);
+        assert_eq!(drift.enabled, ["DP-2"]);
+        assert_eq!(drift.stopped, ["sunshine.service"]);
+        assert_eq!(drift.to_string(), "HDMI-A-1 changed, DP-2 back on, sunshine.service stopped");
+
+        // Once adopted, it's not found again.
+        let mut adopted = Drift::default();
+        adopted.adopt(&drift);
+        adopted.adopt(&drift);
+        assert_eq!(adopted, drift);
+        assert!(find(&by_hand, &enable, &disable, &stopped, &adopted).is_empty());
+    }
+}
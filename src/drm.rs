@@ -0,0 +1,609 @@
+// src/drm.rs — The kernel's view of the connectors, from /sys/class/drm
+//
+// Each connector directory (e.g. `card1-DP-2`) has `status` (connected,
+// disconnected or unknown), `enabled` and `dpms`. The kernel updates them
+// the moment a cable or KVM moves; kscreen-doctor can trail by seconds
+// during a hotplug storm, so connection changes are read from here.
+//
+// The kernel doesn't keep a per-connector hotplug count, so
+// `HotplugCounter` counts the status flips it sees between polls.
+//
+// After a suspend/resume (nvidia-suspend) or a driver reload, connector
+// state can be stale and mode ids renumbered. `GpuWatch` notices both: a
+// reloaded driver re-registers its card, which gets a fresh sysfs
+// directory (new inode), and a suspend shows up as CLOCK_BOOTTIME pulling
+// ahead of CLOCK_MONOTONIC, which stops while the machine sleeps.
+//
+// Output names from the compositor usually match the part of the sysfs
+// directory after the card, on whichever card it is. Where they don't
+// (some drivers number connectors differently from KWin), the connector
+// of the same type at the same position is used: "DP-3" is the third
+// DisplayPort connector, counting across cards in order. Either way the
+// match is cached until its directory goes away.
+//
+// On a hybrid machine (an Intel iGPU next to an NVIDIA card, say) some
+// drivers register the same connector name on both cards: card0-HDMI-A-1
+// with nothing behind it, card1-HDMI-A-1 with the dummy plug. The one with
+// a CRTC wins, then the connected one, and a cached match that's no longer
+// connected is looked up again. `topology` groups the connectors under
+// their cards, with each card's PCI device and render node, so the GPU
+// driving an output can be compared with the one Sunshine encodes on (see
+// sunshine/encoder.rs).
+//
+// `enabled` only says a CRTC is assigned; Sunshine can still capture black
+// until a frame is actually committed to it. Where debugfs is readable,
+// `has_framebuffer` looks at the DRM core's atomic state dump for a plane
+// on that CRTC with a framebuffer attached.
+//
+// VITAMINK_SYSFS points all of this at another directory laid out like
+// /sys (class/drm/..., kernel/debug/dri/...), which is how the
+// integration tests fake a GPU.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use log::info;
+use serde::Serialize;
+
+use crate::display::DpmsState;
+
+const DRM_DIR: &str = "class/drm";
+// One directory per card minor, each with a `state` file.
+const DEBUGFS_DIR: &str = "kernel/debug/dri";
+
+// /sys, unless VITAMINK_SYSFS says otherwise.
+fn sysfs(dir: &str) -> PathBuf {
+    env::var_os("VITAMINK_SYSFS").map_or_else(|| PathBuf::from("/sys"), PathBuf::from).join(dir)
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectorStatus {
+    Connected,
+    Disconnected,
+    // What the kernel says when it can't probe (some DSI panels, VGA).
+    Unknown,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Connector {
+    // "card1"
+    pub card: String,
+    // "DP-2"
+    pub name: String,
+    pub status: ConnectorStatus,
+    // A CRTC is driving it — what Sunshine's KMS capture needs.
+    pub enabled: bool,
+    pub dpms: DpmsState,
+}
+
+// Every connector on every card. Empty if sysfs isn't there.
+pub fn scan() -> Vec<Connector> {
+    let Ok(entries) = fs::read_dir(sysfs(DRM_DIR)) else {
+        return Vec::new();
+    };
+
+    let mut connectors: Vec<Connector> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let dir_name = entry.file_name().to_string_lossy().to_string();
+            let (card, name) = dir_name.split_once('-')?;
+            if !card.starts_with("card") {
+                return None;
+            }
+            read_connector(&entry.path(), card, name)
+        })
+        .collect();
+    connectors.sort_by(|a, b| (card_number(&a.card), &a.name).cmp(&(card_number(&b.card), &b.name)));
+    connectors
+}
+
+// "card2" → 2, so card10 sorts after card9.
+fn card_number(card: &str) -> u32 {
+    card.trim_start_matches("card").parse().unwrap_or(u32::MAX)
+}
+
+// Names of the connectors the kernel reports as physically connected.
+pub fn connected(connectors: &[Connector]) -> BTreeSet<String> {
+    connectors
+        .iter()
+        .filter(|c| c.status == ConnectorStatus::Connected)
+        .map(|c| c.name.clone())
+        .collect()
+}
+
+// Output name → (card, sysfs connector name), once resolved.
+static RESOLVED: Mutex<BTreeMap<String, (String, String)>> = Mutex::new(BTreeMap::new());
+
+// The connector the compositor calls `name`, on whichever card has it.
+pub fn connector(name: &str) -> Option<Connector> {
+    let cached = RESOLVED.lock().unwrap_or_else(|e| e.into_inner()).get(name).cloned();
+    if let Some((card, sysfs_name)) = cached
+        && let Some(connector) = read_connector(&dir(&card, &sysfs_name), &card, &sysfs_name)
+        && connector.status == ConnectorStatus::Connected
+    {
+        return Some(connector);
+    }
+
+    let found = resolve(name, &scan())?.clone();
+    let location = (found.card.clone(), found.name.clone());
+    let previous = RESOLVED.lock().unwrap_or_else(|e| e.into_inner()).insert(name.to_string(), location.clone());
+    if found.name != name && previous != Some(location) {
+        info!("Output {name} is {}-{} in sysfs", found.card, found.name);
+    }
+    Some(found)
+}
+
+// Drops the cached matches, so every output is looked up again, e.g.
+// after a driver reload renumbered the cards (see recovery.rs).
+pub fn forget() {
+    RESOLVED.lock().unwrap_or_else(|e| e.into_inner()).clear();
+}
+
+// Its sysfs directory, e.g. for reading the EDID.
+pub fn connector_dir(name: &str) -> Option<PathBuf> {
+    connector(name).map(|c| dir(&c.card, &c.name))
+}
+
+fn dir(card: &str, name: &str) -> PathBuf {
+    sysfs(DRM_DIR).join(format!("{card}-{name}"))
+}
+
+// An exact name match, or else the connector of the same type at the
+// same position (see the top of this file). `connectors` is in scan order.
+fn resolve<'a>(name: &str, connectors: &'a [Connector]) -> Option<&'a Connector> {
+    // The same name on several cards: the one in use, else the one with
+    // something plugged in.
+    let exact = connectors.iter().filter(|c| c.name == name);
+    if let Some(exact) = exact.min_by_key(|c| (!c.enabled, c.status != ConnectorStatus::Connected)) {
+        return Some(exact);
+    }
+    let (kind, position) = split_type(name)?;
+    let mut same_type: Vec<(&Connector, u32)> = connectors
+        .iter()
+        .filter_map(|c| split_type(&c.name).filter(|(k, _)| *k == kind).map(|(_, index)| (c, index)))
+        .collect();
+    same_type.sort_by_key(|(c, index)| (card_number(&c.card), *index));
+    same_type.get(usize::try_from(position).ok()?.checked_sub(1)?).map(|(c, _)| *c)
+}
+
+// "HDMI-A-1" → ("HDMI-A", 1).
+fn split_type(name: &str) -> Option<(&str, u32)> {
+    let (kind, index) = name.rsplit_once('-')?;
+    Some((kind, index.parse().ok()?))
+}
+
+// None for directories without a `status` file (not a connector).
+fn read_connector(dir: &Path, card: &str, name: &str) -> Option<Connector> {
+    let read = |file: &str| fs::read_to_string(dir.join(file)).unwrap_or_default();
+
+    let status = fs::read_to_string(dir.join("status")).ok()?;
+    Some(Connector {
+        card: card.to_string(),
+        name: name.to_string(),
+        status: parse_status(&status),
+        enabled: read("enabled").trim() == "enabled",
+        dpms: parse_dpms(&read("dpms")),
+    })
+}
+
+fn parse_status(text: &str) -> ConnectorStatus {
+    match text.trim() {
+        "connected" => ConnectorStatus::Connected,
+        "disconnected" => ConnectorStatus::Disconnected,
+        _ => ConnectorStatus::Unknown,
+    }
+}
+
+fn parse_dpms(text: &str) -> DpmsState {
+    match text.trim() {
+        "On" => DpmsState::On,
+        "Off" => DpmsState::Off,
+        _ => DpmsState::Unknown,
+    }
+}
+
+// ---- Hotplug Counting ----
+
+#[derive(Debug, PartialEq)]
+pub struct Hotplug {
+    pub name: String,
+    pub status: ConnectorStatus,
+    // Flips seen for this connector so far, this one included.
+    pub count: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct HotplugCounter {
+    // None until the first observation.
+    last: Option<BTreeMap<String, ConnectorStatus>>,
+    counts: BTreeMap<String, u32>,
+}
+
+impl HotplugCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Status changes since the previous call. The first call only records
+    // a baseline; a connector that vanishes entirely counts as disconnected.
+    pub fn observe(&mut self, connectors: &[Connector]) -> Vec<Hotplug> {
+        // A name on two cards is connected if either one is.
+        let mut current: BTreeMap<String, ConnectorStatus> = BTreeMap::new();
+        for c in connectors {
+            let status = current.entry(c.name.clone()).or_insert(c.status);
+            if c.status == ConnectorStatus::Connected {
+                *status = ConnectorStatus::Connected;
+            }
+        }
+        let Some(last) = &self.last else {
+            self.last = Some(current);
+            return Vec::new();
+        };
+        for name in last.keys() {
+            current.entry(name.clone()).or_insert(ConnectorStatus::Disconnected);
+        }
+
+        let mut changes = Vec::new();
+        for (name, status) in &current {
+            let previous = last.get(name).copied().unwrap_or(ConnectorStatus::Disconnected);
+            if previous == *status {
+                continue;
+            }
+            let count = self.counts.entry(name.clone()).or_insert(0);
+            *count += 1;
+            changes.push(Hotplug { name: name.clone(), status: *status, count: *count });
+        }
+
+        self.last = Some(current);
+        changes
+    }
+
+    pub fn counts(&self) -> &BTreeMap<String, u32> {
+        &self.counts
+    }
+}
+
+// ---- GPU Re-initialization ----
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Card {
+    // "card1"
+    pub name: String,
+    // "amdgpu", "nvidia", ...
+    pub driver: String,
+    // Of the card's sysfs directory; changes when the driver re-registers it.
+    pub inode: u64,
+}
+
+// The DRM cards (not their connectors), sorted by name.
+pub fn cards() -> Vec<Card> {
+    let Ok(entries) = fs::read_dir(sysfs(DRM_DIR)) else {
+        return Vec::new();
+    };
+
+    let mut cards: Vec<Card> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.strip_prefix("card")?.chars().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+            let inode = fs::metadata(entry.path()).ok()?.ino();
+            let driver = fs::read_link(entry.path().join("device/driver"))
+                .ok()
+                .and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+                .unwrap_or_default();
+            Some(Card { name, driver, inode })
+        })
+        .collect();
+    cards.sort_by(|a, b| a.name.cmp(&b.name));
+    cards
+}
+
+// How busy the card's GPU is, 0-100, where the driver says (amdgpu does).
+pub fn busy_percent(card: &Card) -> Option<u32> {
+    let text = fs::read_to_string(sysfs(DRM_DIR).join(&card.name).join("device/gpu_busy_percent")).ok()?;
+    text.trim().parse().ok()
+}
+
+// Total time spent suspended since boot.
+pub fn suspended_time() -> Duration {
+    let read = |clock| {
+        let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+        // SAFETY: clock_gettime only writes into the timespec we pass.
+        unsafe { libc::clock_gettime(clock, &mut ts) };
+        Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+    };
+    read(libc::CLOCK_BOOTTIME).saturating_sub(read(libc::CLOCK_MONOTONIC))
+}
+
+// Sleeps shorter than this are clock noise, not a suspend.
+const SUSPEND_THRESHOLD: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Default)]
+pub struct GpuWatch {
+    // None until the first observation.
+    cards: Option<Vec<Card>>,
+    suspended: Duration,
+}
+
+impl GpuWatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Why the GPU state can't be trusted any more, if anything happened
+    // since the previous call. The first call only records a baseline.
+    pub fn observe(&mut self, cards: Vec<Card>, suspended: Duration) -> Option<String> {
+        let resumed = suspended.saturating_sub(self.suspended) >= SUSPEND_THRESHOLD;
+        self.suspended = suspended;
+        let last = self.cards.replace(cards.clone())?;
+
+        for card in &cards {
+            match last.iter().find(|c| c.name == card.name) {
+                None => return Some(format!("{} ({}) appeared", card.name, card.driver)),
+                Some(old) if old.inode != card.inode || old.driver != card.driver => {
+                    return Some(format!("{} ({}) was re-registered", card.name, card.driver));
+                }
+                Some(_) => {}
+            }
+        }
+        if let Some(gone) = last.iter().find(|c| !cards.iter().any(|card| card.name == c.name)) {
+            return Some(format!("{} ({}) went away", gone.name, gone.driver));
+        }
+        resumed.then(|| "resumed from suspend".to_string())
+    }
+}
+
+// ---- Topology ----
+
+// A card with its connectors.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Gpu {
+    pub card: Card,
+    // The PCI address, e.g. "0000:01:00.0", which the card and its render
+    // node share. Empty if sysfs doesn't link one.
+    pub device: String,
+    // "renderD128", what encoders open.
+    pub render_node: Option<String>,
+    pub connectors: Vec<Connector>,
+}
+
+// "card1 (nvidia, 0000:01:00.0)"
+impl fmt::Display for Gpu {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.card.driver.as_str(), self.device.as_str()) {
+            ("", "") => write!(f, "{}", self.card.name),
+            (driver, "") | ("", driver) => write!(f, "{} ({driver})", self.card.name),
+            (driver, device) => write!(f, "{} ({driver}, {device})", self.card.name),
+        }
+    }
+}
+
+// Every card with its connectors, sorted like `cards`.
+pub fn topology() -> Vec<Gpu> {
+    let connectors = scan();
+    let render_nodes: Vec<(String, String)> = fs::read_dir(sysfs(DRM_DIR))
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .filter(|name| name.starts_with("renderD"))
+                .map(|name| (device(&name), name))
+                .collect()
+        })
+        .unwrap_or_default();
+    cards()
+        .into_iter()
+        .map(|card| {
+            let device = device(&card.name);
+            Gpu {
+                render_node: render_nodes.iter().find(|(d, _)| !device.is_empty() && *d == device).map(|(_, node)| node.clone()),
+                connectors: connectors.iter().filter(|c| c.card == card.name).cloned().collect(),
+                device,
+                card,
+            }
+        })
+        .collect()
+}
+
+// The GPU driving the output the compositor calls `name`.
+pub fn gpu_of(name: &str) -> Option<Gpu> {
+    let connector = connector(name)?;
+    topology().into_iter().find(|gpu| gpu.card.name == connector.card)
+}
+
+// The GPU behind a device node, e.g. "/dev/dri/renderD129" or "card1".
+pub fn gpu_for_node(node: &str) -> Option<Gpu> {
+    let node = Path::new(node).file_name()?.to_str()?;
+    topology().into_iter().find(|gpu| gpu.card.name == node || gpu.render_node.as_deref() == Some(node))
+}
+
+// The last component of where a node's `device` link points.
+fn device(node: &str) -> String {
+    fs::canonicalize(sysfs(DRM_DIR).join(node).join("device"))
+        .ok()
+        .and_then(|path| path.file_name().map(|n| n.to_string_lossy().to_string()))
+        .unwrap_or_default()
+}
+
+// ---- Framebuffers ----
+
+// Whether `name`'s CRTC is active and scanning out a framebuffer. None if
+// the state dump can't be read: debugfs is root-only on most systems, or
+// not mounted.
+pub fn has_framebuffer(name: &str) -> Option<bool> {
+    let connector = connector(name)?;
+    let path = sysfs(DEBUGFS_DIR).join(connector.card.trim_start_matches("card")).join("state");
+    let state = fs::read_to_string(path).ok()?;
+    Some(scanning_out(&state, &connector.name))
+}
+
+// A block of the state dump: "plane[31]: plane-0" and the properties
+// indented once below it ("\tcrtc=crtc-0", "\tfb=110"). Deeper lines
+// describe a property and are skipped.
+struct Block<'a> {
+    kind: &'a str,
+    name: &'a str,
+    properties: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Block<'a> {
+    fn get(&self, key: &str) -> Option<&'a str> {
+        self.properties.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+    }
+}
+
+fn parse_state(state: &str) -> Vec<Block<'_>> {
+    let mut blocks: Vec<Block> = Vec::new();
+    for line in state.lines() {
+        if let Some(property) = line.strip_prefix('\t') {
+            if let Some(block) = blocks.last_mut()
+                && !property.starts_with('\t')
+                && let Some((key, value)) = property.split_once('=')
+            {
+                block.properties.push((key, value));
+            }
+        } else if let Some((head, name)) = line.split_once("]: ")
+            && let Some((kind, _id)) = head.split_once('[')
+        {
+            blocks.push(Block { kind, name, properties: Vec::new() });
+        }
+    }
+    blocks
+}
+
+fn scanning_out(state: &str, connector: &str) -> bool {
+    let blocks = parse_state(state);
+    let Some(crtc) = blocks.iter().find(|b| b.kind == "connector" && b.name == connector).and_then(|b| b.get("crtc")) else {
+        return false;
+    };
+    let active = blocks.iter().any(|b| b.kind == "crtc" && b.name == crtc && b.get("active") == Some("1"));
+    active && blocks.iter().any(|b| b.kind == "plane" && b.get("crtc") == Some(crtc) && b.get("fb").is_some_and(|fb| fb != "0"))
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connector(name: &str, status: ConnectorStatus) -> Connector {
+        Connector { card: "card1".into(), name: name.into(), status, enabled: false, dpms: DpmsState::Unknown }
+    }
+
+    #[test]
+    fn test_resolve() {
+        let on = |card: &str, name: &str| Connector { card: card.into(), ..connector(name, ConnectorStatus::Connected) };
+        let connectors = [on("card1", "HDMI-A-1"), on("card2", "DP-4"), on("card2", "DP-5"), on("card2", "DP-6"), on("card10", "DP-1")];
+        let found = |name| resolve(name, &connectors).map(|c| format!("{}-{}", c.card, c.name));
+
+        assert_eq!(found("HDMI-A-1").as_deref(), Some("card1-HDMI-A-1"));
+        assert_eq!(found("DP-5").as_deref(), Some("card2-DP-5"));
+        // No DP-2 in sysfs: the second DisplayPort connector.
+        assert_eq!(found("DP-2").as_deref(), Some("card2-DP-5"));
+        assert_eq!(found("DP-3").as_deref(), Some("card2-DP-6"));
+        assert_eq!(found("DP-7"), None);
+        assert_eq!(found("eDP-1"), None);
+        assert_eq!(found("Virtual-vitamink"), None);
+
+        // HDMI-A-1 on both cards of a hybrid machine: the connected one,
+        // or better, the one with a CRTC.
+        let off = |card: &str| Connector { card: card.into(), ..connector("HDMI-A-1", ConnectorStatus::Disconnected) };
+        let hybrid = [off("card0"), on("card1", "HDMI-A-1")];
+        assert_eq!(resolve("HDMI-A-1", &hybrid).map(|c| c.card.as_str()), Some("card1"));
+        let driven = [Connector { enabled: true, ..on("card0", "HDMI-A-1") }, on("card1", "HDMI-A-1")];
+        assert_eq!(resolve("HDMI-A-1", &driven).map(|c| c.card.as_str()), Some("card0"));
+    }
+
+    #[test]
+    fn test_parse_status() {
+        assert_eq!(parse_status("connected\n"), ConnectorStatus::Connected);
+        assert_eq!(parse_status("disconnected\n"), ConnectorStatus::Disconnected);
+        assert_eq!(parse_status("unknown\n"), ConnectorStatus::Unknown);
+        assert_eq!(parse_dpms("Off\n"), DpmsState::Off);
+        assert_eq!(parse_dpms(""), DpmsState::Unknown);
+    }
+
+    #[test]
+    fn test_hotplug_counter() {
+        use ConnectorStatus::*;
+        let mut counter = HotplugCounter::new();
+
+        assert!(counter.observe(&[connector("DP-2", Connected), connector("HDMI-A-1", Disconnected)]).is_empty());
+        assert!(counter.observe(&[connector("DP-2", Connected), connector("HDMI-A-1", Disconnected)]).is_empty());
+
+        let changes = counter.observe(&[connector("DP-2", Disconnected), connector("HDMI-A-1", Connected)]);
+        assert_eq!(
+            changes,
+            vec![
+                Hotplug { name: "DP-2".into(), status: Disconnected, count: 1 },
+                Hotplug { name: "HDMI-A-1".into(), status: Connected, count: 1 },
+            ]
+        );
+
+        // DP-2 comes back, then HDMI-A-1's directory disappears altogether
+        counter.observe(&[connector("DP-2", Connected), connector("HDMI-A-1", Connected)]);
+        let changes = counter.observe(&[connector("DP-2", Connected)]);
+        assert_eq!(changes, vec![Hotplug { name: "HDMI-A-1".into(), status: Disconnected, count: 2 }]);
+        assert_eq!(counter.counts()["DP-2"], 2);
+
+        // The same name on a second card, with nothing behind it, isn't a flip.
+        let other = Connector { card: "card0".into(), ..connector("DP-2", Disconnected) };
+        assert!(counter.observe(&[other.clone(), connector("DP-2", Connected)]).is_empty());
+        assert!(counter.observe(&[connector("DP-2", Connected), other]).is_empty());
+    }
+
+    #[test]
+    fn test_gpu_display() {
+        let gpu = |driver: &str, device: &str| Gpu {
+            card: Card { name: "card1".into(), driver: driver.into(), inode: 0 },
+            device: device.into(),
+            render_node: None,
+            connectors: Vec::new(),
+        };
+        assert_eq!(gpu("nvidia", "0000:01:00.0").to_string(), "card1 (nvidia, 0000:01:00.0)");
+        assert_eq!(gpu("i915", "").to_string(), "card1 (i915)");
+        assert_eq!(gpu("", "").to_string(), "card1");
+    }
+
+    #[test]
+    fn test_gpu_watch() {
+        let card = |inode| Card { name: "card1".into(), driver: "nvidia".into(), inode };
+        let mut watch = GpuWatch::new();
+
+        assert_eq!(watch.observe(vec![card(10)], Duration::from_secs(5)), None);
+        assert_eq!(watch.observe(vec![card(10)], Duration::from_secs(6)), None);
+        assert_eq!(watch.observe(vec![card(10)], Duration::from_secs(600)).as_deref(), Some("resumed from suspend"));
+        assert_eq!(
+            watch.observe(vec![card(11)], Duration::from_secs(600)).as_deref(),
+            Some("card1 (nvidia) was re-registered")
+        );
+        assert_eq!(watch.observe(vec![], Duration::from_secs(600)).as_deref(), Some("card1 (nvidia) went away"));
+        assert_eq!(watch.observe(vec![card(12)], Duration::from_secs(600)).as_deref(), Some("card1 (nvidia) appeared"));
+    }
+
+    #[test]
+    fn test_scanning_out() {
+        let state = "plane[31]: plane-0\n\tcrtc=crtc-0\n\tfb=110\n\t\tallocated by = kwin_wayland\n\t\trefcount=2\n\
+                     plane[40]: plane-1\n\tcrtc=crtc-1\n\tfb=0\n\
+                     crtc[51]: crtc-0\n\tenable=1\n\tactive=1\n\
+                     crtc[52]: crtc-1\n\tenable=1\n\tactive=1\n\
+                     connector[95]: DP-2\n\tcrtc=crtc-0\n\
+                     connector[96]: HDMI-A-1\n\tcrtc=crtc-1\n\
+                     connector[97]: DP-3\n\tcrtc=(null)\n";
+        assert!(scanning_out(state, "DP-2"));
+        // A CRTC, but nothing committed to it yet.
+        assert!(!scanning_out(state, "HDMI-A-1"));
+        assert!(!scanning_out(state, "DP-3"));
+        assert!(!scanning_out(state, "DP-9"));
+        assert!(!scanning_out(&state.replace("crtc-0\n\tenable=1\n\tactive=1", "crtc-0\n\tenable=1\n\tactive=0"), "DP-2"));
+    }
+}
@@ -0,0 +1,151 @@
+// src/dummy.rs — Working out which output is the dummy plug
+//
+// `dummy_plug = "auto"` means "whichever connected output isn't the main
+// display". With more than one candidate we narrow down, in order:
+//
+//   1. the first connector listed in `dummy_priority` (globs allowed, so
+//      "DP-*" before "HDMI-*")
+//   2. outputs whose EDID matches `dummy_edid` (manufacturer or name)
+//   3. the smallest physical size — dongles report 0x0 or something tiny
+//
+// and log which rule decided, since a wrong guess is otherwise baffling.
+//
+// A glob as `dummy_plug` that didn't match when the config was resolved
+// (see pattern.rs) is matched against the connected outputs here instead.
+
+use log::info;
+use serde::Deserialize;
+
+use crate::config::Config;
+use crate::display::{self, ConnectionState};
+use crate::edid::{self, Edid};
+use crate::error::{Result, VitaminkError};
+use crate::pattern;
+use crate::virtual_output;
+
+pub const AUTO: &str = "auto";
+
+// The `on_dummy_unplug` config setting: what to do when the dummy plug is
+// pulled out while Away.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnplugAction {
+    // Leave things as they are and use the plug again once it's back.
+    #[default]
+    Wait,
+    // Stream from `[virtual_output]` until then (see virtual_output.rs).
+    Virtual,
+}
+
+#[derive(Debug)]
+pub struct Candidate {
+    pub name: String,
+    pub edid: Option<Edid>,
+}
+
+// The configured connector, the virtual output's name, or the
+// auto-selected one.
+pub fn resolve(config: &Config) -> Result<String> {
+    if config.dummy_plug == virtual_output::VIRTUAL {
+        return Ok(config.virtual_output.output.clone());
+    }
+    if config.dummy_plug != AUTO && !pattern::is_glob(&config.dummy_plug) {
+        return Ok(config.dummy_plug.clone());
+    }
+
+    let candidates: Vec<Candidate> = display::get_displays()?
+        .into_iter()
+        .filter(|d| d.connection == ConnectionState::Connected && !config.main_display.contains(&d.name))
+        .map(|d| Candidate { edid: edid::read(&d.name), name: d.name })
+        .collect();
+    if config.dummy_plug != AUTO {
+        let names: Vec<String> = candidates.into_iter().map(|c| c.name).collect();
+        let name = pattern::pick("dummy_plug", &config.dummy_plug, &names)?;
+        info!("Dummy plug: {name} (matches \"{}\")", config.dummy_plug);
+        return Ok(name);
+    }
+
+    let (name, reason) = select(&candidates, &config.dummy_priority, config.dummy_edid.as_deref())?;
+    info!("Dummy plug: {name} ({reason})");
+    Ok(name)
+}
+
+// Returns the chosen connector and why it won.
+pub fn select(candidates: &[Candidate], priority: &[String], edid_pattern: Option<&str>) -> Result<(String, String)> {
+    let mut remaining: Vec<&Candidate> = candidates.iter().collect();
+
+    if remaining.is_empty() {
+        return Err(VitaminkError::Parse("No connected output could be the dummy plug".to_string()));
+    }
+    if let [only] = remaining[..] {
+        return Ok((only.name.clone(), "only candidate".to_string()));
+    }
+
+    if let Some(c) = priority.iter().find_map(|p| remaining.iter().find(|c| c.name == *p || pattern::glob(p, &c.name))) {
+        return Ok((c.name.clone(), "first available in dummy_priority".to_string()));
+    }
+
+    if let Some(pattern) = edid_pattern {
+        let matching: Vec<&Candidate> =
+            remaining.iter().copied().filter(|c| c.edid.as_ref().is_some_and(|e| e.matches(pattern))).collect();
+        match matching[..] {
+            [] => {}
+            [only] => return Ok((only.name.clone(), format!("EDID matches \"{pattern}\""))),
+            _ => remaining = matching,
+        }
+    }
+
+    // No EDID counts as size unknown, same as a reported 0x0.
+    let area = |c: &Candidate| c.edid.as_ref().map_or(0, |e| e.width_cm as u32 * e.height_cm as u32);
+    let smallest = remaining.iter().min_by_key(|c| area(c)).expect("remaining is never empty");
+    let names: Vec<&str> = remaining.iter().map(|c| c.name.as_str()).collect();
+
+    Ok((
+        smallest.name.clone(),
+        format!("smallest physical size among {} — set dummy_priority to choose", names.join(", ")),
+    ))
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(name: &str, edid_name: &str, size: (u8, u8)) -> Candidate {
+        Candidate {
+            name: name.into(),
+            edid: Some(Edid {
+                manufacturer: "XXX".into(),
+                name: Some(edid_name.into()),
+                product_code: 0,
+                serial: None,
+                width_cm: size.0,
+                height_cm: size.1,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_select() {
+        let candidates = vec![
+            candidate("HDMI-A-1", "LG TV", (160, 90)),
+            candidate("HDMI-A-2", "FUHLEN 4K", (0, 0)),
+            candidate("DP-3", "Headless Ghost", (1, 1)),
+        ];
+
+        let pick = |priority: &[&str], edid| {
+            let priority: Vec<String> = priority.iter().map(|s| s.to_string()).collect();
+            select(&candidates, &priority, edid).unwrap().0
+        };
+
+        assert_eq!(pick(&["DP-9", "DP-3"], None), "DP-3");
+        assert_eq!(pick(&["DP-*", "HDMI-A-1"], None), "DP-3");
+        assert_eq!(pick(&["HDMI-A-[2-9]"], None), "HDMI-A-2");
+        assert_eq!(pick(&[], Some("ghost")), "DP-3");
+        assert_eq!(pick(&[], Some("nothing")), "HDMI-A-2");
+        assert_eq!(pick(&[], None), "HDMI-A-2");
+        assert_eq!(select(&candidates[..1], &[], None).unwrap(), ("HDMI-A-1".to_string(), "only candidate".to_string()));
+        assert!(select(&[], &[], None).is_err());
+    }
+}
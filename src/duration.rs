@@ -0,0 +1,107 @@
+// src/duration.rs — Human-friendly durations: "10s", "2m", "1h30m", "500ms"
+//
+// Shared by the config file and the CLI so "how do I write 90 seconds?"
+// has the same answer everywhere.
+
+use std::time::Duration;
+
+use crate::error::{Result, VitaminkError};
+
+// A sequence of <number><unit> pairs. Units: ms, s, m, h, d.
+pub fn parse(input: &str) -> Result<Duration> {
+    let s = input.trim();
+    let invalid = |why: &str| VitaminkError::Parse(format!("Invalid duration \"{input}\": {why}"));
+
+    if s.is_empty() {
+        return Err(invalid("empty"));
+    }
+
+    let mut total = Duration::ZERO;
+    let mut rest = s;
+
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        if digits == 0 {
+            return Err(invalid("expected a number"));
+        }
+        let value: u64 = rest[..digits].parse().map_err(|_| invalid("number too large"))?;
+        rest = &rest[digits..];
+
+        let unit_len = rest.find(|c: char| c.is_ascii_digit()).unwrap_or(rest.len());
+        let unit = &rest[..unit_len];
+        rest = &rest[unit_len..];
+
+        let seconds = |per: u64| value.checked_mul(per).map(Duration::from_secs);
+        let part = match unit {
+            "ms" => Some(Duration::from_millis(value)),
+            "s" => Some(Duration::from_secs(value)),
+            "m" => seconds(60),
+            "h" => seconds(60 * 60),
+            "d" => seconds(60 * 60 * 24),
+            "" => return Err(invalid("missing unit (ms, s, m, h, d)")),
+            other => return Err(invalid(&format!("unknown unit \"{other}\" (expected ms, s, m, h, d)"))),
+        };
+        total = part.and_then(|part| total.checked_add(part)).ok_or_else(|| invalid("out of range"))?;
+    }
+
+    Ok(total)
+}
+
+// The inverse of `parse`, for writing config values and log messages.
+// Sub-second remainders are shown only when there are no larger units.
+pub fn format(d: Duration) -> String {
+    let mut secs = d.as_secs();
+    if secs == 0 {
+        return format!("{}ms", d.subsec_millis());
+    }
+
+    let mut out = String::new();
+    for (unit, size) in [("d", 86_400), ("h", 3_600), ("m", 60), ("s", 1)] {
+        if secs >= size {
+            out.push_str(&format!("{}{unit}", secs / size));
+            secs %= size;
+        }
+    }
+    out
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("10s").unwrap(), Duration::from_secs(10));
+        assert_eq!(parse("2m").unwrap(), Duration::from_secs(120));
+        assert_eq!(parse("1h30m").unwrap(), Duration::from_secs(5400));
+        assert_eq!(parse(" 500ms ").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse("1d").unwrap(), Duration::from_secs(86_400));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            parse("10").unwrap_err().to_string(),
+            "Invalid duration \"10\": missing unit (ms, s, m, h, d)"
+        );
+        assert_eq!(
+            parse("5x").unwrap_err().to_string(),
+            "Invalid duration \"5x\": unknown unit \"x\" (expected ms, s, m, h, d)"
+        );
+        assert!(parse("").is_err());
+        assert!(parse("s").is_err());
+        assert!(parse("1.5s").is_err());
+        assert_eq!(parse("999999999999999999d").unwrap_err().to_string(), "Invalid duration \"999999999999999999d\": out of range");
+        assert!(parse("18446744073709551615s1s").unwrap_err().to_string().ends_with("out of range"));
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(format(Duration::from_secs(10)), "10s");
+        assert_eq!(format(Duration::from_secs(5400)), "1h30m");
+        assert_eq!(format(Duration::from_millis(250)), "250ms");
+        assert_eq!(parse(&format(Duration::from_secs(93_784))).unwrap(), Duration::from_secs(93_784));
+    }
+}
@@ -0,0 +1,185 @@
+// src/edid.rs — Reading monitor identity from the kernel's EDID copy
+//
+// Every connected output exposes the raw EDID block the monitor sent at
+// /sys/class/drm/cardN-<connector>/edid. It's a fixed binary layout, so we
+// pick out the few fields we care about by offset.
+//
+// Connector names aren't stable — two HDMI outputs can trade places
+// between boots — so anywhere the config names an output it can instead
+// say `edid:PATTERN`, e.g. `main_display = "edid:U2720Q"`. That is swapped
+// for whichever connected output's EDID matches (see pattern.rs).
+//
+// New Rust concepts in this file:
+//
+// - Slices and `chunks_exact`: `&bytes[54..126]` borrows part of the array
+//   without copying; `chunks_exact(18)` walks it in 18-byte pieces.
+
+use std::fs;
+
+use serde::Serialize;
+
+use crate::drm;
+use crate::error::{Result, VitaminkError};
+
+pub const PREFIX: &str = "edid:";
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Edid {
+    // Three-letter PNP id, e.g. "DEL" for Dell.
+    pub manufacturer: String,
+    // The "monitor name" descriptor, if the monitor sends one.
+    pub name: Option<String>,
+    pub product_code: u16,
+    // The serial-number descriptor, or else the numeric serial (0 = none).
+    pub serial: Option<String>,
+    // Physical size; 0 means unknown (common on projectors and dongles).
+    pub width_cm: u8,
+    pub height_cm: u8,
+}
+
+impl Edid {
+    // Whether `pattern` (case-insensitive) appears in the manufacturer,
+    // name, serial, or the full identity.
+    pub fn matches(&self, pattern: &str) -> bool {
+        let pattern = pattern.to_lowercase();
+        self.manufacturer.to_lowercase().contains(&pattern)
+            || self.name.as_ref().is_some_and(|n| n.to_lowercase().contains(&pattern))
+            || self.serial.as_ref().is_some_and(|s| s.to_lowercase().contains(&pattern))
+            || self.identity().to_lowercase() == pattern
+    }
+
+    // "DEL-A0B1-ABC123": stable across ports and boots, for logs and `edid:`.
+    pub fn identity(&self) -> String {
+        let mut id = format!("{}-{:04X}", self.manufacturer, self.product_code);
+        if let Some(serial) = &self.serial {
+            id += &format!("-{serial}");
+        }
+        id
+    }
+}
+
+// The EDID of a connector by name (e.g. "HDMI-A-1"), on whichever card has it.
+pub fn read(connector: &str) -> Option<Edid> {
+    let bytes = fs::read(drm::connector_dir(connector)?.join("edid")).ok()?;
+    parse(&bytes)
+}
+
+const HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+
+pub fn parse(bytes: &[u8]) -> Option<Edid> {
+    if bytes.len() < 128 || bytes[..8] != HEADER {
+        return None;
+    }
+
+    // Bytes 8-9: three 5-bit letters, 1 = 'A'.
+    let id = u16::from_be_bytes([bytes[8], bytes[9]]);
+    let manufacturer = [(id >> 10) & 0x1f, (id >> 5) & 0x1f, id & 0x1f]
+        .iter()
+        .map(|&c| (b'A' + c as u8 - 1) as char)
+        .collect();
+
+    // Four 18-byte descriptors; 0xFC is the monitor name, 0xFF the serial.
+    let descriptor = |tag: u8| {
+        bytes[54..126]
+            .chunks_exact(18)
+            .find(|d| d[0] == 0 && d[1] == 0 && d[3] == tag)
+            .map(|d| String::from_utf8_lossy(&d[5..]).split('\n').next().unwrap_or("").trim().to_string())
+            .filter(|text| !text.is_empty())
+    };
+
+    // Bytes 10-11 and 12-15: product code and serial, little-endian.
+    let product_code = u16::from_le_bytes([bytes[10], bytes[11]]);
+    let serial = match u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]) {
+        0 => None,
+        n => Some(n.to_string()),
+    };
+
+    Some(Edid {
+        manufacturer,
+        name: descriptor(0xfc),
+        product_code,
+        serial: descriptor(0xff).or(serial),
+        width_cm: bytes[21],
+        height_cm: bytes[22],
+    })
+}
+
+// ---- Matching Outputs ----
+
+// The one connected output whose EDID matches `pattern`.
+pub fn find(pattern: &str) -> Result<String> {
+    let connected: Vec<(String, Edid)> = drm::connected(&drm::scan())
+        .into_iter()
+        .filter_map(|name| read(&name).map(|edid| (name, edid)))
+        .collect();
+    pick(&connected, pattern)
+}
+
+fn pick(outputs: &[(String, Edid)], pattern: &str) -> Result<String> {
+    let matching: Vec<&(String, Edid)> = outputs.iter().filter(|(_, edid)| edid.matches(pattern)).collect();
+    match matching[..] {
+        [(name, _)] => Ok(name.clone()),
+        [] => Err(VitaminkError::Parse(format!("No connected output has an EDID matching \"{pattern}\""))),
+        _ => {
+            let found: Vec<String> = matching.iter().map(|(name, edid)| format!("{name} ({})", edid.identity())).collect();
+            Err(VitaminkError::Parse(format!("\"{pattern}\" matches several outputs: {}", found.join(", "))))
+        }
+    }
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Header, manufacturer "DEL", product 0xA0B1, serial 12345, 60x34 cm,
+    // name descriptor "DELL U2720Q".
+    fn sample() -> Vec<u8> {
+        let mut bytes = vec![0u8; 128];
+        bytes[..8].copy_from_slice(&HEADER);
+        bytes[8..10].copy_from_slice(&[0x10, 0xac]);
+        bytes[10..12].copy_from_slice(&0xa0b1u16.to_le_bytes());
+        bytes[12..16].copy_from_slice(&12345u32.to_le_bytes());
+        bytes[21] = 60;
+        bytes[22] = 34;
+        bytes[72 + 3] = 0xfc;
+        bytes[72 + 5..72 + 18].copy_from_slice(b"DELL U2720Q\n ");
+        bytes
+    }
+
+    #[test]
+    fn test_parse() {
+        let edid = parse(&sample()).unwrap();
+        assert_eq!(edid.manufacturer, "DEL");
+        assert_eq!(edid.name.as_deref(), Some("DELL U2720Q"));
+        assert_eq!((edid.width_cm, edid.height_cm), (60, 34));
+        assert_eq!(edid.identity(), "DEL-A0B1-12345");
+        assert!(edid.matches("u2720"));
+        assert!(edid.matches("12345"));
+        assert!(edid.matches("del-a0b1-12345"));
+        assert!(!edid.matches("dummy"));
+
+        // A serial-number descriptor wins over the numeric serial.
+        let mut bytes = sample();
+        bytes[90 + 3] = 0xff;
+        bytes[90 + 5..90 + 12].copy_from_slice(b"ABC123\n");
+        assert_eq!(parse(&bytes).unwrap().serial.as_deref(), Some("ABC123"));
+
+        assert_eq!(parse(&[0u8; 128]), None);
+        assert_eq!(parse(&sample()[..64]), None);
+    }
+
+    #[test]
+    fn test_pick() {
+        let mut other = parse(&sample()).unwrap();
+        other.name = Some("DUMMY 4K".into());
+        other.serial = None;
+        let outputs = vec![("DP-2".to_string(), parse(&sample()).unwrap()), ("HDMI-A-1".to_string(), other)];
+
+        assert_eq!(pick(&outputs, "U2720Q").unwrap(), "DP-2");
+        assert_eq!(pick(&outputs, "dummy").unwrap(), "HDMI-A-1");
+        assert!(pick(&outputs, "DEL").unwrap_err().to_string().contains("several"));
+        assert!(pick(&outputs, "LG").is_err());
+    }
+}
@@ -0,0 +1,183 @@
+// src/environment.rs — The environment every command we run starts with
+//
+// Run from a system service (or started by lingering before anyone logs
+// in), the daemon has none of the session's variables, and neither do
+// the commands it runs: systemctl --user can't find the user manager
+// without XDG_RUNTIME_DIR, pactl and qdbus can't find the session bus
+// without DBUS_SESSION_BUS_ADDRESS. `[environment]` says what they get:
+//
+//   [environment]
+//   detect = true                       # the default
+//   inherit = ["PATH", "HOME", "LANG"]  # unset passes all of ours through
+//   set = { PULSE_SERVER = "unix:/run/user/1000/pulse/native" }
+//
+// `detect` fills in what our own environment lacks (or `inherit` leaves
+// out) from the user's session: XDG_RUNTIME_DIR is /run/user/UID,
+// DBUS_SESSION_BUS_ADDRESS the `bus` socket in it, and WAYLAND_DISPLAY
+// and DISPLAY are what display.rs found. `set` goes on top of all that.
+// A variable a caller sets on one command itself (a hook's VITAMINK_*,
+// kscreen-doctor's WAYLAND_DISPLAY) is left as the caller set it.
+
+use std::env;
+use std::ffi::OsString;
+use std::path::Path;
+use std::process::Command;
+use std::sync::OnceLock;
+
+use log::debug;
+
+use crate::config::EnvironmentConfig;
+use crate::display;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Environment {
+    // The only variables passed through from ours; None passes them all.
+    inherit: Option<Vec<String>>,
+    // Set on every command, detected ones first.
+    vars: Vec<(String, String)>,
+}
+
+static ENVIRONMENT: OnceLock<Environment> = OnceLock::new();
+
+// Works out the environment for the rest of the process. Only the first
+// call counts; until then commands get ours unchanged.
+pub fn init(config: &EnvironmentConfig) {
+    // SAFETY: getuid(2) can't fail.
+    let uid = unsafe { libc::getuid() };
+    let environment = resolve(config, |key| env::var(key).ok().filter(|v| !v.is_empty()), uid, |path| path.exists(), &display::wayland_env());
+    for (key, value) in &environment.vars {
+        debug!("Environment: {key}={value}");
+    }
+    let _ = ENVIRONMENT.set(environment);
+}
+
+// Gives `cmd` the environment from `init`.
+pub fn apply(cmd: &mut Command) {
+    if let Some(environment) = ENVIRONMENT.get() {
+        environment.apply(cmd);
+    }
+}
+
+impl Environment {
+    fn apply(&self, cmd: &mut Command) {
+        let own: Vec<(OsString, Option<OsString>)> = cmd.get_envs().map(|(k, v)| (k.to_owned(), v.map(|v| v.to_owned()))).collect();
+        if let Some(inherit) = &self.inherit {
+            cmd.env_clear();
+            for key in inherit {
+                if let Some(value) = env::var_os(key) {
+                    cmd.env(key, value);
+                }
+            }
+        }
+        cmd.envs(self.vars.iter().map(|(k, v)| (k, v)));
+        // `env_clear` dropped them, and ours may have replaced them.
+        for (key, value) in own {
+            match value {
+                Some(value) => cmd.env(key, value),
+                None => cmd.env_remove(key),
+            };
+        }
+    }
+}
+
+// `ours` looks up our own environment, `session` is what display.rs
+// detected for WAYLAND_DISPLAY and DISPLAY.
+fn resolve(
+    config: &EnvironmentConfig,
+    ours: impl Fn(&str) -> Option<String>,
+    uid: u32,
+    exists: impl Fn(&Path) -> bool,
+    session: &[(&'static str, String)],
+) -> Environment {
+    // What a command would get without help.
+    let passed = |key: &str| ours(key).filter(|_| config.inherit.as_ref().is_none_or(|inherit| inherit.iter().any(|k| k == key)));
+    let mut vars: Vec<(String, String)> = Vec::new();
+
+    if config.detect {
+        let runtime_dir = ours("XDG_RUNTIME_DIR").or_else(|| Some(format!("/run/user/{uid}")).filter(|dir| exists(Path::new(dir))));
+        if let Some(dir) = &runtime_dir {
+            if passed("XDG_RUNTIME_DIR").is_none() {
+                vars.push(("XDG_RUNTIME_DIR".to_string(), dir.clone()));
+            }
+            let bus = ours("DBUS_SESSION_BUS_ADDRESS").or_else(|| {
+                let socket = Path::new(dir).join("bus");
+                exists(&socket).then(|| format!("unix:path={}", socket.display()))
+            });
+            if let Some(bus) = bus
+                && passed("DBUS_SESSION_BUS_ADDRESS").is_none()
+            {
+                vars.push(("DBUS_SESSION_BUS_ADDRESS".to_string(), bus));
+            }
+        }
+        for (key, value) in session {
+            if passed(key).is_none() {
+                vars.push((key.to_string(), value.clone()));
+            }
+        }
+    }
+
+    vars.retain(|(key, _)| !config.set.contains_key(key));
+    vars.extend(config.set.iter().map(|(k, v)| (k.clone(), v.clone())));
+    Environment { inherit: config.inherit.clone(), vars }
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn vars(environment: &Environment) -> Vec<(&str, &str)> {
+        environment.vars.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect()
+    }
+
+    #[test]
+    fn test_resolve() {
+        let session = [("WAYLAND_DISPLAY", "wayland-0".to_string())];
+        let exists = |path: &Path| path.starts_with("/run/user/1000");
+
+        // A system service: nothing of the session's.
+        let config = EnvironmentConfig::default();
+        let environment = resolve(&config, |_| None, 1000, exists, &session);
+        assert_eq!(
+            vars(&environment),
+            [
+                ("XDG_RUNTIME_DIR", "/run/user/1000"),
+                ("DBUS_SESSION_BUS_ADDRESS", "unix:path=/run/user/1000/bus"),
+                ("WAYLAND_DISPLAY", "wayland-0")
+            ]
+        );
+
+        // Inside the session: what we have is passed through as it is.
+        let ours = |key: &str| (key != "DBUS_SESSION_BUS_ADDRESS").then(|| format!("our {key}"));
+        assert!(resolve(&config, ours, 1000, exists, &session).vars.is_empty());
+
+        // Unless `inherit` leaves it out; `set` wins over detection.
+        let config = EnvironmentConfig {
+            inherit: Some(vec!["PATH".to_string()]),
+            set: BTreeMap::from([("WAYLAND_DISPLAY".to_string(), "wayland-1".to_string())]),
+            ..EnvironmentConfig::default()
+        };
+        let environment = resolve(&config, ours, 1000, exists, &session);
+        assert_eq!(vars(&environment), [("XDG_RUNTIME_DIR", "our XDG_RUNTIME_DIR"), ("WAYLAND_DISPLAY", "wayland-1")]);
+
+        // Nothing detected when asked not to, or for a user without a
+        // runtime directory.
+        let config = EnvironmentConfig { detect: false, ..EnvironmentConfig::default() };
+        assert_eq!(resolve(&config, |_| None, 1000, exists, &session), Environment::default());
+        assert!(resolve(&EnvironmentConfig::default(), |_| None, 1001, exists, &[]).vars.is_empty());
+    }
+
+    #[test]
+    fn test_apply() {
+        let environment = Environment { inherit: Some(Vec::new()), vars: vec![("A".to_string(), "ours".to_string())] };
+        let mut cmd = Command::new("true");
+        cmd.env("A", "caller's").env("B", "caller's");
+        environment.apply(&mut cmd);
+        let envs: Vec<(String, Option<String>)> =
+            cmd.get_envs().map(|(k, v)| (k.to_string_lossy().to_string(), v.map(|v| v.to_string_lossy().to_string()))).collect();
+        assert_eq!(envs, [("A".to_string(), Some("caller's".to_string())), ("B".to_string(), Some("caller's".to_string()))]);
+    }
+}
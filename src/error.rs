@@ -0,0 +1,141 @@
+// src/error.rs — The error type shared by every module
+//
+// New Rust concepts in this file:
+//
+// - Enums with data: each variant carries the context for that kind of
+//   failure, so callers can `match` on "kscreen-doctor is missing" vs.
+//   "systemctl said no" instead of comparing strings.
+//
+// - `impl std::error::Error`: the standard trait for error types. `source()`
+//   exposes the underlying cause (e.g. the io::Error) so nothing is lost.
+//
+// - Type alias: `Result<T>` is shorthand for `Result<T, VitaminkError>`.
+//   Modules `use crate::error::Result` and their signatures stay short.
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::process::Output;
+
+#[derive(Debug)]
+pub enum VitaminkError {
+    // The program isn't installed (or not on PATH).
+    CommandNotFound { program: String },
+    // The program exists but couldn't be started.
+    Spawn { program: String, source: io::Error },
+    // The program ran and exited unsuccessfully.
+    CommandFailed { command: String, stderr: String },
+    // Output from an external tool didn't look the way we expected.
+    Parse(String),
+    // Reading or writing a file (sysfs, /dev, config) failed.
+    Io { path: PathBuf, source: io::Error },
+    // Something we were waiting for never happened.
+    Timeout(String),
+    // Something a step needs is taken, e.g. every hardware encoder.
+    Unavailable(String),
+    // The config file is unreadable or invalid.
+    Config { path: PathBuf, message: String },
+    // Session bus registration or calls failed. Boxed because zbus::Error
+    // is large and would bloat every Result in the crate.
+    DBus { context: String, source: Box<zbus::Error> },
+    // The Wayland connection broke, or the compositor threw out a change
+    // because the outputs changed meanwhile (see wlr_output.rs).
+    Wayland(String),
+    // A transition step failed and what came before it was undone —
+    // unless `rollback` says that failed too.
+    Transition { step: String, source: Box<VitaminkError>, rollback: Option<Box<VitaminkError>> },
+}
+
+pub type Result<T> = std::result::Result<T, VitaminkError>;
+
+impl VitaminkError {
+    // Maps a failed `Command::output()` to the right variant.
+    pub fn spawn(program: &str, source: io::Error) -> Self {
+        if source.kind() == io::ErrorKind::NotFound {
+            Self::CommandNotFound { program: program.to_string() }
+        } else {
+            Self::Spawn { program: program.to_string(), source }
+        }
+    }
+
+    pub fn command_failed(command: impl Into<String>, output: &Output) -> Self {
+        Self::CommandFailed {
+            command: command.into(),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        }
+    }
+
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        Self::Io { path: path.into(), source }
+    }
+
+    pub fn dbus(context: impl Into<String>, source: zbus::Error) -> Self {
+        Self::DBus { context: context.into(), source: Box::new(source) }
+    }
+
+    // Worth trying again: the tool or service was there but said no. A
+    // missing program, bad output or bad config won't fix itself.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Self::Spawn { .. } | Self::CommandFailed { .. } | Self::Timeout(_) | Self::DBus { .. } | Self::Wayland(_))
+    }
+}
+
+impl fmt::Display for VitaminkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::CommandNotFound { program } => write!(f, "{program} not found — is it installed and on PATH?"),
+            Self::Spawn { program, source } => write!(f, "Failed to run {program}: {source}"),
+            Self::CommandFailed { command, stderr } => write!(f, "{command} failed: {stderr}"),
+            Self::Parse(message) => write!(f, "{message}"),
+            Self::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            Self::Timeout(message) => write!(f, "{message}"),
+            Self::Unavailable(message) => write!(f, "{message}"),
+            Self::Config { path, message } => write!(f, "{}: {message}", path.display()),
+            Self::DBus { context, source } => write!(f, "{context}: {source}"),
+            Self::Wayland(message) => write!(f, "Wayland: {message}"),
+            Self::Transition { step, source, rollback: None } => write!(f, "Couldn't {step}: {source} (rolled back)"),
+            Self::Transition { step, source, rollback: Some(rollback) } => {
+                write!(f, "Couldn't {step}: {source} (rolling back failed too: {rollback})")
+            }
+        }
+    }
+}
+
+impl std::error::Error for VitaminkError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Spawn { source, .. } | Self::Io { source, .. } => Some(source),
+            Self::DBus { source, .. } => Some(source.as_ref()),
+            Self::Transition { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spawn_not_found() {
+        let err = VitaminkError::spawn("kscreen-doctor", io::Error::from(io::ErrorKind::NotFound));
+        assert!(matches!(err, VitaminkError::CommandNotFound { .. }));
+        assert_eq!(err.to_string(), "kscreen-doctor not found — is it installed and on PATH?");
+
+        let err = VitaminkError::spawn("systemctl", io::Error::from(io::ErrorKind::PermissionDenied));
+        assert!(matches!(err, VitaminkError::Spawn { .. }));
+    }
+
+    #[test]
+    fn test_transition_message() {
+        let failed = || Box::new(VitaminkError::Timeout("HDMI-A-1 never became active".to_string()));
+        let err = VitaminkError::Transition { step: "enable the Away outputs".to_string(), source: failed(), rollback: None };
+        assert_eq!(err.to_string(), "Couldn't enable the Away outputs: HDMI-A-1 never became active (rolled back)");
+
+        let rollback = Box::new(VitaminkError::Parse("no saved layout".to_string()));
+        let err = VitaminkError::Transition { step: "start services".to_string(), source: failed(), rollback: Some(rollback) };
+        assert!(err.to_string().ends_with("(rolling back failed too: no saved layout)"));
+    }
+}
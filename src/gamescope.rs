@@ -0,0 +1,107 @@
+// src/gamescope.rs — A gamescope session for Away
+//
+// With `[gamescope] enabled = true`, going Away doesn't just start
+// Sunshine: it also launches a nested gamescope session at the streaming
+// resolution, so a client lands in Steam Big Picture instead of on the
+// desktop:
+//
+//   [gamescope]
+//   enabled = true
+//   command = "gamescope -W {width} -H {height} -r {refresh} -f -e -- steam -gamepadui"
+//
+// The placeholders are filled in from the dummy plug's mode (the client's,
+// with `match_client_mode`). Like the virtual output's helper, the command
+// runs as a transient systemd user unit: a daemon restart while Away finds
+// the session still running instead of starting a second one. Its PID
+// goes to state.json, and AtDesk stops the unit again.
+
+use std::process::Command;
+
+use log::info;
+
+use crate::config::GamescopeConfig;
+use crate::display::ModeTarget;
+use crate::error::{Result, VitaminkError};
+use crate::process;
+use crate::virtual_output;
+
+const UNIT: &str = "vitamink-gamescope";
+
+// Starts the session unless it's already running. Returns its PID (None
+// in dry-run mode, where nothing is started).
+pub fn launch(config: &GamescopeConfig, mode: Option<&ModeTarget>) -> Result<Option<u32>> {
+    let mode = mode.unwrap_or(&virtual_output::DEFAULT_MODE);
+    if let Some(pid) = main_pid()? {
+        info!("→ gamescope already running (pid {pid})");
+        return Ok(Some(pid));
+    }
+
+    info!("→ Launching gamescope at {mode}");
+    let output = process::run(
+        Command::new("systemd-run")
+            .args(["--user", "--collect", "--unit", UNIT, "sh", "-c"])
+            .arg(virtual_output::expand(&config.command, mode)),
+    )?;
+    if !output.status.success() {
+        return Err(VitaminkError::command_failed("systemd-run", &output));
+    }
+    if process::is_dry_run() {
+        return Ok(None);
+    }
+    let pid = main_pid()?;
+    if pid.is_none() {
+        return Err(VitaminkError::CommandFailed {
+            command: config.command.clone(),
+            stderr: format!("exited right after starting (see journalctl --user -u {UNIT})"),
+        });
+    }
+    Ok(pid)
+}
+
+// Ends the session; fine if it isn't running.
+pub fn stop() -> Result<()> {
+    let output = process::run(Command::new("systemctl").args(["--user", "stop", UNIT]))?;
+    // 5: the unit isn't loaded, i.e. already gone.
+    if !output.status.success() && output.status.code() != Some(5) {
+        return Err(VitaminkError::command_failed(format!("systemctl --user stop {UNIT}"), &output));
+    }
+    Ok(())
+}
+
+// The session's PID, None if it isn't running.
+pub fn main_pid() -> Result<Option<u32>> {
+    let output = process::output(Command::new("systemctl").args(["--user", "show", "--property=MainPID", "--value", UNIT]))?;
+    Ok(parse_pid(&String::from_utf8_lossy(&output.stdout)))
+}
+
+// systemd reports 0 for a unit with no running main process.
+fn parse_pid(text: &str) -> Option<u32> {
+    text.trim().parse().ok().filter(|&pid| pid != 0)
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::mock::FakeRunner;
+
+    #[test]
+    fn test_parse_pid() {
+        assert_eq!(parse_pid("4242\n"), Some(4242));
+        assert_eq!(parse_pid("0\n"), None);
+        assert_eq!(parse_pid(""), None);
+    }
+
+    #[test]
+    fn test_launch_reuses_running_session() {
+        let fake = Rc::new(FakeRunner::new());
+        fake.respond("systemctl --user show", 0, "4242\n");
+        let mode = ModeTarget { width: 2560, height: 1440, refresh: Some(120.0) };
+        let pid = process::with_runner(fake.clone(), || launch(&GamescopeConfig::default(), Some(&mode))).unwrap();
+        assert_eq!(pid, Some(4242));
+        assert!(!fake.calls().iter().any(|c| c.starts_with("systemd-run")));
+    }
+}
@@ -0,0 +1,196 @@
+// src/history.rs — what the daemon switched, when, and why
+//
+// Every transition (including failed ones) is appended as a JSON line to
+// `$XDG_STATE_HOME/vitamink/history.jsonl`, next to state.json. Only the
+// last `history_size` entries are kept: once the file holds twice that
+// many it's rewritten with the newest half, so it behaves like a ring
+// buffer without rewriting on every append. `vitamink history [--json]
+// [--limit N]` prints it:
+//
+//   TIME                 FROM    TO      CAUSE     TOOK   RESULT
+//   2026-10-16 03:12:44  AtDesk  Away    schedule  4.2s   ok
+
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::daemon::State;
+use crate::error::{Result, VitaminkError};
+use crate::logging;
+use crate::persist;
+use crate::presence::PresenceBackend;
+use crate::table::Table;
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    // Unix seconds, when the transition started.
+    pub at: u64,
+    pub from: State,
+    pub to: State,
+    pub cause: Cause,
+    pub duration_ms: u64,
+    // Why it failed, if it did.
+    pub error: Option<String>,
+}
+
+// What set the transition off.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Cause {
+    // The presence reading, by backend (or `away_when`).
+    Dpms,
+    Logind,
+    Idle,
+    Camera,
+    Bluetooth,
+    Condition,
+    // ForceAway/ForceDesk over D-Bus (`vitamink ctl`, MQTT, ...).
+    Command,
+    // A timed `[[schedule]]` switch.
+    Schedule,
+    // Outputs coming back after HoldingPattern.
+    Outputs,
+    // A wake packet or Moonlight probe (see wake.rs).
+    Wake,
+    // Away with nobody streaming for `[standby] after`.
+    Standby,
+}
+
+impl Cause {
+    pub fn presence(config: &Config) -> Self {
+        if config.away_when.is_some() {
+            return Cause::Condition;
+        }
+        match config.presence_backend {
+            PresenceBackend::Dpms => Cause::Dpms,
+            PresenceBackend::Logind => Cause::Logind,
+            PresenceBackend::Idle => Cause::Idle,
+            PresenceBackend::Camera => Cause::Camera,
+            PresenceBackend::Bluetooth => Cause::Bluetooth,
+        }
+    }
+}
+
+impl fmt::Display for Cause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Cause::Dpms => "dpms",
+            Cause::Logind => "logind",
+            Cause::Idle => "idle",
+            Cause::Camera => "camera",
+            Cause::Bluetooth => "bluetooth",
+            Cause::Condition => "away_when",
+            Cause::Command => "command",
+            Cause::Schedule => "schedule",
+            Cause::Outputs => "outputs",
+            Cause::Wake => "wake",
+            Cause::Standby => "standby",
+        };
+        f.write_str(name)
+    }
+}
+
+pub fn path() -> PathBuf {
+    persist::path().with_file_name("history.jsonl")
+}
+
+// Appends `entry`, trimming the file to the newest `keep` entries once it
+// has grown to twice that. `keep` = 0 turns history off.
+pub fn record(entry: &Entry, keep: usize) -> Result<()> {
+    if keep == 0 {
+        return Ok(());
+    }
+    let path = path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| VitaminkError::io(dir, e))?;
+    }
+    let line = serde_json::to_string(entry).map_err(|e| VitaminkError::Parse(format!("history: {e}")))?;
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| writeln!(file, "{line}"))
+        .map_err(|e| VitaminkError::io(&path, e))?;
+
+    let entries = read()?;
+    if entries.len() >= keep * 2 {
+        let kept: String = entries[entries.len() - keep..]
+            .iter()
+            .filter_map(|entry| serde_json::to_string(entry).ok())
+            .map(|line| line + "\n")
+            .collect();
+        persist::write_atomic(&path, kept.as_bytes())?;
+    }
+    Ok(())
+}
+
+// Oldest first. Lines that don't parse (a torn write, a newer version)
+// are skipped; a missing file is an empty history.
+pub fn read() -> Result<Vec<Entry>> {
+    let path = path();
+    match fs::read_to_string(&path) {
+        Ok(text) => Ok(parse(&text)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(VitaminkError::io(&path, e)),
+    }
+}
+
+fn parse(text: &str) -> Vec<Entry> {
+    text.lines().filter_map(|line| serde_json::from_str(line).ok()).collect()
+}
+
+pub fn render(entries: &[Entry]) -> String {
+    let mut table = Table::new(&["TIME", "FROM", "TO", "CAUSE", "TOOK", "RESULT"]);
+    for entry in entries {
+        table.add_row(vec![
+            logging::format_local(entry.at as libc::time_t),
+            entry.from.to_string(),
+            entry.to.to_string(),
+            entry.cause.to_string(),
+            format!("{:.1}s", Duration::from_millis(entry.duration_ms).as_secs_f64()),
+            entry.error.clone().unwrap_or_else(|| "ok".to_string()),
+        ]);
+    }
+    table.render()
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(at: u64, to: State, cause: Cause, error: Option<&str>) -> Entry {
+        let from = if to == State::Away { State::AtDesk } else { State::Away };
+        Entry { at, from, to, cause, duration_ms: 4200, error: error.map(Into::into) }
+    }
+
+    #[test]
+    fn test_parse() {
+        let lines: Vec<String> = [entry(100, State::Away, Cause::Schedule, None), entry(200, State::AtDesk, Cause::Dpms, Some("boom"))]
+            .iter()
+            .map(|e| serde_json::to_string(e).unwrap())
+            .collect();
+        let text = format!("{}\n{{\"at\": 15\n{}\n", lines[0], lines[1]);
+
+        let entries = parse(&text);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].cause, Cause::Schedule);
+        assert_eq!(entries[1].error.as_deref(), Some("boom"));
+        assert!(lines[0].contains("\"cause\":\"schedule\""));
+    }
+
+    #[test]
+    fn test_render() {
+        let text = render(&[entry(0, State::Away, Cause::Command, None), entry(60, State::AtDesk, Cause::Condition, Some("Couldn't stop sunshine"))]);
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines[0].starts_with("TIME"));
+        assert!(lines[1].contains("AtDesk  Away    command    4.2s  ok"));
+        assert!(lines[2].ends_with("away_when  4.2s  Couldn't stop sunshine"));
+    }
+}
@@ -0,0 +1,106 @@
+// src/hooks.rs — User scripts around state transitions
+//
+// Each hook is a shell command line (run with `sh -c`), so pipes and
+// `&&` work. Hooks get the transition in environment variables:
+//
+//   VITAMINK_HOOK            pre_away, on_away, pre_desk or on_desk
+//                            ("transition" for `[transition]` commands)
+//   VITAMINK_STATE           the state being entered (Away / AtDesk)
+//   VITAMINK_PREVIOUS_STATE  the state being left (empty at startup)
+//   VITAMINK_MAIN_DISPLAY    the first of config.main_display
+//   VITAMINK_DUMMY_PLUG      the dummy plug connector, if known
+//
+// Hooks never block a transition: a failure or timeout is logged and the
+// next hook runs. Their output goes straight to our stderr (the journal).
+//
+// New Rust concepts in this file:
+//
+// - `Child::try_wait`: checks whether a process has exited without
+//   blocking, which lets us enforce a timeout with a simple loop.
+
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+
+use crate::environment;
+use crate::process;
+
+pub struct Transition<'a> {
+    pub hook: &'a str,
+    pub state: String,
+    pub previous: Option<String>,
+    pub main_display: &'a str,
+    pub dummy_plug: Option<&'a str>,
+}
+
+pub fn run_all(commands: &[String], transition: &Transition, timeout: Duration) {
+    for command in commands {
+        info!("→ Hook {}: {command}", transition.hook);
+        if process::is_dry_run() {
+            continue;
+        }
+        if let Err(message) = run(command, transition, timeout) {
+            warn!("Hook {} failed: {message}", transition.hook);
+        }
+    }
+}
+
+pub fn run(command: &str, transition: &Transition, timeout: Duration) -> Result<(), String> {
+    let mut cmd = Command::new("sh");
+    cmd.args(["-c", command]).envs(env(transition));
+    environment::apply(&mut cmd);
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+
+    let start = Instant::now();
+    loop {
+        match child.try_wait().map_err(|e| e.to_string())? {
+            Some(status) if status.success() => return Ok(()),
+            Some(status) => return Err(status.to_string()),
+            None if start.elapsed() >= timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("killed after {:.0}s", timeout.as_secs_f64()));
+            }
+            None => thread::sleep(Duration::from_millis(50)),
+        }
+    }
+}
+
+fn env(transition: &Transition) -> Vec<(&'static str, String)> {
+    vec![
+        ("VITAMINK_HOOK", transition.hook.to_string()),
+        ("VITAMINK_STATE", transition.state.clone()),
+        ("VITAMINK_PREVIOUS_STATE", transition.previous.clone().unwrap_or_default()),
+        ("VITAMINK_MAIN_DISPLAY", transition.main_display.to_string()),
+        ("VITAMINK_DUMMY_PLUG", transition.dummy_plug.unwrap_or_default().to_string()),
+    ]
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env() {
+        let transition = Transition {
+            hook: "pre_away",
+            state: "Away".into(),
+            previous: None,
+            main_display: "DP-2",
+            dummy_plug: Some("HDMI-A-1"),
+        };
+        let env = env(&transition);
+        assert!(env.contains(&("VITAMINK_HOOK", "pre_away".to_string())));
+        assert!(env.contains(&("VITAMINK_PREVIOUS_STATE", String::new())));
+        assert!(env.contains(&("VITAMINK_DUMMY_PLUG", "HDMI-A-1".to_string())));
+    }
+}
@@ -0,0 +1,322 @@
+// src/http.rs — A small HTTP API on `[http] listen`
+//
+// For a phone's browser or an iOS Shortcut, which can't reach the control
+// socket: with `[http] enabled = true` the daemon answers
+//
+//   GET  /          a page with Away and Desk buttons
+//   GET  /state     {"ok":true,"status":{...}}, as status.json has it
+//   POST /away      {"ok":true}
+//   POST /desk
+//   POST /inhibit   name, reason and seconds, all optional (name defaults
+//                   to "http", 0 seconds holds until released)
+//
+// The inhibit parameters can come in the query string, a form or a JSON
+// body:
+//
+//   curl -X POST -H "Authorization: Bearer $TOKEN" 'http://pc:9189/inhibit?name=dinner&seconds=3600'
+//
+// Requests are handled like the control socket's (see control.rs), so
+// they turn into the same `Command`s and failures get {"ok":false,
+// "error":"..."}. With `token` set, every request needs it, either as
+// `Authorization: Bearer TOKEN` or `?token=TOKEN`; listening anywhere but
+// loopback needs one. There's no TLS, so the token is only as private as
+// the network: keep it to the LAN, or a VPN like WireGuard or Tailscale.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Duration;
+
+use log::{debug, info};
+use serde_json::{Value, json};
+
+use crate::config::HttpConfig;
+use crate::control::{self, Request, Target};
+use crate::daemon::Command;
+
+// Headers and body together; nothing we answer needs more.
+const MAX_REQUEST: u64 = 16 * 1024;
+
+#[derive(Debug, Default, PartialEq)]
+struct HttpRequest {
+    method: String,
+    path: String,
+    // Query string and form or JSON body together, query first.
+    params: Vec<(String, String)>,
+    token: Option<String>,
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl HttpRequest {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+}
+
+// Binds `listen` and answers requests on a background thread, like
+// metrics::serve. Binding errors are returned; per-connection errors are
+// only logged.
+pub fn serve(config: &HttpConfig, commands: Sender<Command>) -> io::Result<()> {
+    let listener = TcpListener::bind(config.listen)?;
+    let token = config.token.clone();
+    thread::Builder::new().name("http".into()).spawn(move || {
+        for stream in listener.incoming() {
+            let result = stream.and_then(|stream| respond(stream, token.as_deref(), &commands));
+            if let Err(e) = result {
+                debug!("HTTP request failed: {e}");
+            }
+        }
+    })?;
+    info!("Serving the HTTP API on http://{}/", config.listen);
+    Ok(())
+}
+
+fn respond(mut stream: TcpStream, token: Option<&str>, commands: &Sender<Command>) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let peer = stream.peer_addr().map_or("?".to_string(), |addr: SocketAddr| addr.ip().to_string());
+    let response = match read_request(BufReader::new(&stream).take(MAX_REQUEST)) {
+        Ok(request) => {
+            debug!("HTTP: {} {} from {peer}", request.method, request.path);
+            answer(&request, token, commands)
+        }
+        Err(e) => json_response("400 Bad Request", json!({ "ok": false, "error": e.to_string() })),
+    };
+    let challenge = if response.status.starts_with("401") { "WWW-Authenticate: Bearer\r\n" } else { "" };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{challenge}Cache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.content_type,
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()
+}
+
+// ---- Requests ----
+
+fn read_request(mut reader: impl BufRead) -> io::Result<HttpRequest> {
+    let invalid = |why: &str| io::Error::new(io::ErrorKind::InvalidData, why.to_string());
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(invalid("not an HTTP request"));
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let mut request = HttpRequest { method: method.to_string(), path: path.to_string(), params: form(query), token: None };
+
+    let mut headers = HashMap::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("the headers end early"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let length: usize = match headers.get("content-length") {
+        Some(length) => length.parse().ok().filter(|&length| length as u64 <= MAX_REQUEST).ok_or_else(|| invalid("bad Content-Length"))?,
+        None => 0,
+    };
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    let body = String::from_utf8(body).map_err(|_| invalid("the body isn't UTF-8"))?;
+    let json = headers.get("content-type").is_some_and(|t| t.starts_with("application/json")) || body.trim_start().starts_with('{');
+    if json && !body.trim().is_empty() {
+        let Ok(Value::Object(fields)) = serde_json::from_str::<Value>(&body) else {
+            return Err(invalid("the body isn't a JSON object"));
+        };
+        for (key, value) in fields {
+            let value = match value {
+                Value::String(text) => text,
+                other => other.to_string(),
+            };
+            request.params.push((key, value));
+        }
+    } else {
+        request.params.extend(form(&body));
+    }
+
+    request.token = headers
+        .get("authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string())
+        .or_else(|| request.param("token").map(str::to_string));
+    Ok(request)
+}
+
+// "a=1&b=two+words" → [("a", "1"), ("b", "two words")].
+fn form(text: &str) -> Vec<(String, String)> {
+    text.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(key), decode(value))
+        })
+        .collect()
+}
+
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = text.get(i + 1..i + 3).filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit())).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'+', _) => out.push(b' '),
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 2;
+            }
+            (byte, _) => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| if b.is_ascii_alphanumeric() || b"-._~".contains(&b) { (b as char).to_string() } else { format!("%{b:02X}") })
+        .collect()
+}
+
+// ---- Answers ----
+
+fn answer(request: &HttpRequest, token: Option<&str>, commands: &Sender<Command>) -> Response {
+    if let Some(token) = token
+        && !request.token.as_deref().is_some_and(|given| same(given, token))
+    {
+        return json_response("401 Unauthorized", json!({ "ok": false, "error": "A token is needed" }));
+    }
+    let control = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/") => return page(token),
+        ("GET", "/state") => Request::Status,
+        ("POST", "/away") => Request::ForceState { state: Target::Away },
+        ("POST", "/desk") => Request::ForceState { state: Target::Desk },
+        ("POST", "/inhibit") => {
+            let seconds = match request.param("seconds").filter(|s| !s.is_empty()) {
+                Some(seconds) => match seconds.parse() {
+                    Ok(seconds) => seconds,
+                    Err(_) => return json_response("400 Bad Request", json!({ "ok": false, "error": format!("seconds: \"{seconds}\" isn't a number") })),
+                },
+                None => 0,
+            };
+            Request::Inhibit {
+                name: request.param("name").unwrap_or("http").to_string(),
+                reason: request.param("reason").unwrap_or_default().to_string(),
+                seconds,
+            }
+        }
+        (_, "/" | "/state" | "/away" | "/desk" | "/inhibit") => {
+            return json_response("405 Method Not Allowed", json!({ "ok": false, "error": format!("{} isn't supported here", request.method) }));
+        }
+        _ => return json_response("404 Not Found", json!({ "ok": false, "error": "Not found; try /state" })),
+    };
+    let result = control::handle(control, commands);
+    let status = if result.is_ok() { "200 OK" } else { "400 Bad Request" };
+    json_response(status, control::reply(result))
+}
+
+// Compares every byte, so how long it takes says nothing about how much
+// of a guess was right.
+fn same(given: &str, token: &str) -> bool {
+    given.len() == token.len() && given.bytes().zip(token.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+fn json_response(status: &'static str, body: Value) -> Response {
+    Response { status, content_type: "application/json", body: format!("{body}\n") }
+}
+
+// Buttons that post back here, carrying the token along.
+fn page(token: Option<&str>) -> Response {
+    let query = token.map_or(String::new(), |token| format!("?token={}", encode(token)));
+    let button = |path: &str, label: &str| format!("<form method=\"post\" action=\"{path}{query}\"><button>{label}</button></form>\n");
+    let body = format!(
+        "<!doctype html>\n<meta name=\"viewport\" content=\"width=device-width\">\n<title>VitaminK</title>\n\
+         <style>button {{ font-size: 2em; width: 100%; margin: 0.3em 0 }}</style>\n{}{}{}<p><a href=\"/state{query}\">State</a></p>\n",
+        button("/away", "Away"),
+        button("/desk", "Desk"),
+        button("/inhibit", "Hold here")
+    );
+    Response { status: "200 OK", content_type: "text/html; charset=utf-8", body }
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    fn request(text: &str) -> HttpRequest {
+        read_request(text.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_read_request() {
+        let parsed = request("POST /inhibit?name=dinner&reason=Eating+out HTTP/1.1\r\nHost: pc\r\nauthorization: Bearer s3cret\r\n\r\n");
+        assert_eq!(parsed.method, "POST");
+        assert_eq!(parsed.path, "/inhibit");
+        assert_eq!(parsed.param("reason"), Some("Eating out"));
+        assert_eq!(parsed.token.as_deref(), Some("s3cret"));
+
+        let json = "{\"name\":\"game\",\"seconds\":60}";
+        let parsed = request(&format!("POST /inhibit?token=a%2Fb HTTP/1.1\r\nContent-Length: {}\r\n\r\n{json}", json.len()));
+        assert_eq!((parsed.param("name"), parsed.param("seconds")), (Some("game"), Some("60")));
+        assert_eq!(parsed.token.as_deref(), Some("a/b"));
+
+        let parsed = request("POST /away HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: 9\r\n\r\nseconds=5");
+        assert_eq!(parsed.param("seconds"), Some("5"));
+
+        assert!(read_request("\r\n".as_bytes()).is_err());
+        assert!(read_request("GET /state HTTP/1.1\r\nHost: pc\r\n".as_bytes()).is_err());
+        assert!(read_request("POST /away HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: 2\r\n\r\n[]".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_answer() {
+        let (tx, rx) = mpsc::channel();
+        let status = |text: &str, token: Option<&str>| answer(&request(text), token, &tx).status;
+
+        assert_eq!(status("POST /away HTTP/1.1\r\n\r\n", Some("s3cret")), "401 Unauthorized");
+        assert_eq!(status("POST /away?token=guess HTTP/1.1\r\n\r\n", Some("s3cret")), "401 Unauthorized");
+        assert!(rx.try_recv().is_err());
+
+        assert_eq!(status("POST /away?token=s3cret HTTP/1.1\r\n\r\n", Some("s3cret")), "200 OK");
+        assert_eq!(rx.try_recv().unwrap(), Command::ForceAway);
+        assert_eq!(status("POST /desk HTTP/1.1\r\nAuthorization: Bearer s3cret\r\n\r\n", Some("s3cret")), "200 OK");
+        assert_eq!(rx.try_recv().unwrap(), Command::ForceDesk);
+
+        assert_eq!(status("POST /inhibit?seconds=3600 HTTP/1.1\r\n\r\n", None), "200 OK");
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            Command::Inhibit { name: "http".to_string(), reason: String::new(), duration: Some(Duration::from_secs(3600)) }
+        );
+        assert_eq!(status("POST /inhibit?seconds=soon HTTP/1.1\r\n\r\n", None), "400 Bad Request");
+        assert_eq!(status("GET /away HTTP/1.1\r\n\r\n", None), "405 Method Not Allowed");
+        assert_eq!(status("GET /reboot HTTP/1.1\r\n\r\n", None), "404 Not Found");
+        assert!(rx.try_recv().is_err());
+
+        let page = answer(&request("GET /?token=s3cret HTTP/1.1\r\n\r\n"), Some("a/b"), &tx);
+        assert_eq!(page.status, "401 Unauthorized");
+        let page = answer(&request("GET /?token=a%2fb HTTP/1.1\r\n\r\n"), Some("a/b"), &tx);
+        assert!(page.body.contains("action=\"/away?token=a%2Fb\""));
+        assert_eq!(decode("100%+sure%2"), "100%+sure%2".replace('+', " "));
+    }
+}
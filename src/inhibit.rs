@@ -0,0 +1,112 @@
+// src/inhibit.rs — Named inhibitors that pause automatic switching
+//
+// `vitamink inhibit --duration 2h "watching movie"` (or the Inhibit D-Bus
+// method) holds the current state: while any inhibitor is held, presence
+// and the schedule don't switch anything. Manual ForceAway/ForceDesk
+// still work. Each inhibitor has a name, so several scripts can hold and
+// release their own without stepping on each other; one without a
+// duration lasts until it's released or the daemon restarts.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::status;
+
+#[derive(Default)]
+pub struct Inhibitors {
+    held: BTreeMap<String, Inhibitor>,
+}
+
+struct Inhibitor {
+    reason: String,
+    until: Option<Instant>,
+}
+
+// How status.json shows one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Held {
+    pub name: String,
+    pub reason: String,
+    // Unix seconds; None until released.
+    pub until: Option<u64>,
+}
+
+impl Inhibitors {
+    // Takes (or renews) `name`. Holding a name again replaces its reason
+    // and duration. One too long to count down is held until released.
+    pub fn hold(&mut self, name: &str, reason: &str, duration: Option<Duration>, now: Instant) {
+        let until = duration.and_then(|d| now.checked_add(d));
+        self.held.insert(name.to_string(), Inhibitor { reason: reason.to_string(), until });
+    }
+
+    // False if nothing was held under `name`.
+    pub fn release(&mut self, name: &str) -> bool {
+        self.held.remove(name).is_some()
+    }
+
+    // Drops the ones whose time is up and returns their names.
+    pub fn expire(&mut self, now: Instant) -> Vec<String> {
+        let expired: Vec<String> =
+            self.held.iter().filter(|(_, i)| i.until.is_some_and(|until| until <= now)).map(|(name, _)| name.clone()).collect();
+        for name in &expired {
+            self.held.remove(name);
+        }
+        expired
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.held.is_empty()
+    }
+
+    // When the next one runs out, so the daemon can wake up for it.
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.held.values().filter_map(|i| i.until).min()
+    }
+
+    // "watching movie, backup" — for log lines.
+    pub fn reasons(&self) -> String {
+        self.held.values().map(|i| i.reason.as_str()).collect::<Vec<_>>().join(", ")
+    }
+
+    pub fn list(&self, now: Instant) -> Vec<Held> {
+        self.held
+            .iter()
+            .map(|(name, i)| Held {
+                name: name.clone(),
+                reason: i.reason.clone(),
+                until: i.until.map(|until| status::unix_secs(SystemTime::now() + until.saturating_duration_since(now))),
+            })
+            .collect()
+    }
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hold_and_expire() {
+        let start = Instant::now();
+        let mut inhibitors = Inhibitors::default();
+        assert!(inhibitors.is_empty());
+
+        inhibitors.hold("movie", "watching movie", Some(Duration::from_secs(7200)), start);
+        inhibitors.hold("backup", "backup", None, start);
+        assert_eq!(inhibitors.reasons(), "backup, watching movie");
+        assert!(inhibitors.expire(start + Duration::from_secs(3600)).is_empty());
+        assert_eq!(inhibitors.next_expiry(), Some(start + Duration::from_secs(7200)));
+
+        // Renewing replaces the old duration.
+        inhibitors.hold("movie", "watching another movie", Some(Duration::from_secs(60)), start + Duration::from_secs(3600));
+        assert_eq!(inhibitors.expire(start + Duration::from_secs(3660)), ["movie"]);
+        assert_eq!(inhibitors.list(start)[0].until, None);
+
+        assert!(!inhibitors.release("movie"));
+        assert!(inhibitors.release("backup"));
+        assert!(inhibitors.is_empty());
+    }
+}
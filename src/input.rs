@@ -0,0 +1,304 @@
+// src/input.rs — Local input gating while streaming (evdev grabs)
+//
+// New Rust concepts in this file:
+//
+// - `unsafe` + `libc`: the EVIOCGRAB ioctl has no safe std wrapper, so we
+//   call the C function directly. `unsafe` marks the spot where we promise
+//   the compiler the arguments are valid (an open fd and a plain integer).
+//
+// - `Arc<T>`: a reference-counted pointer that can be shared between threads.
+//   The panic-key watcher threads and the `InputGate` all hold the same
+//   `Arc<Shared>`, and it's freed when the last one goes away.
+//
+// - `AtomicBool`: a bool that many threads can read/write without a Mutex.
+//   `swap` sets a new value and returns the old one in a single step, which
+//   makes "release exactly once" easy.
+//
+// - `impl Drop`: runs when a value goes out of scope — like a destructor.
+//   Dropping the `InputGate` always gives the devices back.
+//
+// An EVIOCGRAB grab makes the kernel deliver a device's events only to us,
+// so KWin (and therefore the desktop) stops seeing them. Reading
+// /dev/input/event* requires membership in the `input` group.
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::Read;
+use std::os::fd::AsRawFd;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use log::{info, warn};
+
+use crate::config::InputGatingConfig;
+use crate::error::{Result, VitaminkError};
+
+// _IOW('E', 0x90, int) from linux/input.h
+const EVIOCGRAB: libc::c_ulong = 0x4004_4590;
+
+const EV_KEY: u16 = 0x01;
+const EV_REL: u64 = 1 << 0x02;
+const EV_KEY_BIT: u64 = 1 << 0x01;
+const EV_REP: u64 = 1 << 0x14;
+
+// ---- Device Discovery ----
+
+#[derive(Debug, PartialEq)]
+pub struct InputDevice {
+    pub name: String,
+    // Handler name, e.g. "event3" → /dev/input/event3
+    pub event_node: String,
+    pub keyboard: bool,
+    pub pointer: bool,
+}
+
+pub fn list_devices() -> Result<Vec<InputDevice>> {
+    let path = "/proc/bus/input/devices";
+    let raw = fs::read_to_string(path).map_err(|e| VitaminkError::io(path, e))?;
+    Ok(parse_devices(&raw))
+}
+
+// /proc/bus/input/devices has one blank-line-separated block per device:
+//   N: Name="Logitech USB Receiver"
+//   H: Handlers=sysrq kbd event3 leds
+//   B: EV=120013
+fn parse_devices(input: &str) -> Vec<InputDevice> {
+    let mut devices = Vec::new();
+
+    for block in input.split("\n\n") {
+        let mut name = String::new();
+        let mut handlers: Vec<&str> = Vec::new();
+        let mut ev_bits: u64 = 0;
+
+        for line in block.lines() {
+            if let Some(rest) = line.strip_prefix("N: Name=") {
+                name = rest.trim_matches('"').to_string();
+            } else if let Some(rest) = line.strip_prefix("H: Handlers=") {
+                handlers = rest.split_whitespace().collect();
+            } else if let Some(rest) = line.strip_prefix("B: EV=") {
+                ev_bits = u64::from_str_radix(rest.trim(), 16).unwrap_or(0);
+            }
+        }
+
+        let Some(event_node) = handlers.iter().find(|h| h.starts_with("event")) else {
+            continue;
+        };
+
+        // Power buttons and media remotes report EV_KEY too; key repeat
+        // (EV_REP) is what real keyboards have in common.
+        let keyboard = ev_bits & EV_KEY_BIT != 0 && ev_bits & EV_REP != 0;
+        let pointer = ev_bits & EV_REL != 0 || handlers.iter().any(|h| h.starts_with("mouse"));
+
+        devices.push(InputDevice {
+            name,
+            event_node: event_node.to_string(),
+            keyboard,
+            pointer,
+        });
+    }
+
+    devices
+}
+
+// ---- Panic Keys ----
+
+// Just the keys that make sense in a panic chord. Anything else can be given
+// as a raw keycode number.
+fn key_code(name: &str) -> Option<u16> {
+    let code = match name {
+        "KEY_ESC" => 1,
+        "KEY_BACKSPACE" => 14,
+        "KEY_LEFTCTRL" => 29,
+        "KEY_LEFTSHIFT" => 42,
+        "KEY_RIGHTSHIFT" => 54,
+        "KEY_LEFTALT" => 56,
+        "KEY_SCROLLLOCK" => 70,
+        "KEY_RIGHTCTRL" => 97,
+        "KEY_RIGHTALT" => 100,
+        "KEY_DELETE" => 111,
+        "KEY_PAUSE" => 119,
+        "KEY_LEFTMETA" => 125,
+        "KEY_RIGHTMETA" => 126,
+        _ => return name.parse().ok(),
+    };
+    Some(code)
+}
+
+fn parse_panic_keys(names: &[String]) -> Result<Vec<u16>> {
+    names
+        .iter()
+        .map(|n| key_code(n).ok_or_else(|| VitaminkError::Parse(format!("Unknown panic key: {n}"))))
+        .collect()
+}
+
+// ---- Gate ----
+
+struct Shared {
+    devices: Vec<File>,
+    released: AtomicBool,
+}
+
+impl Shared {
+    // Safe to call from any thread, any number of times.
+    fn release(&self) {
+        if self.released.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        for device in &self.devices {
+            set_grab(device, false);
+        }
+    }
+}
+
+fn set_grab(device: &File, grab: bool) -> bool {
+    let value: libc::c_int = if grab { 1 } else { 0 };
+    // SAFETY: the fd is owned by `device` and stays open for the call;
+    // EVIOCGRAB takes its argument by value.
+    unsafe { libc::ioctl(device.as_raw_fd(), EVIOCGRAB as _, value) == 0 }
+}
+
+// Holds the grabbed devices. Input comes back when this is dropped.
+pub struct InputGate {
+    shared: Arc<Shared>,
+}
+
+impl InputGate {
+    pub fn engage(config: &InputGatingConfig) -> Result<Self> {
+        let panic_keys = parse_panic_keys(&config.panic_keys)?;
+        let candidates: Vec<InputDevice> = list_devices()?
+            .into_iter()
+            .filter(|d| (config.keyboards && d.keyboard) || (config.pointers && d.pointer))
+            .collect();
+
+        let mut devices = Vec::new();
+        let mut keyboards = Vec::new();
+
+        for candidate in &candidates {
+            let path = format!("/dev/input/{}", candidate.event_node);
+            let file = File::open(&path).map_err(|e| VitaminkError::io(&path, e))?;
+
+            if !set_grab(&file, true) {
+                warn!("Could not grab {} ({path}), skipping", candidate.name);
+                continue;
+            }
+
+            info!("Grabbed input: {} ({path})", candidate.name);
+            if candidate.keyboard {
+                keyboards.push(file.try_clone().map_err(|e| VitaminkError::io(&path, e))?);
+            }
+            devices.push(file);
+        }
+
+        let shared = Arc::new(Shared { devices, released: AtomicBool::new(false) });
+
+        for keyboard in keyboards {
+            let shared = Arc::clone(&shared);
+            let panic_keys = panic_keys.clone();
+            thread::spawn(move || watch_panic_keys(keyboard, &panic_keys, &shared));
+        }
+
+        Ok(Self { shared })
+    }
+}
+
+impl Drop for InputGate {
+    fn drop(&mut self) {
+        self.shared.release();
+    }
+}
+
+// Reads a grabbed keyboard (we're the only reader now) and releases every
+// device once the whole panic chord is held down.
+fn watch_panic_keys(mut keyboard: File, panic_keys: &[u16], shared: &Shared) {
+    let event_size = std::mem::size_of::<libc::input_event>();
+    let mut buf = vec![0u8; event_size];
+    let mut held: HashSet<u16> = HashSet::new();
+
+    while !shared.released.load(Ordering::SeqCst) {
+        // Wait with a timeout so the thread notices a release from elsewhere.
+        let mut pfd = libc::pollfd { fd: keyboard.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+        // SAFETY: `pfd` is a valid pollfd and we pass a count of 1.
+        let ready = unsafe { libc::poll(&mut pfd, 1, 250) };
+        if ready <= 0 {
+            continue;
+        }
+
+        if keyboard.read_exact(&mut buf).is_err() {
+            return;
+        }
+
+        // The struct starts with a timeval whose size varies by platform,
+        // but it always ends with type (u16), code (u16), value (i32).
+        let tail = &buf[event_size - 8..];
+        let kind = u16::from_ne_bytes([tail[0], tail[1]]);
+        let code = u16::from_ne_bytes([tail[2], tail[3]]);
+        let value = i32::from_ne_bytes([tail[4], tail[5], tail[6], tail[7]]);
+
+        if kind != EV_KEY {
+            continue;
+        }
+        match value {
+            0 => {
+                held.remove(&code);
+            }
+            _ => {
+                held.insert(code);
+            }
+        }
+
+        if !panic_keys.is_empty() && panic_keys.iter().all(|k| held.contains(k)) {
+            warn!("Panic keys pressed, releasing local input");
+            shared.release();
+        }
+    }
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_devices() {
+        let input = "\
+I: Bus=0019 Vendor=0000 Product=0001 Version=0000
+N: Name=\"Power Button\"
+H: Handlers=kbd event0
+B: EV=3
+
+I: Bus=0003 Vendor=046d Product=c52b Version=0111
+N: Name=\"Logitech USB Receiver\"
+H: Handlers=sysrq kbd leds event3
+B: EV=120013
+
+I: Bus=0003 Vendor=046d Product=c52b Version=0111
+N: Name=\"Logitech USB Receiver Mouse\"
+H: Handlers=mouse0 event4
+B: EV=17
+";
+
+        let devices = parse_devices(input);
+        assert_eq!(devices.len(), 3);
+
+        assert_eq!(devices[0].name, "Power Button");
+        assert!(!devices[0].keyboard);
+        assert!(!devices[0].pointer);
+
+        assert_eq!(devices[1].event_node, "event3");
+        assert!(devices[1].keyboard);
+        assert!(!devices[1].pointer);
+
+        assert_eq!(devices[2].event_node, "event4");
+        assert!(!devices[2].keyboard);
+        assert!(devices[2].pointer);
+    }
+
+    #[test]
+    fn test_parse_panic_keys() {
+        let keys = vec!["KEY_LEFTCTRL".to_string(), "KEY_PAUSE".to_string(), "88".to_string()];
+        assert_eq!(parse_panic_keys(&keys).unwrap(), vec![29, 119, 88]);
+        assert!(parse_panic_keys(&["KEY_NOPE".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,344 @@
+// src/install.rs — `vitamink install`: set up the user service in one go
+//
+// Turns the setup — unit file, config, enabling, checking for Sunshine —
+// into one command:
+//
+//   ✓ systemd unit — wrote ~/.config/systemd/user/vitamink.service
+//   ✓ Config file — wrote ~/.config/vitamink/config.toml (everything commented out)
+//   ✓ Unit enabled — starts with the graphical session
+//   ✗ sunshine unit installed — systemd doesn't know it
+//       → Install Sunshine, or point [[services]] at the unit you have
+//
+// An existing unit is only replaced with `--force`, and an existing
+// config never is. `--linger` also runs `loginctl enable-linger`, so the
+// user manager (and the daemon) keeps running without a login session.
+// The daemon isn't started: the config's output names need checking
+// first (`vitamink displays`).
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::{self, Config};
+use crate::doctor::Check;
+use crate::error::{Result, VitaminkError};
+use crate::process;
+use crate::services;
+
+pub const UNIT: &str = "vitamink.service";
+
+pub struct Options {
+    // Replace an existing unit file.
+    pub force: bool,
+    pub linger: bool,
+}
+
+// A starting point for config.toml: the common settings at their
+// defaults, commented out.
+pub const DEFAULT_CONFIG: &str = r#"# VitaminK — see `vitamink doctor` after changing anything here.
+# Every setting is optional; the values shown are the defaults.
+
+# The monitor(s) at your desk, and the output Sunshine streams from. Run
+# `vitamink displays` to see the names.
+# main_display = "DP-2"
+# dummy_plug = "HDMI-A-1"
+# How the desk turns the dummy plug off: "disable", or "dpms-off" to only
+# blank it so windows stay where they are.
+# away_outputs_off = "disable"
+# The primary output while Away; unset keeps the desk's if it stays on.
+# away_primary = "DP-2"
+# A layout saved with `vitamink layout save NAME` to come back to, rather
+# than the one from before going Away.
+# desk_layout = "docked"
+
+# How presence is detected: "dpms", "logind", "idle", "camera" or "bluetooth".
+# presence_backend = "dpms"
+# Not Away while the keyboard or mouse was used this recently (try
+# "30s" if your monitor reports DPMS off while in use); 0s doesn't check.
+# recent_input = "0s"
+# grace_period = "10s"
+# cooldown = "0s"
+# override_timeout = "0s"
+# poll_interval = "5s"
+# max_poll_interval = "20s"
+# How long to wait at login for the compositor to be up.
+# startup_timeout = "60s"
+
+# What stopping the daemon while Away does: "persist" or "restore".
+# on_shutdown = "persist"
+# What outputs or services changed by hand while Away do: "adopt",
+# "reapply" or "pause".
+# on_drift = "adopt"
+# command_timeout = "30s"
+# The log as one JSON object per line, for Loki and the like: "text" or "json".
+# log_format = "text"
+# Appends the JSON lines to a file too, whatever log_format is.
+# log_file = "~/.local/state/vitamink/vitamink.jsonl"
+
+# Which login session to automate on a machine with several; going Away
+# waits while another user is active at the seat.
+# [seat]
+# seat = "seat0"
+# session_types = ["wayland", "x11"]
+# yield_to_other_users = true
+
+# Laptops: whether to go Away on battery, and a power-profiles-daemon
+# profile to use while Away.
+# [power]
+# away_on_battery = true
+# profile = "performance"
+
+# [sunshine]
+# block_desk_while_streaming = true
+# The web UI's login, for `vitamink sunshine` and [apps].
+# api_username = "admin"
+# api_password = "password"
+# Put the dummy plug in each Moonlight client's mode, and back once it disconnects.
+# match_client_mode = false
+# encoder_check = "warn"
+# drain_timeout = "0s"
+# What an error in Sunshine's log does while Away: "notify", "restart",
+# "reapply" or "off".
+# on_error = "notify"
+
+# Start one of Sunshine's apps when going Away, and close it at the desk.
+# Needs the api_username and api_password above.
+# [apps]
+# launch = "Steam Big Picture"
+# close_on_desk = true
+
+# [notifications]
+# enabled = false
+
+# Mute the mic, cut off webcams, and lock the desk's session before
+# Sunshine starts, so nobody at the desk can use the streamed desktop.
+# [privacy]
+# mute_microphone = true
+# webcam_usb_devices = ["1-4"]
+# lock_session = true
+# lock_timeout = "5s"
+
+# Put KDE's windows back where they were after Away, once KWin has had
+# `settle` to rearrange them itself.
+# [windows]
+# restore = true
+# settle = "2s"
+
+# GET /state, POST /away, /desk and /inhibit for a phone or a shortcut.
+# Listening beyond loopback ("0.0.0.0:9189") needs a token.
+# [http]
+# enabled = false
+# listen = "127.0.0.1:9189"
+# token = "a-long-random-string"
+
+# Other PCs with [http] on, for `--host den-pc` and `vitamink fleet status`.
+# [hosts.den-pc]
+# url = "http://den-pc:9189"
+# token = "den-pc-token"
+
+# Posts transitions and errors to a URL; format is "generic", "telegram"
+# (with chat_id) or "discord".
+# [[webhooks]]
+# url = "https://example.com/hook"
+# format = "generic"
+
+# A mode the dummy plug doesn't advertise, added while Away and removed
+# at the desk (KDE Plasma 6.2+ or X11); pick it with dummy_mode.
+# [[custom_modes]]
+# output = "HDMI-A-1"
+# mode = "1920x1080@120"
+# reduced_blanking = false
+
+# What the commands VitaminK runs get in their environment. Session
+# variables our own environment lacks (XDG_RUNTIME_DIR,
+# DBUS_SESSION_BUS_ADDRESS, WAYLAND_DISPLAY, DISPLAY) are detected.
+# [environment]
+# detect = true
+# inherit = ["PATH", "HOME"]
+# set = { PULSE_SERVER = "unix:/run/user/1000/pulse/native" }
+
+# When polls keep failing: how many in a row before looking the session
+# and outputs up afresh, and before exiting for systemd to restart the
+# daemon (0 for never), and how often the same error is logged again.
+# [recovery]
+# after = 10
+# exit_after = 0
+# log_every = "5m"
+
+# Hold off going Away while the machine is busy; 0 turns each check off.
+# [load]
+# cpu_above = 90
+# gpu_above = 80
+# min_free_gb = 20.0
+# disk_path = "~/Videos"
+
+# [hooks]
+# on_away = []
+# on_desk = []
+
+# The order going Away runs its steps in, with commands ("run ...") and
+# pauses ("wait 2s") in between. Every step once; desk defaults to the
+# steps backwards.
+# [transition]
+# away = ["privacy", "layout", "outputs", "wait 2s", "services", "gamescope", "app", "desktop", "compositor", "power", "audio", "input"]
+# timeout = "30s"
+"#;
+
+// What `systemctl --user cat vitamink` shows; see systemd.rs for the
+// notify protocol it relies on.
+pub fn unit_text(exe: &Path) -> String {
+    format!(
+        "[Unit]\n\
+         Description=VitaminK: stream with Sunshine while away from the desk\n\
+         PartOf=graphical-session.target\n\
+         After=graphical-session.target\n\
+         \n\
+         [Service]\n\
+         Type=notify\n\
+         ExecStart={} daemon\n\
+         ExecReload=kill -HUP $MAINPID\n\
+         WatchdogSec=30\n\
+         Restart=on-failure\n\
+         \n\
+         [Install]\n\
+         WantedBy=graphical-session.target\n",
+        exe.display()
+    )
+}
+
+// `$XDG_CONFIG_HOME/systemd/user/vitamink.service`, falling back to
+// `~/.config`.
+pub fn unit_path() -> PathBuf {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_else(|| PathBuf::from("."));
+
+    base.join("systemd").join("user").join(UNIT)
+}
+
+// Carries on past failures, so one run reports everything that's left to
+// do.
+pub fn run(options: &Options) -> Vec<Check> {
+    let mut checks = vec![write_unit(options.force), write_config()];
+    checks.push(enable_unit());
+    if options.linger {
+        checks.push(enable_linger());
+    }
+    // What the daemon would start, going by the config now on disk.
+    let config = config::load().unwrap_or_else(|_| Config::default());
+    if let Some(sunshine) = services::sunshine(&config.services) {
+        let name = format!("{} unit installed", sunshine.unit);
+        checks.push(if services::is_installed(sunshine) {
+            Check::pass(name, "loaded")
+        } else {
+            Check::fail(name, "systemd doesn't know it", "Install Sunshine, or point [[services]] at the unit you have")
+        });
+    }
+    checks
+}
+
+fn write_unit(force: bool) -> Check {
+    let name = "systemd unit";
+    let path = unit_path();
+    if path.exists() && !force {
+        return Check::pass(name, format!("{} already exists (--force replaces it)", path.display()));
+    }
+    let exe = match env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => return Check::fail(name, format!("can't tell where vitamink is: {e}"), "Run it by its full path"),
+    };
+    match write(&path, &unit_text(&exe)) {
+        Ok(()) => Check::pass(name, format!("wrote {}", path.display())),
+        Err(e) => Check::fail(name, e.to_string(), "Check the directory's permissions"),
+    }
+}
+
+fn write_config() -> Check {
+    let name = "Config file";
+    let path = config::default_path();
+    if path.exists() {
+        return Check::pass(name, format!("{} already exists, left as it is", path.display()));
+    }
+    match write(&path, DEFAULT_CONFIG) {
+        Ok(()) => Check::pass(name, format!("wrote {} (everything commented out)", path.display())),
+        Err(e) => Check::fail(name, e.to_string(), "Check the directory's permissions"),
+    }
+}
+
+fn write(path: &Path, text: &str) -> Result<()> {
+    if process::is_dry_run() {
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| VitaminkError::io(dir, e))?;
+    }
+    fs::write(path, text).map_err(|e| VitaminkError::io(path, e))
+}
+
+fn enable_unit() -> Check {
+    let name = "Unit enabled";
+    let result = systemctl(&["daemon-reload"]).and_then(|()| systemctl(&["enable", UNIT]));
+    match result {
+        Ok(()) => Check::pass(name, "starts with the graphical session"),
+        Err(e) => Check::fail(name, e.to_string(), "Is the systemd user manager running? (`systemctl --user status`)"),
+    }
+}
+
+fn enable_linger() -> Check {
+    let name = "Lingering";
+    let user = env::var("USER").unwrap_or_default();
+    let result = process::run(Command::new("loginctl").args(["enable-linger", &user]));
+    match result {
+        Ok(output) if output.status.success() => Check::pass(name, format!("enabled for {user}")),
+        Ok(output) => Check::fail(
+            name,
+            VitaminkError::command_failed("loginctl enable-linger", &output).to_string(),
+            "Your polkit rules may not allow it; try `sudo loginctl enable-linger $USER`",
+        ),
+        Err(e) => Check::fail(name, e.to_string(), "Is systemd-logind running?"),
+    }
+}
+
+fn systemctl(args: &[&str]) -> Result<()> {
+    let output = process::run(Command::new("systemctl").arg("--user").args(args))?;
+    if !output.status.success() {
+        return Err(VitaminkError::command_failed(format!("systemctl --user {}", args.join(" ")), &output));
+    }
+    Ok(())
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_config() {
+        assert!(config::parse(DEFAULT_CONFIG).is_ok());
+
+        // Uncommenting the settings gives the defaults they claim to be.
+        let uncommented: String = DEFAULT_CONFIG
+            .lines()
+            .map(|line| match line.strip_prefix("# ") {
+                Some(setting) if setting.starts_with('[') || setting.contains(" = ") => setting,
+                _ => line,
+            })
+            .map(|line| format!("{line}\n"))
+            .collect();
+        let config = config::parse(&uncommented).unwrap();
+        let defaults = Config::default();
+        assert_eq!(config.main_display, defaults.main_display);
+        assert_eq!(config.grace_period, defaults.grace_period);
+        assert_eq!(config.command_timeout, defaults.command_timeout);
+    }
+
+    #[test]
+    fn test_unit_text() {
+        let unit = unit_text(Path::new("/home/me/.cargo/bin/vitamink"));
+        assert!(unit.contains("ExecStart=/home/me/.cargo/bin/vitamink daemon\n"));
+        assert!(unit.contains("Type=notify\n"));
+    }
+}
@@ -0,0 +1,120 @@
+// src/instance.rs — One daemon at a time
+//
+// Two daemons (say the systemd unit and one started by hand) fight over
+// the dummy plug, each undoing the other's transitions. So the daemon
+// holds an exclusive flock on `$XDG_RUNTIME_DIR/vitamink/daemon.lock` for
+// as long as it runs, with its pid written inside; a second one finds it
+// taken and exits, naming that pid. `vitamink daemon --replace` instead
+// sends the running one SIGTERM — it shuts down as `on_shutdown` says —
+// and takes over once the lock is free.
+//
+// The kernel drops a flock when its holder exits, however that happens,
+// so a crash never leaves a stale lock behind.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::info;
+
+use crate::error::{Result, VitaminkError};
+use crate::status;
+
+// How long `--replace` waits for the old daemon, which may be switching
+// back to AtDesk on its way out.
+pub const REPLACE_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Held until dropped (or the process exits).
+pub struct Lock {
+    _file: File,
+}
+
+// Next to status.json.
+pub fn path() -> PathBuf {
+    status::path().with_file_name("daemon.lock")
+}
+
+// None if another daemon has it.
+pub fn try_lock() -> Result<Option<Lock>> {
+    lock_at(&path())
+}
+
+// The pid of the daemon holding the lock, if it wrote one.
+pub fn holder() -> Option<u32> {
+    holder_at(&path())
+}
+
+// Asks the daemon holding the lock to stop, then takes it.
+pub fn replace(timeout: Duration) -> Result<Lock> {
+    let started = Instant::now();
+    let mut signalled = false;
+    loop {
+        if let Some(lock) = try_lock()? {
+            return Ok(lock);
+        }
+        if !signalled && let Some(pid) = holder() {
+            info!("Asking the running daemon (pid {pid}) to stop");
+            // SAFETY: kill() has no memory-safety preconditions.
+            if unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) } != 0 {
+                return Err(VitaminkError::io(path(), io::Error::last_os_error()));
+            }
+            signalled = true;
+        }
+        if started.elapsed() >= timeout {
+            return Err(VitaminkError::Timeout(format!("The running daemon didn't stop within {}s", timeout.as_secs())));
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+}
+
+fn lock_at(path: &Path) -> Result<Option<Lock>> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| VitaminkError::io(dir, e))?;
+    }
+    // Not truncated on open: until we hold the lock, the pid in it is the
+    // holder's.
+    let mut file =
+        OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path).map_err(|e| VitaminkError::io(path, e))?;
+
+    // SAFETY: the fd is valid for as long as `file` lives.
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        let e = io::Error::last_os_error();
+        return match e.kind() {
+            io::ErrorKind::WouldBlock => Ok(None),
+            _ => Err(VitaminkError::io(path, e)),
+        };
+    }
+
+    file.set_len(0).and_then(|()| writeln!(file, "{}", std::process::id())).map_err(|e| VitaminkError::io(path, e))?;
+    Ok(Some(Lock { _file: file }))
+}
+
+fn holder_at(path: &Path) -> Option<u32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn test_lock() {
+        let path = env::temp_dir().join(format!("vitamink-lock-{}", std::process::id())).join("daemon.lock");
+
+        let lock = lock_at(&path).unwrap().expect("nobody holds it yet");
+        assert_eq!(holder_at(&path), Some(std::process::id()));
+        // A separate open counts as another holder, even in this process.
+        assert!(lock_at(&path).unwrap().is_none());
+
+        drop(lock);
+        assert!(lock_at(&path).unwrap().is_some());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
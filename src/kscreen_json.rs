@@ -0,0 +1,197 @@
+// src/kscreen_json.rs — Parsing `kscreen-doctor -j` output
+//
+// The JSON dump comes from libkscreen's ConfigSerializer, which has been
+// stable across Plasma versions, unlike the human-readable `-o` output the
+// text parser in display.rs has to keep up with.
+//
+// New Rust concepts in this file:
+//
+// - `#[serde(rename_all = "camelCase")]`: the JSON uses `currentModeId`,
+//   we use `current_mode_id`. serde maps between them.
+//
+// - Unknown JSON fields are ignored by default, so new fields in future
+//   Plasma versions won't break parsing.
+
+use serde::Deserialize;
+
+use crate::display::{Color, ConnectionState, Display, DisplayState, Geometry, Mode, Rotation, Vrr};
+use crate::error::{Result, VitaminkError};
+
+#[derive(Deserialize)]
+struct Config {
+    outputs: Vec<Output>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Output {
+    id: u32,
+    name: String,
+    enabled: bool,
+    connected: bool,
+    #[serde(default)]
+    current_mode_id: String,
+    #[serde(default)]
+    preferred_modes: Vec<String>,
+    #[serde(default)]
+    modes: Vec<JsonMode>,
+    pos: Option<Point>,
+    // Pixel size of the current mode; the text output shows logical size.
+    size: Option<Size>,
+    scale: Option<f64>,
+    priority: Option<u32>,
+    // Plasma 6 and later; absent on outputs that can't.
+    hdr: Option<bool>,
+    wcg: Option<bool>,
+    // 0 = automatic.
+    max_bpc: Option<u32>,
+    // 0 = never, 1 = always, 2 = automatic.
+    vrr_policy: Option<u32>,
+    // KScreen's rotation flags: 1, 2, 4 or 8.
+    rotation: Option<u32>,
+    overscan: Option<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonMode {
+    id: String,
+    size: Size,
+    refresh_rate: f64,
+}
+
+#[derive(Deserialize)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+#[derive(Deserialize)]
+struct Size {
+    width: u32,
+    height: u32,
+}
+
+pub fn parse(json: &str) -> Result<Vec<Display>> {
+    let config: Config =
+        serde_json::from_str(json).map_err(|e| VitaminkError::Parse(format!("Invalid kscreen-doctor JSON: {e}")))?;
+
+    config.outputs.into_iter().map(convert).collect()
+}
+
+fn convert(output: Output) -> Result<Display> {
+    let mut modes = Vec::new();
+    for m in &output.modes {
+        let id = m
+            .id
+            .parse()
+            .map_err(|_| VitaminkError::Parse(format!("Invalid mode id in {}: {}", output.name, m.id)))?;
+        modes.push(Mode {
+            id,
+            width: m.size.width,
+            height: m.size.height,
+            refresh: m.refresh_rate,
+            preferred: output.preferred_modes.contains(&m.id),
+            current: m.id == output.current_mode_id,
+        });
+    }
+
+    // Match the text output: logical size = pixel size / scale, turned
+    // with the output.
+    let scale = output.scale.unwrap_or(1.0);
+    let rotation = output.rotation.and_then(Rotation::from_kscreen);
+    let geometry = match (&output.pos, &output.size) {
+        (Some(pos), Some(size)) if output.enabled => {
+            let (width, height) =
+                if rotation.is_some_and(Rotation::is_sideways) { (size.height, size.width) } else { (size.width, size.height) };
+            Some(Geometry {
+                x: pos.x,
+                y: pos.y,
+                width: (f64::from(width) / scale).round() as u32,
+                height: (f64::from(height) / scale).round() as u32,
+            })
+        }
+        _ => None,
+    };
+
+    Ok(Display {
+        index: output.id,
+        name: output.name,
+        // The serializer doesn't include the EDID-derived UUID.
+        uuid: None,
+        state: if output.enabled { DisplayState::Enabled } else { DisplayState::Disabled },
+        connection: if output.connected { ConnectionState::Connected } else { ConnectionState::Disconnected },
+        modes,
+        geometry,
+        scale: output.scale,
+        priority: output.priority,
+        color: Color { hdr: output.hdr, wide_gamut: output.wcg, bit_depth: output.max_bpc.filter(|&bpc| bpc > 0) },
+        vrr: match output.vrr_policy {
+            Some(0) => Some(Vrr::Never),
+            Some(1) => Some(Vrr::Always),
+            Some(2) => Some(Vrr::Automatic),
+            _ => None,
+        },
+        rotation,
+        overscan: output.overscan,
+    })
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let json = r#"{
+  "outputs": [
+    {
+      "id": 1, "name": "HDMI-A-1", "enabled": false, "connected": true,
+      "currentModeId": "", "preferredModes": ["2"],
+      "modes": [
+        {"id": "1", "name": "640x480@60", "refreshRate": 60.0, "size": {"width": 640, "height": 480}},
+        {"id": "2", "name": "3840x2160@60", "refreshRate": 60.0, "size": {"width": 3840, "height": 2160}}
+      ],
+      "pos": {"x": 0, "y": 0}, "scale": 1, "priority": 0, "type": 11
+    },
+    {
+      "id": 2, "name": "DP-2", "enabled": true, "connected": true,
+      "currentModeId": "3", "preferredModes": ["3"],
+      "modes": [{"id": "3", "refreshRate": 240.02, "size": {"width": 3840, "height": 2160}}],
+      "pos": {"x": 1920, "y": 0}, "size": {"width": 3840, "height": 2160},
+      "scale": 1.5, "priority": 1, "followPreferredMode": false,
+      "hdr": true, "wcg": true, "maxBpc": 0, "vrrPolicy": 2, "rotation": 2, "overscan": 0
+    }
+  ],
+  "screen": {"id": 0}
+}"#;
+
+        let displays = parse(json).unwrap();
+        assert_eq!(displays.len(), 2);
+
+        assert_eq!(displays[0].name, "HDMI-A-1");
+        assert_eq!(displays[0].state, DisplayState::Disabled);
+        assert_eq!(displays[0].connection, ConnectionState::Connected);
+        assert!(displays[0].modes[1].preferred);
+        assert!(!displays[0].modes.iter().any(|m| m.current));
+        assert_eq!(displays[0].geometry, None);
+
+        assert_eq!(displays[1].modes[0].id, 3);
+        assert!(displays[1].modes[0].current);
+        assert_eq!(displays[1].geometry, Some(Geometry { x: 1920, y: 0, width: 1440, height: 2560 }));
+        assert_eq!(displays[1].rotation, Some(Rotation::Left));
+        assert_eq!(displays[1].overscan, Some(0));
+        assert_eq!(displays[1].priority, Some(1));
+        assert_eq!(displays[1].color, Color { hdr: Some(true), wide_gamut: Some(true), bit_depth: None });
+        assert_eq!(displays[1].vrr, Some(Vrr::Automatic));
+        assert_eq!(displays[0].vrr, None);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(parse("Output: 1 DP-2 uuid").is_err());
+        assert!(parse(r#"{"outputs": [{"id": 1}]}"#).is_err());
+    }
+}
@@ -0,0 +1,578 @@
+// src/layout.rs — Snapshot and restore of the full output layout
+//
+// Disabling and re-enabling outputs makes KWin re-place them, and it
+// doesn't always put things back where they were — the main monitor ends
+// up on the wrong side, or the dummy plug becomes primary. We record
+// everything the display backend tells us before going Away and replay it on
+// return, in a single configuration change.
+//
+// Things can change while we're Away: a monitor gets swapped, or a dock
+// renumbers the modes. A checksum of every output's mode list is taken with
+// the snapshot; if it no longer matches, the layout is checked against what
+// is there now and patched up (with a warning for each change) rather than
+// failing and leaving every screen dark.
+//
+// The same snapshot can be kept under a name, for docking and undocking
+// as much as for streaming:
+//
+//   vitamink layout save docked     # ~/.config/vitamink/layouts/docked.json
+//   vitamink layout apply docked
+//   vitamink layout list
+//
+// and `desk_layout = "docked"` has the daemon come back to that preset
+// rather than to the layout from before going Away.
+
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+use crate::display::{self, Color, ConnectionState, Display, DisplayState, Mode, ModeTarget, Rotation, Vrr};
+use crate::error::{Result, VitaminkError};
+use crate::persist;
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Layout {
+    pub outputs: Vec<OutputLayout>,
+    // See `checksum`. 0 in state files from before it existed.
+    #[serde(default)]
+    pub checksum: u64,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct OutputLayout {
+    pub name: String,
+    pub enabled: bool,
+    pub mode_id: Option<u32>,
+    // Width, height and refresh of `mode_id`, to find the mode again if the
+    // ids have shifted.
+    #[serde(default)]
+    pub mode: Option<(u32, u32, f64)>,
+    pub position: Option<(i32, i32)>,
+    pub scale: Option<f64>,
+    pub priority: Option<u32>,
+    // Re-enabling an output drops it back to SDR, so this is set
+    // explicitly. Missing from state files from before it existed.
+    #[serde(default)]
+    pub color: Color,
+    // The same goes for the adaptive sync policy.
+    #[serde(default)]
+    pub vrr: Option<Vrr>,
+    #[serde(default)]
+    pub rotation: Option<Rotation>,
+    #[serde(default)]
+    pub overscan: Option<u32>,
+}
+
+impl OutputLayout {
+    // The saved mode among `modes`, matched by size and refresh since ids
+    // aren't stable across replugs. Layouts saved before the size was
+    // recorded fall back to the id.
+    pub fn find_mode<'a>(&self, modes: &'a [Mode]) -> Option<&'a Mode> {
+        match self.mode {
+            Some(size) => modes.iter().find(|m| same_mode(m, size)),
+            None => modes.iter().find(|m| Some(m.id) == self.mode_id),
+        }
+    }
+}
+
+impl Layout {
+    pub fn capture() -> Result<Self> {
+        Ok(Self::from_displays(&display::refresh_displays()?))
+    }
+
+    pub fn from_displays(displays: &[Display]) -> Self {
+        let outputs = displays
+            .iter()
+            .map(|d| OutputLayout {
+                name: d.name.clone(),
+                enabled: d.state == DisplayState::Enabled,
+                mode_id: d.modes.iter().find(|m| m.current).map(|m| m.id),
+                mode: d.modes.iter().find(|m| m.current).map(|m| (m.width, m.height, m.refresh)),
+                position: d.geometry.map(|g| (g.x, g.y)),
+                scale: d.scale,
+                priority: d.priority,
+                color: d.color,
+                vrr: d.vrr,
+                rotation: d.rotation,
+                overscan: d.overscan,
+            })
+            .collect();
+
+        Self { outputs, checksum: checksum(displays) }
+    }
+
+    // Keeps `name` off when the layout is restored.
+    pub fn disable(&mut self, name: &str) {
+        for o in self.outputs.iter_mut().filter(|o| o.name == name) {
+            o.enabled = false;
+        }
+    }
+
+    // Leaves `name` as it is when the layout is restored.
+    pub fn leave(&mut self, name: &str) {
+        self.outputs.retain(|o| o.name != name);
+    }
+
+    // The enabled output with priority 1, if any.
+    pub fn primary(&self) -> Option<&str> {
+        self.outputs.iter().find(|o| o.enabled && o.priority == Some(1)).map(|o| o.name.as_str())
+    }
+
+    // Gives `name` priority 1 and moves the other enabled outputs down one,
+    // keeping their order.
+    pub fn set_primary(&mut self, name: &str) {
+        let mut enabled: Vec<&mut OutputLayout> = self.outputs.iter_mut().filter(|o| o.enabled).collect();
+        enabled.sort_by_key(|o| (o.name != name, o.priority.unwrap_or(u32::MAX)));
+        for (priority, output) in (1..).zip(enabled) {
+            output.priority = Some(priority);
+        }
+    }
+
+    // `fallback` is turned on if nothing else would be. Nothing is applied
+    // if the outputs are laid out like that already, e.g. after a daemon
+    // restart at the desk: KWin would redo the whole layout, flicker and all.
+    pub fn restore(&self, fallback: &str) -> Result<()> {
+        let current = display::get_displays()?;
+        let layout = if self.checksum != 0 && self.checksum == checksum(&current) {
+            self.clone()
+        } else {
+            let (layout, warnings) = self.reconcile(&current, fallback);
+            for warning in &warnings {
+                warn!("Saved layout: {warning}");
+            }
+            layout
+        };
+        if layout.is_current(&current) {
+            debug!("The outputs are laid out as saved already");
+            return Ok(());
+        }
+        display::apply_layout(&layout)
+    }
+
+    // Whether `current` already looks like this: the same outputs on, and
+    // those with the same settings. What a disabled output would have
+    // doesn't matter.
+    pub fn is_current(&self, current: &[Display]) -> bool {
+        let now = Self::from_displays(current);
+        self.outputs.iter().all(|saved| {
+            now.outputs.iter().any(|o| o.name == saved.name && o.enabled == saved.enabled && (!saved.enabled || o == saved))
+        })
+    }
+
+    // A copy that can be applied to `current`, and what had to change:
+    // outputs that are gone are dropped, missing modes are replaced by the
+    // closest one, and if nothing would be enabled, `fallback` (or failing
+    // that the first connected output) is.
+    pub fn reconcile(&self, current: &[Display], fallback: &str) -> (Layout, Vec<String>) {
+        let connected: Vec<&Display> =
+            current.iter().filter(|d| d.connection == ConnectionState::Connected).collect();
+        let mut warnings = Vec::new();
+        let mut outputs = Vec::new();
+
+        for saved in &self.outputs {
+            let Some(display) = connected.iter().find(|d| d.name == saved.name) else {
+                if saved.enabled {
+                    warnings.push(format!("{} is no longer connected, skipping it", saved.name));
+                }
+                continue;
+            };
+
+            let mut output = saved.clone();
+            if output.enabled
+                && let Some(id) = output.mode_id
+                && !display.modes.iter().any(|m| m.id == id && output.mode.is_none_or(|size| same_mode(m, size)))
+            {
+                let target = output.mode.map(|(width, height, refresh)| ModeTarget { width, height, refresh: Some(refresh) });
+                match display::select_mode(&display.modes, target.as_ref()) {
+                    Some(m) => {
+                        warnings.push(format!(
+                            "{}: mode {id} is gone, using {}x{}@{:.2} ({})",
+                            output.name, m.width, m.height, m.refresh, m.id
+                        ));
+                        output.mode_id = Some(m.id);
+                        output.mode = Some((m.width, m.height, m.refresh));
+                    }
+                    None => {
+                        warnings.push(format!("{}: mode {id} is gone, keeping the current one", output.name));
+                        output.mode_id = None;
+                        output.mode = None;
+                    }
+                }
+            }
+            outputs.push(output);
+        }
+
+        if !outputs.iter().any(|o| o.enabled)
+            && let Some(rescue) = connected.iter().find(|d| d.name == fallback).or(connected.first())
+        {
+            warnings.push(format!("nothing would be enabled, turning on {}", rescue.name));
+            match outputs.iter_mut().find(|o| o.name == rescue.name) {
+                Some(o) => o.enabled = true,
+                None => outputs.push(OutputLayout {
+                    name: rescue.name.clone(),
+                    enabled: true,
+                    mode_id: None,
+                    mode: None,
+                    position: None,
+                    scale: None,
+                    priority: Some(1),
+                    color: Color::default(),
+                    vrr: None,
+                    rotation: None,
+                    overscan: None,
+                }),
+            }
+        }
+
+        (Layout { outputs, checksum: checksum(current) }, warnings)
+    }
+
+    // kscreen-doctor arguments that recreate this layout on `displays`.
+    // Disabled outputs only get `disable`; their position/mode would be
+    // ignored anyway. A mode that no longer exists is left out.
+    pub fn settings(&self, displays: &[Display]) -> Vec<String> {
+        let mut settings = Vec::new();
+
+        for o in &self.outputs {
+            let prefix = format!("output.{}", o.name);
+            if !o.enabled {
+                settings.push(format!("{prefix}.disable"));
+                continue;
+            }
+
+            settings.push(format!("{prefix}.enable"));
+            if let Some(display) = displays.iter().find(|d| d.name == o.name)
+                && let Some(mode) = o.find_mode(&display.modes)
+            {
+                settings.push(format!("{prefix}.mode.{}", display::kscreen_mode(&display.modes, mode)));
+            }
+            if let Some((x, y)) = o.position {
+                settings.push(format!("{prefix}.position.{x},{y}"));
+            }
+            if let Some(scale) = o.scale {
+                settings.push(format!("{prefix}.scale.{scale}"));
+            }
+            if let Some(rotation) = o.rotation {
+                settings.push(format!("{prefix}.rotation.{}", rotation.setting()));
+            }
+            if let Some(overscan) = o.overscan {
+                settings.push(format!("{prefix}.overscan.{overscan}"));
+            }
+            if let Some(priority) = o.priority {
+                settings.push(format!("{prefix}.priority.{priority}"));
+            }
+            let switch = |on| if on { "enable" } else { "disable" };
+            if let Some(hdr) = o.color.hdr {
+                settings.push(format!("{prefix}.hdr.{}", switch(hdr)));
+            }
+            if let Some(wide_gamut) = o.color.wide_gamut {
+                settings.push(format!("{prefix}.wcg.{}", switch(wide_gamut)));
+            }
+            if let Some(bits) = o.color.bit_depth {
+                settings.push(format!("{prefix}.maxbpc.{bits}"));
+            }
+            if let Some(vrr) = o.vrr {
+                settings.push(format!("{prefix}.vrrpolicy.{}", vrr.setting()));
+            }
+        }
+
+        settings
+    }
+}
+
+// Which outputs are connected and what modes they offer. Enabled/disabled
+// state and positions don't count; those are what the layout sets.
+pub fn checksum(displays: &[Display]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for d in displays {
+        d.name.hash(&mut hasher);
+        (d.connection == ConnectionState::Connected).hash(&mut hasher);
+        for m in &d.modes {
+            (m.id, m.width, m.height, m.refresh.to_bits()).hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+fn same_mode(mode: &Mode, (width, height, refresh): (u32, u32, f64)) -> bool {
+    mode.width == width && mode.height == height && (mode.refresh - refresh).abs() < 0.01
+}
+
+// ---- Presets ----
+
+// `layouts/` next to config.toml: presets are made on purpose and may be
+// edited by hand, so they're plain JSON rather than checked state.
+pub fn preset_dir() -> PathBuf {
+    config::default_path().with_file_name("layouts")
+}
+
+// Letters, digits, '-', '_' and '.', not starting with '.', so a name is
+// always a file in `preset_dir`.
+pub fn is_preset_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && name.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+}
+
+pub fn save_preset(name: &str, layout: &Layout) -> Result<PathBuf> {
+    save_preset_in(&preset_dir(), name, layout)
+}
+
+pub fn load_preset(name: &str) -> Result<Layout> {
+    load_preset_in(&preset_dir(), name)
+}
+
+// The saved presets' names, sorted.
+pub fn presets() -> Result<Vec<String>> {
+    presets_in(&preset_dir())
+}
+
+fn preset_path(dir: &Path, name: &str) -> Result<PathBuf> {
+    if !is_preset_name(name) {
+        return Err(VitaminkError::Parse(format!("\"{name}\" isn't a layout name; use letters, digits, '-', '_' and '.'")));
+    }
+    Ok(dir.join(format!("{name}.json")))
+}
+
+fn save_preset_in(dir: &Path, name: &str, layout: &Layout) -> Result<PathBuf> {
+    let path = preset_path(dir, name)?;
+    let json = serde_json::to_string_pretty(layout).map_err(|e| VitaminkError::Parse(format!("{}: {e}", path.display())))?;
+    persist::write_atomic(&path, format!("{json}\n").as_bytes())?;
+    Ok(path)
+}
+
+fn load_preset_in(dir: &Path, name: &str) -> Result<Layout> {
+    let path = preset_path(dir, name)?;
+    let text = fs::read_to_string(&path).map_err(|e| VitaminkError::io(&path, e))?;
+    serde_json::from_str(&text).map_err(|e| VitaminkError::Parse(format!("{}: {e}", path.display())))
+}
+
+fn presets_in(dir: &Path) -> Result<Vec<String>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(VitaminkError::io(dir, e)),
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.strip_suffix(".json").map(str::to_string))
+        .filter(|name| is_preset_name(name))
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings() {
+        let layout = Layout {
+            outputs: vec![
+                OutputLayout {
+                    name: "DP-2".into(),
+                    enabled: true,
+                    mode_id: Some(3),
+                    mode: Some((2560, 1440, 144.0)),
+                    position: Some((1920, 0)),
+                    scale: Some(1.5),
+                    priority: Some(1),
+                    color: Color { hdr: Some(true), wide_gamut: None, bit_depth: Some(10) },
+                    vrr: Some(Vrr::Automatic),
+                    rotation: Some(Rotation::Left),
+                    overscan: Some(0),
+                },
+                OutputLayout {
+                    name: "HDMI-A-1".into(),
+                    enabled: false,
+                    mode_id: Some(1),
+                    mode: None,
+                    position: Some((0, 0)),
+                    scale: Some(1.0),
+                    priority: Some(2),
+                    color: Color::default(),
+                    vrr: None,
+                    rotation: None,
+                    overscan: None,
+                },
+            ],
+            checksum: 0,
+        };
+
+        // The mode id has moved from 3 to 7 since the layout was saved.
+        let displays = [display("DP-2", true, &[(1, 1920, 1080, 60.0), (7, 2560, 1440, 144.0)])];
+        assert_eq!(
+            layout.settings(&displays),
+            vec![
+                "output.DP-2.enable",
+                "output.DP-2.mode.2560x1440@144",
+                "output.DP-2.position.1920,0",
+                "output.DP-2.scale.1.5",
+                "output.DP-2.rotation.left",
+                "output.DP-2.overscan.0",
+                "output.DP-2.priority.1",
+                "output.DP-2.hdr.enable",
+                "output.DP-2.maxbpc.10",
+                "output.DP-2.vrrpolicy.automatic",
+                "output.HDMI-A-1.disable",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_set_primary() {
+        let output = |name: &str, enabled, priority| OutputLayout {
+            name: name.into(),
+            enabled,
+            mode_id: None,
+            mode: None,
+            position: None,
+            scale: None,
+            priority,
+            color: Color::default(),
+            vrr: None,
+            rotation: None,
+            overscan: None,
+        };
+        let mut layout = Layout {
+            outputs: vec![output("DP-2", true, Some(1)), output("DP-3", true, Some(2)), output("HDMI-A-1", true, None), output("DP-1", false, None)],
+            checksum: 0,
+        };
+        assert_eq!(layout.primary(), Some("DP-2"));
+        layout.set_primary("HDMI-A-1");
+        assert_eq!(layout.primary(), Some("HDMI-A-1"));
+        let priorities: Vec<Option<u32>> = layout.outputs.iter().map(|o| o.priority).collect();
+        assert_eq!(priorities, [Some(2), Some(3), Some(1), None]);
+    }
+
+    fn display(name: &str, connected: bool, modes: &[(u32, u32, u32, f64)]) -> Display {
+        Display {
+            index: 0,
+            name: name.into(),
+            uuid: None,
+            state: DisplayState::Disabled,
+            connection: if connected { ConnectionState::Connected } else { ConnectionState::Disconnected },
+            modes: modes
+                .iter()
+                .map(|&(id, width, height, refresh)| Mode { id, width, height, refresh, preferred: false, current: false })
+                .collect(),
+            geometry: None,
+            scale: None,
+            priority: None,
+            color: Color::default(),
+            vrr: None,
+            rotation: None,
+            overscan: None,
+        }
+    }
+
+    fn output(name: &str, enabled: bool, mode_id: u32, mode: (u32, u32, f64)) -> OutputLayout {
+        OutputLayout {
+            name: name.into(),
+            enabled,
+            mode_id: Some(mode_id),
+            mode: Some(mode),
+            position: Some((0, 0)),
+            scale: None,
+            priority: None,
+            color: Color::default(),
+            vrr: None,
+            rotation: None,
+            overscan: None,
+        }
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let saved = Layout {
+            outputs: vec![
+                output("DP-2", true, 3, (2560, 1440, 144.0)),
+                output("DP-3", true, 1, (1920, 1080, 60.0)),
+                output("HDMI-A-1", false, 1, (1920, 1080, 60.0)),
+            ],
+            checksum: 0,
+        };
+
+        // Unchanged: nothing to fix.
+        let current = vec![
+            display("DP-2", true, &[(3, 2560, 1440, 144.0)]),
+            display("DP-3", true, &[(1, 1920, 1080, 60.0)]),
+            display("HDMI-A-1", true, &[(1, 1920, 1080, 60.0)]),
+        ];
+        let (fixed, warnings) = saved.reconcile(&current, "DP-2");
+        assert!(warnings.is_empty());
+        assert_eq!(fixed.outputs, saved.outputs);
+
+        // DP-2 swapped for a monitor with other mode ids, DP-3 unplugged.
+        let current = vec![
+            display("DP-2", true, &[(7, 3840, 2160, 60.0), (8, 2560, 1440, 120.0)]),
+            display("DP-3", false, &[]),
+            display("HDMI-A-1", true, &[(1, 1920, 1080, 60.0)]),
+        ];
+        let (fixed, warnings) = saved.reconcile(&current, "DP-2");
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(fixed.outputs.len(), 2);
+        assert_eq!(fixed.outputs[0].mode_id, Some(8));
+        assert!(!fixed.outputs[1].enabled);
+
+        // Only a disabled output left: the fallback is turned on.
+        let current = vec![display("HDMI-A-1", true, &[(1, 1920, 1080, 60.0)])];
+        let (fixed, warnings) = saved.reconcile(&current, "DP-2");
+        assert_eq!(warnings.len(), 3);
+        assert!(fixed.outputs[0].enabled);
+        assert_eq!(fixed.outputs[0].name, "HDMI-A-1");
+    }
+
+    #[test]
+    fn test_is_current() {
+        let mut current = vec![display("DP-2", true, &[(3, 2560, 1440, 144.0)]), display("HDMI-A-1", true, &[(1, 1920, 1080, 60.0)])];
+        current[0].state = DisplayState::Enabled;
+        current[0].modes[0].current = true;
+        current[0].geometry = Some(display::Geometry { x: 0, y: 0, width: 2560, height: 1440 });
+        let saved = Layout {
+            outputs: vec![output("DP-2", true, 3, (2560, 1440, 144.0)), output("HDMI-A-1", false, 7, (3840, 2160, 30.0))],
+            checksum: 0,
+        };
+        assert!(saved.is_current(&current));
+
+        // Moved, or the other output on as well: both need applying.
+        current[0].geometry = Some(display::Geometry { x: 1920, y: 0, width: 2560, height: 1440 });
+        assert!(!saved.is_current(&current));
+        current[0].geometry = Some(display::Geometry { x: 0, y: 0, width: 2560, height: 1440 });
+        current[1].state = DisplayState::Enabled;
+        assert!(!saved.is_current(&current));
+    }
+
+    #[test]
+    fn test_presets() {
+        let dir = std::env::temp_dir().join(format!("vitamink-layouts-{}", std::process::id()));
+        assert_eq!(presets_in(&dir).unwrap(), Vec::<String>::new());
+
+        let layout = Layout { outputs: vec![output("DP-2", true, 3, (2560, 1440, 144.0)), output("HDMI-A-1", false, 1, (1920, 1080, 60.0))], checksum: 42 };
+        let path = save_preset_in(&dir, "docked", &layout).unwrap();
+        assert_eq!(path, dir.join("docked.json"));
+        save_preset_in(&dir, "couch.v2", &layout).unwrap();
+        assert_eq!(load_preset_in(&dir, "docked").unwrap(), layout);
+        assert_eq!(presets_in(&dir).unwrap(), ["couch.v2", "docked"]);
+
+        assert!(load_preset_in(&dir, "undocked").is_err());
+        assert!(save_preset_in(&dir, "../config", &layout).is_err());
+        assert!(!is_preset_name(".hidden"));
+        assert!(!is_preset_name(""));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_checksum() {
+        let a = vec![display("DP-2", true, &[(3, 2560, 1440, 144.0)])];
+        let b = vec![display("DP-2", true, &[(4, 2560, 1440, 144.0)])];
+        assert_eq!(checksum(&a), checksum(&a.clone()));
+        assert_ne!(checksum(&a), checksum(&b));
+    }
+}
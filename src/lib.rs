@@ -0,0 +1,202 @@
+// src/lib.rs — VitaminK as a library
+//
+// The `vitamink` binary (main.rs) is a thin CLI over this crate, and other
+// tools can embed the same pieces: list and switch outputs with `display`,
+// start and stop Sunshine and friends with `services`, or drive the whole state machine with
+// `daemon::Daemon` — either with its blocking `run` loop or step by step
+// (see "Embedding" in daemon.rs).
+//
+// The modules above the line are the supported API. The rest are public
+// only because the binary needs them, and may change in any release.
+//
+// Optional integrations are Cargo features, all but wlr-output on by
+// default:
+//
+//   mutter        GNOME output control (Mutter's DisplayConfig D-Bus API)
+//   wlr-randr     output control on wlroots compositors
+//   wlr-output    the same over the Wayland protocol, without wlr-randr
+//                 (pulls in wayland-client)
+//   xrandr        output control and DPMS in X11 sessions
+//   sunshine-api  Sunshine's web API (sunshine::api)
+//
+// New Rust concepts in this file:
+//
+// - Library + binary in one package: Cargo builds src/lib.rs as the
+//   `vitamink` library and src/main.rs as a binary that uses it like any
+//   other dependency (`use vitamink::display`).
+//
+// - `#[cfg(feature = "...")]`: the item only exists when the feature is
+//   enabled, so code behind a disabled feature isn't even compiled.
+//
+// - `#[doc(hidden)]`: keeps an item out of the generated documentation
+//   without making it private.
+
+pub mod config;
+pub mod daemon;
+pub mod display;
+pub mod error;
+pub mod services;
+pub mod sunshine;
+
+// ---- Internal ----
+
+#[doc(hidden)]
+pub mod accounting;
+#[doc(hidden)]
+pub mod audio;
+#[doc(hidden)]
+pub mod bluetooth;
+#[doc(hidden)]
+pub mod camera;
+#[doc(hidden)]
+pub mod cli;
+#[doc(hidden)]
+pub mod compositor;
+#[doc(hidden)]
+pub mod condition;
+#[doc(hidden)]
+pub mod control;
+#[doc(hidden)]
+pub mod crash;
+#[doc(hidden)]
+pub mod custom_mode;
+#[doc(hidden)]
+pub mod dbus;
+#[doc(hidden)]
+pub mod ddc;
+#[doc(hidden)]
+pub mod desktop;
+#[doc(hidden)]
+pub mod detail;
+#[doc(hidden)]
+pub mod diagnostics;
+#[doc(hidden)]
+pub mod diagram;
+#[doc(hidden)]
+pub mod digest;
+#[doc(hidden)]
+pub mod dock;
+#[doc(hidden)]
+pub mod doctor;
+#[doc(hidden)]
+pub mod drift;
+#[doc(hidden)]
+pub mod drm;
+#[doc(hidden)]
+pub mod dummy;
+#[doc(hidden)]
+pub mod duration;
+#[doc(hidden)]
+pub mod edid;
+#[doc(hidden)]
+pub mod environment;
+#[doc(hidden)]
+pub mod gamescope;
+#[doc(hidden)]
+pub mod history;
+#[doc(hidden)]
+pub mod http;
+#[doc(hidden)]
+pub mod hooks;
+#[doc(hidden)]
+pub mod inhibit;
+#[doc(hidden)]
+pub mod input;
+#[doc(hidden)]
+pub mod install;
+#[doc(hidden)]
+pub mod instance;
+#[doc(hidden)]
+pub mod kscreen_json;
+#[doc(hidden)]
+pub mod layout;
+#[doc(hidden)]
+pub mod listing;
+#[doc(hidden)]
+pub mod load;
+#[doc(hidden)]
+pub mod logging;
+#[doc(hidden)]
+pub mod machine;
+#[doc(hidden)]
+pub mod metrics;
+#[doc(hidden)]
+pub mod mock;
+#[doc(hidden)]
+pub mod mqtt;
+#[cfg(feature = "mutter")]
+#[doc(hidden)]
+pub mod mutter;
+#[doc(hidden)]
+pub mod notify;
+#[doc(hidden)]
+pub mod overview;
+#[doc(hidden)]
+pub mod pattern;
+#[doc(hidden)]
+pub mod persist;
+#[doc(hidden)]
+pub mod pipeline;
+#[doc(hidden)]
+pub mod power;
+#[doc(hidden)]
+pub mod presence;
+#[doc(hidden)]
+pub mod privacy;
+#[doc(hidden)]
+pub mod process;
+#[doc(hidden)]
+pub mod recovery;
+#[doc(hidden)]
+pub mod remote;
+#[doc(hidden)]
+pub mod processes;
+#[doc(hidden)]
+pub mod schedule;
+#[doc(hidden)]
+pub mod seat;
+#[doc(hidden)]
+pub mod shutdown;
+#[doc(hidden)]
+pub mod simulate;
+#[doc(hidden)]
+pub mod sleep;
+#[doc(hidden)]
+pub mod smoke;
+#[doc(hidden)]
+pub mod startup;
+#[doc(hidden)]
+pub mod status;
+#[doc(hidden)]
+pub mod statusline;
+#[doc(hidden)]
+pub mod systemd;
+#[doc(hidden)]
+pub mod table;
+#[doc(hidden)]
+pub mod topology;
+#[doc(hidden)]
+pub mod tune;
+#[doc(hidden)]
+pub mod validate;
+#[doc(hidden)]
+pub mod virtual_output;
+#[doc(hidden)]
+pub mod wake;
+#[doc(hidden)]
+pub mod watch;
+#[doc(hidden)]
+pub mod watchdog;
+#[doc(hidden)]
+pub mod webhook;
+#[doc(hidden)]
+pub mod windows;
+#[cfg(feature = "wlr-output")]
+#[doc(hidden)]
+pub mod wlr_output;
+#[cfg(feature = "wlr-randr")]
+#[doc(hidden)]
+pub mod wlr_randr;
+#[cfg(feature = "xrandr")]
+#[doc(hidden)]
+pub mod xrandr;
@@ -0,0 +1,243 @@
+// src/listing.rs — `vitamink displays` and `vitamink modes`: output tables
+//
+//   vitamink displays [--connected] [--sort name|refresh|resolution|state] [--wide] [--json]
+//   vitamink modes NAME [--json]
+//
+// The plain `vitamink` status view uses the same table with the defaults.
+// `--json` prints the parsed outputs (or one output's modes) instead.
+
+use crate::display::{ConnectionState, Display, DisplayState, DpmsState, Mode};
+use crate::drm::ConnectorStatus;
+use crate::table::Table;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SortKey {
+    // kscreen's own order.
+    Index,
+    Name,
+    // Highest current refresh rate first.
+    Refresh,
+    // Most pixels first.
+    Resolution,
+    // Enabled before disabled.
+    State,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub connected_only: bool,
+    pub sort: SortKey,
+    pub wide: bool,
+    pub json: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self { connected_only: false, sort: SortKey::Index, wide: false, json: false }
+    }
+}
+
+// What the compositor doesn't know about an output: sysfs and EDID.
+pub struct Hardware {
+    pub model: Option<String>,
+    pub dpms: DpmsState,
+    pub drm_active: bool,
+    // The kernel's connector status; `None` without sysfs.
+    pub link: Option<ConnectorStatus>,
+}
+
+pub fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--connected" => options.connected_only = true,
+            "--wide" => options.wide = true,
+            "--json" => options.json = true,
+            "--sort" => {
+                options.sort = match args.next().map(String::as_str) {
+                    Some("index") => SortKey::Index,
+                    Some("name") => SortKey::Name,
+                    Some("refresh") => SortKey::Refresh,
+                    Some("resolution") => SortKey::Resolution,
+                    Some("state") => SortKey::State,
+                    Some(other) => return Err(format!("Unknown sort key \"{other}\" (name, refresh, resolution, state, index)")),
+                    None => return Err("--sort needs a key".to_string()),
+                }
+            }
+            other => return Err(format!("Unknown option {other}")),
+        }
+    }
+
+    Ok(options)
+}
+
+// The outputs `options` asks for, in its order.
+pub fn select<'a>(displays: &'a [Display], options: &Options) -> Vec<&'a Display> {
+    let mut shown: Vec<&Display> = displays
+        .iter()
+        .filter(|d| !options.connected_only || d.connection == ConnectionState::Connected)
+        .collect();
+
+    let current = |d: &Display| d.modes.iter().find(|m| m.current).map(|m| (m.refresh, m.width * m.height));
+    match options.sort {
+        SortKey::Index => shown.sort_by_key(|d| d.index),
+        SortKey::Name => shown.sort_by(|a, b| a.name.cmp(&b.name)),
+        SortKey::Refresh => shown.sort_by(|a, b| {
+            let (ra, rb) = (current(a).map_or(0.0, |c| c.0), current(b).map_or(0.0, |c| c.0));
+            rb.total_cmp(&ra)
+        }),
+        SortKey::Resolution => shown.sort_by_key(|d| std::cmp::Reverse(current(d).map_or(0, |c| c.1))),
+        SortKey::State => shown.sort_by_key(|d| d.state != DisplayState::Enabled),
+    }
+    shown
+}
+
+pub fn render(displays: &[Display], options: &Options, hardware: impl Fn(&Display) -> Hardware) -> String {
+    let shown = select(displays, options);
+
+    let mut headers = vec!["NAME", "MODEL", "STATE", "DPMS", "CURRENT", "PREFERRED", "DRM"];
+    if options.wide {
+        headers.extend(["CONNECTION", "LINK", "POSITION", "SCALE", "PRIORITY", "MODES"]);
+    }
+    let mut table = Table::new(&headers);
+
+    for d in shown {
+        let hw = hardware(d);
+        let mut row = vec![
+            d.name.clone(),
+            hw.model.unwrap_or_else(|| "—".to_string()),
+            match d.state {
+                DisplayState::Enabled => "enabled".to_string(),
+                DisplayState::Disabled => "disabled".to_string(),
+            },
+            format!("{:?}", hw.dpms),
+            mode_cell(d.modes.iter().find(|m| m.current)),
+            mode_cell(d.modes.iter().find(|m| m.preferred)),
+            if hw.drm_active { "active" } else { "inactive" }.to_string(),
+        ];
+
+        if options.wide {
+            row.extend([
+                match d.connection {
+                    ConnectionState::Connected => "connected".to_string(),
+                    ConnectionState::Disconnected => "disconnected".to_string(),
+                },
+                hw.link.map_or("—".to_string(), |l| format!("{l:?}").to_lowercase()),
+                d.geometry.map_or("—".to_string(), |g| format!("{},{}", g.x, g.y)),
+                d.scale.map_or("—".to_string(), |s| s.to_string()),
+                d.priority.map_or("—".to_string(), |p| p.to_string()),
+                d.modes.len().to_string(),
+            ]);
+        }
+        table.add_row(row);
+    }
+
+    table.render()
+}
+
+// One output's modes, as the backend lists them.
+pub fn render_modes(modes: &[Mode]) -> String {
+    let mut table = Table::new(&["ID", "RESOLUTION", "REFRESH", "FLAGS"]);
+    for m in modes {
+        let flags: Vec<&str> = [(m.current, "current"), (m.preferred, "preferred")]
+            .into_iter()
+            .filter_map(|(set, flag)| set.then_some(flag))
+            .collect();
+        table.add_row(vec![m.id.to_string(), format!("{}x{}", m.width, m.height), format!("{:.2}", m.refresh), flags.join(", ")]);
+    }
+    table.render()
+}
+
+fn mode_cell(mode: Option<&Mode>) -> String {
+    mode.map_or("—".to_string(), |m| format!("{}x{}@{:.2}", m.width, m.height, m.refresh))
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::Color;
+
+    fn display(index: u32, name: &str, connected: bool, mode: Option<(u32, u32, f64)>) -> Display {
+        Display {
+            index,
+            name: name.into(),
+            uuid: None,
+            state: if mode.is_some() { DisplayState::Enabled } else { DisplayState::Disabled },
+            connection: if connected { ConnectionState::Connected } else { ConnectionState::Disconnected },
+            modes: mode
+                .map(|(width, height, refresh)| Mode { id: 1, width, height, refresh, preferred: true, current: true })
+                .into_iter()
+                .collect(),
+            geometry: None,
+            scale: None,
+            priority: None,
+            color: Color::default(),
+            vrr: None,
+            rotation: None,
+            overscan: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(parse_args(&[]).unwrap(), Options::default());
+        assert_eq!(
+            parse_args(&args(&["--connected", "--sort", "refresh", "--wide", "--json"])).unwrap(),
+            Options { connected_only: true, sort: SortKey::Refresh, wide: true, json: true }
+        );
+        assert!(parse_args(&args(&["--sort"])).is_err());
+        assert!(parse_args(&args(&["--sort", "colour"])).is_err());
+        assert!(parse_args(&args(&["--tall"])).is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let displays = vec![
+            display(1, "DP-2", true, Some((2560, 1440, 144.0))),
+            display(2, "HDMI-A-1", true, Some((3840, 2160, 60.0))),
+            display(3, "DP-1", false, None),
+        ];
+        let hardware = |d: &Display| Hardware {
+            model: (d.name == "DP-2").then(|| "DELL S2721DGF".to_string()),
+            dpms: DpmsState::On,
+            drm_active: d.state == DisplayState::Enabled,
+            link: Some(if d.connection == ConnectionState::Connected {
+                ConnectorStatus::Connected
+            } else {
+                ConnectorStatus::Disconnected
+            }),
+        };
+
+        let options = Options { connected_only: true, sort: SortKey::Resolution, ..Options::default() };
+        let out = render(&displays, &options, hardware);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("NAME "));
+        assert!(lines[1].starts_with("HDMI-A-1  —  "));
+        assert!(lines[2].contains("DELL S2721DGF"));
+        assert!(lines[2].contains("2560x1440@144.00 "));
+
+        let options = Options { connected_only: false, sort: SortKey::Refresh, wide: true, json: false };
+        let out = render(&displays, &options, hardware);
+        assert!(out.lines().nth(1).unwrap().starts_with("DP-2"));
+        assert!(out.contains("disconnected"));
+    }
+
+    #[test]
+    fn test_render_modes() {
+        let modes = vec![
+            Mode { id: 1, width: 3840, height: 2160, refresh: 60.0, preferred: true, current: false },
+            Mode { id: 2, width: 1920, height: 1080, refresh: 119.88, preferred: false, current: true },
+        ];
+        let out = render_modes(&modes);
+        let lines: Vec<&str> = out.lines().collect();
+        assert!(lines[0].starts_with("ID"));
+        assert!(lines[1].contains("3840x2160") && lines[1].ends_with("preferred"));
+        assert!(lines[2].contains("119.88") && lines[2].ends_with("current"));
+    }
+}
@@ -0,0 +1,143 @@
+// src/load.rs — Too busy to go Away?
+//
+// Going Away starts Sunshine, which wants a share of the GPU for encoding
+// and of the CPU for everything else, and its recordings want disk space.
+// A monitor switched off during an overnight render or a long compile
+// would have all of that taken from the job. With
+//
+//   [load]
+//   cpu_above = 90
+//   gpu_above = 80
+//   min_free_gb = 20
+//   disk_path = "~/Videos"
+//
+// a presence-driven switch to Away that's due is held while
+//
+//   - the 1-minute load average per CPU (/proc/loadavg) is above
+//     `cpu_above` percent,
+//   - any GPU is busier than `gpu_above` percent (amdgpu's
+//     gpu_busy_percent in sysfs, `nvidia-smi` for NVIDIA), or
+//   - the filesystem holding `disk_path` has less than `min_free_gb` free,
+//
+// and goes ahead once none of them is. 0 turns each off. A reading that
+// can't be taken doesn't hold anything. Manual and timed switches go
+// through regardless, and so does `vitamink reconcile --force`.
+
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::process::Command;
+use std::thread;
+
+use log::debug;
+
+use crate::config::LoadConfig;
+use crate::drm;
+use crate::process;
+use crate::sunshine::conf::expand_home;
+
+// Why the machine is too busy for Away, if it is.
+pub fn check(config: &LoadConfig) -> Option<String> {
+    let mut reasons = Vec::new();
+    if config.cpu_above != 0
+        && let Some(percent) = cpu_percent()
+        && percent > config.cpu_above as f64
+    {
+        reasons.push(format!("CPU load at {percent:.0}%"));
+    }
+    if config.gpu_above != 0
+        && let Some((gpu, percent)) = gpu_percent()
+        && percent > config.gpu_above
+    {
+        reasons.push(format!("{gpu} {percent}% busy"));
+    }
+    if config.min_free_gb != 0.0
+        && let Some(free) = free_gb(&config.disk_path)
+        && free < config.min_free_gb
+    {
+        reasons.push(format!("{free:.1} GB free under {}", config.disk_path));
+    }
+    (!reasons.is_empty()).then(|| reasons.join(", "))
+}
+
+fn cpu_percent() -> Option<f64> {
+    let cpus = thread::available_parallelism().map_or(1, |n| n.get());
+    loadavg_percent(&fs::read_to_string("/proc/loadavg").ok()?, cpus)
+}
+
+// "3.52 2.10 1.80 2/1234 5678": the first figure, per CPU.
+fn loadavg_percent(text: &str, cpus: usize) -> Option<f64> {
+    let load: f64 = text.split_whitespace().next()?.parse().ok()?;
+    Some(load / cpus.max(1) as f64 * 100.0)
+}
+
+// The busiest GPU and how busy it is.
+fn gpu_percent() -> Option<(String, u32)> {
+    let cards = drm::cards();
+    let mut gpus: Vec<(String, u32)> = cards.iter().filter_map(|card| Some((card.name.clone(), drm::busy_percent(card)?))).collect();
+    if cards.iter().any(|card| card.driver == "nvidia") {
+        gpus.extend(nvidia());
+    }
+    gpus.into_iter().max_by_key(|&(_, percent)| percent)
+}
+
+fn nvidia() -> Vec<(String, u32)> {
+    let output = process::output(Command::new("nvidia-smi").args(["--query-gpu=index,utilization.gpu", "--format=csv,noheader,nounits"]));
+    match output {
+        Ok(output) if output.status.success() => nvidia_utilization(&String::from_utf8_lossy(&output.stdout)),
+        Ok(output) => {
+            debug!("Load check: nvidia-smi failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+            Vec::new()
+        }
+        Err(e) => {
+            debug!("Load check: {e}");
+            Vec::new()
+        }
+    }
+}
+
+// "0, 97" per line.
+fn nvidia_utilization(csv: &str) -> Vec<(String, u32)> {
+    csv.lines()
+        .filter_map(|line| {
+            let (index, percent) = line.split_once(',')?;
+            Some((format!("GPU {}", index.trim()), percent.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+fn free_gb(path: &str) -> Option<f64> {
+    let path = CString::new(expand_home(path).as_os_str().as_bytes()).ok()?;
+    // SAFETY: statvfs only reads the path and writes into the zeroed
+    // struct we hand it.
+    let stat = unsafe {
+        let mut stat: libc::statvfs = std::mem::zeroed();
+        if libc::statvfs(path.as_ptr(), &mut stat) != 0 {
+            return None;
+        }
+        stat
+    };
+    Some(stat.f_bavail as f64 * stat.f_frsize as f64 / 1e9)
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_readings() {
+        assert_eq!(loadavg_percent("2.00 2.10 1.80 2/1234 5678\n", 4), Some(50.0));
+        assert_eq!(loadavg_percent("", 4), None);
+        assert_eq!(nvidia_utilization("0, 97\n1, 3\nGPU lost\n"), [("GPU 0".to_string(), 97), ("GPU 1".to_string(), 3)]);
+        assert!(free_gb("/").is_some());
+
+        // Everything off holds nothing, whatever the machine is doing.
+        let off = LoadConfig { cpu_above: 0, gpu_above: 0, min_free_gb: 0.0, disk_path: "/".into() };
+        assert_eq!(check(&off), None);
+        // No filesystem has this much.
+        let full = LoadConfig { min_free_gb: 1e12, ..off };
+        assert!(check(&full).unwrap().contains("GB free under /"));
+    }
+}
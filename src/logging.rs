@@ -0,0 +1,261 @@
+// src/logging.rs — Log output for the daemon and the CLI
+//
+// Everything logs through the `log` crate's macros (`info!`, `warn!`, ...);
+// this is the one place that decides what a line looks like.
+//
+// Under systemd, stderr goes to the journal (which sets $JOURNAL_STREAM).
+// There each line starts with a syslog priority like `<4>`, which journald
+// strips and uses as the entry's priority — so warnings and errors show up
+// highlighted in `journalctl`, and `journalctl -p warning` works. The
+// journal adds its own timestamps. In a terminal, lines get a local
+// timestamp and the level instead.
+//
+// The last few hundred lines are also kept in memory, timestamped either
+// way, so a diagnostics bundle can include them (see diagnostics.rs).
+//
+// For Loki and the like, `log_format = "json"` makes each line one JSON
+// object instead (still after the `<4>` under systemd), and `log_file`
+// appends the same objects to a file alongside whatever stderr gets:
+//
+//   {"event":"Away mode active","level":"INFO","module":"vitamink::daemon","state":"Away","timestamp":"2026-10-16T12:03:27.120Z"}
+//
+// `state` is the daemon's (null before it has one, and from the CLI).
+//
+// New Rust concepts in this file:
+//
+// - Implementing a trait from another crate: `log::Log` is how the `log`
+//   macros find us once `log::set_logger` has been called.
+
+use std::collections::VecDeque;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{Level, LevelFilter, Log, Metadata, Record, warn};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::daemon::State;
+use crate::sunshine::conf::expand_home;
+
+struct Logger {
+    journald: bool,
+}
+
+// The `log_format` config setting: what stderr gets.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+struct Output {
+    format: LogFormat,
+    file: Option<File>,
+}
+
+const KEPT_LINES: usize = 500;
+static RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+static OUTPUT: Mutex<Output> = Mutex::new(Output { format: LogFormat::Text, file: None });
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = record.args().to_string();
+        let timestamp = local_timestamp();
+        remember(format_line(record.level(), &message, Some(&timestamp)));
+
+        let mut output = OUTPUT.lock().unwrap_or_else(|e| e.into_inner());
+        let json = (output.format == LogFormat::Json || output.file.is_some()).then(|| {
+            let state = *STATE.lock().unwrap_or_else(|e| e.into_inner());
+            format_json(record.level(), record.module_path().unwrap_or(record.target()), state, &message, &utc_timestamp())
+        });
+        let line = match (&json, output.format) {
+            (Some(json), LogFormat::Json) if self.journald => format!("<{}>{json}", priority(record.level())),
+            (Some(json), LogFormat::Json) => json.clone(),
+            _ => format_line(record.level(), &message, (!self.journald).then_some(timestamp.as_str())),
+        };
+        // One write per line so lines from different threads don't interleave.
+        let _ = std::io::stderr().lock().write_all(line.as_bytes());
+        if let (Some(file), Some(json)) = (&mut output.file, &json) {
+            let _ = file.write_all(json.as_bytes());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+// Sets the level and installs the logger. Only the first call counts.
+pub fn init(level: LevelFilter) {
+    let journald = env::var_os("JOURNAL_STREAM").is_some();
+    let logger: &'static Logger = Box::leak(Box::new(Logger { journald }));
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+// The `log_format` and `log_file` settings, from the daemon's config.
+pub fn configure(format: LogFormat, file: Option<&str>) {
+    let opened = file.map(|path| {
+        let path = expand_home(path);
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        OpenOptions::new().create(true).append(true).open(&path).map_err(|e| format!("Couldn't open log_file {}: {e}", path.display()))
+    });
+    let (file, error) = match opened {
+        Some(Ok(file)) => (Some(file), None),
+        Some(Err(e)) => (None, Some(e)),
+        None => (None, None),
+    };
+    *OUTPUT.lock().unwrap_or_else(|e| e.into_inner()) = Output { format, file };
+    if let Some(e) = error {
+        warn!("{e}");
+    }
+}
+
+// The daemon's state, for the `state` field.
+pub fn set_state(state: State) {
+    *STATE.lock().unwrap_or_else(|e| e.into_inner()) = Some(state);
+}
+
+fn remember(line: String) {
+    let mut recent = RECENT.lock().unwrap_or_else(|e| e.into_inner());
+    if recent.len() == KEPT_LINES {
+        recent.pop_front();
+    }
+    recent.push_back(line);
+}
+
+// The most recent log lines, oldest first, with timestamps.
+pub fn recent() -> Vec<String> {
+    RECENT.lock().unwrap_or_else(|e| e.into_inner()).iter().cloned().collect()
+}
+
+// The `--log-level` values: off, error, warn, info, debug, trace.
+pub fn parse_level(text: &str) -> Result<LevelFilter, String> {
+    text.parse()
+        .map_err(|_| format!("Unknown log level \"{text}\" (off, error, warn, info, debug, trace)"))
+}
+
+// sd-daemon(3) priorities.
+fn priority(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+fn format_line(level: Level, message: &str, timestamp: Option<&str>) -> String {
+    match timestamp {
+        None => format!("<{}>{message}\n", priority(level)),
+        Some(ts) => format!("{ts} {level:<5} [vitamink] {message}\n"),
+    }
+}
+
+fn format_json(level: Level, module: &str, state: Option<State>, message: &str, timestamp: &str) -> String {
+    let object = json!({
+        "timestamp": timestamp,
+        "level": level.as_str(),
+        "module": module,
+        "state": state.map(|s| s.to_string()),
+        "event": message,
+    });
+    format!("{object}\n")
+}
+
+// "2026-10-16T12:03:27.120Z": now, in UTC with milliseconds.
+fn utc_timestamp() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    format_utc(now.as_secs() as libc::time_t, now.subsec_millis())
+}
+
+fn format_utc(time: libc::time_t, millis: u32) -> String {
+    // SAFETY: as in format_local.
+    let tm = unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::gmtime_r(&time, &mut tm);
+        tm
+    };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{millis:03}Z",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    )
+}
+
+fn local_timestamp() -> String {
+    // SAFETY: `time` accepts a null pointer.
+    format_local(unsafe { libc::time(std::ptr::null_mut()) })
+}
+
+// "2026-10-16 14:03:27": Unix seconds in local time.
+pub fn format_local(time: libc::time_t) -> String {
+    // SAFETY: `localtime_r` only reads `time` and writes into the zeroed
+    // `tm` we hand it.
+    let tm = unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&time, &mut tm);
+        tm
+    };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    )
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_line() {
+        assert_eq!(format_line(Level::Warn, "Poll error: x", None), "<4>Poll error: x\n");
+        assert_eq!(format_line(Level::Info, "Away mode active", None), "<6>Away mode active\n");
+        assert_eq!(
+            format_line(Level::Error, "boom", Some("2026-10-16 14:03:27")),
+            "2026-10-16 14:03:27 ERROR [vitamink] boom\n"
+        );
+    }
+
+    #[test]
+    fn test_format_json() {
+        assert_eq!(format_utc(1_792_152_207, 120), "2026-10-16T12:03:27.120Z");
+        assert_eq!(
+            format_json(Level::Info, "vitamink::daemon", Some(State::Away), "Away mode \"tv\" active", "2026-10-16T12:03:27.120Z"),
+            "{\"event\":\"Away mode \\\"tv\\\" active\",\"level\":\"INFO\",\"module\":\"vitamink::daemon\",\"state\":\"Away\",\"timestamp\":\"2026-10-16T12:03:27.120Z\"}\n"
+        );
+        assert!(format_json(Level::Warn, "vitamink", None, "x", "").contains("\"state\":null"));
+    }
+
+    #[test]
+    fn test_parse_level() {
+        assert_eq!(parse_level("debug"), Ok(LevelFilter::Debug));
+        assert_eq!(parse_level("WARN"), Ok(LevelFilter::Warn));
+        assert!(parse_level("loud").is_err());
+    }
+}
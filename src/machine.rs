@@ -0,0 +1,692 @@
+// src/machine.rs — When to switch: the daemon's decisions, without the I/O
+//
+// `StateMachine` takes events — presence readings, manual overrides, a
+// stream starting or ending, outputs vanishing — and says what to do
+// about them as `Action`s. It owns the state and everything that decides
+// it (the grace period timer, the cooldown since the last switch, a
+// manual override's hold, HoldingPattern's resume state, how long Away
+// has gone without a stream), but never reads a sensor or runs a command: the daemon
+// feeds it and carries out the actions, e.g. a `Transition` to Away runs
+// the steps in `apply_steps`. Time comes in with each event too, so the
+// tests below can walk through a grace period without sleeping.
+
+use std::time::{Duration, Instant};
+
+use log::{debug, info, warn};
+
+use crate::config::Config;
+use crate::daemon::State;
+use crate::presence::Presence;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Event {
+    // A presence reading: what the backend saw, and what counts once the
+    // schedule has had its say (see `Daemon::scheduled`).
+    Presence { detected: Presence, effective: Presence },
+    // Time passing with the same readings as last time.
+    Tick,
+    // ForceAway/ForceDesk, a timed switch or a wake: switch now, and stay
+    // there until the reading moves off `detected`.
+    ManualOverride { target: State, detected: Presence },
+    // Calls off a pending switch, holding the state the same way.
+    CancelPending { detected: Presence },
+    StreamStarted,
+    StreamEnded,
+    // The machine is too busy for Away (see load.rs), or no longer is.
+    Busy,
+    NotBusy,
+    // Something else (a dock settling, an inhibitor) holds the state for
+    // now; a pending switch starts over afterwards.
+    Pause,
+    // No outputs at all, and them coming back.
+    OutputsLost,
+    OutputsReturned,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Action {
+    // The reading asks for `to`; the grace period has started.
+    StartGrace { to: State },
+    // Go from one state to the other (the machine already says `to`).
+    Transition { from: State, to: State },
+    // Due to leave Away, but someone is streaming.
+    HoldForStream,
+    // Due to go Away, but the machine is busy.
+    HoldForLoad,
+    // Due, but the last switch was less than `cooldown` ago. `in_a_row`
+    // counts the holds since a switch the cooldown didn't touch.
+    HoldForCooldown { in_a_row: u32 },
+    // Entered HoldingPattern from `from`.
+    Hold { from: State },
+    // A pending switch was called off.
+    Cancelled,
+}
+
+// Standby counts as Away as far as presence goes: it's Away with the
+// lights off.
+fn as_presence_state(state: State) -> State {
+    if state == State::Standby { State::Away } else { state }
+}
+
+// Holds in a row from which each one is a warning: DPMS is bouncing.
+const COOLDOWN_WARNING: u32 = 3;
+
+pub struct StateMachine {
+    state: State,
+    grace_period: Duration,
+    // `cooldown`: the least time between two switches, and when the last
+    // one was.
+    cooldown: Duration,
+    last_switch: Option<Instant>,
+    // The pending switch is being held by the cooldown (reported once),
+    // and how many were held since one went through untouched.
+    held_by_cooldown: bool,
+    cooldown_holds: u32,
+    block_desk_while_streaming: bool,
+    // The state the reading asks for and since when, while it differs
+    // from ours. We only switch once it has held for `grace_period`, so a
+    // monitor that blinks off and on doesn't flap.
+    pending: Option<(State, Instant)>,
+    // Set by a manual override to the presence reading at that moment.
+    // The forced state sticks until the reading actually changes,
+    // otherwise the next poll would just undo the override, or until
+    // `override_timeout` is up, if that's set.
+    override_presence: Option<Presence>,
+    override_timeout: Duration,
+    override_until: Option<Instant>,
+    streaming: bool,
+    // True while leaving Away is held back by a live stream, so that's
+    // reported once instead of every poll.
+    held_by_stream: bool,
+    // The same for going Away while the machine is busy.
+    busy: bool,
+    held_by_load: bool,
+    // The state to go back to when a HoldingPattern ends.
+    resume: Option<State>,
+    last_reading: Option<(Presence, Presence)>,
+    // `[standby] after`, if enabled, and since when Away has gone without
+    // a stream.
+    standby_after: Option<Duration>,
+    quiet_since: Option<Instant>,
+}
+
+impl StateMachine {
+    pub fn new(state: State, config: &Config) -> Self {
+        let mut machine = Self {
+            state,
+            grace_period: Duration::ZERO,
+            cooldown: Duration::ZERO,
+            last_switch: None,
+            held_by_cooldown: false,
+            cooldown_holds: 0,
+            block_desk_while_streaming: false,
+            pending: None,
+            override_presence: None,
+            override_timeout: Duration::ZERO,
+            override_until: None,
+            streaming: false,
+            held_by_stream: false,
+            busy: false,
+            held_by_load: false,
+            resume: None,
+            last_reading: None,
+            standby_after: None,
+            quiet_since: None,
+        };
+        machine.configure(config);
+        machine
+    }
+
+    // Picks up the settings it uses, e.g. after a config reload.
+    pub fn configure(&mut self, config: &Config) {
+        self.grace_period = config.grace_period;
+        self.cooldown = config.cooldown;
+        self.override_timeout = config.override_timeout;
+        self.block_desk_while_streaming = config.sunshine.block_desk_while_streaming;
+        self.standby_after = config.standby.enabled.then_some(config.standby.after);
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    // For when carrying out a transition ends somewhere else: a failed
+    // Away rolls back to AtDesk.
+    pub fn set_state(&mut self, state: State) {
+        self.state = state;
+    }
+
+    // The switch waiting out the grace period, and since when.
+    pub fn pending(&self) -> Option<(State, Instant)> {
+        self.pending
+    }
+
+    pub fn is_streaming(&self) -> bool {
+        self.streaming
+    }
+
+    pub fn held_by_stream(&self) -> bool {
+        self.held_by_stream
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.busy
+    }
+
+    pub fn held_by_load(&self) -> bool {
+        self.held_by_load
+    }
+
+    // When the pending switch will happen: at the end of the grace
+    // period, or of the cooldown if that's later.
+    pub fn due(&self) -> Option<Instant> {
+        let (_, started) = self.pending?;
+        let due = started + self.grace_period;
+        Some(self.last_switch.map_or(due, |at| due.max(at + self.cooldown)))
+    }
+
+    // When a manual override runs out (`override_timeout`), if one holds.
+    pub fn override_due(&self) -> Option<Instant> {
+        self.override_presence.and(self.override_until)
+    }
+
+    fn cooling_down(&self, now: Instant) -> bool {
+        self.last_switch.is_some_and(|at| now - at < self.cooldown)
+    }
+
+    // Away for `[standby] after` without anyone streaming, so the next
+    // event goes to Standby.
+    pub fn standby_due(&self, now: Instant) -> bool {
+        self.state == State::Away
+            && !self.streaming
+            && matches!((self.standby_after, self.quiet_since), (Some(after), Some(since)) if now - since >= after)
+    }
+
+    pub fn handle(&mut self, event: Event, now: Instant) -> Vec<Action> {
+        match event {
+            Event::Presence { detected, effective } => {
+                self.last_reading = Some((detected, effective));
+                self.decide(detected, effective, now)
+            }
+            Event::Tick => match self.last_reading {
+                Some((detected, effective)) => self.decide(detected, effective, now),
+                None => Vec::new(),
+            },
+            Event::ManualOverride { target, detected } => {
+                if self.state == State::HoldingPattern {
+                    warn!("Ignoring the switch to {target}: no outputs to work with");
+                    return Vec::new();
+                }
+                self.override_presence = Some(detected);
+                self.override_until = (!self.override_timeout.is_zero()).then(|| now + self.override_timeout);
+                if target == self.state {
+                    self.pending = None;
+                    return Vec::new();
+                }
+                self.switch(target, now)
+            }
+            Event::CancelPending { detected } => {
+                if self.pending.take().is_none() {
+                    return Vec::new();
+                }
+                info!("Pending switch cancelled, staying {}", self.state);
+                self.override_presence = Some(detected);
+                self.override_until = None;
+                vec![Action::Cancelled]
+            }
+            Event::StreamStarted => {
+                self.streaming = true;
+                self.quiet_since = None;
+                Vec::new()
+            }
+            Event::StreamEnded => {
+                self.streaming = false;
+                self.quiet_since = Some(now);
+                Vec::new()
+            }
+            Event::Busy => {
+                self.busy = true;
+                Vec::new()
+            }
+            Event::NotBusy => {
+                self.busy = false;
+                self.held_by_load = false;
+                Vec::new()
+            }
+            Event::Pause => {
+                self.pending = None;
+                Vec::new()
+            }
+            Event::OutputsLost => {
+                if self.state == State::HoldingPattern {
+                    return Vec::new();
+                }
+                warn!("No outputs found (GPU reset?), holding in {} until they return", self.state);
+                let from = self.state;
+                self.resume = Some(from);
+                self.state = State::HoldingPattern;
+                self.pending = None;
+                vec![Action::Hold { from }]
+            }
+            Event::OutputsReturned => match self.resume.take() {
+                Some(resume) => {
+                    info!("Outputs are back, re-applying {resume}");
+                    self.switch(resume, now)
+                }
+                None => Vec::new(),
+            },
+        }
+    }
+
+    fn decide(&mut self, detected: Presence, effective: Presence, now: Instant) -> Vec<Action> {
+        if self.state == State::HoldingPattern {
+            return Vec::new();
+        }
+        // Counting from the first reading, for an Away we started in. A
+        // switch back to AtDesk that's waiting out the grace period goes
+        // first.
+        if self.state == State::Away {
+            self.quiet_since.get_or_insert(now);
+        }
+        if self.standby_due(now) && self.pending.is_none() {
+            info!("Nobody has streamed for a while, going to Standby");
+            return self.switch(State::Standby, now);
+        }
+        let desired = match effective {
+            Presence::Absent => State::Away,
+            Presence::Present => State::AtDesk,
+            Presence::Unknown => {
+                info!("Presence unknown, holding current state");
+                return Vec::new();
+            }
+        };
+
+        if let Some(held) = self.override_presence {
+            if self.override_until.is_some_and(|until| now >= until) {
+                info!("Manual override ran out, following presence again");
+            } else if detected == held {
+                return Vec::new();
+            } else {
+                info!("Presence changed to {detected:?}, releasing manual override");
+            }
+            self.override_presence = None;
+            self.override_until = None;
+        }
+
+        if desired == as_presence_state(self.state) {
+            // Already in the right state — clear any pending transition
+            self.pending = None;
+            self.held_by_stream = false;
+            self.held_by_load = false;
+            return Vec::new();
+        }
+
+        match self.pending {
+            None => {
+                info!("Presence changed to {effective:?}, waiting grace period...");
+                self.pending = Some((desired, now));
+                self.held_by_cooldown = false;
+                if self.grace_period.is_zero() { Vec::new() } else { vec![Action::StartGrace { to: desired }] }
+            }
+            // Someone is mid-stream (e.g. from the couch): leaving Away would
+            // kill it. Keep the grace timer so we switch as soon as it ends.
+            Some((_, started))
+                if now - started >= self.grace_period
+                    && desired == State::AtDesk
+                    && self.block_desk_while_streaming
+                    && self.streaming =>
+            {
+                if self.held_by_stream {
+                    return Vec::new();
+                }
+                info!("Stream in progress, staying Away until it ends");
+                self.held_by_stream = true;
+                vec![Action::HoldForStream]
+            }
+            // A render or a compile would lose the GPU and CPU time Away
+            // takes. Like a stream, this keeps the grace timer.
+            Some((_, started)) if now - started >= self.grace_period && desired == State::Away && self.busy => {
+                if self.held_by_load {
+                    return Vec::new();
+                }
+                info!("Machine busy, staying {} until it isn't", self.state);
+                self.held_by_load = true;
+                vec![Action::HoldForLoad]
+            }
+            // The reading has held, but we switched only just now: DPMS
+            // bouncing on a flaky cable would otherwise start and stop
+            // Sunshine every grace period.
+            Some((_, started)) if now - started >= self.grace_period && self.cooling_down(now) => {
+                if self.held_by_cooldown {
+                    return Vec::new();
+                }
+                self.held_by_cooldown = true;
+                self.cooldown_holds += 1;
+                let since = self.last_switch.map_or(Duration::ZERO, |at| now - at);
+                if self.cooldown_holds >= COOLDOWN_WARNING {
+                    warn!(
+                        "Switch to {desired} held back by the cooldown again ({} in a row) — is the main display's connection flaky?",
+                        self.cooldown_holds
+                    );
+                } else {
+                    info!("Last switch was {:.0}s ago, holding {desired} until the cooldown is up", since.as_secs_f64());
+                }
+                vec![Action::HoldForCooldown { in_a_row: self.cooldown_holds }]
+            }
+            Some((_, started)) if now - started >= self.grace_period => {
+                info!("Grace period elapsed, transitioning: {} → {desired}", self.state);
+                if !self.held_by_cooldown {
+                    self.cooldown_holds = 0;
+                }
+                self.switch(desired, now)
+            }
+            Some((_, started)) => {
+                let remaining = self.grace_period - (now - started);
+                debug!("Waiting... {:.0}s remaining", remaining.as_secs_f64());
+                Vec::new()
+            }
+        }
+    }
+
+    fn switch(&mut self, to: State, now: Instant) -> Vec<Action> {
+        let from = self.state;
+        self.state = to;
+        self.pending = None;
+        self.held_by_stream = false;
+        self.held_by_load = false;
+        self.held_by_cooldown = false;
+        self.last_switch = Some(now);
+        self.quiet_since = (to == State::Away).then_some(now);
+        vec![Action::Transition { from, to }]
+    }
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRACE: Duration = Duration::from_secs(10);
+
+    fn machine(state: State) -> StateMachine {
+        let config = Config { grace_period: GRACE, ..Config::default() };
+        StateMachine::new(state, &config)
+    }
+
+    fn reading(presence: Presence) -> Event {
+        Event::Presence { detected: presence, effective: presence }
+    }
+
+    fn secs(n: u64) -> Duration {
+        Duration::from_secs(n)
+    }
+
+    #[test]
+    fn test_grace_period() {
+        let start = Instant::now();
+        let mut m = machine(State::AtDesk);
+
+        assert_eq!(m.handle(reading(Presence::Present), start), []);
+        assert_eq!(m.handle(reading(Presence::Absent), start), [Action::StartGrace { to: State::Away }]);
+        assert_eq!(m.pending(), Some((State::Away, start)));
+        assert_eq!(m.handle(Event::Tick, start + secs(9)), []);
+        assert_eq!(m.handle(Event::Tick, start + GRACE), [Action::Transition { from: State::AtDesk, to: State::Away }]);
+        assert_eq!(m.state(), State::Away);
+        assert_eq!(m.pending(), None);
+    }
+
+    #[test]
+    fn test_flap_resets_grace_period() {
+        let start = Instant::now();
+        let mut m = machine(State::AtDesk);
+
+        m.handle(reading(Presence::Absent), start);
+        // The monitor comes back before the grace period is up...
+        assert_eq!(m.handle(reading(Presence::Present), start + secs(5)), []);
+        assert_eq!(m.pending(), None);
+        // ...so going off again starts a new one.
+        assert_eq!(m.handle(reading(Presence::Absent), start + secs(8)), [Action::StartGrace { to: State::Away }]);
+        assert_eq!(m.handle(Event::Tick, start + secs(12)), []);
+        assert_eq!(m.handle(Event::Tick, start + secs(18)).len(), 1);
+    }
+
+    #[test]
+    fn test_cooldown() {
+        let start = Instant::now();
+        let config = Config { grace_period: GRACE, cooldown: secs(60), ..Config::default() };
+        let mut m = StateMachine::new(State::AtDesk, &config);
+
+        // The first switch isn't held: there's no last one.
+        m.handle(reading(Presence::Absent), start);
+        assert_eq!(m.handle(Event::Tick, start + GRACE).len(), 1);
+
+        // DPMS bounces: the switch back is due after the grace period,
+        // but held until a minute after the last one, reported once.
+        let switched = start + GRACE;
+        m.handle(reading(Presence::Present), switched + secs(5));
+        assert_eq!(m.handle(Event::Tick, switched + secs(15)), [Action::HoldForCooldown { in_a_row: 1 }]);
+        assert_eq!(m.handle(Event::Tick, switched + secs(20)), []);
+        assert_eq!(m.due(), Some(switched + secs(60)));
+        assert_eq!(m.state(), State::Away);
+
+        // Bouncing back before then calls it off; the holds add up.
+        m.handle(reading(Presence::Absent), switched + secs(25));
+        assert_eq!(m.pending(), None);
+        m.handle(reading(Presence::Present), switched + secs(30));
+        assert_eq!(m.handle(Event::Tick, switched + secs(40)), [Action::HoldForCooldown { in_a_row: 2 }]);
+        assert_eq!(m.handle(Event::Tick, switched + secs(60)), [Action::Transition { from: State::Away, to: State::AtDesk }]);
+
+        // Still flapping after that: the count carries on.
+        m.handle(reading(Presence::Absent), switched + secs(61));
+        assert_eq!(m.handle(Event::Tick, switched + secs(71)), [Action::HoldForCooldown { in_a_row: 3 }]);
+
+        // A switch the cooldown didn't touch starts it over.
+        m.handle(reading(Presence::Present), switched + secs(72));
+        m.handle(reading(Presence::Absent), switched + secs(200));
+        assert_eq!(m.handle(Event::Tick, switched + secs(210)).len(), 1);
+        m.handle(reading(Presence::Present), switched + secs(215));
+        assert_eq!(m.handle(Event::Tick, switched + secs(225)), [Action::HoldForCooldown { in_a_row: 1 }]);
+    }
+
+    #[test]
+    fn test_unknown_keeps_timer() {
+        let start = Instant::now();
+        let mut m = machine(State::AtDesk);
+
+        m.handle(reading(Presence::Absent), start);
+        assert_eq!(m.handle(reading(Presence::Unknown), start + secs(5)), []);
+        assert_eq!(m.pending(), Some((State::Away, start)));
+        assert_eq!(m.handle(reading(Presence::Absent), start + GRACE).len(), 1);
+    }
+
+    #[test]
+    fn test_zero_grace_period() {
+        let start = Instant::now();
+        let mut m = StateMachine::new(State::AtDesk, &Config { grace_period: Duration::ZERO, ..Config::default() });
+
+        // No countdown to show; the switch comes with the next reading.
+        assert_eq!(m.handle(reading(Presence::Absent), start), []);
+        assert_eq!(m.handle(Event::Tick, start), [Action::Transition { from: State::AtDesk, to: State::Away }]);
+    }
+
+    #[test]
+    fn test_manual_override() {
+        let start = Instant::now();
+        let mut m = machine(State::AtDesk);
+
+        let force = Event::ManualOverride { target: State::Away, detected: Presence::Present };
+        assert_eq!(m.handle(force, start), [Action::Transition { from: State::AtDesk, to: State::Away }]);
+        // Held while the reading is what it was when forced...
+        assert_eq!(m.handle(reading(Presence::Present), start + secs(60)), []);
+        assert_eq!(m.state(), State::Away);
+        // ...and released once it changes.
+        assert_eq!(m.handle(reading(Presence::Absent), start + secs(61)), []);
+        assert_eq!(m.handle(reading(Presence::Present), start + secs(62)), [Action::StartGrace { to: State::AtDesk }]);
+
+        // Forcing the state we're in only drops the pending switch.
+        let force = Event::ManualOverride { target: State::Away, detected: Presence::Present };
+        assert_eq!(m.handle(force, start + secs(63)), []);
+        assert_eq!(m.pending(), None);
+    }
+
+    #[test]
+    fn test_override_timeout() {
+        let start = Instant::now();
+        let mut m = StateMachine::new(State::AtDesk, &Config { grace_period: GRACE, override_timeout: secs(600), ..Config::default() });
+
+        let force = Event::ManualOverride { target: State::Away, detected: Presence::Present };
+        assert_eq!(m.handle(force, start), [Action::Transition { from: State::AtDesk, to: State::Away }]);
+        assert_eq!(m.override_due(), Some(start + secs(600)));
+        assert_eq!(m.handle(reading(Presence::Present), start + secs(599)), []);
+        // Time's up with the monitor still on: back to AtDesk, after the
+        // grace period like any other switch.
+        assert_eq!(m.handle(Event::Tick, start + secs(600)), [Action::StartGrace { to: State::AtDesk }]);
+        assert_eq!(m.override_due(), None);
+        assert_eq!(m.handle(Event::Tick, start + secs(600) + GRACE), [Action::Transition { from: State::Away, to: State::AtDesk }]);
+    }
+
+    #[test]
+    fn test_override_follows_detected() {
+        let start = Instant::now();
+        let mut m = machine(State::AtDesk);
+
+        // Forced Away with the monitor on, then an Away window starts: the
+        // effective reading changes, the detected one doesn't, so the
+        // override still holds.
+        m.handle(Event::ManualOverride { target: State::Away, detected: Presence::Present }, start);
+        let window = Event::Presence { detected: Presence::Present, effective: Presence::Absent };
+        assert_eq!(m.handle(window, start + secs(1)), []);
+        assert_eq!(m.handle(reading(Presence::Absent), start + secs(2)), []);
+        assert_eq!(m.handle(reading(Presence::Present), start + secs(3)), [Action::StartGrace { to: State::AtDesk }]);
+    }
+
+    #[test]
+    fn test_cancel_pending() {
+        let start = Instant::now();
+        let mut m = machine(State::AtDesk);
+
+        assert_eq!(m.handle(Event::CancelPending { detected: Presence::Absent }, start), []);
+        m.handle(reading(Presence::Absent), start);
+        assert_eq!(m.handle(Event::CancelPending { detected: Presence::Absent }, start + secs(5)), [Action::Cancelled]);
+        assert_eq!(m.handle(Event::Tick, start + secs(30)), []);
+        assert_eq!(m.state(), State::AtDesk);
+    }
+
+    #[test]
+    fn test_stream_holds_away() {
+        let start = Instant::now();
+        let mut m = machine(State::Away);
+
+        m.handle(Event::StreamStarted, start);
+        m.handle(reading(Presence::Present), start);
+        assert_eq!(m.handle(Event::Tick, start + GRACE), [Action::HoldForStream]);
+        assert!(m.held_by_stream());
+        // Reported once, not every poll.
+        assert_eq!(m.handle(Event::Tick, start + secs(20)), []);
+
+        // The switch happens as soon as the stream ends; the grace period
+        // is long over.
+        m.handle(Event::StreamEnded, start + secs(30));
+        assert_eq!(m.handle(Event::Tick, start + secs(30)), [Action::Transition { from: State::Away, to: State::AtDesk }]);
+        assert!(!m.held_by_stream());
+    }
+
+    #[test]
+    fn test_load_holds_away() {
+        let start = Instant::now();
+        let mut m = machine(State::AtDesk);
+
+        m.handle(Event::Busy, start);
+        m.handle(reading(Presence::Absent), start);
+        assert_eq!(m.handle(Event::Tick, start + GRACE), [Action::HoldForLoad]);
+        assert!(m.held_by_load());
+        assert_eq!(m.handle(Event::Tick, start + secs(20)), []);
+
+        m.handle(Event::NotBusy, start + secs(30));
+        assert_eq!(m.handle(Event::Tick, start + secs(30)), [Action::Transition { from: State::AtDesk, to: State::Away }]);
+
+        // Leaving Away, or a manual switch, isn't held.
+        m.handle(Event::Busy, start + secs(40));
+        m.handle(reading(Presence::Present), start + secs(40));
+        assert_eq!(m.handle(Event::Tick, start + secs(50)), [Action::Transition { from: State::Away, to: State::AtDesk }]);
+        let forced = m.handle(Event::ManualOverride { target: State::Away, detected: Presence::Present }, start + secs(60));
+        assert_eq!(forced, [Action::Transition { from: State::AtDesk, to: State::Away }]);
+    }
+
+    #[test]
+    fn test_stream_without_blocking() {
+        let start = Instant::now();
+        let mut config = Config { grace_period: GRACE, ..Config::default() };
+        config.sunshine.block_desk_while_streaming = false;
+        let mut m = StateMachine::new(State::Away, &config);
+
+        m.handle(Event::StreamStarted, start);
+        m.handle(reading(Presence::Present), start);
+        assert_eq!(m.handle(Event::Tick, start + GRACE), [Action::Transition { from: State::Away, to: State::AtDesk }]);
+    }
+
+    #[test]
+    fn test_standby() {
+        let start = Instant::now();
+        let mut config = Config { grace_period: GRACE, ..Config::default() };
+        config.standby.enabled = true;
+        config.standby.after = secs(600);
+        let mut m = StateMachine::new(State::Away, &config);
+
+        // A stream keeps it from counting...
+        assert_eq!(m.handle(reading(Presence::Absent), start), []);
+        m.handle(Event::StreamStarted, start + secs(60));
+        assert!(!m.standby_due(start + secs(900)));
+        // ...and its end starts the clock over.
+        m.handle(Event::StreamEnded, start + secs(900));
+        assert!(!m.standby_due(start + secs(1400)));
+        assert!(m.standby_due(start + secs(1500)));
+        assert_eq!(m.handle(Event::Tick, start + secs(1500)), [Action::Transition { from: State::Away, to: State::Standby }]);
+
+        // Still Away as far as presence goes; a wake brings it back, and
+        // the desk being used again ends it.
+        assert_eq!(m.handle(reading(Presence::Absent), start + secs(1600)), []);
+        let wake = Event::ManualOverride { target: State::Away, detected: Presence::Absent };
+        assert_eq!(m.handle(wake, start + secs(1700)), [Action::Transition { from: State::Standby, to: State::Away }]);
+        assert!(!m.standby_due(start + secs(2200)));
+        assert_eq!(m.handle(Event::Tick, start + secs(2300)), [Action::Transition { from: State::Away, to: State::Standby }]);
+        assert_eq!(m.handle(reading(Presence::Present), start + secs(2400)), [Action::StartGrace { to: State::AtDesk }]);
+        assert_eq!(
+            m.handle(Event::Tick, start + secs(2400) + GRACE),
+            [Action::Transition { from: State::Standby, to: State::AtDesk }]
+        );
+    }
+
+    #[test]
+    fn test_pause() {
+        let start = Instant::now();
+        let mut m = machine(State::AtDesk);
+
+        m.handle(reading(Presence::Absent), start);
+        m.handle(Event::Pause, start + secs(9));
+        // The grace period starts over once readings flow again.
+        assert_eq!(m.handle(reading(Presence::Absent), start + secs(11)), [Action::StartGrace { to: State::Away }]);
+        assert_eq!(m.handle(Event::Tick, start + secs(20)), []);
+    }
+
+    #[test]
+    fn test_holding_pattern() {
+        let start = Instant::now();
+        let mut m = machine(State::Away);
+
+        m.handle(reading(Presence::Present), start);
+        assert_eq!(m.handle(Event::OutputsLost, start + secs(1)), [Action::Hold { from: State::Away }]);
+        assert_eq!(m.handle(Event::OutputsLost, start + secs(2)), []);
+        assert_eq!(m.pending(), None);
+        // Nothing is decided while holding, readings or overrides.
+        assert_eq!(m.handle(Event::Tick, start + secs(30)), []);
+        assert_eq!(m.handle(Event::ManualOverride { target: State::AtDesk, detected: Presence::Present }, start), []);
+
+        assert_eq!(
+            m.handle(Event::OutputsReturned, start + secs(40)),
+            [Action::Transition { from: State::HoldingPattern, to: State::Away }]
+        );
+        assert_eq!(m.handle(Event::OutputsReturned, start + secs(41)), []);
+    }
+}
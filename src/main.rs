@@ -1,64 +1,929 @@
 // src/main.rs — VitaminK entry point
 //
-// New Rust concept: `mod` declarations.
-// `mod display;` tells Rust to look for src/display.rs and include it.
-// Each module is its own namespace: `display::get_displays()`, etc.
-
-mod daemon;
-mod display;
-mod sunshine;
+// Just the command line: everything it drives lives in the library crate
+// (see lib.rs), reached as `vitamink::display` and so on.
 
 use std::env;
 
+use log::{LevelFilter, error, info, warn};
+
+use vitamink::{
+    cli, config, crash, daemon, dbus, detail, diagram, doctor, display, drm, duration, edid, environment, error, history, install, instance, layout, listing, logging, overview, pattern, persist, process, remote, services,
+    simulate, smoke, startup, status, statusline, sunshine, tune, validate, watch,
+};
+
 fn main() {
-    // Simple argument handling: `vitamink daemon` runs the polling loop,
-    // anything else (or no args) prints system status.
-    let args: Vec<String> = env::args().collect();
+    // Simple argument handling: `vitamink daemon` runs the polling loop
+    // (with `--dry-run`, changes are logged instead of made; `--replace`
+    // stops one that's already running, see instance.rs), `vitamink
+    // reconcile [--dry-run] [--force]` does what one of its polls would, once and
+    // without the grace period, then exits, `vitamink tune`
+    // measures timings, `vitamink diagram [--mermaid]` prints the state
+    // machine (`vitamink simulate SCRIPT [--grace-period 20s] [--cooldown 1m]`
+    // rehearses it, see simulate.rs), `vitamink displays` lists outputs and `vitamink modes NAME`
+    // one output's modes (see listing.rs), `vitamink set-mode NAME WxH@HZ`
+    // switches an output's mode, `vitamink display show NAME [--json]`
+    // details one (see detail.rs),
+    // `vitamink output power-cycle NAME` turns one off and on again,
+    // `vitamink layout save|apply NAME` keeps and restores named layouts
+    // (`vitamink layout list` lists them, see layout.rs),
+    // `vitamink watch` shows it all live (see watch.rs), `vitamink history
+    // [--json] [--limit N]` lists past transitions (see history.rs),
+    // `vitamink doctor` checks the setup (see doctor.rs), `vitamink config
+    // check` the config against this machine (see validate.rs), `vitamink install
+    // [--force] [--linger]` sets up the user service (see install.rs), `vitamink
+    // smoke-test` checks the build without hardware (see smoke.rs),
+    // `vitamink profile [NAME|none]` lists or switches Away profiles,
+    // `vitamink away` and `vitamink desk` switch the running daemon by hand,
+    // `vitamink sunshine apps|clients|close` talks to Sunshine's web API,
+    // `vitamink ctl diagnostics [--kscreen]` has the running daemon write a
+    // debug bundle (`ctl client-mode` is for Sunshine prep commands), `vitamink status --json` prints the daemon's
+    // status.json (`vitamink statusline --format waybar|i3bar [--follow]`
+    // as a bar module, see statusline.rs), `vitamink inhibit [--duration 2h] REASON` pauses
+    // automatic switching (see inhibit.rs), anything else (or no args)
+    // prints system status (`vitamink status --diff` also what's out of
+    // step with the daemon's state, see overview.rs). `vitamink completions
+    // bash|zsh|fish` and `vitamink man` print what's described in cli.rs.
+    // `vitamink fleet status` asks every `[hosts]` entry how it's doing.
+    //
+    // `--log-level LEVEL` (error, warn, info, debug, ...) works with any of
+    // them. `--host NAME` sends status, away, desk or inhibit to another
+    // PC's daemon instead (see remote.rs).
+    let mut args: Vec<String> = env::args().collect();
+    logging::init(take_log_level(&mut args));
+    if let Some(host) = take_host(&mut args) {
+        return run_remote(&host, &args);
+    }
     let command = args.get(1).map(|s| s.as_str());
 
     match command {
-        Some("daemon") => run_daemon(),
-        _ => print_status(),
+        Some("daemon") => run_daemon(args.iter().any(|a| a == "--dry-run"), args.iter().any(|a| a == "--replace")),
+        Some("reconcile") => run_reconcile(args.iter().any(|a| a == "--dry-run"), args.iter().any(|a| a == "--force")),
+        Some("tune") => run_tune(args.iter().any(|a| a == "--write")),
+        Some("diagram") => print_diagram(args.iter().any(|a| a == "--mermaid")),
+        Some("simulate") => run_simulate(&args[2..]),
+        Some("displays") => print_displays(&args[2..]),
+        Some("display") => show_display(&args[2..]),
+        Some("modes") => print_modes(&args[2..]),
+        Some("set-mode") => run_set_mode(&args[2..]),
+        Some("output") => run_output(&args[2..]),
+        Some("layout") => run_layout(&args[2..]),
+        Some("watch") => run_watch(),
+        Some("history") => print_history(&args[2..]),
+        Some("status") if args.iter().any(|a| a == "--json") => print_status_json(),
+        Some("status") => print_status(args.iter().any(|a| a == "--diff")),
+        Some("statusline") => run_statusline(&args[2..]),
+        Some("profile") => run_profile(args.get(2).map(|s| s.as_str())),
+        Some("away") => run_force(true),
+        Some("desk") => run_force(false),
+        Some("fleet") => run_fleet(&args[2..]),
+        #[cfg(feature = "sunshine-api")]
+        Some("sunshine") => run_sunshine(args.get(2).map(|s| s.as_str())),
+        Some("ctl") => run_ctl(&args[2..]),
+        Some("inhibit") => run_inhibit(&args[2..], None),
+        Some("doctor") => run_doctor(),
+        Some("config") => run_config(&args[2..]),
+        Some("install") => run_install(&args[2..]),
+        Some("smoke-test") => run_smoke_test(),
+        Some("completions") => print_completions(args.get(2).map(|s| s.as_str())),
+        Some("man") => print!("{}", cli::man()),
+        _ => print_status(false),
+    }
+}
+
+fn print_completions(shell: Option<&str>) {
+    match shell.map(str::parse::<cli::Shell>) {
+        Some(Ok(shell)) => print!("{}", cli::completions(shell)),
+        Some(Err(e)) => {
+            eprintln!("Error: {e}");
+            std::process::exit(2);
+        }
+        None => {
+            eprintln!("Usage: vitamink completions bash|zsh|fish");
+            std::process::exit(2);
+        }
     }
 }
 
-fn run_daemon() {
-    eprintln!("[vitamink] VitaminK Daemon starting...");
-    let config = daemon::Config::default();
+// Removes `--log-level LEVEL` from `args` so the commands never see it.
+fn take_log_level(args: &mut Vec<String>) -> LevelFilter {
+    let Some(i) = args.iter().position(|a| a == "--log-level") else {
+        return LevelFilter::Info;
+    };
+    let value = args.drain(i..(i + 2).min(args.len())).nth(1).unwrap_or_default();
+    match logging::parse_level(&value) {
+        Ok(level) => level,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            std::process::exit(2);
+        }
+    }
+}
+
+// Removes `--host NAME` from `args`, like `--log-level`.
+fn take_host(args: &mut Vec<String>) -> Option<String> {
+    let i = args.iter().position(|a| a == "--host")?;
+    match args.drain(i..(i + 2).min(args.len())).nth(1) {
+        Some(host) if !host.is_empty() => Some(host),
+        _ => {
+            eprintln!("Error: --host needs a name from [hosts], or HOST[:PORT]");
+            std::process::exit(2);
+        }
+    }
+}
+
+fn load_config() -> config::Config {
+    let mut config = read_config();
+    init(&mut config);
+    config
+}
+
+fn read_config() -> config::Config {
+    match config::load() {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Config error: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn init(c: &mut config::Config) {
+    pattern::resolve_config(c);
+    display::init(c);
+    environment::init(&c.environment);
+    process::set_timeout(c.command_timeout);
+}
+
+fn run_daemon(dry_run: bool, replace: bool) {
+    info!("VitaminK Daemon starting...");
+    crash::install();
+    // Held until the daemon exits.
+    let _lock = lock_instance(replace);
+    if dry_run {
+        info!("Dry run: commands that change anything will be logged, not run");
+        process::set_dry_run(true);
+    }
+    // Started at login, the compositor may not be up yet (see startup.rs).
+    let mut config = read_config();
+    logging::configure(config.log_format, config.log_file.as_deref());
+    let deadline = startup::deadline(&config);
+    startup::wait_for_socket(&config, deadline);
+    init(&mut config);
+    startup::wait_for_outputs(&config, deadline);
+    check_outputs(&config);
     let mut daemon = daemon::Daemon::new(config);
     daemon.run();
+    if daemon.gave_up() {
+        // Nonzero, for systemd's Restart=on-failure (see recovery.rs).
+        error!("VitaminK Daemon stopped after too many failed polls");
+        std::process::exit(1);
+    }
+    info!("VitaminK Daemon stopped");
 }
 
-fn print_status() {
-    println!("VitaminK — Sunshine Lifecycle Manager\n");
+// For a shortcut or an acpid hook instead of the daemon: reads presence,
+// sets up the state it calls for, prints it and exits. A daemon that's
+// running does all this itself, so that's an error. `--force` goes Away
+// even with the machine busy (see load.rs).
+fn run_reconcile(dry_run: bool, force: bool) {
+    let _lock = match instance::try_lock() {
+        Ok(Some(lock)) => lock,
+        Ok(None) => {
+            error!("The daemon is running and already does this; its D-Bus methods ForceAway and ForceDesk switch it by hand");
+            std::process::exit(1);
+        }
+        Err(e) => {
+            error!("Couldn't take the daemon lock: {e}");
+            std::process::exit(1);
+        }
+    };
+    if dry_run {
+        process::set_dry_run(true);
+    }
+    let mut config = read_config();
+    init(&mut config);
+    let mut daemon = daemon::Daemon::new(config);
+    match daemon.reconcile(force) {
+        Ok(state) => println!("{state}"),
+        Err(e) => {
+            error!("{e}");
+            println!("{}", daemon.state());
+            std::process::exit(1);
+        }
+    }
+}
 
+// Exits if another daemon is running, unless `replace` says to stop it.
+fn lock_instance(replace: bool) -> instance::Lock {
+    let result = if replace { instance::replace(instance::REPLACE_TIMEOUT).map(Some) } else { instance::try_lock() };
+    match result {
+        Ok(Some(lock)) => lock,
+        Ok(None) => {
+            match instance::holder() {
+                Some(pid) => error!("Another VitaminK daemon is already running (pid {pid})"),
+                None => error!("Another VitaminK daemon is already running"),
+            }
+            error!("Stop it first, or start this one with --replace to take over");
+            std::process::exit(1);
+        }
+        Err(e) => {
+            error!("Couldn't take the daemon lock: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+// Exits if the configured outputs don't match the hardware. If no outputs
+// can be listed at all (e.g. the GPU isn't up yet), the daemon starts
+// anyway and waits for them in HoldingPattern.
+fn check_outputs(config: &config::Config) {
     let displays = match display::get_displays() {
-        Ok(d) => d,
+        Ok(displays) if !displays.is_empty() => displays,
+        Ok(_) => {
+            warn!("No outputs listed yet, skipping the output check");
+            return;
+        }
+        Err(e) => {
+            warn!("Couldn't check the configured outputs: {e}");
+            return;
+        }
+    };
+    let problems = config.check_outputs(&displays);
+    for problem in &problems {
+        error!("Config: {problem}");
+    }
+    if !problems.is_empty() {
+        error!("Fix {} or add the outputs to allow_missing", config::default_path().display());
+        std::process::exit(1);
+    }
+}
+
+fn run_tune(write: bool) {
+    let config = load_config();
+
+    println!("Measuring display timings — your monitors will blank briefly.\n");
+    let m = match tune::measure(&config) {
+        Ok(m) => m,
         Err(e) => {
             eprintln!("Error: {e}");
             std::process::exit(1);
         }
     };
 
-    for d in &displays {
-        let state = match d.state {
-            display::DisplayState::Enabled => "enabled",
-            display::DisplayState::Disabled => "disabled",
+    let show = |d: Option<std::time::Duration>| d.map_or("no change seen".to_string(), |d| format!("{:.2}s", d.as_secs_f64()));
+    println!("kscreen-doctor enumeration: {:.2}s", m.enumerate.as_secs_f64());
+    println!("Dummy plug enable:          {:.2}s", m.apply.as_secs_f64());
+    println!("DRM activation:             {:.2}s", m.drm_activation.as_secs_f64());
+    println!("DPMS off propagation:       {}", show(m.dpms_off));
+    println!("DPMS on propagation:        {}", show(m.dpms_on));
+
+    let rec = tune::recommend(&m);
+    println!("\nRecommended settings:");
+    println!("  poll_interval = \"{}\"", duration::format(rec.poll_interval));
+    println!("  grace_period = \"{}\"", duration::format(rec.grace_period));
+    println!("  drm_timeout = \"{}\"", duration::format(rec.drm_timeout));
+
+    if write {
+        let path = config::default_path();
+        match tune::write(&path, &rec) {
+            Ok(()) => println!("\nWritten to {}", path.display()),
+            Err(e) => {
+                eprintln!("Error: {e}");
+                std::process::exit(1);
+            }
+        }
+    } else {
+        println!("\nRun `vitamink tune --write` to save them to {}", config::default_path().display());
+    }
+}
+
+fn print_diagram(mermaid: bool) {
+    let machine = diagram::build(&load_config());
+    if mermaid {
+        print!("{}", diagram::to_mermaid(&machine));
+    } else {
+        print!("{}", diagram::to_dot(&machine));
+    }
+}
+
+// `vitamink simulate SCRIPT [--grace-period DUR] [--cooldown DUR] [--poll-interval DUR]`:
+// the overrides try other timings without editing the config.
+fn run_simulate(args: &[String]) {
+    let usage = || -> ! {
+        eprintln!("Usage: vitamink simulate SCRIPT [--grace-period 20s] [--cooldown 1m] [--poll-interval 5s]");
+        std::process::exit(2);
+    };
+    let mut config = load_config();
+    // The state machine's own log lines would only repeat what's printed.
+    log::set_max_level(log::max_level().min(LevelFilter::Warn));
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let setting = match arg.as_str() {
+            "--grace-period" => &mut config.grace_period,
+            "--cooldown" => &mut config.cooldown,
+            "--poll-interval" => &mut config.poll_interval,
+            _ if path.is_none() && !arg.starts_with("--") => {
+                path = Some(arg.clone());
+                continue;
+            }
+            _ => usage(),
         };
-        let conn = match d.connection {
-            display::ConnectionState::Connected => "connected",
-            display::ConnectionState::Disconnected => "disconnected",
+        *setting = match duration::parse(args.next().unwrap_or_else(|| usage())) {
+            Ok(d) => d,
+            Err(e) => {
+                eprintln!("Error: {e}");
+                std::process::exit(2);
+            }
         };
-        let dpms = display::read_dpms(&d.name);
+    }
+    let path = path.unwrap_or_else(|| usage());
+    let script = std::fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|text| simulate::parse(&text));
+    match script {
+        Ok(script) => print!("{}", simulate::render(&config, &simulate::run(&script, &config))),
+        Err(e) => {
+            eprintln!("Error: {path}: {e}");
+            std::process::exit(2);
+        }
+    }
+}
 
-        println!("{} (Output {}): {state}, {conn}, DPMS: {dpms:?}", d.name, d.index);
-        println!("  {} modes available", d.modes.len());
+// `vitamink displays [--connected] [--sort KEY] [--wide]`
+fn print_displays(args: &[String]) {
+    let options = match listing::parse_args(args) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            std::process::exit(2);
+        }
+    };
+    load_config();
+    if options.json {
+        let displays = get_displays_or_exit();
+        let shown = listing::select(&displays, &options);
+        println!("{}", serde_json::to_string_pretty(&shown).expect("Display serializes"));
+        return;
+    }
+    print!("{}", displays_table(&options));
+}
 
-        if let Some(current) = d.modes.iter().find(|m| m.current) {
-            println!("  Current: {}x{}@{:.2}Hz", current.width, current.height, current.refresh);
+// `vitamink modes NAME [--json]`: what `set-mode` can pick from.
+fn print_modes(args: &[String]) {
+    let json = args.iter().any(|a| a == "--json");
+    let Some(name) = args.iter().find(|a| !a.starts_with("--")) else {
+        eprintln!("Usage: vitamink modes NAME [--json]");
+        std::process::exit(2);
+    };
+
+    load_config();
+    let found = find_display_or_exit(name);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&found.modes).expect("Mode serializes"));
+    } else {
+        print!("{}", listing::render_modes(&found.modes));
+    }
+}
+
+// `vitamink set-mode NAME WxH[@HZ]`: looks up the mode id the way the
+// daemon does for the dummy plug, and applies it. Unlike the daemon it
+// won't settle for a different resolution.
+fn run_set_mode(args: &[String]) {
+    let (name, target) = match args {
+        [name, mode] => match mode.parse::<display::ModeTarget>() {
+            Ok(target) => (name, target),
+            Err(e) => {
+                eprintln!("Error: {e}");
+                std::process::exit(2);
+            }
+        },
+        _ => {
+            eprintln!("Usage: vitamink set-mode NAME WIDTHxHEIGHT[@HZ]");
+            std::process::exit(2);
+        }
+    };
+
+    load_config();
+    let found = find_display_or_exit(name);
+    let mode = display::select_mode(&found.modes, Some(&target))
+        .filter(|m| m.width == target.width && m.height == target.height)
+        .cloned();
+    let Some(mode) = mode else {
+        eprintln!("Error: {name} has no {target} mode (`vitamink modes {name}` lists them)");
+        std::process::exit(1);
+    };
+    match display::enable_output(name, Some(&target), None) {
+        Ok(()) => println!("{name} is at {}x{}@{:.2} (mode {})", mode.width, mode.height, mode.refresh, mode.id),
+        Err(e) => {
+            eprintln!("Error: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn get_displays_or_exit() -> Vec<display::Display> {
+    match display::get_displays() {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn find_display_or_exit(name: &str) -> display::Display {
+    match get_displays_or_exit().into_iter().find(|d| d.name == name) {
+        Some(d) => d,
+        None => {
+            eprintln!("Error: No output named {name}");
+            std::process::exit(1);
         }
     }
+}
 
-    println!("\nSunshine: {}", if sunshine::is_running() { "running" } else { "stopped" });
+// Lists the configured profiles, or asks the running daemon to switch.
+fn run_profile(name: Option<&str>) {
+    let result = match name {
+        None => {
+            let config = load_config();
+            let active: String = dbus::call_daemon("GetProfile", &()).unwrap_or_default();
+            for profile in config.profiles.keys() {
+                let mark = if *profile == active { "*" } else { " " };
+                println!("{mark} {profile}");
+            }
+            if config.profiles.is_empty() {
+                println!("No profiles configured — add [profiles.NAME] sections to {}", config::default_path().display());
+            }
+            Ok(())
+        }
+        Some("none") => dbus::call_daemon::<()>("SetProfile", &("",)),
+        Some(name) => dbus::call_daemon::<()>("SetProfile", &(name,)),
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
+    }
 }
 
+// `vitamink away|desk`: what the D-Bus methods ForceAway and ForceDesk do.
+fn run_force(away: bool) {
+    let method = if away { "ForceAway" } else { "ForceDesk" };
+    if let Err(e) = dbus::call_daemon::<()>(method, &()) {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
+    }
+}
+
+// `vitamink --host NAME status [--json] | away | desk | inhibit ...`
+fn run_remote(name: &str, args: &[String]) {
+    let host = remote::resolve(&read_config(), name);
+    let result = match args.get(1).map(String::as_str) {
+        None | Some("status") => remote::status(&host).map(|s| {
+            if args.iter().any(|a| a == "--json") {
+                println!("{}", serde_json::to_string_pretty(&s).expect("Status serializes"));
+            } else {
+                print!("{}", overview::daemon(Some(&s), status::unix_now(), overview::colors()));
+            }
+        }),
+        Some("away") => remote::force(&host, true),
+        Some("desk") => remote::force(&host, false),
+        Some("inhibit") => return run_inhibit(&args[2..], Some(&host)),
+        Some(other) => {
+            eprintln!("Error: `{other}` only works on this PC; --host takes status, away, desk and inhibit");
+            std::process::exit(2);
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
+    }
+}
+
+// `vitamink fleet status` (see remote.rs).
+fn run_fleet(args: &[String]) {
+    if args.first().map(String::as_str) != Some("status") {
+        eprintln!("Usage: vitamink fleet status");
+        std::process::exit(2);
+    }
+    let config = read_config();
+    let hosts = remote::hosts(&config);
+    if hosts.is_empty() {
+        println!("No hosts configured — add [hosts.NAME] sections to {}", config::default_path().display());
+        return;
+    }
+    let statuses = remote::fleet(&hosts);
+    print!("{}", remote::render(&hosts, &statuses, status::unix_now()));
+    if statuses.iter().any(Result::is_err) {
+        std::process::exit(1);
+    }
+}
+
+// Before anything else runs: it rewires PATH and the XDG directories.
+fn run_smoke_test() {
+    let components = smoke::run();
+    print!("{}", smoke::render(&components));
+    if components.iter().any(|c| c.result.is_err()) {
+        std::process::exit(1);
+    }
+}
+
+fn run_doctor() {
+    let checks = doctor::run(&load_config());
+    print!("{}", doctor::render(&checks));
+    if checks.iter().any(|c| c.failed()) {
+        std::process::exit(1);
+    }
+}
+
+// `vitamink config check`: prints the effective config, then how it fits
+// the outputs and services here.
+fn run_config(args: &[String]) {
+    if !matches!(args, [action] if action == "check") {
+        eprintln!("Usage: vitamink config check");
+        std::process::exit(2);
+    }
+    let mut config = match config::load() {
+        Ok(c) => c,
+        Err(e) => {
+            println!("✗ {e}");
+            std::process::exit(1);
+        }
+    };
+    // As `init` does, but leaving the patterns for `validate::run`.
+    display::init(&config);
+    environment::init(&config.environment);
+    process::set_timeout(config.command_timeout);
+    let checks = validate::run(&mut config);
+    println!("{}", validate::dump(config));
+    print!("{}", doctor::render(&checks));
+    if checks.iter().any(|c| c.failed()) {
+        std::process::exit(1);
+    }
+}
+
+fn run_install(args: &[String]) {
+    let options = install::Options {
+        force: args.iter().any(|a| a == "--force"),
+        linger: args.iter().any(|a| a == "--linger"),
+    };
+    let checks = install::run(&options);
+    for check in &checks {
+        match &check.outcome {
+            doctor::Outcome::Pass(detail) => println!("✓ {} — {detail}", check.name),
+            doctor::Outcome::Fail { problem, hint } => println!("✗ {} — {problem}\n    → {hint}", check.name),
+        }
+    }
+    if checks.iter().any(|c| c.failed()) {
+        std::process::exit(1);
+    }
+    println!(
+        "\nNext: check the output names in {} (`vitamink displays`), then `systemctl --user start vitamink`",
+        config::default_path().display()
+    );
+}
+
+// `vitamink ctl diagnostics [--kscreen]`, `vitamink ctl cancel` (a pending
+// switch), `vitamink ctl reload` (the config) and `vitamink ctl client-mode [WxH@HZ|--reset]` — requests for
+// the running daemon. Without a mode, client-mode reads Sunshine's prep
+// command environment.
+fn run_ctl(args: &[String]) {
+    let kscreen = args.iter().any(|a| a == "--kscreen");
+    let reset = args.iter().any(|a| a == "--reset");
+    let result = match args.first().map(String::as_str) {
+        Some("diagnostics") => dbus::call_daemon::<String>("Diagnostics", &(kscreen,)).map(|path| println!("{path}")),
+        Some("cancel") => dbus::call_daemon::<()>("CancelPending", &()),
+        Some("reload") => dbus::call_daemon::<()>("Reload", &()),
+        Some("client-mode") => {
+            let mode = match args.get(1) {
+                _ if reset => String::new(),
+                Some(mode) => mode.clone(),
+                None => match sunshine::client_mode() {
+                    Some(mode) => mode.to_string(),
+                    None => {
+                        eprintln!("Error: no mode given and SUNSHINE_CLIENT_WIDTH/HEIGHT aren't set");
+                        std::process::exit(2);
+                    }
+                },
+            };
+            dbus::call_daemon::<()>("SetClientMode", &(mode,))
+        }
+        _ => {
+            eprintln!("Usage: vitamink ctl diagnostics [--kscreen] | cancel | reload | client-mode [WxH@HZ|--reset]");
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
+    }
+}
+
+// `vitamink inhibit [--name NAME] [--duration 2h] REASON` holds an
+// inhibitor in the running daemon (named after the reason unless --name
+// is given), or `host`'s; `vitamink inhibit --release NAME` lets go of it.
+fn run_inhibit(args: &[String], host: Option<&remote::Host>) {
+    let usage = || -> ! {
+        eprintln!("Usage: vitamink inhibit [--name NAME] [--duration 2h] REASON | --release NAME");
+        std::process::exit(2);
+    };
+    let mut name = None;
+    let mut duration = None;
+    let mut release = None;
+    let mut reason = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--name" => name = Some(args.next().unwrap_or_else(|| usage()).clone()),
+            "--release" => release = Some(args.next().unwrap_or_else(|| usage()).clone()),
+            "--duration" => match duration::parse(args.next().unwrap_or_else(|| usage())) {
+                Ok(d) if !d.is_zero() => duration = Some(d),
+                Ok(_) => usage(),
+                Err(e) => {
+                    eprintln!("Error: {e}");
+                    std::process::exit(2);
+                }
+            },
+            _ if reason.is_none() && !arg.starts_with("--") => reason = Some(arg.clone()),
+            _ => usage(),
+        }
+    }
+
+    let result = match (release, reason) {
+        (Some(_), None) if host.is_some() => {
+            eprintln!("Error: the HTTP API can't release an inhibitor; give it a --duration instead");
+            std::process::exit(2);
+        }
+        (Some(name), None) => dbus::call_daemon::<()>("Release", &(name,)),
+        (None, Some(reason)) => {
+            let name = name.unwrap_or_else(|| reason.clone());
+            let seconds = duration.map_or(0, |d| d.as_secs().max(1));
+            match host {
+                Some(host) => remote::inhibit(host, &name, &reason, seconds),
+                None => dbus::call_daemon::<()>("Inhibit", &(name, reason, seconds)),
+            }
+        }
+        _ => usage(),
+    };
+    if let Err(e) = result {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
+    }
+}
+
+// `vitamink sunshine apps|clients|close` — needs api_username/api_password.
+#[cfg(feature = "sunshine-api")]
+fn run_sunshine(action: Option<&str>) {
+    let config = load_config();
+    sunshine::compat::probe(&config.sunshine);
+    let Some(api) = sunshine::api::Client::from_config(&config.sunshine) else {
+        eprintln!("Error: set api_username and api_password under [sunshine] in {}", config::default_path().display());
+        std::process::exit(1);
+    };
+
+    let result = match action {
+        Some("apps") => api.apps().map(|apps| apps.iter().for_each(|app| println!("{}", app.name))),
+        Some("clients") if !sunshine::compat::supports(sunshine::compat::Feature::ClientList) => {
+            Err(error::VitaminkError::Parse("This Sunshine is too old to list paired clients".to_string()))
+        }
+        Some("clients") => api
+            .paired_clients()
+            .map(|clients| clients.iter().for_each(|client| println!("{}  {}", client.uuid, client.name))),
+        Some("close") => api.close_app(),
+        _ => {
+            eprintln!("Usage: vitamink sunshine apps|clients|close");
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
+    }
+}
+
+// `vitamink display show NAME [--json]`
+fn show_display(args: &[String]) {
+    let json = args.iter().any(|a| a == "--json");
+    let positional: Vec<&String> = args.iter().filter(|a| !a.starts_with("--")).collect();
+    let name = match positional[..] {
+        [show, name] if show == "show" => name,
+        _ => {
+            eprintln!("Usage: vitamink display show NAME [--json]");
+            std::process::exit(2);
+        }
+    };
+
+    load_config();
+    let found = display::get_displays().and_then(|displays| {
+        displays
+            .into_iter()
+            .find(|d| d.name == *name)
+            .ok_or_else(|| error::VitaminkError::Parse(format!("No output named {name}")))
+    });
+    let found = match found {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    let raw = display::backend().raw_output(name).unwrap_or_else(|e| vec![format!("(unavailable: {e})")]);
+    let detail = detail::Detail {
+        capabilities: detail::Capabilities {
+            internal_panel: display::is_internal_panel(name),
+            dpms: display::read_dpms(name),
+            drm_active: display::is_drm_active(name),
+            link: drm::connector(name).map(|c| c.status),
+            gpu: drm::gpu_of(name).map(|gpu| gpu.to_string()),
+            reported: detail::reported_capabilities(&raw),
+        },
+        edid: edid::read(name),
+        raw,
+        display: &found,
+    };
+
+    if json {
+        println!("{}", detail::to_json(&detail));
+    } else {
+        print!("{}", detail::to_text(&detail));
+    }
+}
+
+// `vitamink history [--json] [--limit N]`, newest last.
+fn print_history(args: &[String]) {
+    let json = args.iter().any(|a| a == "--json");
+    let limit = match args.iter().position(|a| a == "--limit").map(|i| args.get(i + 1).and_then(|n| n.parse().ok())) {
+        None => 20,
+        Some(Some(n)) => n,
+        Some(None) => {
+            eprintln!("Usage: vitamink history [--json] [--limit N]");
+            std::process::exit(2);
+        }
+    };
+
+    let entries = match history::read() {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            std::process::exit(1);
+        }
+    };
+    let shown = &entries[entries.len().saturating_sub(limit)..];
+    if json {
+        println!("{}", serde_json::to_string_pretty(shown).expect("history serializes"));
+    } else if shown.is_empty() {
+        println!("No transitions recorded yet ({})", history::path().display());
+    } else {
+        print!("{}", history::render(shown));
+    }
+}
+
+fn run_watch() {
+    let config = load_config();
+    if let Err(e) = watch::run(&config) {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
+    }
+}
+
+// `vitamink output power-cycle NAME`
+fn run_output(args: &[String]) {
+    let name = match args {
+        [action, name] if action == "power-cycle" => name,
+        _ => {
+            eprintln!("Usage: vitamink output power-cycle NAME");
+            std::process::exit(2);
+        }
+    };
+
+    let config = load_config();
+    match display::power_cycle(name, config.drm_timeout) {
+        Ok(mode) => println!("{name} is back at {}x{}@{:.2}", mode.width, mode.height, mode.refresh),
+        Err(e) => {
+            eprintln!("Error: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+// `vitamink layout save NAME`, `vitamink layout apply NAME` or
+// `vitamink layout list`.
+fn run_layout(args: &[String]) {
+    let usage = || -> ! {
+        eprintln!("Usage: vitamink layout save|apply NAME, or vitamink layout list");
+        std::process::exit(2);
+    };
+    let result = match args {
+        [action] if action == "list" => layout::presets().map(|names| {
+            if names.is_empty() {
+                println!("No saved layouts in {}", layout::preset_dir().display());
+            }
+            for name in names {
+                println!("{name}");
+            }
+        }),
+        [action, name] if action == "save" => {
+            load_config();
+            layout::Layout::capture().and_then(|current| {
+                let path = layout::save_preset(name, &current)?;
+                let outputs: Vec<String> =
+                    current.outputs.iter().map(|o| if o.enabled { o.name.clone() } else { format!("{} (off)", o.name) }).collect();
+                println!("Saved {} to {}", outputs.join(", "), path.display());
+                Ok(())
+            })
+        }
+        [action, name] if action == "apply" => {
+            let config = load_config();
+            layout::load_preset(name).and_then(|preset| preset.restore(config.primary_display())).map(|()| println!("Applied {name}"))
+        }
+        _ => usage(),
+    };
+    if let Err(e) = result {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
+    }
+}
+
+fn displays_table(options: &listing::Options) -> String {
+    let displays = get_displays_or_exit();
+
+    listing::render(&displays, options, |d| listing::Hardware {
+        model: edid::read(&d.name).map(|e| e.name.unwrap_or(e.manufacturer)),
+        dpms: display::read_dpms(&d.name),
+        drm_active: display::is_drm_active(&d.name),
+        link: drm::connector(&d.name).map(|c| c.status),
+    })
+}
+
+// What the running daemon last wrote; fails if it isn't running.
+fn print_status_json() {
+    match status::read() {
+        Ok(s) if status::is_stale(&s) => {
+            eprintln!("Error: {} is stale (pid {} is gone)", status::path().display(), s.pid);
+            std::process::exit(1);
+        }
+        Ok(s) => println!("{}", serde_json::to_string_pretty(&s).expect("Status serializes")),
+        Err(e) => {
+            eprintln!("Error: {e} — is the daemon running?");
+            std::process::exit(1);
+        }
+    }
+}
+
+// `vitamink statusline [--format waybar|i3bar] [--follow]`
+fn run_statusline(args: &[String]) {
+    let format = match args.iter().position(|a| a == "--format").map(|i| args.get(i + 1)) {
+        None => statusline::Format::Waybar,
+        Some(value) => match value.map_or(Err("--format needs a value".to_string()), |v| v.parse()) {
+            Ok(format) => format,
+            Err(e) => {
+                eprintln!("Error: {e}");
+                std::process::exit(2);
+            }
+        },
+    };
+    // A closed pipe is the bar going away, not an error worth reporting.
+    if let Err(e) = statusline::run(format, args.iter().any(|a| a == "--follow"))
+        && e.kind() != std::io::ErrorKind::BrokenPipe
+    {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
+    }
+}
+
+// `vitamink status [--diff]` (see overview.rs).
+fn print_status(diff: bool) {
+    println!("VitaminK — Sunshine Lifecycle Manager\n");
+    let config = load_config();
+    let color = overview::colors();
+
+    let status = status::read().ok().filter(|s| !status::is_stale(s));
+    println!("{}", overview::daemon(status.as_ref(), status::unix_now(), color));
+
+    print!("{}", displays_table(&listing::Options::default()));
+
+    println!();
+    let units: Vec<(String, bool)> = config.services.iter().map(|s| (s.unit.clone(), services::is_active(s))).collect();
+    print!("{}", overview::services(&units, color));
+
+    if !diff {
+        return;
+    }
+    let persisted = persist::load().unwrap_or_else(|e| {
+        warn!("{e}");
+        None
+    });
+    let Some(state) = status.as_ref().map(|s| s.state).or(persisted.as_ref().map(|p| p.state)) else {
+        println!("\nNo daemon running and no state saved: nothing to compare");
+        return;
+    };
+    let components = overview::compare(state, &config, persisted.as_ref(), &get_displays_or_exit(), &units);
+    println!("\nWhat {state} wants:");
+    print!("{}", overview::diff(&components, color));
+    if !components.iter().all(overview::Component::in_sync) {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,257 @@
+// src/metrics.rs — Prometheus metrics on `[metrics] listen`
+//
+// The daemon counts what it does into a process-wide registry and, when
+// `[metrics] enabled = true`, serves it in the Prometheus text format from
+// a tiny HTTP listener on its own thread:
+//
+//   curl -s http://127.0.0.1:9188/metrics
+//
+// Only `GET /metrics` is answered; there's no keep-alive and no TLS, so
+// keep the listener on loopback and scrape through a local agent.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use log::{debug, info};
+
+use crate::daemon::State;
+
+// Upper bounds, in seconds, of the transition duration histogram. A
+// transition waits for DRM (10s by default) and starts units, so most
+// land between 1 and 10.
+const BUCKETS: [f64; 8] = [0.5, 1.0, 2.0, 5.0, 10.0, 20.0, 30.0, 60.0];
+
+const STATES: [State; 4] = [State::AtDesk, State::Away, State::HoldingPattern, State::Standby];
+
+struct Metrics {
+    // (from, to) → completed transitions.
+    transitions: BTreeMap<(State, State), u64>,
+    // Per bucket in BUCKETS, not cumulative; render() adds them up.
+    durations: [u64; BUCKETS.len()],
+    duration_sum: f64,
+    duration_count: u64,
+    state: Option<State>,
+    sunshine_running: bool,
+    // Seconds in each state, over every run (see accounting.rs).
+    state_seconds: BTreeMap<State, u64>,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            transitions: BTreeMap::new(),
+            durations: [0; BUCKETS.len()],
+            duration_sum: 0.0,
+            duration_count: 0,
+            state: None,
+            sunshine_running: false,
+            state_seconds: BTreeMap::new(),
+        }
+    }
+}
+
+static METRICS: Mutex<Metrics> = Mutex::new(Metrics::new());
+// Bumped from display.rs, which shouldn't have to take the lock.
+static KSCREEN_FAILURES: AtomicU64 = AtomicU64::new(0);
+static COOLDOWN_HOLDS: AtomicU64 = AtomicU64::new(0);
+
+// ---- Recording ----
+
+// A transition that finished without error, and how long applying it took.
+pub fn record_transition(from: State, to: State, took: Duration) {
+    let mut metrics = METRICS.lock().unwrap_or_else(|e| e.into_inner());
+    observe(&mut metrics, from, to, took);
+}
+
+fn observe(metrics: &mut Metrics, from: State, to: State, took: Duration) {
+    *metrics.transitions.entry((from, to)).or_default() += 1;
+    let seconds = took.as_secs_f64();
+    if let Some(bucket) = BUCKETS.iter().position(|&le| seconds <= le) {
+        metrics.durations[bucket] += 1;
+    }
+    metrics.duration_sum += seconds;
+    metrics.duration_count += 1;
+}
+
+pub fn record_kscreen_failure() {
+    KSCREEN_FAILURES.fetch_add(1, Ordering::Relaxed);
+}
+
+// A switch that was due but held back by `cooldown`.
+pub fn record_cooldown_hold() {
+    COOLDOWN_HOLDS.fetch_add(1, Ordering::Relaxed);
+}
+
+// The gauges, refreshed whenever the daemon writes status.json.
+pub fn set_status(state: State, sunshine_running: bool) {
+    let mut metrics = METRICS.lock().unwrap_or_else(|e| e.into_inner());
+    metrics.state = Some(state);
+    metrics.sunshine_running = sunshine_running;
+}
+
+pub fn set_state_seconds(seconds: &BTreeMap<State, u64>) {
+    let mut metrics = METRICS.lock().unwrap_or_else(|e| e.into_inner());
+    metrics.state_seconds.clone_from(seconds);
+}
+
+// ---- Exposition ----
+
+pub fn render() -> String {
+    let metrics = METRICS.lock().unwrap_or_else(|e| e.into_inner());
+    format(&metrics, KSCREEN_FAILURES.load(Ordering::Relaxed), COOLDOWN_HOLDS.load(Ordering::Relaxed))
+}
+
+fn format(metrics: &Metrics, kscreen_failures: u64, cooldown_holds: u64) -> String {
+    let mut out = String::new();
+
+    header(&mut out, "transitions_total", "counter", "Completed state transitions, by direction.");
+    for ((from, to), count) in &metrics.transitions {
+        let _ = writeln!(out, "vitamink_transitions_total{{from=\"{from}\",to=\"{to}\"}} {count}");
+    }
+
+    header(&mut out, "transition_duration_seconds", "histogram", "Time spent applying a completed transition.");
+    let mut cumulative = 0;
+    for (le, count) in BUCKETS.iter().zip(metrics.durations) {
+        cumulative += count;
+        let _ = writeln!(out, "vitamink_transition_duration_seconds_bucket{{le=\"{le}\"}} {cumulative}");
+    }
+    let _ = writeln!(out, "vitamink_transition_duration_seconds_bucket{{le=\"+Inf\"}} {}", metrics.duration_count);
+    let _ = writeln!(out, "vitamink_transition_duration_seconds_sum {}", metrics.duration_sum);
+    let _ = writeln!(out, "vitamink_transition_duration_seconds_count {}", metrics.duration_count);
+
+    header(&mut out, "kscreen_failures_total", "counter", "kscreen-doctor invocations that failed.");
+    let _ = writeln!(out, "vitamink_kscreen_failures_total {kscreen_failures}");
+
+    header(&mut out, "cooldown_holds_total", "counter", "Switches held back because the last one was less than `cooldown` ago.");
+    let _ = writeln!(out, "vitamink_cooldown_holds_total {cooldown_holds}");
+
+    header(&mut out, "current_state", "gauge", "1 for the state the daemon is in, 0 for the others.");
+    for state in STATES {
+        let value = u8::from(metrics.state == Some(state));
+        let _ = writeln!(out, "vitamink_current_state{{state=\"{state}\"}} {value}");
+    }
+
+    header(&mut out, "sunshine_running", "gauge", "Whether the Sunshine unit is active.");
+    let _ = writeln!(out, "vitamink_sunshine_running {}", u8::from(metrics.sunshine_running));
+
+    header(&mut out, "state_seconds_total", "counter", "Time spent in each state, kept across restarts.");
+    for state in STATES {
+        let seconds = metrics.state_seconds.get(&state).copied().unwrap_or_default();
+        let _ = writeln!(out, "vitamink_state_seconds_total{{state=\"{state}\"}} {seconds}");
+    }
+
+    out
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP vitamink_{name} {help}");
+    let _ = writeln!(out, "# TYPE vitamink_{name} {kind}");
+}
+
+// ---- HTTP ----
+
+// Binds `listen` and answers scrapes on a background thread. Binding
+// errors are returned; per-connection errors are only logged.
+pub fn serve(listen: SocketAddr) -> io::Result<()> {
+    let listener = TcpListener::bind(listen)?;
+    thread::Builder::new().name("metrics".into()).spawn(move || {
+        for stream in listener.incoming() {
+            let result = stream.and_then(respond);
+            if let Err(e) = result {
+                debug!("Metrics request failed: {e}");
+            }
+        }
+    })?;
+    info!("Serving metrics on http://{listen}/metrics");
+    Ok(())
+}
+
+fn respond(mut stream: TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut request_line = String::new();
+    BufReader::new(&stream).take(8192).read_line(&mut request_line)?;
+
+    let (status, body) = match route(&request_line) {
+        Route::Metrics => ("200 OK", render()),
+        Route::NotFound => ("404 Not Found", "Not found; try /metrics\n".to_string()),
+        Route::BadMethod => ("405 Method Not Allowed", "Only GET is supported\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+#[derive(Debug, PartialEq)]
+enum Route {
+    Metrics,
+    NotFound,
+    BadMethod,
+}
+
+// "GET /metrics HTTP/1.1" → Metrics. A query string is ignored.
+fn route(request_line: &str) -> Route {
+    let mut parts = request_line.split_whitespace();
+    if parts.next() != Some("GET") {
+        return Route::BadMethod;
+    }
+    let path = parts.next().unwrap_or("");
+    match path.split('?').next() {
+        Some("/metrics") => Route::Metrics,
+        _ => Route::NotFound,
+    }
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let mut metrics = Metrics::new();
+        observe(&mut metrics, State::AtDesk, State::Away, Duration::from_millis(3500));
+        observe(&mut metrics, State::Away, State::AtDesk, Duration::from_millis(800));
+        observe(&mut metrics, State::AtDesk, State::Away, Duration::from_secs(90));
+        metrics.state = Some(State::Away);
+        metrics.sunshine_running = true;
+        metrics.state_seconds = BTreeMap::from([(State::Away, 50_400)]);
+
+        let text = format(&metrics, 2, 1);
+        assert!(text.contains("# TYPE vitamink_transitions_total counter\n"));
+        assert!(text.contains("vitamink_transitions_total{from=\"AtDesk\",to=\"Away\"} 2\n"));
+        assert!(text.contains("vitamink_transitions_total{from=\"Away\",to=\"AtDesk\"} 1\n"));
+        assert!(text.contains("vitamink_transition_duration_seconds_bucket{le=\"0.5\"} 0\n"));
+        assert!(text.contains("vitamink_transition_duration_seconds_bucket{le=\"1\"} 1\n"));
+        assert!(text.contains("vitamink_transition_duration_seconds_bucket{le=\"5\"} 2\n"));
+        assert!(text.contains("vitamink_transition_duration_seconds_bucket{le=\"60\"} 2\n"));
+        assert!(text.contains("vitamink_transition_duration_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(text.contains("vitamink_transition_duration_seconds_sum 94.3\n"));
+        assert!(text.contains("vitamink_transition_duration_seconds_count 3\n"));
+        assert!(text.contains("vitamink_kscreen_failures_total 2\n"));
+        assert!(text.contains("vitamink_cooldown_holds_total 1\n"));
+        assert!(text.contains("vitamink_current_state{state=\"Away\"} 1\n"));
+        assert!(text.contains("vitamink_current_state{state=\"AtDesk\"} 0\n"));
+        assert!(text.contains("vitamink_sunshine_running 1\n"));
+        assert!(text.contains("vitamink_state_seconds_total{state=\"Away\"} 50400\n"));
+        assert!(text.contains("vitamink_state_seconds_total{state=\"AtDesk\"} 0\n"));
+    }
+
+    #[test]
+    fn test_route() {
+        assert_eq!(route("GET /metrics HTTP/1.1\r\n"), Route::Metrics);
+        assert_eq!(route("GET /metrics?x=1 HTTP/1.1\r\n"), Route::Metrics);
+        assert_eq!(route("GET / HTTP/1.1\r\n"), Route::NotFound);
+        assert_eq!(route("POST /metrics HTTP/1.1\r\n"), Route::BadMethod);
+        assert_eq!(route(""), Route::BadMethod);
+    }
+}
@@ -0,0 +1,208 @@
+// src/mock.rs — An in-memory compositor, for running without hardware
+//
+// `MockBackend` keeps a list of outputs and changes it the way a real
+// compositor would: enabling an output gives it the mode asked for,
+// applying a layout moves and scales outputs. Nothing outside the process
+// is touched. `vitamink smoke-test` (see smoke.rs) installs it with
+// `display::use_backend`.
+//
+// `FakeRunner` does the same one level down, for code that runs
+// kscreen-doctor, systemctl and friends through process.rs.
+
+use std::cell::RefCell;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, ExitStatus, Output};
+use std::sync::Mutex;
+
+use crate::display::{Color, CompositorBackend, ConnectionState, Display, DisplayState, Geometry, Mode};
+use crate::error::{Result, VitaminkError};
+use crate::layout::Layout;
+use crate::process::{self, CommandRunner};
+
+pub struct MockBackend {
+    outputs: Mutex<Vec<Display>>,
+    dpms_on: Mutex<bool>,
+}
+
+impl MockBackend {
+    pub fn new(outputs: Vec<Display>) -> Self {
+        Self { outputs: Mutex::new(outputs), dpms_on: Mutex::new(true) }
+    }
+
+    // A 4K monitor on DP-2 and a dummy plug on HDMI-A-1 — what the default
+    // config expects.
+    pub fn desk() -> Self {
+        let mode = |id, width, height, refresh, current| Mode { id, width, height, refresh, preferred: id == 1, current };
+        Self::new(vec![
+            Display {
+                index: 1,
+                name: "DP-2".to_string(),
+                uuid: None,
+                state: DisplayState::Enabled,
+                connection: ConnectionState::Connected,
+                modes: vec![mode(1, 3840, 2160, 60.0, true), mode(2, 2560, 1440, 144.0, false)],
+                geometry: Some(Geometry { x: 0, y: 0, width: 2560, height: 1440 }),
+                scale: Some(1.5),
+                priority: Some(1),
+                color: Color { hdr: Some(true), wide_gamut: Some(true), bit_depth: Some(10) },
+                vrr: None,
+                rotation: None,
+                overscan: None,
+            },
+            Display {
+                index: 2,
+                name: "HDMI-A-1".to_string(),
+                uuid: None,
+                state: DisplayState::Disabled,
+                connection: ConnectionState::Connected,
+                modes: vec![
+                    mode(1, 3840, 2160, 60.0, false),
+                    mode(2, 1920, 1080, 60.0, false),
+                    mode(3, 1920, 1080, 120.0, false),
+                ],
+                geometry: None,
+                scale: None,
+                priority: None,
+                color: Color::default(),
+                vrr: None,
+                rotation: None,
+                overscan: None,
+            },
+        ])
+    }
+
+    pub fn dpms_on(&self) -> bool {
+        *self.dpms_on.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn update(&self, name: &str, f: impl FnOnce(&mut Display)) -> Result<()> {
+        let mut outputs = self.outputs.lock().unwrap_or_else(|e| e.into_inner());
+        let display = outputs
+            .iter_mut()
+            .find(|d| d.name == name)
+            .ok_or_else(|| VitaminkError::Parse(format!("Output {name} not found in mock output")))?;
+        f(display);
+        Ok(())
+    }
+}
+
+impl CompositorBackend for MockBackend {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn get_displays(&self) -> Result<Vec<Display>> {
+        Ok(self.outputs.lock().unwrap_or_else(|e| e.into_inner()).clone())
+    }
+
+    fn enable_output(&self, display: &Display, mode: &Mode, scale: Option<f64>) -> Result<()> {
+        self.update(&display.name, |d| {
+            d.state = DisplayState::Enabled;
+            for m in &mut d.modes {
+                m.current = m.id == mode.id;
+            }
+            let scale = *d.scale.insert(scale.or(d.scale).unwrap_or(1.0));
+            let origin = d.geometry.map_or((0, 0), |g| (g.x, g.y));
+            let (width, height) = ((mode.width as f64 / scale).round() as u32, (mode.height as f64 / scale).round() as u32);
+            d.geometry = Some(Geometry { x: origin.0, y: origin.1, width, height });
+        })
+    }
+
+    fn disable_output(&self, name: &str) -> Result<()> {
+        self.update(name, |d| {
+            d.state = DisplayState::Disabled;
+            for m in &mut d.modes {
+                m.current = false;
+            }
+            // Like KWin: HDR is off when the output comes back.
+            d.color.hdr = d.color.hdr.map(|_| false);
+        })
+    }
+
+    fn apply_layout(&self, layout: &Layout) -> Result<()> {
+        for output in &layout.outputs {
+            self.update(&output.name, |d| {
+                d.state = if output.enabled { DisplayState::Enabled } else { DisplayState::Disabled };
+                for m in &mut d.modes {
+                    m.current = output.enabled && Some(m.id) == output.mode_id;
+                }
+                let size = d.modes.iter().find(|m| m.current).map(|m| (m.width, m.height));
+                d.geometry = match (output.position, size, d.geometry) {
+                    (Some((x, y)), Some((width, height)), _) => Some(Geometry { x, y, width, height }),
+                    (Some((x, y)), None, Some(g)) => Some(Geometry { x, y, ..g }),
+                    (_, _, geometry) => geometry,
+                };
+                d.scale = output.scale.or(d.scale);
+                d.priority = output.priority;
+                d.color = Color {
+                    hdr: output.color.hdr.or(d.color.hdr),
+                    wide_gamut: output.color.wide_gamut.or(d.color.wide_gamut),
+                    bit_depth: output.color.bit_depth.or(d.color.bit_depth),
+                };
+            })?;
+        }
+        Ok(())
+    }
+
+    fn set_primary(&self, name: &str) -> Result<()> {
+        let mut layout = Layout::from_displays(&self.get_displays()?);
+        layout.set_primary(name);
+        self.apply_layout(&layout)
+    }
+
+    fn set_all_dpms(&self, on: bool) -> Result<()> {
+        *self.dpms_on.lock().unwrap_or_else(|e| e.into_inner()) = on;
+        Ok(())
+    }
+}
+
+// ---- Commands ----
+
+// A `CommandRunner` with scripted answers, for tests:
+//
+//   let fake = Rc::new(FakeRunner::new());
+//   fake.respond("systemctl --user is-active", 3, "");
+//   process::with_runner(fake.clone(), || services::is_active(&service));
+//
+// The most recently added response whose prefix matches the command line
+// wins, so a test can change an answer halfway through. A command nothing
+// matches fails as if the program weren't installed.
+pub struct FakeRunner {
+    responses: RefCell<Vec<(String, i32, String)>>,
+    calls: RefCell<Vec<String>>,
+}
+
+impl FakeRunner {
+    pub fn new() -> Self {
+        Self { responses: RefCell::new(Vec::new()), calls: RefCell::new(Vec::new()) }
+    }
+
+    pub fn respond(&self, prefix: &str, code: i32, stdout: &str) {
+        self.responses.borrow_mut().push((prefix.to_string(), code, stdout.to_string()));
+    }
+
+    // Every command line run so far, oldest first.
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.borrow().clone()
+    }
+}
+
+impl Default for FakeRunner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CommandRunner for FakeRunner {
+    fn run(&self, cmd: &mut Command, _input: Option<&[u8]>) -> Result<Output> {
+        let line = process::describe(cmd);
+        self.calls.borrow_mut().push(line.clone());
+
+        let responses = self.responses.borrow();
+        let (_, code, stdout) = responses.iter().rev().find(|(prefix, ..)| line.starts_with(prefix.as_str())).ok_or_else(|| {
+            VitaminkError::CommandNotFound { program: cmd.get_program().to_string_lossy().to_string() }
+        })?;
+        // Exit codes sit in the second byte of a wait status.
+        Ok(Output { status: ExitStatus::from_raw(code << 8), stdout: stdout.clone().into_bytes(), stderr: Vec::new() })
+    }
+}
@@ -0,0 +1,217 @@
+// src/mqtt.rs — state and commands over MQTT, for Home Assistant
+//
+// With `[mqtt] enabled = true` the daemon announces itself to Home
+// Assistant through MQTT discovery (a state sensor and Away/Desk buttons),
+// publishes every state change, and takes commands from a topic — so an
+// automation can put the PC into streaming standby when your phone leaves
+// home:
+//
+//   vitamink/NODE/state          AtDesk | Away | Standby | HoldingPattern (retained)
+//   vitamink/NODE/availability   online | offline (retained)
+//   vitamink/NODE/command        away | desk | cancel
+//
+// Like the Sunshine API going through curl, we don't speak the protocol
+// ourselves: publishing runs `mosquitto_pub`, and a long-lived
+// `mosquitto_sub` feeds the command topic into the daemon's channel. Its
+// last will marks us offline if the daemon dies. Credentials go in
+// ~/.config/mosquitto_pub and ~/.config/mosquitto_sub, which both clients
+// read on their own, rather than on a command line anyone can `ps`.
+
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command as Process, Stdio};
+use std::sync::mpsc::Sender;
+use std::thread;
+
+use log::{info, warn};
+use serde_json::json;
+
+use crate::config::MqttConfig;
+use crate::daemon::{Command, State};
+use crate::environment;
+use crate::error::{Result, VitaminkError};
+use crate::process;
+
+pub struct Client {
+    // `-h HOST -p PORT`, shared by every invocation.
+    broker: Vec<String>,
+    topic: String,
+    subscriber: Child,
+}
+
+impl Client {
+    // Announces discovery configs, subscribes to the command topic and
+    // marks us online. Commands are forwarded to `commands`.
+    pub fn start(config: &MqttConfig, commands: Sender<Command>) -> Result<Self> {
+        let node = config.node_id.clone().unwrap_or_else(hostname);
+        let topic = config.topic.clone().unwrap_or_else(|| format!("vitamink/{node}"));
+        let broker = vec!["-h".to_string(), config.host.clone(), "-p".to_string(), config.port.to_string()];
+
+        for (discovery_topic, payload) in discovery(&config.discovery_prefix, &topic, &node) {
+            publish(&broker, &discovery_topic, &payload)?;
+        }
+
+        let mut subscriber = Process::new("mosquitto_sub");
+        subscriber
+            .args(&broker)
+            .args(["-i", &format!("vitamink-{node}"), "-t", &format!("{topic}/command")])
+            .args(["--will-topic", &format!("{topic}/availability"), "--will-payload", "offline", "--will-retain"]);
+        environment::apply(&mut subscriber);
+        let mut subscriber = subscriber
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| VitaminkError::spawn("mosquitto_sub", e))?;
+        let stdout = subscriber.stdout.take().expect("stdout is piped");
+
+        thread::Builder::new()
+            .name("mqtt".into())
+            .spawn(move || {
+                for line in BufReader::new(stdout).lines().map_while(|line| line.ok()) {
+                    match parse_command(&line) {
+                        Some(command) => {
+                            info!("MQTT: {command:?}");
+                            let _ = commands.send(command);
+                        }
+                        None => warn!("MQTT: ignoring unknown command {line:?}"),
+                    }
+                }
+                warn!("MQTT: command subscription ended");
+            })
+            .map_err(|e| VitaminkError::io("mqtt thread", e))?;
+
+        let client = Self { broker, topic, subscriber };
+        publish(&client.broker, &format!("{}/availability", client.topic), "online")?;
+        info!("MQTT: publishing to {}/state on {}:{}", client.topic, config.host, config.port);
+        Ok(client)
+    }
+
+    pub fn publish_state(&self, state: State) -> Result<()> {
+        publish(&self.broker, &format!("{}/state", self.topic), &state.to_string())
+    }
+}
+
+impl Drop for Client {
+    // A clean exit says so itself; the subscriber's will covers crashes.
+    fn drop(&mut self) {
+        if let Err(e) = publish(&self.broker, &format!("{}/availability", self.topic), "offline") {
+            warn!("MQTT: {e}");
+        }
+        let _ = self.subscriber.kill();
+        let _ = self.subscriber.wait();
+    }
+}
+
+// Retained, so Home Assistant sees the latest value whenever it connects.
+// Runs in dry-run mode too: it changes nothing on this machine.
+fn publish(broker: &[String], topic: &str, payload: &str) -> Result<()> {
+    let output = process::output(Process::new("mosquitto_pub").args(broker).args(["-r", "-t", topic, "-m", payload]))?;
+    if !output.status.success() {
+        return Err(VitaminkError::command_failed("mosquitto_pub", &output));
+    }
+    Ok(())
+}
+
+fn parse_command(payload: &str) -> Option<Command> {
+    match payload.trim().to_ascii_lowercase().as_str() {
+        "away" => Some(Command::ForceAway),
+        "desk" => Some(Command::ForceDesk),
+        "cancel" => Some(Command::CancelPending),
+        _ => None,
+    }
+}
+
+// ---- Home Assistant Discovery ----
+
+// (config topic, payload) for the state sensor and the two buttons, all
+// grouped under one device.
+fn discovery(prefix: &str, topic: &str, node: &str) -> Vec<(String, String)> {
+    let id = format!("vitamink_{node}");
+    let device = json!({ "identifiers": [id], "name": format!("VitaminK ({node})") });
+    let availability = format!("{topic}/availability");
+
+    let sensor = json!({
+        "name": "State",
+        "unique_id": format!("{id}_state"),
+        "state_topic": format!("{topic}/state"),
+        "availability_topic": availability,
+        "icon": "mdi:monitor-shimmer",
+        "device": device,
+    });
+    let button = |action: &str, name: &str, icon: &str| {
+        json!({
+            "name": name,
+            "unique_id": format!("{id}_{action}"),
+            "command_topic": format!("{topic}/command"),
+            "payload_press": action,
+            "availability_topic": availability,
+            "icon": icon,
+            "device": device,
+        })
+    };
+
+    vec![
+        (format!("{prefix}/sensor/{id}/state/config"), sensor.to_string()),
+        (format!("{prefix}/button/{id}/away/config"), button("away", "Go Away", "mdi:television-play").to_string()),
+        (format!("{prefix}/button/{id}/desk/config"), button("desk", "Back to desk", "mdi:desk").to_string()),
+    ]
+}
+
+// Discovery ids only allow [a-zA-Z0-9_-].
+fn hostname() -> String {
+    let name = fs::read_to_string("/proc/sys/kernel/hostname").unwrap_or_default();
+    let name: String = name.trim().chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' }).collect();
+    if name.is_empty() { "pc".to_string() } else { name }
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::mock::FakeRunner;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command("away\n"), Some(Command::ForceAway));
+        assert_eq!(parse_command("Desk"), Some(Command::ForceDesk));
+        assert_eq!(parse_command("cancel"), Some(Command::CancelPending));
+        assert_eq!(parse_command("shutdown"), None);
+    }
+
+    #[test]
+    fn test_discovery() {
+        let configs = discovery("homeassistant", "vitamink/studio", "studio");
+        let topics: Vec<&str> = configs.iter().map(|(topic, _)| topic.as_str()).collect();
+        assert_eq!(topics, [
+            "homeassistant/sensor/vitamink_studio/state/config",
+            "homeassistant/button/vitamink_studio/away/config",
+            "homeassistant/button/vitamink_studio/desk/config",
+        ]);
+
+        let sensor: serde_json::Value = serde_json::from_str(&configs[0].1).unwrap();
+        assert_eq!(sensor["state_topic"], "vitamink/studio/state");
+        assert_eq!(sensor["availability_topic"], "vitamink/studio/availability");
+        assert_eq!(sensor["device"]["identifiers"][0], "vitamink_studio");
+
+        let away: serde_json::Value = serde_json::from_str(&configs[1].1).unwrap();
+        assert_eq!(away["command_topic"], "vitamink/studio/command");
+        assert_eq!(away["payload_press"], "away");
+    }
+
+    #[test]
+    fn test_publish() {
+        let broker = ["-h".to_string(), "nas".to_string(), "-p".to_string(), "1883".to_string()];
+        let fake = Rc::new(FakeRunner::new());
+        fake.respond("mosquitto_pub", 0, "");
+
+        process::with_runner(fake.clone(), || publish(&broker, "vitamink/studio/state", "Away")).unwrap();
+        assert_eq!(fake.calls(), ["mosquitto_pub -h nas -p 1883 -r -t vitamink/studio/state -m Away"]);
+
+        fake.respond("mosquitto_pub", 5, "");
+        assert!(process::with_runner(fake.clone(), || publish(&broker, "t", "x")).is_err());
+    }
+}
@@ -0,0 +1,455 @@
+// src/mutter.rs — Output control on GNOME via Mutter's DisplayConfig D-Bus API
+//
+// This is the interface `gnome-monitor-config` and GNOME Settings use.
+// `GetCurrentState` describes every connected monitor and how they're
+// arranged into "logical monitors"; `ApplyMonitorsConfig` replaces the
+// whole arrangement at once. There's no "just turn this one on" call, so
+// every change is: read the current arrangement, edit it, send it back.
+//
+// New Rust concepts in this file:
+//
+// - Type aliases for tuples: D-Bus structs arrive as plain tuples, e.g.
+//   `(ssss)` is `(String, String, String, String)`. Naming them keeps the
+//   signatures readable.
+//
+// - `OwnedValue`: zbus's "any D-Bus value", used for the `a{sv}` property
+//   maps. We pull out the few keys we need and ignore the rest.
+
+use std::collections::HashMap;
+
+use log::info;
+use zbus::blocking::Connection;
+use zbus::zvariant::{OwnedValue, Value};
+
+use crate::dbus;
+use crate::display::{Color, CompositorBackend, ConnectionState, Display, DisplayState, Geometry, Mode};
+use crate::error::{Result, VitaminkError};
+use crate::layout::Layout;
+use crate::process;
+
+const DESTINATION: &str = "org.gnome.Mutter.DisplayConfig";
+const PATH: &str = "/org/gnome/Mutter/DisplayConfig";
+const INTERFACE: &str = "org.gnome.Mutter.DisplayConfig";
+
+// ApplyMonitorsConfig methods: 1 = temporary (not saved to monitors.xml).
+const METHOD_TEMPORARY: u32 = 1;
+
+pub struct MutterBackend;
+
+impl CompositorBackend for MutterBackend {
+    fn name(&self) -> &'static str {
+        "mutter"
+    }
+
+    fn get_displays(&self) -> Result<Vec<Display>> {
+        Ok(displays(&get_state(&dbus::session()?)?))
+    }
+
+    fn enable_output(&self, display: &Display, mode: &Mode, scale: Option<f64>) -> Result<()> {
+        let name = display.name.as_str();
+        let conn = dbus::session()?;
+        let state = get_state(&conn)?;
+        let mut plan = current_plan(&state);
+        plan.retain(|p| p.connector != name);
+
+        let mode_id = mode_string(&state, name, mode.id)?;
+        let x = plan.iter().map(|p| p.x + p.logical_width(&state)).max().unwrap_or(0);
+        plan.push(Planned { connector: name.to_string(), mode_id, x, y: 0, scale: scale.unwrap_or(1.0), primary: false });
+
+        apply(&conn, &state, plan)
+    }
+
+    fn disable_output(&self, name: &str) -> Result<()> {
+        let conn = dbus::session()?;
+        let state = get_state(&conn)?;
+        let mut plan = current_plan(&state);
+        plan.retain(|p| p.connector != name);
+        apply(&conn, &state, plan)
+    }
+
+    fn apply_layout(&self, layout: &Layout) -> Result<()> {
+        let conn = dbus::session()?;
+        let state = get_state(&conn)?;
+        let plan = layout_plan(&state, layout);
+        apply(&conn, &state, plan)
+    }
+
+    // Mutter has a primary flag rather than priorities; the layout as it
+    // is, with `name` first, sets it.
+    fn set_primary(&self, name: &str) -> Result<()> {
+        let mut layout = Layout::from_displays(&self.get_displays()?);
+        layout.set_primary(name);
+        self.apply_layout(&layout)
+    }
+
+    // Mutter's PowerSaveMode property: 0 = on, 3 = off.
+    fn set_all_dpms(&self, on: bool) -> Result<()> {
+        if process::is_dry_run() {
+            info!("[dry-run] Mutter PowerSaveMode = {}", if on { 0 } else { 3 });
+            return Ok(());
+        }
+        let conn = dbus::session()?;
+        let mode = Value::from(if on { 0i32 } else { 3i32 });
+        dbus::call(
+            &conn,
+            DESTINATION,
+            PATH,
+            "org.freedesktop.DBus.Properties",
+            "Set",
+            &(INTERFACE, "PowerSaveMode", mode),
+        )
+    }
+}
+
+// ---- Current State ----
+
+type Properties = HashMap<String, OwnedValue>;
+type MonitorSpec = (String, String, String, String);
+type RawMode = (String, i32, i32, f64, f64, Vec<f64>, Properties);
+type RawMonitor = (MonitorSpec, Vec<RawMode>, Properties);
+type RawLogicalMonitor = (i32, i32, f64, u32, bool, Vec<MonitorSpec>, Properties);
+type RawState = (u32, Vec<RawMonitor>, Vec<RawLogicalMonitor>, Properties);
+// What ApplyMonitorsConfig takes: `(connector, mode id, properties)` per monitor.
+type NewLogicalMonitor = (i32, i32, f64, u32, bool, Vec<(String, String, Properties)>);
+
+#[derive(Debug)]
+struct State {
+    serial: u32,
+    monitors: Vec<Monitor>,
+    logical_monitors: Vec<LogicalMonitor>,
+    // "layout-mode" 2: logical monitor sizes are in physical pixels.
+    physical_layout: bool,
+}
+
+#[derive(Debug)]
+struct Monitor {
+    connector: String,
+    modes: Vec<MutterMode>,
+}
+
+#[derive(Debug)]
+struct MutterMode {
+    id: String,
+    width: i32,
+    height: i32,
+    refresh: f64,
+    preferred: bool,
+    current: bool,
+}
+
+#[derive(Debug)]
+struct LogicalMonitor {
+    x: i32,
+    y: i32,
+    scale: f64,
+    primary: bool,
+    connectors: Vec<String>,
+}
+
+fn get_state(conn: &Connection) -> Result<State> {
+    let (serial, monitors, logical, props): RawState = dbus::call(conn, DESTINATION, PATH, INTERFACE, "GetCurrentState", &())?;
+
+    let flag = |props: &Properties, key: &str| props.get(key).and_then(|v| bool::try_from(v).ok()).unwrap_or(false);
+
+    Ok(State {
+        serial,
+        monitors: monitors
+            .into_iter()
+            .map(|(spec, modes, _)| Monitor {
+                connector: spec.0,
+                modes: modes
+                    .into_iter()
+                    .map(|(id, width, height, refresh, _, _, props)| MutterMode {
+                        preferred: flag(&props, "is-preferred"),
+                        current: flag(&props, "is-current"),
+                        id,
+                        width,
+                        height,
+                        refresh,
+                    })
+                    .collect(),
+            })
+            .collect(),
+        logical_monitors: logical
+            .into_iter()
+            .map(|(x, y, scale, _, primary, specs, _)| LogicalMonitor {
+                x,
+                y,
+                scale,
+                primary,
+                connectors: specs.into_iter().map(|s| s.0).collect(),
+            })
+            .collect(),
+        physical_layout: props.get("layout-mode").and_then(|v| u32::try_from(v).ok()) == Some(2),
+    })
+}
+
+fn displays(state: &State) -> Vec<Display> {
+    state
+        .monitors
+        .iter()
+        .enumerate()
+        .map(|(i, monitor)| {
+            let logical = state.logical_monitors.iter().find(|l| l.connectors.contains(&monitor.connector));
+            let modes: Vec<Mode> = monitor
+                .modes
+                .iter()
+                .enumerate()
+                .map(|(id, m)| Mode {
+                    id: id as u32,
+                    width: m.width as u32,
+                    height: m.height as u32,
+                    refresh: m.refresh,
+                    preferred: m.preferred,
+                    current: m.current,
+                })
+                .collect();
+
+            let geometry = match (logical, modes.iter().find(|m| m.current)) {
+                (Some(l), Some(mode)) => Some(Geometry {
+                    x: l.x,
+                    y: l.y,
+                    width: (mode.width as f64 / l.scale).round() as u32,
+                    height: (mode.height as f64 / l.scale).round() as u32,
+                }),
+                _ => None,
+            };
+
+            Display {
+                index: i as u32 + 1,
+                name: monitor.connector.clone(),
+                uuid: None,
+                state: if logical.is_some() { DisplayState::Enabled } else { DisplayState::Disabled },
+                // Mutter only lists connected monitors.
+                connection: ConnectionState::Connected,
+                modes,
+                geometry,
+                scale: logical.map(|l| l.scale),
+                priority: logical.map(|l| if l.primary { 1 } else { 2 }),
+                // GetCurrentState doesn't report color modes.
+                color: Color::default(),
+                vrr: None,
+                rotation: None,
+                overscan: None,
+            }
+        })
+        .collect()
+}
+
+// ---- Applying ----
+
+// One logical monitor with a single monitor in it — we never mirror.
+#[derive(Debug, PartialEq)]
+struct Planned {
+    connector: String,
+    mode_id: String,
+    x: i32,
+    y: i32,
+    scale: f64,
+    primary: bool,
+}
+
+impl Planned {
+    // Width in layout coordinates, for placing the next monitor beside it.
+    fn logical_width(&self, state: &State) -> i32 {
+        let width = state
+            .monitors
+            .iter()
+            .filter(|m| m.connector == self.connector)
+            .flat_map(|m| &m.modes)
+            .find(|m| m.id == self.mode_id)
+            .map_or(0, |m| m.width);
+
+        if state.physical_layout { width } else { (width as f64 / self.scale).round() as i32 }
+    }
+}
+
+fn mode_string(state: &State, connector: &str, index: u32) -> Result<String> {
+    state
+        .monitors
+        .iter()
+        .find(|m| m.connector == connector)
+        .and_then(|m| m.modes.get(index as usize))
+        .map(|m| m.id.clone())
+        .ok_or_else(|| VitaminkError::Parse(format!("Mutter has no mode {index} for {connector}")))
+}
+
+fn current_mode(state: &State, connector: &str) -> Option<String> {
+    state
+        .monitors
+        .iter()
+        .find(|m| m.connector == connector)?
+        .modes
+        .iter()
+        .find(|m| m.current)
+        .map(|m| m.id.clone())
+}
+
+// The arrangement that's on screen now.
+fn current_plan(state: &State) -> Vec<Planned> {
+    state
+        .logical_monitors
+        .iter()
+        .filter_map(|l| {
+            let connector = l.connectors.first()?;
+            Some(Planned {
+                connector: connector.clone(),
+                mode_id: current_mode(state, connector)?,
+                x: l.x,
+                y: l.y,
+                scale: l.scale,
+                primary: l.primary,
+            })
+        })
+        .collect()
+}
+
+// The arrangement recorded in `layout`, limited to monitors still connected.
+fn layout_plan(state: &State, layout: &Layout) -> Vec<Planned> {
+    let monitors = displays(state);
+    layout
+        .outputs
+        .iter()
+        .filter(|o| o.enabled)
+        .filter_map(|o| {
+            let saved = monitors.iter().find(|d| d.name == o.name).and_then(|d| o.find_mode(&d.modes));
+            let mode_id = match saved {
+                Some(mode) => mode_string(state, &o.name, mode.id).ok()?,
+                None => current_mode(state, &o.name)?,
+            };
+            let (x, y) = o.position.unwrap_or((0, 0));
+            Some(Planned {
+                connector: o.name.clone(),
+                mode_id,
+                x,
+                y,
+                scale: o.scale.unwrap_or(1.0),
+                primary: o.priority == Some(1),
+            })
+        })
+        .collect()
+}
+
+fn apply(conn: &Connection, state: &State, mut plan: Vec<Planned>) -> Result<()> {
+    if plan.is_empty() {
+        return Err(VitaminkError::Parse("Refusing to disable every monitor".to_string()));
+    }
+    // Mutter rejects configurations without exactly one primary.
+    if !plan.iter().any(|p| p.primary) {
+        plan[0].primary = true;
+    }
+
+    if process::is_dry_run() {
+        info!("[dry-run] Mutter ApplyMonitorsConfig: {plan:?}");
+        return Ok(());
+    }
+
+    let logical_monitors: Vec<NewLogicalMonitor> = plan
+        .into_iter()
+        .map(|p| (p.x, p.y, p.scale, 0, p.primary, vec![(p.connector, p.mode_id, Properties::new())]))
+        .collect();
+
+    dbus::call(
+        conn,
+        DESTINATION,
+        PATH,
+        INTERFACE,
+        "ApplyMonitorsConfig",
+        &(state.serial, METHOD_TEMPORARY, logical_monitors, Properties::new()),
+    )
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::OutputLayout;
+
+    fn mode(id: &str, width: i32, height: i32, current: bool) -> MutterMode {
+        MutterMode { id: id.into(), width, height, refresh: 60.0, preferred: current, current }
+    }
+
+    fn sample() -> State {
+        State {
+            serial: 7,
+            monitors: vec![
+                Monitor {
+                    connector: "DP-1".into(),
+                    modes: vec![mode("3840x2160@60", 3840, 2160, true), mode("1920x1080@60", 1920, 1080, false)],
+                },
+                Monitor { connector: "HDMI-1".into(), modes: vec![mode("1920x1080@60", 1920, 1080, false)] },
+            ],
+            logical_monitors: vec![LogicalMonitor {
+                x: 0,
+                y: 0,
+                scale: 2.0,
+                primary: true,
+                connectors: vec!["DP-1".into()],
+            }],
+            physical_layout: false,
+        }
+    }
+
+    #[test]
+    fn test_displays() {
+        let displays = displays(&sample());
+        assert_eq!(displays[0].state, DisplayState::Enabled);
+        assert_eq!(displays[0].geometry, Some(Geometry { x: 0, y: 0, width: 1920, height: 1080 }));
+        assert_eq!(displays[0].priority, Some(1));
+        assert_eq!(displays[1].state, DisplayState::Disabled);
+        assert_eq!(displays[1].modes[0].id, 0);
+    }
+
+    #[test]
+    fn test_plans() {
+        let state = sample();
+        let current = current_plan(&state);
+        assert_eq!(current.len(), 1);
+        assert_eq!(current[0].mode_id, "3840x2160@60");
+        assert_eq!(current[0].logical_width(&state), 1920);
+
+        let layout = Layout {
+            outputs: vec![
+                OutputLayout {
+                    name: "DP-1".into(),
+                    enabled: true,
+                    mode_id: Some(1),
+                    mode: None,
+                    position: Some((0, 0)),
+                    scale: Some(1.0),
+                    priority: Some(1),
+                    color: Color::default(),
+                    vrr: None,
+                    rotation: None,
+                    overscan: None,
+                },
+                OutputLayout {
+                    name: "HDMI-1".into(),
+                    enabled: false,
+                    mode_id: Some(0),
+                    mode: None,
+                    position: None,
+                    scale: None,
+                    priority: None,
+                    color: Color::default(),
+                    vrr: None,
+                    rotation: None,
+                    overscan: None,
+                },
+            ],
+            checksum: 0,
+        };
+        assert_eq!(
+            layout_plan(&state, &layout),
+            vec![Planned {
+                connector: "DP-1".into(),
+                mode_id: "1920x1080@60".into(),
+                x: 0,
+                y: 0,
+                scale: 1.0,
+                primary: true,
+            }]
+        );
+    }
+}
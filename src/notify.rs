@@ -0,0 +1,184 @@
+// src/notify.rs — Desktop notifications through org.freedesktop.Notifications
+//
+// Plasma, GNOME and most notification daemons implement the same D-Bus
+// interface. Each event category can be switched off and given its own
+// urgency (critical notifications stay on screen until dismissed).
+//
+// Transition notifications replace the previous one instead of stacking,
+// and an error that repeats on every poll is only shown once.
+//
+// While the grace period runs, a "pending" notification counts down to the
+// switch with a Cancel button; clicking it (or the notification itself)
+// calls the handler given to `on_cancel`, which the daemon treats like
+// `vitamink ctl cancel`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use zbus::zvariant::Value;
+
+use crate::config::NotificationsConfig;
+use crate::dbus;
+use crate::error::VitaminkError;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Event {
+    // AtDesk ↔ Away.
+    Transition,
+    // A failed transition or poll.
+    Error,
+    // Entering or leaving HoldingPattern.
+    Holding,
+    // A switch waiting out the grace period, with a Cancel button.
+    Pending,
+    // Back at the desk: a digest of the time Away. Not on by default.
+    Summary,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Urgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+impl Urgency {
+    fn hint(self) -> u8 {
+        match self {
+            Urgency::Low => 0,
+            Urgency::Normal => 1,
+            Urgency::Critical => 2,
+        }
+    }
+}
+
+// The id of the last notification per event, so the next replaces it,
+// and the last error body, so repeats are dropped.
+static LAST: Mutex<(BTreeMap<Event, u32>, Option<String>)> = Mutex::new((BTreeMap::new(), None));
+
+pub fn send(config: &NotificationsConfig, event: Event, summary: &str, body: &str) {
+    send_with(config, event, summary, body, &[], None);
+}
+
+// The countdown to an automatic switch. It expires by itself when the
+// grace period is over.
+pub fn send_pending(config: &NotificationsConfig, summary: &str, body: &str, timeout: Duration) {
+    send_with(config, Event::Pending, summary, body, &["default", "Cancel", "cancel", "Cancel"], Some(timeout));
+}
+
+// Takes down the event's notification, e.g. the countdown once the switch
+// has happened or been called off.
+pub fn close(event: Event) {
+    let Some(id) = LAST.lock().unwrap_or_else(|e| e.into_inner()).0.remove(&event) else {
+        return;
+    };
+    let closed = dbus::session().and_then(|conn| {
+        dbus::call::<()>(
+            &conn,
+            "org.freedesktop.Notifications",
+            "/org/freedesktop/Notifications",
+            "org.freedesktop.Notifications",
+            "CloseNotification",
+            &(id,),
+        )
+    });
+    if let Err(e) = closed {
+        debug!("Couldn't close notification {id}: {e}");
+    }
+}
+
+// Listens for clicks on the pending notification in a thread of its own.
+pub fn on_cancel(handler: impl Fn() + Send + 'static) -> crate::error::Result<()> {
+    let conn = dbus::session()?;
+    let signals = zbus::blocking::Proxy::new(
+        &conn,
+        "org.freedesktop.Notifications",
+        "/org/freedesktop/Notifications",
+        "org.freedesktop.Notifications",
+    )
+    .and_then(|proxy| proxy.receive_signal("ActionInvoked"))
+    .map_err(|e| VitaminkError::dbus("Couldn't subscribe to notification actions", e))?;
+
+    thread::Builder::new()
+        .name("notify-actions".into())
+        .spawn(move || {
+            // Keeps the connection alive as long as we listen.
+            let _conn = conn;
+            for signal in signals {
+                let Ok((id, _action)) = signal.body().deserialize::<(u32, String)>() else {
+                    continue;
+                };
+                let pending = LAST.lock().unwrap_or_else(|e| e.into_inner()).0.get(&Event::Pending).copied();
+                if pending == Some(id) {
+                    handler();
+                }
+            }
+        })
+        .map_err(|e| VitaminkError::io("notify-actions thread", e))?;
+    Ok(())
+}
+
+fn send_with(
+    config: &NotificationsConfig,
+    event: Event,
+    summary: &str,
+    body: &str,
+    actions: &[&str],
+    timeout: Option<Duration>,
+) {
+    if !config.enabled || !config.events.contains(&event) {
+        return;
+    }
+
+    let mut last = LAST.lock().unwrap_or_else(|e| e.into_inner());
+    if event == Event::Error {
+        if last.1.as_deref() == Some(body) {
+            return;
+        }
+        last.1 = Some(body.to_string());
+    }
+
+    let replaces = last.0.get(&event).copied().unwrap_or(0);
+    let urgency = match event {
+        Event::Error => config.error_urgency,
+        Event::Transition | Event::Holding | Event::Pending | Event::Summary => config.urgency,
+    };
+    // -1 = the server's default.
+    let timeout = timeout.map_or(-1, |t| i32::try_from(t.as_millis()).unwrap_or(i32::MAX));
+
+    match notify(replaces, summary, body, urgency, actions, timeout) {
+        Ok(id) => {
+            last.0.insert(event, id);
+        }
+        Err(e) => warn!("Notification failed: {e}"),
+    }
+}
+
+// A successful transition means earlier errors are resolved; the next one
+// should be shown even if it's identical.
+pub fn clear_error() {
+    LAST.lock().unwrap_or_else(|e| e.into_inner()).1 = None;
+}
+
+// `actions` alternates keys and labels; "default" is a click on the
+// notification itself.
+fn notify(replaces: u32, summary: &str, body: &str, urgency: Urgency, actions: &[&str], timeout: i32) -> crate::error::Result<u32> {
+    let conn = dbus::session()?;
+    let hints = HashMap::from([("urgency", Value::from(urgency.hint()))]);
+
+    dbus::call(
+        &conn,
+        "org.freedesktop.Notifications",
+        "/org/freedesktop/Notifications",
+        "org.freedesktop.Notifications",
+        "Notify",
+        // app_name, replaces_id, app_icon, summary, body, actions, hints, expire_timeout (ms)
+        &("VitaminK", replaces, "video-display", summary, body, actions, hints, timeout),
+    )
+}
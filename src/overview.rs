@@ -0,0 +1,214 @@
+// src/overview.rs — `vitamink status`: the daemon and the desk at a glance
+//
+//   Daemon:     Away (pid 4242)
+//   Profile:    tv
+//   Pending:    AtDesk in 7s
+//   Time spent: Away 14h2m, AtDesk 9h58m
+//
+//   (the `vitamink displays` table)
+//
+//   SERVICE   STATE
+//   sunshine  running
+//
+// With `--diff`, what the daemon's state calls for is held up against
+// what's so, one row per output and service it touches:
+//
+//     COMPONENT        WANTED   ACTUAL
+//   ✓ output DP-2      on       on
+//   ✗ output HDMI-A-1  on       off
+//   ✓ sunshine         running  running
+//
+// and the command exits nonzero if any row differs. Without a daemon
+// running, the state is the one state.json says the desk was left in.
+// Colors only go to a terminal, and not with NO_COLOR set.
+
+use std::env;
+use std::io::{self, IsTerminal};
+
+use crate::accounting;
+use crate::config::Config;
+use crate::daemon::State;
+use crate::display::{Display, DisplayState, OffStrategy};
+use crate::persist::Persisted;
+use crate::status::Status;
+use crate::table::Table;
+
+const GREEN: &str = "32";
+const YELLOW: &str = "33";
+const RED: &str = "31";
+const CYAN: &str = "36";
+const DIM: &str = "2";
+
+// Whether to color what goes to stdout.
+pub fn colors() -> bool {
+    io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+}
+
+fn paint(color: bool, code: &str, text: &str) -> String {
+    if color { format!("\x1b[{code}m{text}\x1b[0m") } else { text.to_string() }
+}
+
+fn state_code(state: State) -> &'static str {
+    match state {
+        State::AtDesk => GREEN,
+        State::Away => CYAN,
+        State::Standby => YELLOW,
+        State::HoldingPattern => RED,
+    }
+}
+
+// The lines about the daemon; `status` is None if it isn't running.
+pub fn daemon(status: Option<&Status>, now: u64, color: bool) -> String {
+    let Some(s) = status else {
+        return format!("{:<12}{}\n", "Daemon:", paint(color, DIM, "not running"));
+    };
+    let mut lines = vec![("Daemon:", format!("{} (pid {})", paint(color, state_code(s.state), &s.state.to_string()), s.pid))];
+    if let Some(profile) = &s.profile {
+        lines.push(("Profile:", profile.clone()));
+    }
+    if let Some(pending) = &s.pending {
+        let held = match &pending.held_by_load {
+            _ if pending.held_by_stream => ", held by a stream".to_string(),
+            Some(reason) => format!(", held: {reason}"),
+            None => String::new(),
+        };
+        let left = pending.due.saturating_sub(now);
+        lines.push(("Pending:", paint(color, YELLOW, &format!("{} in {left}s{held}", pending.to))));
+    }
+    if let Some(progress) = &s.progress {
+        lines.push(("Switching:", format!("to {}: {}…", progress.to, progress.describe(now))));
+    }
+    for held in &s.inhibitors {
+        lines.push(("Inhibited:", paint(color, YELLOW, &format!("{} ({})", held.name, held.reason))));
+    }
+    if let Some(error) = &s.last_error {
+        lines.push(("Last error:", paint(color, RED, error)));
+    }
+    if !s.time_in_state.is_empty() {
+        lines.push(("Time spent:", accounting::summary(&s.time_in_state)));
+    }
+    lines.into_iter().map(|(key, value)| format!("{key:<12}{value}\n")).collect()
+}
+
+pub fn services(services: &[(String, bool)], color: bool) -> String {
+    let mut table = Table::new(&["SERVICE", "STATE"]);
+    for (unit, active) in services {
+        let state = if *active { paint(color, GREEN, "running") } else { paint(color, DIM, "stopped") };
+        table.add_row(vec![unit.clone(), state]);
+    }
+    table.render()
+}
+
+// ---- Diff ----
+
+// One output or service, as `state` wants it and as it is.
+#[derive(Debug, PartialEq)]
+pub struct Component {
+    pub name: String,
+    pub wanted: &'static str,
+    pub actual: &'static str,
+}
+
+impl Component {
+    pub fn in_sync(&self) -> bool {
+        self.wanted == self.actual
+    }
+}
+
+// What `state` turns on and off, next to `displays` and `services` (unit,
+// running). `persisted` says which dummy plug or profile Away used.
+pub fn compare(state: State, config: &Config, persisted: Option<&Persisted>, displays: &[Display], services: &[(String, bool)]) -> Vec<Component> {
+    let profile = persisted.and_then(|p| p.profile.as_ref()).or(config.profile.as_ref()).and_then(|name| config.profiles.get(name));
+    let away: Vec<String> = match profile {
+        Some(profile) => profile.enable.iter().map(|o| o.name.clone()).collect(),
+        None => vec![persisted.and_then(|p| p.dummy_plug.clone()).unwrap_or_else(|| config.dummy_plug.clone())],
+    };
+    let disable = profile.map(|p| p.disable.clone()).unwrap_or_default();
+    // With "dpms-off", leaving Away keeps them enabled.
+    let away_off = if config.away_outputs_off == OffStrategy::Disable { away.clone() } else { Vec::new() };
+
+    let (on, off, running): (Vec<String>, Vec<String>, Option<bool>) = match state {
+        State::Away => (away, disable, Some(true)),
+        // Standby stops the services and the Away outputs; the rest stays.
+        State::Standby => (Vec::new(), away_off, Some(false)),
+        State::AtDesk => {
+            let off = away_off.into_iter().filter(|name| !config.main_display.contains(name)).collect();
+            (config.main_display.clone(), off, Some(false))
+        }
+        State::HoldingPattern => (Vec::new(), Vec::new(), None),
+    };
+
+    let output = |name: &String, wanted| {
+        let actual = match displays.iter().find(|d| &d.name == name) {
+            Some(d) if d.state == DisplayState::Enabled => "on",
+            Some(_) => "off",
+            None => "missing",
+        };
+        Component { name: format!("output {name}"), wanted, actual }
+    };
+    let mut components: Vec<Component> = on.iter().map(|name| output(name, "on")).chain(off.iter().map(|name| output(name, "off"))).collect();
+    if let Some(running) = running {
+        let word = |active| if active { "running" } else { "stopped" };
+        components.extend(services.iter().map(|(unit, active)| Component { name: unit.clone(), wanted: word(running), actual: word(*active) }));
+    }
+    components
+}
+
+pub fn diff(components: &[Component], color: bool) -> String {
+    let mut table = Table::new(&["  COMPONENT", "WANTED", "ACTUAL"]);
+    for c in components {
+        let (mark, actual) = if c.in_sync() { (paint(color, GREEN, "✓"), c.actual.to_string()) } else { (paint(color, RED, "✗"), paint(color, RED, c.actual)) };
+        table.add_row(vec![format!("{mark} {}", c.name), c.wanted.to_string(), actual]);
+    }
+    table.render()
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+    use crate::display::{Color, ConnectionState};
+
+    fn output(name: &str, enabled: bool) -> Display {
+        Display {
+            index: 0,
+            name: name.into(),
+            uuid: None,
+            state: if enabled { DisplayState::Enabled } else { DisplayState::Disabled },
+            connection: ConnectionState::Connected,
+            modes: Vec::new(),
+            geometry: None,
+            scale: None,
+            priority: None,
+            color: Color::default(),
+            vrr: None,
+            rotation: None,
+            overscan: None,
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let config = config::parse("main_display = \"DP-2\"\ndummy_plug = \"HDMI-A-1\"").unwrap();
+        let displays = [output("DP-2", true), output("HDMI-A-1", false)];
+        let services = [("sunshine".to_string(), true)];
+
+        // Away, but the dummy plug was turned off by hand.
+        let away = compare(State::Away, &config, None, &displays, &services);
+        assert_eq!(away.len(), 2);
+        assert_eq!(away[0], Component { name: "output HDMI-A-1".into(), wanted: "on", actual: "off" });
+        assert!(away[1].in_sync());
+
+        // At the desk, only Sunshine is left over.
+        let desk = compare(State::AtDesk, &config, None, &displays, &services);
+        let out: Vec<&str> = desk.iter().filter(|c| !c.in_sync()).map(|c| c.name.as_str()).collect();
+        assert_eq!(out, ["sunshine"]);
+        assert_eq!(desk.len(), 3);
+
+        let table = diff(&away, false);
+        assert_eq!(table, "  COMPONENT        WANTED   ACTUAL\n✗ output HDMI-A-1  on       off\n✓ sunshine         running  running\n");
+        assert!(compare(State::HoldingPattern, &config, None, &displays, &services).is_empty());
+    }
+}
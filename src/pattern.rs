@@ -0,0 +1,190 @@
+// src/pattern.rs — Output names that say which connector, not its name
+//
+// Connector names move: a dummy plug behind a dock can be DP-3 on one
+// boot and DP-4 after the dock reconnects. Anywhere the config names an
+// output (`main_display`, `dummy_plug`, `[profiles]`) it can instead give
+//
+//   - a glob over connector names: "DP-*", "HDMI-A-?", "DP-[34]"
+//   - `edid:PATTERN`, matched against the monitor's EDID (see edid.rs)
+//
+// At startup, on a reload, and whenever the outputs settle after a dock
+// or hotplug event, each is swapped for the one connected output it
+// matches. A pattern that matches several is an error that lists them,
+// rather than a guess; one that matches nothing is left as it is (so it
+// matches nothing) with a warning, since the monitor may just be off.
+// A `dummy_plug` glob never matches a main display, and is tried again
+// when going Away if it didn't match at the last resolve.
+
+use log::{debug, warn};
+
+use crate::config::Config;
+use crate::drm;
+use crate::edid;
+use crate::error::{Result, VitaminkError};
+
+// Whether `name` is a glob rather than a connector name.
+pub fn is_glob(name: &str) -> bool {
+    name.contains(['*', '?', '['])
+}
+
+// `*` is any run of characters, `?` any one, `[abc]`/`[0-9]` one of a
+// set (`[!...]` one outside it). Case-sensitive, like connector names.
+pub fn glob(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    matches_from(&pattern, &name)
+}
+
+fn matches_from(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|skip| matches_from(&pattern[1..], &name[skip..])),
+        Some('?') => !name.is_empty() && matches_from(&pattern[1..], &name[1..]),
+        Some('[') => {
+            let Some(end) = pattern.iter().skip(2).position(|&c| c == ']').map(|i| i + 2) else {
+                // No closing bracket: a literal '['.
+                return name.first() == Some(&'[') && matches_from(&pattern[1..], &name[1..]);
+            };
+            let Some(&c) = name.first() else { return false };
+            in_set(&pattern[1..end], c) && matches_from(&pattern[end + 1..], &name[1..])
+        }
+        Some(&literal) => name.first() == Some(&literal) && matches_from(&pattern[1..], &name[1..]),
+    }
+}
+
+// The inside of a `[...]`.
+fn in_set(set: &[char], c: char) -> bool {
+    let (negated, set) = match set.split_first() {
+        Some(('!' | '^', rest)) => (true, rest),
+        _ => (false, set),
+    };
+    let mut found = false;
+    let mut i = 0;
+    while i < set.len() {
+        if i + 2 < set.len() && set[i + 1] == '-' {
+            found |= (set[i]..=set[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            found |= set[i] == c;
+            i += 1;
+        }
+    }
+    found != negated
+}
+
+// The one name in `names` that `pattern` matches, for `setting`.
+pub fn pick(setting: &str, pattern: &str, names: &[String]) -> Result<String> {
+    let matching: Vec<&String> = names.iter().filter(|name| glob(pattern, name)).collect();
+    match matching[..] {
+        [name] => Ok(name.clone()),
+        [] => Err(VitaminkError::Parse(format!(
+            "{setting} \"{pattern}\" matches none of the connected outputs ({})",
+            if names.is_empty() { "there are none".to_string() } else { names.join(", ") }
+        ))),
+        _ => {
+            let found: Vec<&str> = matching.iter().map(|name| name.as_str()).collect();
+            Err(VitaminkError::Parse(format!(
+                "{setting} \"{pattern}\" matches several outputs: {} — narrow it down, or use edid:",
+                found.join(", ")
+            )))
+        }
+    }
+}
+
+// ---- Resolving the Config ----
+
+// A pattern in the config, and what it came to.
+pub struct Resolved {
+    pub setting: String,
+    pub pattern: String,
+    pub connector: Result<String>,
+}
+
+// Replaces every pattern among the config's output names with the
+// connector it matches right now, as the kernel names them. Returns what
+// each pattern came to, for `vitamink config check`.
+pub fn resolve_config(config: &mut Config) -> Vec<Resolved> {
+    let connected: Vec<String> = drm::connected(&drm::scan()).into_iter().collect();
+    let mut resolved = Vec::new();
+
+    for name in &mut config.main_display {
+        resolved.extend(resolve("main_display", name, &connected));
+    }
+    // Whatever the main displays turned out to be can't be the plug too.
+    let others: Vec<String> = connected.iter().filter(|name| !config.main_display.contains(name)).cloned().collect();
+    resolved.extend(resolve("dummy_plug", &mut config.dummy_plug, &others));
+    if let Some(name) = &mut config.away_primary {
+        resolved.extend(resolve("away_primary", name, &connected));
+    }
+
+    for (profile_name, profile) in &mut config.profiles {
+        let setting = format!("[profiles.{profile_name}]");
+        for output in &mut profile.enable {
+            resolved.extend(resolve(&setting, &mut output.name, &connected));
+        }
+        for name in &mut profile.disable {
+            resolved.extend(resolve(&setting, name, &connected));
+        }
+    }
+    resolved
+}
+
+fn resolve(setting: &str, name: &mut String, connected: &[String]) -> Option<Resolved> {
+    let connector = if let Some(pattern) = name.strip_prefix(edid::PREFIX) {
+        edid::find(pattern)
+    } else if is_glob(name) {
+        pick(setting, name, connected)
+    } else {
+        return None;
+    };
+    let pattern = name.clone();
+    match &connector {
+        Ok(connector) => {
+            debug!("{name} is {connector}");
+            *name = connector.clone();
+        }
+        Err(e) => warn!("Can't resolve {name}: {e}"),
+    }
+    Some(Resolved { setting: setting.to_string(), pattern, connector })
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob() {
+        assert!(glob("DP-*", "DP-3"));
+        assert!(glob("DP-*", "DP-"));
+        assert!(!glob("DP-*", "eDP-1"));
+        assert!(glob("*DP-1", "eDP-1"));
+        assert!(glob("HDMI-A-?", "HDMI-A-2"));
+        assert!(!glob("HDMI-A-?", "HDMI-A-10"));
+        assert!(glob("DP-[34]", "DP-4"));
+        assert!(!glob("DP-[34]", "DP-5"));
+        assert!(glob("DP-[2-4]", "DP-3"));
+        assert!(glob("DP-[!1]", "DP-2"));
+        assert!(!glob("DP-[!1]", "DP-1"));
+        assert!(glob("DP-2", "DP-2"));
+        assert!(!glob("dp-2", "DP-2"));
+        // An unclosed bracket is just a bracket.
+        assert!(glob("DP-[", "DP-["));
+
+        assert!(is_glob("DP-*"));
+        assert!(!is_glob("HDMI-A-1"));
+        assert!(!is_glob("edid:U2720Q"));
+    }
+
+    #[test]
+    fn test_pick() {
+        let names: Vec<String> = ["DP-2", "DP-4", "HDMI-A-1"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(pick("dummy_plug", "HDMI-*", &names).unwrap(), "HDMI-A-1");
+
+        let e = pick("dummy_plug", "DP-*", &names).unwrap_err().to_string();
+        assert!(e.contains("dummy_plug \"DP-*\" matches several outputs: DP-2, DP-4"), "{e}");
+        let e = pick("main_display", "eDP-*", &names).unwrap_err().to_string();
+        assert!(e.contains("matches none of the connected outputs (DP-2, DP-4, HDMI-A-1)"), "{e}");
+    }
+}
@@ -0,0 +1,264 @@
+// src/persist.rs — Remembering what Away changed, across restarts
+//
+// If the daemon dies while Away (crash, OOM kill, logout), the next start
+// used to assume a clean AtDesk: the dummy plug stayed on, the layout was
+// never put back, the mic stayed muted. Now every change to the Away
+// bookkeeping is written to `$XDG_STATE_HOME/vitamink/state.json`, and
+// startup loads it so the first apply_state can undo what's left over.
+//
+// A crash or power cut mid-write must not cost us that bookkeeping, so
+// files are written to a temporary name, synced, and renamed into place.
+// Each carries a checksum of its contents; a file that fails it (or
+// doesn't parse) is moved aside as `NAME.corrupt-<time>` for inspection,
+// and the daemon starts from defaults instead of refusing to run.
+//
+// New Rust concepts in this file:
+//
+// - `#[derive(Serialize, Deserialize)]` on structs from other modules:
+//   the Restore types just need the derive; serde handles the nesting.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use log::warn;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::audio;
+use crate::compositor;
+use crate::config::CustomMode;
+use crate::daemon::State;
+use crate::desktop;
+use crate::error::{Result, VitaminkError};
+use crate::layout::Layout;
+use crate::power;
+use crate::privacy;
+use crate::sunshine;
+use crate::windows;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Persisted {
+    // The state the hardware was last successfully put into.
+    pub state: State,
+    pub dummy_plug: Option<String>,
+    // Missing from files written before profiles existed.
+    #[serde(default)]
+    pub profile: Option<String>,
+    pub saved_layout: Option<Layout>,
+    pub privacy: Option<privacy::Restore>,
+    pub desktop: Option<desktop::Restore>,
+    pub compositor: Option<compositor::Restore>,
+    // Missing from files written before audio switching existed.
+    #[serde(default)]
+    pub audio: Option<audio::Restore>,
+    // The gamescope session's PID. Missing from files written before
+    // gamescope sessions existed.
+    #[serde(default)]
+    pub gamescope: Option<u32>,
+    // Missing from files written before sunshine.conf templating existed.
+    #[serde(default)]
+    pub sunshine_conf: Option<sunshine::conf::Restore>,
+    // Missing from files written before power profiles existed.
+    #[serde(default)]
+    pub power: Option<power::Restore>,
+    // Missing from files written before [apps] existed.
+    #[serde(default)]
+    pub app: Option<sunshine::apps::Restore>,
+    // Missing from files written before [windows] existed.
+    #[serde(default)]
+    pub windows: Option<Vec<windows::Placement>>,
+    // Missing from files written before [[custom_modes]] existed.
+    #[serde(default)]
+    pub custom_modes: Vec<CustomMode>,
+}
+
+// What a first run starts from: AtDesk, with nothing left engaged.
+impl Default for Persisted {
+    fn default() -> Self {
+        Self {
+            state: State::AtDesk,
+            dummy_plug: None,
+            profile: None,
+            saved_layout: None,
+            privacy: None,
+            desktop: None,
+            compositor: None,
+            audio: None,
+            gamescope: None,
+            sunshine_conf: None,
+            power: None,
+            app: None,
+            windows: None,
+            custom_modes: Vec::new(),
+        }
+    }
+}
+
+// `$XDG_STATE_HOME/vitamink/state.json`, falling back to `~/.local/state`.
+pub fn path() -> PathBuf {
+    let base = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .unwrap_or_else(|| PathBuf::from("."));
+
+    base.join("vitamink").join("state.json")
+}
+
+// None if there's no state file yet, or it was corrupt.
+pub fn load() -> Result<Option<Persisted>> {
+    read_checked(&path())
+}
+
+pub fn save(persisted: &Persisted) -> Result<()> {
+    write_checked(&path(), persisted)
+}
+
+// ---- Checked Files ----
+
+// What's on disk: the data plus a checksum of its compact JSON form.
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    checksum: String,
+    data: serde_json::Value,
+}
+
+pub fn write_checked<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let json = encode(value).map_err(|e| VitaminkError::Parse(format!("{}: {e}", path.display())))?;
+    write_atomic(path, json.as_bytes())
+}
+
+// None if the file doesn't exist. A corrupt file is quarantined and also
+// reads as None, so callers fall back to their defaults.
+pub fn read_checked<T: DeserializeOwned>(path: &Path) -> Result<Option<T>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(VitaminkError::io(path, e)),
+    };
+
+    match decode(&text) {
+        Ok(value) => Ok(Some(value)),
+        Err(problem) => {
+            let moved = quarantine(path)?;
+            warn!("{} is corrupt ({problem}), moved it to {} and starting fresh", path.display(), moved.display());
+            Ok(None)
+        }
+    }
+}
+
+// Readers see either the old file or the new one, never half of either.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(dir).map_err(|e| VitaminkError::io(dir, e))?;
+
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = dir.join(tmp_name);
+
+    let mut file = File::create(&tmp).map_err(|e| VitaminkError::io(&tmp, e))?;
+    file.write_all(contents).and_then(|()| file.sync_all()).map_err(|e| VitaminkError::io(&tmp, e))?;
+    fs::rename(&tmp, path).map_err(|e| VitaminkError::io(path, e))?;
+
+    // The rename itself lives in the directory; sync that too.
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+fn quarantine(path: &Path) -> Result<PathBuf> {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".corrupt-{}", crate::status::unix_now()));
+    let moved = path.with_file_name(name);
+    fs::rename(path, &moved).map_err(|e| VitaminkError::io(path, e))?;
+    Ok(moved)
+}
+
+fn encode<T: Serialize>(value: &T) -> std::result::Result<String, String> {
+    let data = serde_json::to_value(value).map_err(|e| e.to_string())?;
+    let compact = serde_json::to_string(&data).map_err(|e| e.to_string())?;
+    let envelope = Envelope { checksum: format!("{:016x}", fnv1a(compact.as_bytes())), data };
+    serde_json::to_string_pretty(&envelope).map_err(|e| e.to_string())
+}
+
+// Files written before checksums existed are the bare data; they're
+// accepted as-is and get an envelope on the next write.
+fn decode<T: DeserializeOwned>(text: &str) -> std::result::Result<T, String> {
+    let data = match serde_json::from_str::<Envelope>(text) {
+        Ok(envelope) => {
+            let compact = serde_json::to_string(&envelope.data).map_err(|e| e.to_string())?;
+            let actual = format!("{:016x}", fnv1a(compact.as_bytes()));
+            if actual != envelope.checksum {
+                return Err(format!("checksum {actual} doesn't match {}", envelope.checksum));
+            }
+            envelope.data
+        }
+        Err(_) => serde_json::from_str(text).map_err(|e| e.to_string())?,
+    };
+    serde_json::from_value(data).map_err(|e| e.to_string())
+}
+
+// FNV-1a: tiny, and unlike `DefaultHasher` guaranteed not to change
+// between Rust versions.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| (hash ^ u64::from(b)).wrapping_mul(0x100000001b3))
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::Color;
+    use crate::layout::OutputLayout;
+
+    #[test]
+    fn test_round_trip() {
+        let persisted = Persisted {
+            state: State::Away,
+            dummy_plug: Some("HDMI-A-1".into()),
+            profile: Some("tv".into()),
+            saved_layout: Some(Layout {
+                outputs: vec![OutputLayout {
+                    name: "DP-2".into(),
+                    enabled: true,
+                    mode_id: Some(3),
+                    mode: None,
+                    position: Some((0, 0)),
+                    scale: Some(1.25),
+                    priority: Some(1),
+                    color: Color::default(),
+                    vrr: None,
+                    rotation: None,
+                    overscan: None,
+                }],
+                checksum: 0,
+            }),
+            ..Persisted::default()
+        };
+
+        let json = serde_json::to_string(&persisted).unwrap();
+        let back: Persisted = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.state, State::Away);
+        assert_eq!(back.dummy_plug.as_deref(), Some("HDMI-A-1"));
+        assert_eq!(back.profile.as_deref(), Some("tv"));
+        assert_eq!(back.saved_layout, persisted.saved_layout);
+        assert!(serde_json::from_str::<Persisted>("{}").is_err());
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let text = encode(&(State::Away, "HDMI-A-1")).unwrap();
+        assert_eq!(decode::<(State, String)>(&text).unwrap(), (State::Away, "HDMI-A-1".to_string()));
+
+        // A flipped byte in the data fails the checksum.
+        let tampered = text.replace("HDMI-A-1", "HDMI-A-2");
+        assert!(decode::<(State, String)>(&tampered).unwrap_err().contains("checksum"));
+
+        // Pre-checksum files are still read.
+        assert_eq!(decode::<Vec<u32>>("[1, 2]").unwrap(), vec![1, 2]);
+        assert!(decode::<Vec<u32>>("[1, 2").is_err());
+    }
+}
@@ -0,0 +1,231 @@
+// src/pipeline.rs — What a transition does, and in which order
+//
+// Going Away runs its steps in `AWAY_STEPS` order: the desk layout saved
+// before the dummy plug changes anything, Sunshine started once there's
+// an output for it, and so on. Coming back undoes them in reverse. With
+// `[transition]` the order is yours, and commands and pauses can go in
+// between:
+//
+//   [transition]
+//   away = ["privacy", "layout", "outputs", "wait 2s", "services",
+//           "run ~/bin/lights dim", "gamescope", "app", "desktop",
+//           "compositor", "power", "audio", "input"]
+//   desk = ["input", "audio", "power", "compositor", "desktop", "app",
+//           "gamescope", "services", "outputs", "layout", "privacy"]
+//   timeout = "30s"
+//
+// A list names every step once, and some have to come after others:
+// nothing changes the outputs before the layout is saved, Sunshine,
+// gamescope and the app need the Away outputs, and the app is looked up
+// in Sunshine. `desk` is held to the same, the other way round. Left out,
+// `desk` is `away`'s steps backwards, without its commands and pauses.
+//
+// `run COMMAND` goes through `sh -c` with the hooks' VITAMINK_*
+// variables (VITAMINK_HOOK is "transition"). Unlike a hook, it's part of
+// the transition: failing, or still running after `timeout`, fails it
+// like a step would, and Away is rolled back. `wait DURATION` just waits.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::duration;
+
+// What going Away sets up (see `apply_steps` in daemon.rs).
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Step {
+    Privacy,
+    Layout,
+    Outputs,
+    Desktop,
+    Compositor,
+    Power,
+    Services,
+    Gamescope,
+    App,
+    Audio,
+    Input,
+}
+
+pub const AWAY_STEPS: [Step; 11] = [
+    Step::Privacy,
+    Step::Layout,
+    Step::Outputs,
+    Step::Desktop,
+    Step::Compositor,
+    Step::Power,
+    Step::Services,
+    Step::Gamescope,
+    Step::App,
+    Step::Audio,
+    Step::Input,
+];
+
+// What Standby stops, in order.
+pub const STANDBY_STEPS: [Step; 4] = [Step::App, Step::Gamescope, Step::Services, Step::Outputs];
+
+// (step, what has to be engaged before it, why).
+const DEPENDS: &[(Step, Step, &str)] = &[
+    (Step::Outputs, Step::Layout, "the desk layout has to be saved before the outputs change"),
+    (Step::Services, Step::Outputs, "Sunshine needs the Away outputs"),
+    (Step::Services, Step::Privacy, "the session has to be locked before Sunshine starts"),
+    (Step::Gamescope, Step::Outputs, "gamescope runs at the Away outputs' mode"),
+    (Step::App, Step::Outputs, "the app needs the Away outputs"),
+    (Step::App, Step::Services, "the app is looked up in Sunshine"),
+];
+
+impl Step {
+    // As `[transition]` names it.
+    pub fn name(self) -> &'static str {
+        match self {
+            Step::Privacy => "privacy",
+            Step::Layout => "layout",
+            Step::Outputs => "outputs",
+            Step::Desktop => "desktop",
+            Step::Compositor => "compositor",
+            Step::Power => "power",
+            Step::Services => "services",
+            Step::Gamescope => "gamescope",
+            Step::App => "app",
+            Step::Audio => "audio",
+            Step::Input => "input",
+        }
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Step::Privacy => write!(f, "apply mic/webcam privacy"),
+            Step::Layout => write!(f, "save the display layout"),
+            Step::Outputs => write!(f, "enable the Away outputs"),
+            Step::Desktop => write!(f, "switch wallpaper/activity"),
+            Step::Compositor => write!(f, "reduce compositor effects"),
+            Step::Power => write!(f, "switch the power profile"),
+            Step::Services => write!(f, "start services"),
+            Step::Gamescope => write!(f, "launch gamescope"),
+            Step::App => write!(f, "launch the Sunshine app"),
+            Step::Audio => write!(f, "switch the audio sink"),
+            Step::Input => write!(f, "grab local input"),
+        }
+    }
+}
+
+// One entry of a `[transition]` list.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Stage {
+    Step(Step),
+    Wait(Duration),
+    Run(String),
+}
+
+impl FromStr for Stage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(command) = s.strip_prefix("run ") {
+            return Ok(Stage::Run(command.trim().to_string()));
+        }
+        if let Some(wait) = s.strip_prefix("wait ") {
+            return duration::parse(wait.trim()).map(Stage::Wait).map_err(|e| format!("\"{s}\": {e}"));
+        }
+        AWAY_STEPS.into_iter().find(|step| step.name() == s).map(Stage::Step).ok_or_else(|| {
+            let names: Vec<&str> = AWAY_STEPS.iter().map(|step| step.name()).collect();
+            format!("unknown step \"{s}\" (expected one of {}, \"run COMMAND\" or \"wait DURATION\")", names.join(", "))
+        })
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stage::Step(step) => write!(f, "{step}"),
+            Stage::Wait(d) => write!(f, "wait {}", duration::format(*d)),
+            Stage::Run(command) => write!(f, "run {command}"),
+        }
+    }
+}
+
+// The default: every step in `AWAY_STEPS` order.
+pub fn default_away() -> Vec<Stage> {
+    AWAY_STEPS.into_iter().map(Stage::Step).collect()
+}
+
+// `away`'s steps undone backwards.
+pub fn default_desk(away: &[Stage]) -> Vec<Stage> {
+    away.iter().rev().filter(|stage| matches!(stage, Stage::Step(_))).cloned().collect()
+}
+
+// Every step once, each after (or for `undo`, before) what it needs.
+pub fn check(stages: &[Stage], undo: bool) -> Result<(), String> {
+    let position = |step: Step| stages.iter().position(|stage| *stage == Stage::Step(step));
+    for step in AWAY_STEPS {
+        let count = stages.iter().filter(|stage| **stage == Stage::Step(step)).count();
+        if count != 1 {
+            return Err(format!("has to name \"{}\" once, not {count} times", step.name()));
+        }
+    }
+    for &(step, needs, why) in DEPENDS {
+        let (Some(step_at), Some(needs_at)) = (position(step), position(needs)) else {
+            continue;
+        };
+        if !undo && step_at < needs_at {
+            return Err(format!("has \"{}\" before \"{}\", but {why}", step.name(), needs.name()));
+        }
+        if undo && step_at > needs_at {
+            return Err(format!("has \"{}\" after \"{}\", but {why}", step.name(), needs.name()));
+        }
+    }
+    Ok(())
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stages(list: &[&str]) -> Vec<Stage> {
+        list.iter().map(|s| s.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_parse_stage() {
+        assert_eq!("outputs".parse(), Ok(Stage::Step(Step::Outputs)));
+        assert_eq!("wait 2s".parse(), Ok(Stage::Wait(Duration::from_secs(2))));
+        assert_eq!("run ~/bin/lights dim".parse(), Ok(Stage::Run("~/bin/lights dim".to_string())));
+        assert!("output".parse::<Stage>().unwrap_err().contains("unknown step"));
+        assert!("wait soon".parse::<Stage>().is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let away = default_away();
+        assert_eq!(check(&away, false), Ok(()));
+        assert_eq!(check(&default_desk(&away), true), Ok(()));
+        // The reverse doesn't work either way round.
+        assert!(check(&default_desk(&away), false).is_err());
+
+        let mut away = stages(&["privacy", "layout", "outputs", "wait 2s", "run lights dim", "desktop", "compositor", "power", "services"]);
+        away.extend(stages(&["gamescope", "app", "audio", "input"]));
+        assert_eq!(check(&away, false), Ok(()));
+        assert_eq!(default_desk(&away).len(), 11);
+
+        // Sunshine before the dummy plug is on.
+        let mut early = away.clone();
+        early.swap(2, 8);
+        assert_eq!(check(&early, false), Err("has \"services\" before \"outputs\", but Sunshine needs the Away outputs".to_string()));
+        // Sunshine before the session is locked.
+        let mut unlocked = away.clone();
+        unlocked.remove(0);
+        unlocked.push(Stage::Step(Step::Privacy));
+        assert_eq!(check(&unlocked, false), Err("has \"services\" before \"privacy\", but the session has to be locked before Sunshine starts".to_string()));
+        assert!(check(&default_desk(&unlocked), true).is_err());
+        // A step left out, or named twice.
+        assert!(check(&away[1..], false).unwrap_err().contains("\"privacy\" once, not 0"));
+        let mut twice = away.clone();
+        twice.push(Stage::Step(Step::Input));
+        assert!(check(&twice, false).unwrap_err().contains("\"input\" once, not 2"));
+    }
+}
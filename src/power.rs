@@ -0,0 +1,179 @@
+// src/power.rs — Laptops: running on battery, and power profiles
+//
+// Streaming from a laptop on battery drains it in no time, so `[power]`
+// can keep it from going Away then:
+//
+//   [power]
+//   away_on_battery = false    # hold the desk while unplugged
+//   profile = "performance"    # power-profiles-daemon profile while Away
+//
+// `away_when` can say the same with more nuance through the `on_battery`
+// term ("dpms_off and not on_battery"). The power source comes from
+// /sys/class/power_supply: on battery means the machine has a battery of
+// its own (not a mouse's or a headset's) and no charger is online.
+//
+// `profile` is set with `powerprofilesctl` when going Away, and the one
+// that was active before is put back at the desk, like audio.rs does with
+// the default sink.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::config::PowerConfig;
+use crate::error::{Result, VitaminkError};
+use crate::process;
+
+// What power-profiles-daemon offers everywhere.
+pub const PROFILES: &[&str] = &["performance", "balanced", "power-saver"];
+
+const POWER_SUPPLY: &str = "/sys/class/power_supply";
+
+// One entry under /sys/class/power_supply.
+#[derive(Debug, PartialEq)]
+pub struct Supply {
+    // "Battery", "Mains", "USB", ...
+    pub kind: String,
+    // "System", or "Device" for a peripheral's battery.
+    pub scope: String,
+    // Chargers only.
+    pub online: Option<bool>,
+    // Batteries only: "Charging", "Discharging", "Full", ...
+    pub status: String,
+}
+
+// The profile to go back to, like audio::Restore.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Restore {
+    previous: String,
+}
+
+// ---- Power Source ----
+
+// Some(true) on battery, Some(false) on mains or without a battery, None
+// if sysfs can't be read.
+pub fn on_battery() -> Option<bool> {
+    match supplies(Path::new(POWER_SUPPLY)) {
+        Ok(supplies) => Some(on_battery_from(&supplies)),
+        Err(e) => {
+            debug!("Can't read {POWER_SUPPLY}: {e}");
+            None
+        }
+    }
+}
+
+pub fn on_battery_from(supplies: &[Supply]) -> bool {
+    let batteries: Vec<&Supply> = supplies.iter().filter(|s| s.kind == "Battery" && s.scope != "Device").collect();
+    if batteries.is_empty() {
+        return false;
+    }
+    let chargers: Vec<&Supply> = supplies.iter().filter(|s| s.kind != "Battery").collect();
+    if chargers.is_empty() {
+        // Some firmware lists no adapter; the battery still says.
+        return batteries.iter().any(|b| b.status == "Discharging");
+    }
+    !chargers.iter().any(|c| c.online == Some(true))
+}
+
+fn supplies(dir: &Path) -> Result<Vec<Supply>> {
+    let entries = fs::read_dir(dir).map_err(|e| VitaminkError::io(dir, e))?;
+    let mut supplies = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let read = |name: &str| fs::read_to_string(path.join(name)).map(|s| s.trim().to_string()).unwrap_or_default();
+        supplies.push(Supply {
+            kind: read("type"),
+            scope: read("scope"),
+            online: match read("online").as_str() {
+                "" => None,
+                online => Some(online != "0"),
+            },
+            status: read("status"),
+        });
+    }
+    Ok(supplies)
+}
+
+// ---- Power Profiles ----
+
+pub fn engage(config: &PowerConfig) -> Result<Option<Restore>> {
+    let Some(profile) = &config.profile else {
+        return Ok(None);
+    };
+    let previous = active_profile()?;
+    set_profile(profile)?;
+    Ok(Some(Restore { previous }))
+}
+
+pub fn restore(restore: &Restore) -> Result<()> {
+    set_profile(&restore.previous)
+}
+
+// Queries: always run, even in dry-run mode.
+pub fn active_profile() -> Result<String> {
+    let output = process::output(Command::new("powerprofilesctl").arg("get"))?;
+    if !output.status.success() {
+        return Err(VitaminkError::command_failed("powerprofilesctl get", &output));
+    }
+    let profile = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if profile.is_empty() {
+        return Err(VitaminkError::Parse("powerprofilesctl reports no active profile".to_string()));
+    }
+    Ok(profile)
+}
+
+// Changes: only logged in dry-run mode.
+fn set_profile(profile: &str) -> Result<()> {
+    let output = process::run(Command::new("powerprofilesctl").args(["set", profile]))?;
+    if !output.status.success() {
+        return Err(VitaminkError::command_failed(format!("powerprofilesctl set {profile}"), &output));
+    }
+    Ok(())
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::mock::FakeRunner;
+
+    fn supply(kind: &str, scope: &str, online: Option<bool>, status: &str) -> Supply {
+        Supply { kind: kind.to_string(), scope: scope.to_string(), online, status: status.to_string() }
+    }
+
+    #[test]
+    fn test_on_battery_from() {
+        let battery = supply("Battery", "", None, "Discharging");
+        assert!(on_battery_from(&[supply("Mains", "", Some(false), ""), supply("Battery", "", None, "Full")]));
+        assert!(!on_battery_from(&[supply("Mains", "", Some(true), ""), supply("Battery", "", None, "Charging")]));
+        // A USB-C charger counts as much as the barrel plug.
+        assert!(!on_battery_from(&[supply("Mains", "", Some(false), ""), supply("USB", "", Some(true), ""), supply("Battery", "", None, "")]));
+        // No adapter listed: the battery's status decides.
+        assert!(on_battery_from(std::slice::from_ref(&battery)));
+        // A desktop with a wireless mouse isn't on battery.
+        assert!(!on_battery_from(&[supply("Battery", "Device", None, "Discharging")]));
+        assert!(!on_battery_from(&[]));
+    }
+
+    #[test]
+    fn test_engage_and_restore() {
+        let config = PowerConfig { profile: Some("performance".into()), ..PowerConfig::default() };
+        let fake = Rc::new(FakeRunner::new());
+        fake.respond("powerprofilesctl", 0, "");
+        fake.respond("powerprofilesctl get", 0, "balanced\n");
+
+        let restore = process::with_runner(fake.clone(), || engage(&config)).unwrap().unwrap();
+        assert_eq!(restore.previous, "balanced");
+        assert_eq!(fake.calls().last().unwrap(), "powerprofilesctl set performance");
+
+        process::with_runner(fake.clone(), || super::restore(&restore)).unwrap();
+        assert_eq!(fake.calls().last().unwrap(), "powerprofilesctl set balanced");
+        assert!(engage(&PowerConfig::default()).unwrap().is_none());
+    }
+}
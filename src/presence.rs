@@ -0,0 +1,406 @@
+// src/presence.rs — Is anyone at the desk?
+//
+// DPMS is the original signal, but some monitors have deep-sleep quirks
+// that make it unreliable. Two alternatives can be selected in the config:
+//
+// - `logind`: the session's IdleHint from org.freedesktop.login1, which
+//   KDE sets when the idle timeout in System Settings kicks in.
+// - `idle`: raw seconds since the last input, from KDE's implementation of
+//   org.freedesktop.ScreenSaver (backed by the Wayland idle protocol inside
+//   KWin), compared against our own `idle_timeout`.
+//
+// - `camera`: an opt-in helper command that checks a local camera for a
+//   person (see camera.rs for what it may and may not do).
+// - `bluetooth`: whether a paired phone or watch is in range (see
+//   bluetooth.rs).
+//
+// `away_when` replaces the backend with a condition over several signals,
+// including the screen lock (see condition.rs).
+//
+// When the main display is a laptop panel, a closed lid means Away no
+// matter which backend is selected; so does a main display the kernel
+// reports as disconnected.
+//
+// On a machine with several login sessions, going Away also waits while
+// another user is active at our seat (see seat.rs). Input in the last
+// `recent_input` (KDE's idle time again) holds it off too: someone is
+// typing, whatever a quirky monitor's DPMS says for a moment.
+//
+// With several main displays, each is judged on its own and the readings
+// are combined per `main_display_logic`: with "all_off" the desk is empty
+// once every one is off, with "any_off" as soon as one is.
+//
+// All of it is read when the daemon polls. logind and the screensaver also
+// announce the session locking or going idle, and `watch` passes that on,
+// so the daemon can poll right away instead of at the next interval.
+
+use std::collections::HashMap;
+use std::thread;
+
+use log::{debug, warn};
+use serde::Deserialize;
+use zbus::blocking::{Connection, Proxy};
+use zbus::message::Message;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue};
+
+use crate::bluetooth;
+use crate::camera;
+use crate::condition::{Condition, Term};
+use crate::config::Config;
+use crate::dbus;
+use crate::display::{self, DpmsState};
+use crate::drm::{self, ConnectorStatus};
+use crate::error::{Result, VitaminkError};
+use crate::power;
+use crate::processes;
+use crate::seat;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Presence {
+    Present,
+    Absent,
+    // The backend couldn't tell (e.g. no DPMS file) — hold the current state.
+    Unknown,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Aggregation {
+    AllOff,
+    AnyOff,
+}
+
+impl Aggregation {
+    // Some(true) if the displays count as off together, None if the ones
+    // that can't be read would decide it.
+    pub fn combine(self, off: impl IntoIterator<Item = Option<bool>>) -> Option<bool> {
+        let off: Vec<Option<bool>> = off.into_iter().collect();
+        // The reading that settles it on its own: one On for all_off, one
+        // Off for any_off.
+        let decisive = self == Aggregation::AnyOff;
+        if off.contains(&Some(decisive)) {
+            Some(decisive)
+        } else if off.iter().all(Option::is_some) {
+            Some(!decisive)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PresenceBackend {
+    Dpms,
+    Logind,
+    Idle,
+    Camera,
+    Bluetooth,
+}
+
+// The reading, unless going Away has to wait for another session (see
+// seat.rs) or for mains power (see power.rs).
+pub fn detect(config: &Config) -> Result<Presence> {
+    let presence = reading(config)?;
+    if presence == Presence::Absent {
+        if !config.power.away_on_battery && power::on_battery() == Some(true) {
+            debug!("Not going Away: running on battery");
+            return Ok(Presence::Unknown);
+        }
+        if let Some(reason) = seat::check(&config.seat) {
+            debug!("Not going Away: {reason}");
+            return Ok(Presence::Unknown);
+        }
+        if let Some(idle) = recent_input(config) {
+            debug!("Not going Away: input {idle}s ago");
+            return Ok(Presence::Unknown);
+        }
+    }
+    Ok(presence)
+}
+
+fn reading(config: &Config) -> Result<Presence> {
+    // Unplugged or powered off at the switch: nobody is looking at it, and
+    // the kernel knows well before the compositor does.
+    let mut unplugged = Vec::new();
+    for name in &config.main_display {
+        let disconnected = drm::connector(name).is_some_and(|c| c.status == ConnectorStatus::Disconnected);
+        unplugged.push(disconnected || (display::is_internal_panel(name) && lid_closed()?));
+    }
+    if config.main_display_logic.combine(unplugged.iter().map(|&u| Some(u))) == Some(true) {
+        return Ok(Presence::Absent);
+    }
+
+    if let Some(condition) = &config.away_when {
+        return Ok(from_condition(condition, config));
+    }
+
+    match config.presence_backend {
+        PresenceBackend::Dpms => {
+            let off = config
+                .main_display
+                .iter()
+                .zip(&unplugged)
+                .map(|(name, &unplugged)| if unplugged { Some(true) } else { dpms_off(name) });
+            Ok(from_off(config.main_display_logic.combine(off)))
+        }
+        PresenceBackend::Logind => logind_idle_hint().map(from_idle),
+        PresenceBackend::Idle => {
+            let idle_secs = session_idle_seconds()?;
+            Ok(from_idle(u64::from(idle_secs) >= config.idle_timeout.as_secs()))
+        }
+        PresenceBackend::Camera => Ok(from_off(camera::absent(&config.camera))),
+        PresenceBackend::Bluetooth => Ok(from_off(bluetooth::absent(&config.bluetooth))),
+    }
+}
+
+fn dpms_off(name: &str) -> Option<bool> {
+    match display::read_dpms(name) {
+        DpmsState::On => Some(false),
+        DpmsState::Off => Some(true),
+        DpmsState::Unknown => None,
+    }
+}
+
+fn from_off(off: Option<bool>) -> Presence {
+    match off {
+        Some(true) => Presence::Absent,
+        Some(false) => Presence::Present,
+        None => Presence::Unknown,
+    }
+}
+
+fn from_idle(idle: bool) -> Presence {
+    if idle { Presence::Absent } else { Presence::Present }
+}
+
+// A signal that can't be read is unknown to the condition, rather than an
+// error, so the others can still decide.
+fn from_condition(condition: &Condition, config: &Config) -> Presence {
+    let away = condition.eval(&mut |term| match term {
+        Term::Locked => screen_locked().ok(),
+        Term::DpmsOff => config.main_display_logic.combine(config.main_display.iter().map(|name| dpms_off(name))),
+        Term::Idle(limit) => {
+            let limit = limit.unwrap_or(config.idle_timeout);
+            session_idle_seconds().ok().map(|secs| u64::from(secs) >= limit.as_secs())
+        }
+        Term::CameraEmpty => camera::absent(&config.camera),
+        Term::ProcessRunning => processes::any_running(&config.processes),
+        Term::BluetoothAway => bluetooth::absent(&config.bluetooth),
+        Term::OnBattery => power::on_battery(),
+    });
+    from_off(away)
+}
+
+// ---- logind ----
+
+// The daemon usually runs as a systemd user service, outside any login
+// session, so we ask logind for the user's *display* session instead of
+// our own.
+fn logind_idle_hint() -> Result<bool> {
+    let conn = system_bus()?;
+    let idle = session_property(&conn, "IdleHint")?;
+    bool::try_from(idle).map_err(|e| VitaminkError::Parse(format!("Unexpected logind IdleHint property: {e}")))
+}
+
+// logind's LockedHint, which KDE and GNOME keep in sync with their lock
+// screens. Desktops that don't set it still answer ScreenSaver.GetActive.
+pub fn screen_locked() -> Result<bool> {
+    let hint = system_bus().and_then(|conn| session_property(&conn, "LockedHint")).and_then(|locked| {
+        bool::try_from(locked).map_err(|e| VitaminkError::Parse(format!("Unexpected logind LockedHint property: {e}")))
+    });
+    let screensaver_active = || -> Result<bool> {
+        let conn = dbus::session()?;
+        dbus::call(&conn, "org.freedesktop.ScreenSaver", "/ScreenSaver", "org.freedesktop.ScreenSaver", "GetActive", &())
+    };
+    match hint {
+        Ok(true) => Ok(true),
+        Ok(false) => Ok(screensaver_active().unwrap_or(false)),
+        Err(_) => screensaver_active(),
+    }
+}
+
+// The X11 display (e.g. ":1") logind recorded for the graphical session.
+// Empty for pure Wayland sessions.
+pub fn session_x11_display() -> Result<String> {
+    let conn = system_bus()?;
+    let display = session_property(&conn, "Display")?;
+    String::try_from(display).map_err(|e| VitaminkError::Parse(format!("Unexpected logind Display property: {e}")))
+}
+
+fn session_property(conn: &Connection, name: &str) -> Result<OwnedValue> {
+    let (_id, session_path) = display_session(conn)?;
+    get_property(conn, session_path.as_str(), "org.freedesktop.login1.Session", name)
+}
+
+// The id and object path of the user's display session.
+pub fn display_session(conn: &Connection) -> Result<(String, OwnedObjectPath)> {
+    let display: OwnedValue = get_property(conn, "/org/freedesktop/login1/user/self", "org.freedesktop.login1.User", "Display")?;
+    display.try_into().map_err(|e| VitaminkError::Parse(format!("Unexpected logind Display property: {e}")))
+}
+
+// logind's LidClosed is false on machines without a lid.
+pub fn lid_closed() -> Result<bool> {
+    let conn = system_bus()?;
+    let closed: OwnedValue = get_property(&conn, "/org/freedesktop/login1", "org.freedesktop.login1.Manager", "LidClosed")?;
+    bool::try_from(closed).map_err(|e| VitaminkError::Parse(format!("Unexpected logind LidClosed property: {e}")))
+}
+
+pub fn system_bus() -> Result<Connection> {
+    Connection::system().map_err(|e| VitaminkError::dbus("System bus unavailable", e))
+}
+
+pub fn get_property(conn: &Connection, path: &str, interface: &str, name: &str) -> Result<OwnedValue> {
+    dbus::call(
+        conn,
+        "org.freedesktop.login1",
+        path,
+        "org.freedesktop.DBus.Properties",
+        "Get",
+        &(interface, name),
+    )
+}
+
+// ---- Change Signals ----
+
+// Calls `handler`, from threads of its own, whenever logind's LockedHint
+// or IdleHint changes or the screensaver turns on or off. Only an error if
+// neither bus could be subscribed to.
+pub fn watch(handler: impl Fn() + Send + Clone + 'static) -> Result<()> {
+    let logind = watch_logind(handler.clone());
+    let screensaver = watch_screensaver(handler);
+    match (logind, screensaver) {
+        (Err(e), Err(_)) => Err(e),
+        (Err(e), Ok(())) | (Ok(()), Err(e)) => {
+            debug!("{e}");
+            Ok(())
+        }
+        (Ok(()), Ok(())) => Ok(()),
+    }
+}
+
+fn watch_logind(handler: impl Fn() + Send + 'static) -> Result<()> {
+    let conn = system_bus()?;
+    let (_id, path) = display_session(&conn)?;
+    let signals = Proxy::new(&conn, "org.freedesktop.login1", path.into_inner(), "org.freedesktop.DBus.Properties")
+        .and_then(|proxy| proxy.receive_signal("PropertiesChanged"))
+        .map_err(|e| VitaminkError::dbus("Couldn't subscribe to logind session changes", e))?;
+    listen("logind-watch", conn, signals, move |message| {
+        let Ok((interface, changed, invalidated)) = message.body().deserialize::<(String, HashMap<String, OwnedValue>, Vec<String>)>() else {
+            return;
+        };
+        let watched = |name: &str| name == "LockedHint" || name == "IdleHint";
+        if interface == "org.freedesktop.login1.Session" && (changed.keys().any(|k| watched(k)) || invalidated.iter().any(|k| watched(k))) {
+            handler();
+        }
+    })
+}
+
+fn watch_screensaver(handler: impl Fn() + Send + 'static) -> Result<()> {
+    let conn = dbus::session()?;
+    let signals = Proxy::new(&conn, "org.freedesktop.ScreenSaver", "/ScreenSaver", "org.freedesktop.ScreenSaver")
+        .and_then(|proxy| proxy.receive_signal("ActiveChanged"))
+        .map_err(|e| VitaminkError::dbus("Couldn't subscribe to screensaver changes", e))?;
+    listen("screensaver-watch", conn, signals, move |_| handler())
+}
+
+fn listen(name: &str, conn: Connection, signals: impl Iterator<Item = Message> + Send + 'static, on_signal: impl Fn(Message) + Send + 'static) -> Result<()> {
+    let thread = name.to_string();
+    thread::Builder::new()
+        .name(thread.clone())
+        .spawn(move || {
+            // Keeps the connection alive as long as we listen.
+            let _conn = conn;
+            for message in signals {
+                on_signal(message);
+            }
+            warn!("{thread}: the bus stopped sending signals");
+        })
+        .map_err(|e| VitaminkError::spawn(name, e))?;
+    Ok(())
+}
+
+// ---- KDE idle time ----
+
+// Seconds since the last input, if that's under `recent_input`. Without
+// KDE's idle time, nothing is vetoed.
+fn recent_input(config: &Config) -> Option<u32> {
+    input_veto(config, || {
+        session_idle_seconds().map_err(|e| debug!("Not checking for recent input: {e}")).ok()
+    })
+}
+
+// `recent_input` given the idle time, which is only asked for when it
+// matters. The idle backend reads nothing else, so there's nothing to veto
+// there.
+fn input_veto(config: &Config, idle_seconds: impl FnOnce() -> Option<u32>) -> Option<u32> {
+    if config.recent_input.is_zero() || (config.presence_backend == PresenceBackend::Idle && config.away_when.is_none()) {
+        return None;
+    }
+    idle_seconds().filter(|&secs| u64::from(secs) < config.recent_input.as_secs())
+}
+
+fn session_idle_seconds() -> Result<u32> {
+    let conn = dbus::session()?;
+    dbus::call(
+        &conn,
+        "org.freedesktop.ScreenSaver",
+        "/ScreenSaver",
+        "org.freedesktop.ScreenSaver",
+        "GetSessionIdleTime",
+        &(),
+    )
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_off() {
+        assert_eq!(from_off(Some(false)), Presence::Present);
+        assert_eq!(from_off(Some(true)), Presence::Absent);
+        assert_eq!(from_off(None), Presence::Unknown);
+    }
+
+    #[test]
+    fn test_input_veto() {
+        let config = crate::config::parse("recent_input = \"30s\"").unwrap();
+        assert_eq!(input_veto(&config, || Some(5)), Some(5));
+        assert_eq!(input_veto(&config, || Some(29)), Some(29));
+        // Idle long enough, or no idle time to go by.
+        assert_eq!(input_veto(&config, || Some(30)), None);
+        assert_eq!(input_veto(&config, || None), None);
+
+        // Neither asks for the idle time.
+        let never = || -> Option<u32> { panic!("idle time read") };
+        assert_eq!(input_veto(&crate::config::parse("").unwrap(), never), None);
+        assert_eq!(input_veto(&crate::config::parse("recent_input = \"30s\"\npresence_backend = \"idle\"").unwrap(), never), None);
+        let away_when = crate::config::parse("recent_input = \"30s\"\npresence_backend = \"idle\"\naway_when = \"dpms_off\"").unwrap();
+        assert_eq!(input_veto(&away_when, || Some(5)), Some(5));
+    }
+
+    #[test]
+    fn test_combine() {
+        let (on, off, unknown) = (Some(false), Some(true), None);
+        use Aggregation::{AllOff, AnyOff};
+
+        assert_eq!(AllOff.combine([off, off, off]), Some(true));
+        assert_eq!(AllOff.combine([off, on, off]), Some(false));
+        assert_eq!(AllOff.combine([off, unknown, on]), Some(false));
+        assert_eq!(AllOff.combine([off, unknown]), None);
+
+        assert_eq!(AnyOff.combine([on, off, on]), Some(true));
+        assert_eq!(AnyOff.combine([on, on]), Some(false));
+        assert_eq!(AnyOff.combine([unknown, off]), Some(true));
+        assert_eq!(AnyOff.combine([on, unknown]), None);
+
+        // A single display reads the same either way.
+        for reading in [on, off, unknown] {
+            assert_eq!(AllOff.combine([reading]), reading);
+            assert_eq!(AnyOff.combine([reading]), reading);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+// src/privacy.rs — Microphone and webcam privacy while streaming
+//
+// Away mode means nobody is at the desk, so the office mic and camera
+// have no business being live. We mute the default PipeWire/PulseAudio
+// source with `pactl`, and de-authorize webcams at the USB level, which
+// makes the kernel drop the device entirely (nothing can open it).
+//
+// USB authorization lives under /sys/bus/usb/devices/<id>/authorized and
+// is root-writable by default; a udev rule granting write access to the
+// user's group is needed for the webcam part.
+//
+// With `lock_session`, the desk's session is locked through logind first,
+// so whoever sits down at the desk can't type into or copy out of the
+// desktop being streamed. Sunshine starts only once logind's LockedHint
+// (or the screensaver) says the lock screen is up; if it never does, going
+// Away fails and is rolled back. Coming back doesn't unlock anything: the
+// user unlocks at the desk like any other time.
+
+use std::fs;
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::config::PrivacyConfig;
+use crate::dbus;
+use crate::duration;
+use crate::error::{Result, VitaminkError};
+use crate::presence;
+use crate::process;
+
+// What we changed on the way into Away, so the way out can put back
+// exactly that — and nothing the user had set up themselves.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Restore {
+    // Some(previous mute state) if we touched the microphone.
+    microphone_was_muted: Option<bool>,
+    // USB devices we de-authorized.
+    webcams: Vec<String>,
+}
+
+// If a step fails, the ones before it are undone here: the daemon only
+// keeps a Restore it got back, so nothing else would.
+pub fn engage(config: &PrivacyConfig) -> Result<Restore> {
+    // First, so a session that won't lock leaves nothing to undo.
+    if config.lock_session {
+        lock_session(config.lock_timeout)?;
+    }
+
+    let mut restore = Restore::default();
+    if let Err(e) = engage_devices(config, &mut restore) {
+        if let Err(undo) = self::restore(&restore) {
+            warn!("Couldn't undo the privacy changes made so far: {undo}");
+        }
+        return Err(e);
+    }
+    Ok(restore)
+}
+
+// Records each change in `restore` as soon as it's made.
+fn engage_devices(config: &PrivacyConfig, restore: &mut Restore) -> Result<()> {
+    if config.mute_microphone {
+        let was_muted = source_muted()?;
+        set_source_mute(true)?;
+        restore.microphone_was_muted = Some(was_muted);
+    }
+
+    for id in &config.webcam_usb_devices {
+        set_usb_authorized(id, false)?;
+        restore.webcams.push(id.clone());
+    }
+    Ok(())
+}
+
+// Best effort: every step is attempted, and the first error is reported.
+pub fn restore(restore: &Restore) -> Result<()> {
+    let mut first_error = None;
+
+    if let Some(was_muted) = restore.microphone_was_muted
+        && let Err(e) = set_source_mute(was_muted)
+    {
+        first_error.get_or_insert(e);
+    }
+
+    for id in &restore.webcams {
+        if let Err(e) = set_usb_authorized(id, true) {
+            first_error.get_or_insert(e);
+        }
+    }
+
+    match first_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+// ---- Microphone ----
+
+fn run_pactl(args: &[&str]) -> Result<String> {
+    let output = process::output(Command::new("pactl").args(args))?;
+
+    if !output.status.success() {
+        return Err(VitaminkError::command_failed(format!("pactl {}", args.join(" ")), &output));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn source_muted() -> Result<bool> {
+    let raw = run_pactl(&["get-source-mute", "@DEFAULT_SOURCE@"])?;
+    parse_mute(&raw).ok_or_else(|| VitaminkError::Parse(format!("Unexpected pactl output: {}", raw.trim())))
+}
+
+fn set_source_mute(muted: bool) -> Result<()> {
+    let value = if muted { "1" } else { "0" };
+    run_pactl(&["set-source-mute", "@DEFAULT_SOURCE@", value])?;
+    Ok(())
+}
+
+// `pactl get-source-mute` prints "Mute: yes" or "Mute: no".
+fn parse_mute(output: &str) -> Option<bool> {
+    match output.trim().strip_prefix("Mute:")?.trim() {
+        "yes" => Some(true),
+        "no" => Some(false),
+        _ => None,
+    }
+}
+
+// ---- Session lock ----
+
+// Asks logind to lock the display session, and waits for the lock screen
+// to be reported up.
+fn lock_session(timeout: Duration) -> Result<()> {
+    if presence::screen_locked().unwrap_or(false) {
+        return Ok(());
+    }
+    let conn = presence::system_bus()?;
+    let (_id, path) = presence::display_session(&conn)?;
+    dbus::call::<()>(&conn, "org.freedesktop.login1", path.as_str(), "org.freedesktop.login1.Session", "Lock", &())?;
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        if presence::screen_locked().unwrap_or(false) {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(VitaminkError::Timeout(format!("The session didn't lock within {}", duration::format(timeout))));
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+}
+
+// ---- Webcam ----
+
+fn set_usb_authorized(id: &str, authorized: bool) -> Result<()> {
+    let path = format!("/sys/bus/usb/devices/{id}/authorized");
+    let value = if authorized { "1" } else { "0" };
+    fs::write(&path, value).map_err(|e| VitaminkError::io(path, e))
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::mock::FakeRunner;
+
+    #[test]
+    fn test_engage_undoes_on_failure() {
+        let fake = Rc::new(FakeRunner::new());
+        fake.respond("pactl get-source-mute", 0, "Mute: no\n");
+        fake.respond("pactl set-source-mute", 0, "");
+        // No such USB device, so de-authorizing it fails after the mute.
+        let config = PrivacyConfig { mute_microphone: true, webcam_usb_devices: vec!["vitamink-test".into()], ..PrivacyConfig::default() };
+
+        assert!(process::with_runner(fake.clone(), || engage(&config)).is_err());
+        assert_eq!(
+            fake.calls(),
+            ["pactl get-source-mute @DEFAULT_SOURCE@", "pactl set-source-mute @DEFAULT_SOURCE@ 1", "pactl set-source-mute @DEFAULT_SOURCE@ 0"]
+        );
+    }
+
+    #[test]
+    fn test_parse_mute() {
+        assert_eq!(parse_mute("Mute: yes\n"), Some(true));
+        assert_eq!(parse_mute("Mute: no"), Some(false));
+        assert_eq!(parse_mute("Stummschalten: ja"), None);
+    }
+}
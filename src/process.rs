@@ -0,0 +1,330 @@
+// src/process.rs — Running external commands, with a registry of live children
+//
+// Everything VitaminK does to the system goes through an external program
+// (kscreen-doctor, systemctl, pactl, ...). Running them all through
+// `process::output` gives us one place that knows which children are alive
+// and for how long — the watchdog uses that to find and kill a hung one.
+//
+// Commands that change something go through `process::run` instead, which
+// in `--dry-run` mode only logs the command line.
+//
+// A command still running after `command_timeout` (see `set_timeout`) is
+// killed and reaped, and the caller gets a Timeout error, so a wedged
+// compositor can't hold up the daemon loop until the watchdog steps in.
+//
+// Both end up at a `CommandRunner`: normally `System`, which really runs
+// the program, but tests can put a fake in its place for the current
+// thread (see `with_runner` and mock.rs) to see what display, services
+// and sunshine would run and script what kscreen-doctor or systemctl
+// answer.
+//
+// New Rust concepts in this file:
+//
+// - `static` + `Mutex`: a global variable that any thread can lock and
+//   modify. `Mutex::new` is a `const fn`, so it can initialize a static
+//   without lazy-init tricks.
+//
+// - `libc::kill`: sends a Unix signal to a PID. std's `Child::kill` only
+//   works from the thread that owns the `Child`, and here that thread is
+//   the one that's stuck.
+
+use std::cell::RefCell;
+use std::io::{self, Read, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use log::info;
+
+use crate::environment;
+use crate::error::{Result, VitaminkError};
+
+#[derive(Debug, Clone)]
+pub struct RunningChild {
+    pub pid: u32,
+    pub command: String,
+    pub started: Instant,
+}
+
+static RUNNING: Mutex<Vec<RunningChild>> = Mutex::new(Vec::new());
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+// Milliseconds; 0 waits forever. Matches the config's default until
+// `set_timeout` is called.
+static TIMEOUT_MS: AtomicU64 = AtomicU64::new(30_000);
+
+// How often a running command is checked on.
+const WAIT_TICK: Duration = Duration::from_millis(10);
+// How long past the deadline the pipes of a command that exited in time
+// still get to close.
+const PIPE_GRACE: Duration = Duration::from_millis(200);
+
+pub fn set_dry_run(enabled: bool) {
+    DRY_RUN.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+// How long a command may run before it's killed; zero means no limit.
+pub fn set_timeout(timeout: Duration) {
+    TIMEOUT_MS.store(timeout.as_millis() as u64, Ordering::Relaxed);
+}
+
+fn timeout() -> Option<Duration> {
+    Some(Duration::from_millis(TIMEOUT_MS.load(Ordering::Relaxed))).filter(|t| !t.is_zero())
+}
+
+// For commands with side effects. In dry-run mode, logs the command and
+// pretends it succeeded with no output.
+pub fn run(cmd: &mut Command) -> Result<Output> {
+    if is_dry_run() {
+        info!("[dry-run] {}", describe(cmd));
+        return Ok(Output { status: ExitStatus::from_raw(0), stdout: Vec::new(), stderr: Vec::new() });
+    }
+    output(cmd)
+}
+
+// `run`, feeding `input` to the command's stdin.
+pub fn run_with_input(cmd: &mut Command, input: &[u8]) -> Result<Output> {
+    if is_dry_run() {
+        info!("[dry-run] {}", describe(cmd));
+        return Ok(Output { status: ExitStatus::from_raw(0), stdout: Vec::new(), stderr: Vec::new() });
+    }
+    output_with_input(cmd, input)
+}
+
+// Like `Command::output()`, but tracked in the registry while it runs.
+pub fn output(cmd: &mut Command) -> Result<Output> {
+    runner_run(cmd, None)
+}
+
+// `output`, feeding `input` to the command's stdin — for secrets that
+// shouldn't show up in the argument list.
+pub fn output_with_input(cmd: &mut Command, input: &[u8]) -> Result<Output> {
+    runner_run(cmd, Some(input))
+}
+
+// ---- Runners ----
+
+pub trait CommandRunner {
+    // Runs `cmd` to completion, with `input` on its stdin.
+    fn run(&self, cmd: &mut Command, input: Option<&[u8]>) -> Result<Output>;
+}
+
+// Spawns the real program and tracks it in the registry.
+pub struct System;
+
+impl CommandRunner for System {
+    fn run(&self, cmd: &mut Command, input: Option<&[u8]>) -> Result<Output> {
+        spawn_tracked(cmd, input, timeout())
+    }
+}
+
+thread_local! {
+    static RUNNER: RefCell<Option<Rc<dyn CommandRunner>>> = const { RefCell::new(None) };
+}
+
+// Sends every command this thread runs inside `f` to `runner`. Other
+// threads (and this one afterwards, even if `f` panics) keep theirs.
+pub fn with_runner<T>(runner: Rc<dyn CommandRunner>, f: impl FnOnce() -> T) -> T {
+    struct Restore(Option<Rc<dyn CommandRunner>>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            RUNNER.with(|r| *r.borrow_mut() = self.0.take());
+        }
+    }
+
+    let _restore = Restore(RUNNER.with(|r| r.replace(Some(runner))));
+    f()
+}
+
+fn runner_run(cmd: &mut Command, input: Option<&[u8]>) -> Result<Output> {
+    match RUNNER.with(|r| r.borrow().clone()) {
+        Some(runner) => runner.run(cmd, input),
+        None => System.run(cmd, input),
+    }
+}
+
+fn spawn_tracked(cmd: &mut Command, input: Option<&[u8]>, timeout: Option<Duration>) -> Result<Output> {
+    let program = cmd.get_program().to_string_lossy().to_string();
+    environment::apply(cmd);
+    let mut child = cmd
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| VitaminkError::spawn(&program, e))?;
+
+    // Small inputs only: the whole thing is written before any output is
+    // read, and dropping the handle closes the pipe.
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(input).map_err(|e| VitaminkError::spawn(&program, e))?;
+    }
+
+    let pid = child.id();
+    lock().push(RunningChild { pid, command: describe(cmd), started: Instant::now() });
+
+    let result = wait(child, timeout);
+    // Already gone unless waiting failed.
+    lock().retain(|c| c.pid != pid);
+
+    match result.map_err(|e| VitaminkError::spawn(&program, e))? {
+        Some(output) => Ok(output),
+        None => Err(VitaminkError::Timeout(format!(
+            "{} didn't finish within {:.0}s and was killed",
+            describe(cmd),
+            timeout.unwrap_or_default().as_secs_f64()
+        ))),
+    }
+}
+
+// `wait_with_output`, except that a child still running after `timeout`
+// is killed and reaped (no zombie left behind), returning None.
+//
+// A child can exit and leave a background grandchild holding its stdout
+// or stderr open. Its output is only waited for until the deadline (and
+// `PIPE_GRACE`), then given up on: the reader is left to finish whenever
+// the pipe closes, and that stream comes back with what was read so far.
+fn wait(mut child: Child, timeout: Option<Duration>) -> io::Result<Option<Output>> {
+    // Read both pipes as it goes, so a chatty child can't fill one up and
+    // block before it exits.
+    let stdout = child.stdout.take().map(drain);
+    let stderr = child.stderr.take().map(drain);
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    loop {
+        if let Some(status) = reap(&mut child, Child::try_wait)? {
+            let until = deadline.map(|deadline| deadline.max(Instant::now() + PIPE_GRACE));
+            return Ok(Some(Output { status, stdout: collect(stdout, until), stderr: collect(stderr, until) }));
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            // The readers finish once the pipes close; they aren't joined in
+            // case a grandchild still holds them open.
+            child.kill()?;
+            reap(&mut child, |child| child.wait().map(Some))?;
+            return Ok(None);
+        }
+        thread::sleep(WAIT_TICK);
+    }
+}
+
+// Reaps `child` with `how` while holding the registry, and unregisters
+// it in the same breath, so a PID that's still listed hasn't been reaped
+// (see kill_older_than).
+fn reap(child: &mut Child, how: impl FnOnce(&mut Child) -> io::Result<Option<ExitStatus>>) -> io::Result<Option<ExitStatus>> {
+    let mut running = lock();
+    let status = how(child)?;
+    if status.is_some() {
+        let pid = child.id();
+        running.retain(|c| c.pid != pid);
+    }
+    Ok(status)
+}
+
+// A pipe being read on its own thread, into `read` as it arrives.
+struct Drain {
+    read: Arc<Mutex<Vec<u8>>>,
+    reader: JoinHandle<()>,
+}
+
+// What `drain` read, once it's done or `until` passes (None waits as
+// long as it takes).
+fn collect(drain: Option<Drain>, until: Option<Instant>) -> Vec<u8> {
+    let Some(drain) = drain else {
+        return Vec::new();
+    };
+    while !drain.reader.is_finished() && until.is_none_or(|until| Instant::now() < until) {
+        thread::sleep(WAIT_TICK);
+    }
+    std::mem::take(&mut *drain.read.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+fn drain(mut pipe: impl Read + Send + 'static) -> Drain {
+    let read = Arc::new(Mutex::new(Vec::new()));
+    let into = Arc::clone(&read);
+    let reader = thread::spawn(move || {
+        let mut chunk = [0; 4096];
+        loop {
+            match pipe.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => into.lock().unwrap_or_else(|e| e.into_inner()).extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+        }
+    });
+    Drain { read, reader }
+}
+
+pub fn running() -> Vec<RunningChild> {
+    lock().clone()
+}
+
+// Kills every tracked child that has been running longer than `age`.
+// Returns what was killed, for logging.
+pub fn kill_older_than(age: Duration) -> Vec<RunningChild> {
+    // Held until they're all signalled, so none gets reaped meanwhile.
+    let running = lock();
+    let stuck: Vec<RunningChild> = running.iter().filter(|c| c.started.elapsed() > age).cloned().collect();
+
+    for child in &stuck {
+        // SAFETY: kill() has no memory-safety preconditions. The PID is still
+        // registered and the registry is locked, and `reap` only reaps with
+        // it locked and unregisters what it reaped, so it can't have been
+        // reused.
+        unsafe {
+            libc::kill(child.pid as libc::pid_t, libc::SIGKILL);
+        }
+    }
+
+    stuck
+}
+
+// A poisoned lock only means another thread panicked mid-update; the
+// Vec itself is still usable, so carry on with it.
+fn lock() -> std::sync::MutexGuard<'static, Vec<RunningChild>> {
+    RUNNING.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub fn describe(cmd: &Command) -> String {
+    let mut parts = vec![cmd.get_program().to_string_lossy().to_string()];
+    parts.extend(cmd.get_args().map(|a| a.to_string_lossy().to_string()));
+    parts.join(" ")
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe() {
+        let mut cmd = Command::new("systemctl");
+        cmd.args(["--user", "start", "sunshine"]);
+        assert_eq!(describe(&cmd), "systemctl --user start sunshine");
+    }
+
+    #[test]
+    fn test_timeout() {
+        let output = spawn_tracked(Command::new("echo").arg("hi"), None, Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(output.stdout, b"hi\n");
+
+        let started = Instant::now();
+        let result = spawn_tracked(Command::new("sleep").arg("5"), None, Some(Duration::from_millis(100)));
+        assert!(matches!(result, Err(VitaminkError::Timeout(_))));
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(!running().iter().any(|c| c.command == "sleep 5"));
+
+        // Exits at once, leaving a grandchild on its stdout.
+        let started = Instant::now();
+        let output = spawn_tracked(Command::new("sh").args(["-c", "sleep 5 & echo hi"]), None, Some(Duration::from_millis(300))).unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"hi\n");
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
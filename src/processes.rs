@@ -0,0 +1,61 @@
+// src/processes.rs — Whether one of the configured programs is running
+//
+// A monitor left off while Steam downloads an update or compiles shaders
+// looks like nobody's there, and going Away then would restart things
+// halfway through. The `process_running` term of `away_when` is true while
+// any program listed in `processes` runs, so it can hold Away off:
+//
+//   processes = ["steam", "gamescope", "wine"]
+//   away_when = "dpms_off and not process_running"
+//
+// or, the other way round, only allow it then. A name matches a process's
+// command name (what `ps -e` shows) or the file name of the program it
+// was started as, which catches scripts and Wine's loaders. Only
+// /proc is read; nothing is run.
+
+use std::fs;
+use std::path::Path;
+
+// The kernel cuts command names to 15 bytes.
+const COMM_LEN: usize = 15;
+
+// None if /proc can't be listed.
+pub fn any_running(names: &[String]) -> Option<bool> {
+    let entries = fs::read_dir("/proc").ok()?;
+    let running = entries.flatten().filter(|e| e.file_name().to_string_lossy().bytes().all(|b| b.is_ascii_digit())).any(|entry| {
+        // Processes can exit between listing and reading: they just don't match.
+        let comm = fs::read_to_string(entry.path().join("comm")).unwrap_or_default();
+        let cmdline = fs::read(entry.path().join("cmdline")).unwrap_or_default();
+        let program = cmdline.split(|&b| b == 0).next().map(String::from_utf8_lossy).unwrap_or_default();
+        names.iter().any(|name| matches(name, comm.trim_end(), &program))
+    });
+    Some(running)
+}
+
+fn matches(name: &str, comm: &str, program: &str) -> bool {
+    let short = name.get(..COMM_LEN).unwrap_or(name);
+    comm == short || Path::new(program).file_name().is_some_and(|file| file == name)
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("steam", "steam", "/home/me/.local/share/Steam/ubuntu12_32/steam"));
+        assert!(matches("wine64-preloader", "wine64-preloade", "/usr/bin/wine64-preloader"));
+        assert!(matches("steam.sh", "bash", "/usr/lib/steam/steam.sh"));
+        assert!(!matches("steam", "steamwebhelper", "./steamwebhelper"));
+    }
+
+    #[test]
+    fn test_any_running() {
+        // This test binary itself.
+        let me = fs::read_to_string("/proc/self/comm").unwrap().trim_end().to_string();
+        assert_eq!(any_running(&[me]), Some(true));
+        assert_eq!(any_running(&["no-such-program-here".to_string()]), Some(false));
+    }
+}
@@ -0,0 +1,122 @@
+// src/recovery.rs — What a poll that keeps failing leads to
+//
+// A compositor that crashed, or came back on another socket, can leave
+// every kscreen-doctor call failing. Instead of the same error every few
+// seconds for as long as that lasts, the daemon
+//
+//   1. logs (and notifies) an error the first time, and when it changes;
+//      the same one again is logged at most every `log_every`, with the
+//      count so far,
+//   2. after `after` failed polls in a row (and every `after` more), sets
+//      things up afresh: the session's WAYLAND_DISPLAY and DISPLAY, the
+//      DRM connectors behind each output, and whatever the display
+//      backend has cached, and notifies that it did, and
+//   3. after `exit_after`, if set, exits nonzero, for systemd
+//      (`Restart=on-failure`) to start it again from scratch.
+//
+//   [recovery]
+//   after = 10
+//   exit_after = 60
+//   log_every = "5m"
+//
+// The first poll that works again says how many didn't.
+
+use std::time::Instant;
+
+use crate::config::{Config, RecoveryConfig};
+use crate::display;
+use crate::drm;
+
+#[derive(Debug, PartialEq)]
+pub enum Escalation {
+    // A new error, or a different one: log it and notify.
+    New,
+    // The same one, due to be logged again.
+    Repeated,
+    // The same one, logged not long ago.
+    Quiet,
+    // Time for `recover`.
+    Recover,
+    // Time to give up and exit.
+    Exit,
+}
+
+// The run of failed polls so far.
+#[derive(Debug, Default)]
+pub struct Failures {
+    count: u32,
+    // The error last logged, and when.
+    logged: Option<(String, Instant)>,
+}
+
+impl Failures {
+    // One more poll failed with `error`.
+    pub fn failed(&mut self, error: &str, config: &RecoveryConfig, now: Instant) -> Escalation {
+        self.count += 1;
+        let escalation = if config.exit_after != 0 && self.count >= config.exit_after {
+            Escalation::Exit
+        } else if config.after != 0 && self.count.is_multiple_of(config.after) {
+            Escalation::Recover
+        } else {
+            match &self.logged {
+                Some((last, _)) if last != error => Escalation::New,
+                Some((_, at)) if now.saturating_duration_since(*at) < config.log_every => return Escalation::Quiet,
+                Some(_) => Escalation::Repeated,
+                None => Escalation::New,
+            }
+        };
+        self.logged = Some((error.to_string(), now));
+        escalation
+    }
+
+    // A poll worked. Returns how many in a row hadn't.
+    pub fn succeeded(&mut self) -> u32 {
+        self.logged = None;
+        std::mem::take(&mut self.count)
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+}
+
+// Step 2 above.
+pub fn recover(config: &Config) {
+    display::redetect_session_env(config);
+    drm::forget();
+    display::backend().reset();
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_failed() {
+        let config = RecoveryConfig { after: 3, exit_after: 7, log_every: Duration::from_secs(60) };
+        let start = Instant::now();
+        let at = |seconds| start + Duration::from_secs(seconds);
+        let mut failures = Failures::default();
+
+        assert_eq!(failures.failed("kscreen-doctor failed", &config, at(0)), Escalation::New);
+        assert_eq!(failures.failed("kscreen-doctor failed", &config, at(5)), Escalation::Quiet);
+        assert_eq!(failures.failed("kscreen-doctor failed", &config, at(10)), Escalation::Recover);
+        assert_eq!(failures.failed("no such output", &config, at(15)), Escalation::New);
+        assert_eq!(failures.failed("no such output", &config, at(75)), Escalation::Repeated);
+        assert_eq!(failures.failed("no such output", &config, at(80)), Escalation::Recover);
+        assert_eq!(failures.failed("no such output", &config, at(85)), Escalation::Exit);
+        assert_eq!(failures.count(), 7);
+
+        assert_eq!(failures.succeeded(), 7);
+        assert_eq!(failures.succeeded(), 0);
+        assert_eq!(failures.failed("no such output", &config, at(90)), Escalation::New);
+
+        // 0 turns either off.
+        let never = RecoveryConfig { after: 0, exit_after: 0, log_every: Duration::ZERO };
+        let mut failures = Failures::default();
+        assert!((0..100).all(|_| failures.failed("x", &never, start) != Escalation::Recover));
+    }
+}
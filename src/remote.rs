@@ -0,0 +1,189 @@
+// src/remote.rs — Other PCs' daemons, over their HTTP API
+//
+// With a few streaming PCs, checking on each one from its own desk gets
+// old. Any of them with `[http] enabled = true` (see http.rs) can be
+// asked from here:
+//
+//   vitamink --host den-pc status
+//   vitamink --host den-pc away
+//   vitamink --host den-pc inhibit --duration 2h "Movie night"
+//   vitamink fleet status
+//
+// `--host` takes the name of a `[hosts.NAME]` entry,
+//
+//   [hosts.den-pc]
+//   url = "http://den-pc:9189"
+//   token = "a-long-random-string"
+//
+// or a plain HOST[:PORT] (port 9189, no token). The API has the state,
+// Away, Desk and inhibiting, so that's what `--host` covers. `fleet status`
+// asks every entry at once and prints one row per host; a host that
+// doesn't answer is a row too, and makes it exit nonzero.
+//
+// Requests go through curl, like the webhooks, with the URL and token on
+// its stdin rather than the command line.
+
+use std::process::Command;
+use std::thread;
+
+use serde_json::{Value, json};
+
+use crate::config::Config;
+use crate::error::{Result, VitaminkError};
+use crate::process;
+use crate::status::Status;
+use crate::table::Table;
+
+// `[http] listen`'s default port.
+const DEFAULT_PORT: u16 = 9189;
+
+#[derive(Debug, PartialEq)]
+pub struct Host {
+    pub name: String,
+    pub url: String,
+    pub token: Option<String>,
+}
+
+// The `[hosts]` entry called `name`, or `name` as HOST[:PORT].
+pub fn resolve(config: &Config, name: &str) -> Host {
+    match config.hosts.get(name) {
+        Some(host) => Host { name: name.to_string(), url: host.url.trim_end_matches('/').to_string(), token: host.token.clone() },
+        None if name.contains(':') => Host { name: name.to_string(), url: format!("http://{name}"), token: None },
+        None => Host { name: name.to_string(), url: format!("http://{name}:{DEFAULT_PORT}"), token: None },
+    }
+}
+
+// Every `[hosts]` entry, in name order.
+pub fn hosts(config: &Config) -> Vec<Host> {
+    config.hosts.keys().map(|name| resolve(config, name)).collect()
+}
+
+pub fn status(host: &Host) -> Result<Status> {
+    let reply = request(host, "/state", None)?;
+    serde_json::from_value(reply["status"].clone()).map_err(|e| VitaminkError::Parse(format!("Unexpected status from {}: {e}", host.name)))
+}
+
+// POST /away or /desk.
+pub fn force(host: &Host, away: bool) -> Result<()> {
+    request(host, if away { "/away" } else { "/desk" }, Some(json!({}))).map(|_| ())
+}
+
+// 0 seconds holds until released, which only the host itself can do.
+pub fn inhibit(host: &Host, name: &str, reason: &str, seconds: u64) -> Result<()> {
+    request(host, "/inhibit", Some(json!({ "name": name, "reason": reason, "seconds": seconds }))).map(|_| ())
+}
+
+// GET `path`, or POST `body` to it. The reply's {"ok":false,"error":...}
+// comes back as an error.
+fn request(host: &Host, path: &str, body: Option<Value>) -> Result<Value> {
+    let mut cmd = Command::new("curl");
+    cmd.args(["--silent", "--show-error", "--max-time", "10", "--config", "-"]);
+    let input = curl_config(&format!("{}{path}", host.url), host.token.as_deref(), body.as_ref());
+    let output = match body {
+        Some(_) => process::run_with_input(&mut cmd, input.as_bytes())?,
+        None => process::output_with_input(&mut cmd, input.as_bytes())?,
+    };
+    if !output.status.success() {
+        return Err(VitaminkError::command_failed(format!("{} {path}", host.name), &output));
+    }
+    parse_reply(&host.name, &String::from_utf8_lossy(&output.stdout))
+}
+
+// A curl config file (read from stdin with `--config -`) for one request.
+fn curl_config(url: &str, token: Option<&str>, body: Option<&Value>) -> String {
+    let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+    let mut config = format!("url = {}\n", quote(url));
+    if let Some(token) = token {
+        config += &format!("header = {}\n", quote(&format!("Authorization: Bearer {token}")));
+    }
+    if let Some(body) = body {
+        config += &format!("header = \"Content-Type: application/json\"\ndata = {}\n", quote(&body.to_string()));
+    }
+    config
+}
+
+fn parse_reply(host: &str, text: &str) -> Result<Value> {
+    let reply: Value = serde_json::from_str(text).map_err(|_| VitaminkError::Parse(format!("{host} didn't answer like VitaminK's HTTP API: {}", text.trim())))?;
+    if reply["ok"] == json!(true) {
+        return Ok(reply);
+    }
+    let error = reply["error"].as_str().unwrap_or("no reason given");
+    Err(VitaminkError::Unavailable(format!("{host}: {error}")))
+}
+
+// ---- Fleet ----
+
+// Every host's status, asked all at once.
+pub fn fleet(hosts: &[Host]) -> Vec<Result<Status>> {
+    thread::scope(|scope| {
+        let asking: Vec<_> = hosts.iter().map(|host| scope.spawn(move || status(host))).collect();
+        asking.into_iter().map(|asked| asked.join().unwrap_or_else(|_| Err(VitaminkError::Unavailable("the request panicked".to_string())))).collect()
+    })
+}
+
+pub fn render(hosts: &[Host], statuses: &[Result<Status>], now: u64) -> String {
+    let mut table = Table::new(&["HOST", "STATE", "PROFILE", "NOTE"]);
+    for (host, status) in hosts.iter().zip(statuses) {
+        let row = match status {
+            Ok(s) => {
+                let note = match (&s.pending, &s.last_error) {
+                    (Some(pending), _) => format!("{} in {}s", pending.to, pending.due.saturating_sub(now)),
+                    (None, Some(error)) => error.clone(),
+                    (None, None) => String::new(),
+                };
+                vec![host.name.clone(), s.state.to_string(), s.profile.clone().unwrap_or_default(), note]
+            }
+            Err(e) => vec![host.name.clone(), "unreachable".to_string(), String::new(), e.to_string()],
+        };
+        table.add_row(row);
+    }
+    table.render()
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    #[test]
+    fn test_resolve() {
+        let config = config::parse("[hosts.den-pc]\nurl = \"http://10.0.0.5:9189/\"\ntoken = \"s3cret\"").unwrap();
+        assert_eq!(resolve(&config, "den-pc"), Host { name: "den-pc".into(), url: "http://10.0.0.5:9189".into(), token: Some("s3cret".into()) });
+        assert_eq!(resolve(&config, "office").url, "http://office:9189");
+        assert_eq!(resolve(&config, "office:8080").url, "http://office:8080");
+        assert_eq!(hosts(&config).len(), 1);
+    }
+
+    #[test]
+    fn test_curl_config() {
+        let config = curl_config("http://den-pc:9189/away", Some("s3cret"), Some(&json!({ "name": "x" })));
+        assert_eq!(
+            config,
+            "url = \"http://den-pc:9189/away\"\nheader = \"Authorization: Bearer s3cret\"\nheader = \"Content-Type: application/json\"\ndata = \"{\\\"name\\\":\\\"x\\\"}\"\n"
+        );
+        assert_eq!(curl_config("http://den-pc:9189/state", None, None), "url = \"http://den-pc:9189/state\"\n");
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        assert!(parse_reply("den-pc", "{\"ok\":true}\n").is_ok());
+        assert_eq!(parse_reply("den-pc", "{\"ok\":false,\"error\":\"A token is needed\"}").unwrap_err().to_string(), "den-pc: A token is needed");
+        assert!(parse_reply("den-pc", "<html>").is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let config = config::parse("[hosts.den-pc]\nurl = \"http://den-pc:9189\"\n[hosts.office]\nurl = \"http://office:9189\"").unwrap();
+        let status: Status = serde_json::from_str(
+            r#"{"state":"Away","pid":1,"main_display":"DP-2","dpms":"off","sunshine_running":true,"profile":"tv","last_transition":null,"last_error":null,"hotplugs":{},"updated":100}"#,
+        )
+        .unwrap();
+        let statuses = vec![Ok(status), Err(VitaminkError::Unavailable("office: A token is needed".into()))];
+        assert_eq!(
+            render(&hosts(&config), &statuses, 100),
+            "HOST    STATE        PROFILE  NOTE\nden-pc  Away         tv\noffice  unreachable           office: A token is needed\n"
+        );
+    }
+}
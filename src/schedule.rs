@@ -0,0 +1,377 @@
+// src/schedule.rs — Quiet hours and timed switches
+//
+// `[[schedule]]` rules override presence detection at certain times:
+//
+//   # Never go Away by itself at night.
+//   [[schedule]]
+//   action = "suppress"
+//   from = "02:00"
+//   to = "07:00"
+//
+//   # Stream-ready for the workday, whether the monitor sleeps or not.
+//   [[schedule]]
+//   action = "away"
+//   days = "mon-fri"
+//   from = "09:00"
+//
+// - `suppress` holds off automatic switches to Away between `from` and
+//   `to` (AtDesk still happens, and manual overrides always work).
+// - `away` with a `to` keeps the daemon Away for the whole window; without
+//   one it switches to Away at `from`, like `ForceAway` would, and presence
+//   takes over again from there.
+//
+// `days` is "daily" (the default), "weekdays", "weekends", a range like
+// "mon-fri", or a list like "sat,sun". A window that ends before it starts
+// ("22:00" to "06:00") runs past midnight and belongs to the day it starts.
+//
+// Times are wall-clock times in the system's time zone (or the daemon's
+// `TZ`), so "09:00" stays 09:00 when the clocks change. Windows are judged
+// by the wall clock alone. One-shot switches are resolved to a real
+// instant first, because twice a year a wall-clock time doesn't map to
+// exactly one: a time the clocks skip over in spring fires as they jump
+// past it, and a time that happens twice in autumn fires the first time
+// only. The first rule in the list that matches wins.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::config::ScheduleRule as Rule;
+use crate::error::{Result, VitaminkError};
+
+const DAY_NAMES: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+const MINUTES_PER_DAY: u32 = 24 * 60;
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Suppress,
+    Away,
+}
+
+// "HH:MM", as minutes since midnight.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub struct TimeOfDay(u32);
+
+// A set of weekdays, bit 0 = Monday.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Days(u8);
+
+impl Days {
+    pub const ALL: Days = Days(0x7f);
+
+    fn contains(self, weekday: u32) -> bool {
+        self.0 & (1 << weekday) != 0
+    }
+}
+
+// A point in the week: Monday 00:00 is 0.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct WeekTime(u32);
+
+impl WeekTime {
+    pub fn new(weekday: u32, time: TimeOfDay) -> Self {
+        Self(weekday * MINUTES_PER_DAY + time.0)
+    }
+
+    // The wall-clock time in `zone` at Unix time `utc`.
+    pub fn at(utc: i64, zone: &impl Zone) -> Self {
+        let wall = utc + zone.offset(utc);
+        let minutes = wall.rem_euclid(SECONDS_PER_DAY) / 60;
+        Self::new(weekday(wall.div_euclid(SECONDS_PER_DAY)), TimeOfDay(minutes as u32))
+    }
+
+    fn weekday(self) -> u32 {
+        self.0 / MINUTES_PER_DAY
+    }
+
+    fn minutes(self) -> u32 {
+        self.0 % MINUTES_PER_DAY
+    }
+}
+
+impl Rule {
+    // Whether `now` falls inside this rule's window.
+    fn covers(&self, now: WeekTime) -> bool {
+        let Some(to) = self.to else {
+            return false;
+        };
+        let (day, minutes) = (now.weekday(), now.minutes());
+        let yesterday = (day + 6) % 7;
+        if self.from.0 <= to.0 {
+            self.days.contains(day) && (self.from.0..to.0).contains(&minutes)
+        } else {
+            (self.days.contains(day) && minutes >= self.from.0) || (self.days.contains(yesterday) && minutes < to.0)
+        }
+    }
+
+    // Whether this one-shot rule fires in (since, now], both Unix times.
+    // A clock that steps backwards fires nothing.
+    fn fires_between(&self, since: i64, now: i64, zone: &impl Zone) -> bool {
+        if self.to.is_some() || now <= since {
+            return false;
+        }
+        // Local days, with a day's slack either side for the offset; a
+        // suspend longer than a week only needs one week's worth.
+        let first = (since.max(now - 8 * SECONDS_PER_DAY)).div_euclid(SECONDS_PER_DAY) - 1;
+        let last = now.div_euclid(SECONDS_PER_DAY) + 1;
+        (first..=last).filter(|&day| self.days.contains(weekday(day))).any(|day| {
+            let moment = resolve(day * SECONDS_PER_DAY + i64::from(self.from.0) * 60, zone);
+            since < moment && moment <= now
+        })
+    }
+}
+
+// ---- Time Zones ----
+
+// Where the wall clock stands relative to UTC.
+pub trait Zone {
+    // Seconds east of UTC at Unix time `utc`.
+    fn offset(&self, utc: i64) -> i64;
+}
+
+// The system's zone (or `TZ`), through localtime_r.
+pub struct Local;
+
+impl Zone for Local {
+    fn offset(&self, utc: i64) -> i64 {
+        // SAFETY: `localtime_r` only reads `time` and writes into the
+        // zeroed `tm` we hand it.
+        let tm = unsafe {
+            let time = utc as libc::time_t;
+            let mut tm: libc::tm = std::mem::zeroed();
+            libc::localtime_r(&time, &mut tm);
+            tm
+        };
+        tm.tm_gmtoff
+    }
+}
+
+// Days since 1970-01-01, which was a Thursday.
+fn weekday(day: i64) -> u32 {
+    (day + 3).rem_euclid(7) as u32
+}
+
+// The first Unix time at which the wall clock reads `wall` (seconds since
+// the epoch on the wall clock) or later. That's the earlier of the two
+// when the clocks go back, and the moment of the jump when `wall` is
+// skipped. Assumes at most one change within a day, which every real zone
+// meets.
+fn resolve(wall: i64, zone: &impl Zone) -> i64 {
+    let local = |utc: i64| utc + zone.offset(utc);
+    let before = wall - zone.offset(wall - SECONDS_PER_DAY);
+    let after = wall - zone.offset(wall + SECONDS_PER_DAY);
+    if let Some(utc) = [before, after].into_iter().filter(|&utc| local(utc) == wall).min() {
+        return utc;
+    }
+
+    // In a gap: local(low) < wall < local(high); find where it jumps.
+    let (mut low, mut high) = (before.min(after), before.max(after));
+    while high - low > 1 {
+        let middle = low + (high - low) / 2;
+        if local(middle) < wall { low = middle } else { high = middle }
+    }
+    high
+}
+
+// The window rule in force at `now`, if any.
+pub fn active(rules: &[Rule], now: WeekTime) -> Option<Action> {
+    rules.iter().find(|r| r.covers(now)).map(|r| r.action)
+}
+
+// Whether a one-shot `away` rule came due after `since`, up to `now`
+// (Unix times).
+pub fn away_due(rules: &[Rule], since: i64, now: i64, zone: &impl Zone) -> bool {
+    rules.iter().any(|r| r.action == Action::Away && r.fires_between(since, now, zone))
+}
+
+// The current Unix time.
+pub fn now() -> i64 {
+    crate::status::unix_now() as i64
+}
+
+// ---- Parsing ----
+
+impl FromStr for TimeOfDay {
+    type Err = VitaminkError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || VitaminkError::Parse(format!("Invalid time \"{s}\", expected HH:MM"));
+        let (h, m) = s.trim().split_once(':').ok_or_else(invalid)?;
+        let (h, m): (u32, u32) = (h.parse().map_err(|_| invalid())?, m.parse().map_err(|_| invalid())?);
+        if h > 23 || m > 59 {
+            return Err(invalid());
+        }
+        Ok(Self(h * 60 + m))
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.0 / 60, self.0 % 60)
+    }
+}
+
+impl FromStr for Days {
+    type Err = VitaminkError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let day = |name: &str| {
+            DAY_NAMES.iter().position(|d| name.trim().eq_ignore_ascii_case(d)).ok_or_else(|| {
+                VitaminkError::Parse(format!("Unknown day \"{}\" in \"{s}\" (mon, tue, ..., sun)", name.trim()))
+            })
+        };
+
+        match s.trim() {
+            "daily" => return Ok(Days::ALL),
+            "weekdays" => return Ok(Days(0x1f)),
+            "weekends" => return Ok(Days(0x60)),
+            _ => {}
+        }
+        let mut days = 0u8;
+        for part in s.split(',') {
+            match part.split_once('-') {
+                // Ranges wrap: "fri-mon" is Friday to Monday.
+                Some((first, last)) => {
+                    let (first, last) = (day(first)?, day(last)?);
+                    let len = (last + 7 - first) % 7;
+                    for i in 0..=len {
+                        days |= 1 << ((first + i) % 7);
+                    }
+                }
+                None => days |= 1 << day(part)?,
+            }
+        }
+        Ok(Days(days))
+    }
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(action: Action, days: &str, from: &str, to: Option<&str>) -> Rule {
+        Rule {
+            action,
+            days: days.parse().unwrap(),
+            from: from.parse().unwrap(),
+            to: to.map(|t| t.parse().unwrap()),
+        }
+    }
+
+    fn at(weekday: u32, time: &str) -> WeekTime {
+        WeekTime::new(weekday, time.parse().unwrap())
+    }
+
+    // Central European time: +1, and +2 from `change` on (or before it,
+    // with `spring` false).
+    struct Europe {
+        change: i64,
+        spring: bool,
+    }
+
+    impl Zone for Europe {
+        fn offset(&self, utc: i64) -> i64 {
+            if (utc >= self.change) == self.spring { 7200 } else { 3600 }
+        }
+    }
+
+    struct Utc;
+
+    impl Zone for Utc {
+        fn offset(&self, _utc: i64) -> i64 {
+            0
+        }
+    }
+
+    // Unix time of HH:MM UTC on the day starting at `midnight`.
+    fn utc(midnight: i64, time: &str) -> i64 {
+        let time: TimeOfDay = time.parse().unwrap();
+        midnight + i64::from(time.0) * 60
+    }
+
+    // Monday 2025-03-31, 00:00 UTC.
+    const MONDAY: i64 = 1_743_379_200;
+    // Sunday 2025-03-30: the clocks go from 02:00 to 03:00 at 01:00 UTC.
+    const SPRING: i64 = 1_743_292_800;
+    // Sunday 2025-10-26: the clocks go from 03:00 back to 02:00 at 01:00 UTC.
+    const AUTUMN: i64 = 1_761_436_800;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("07:30".parse::<TimeOfDay>().unwrap().to_string(), "07:30");
+        assert!("24:00".parse::<TimeOfDay>().is_err());
+        assert!("7".parse::<TimeOfDay>().is_err());
+
+        assert_eq!("mon-fri".parse::<Days>().unwrap(), "weekdays".parse::<Days>().unwrap());
+        assert_eq!("sat,sun".parse::<Days>().unwrap(), Days(0x60));
+        assert_eq!("fri-mon".parse::<Days>().unwrap(), Days(0x71));
+        assert!("someday".parse::<Days>().is_err());
+    }
+
+    #[test]
+    fn test_active() {
+        let rules = [
+            rule(Action::Suppress, "daily", "02:00", Some("07:00")),
+            rule(Action::Away, "fri", "22:00", Some("06:00")),
+        ];
+        assert_eq!(active(&rules, at(0, "03:00")), Some(Action::Suppress));
+        assert_eq!(active(&rules, at(0, "07:00")), None);
+        // Friday's window runs into Saturday morning, where the earlier
+        // suppress rule wins again.
+        assert_eq!(active(&rules, at(4, "23:00")), Some(Action::Away));
+        assert_eq!(active(&rules, at(5, "01:00")), Some(Action::Away));
+        assert_eq!(active(&rules, at(5, "03:00")), Some(Action::Suppress));
+        assert_eq!(active(&rules, at(6, "01:00")), None);
+    }
+
+    #[test]
+    fn test_away_due() {
+        let rules = [rule(Action::Away, "weekdays", "09:00", None)];
+        let due = |since, now| away_due(&rules, since, now, &Utc);
+        assert!(due(utc(MONDAY, "08:59"), utc(MONDAY, "09:00")));
+        assert!(due(utc(MONDAY, "08:30"), utc(MONDAY, "09:30")));
+        assert!(!due(utc(MONDAY, "09:00"), utc(MONDAY, "09:01")));
+        let saturday = MONDAY + 5 * SECONDS_PER_DAY;
+        assert!(!due(utc(saturday, "08:59"), utc(saturday, "09:00")));
+        // Across the end of the week.
+        assert!(due(utc(MONDAY, "23:00") - SECONDS_PER_DAY, utc(MONDAY, "09:05")));
+        // The clock stepped back.
+        assert!(!due(utc(MONDAY, "09:30"), utc(MONDAY, "08:30")));
+    }
+
+    #[test]
+    fn test_dst() {
+        let rules = [rule(Action::Away, "sun", "02:30", None)];
+        let spring = Europe { change: utc(SPRING, "01:00"), spring: true };
+        let autumn = Europe { change: utc(AUTUMN, "01:00"), spring: false };
+
+        // 02:30 never happens in spring; it fires as the clock jumps from
+        // 01:59 to 03:00, and only then.
+        assert_eq!(WeekTime::at(utc(SPRING, "01:00"), &spring), at(6, "03:00"));
+        assert!(away_due(&rules, utc(SPRING, "00:59"), utc(SPRING, "01:00"), &spring));
+        assert!(!away_due(&rules, utc(SPRING, "01:00"), utc(SPRING, "01:01"), &spring));
+        assert!(!away_due(&rules, utc(SPRING, "00:00"), utc(SPRING, "00:59"), &spring));
+
+        // 02:30 happens twice in autumn (00:30 and 01:30 UTC); only the
+        // first one fires.
+        assert_eq!(WeekTime::at(utc(AUTUMN, "00:30"), &autumn), at(6, "02:30"));
+        assert_eq!(WeekTime::at(utc(AUTUMN, "01:30"), &autumn), at(6, "02:30"));
+        assert!(away_due(&rules, utc(AUTUMN, "00:29"), utc(AUTUMN, "00:31"), &autumn));
+        assert!(!away_due(&rules, utc(AUTUMN, "01:29"), utc(AUTUMN, "01:31"), &autumn));
+
+        // An overnight window holds through both changes.
+        let window = [rule(Action::Suppress, "sat", "22:00", Some("06:00"))];
+        for (zone, midnight) in [(&spring, SPRING), (&autumn, AUTUMN)] {
+            for time in ["00:30", "01:00", "01:30", "03:30"] {
+                let now = WeekTime::at(utc(midnight, time), zone);
+                assert_eq!(active(&window, now), Some(Action::Suppress), "{time} UTC");
+            }
+            assert_eq!(active(&window, WeekTime::at(utc(midnight, "05:00"), zone)), None);
+        }
+    }
+}
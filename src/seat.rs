@@ -0,0 +1,184 @@
+// src/seat.rs — Whose desk is it?
+//
+// logind can run several sessions at once: a second user switched to on
+// the same seat, a second seat with its own monitor and keyboard, an SSH
+// or XRDP login. DPMS off on our main display says nothing about them —
+// with fast user switching our session's screen goes dark precisely
+// because somebody else is now using it. Before an Absent reading is
+// acted on, `hold_reason` looks at the sessions logind lists and holds
+// the current state (as an Unknown reading does) when:
+//
+//   - another user has an active local session: on `[seat] seat`, or on
+//     our own session's seat when that's unset
+//   - `[seat] seat` is set and our display session isn't on it (a remote
+//     login has no seat at all)
+//   - `[seat] session_types` is set and our display session's type, as
+//     logind reports it ("wayland", "x11", "tty"), isn't among them
+//
+// Only going Away is held: a Present reading always brings the desk
+// back. If logind can't be asked (no system bus), nothing is held.
+
+use std::collections::HashMap;
+
+use log::debug;
+use zbus::blocking::Connection;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue};
+
+use crate::config::SeatConfig;
+use crate::dbus;
+use crate::error::Result;
+use crate::presence;
+
+// What `session_types` may list: the types logind knows.
+pub const SESSION_TYPES: &[&str] = &["wayland", "x11", "mir", "tty", "web", "unspecified"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub id: String,
+    pub uid: u32,
+    pub user: String,
+    // Empty for sessions without a seat (SSH, most remote desktops).
+    pub seat: String,
+    pub kind: String,
+    pub class: String,
+    pub active: bool,
+    pub remote: bool,
+}
+
+impl Session {
+    // Someone sitting at a seat and using it right now.
+    fn is_local_user(&self) -> bool {
+        !self.seat.is_empty() && !self.remote && self.active && self.class == "user"
+    }
+}
+
+// Why going Away should wait, if it should. Errors asking logind count
+// as no reason.
+pub fn check(config: &SeatConfig) -> Option<String> {
+    let found = presence::system_bus().and_then(|conn| {
+        // No display session (a user service started by lingering) is
+        // no session of ours, not an error.
+        let ours = presence::display_session(&conn).ok().map(|(id, _)| id);
+        Ok((sessions(&conn)?, ours))
+    });
+    match found {
+        Ok((sessions, ours)) => hold_reason(config, &sessions, ours.as_deref(), current_uid()),
+        Err(e) => {
+            debug!("Can't list logind sessions: {e}");
+            None
+        }
+    }
+}
+
+pub fn hold_reason(config: &SeatConfig, sessions: &[Session], ours: Option<&str>, uid: u32) -> Option<String> {
+    let own = ours.and_then(|id| sessions.iter().find(|s| s.id == id));
+    let restricted = config.seat.is_some() || !config.session_types.is_empty();
+
+    if restricted {
+        let Some(own) = own else {
+            return Some("logind lists no display session of ours".to_string());
+        };
+        if let Some(seat) = &config.seat
+            && own.seat != *seat
+        {
+            let on = if own.seat.is_empty() { "no seat".to_string() } else { own.seat.clone() };
+            return Some(format!("our session {} is on {on}, not {seat}", own.id));
+        }
+        if !config.session_types.is_empty() && !config.session_types.contains(&own.kind) {
+            return Some(format!("our session {} is {}, not {}", own.id, own.kind, config.session_types.join(" or ")));
+        }
+    }
+
+    if config.yield_to_other_users {
+        let seat = config.seat.as_deref().or(own.map(|s| s.seat.as_str()).filter(|seat| !seat.is_empty()));
+        if let Some(other) = sessions.iter().find(|s| s.uid != uid && s.is_local_user() && seat.is_none_or(|seat| s.seat == seat)) {
+            return Some(format!("{} is using {}", other.user, other.seat));
+        }
+    }
+    None
+}
+
+// Every session logind knows of, with the properties `hold_reason` needs.
+pub fn sessions(conn: &Connection) -> Result<Vec<Session>> {
+    let listed: Vec<(String, u32, String, String, OwnedObjectPath)> =
+        dbus::call(conn, "org.freedesktop.login1", "/org/freedesktop/login1", "org.freedesktop.login1.Manager", "ListSessions", &())?;
+    let mut sessions = Vec::new();
+    for (id, uid, user, seat, path) in listed {
+        // Sessions can end between the two calls.
+        let properties = match properties(conn, &path) {
+            Ok(properties) => properties,
+            Err(e) => {
+                debug!("Skipping session {id}: {e}");
+                continue;
+            }
+        };
+        let text = |name: &str| properties.get(name).and_then(|v| String::try_from(v.clone()).ok()).unwrap_or_default();
+        let flag = |name: &str| properties.get(name).and_then(|v| bool::try_from(v.clone()).ok()).unwrap_or(false);
+        sessions.push(Session {
+            kind: text("Type"),
+            class: text("Class"),
+            active: flag("Active"),
+            remote: flag("Remote"),
+            id,
+            uid,
+            user,
+            seat,
+        });
+    }
+    Ok(sessions)
+}
+
+fn properties(conn: &Connection, path: &OwnedObjectPath) -> Result<HashMap<String, OwnedValue>> {
+    dbus::call(conn, "org.freedesktop.login1", path.as_str(), "org.freedesktop.DBus.Properties", "GetAll", &("org.freedesktop.login1.Session",))
+}
+
+fn current_uid() -> u32 {
+    // SAFETY: getuid(2) can't fail.
+    unsafe { libc::getuid() }
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(id: &str, uid: u32, seat: &str, kind: &str, active: bool) -> Session {
+        Session {
+            id: id.to_string(),
+            uid,
+            user: format!("user{uid}"),
+            seat: seat.to_string(),
+            kind: kind.to_string(),
+            class: "user".to_string(),
+            active,
+            remote: seat.is_empty(),
+        }
+    }
+
+    #[test]
+    fn test_hold_reason() {
+        let config = SeatConfig::default();
+        let mine = session("2", 1000, "seat0", "wayland", true);
+        let ssh = session("5", 1001, "", "tty", true);
+        assert_eq!(hold_reason(&config, &[mine.clone(), ssh.clone()], Some("2"), 1000), None);
+
+        // Switched to another user on the same seat.
+        let switched = [session("2", 1000, "seat0", "wayland", false), session("7", 1001, "seat0", "x11", true)];
+        assert_eq!(hold_reason(&config, &switched, Some("2"), 1000).as_deref(), Some("user1001 is using seat0"));
+        let config = SeatConfig { yield_to_other_users: false, ..SeatConfig::default() };
+        assert_eq!(hold_reason(&config, &switched, Some("2"), 1000), None);
+
+        // Someone at another seat is none of our business.
+        let config = SeatConfig::default();
+        let other_seat = [mine.clone(), session("9", 1002, "seat1", "wayland", true)];
+        assert_eq!(hold_reason(&config, &other_seat, Some("2"), 1000), None);
+
+        // Restricted to a seat and type.
+        let config = SeatConfig { seat: Some("seat1".to_string()), ..SeatConfig::default() };
+        assert_eq!(hold_reason(&config, std::slice::from_ref(&mine), Some("2"), 1000).as_deref(), Some("our session 2 is on seat0, not seat1"));
+        let config = SeatConfig { session_types: vec!["x11".to_string()], ..SeatConfig::default() };
+        assert_eq!(hold_reason(&config, std::slice::from_ref(&mine), Some("2"), 1000).as_deref(), Some("our session 2 is wayland, not x11"));
+        assert!(hold_reason(&config, &[mine, ssh], None, 1000).is_some());
+    }
+}
@@ -0,0 +1,741 @@
+// src/services.rs — Services started for Away and stopped at the desk
+//
+// Sunshine is the default and usually the only one, but anything that
+// should only run while Away can join it — a VNC server, a game launcher:
+//
+//   [[services]]
+//   unit = "sunshine"
+//
+//   [[services]]
+//   unit = "wayvnc.service"
+//   order = 10
+//
+// Units start in ascending `order` and stop in the reverse order, so a
+// unit that depends on another gets a higher number. Ties keep the order
+// they're listed in.
+//
+// They're systemd units unless `manager` says otherwise:
+//
+//   - "runit": `unit` is a service `sv` knows (a name under $SVDIR, or a
+//     path), started with `sv up` and stopped with `sv down`.
+//   - "s6": `unit` is the service directory, driven with `s6-svc -u`/`-d`.
+//   - "process": no supervisor at all. VitaminK runs `command` itself in a
+//     session of its own, appending its output to
+//     `$XDG_STATE_HOME/vitamink/UNIT.log`, and keeps its PID in
+//     `$XDG_RUNTIME_DIR/vitamink/UNIT.pid` so a restarted daemon still
+//     finds it. Stopping sends SIGTERM to the whole session, then SIGKILL
+//     if it's still there after STOP_TIMEOUT. Under systemd the process
+//     stays in VitaminK's own cgroup, so stopping the vitamink unit takes
+//     it down too.
+//
+//     [[services]]
+//     unit = "sunshine"
+//     manager = "process"
+//     command = "~/bin/start-sunshine.sh"
+//
+// `scope`, `tuning` and `[slice]` are systemd's alone.
+//
+// `scope = "system"` units are started with plain `systemctl`, which
+// needs root or a polkit rule allowing it. VitaminK asks with
+// `--no-ask-password` first so nothing blocks, and only if that is denied
+// falls back to `pkexec`, whose password prompt appears on the desktop.
+// A rule like this skips the prompt for one unit:
+//
+//   // /etc/polkit-1/rules.d/50-vitamink.rules
+//   polkit.addRule(function(action, subject) {
+//       if (action.id == "org.freedesktop.systemd1.manage-units" &&
+//           action.lookup("unit") == "wayvnc.service" &&
+//           subject.user == "me") {
+//           return polkit.Result.YES;
+//       }
+//   });
+//
+// On a shared machine the encoder can lose out to background jobs.
+// `tuning` gives a unit CPU affinity, nice/ionice and a scheduling class
+// while Away:
+//
+//   [[services]]
+//   unit = "sunshine"
+//   tuning = { cpu_affinity = "0-3", nice = -5, io_class = "best-effort", io_priority = 0 }
+//
+// They go into a runtime drop-in,
+// `$XDG_RUNTIME_DIR/systemd/user/UNIT.d/50-vitamink.conf`, written before
+// the unit starts and removed after it stops, so the unit file itself is
+// never touched and a reboot forgets them. User units only — the system
+// manager's runtime directory isn't ours to write.
+//
+// `[slice]` goes one step further and moves units into a slice of their
+// own while Away — and with them every game Sunshine launches, since those
+// are its children — weighted against the rest of the session:
+//
+//   [slice]
+//   name = "vitamink-stream.slice"
+//   units = ["sunshine"]
+//   cpu_weight = 1000
+//   memory_low = "4G"
+//
+// Membership is a `Slice=` line in the drop-in above; the weights are set
+// on the slice with `systemctl --user set-property --runtime` before the
+// first unit starts, and `systemctl --user revert` drops them again once
+// the last one has stopped. The weights are relative to the session's
+// other slices (app.slice, background.slice), which default to 100.
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{debug, info, warn};
+use serde::Deserialize;
+
+use crate::config::{ServiceConfig, ServiceTuning, SliceConfig};
+use crate::error::{Result, VitaminkError};
+use crate::persist;
+use crate::process;
+use crate::status;
+
+// How long a "process" service gets to exit after SIGTERM.
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    User,
+    System,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Manager {
+    Systemd,
+    Runit,
+    S6,
+    Process,
+}
+
+impl fmt::Display for Manager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Manager::Systemd => "systemd",
+            Manager::Runit => "runit",
+            Manager::S6 => "s6",
+            Manager::Process => "process",
+        })
+    }
+}
+
+// systemd's IOSchedulingClass= values.
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IoClass {
+    Realtime,
+    BestEffort,
+    Idle,
+}
+
+// systemd's CPUSchedulingPolicy= values.
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SchedPolicy {
+    Other,
+    Batch,
+    Idle,
+    Fifo,
+    Rr,
+}
+
+// Starts every service, lowest `order` first.
+pub fn start_all(services: &[ServiceConfig], slice: &SliceConfig) -> Result<()> {
+    if let Some(name) = &slice.name {
+        info!("→ Weighting {name}");
+        set_slice_properties(name, &slice_properties(slice))?;
+    }
+    for service in ordered(services) {
+        let slice = slice.name.as_deref().filter(|_| slice.contains(&service.unit));
+        if service.tuning.is_enabled() || slice.is_some() {
+            info!("→ Tuning {}", service.unit);
+            set_drop_in(service, Some(&drop_in(&service.tuning, slice)))?;
+        }
+        // E.g. still up after a daemon restart while Away.
+        if is_active(service) {
+            info!("→ {} already running", service.unit);
+            continue;
+        }
+        info!("→ Starting {}", service.unit);
+        backend(service).start(service)?;
+    }
+    Ok(())
+}
+
+// Stops the running services, highest `order` first.
+pub fn stop_all(services: &[ServiceConfig], slice: &SliceConfig) -> Result<()> {
+    for service in ordered(services).into_iter().rev() {
+        if is_active(service) {
+            info!("→ Stopping {}", service.unit);
+            backend(service).stop(service)?;
+        }
+        if service.tuning.is_enabled() || slice.contains(&service.unit) {
+            set_drop_in(service, None)?;
+        }
+    }
+    if let Some(name) = &slice.name {
+        info!("→ Resetting {name}");
+        let output = process::run(Command::new("systemctl").args(["--user", "revert", name]))?;
+        if !output.status.success() {
+            return Err(VitaminkError::command_failed(format!("systemctl --user revert {name}"), &output));
+        }
+    }
+    Ok(())
+}
+
+pub fn restart(service: &ServiceConfig) -> Result<()> {
+    info!("→ Restarting {}", service.unit);
+    backend(service).restart(service)
+}
+
+pub fn is_active(service: &ServiceConfig) -> bool {
+    backend(service).is_active(service)
+}
+
+// Whether its manager knows it at all.
+pub fn is_installed(service: &ServiceConfig) -> bool {
+    backend(service).is_installed(service)
+}
+
+// The configured Sunshine unit, if there is one. An s6 service directory
+// counts by its last component.
+pub fn sunshine(services: &[ServiceConfig]) -> Option<&ServiceConfig> {
+    services.iter().find(|s| {
+        let name = Path::new(&s.unit).file_name().map_or(s.unit.as_str(), |n| n.to_str().unwrap_or_default());
+        name.trim_end_matches(".service") == "sunshine"
+    })
+}
+
+fn ordered(services: &[ServiceConfig]) -> Vec<&ServiceConfig> {
+    let mut ordered: Vec<&ServiceConfig> = services.iter().collect();
+    ordered.sort_by_key(|s| s.order);
+    ordered
+}
+
+fn systemctl(scope: Scope) -> Command {
+    let mut cmd = Command::new("systemctl");
+    match scope {
+        Scope::User => cmd.arg("--user"),
+        Scope::System => cmd.arg("--no-ask-password"),
+    };
+    cmd
+}
+
+// ---- Managers ----
+
+// Starting, stopping and checking on a service, whichever supervisor (if
+// any) runs it.
+pub trait ServiceBackend {
+    fn start(&self, service: &ServiceConfig) -> Result<()>;
+    fn stop(&self, service: &ServiceConfig) -> Result<()>;
+    fn restart(&self, service: &ServiceConfig) -> Result<()> {
+        self.stop(service)?;
+        self.start(service)
+    }
+    fn is_active(&self, service: &ServiceConfig) -> bool;
+    fn is_installed(&self, service: &ServiceConfig) -> bool;
+}
+
+pub fn backend(service: &ServiceConfig) -> &'static dyn ServiceBackend {
+    match service.manager {
+        Manager::Systemd => &Systemd,
+        Manager::Runit => &Runit,
+        Manager::S6 => &S6,
+        Manager::Process => &Process,
+    }
+}
+
+struct Systemd;
+
+impl ServiceBackend for Systemd {
+    fn start(&self, service: &ServiceConfig) -> Result<()> {
+        control(service, "start")
+    }
+
+    fn stop(&self, service: &ServiceConfig) -> Result<()> {
+        control(service, "stop")
+    }
+
+    fn restart(&self, service: &ServiceConfig) -> Result<()> {
+        control(service, "restart")
+    }
+
+    fn is_active(&self, service: &ServiceConfig) -> bool {
+        process::output(systemctl(service.scope).args(["is-active", "--quiet", &service.unit]))
+            .map(|o| o.status.success())
+            .unwrap_or(false)
+    }
+
+    fn is_installed(&self, service: &ServiceConfig) -> bool {
+        process::output(systemctl(service.scope).args(["show", "--property=LoadState", "--value", &service.unit]))
+            .is_ok_and(|o| String::from_utf8_lossy(&o.stdout).trim() == "loaded")
+    }
+}
+
+struct Runit;
+
+impl ServiceBackend for Runit {
+    fn start(&self, service: &ServiceConfig) -> Result<()> {
+        run_checked(Command::new("sv").args(["up", &service.unit]))
+    }
+
+    fn stop(&self, service: &ServiceConfig) -> Result<()> {
+        run_checked(Command::new("sv").args(["down", &service.unit]))
+    }
+
+    fn restart(&self, service: &ServiceConfig) -> Result<()> {
+        run_checked(Command::new("sv").args(["restart", &service.unit]))
+    }
+
+    // "run: sunshine: (pid 4242) 310s", "down: ...", "fail: ..." for an
+    // unknown service.
+    fn is_active(&self, service: &ServiceConfig) -> bool {
+        sv_status(service).is_some_and(|status| status.starts_with("run:"))
+    }
+
+    fn is_installed(&self, service: &ServiceConfig) -> bool {
+        sv_status(service).is_some_and(|status| !status.starts_with("fail:"))
+    }
+}
+
+fn sv_status(service: &ServiceConfig) -> Option<String> {
+    let output = process::output(Command::new("sv").args(["status", &service.unit])).ok()?;
+    Some(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+struct S6;
+
+impl ServiceBackend for S6 {
+    fn start(&self, service: &ServiceConfig) -> Result<()> {
+        run_checked(Command::new("s6-svc").args(["-u", &service.unit]))
+    }
+
+    fn stop(&self, service: &ServiceConfig) -> Result<()> {
+        run_checked(Command::new("s6-svc").args(["-d", &service.unit]))
+    }
+
+    fn restart(&self, service: &ServiceConfig) -> Result<()> {
+        run_checked(Command::new("s6-svc").args(["-r", &service.unit]))
+    }
+
+    fn is_active(&self, service: &ServiceConfig) -> bool {
+        process::output(Command::new("s6-svstat").args(["-o", "up", &service.unit]))
+            .is_ok_and(|o| o.status.success() && String::from_utf8_lossy(&o.stdout).trim() == "true")
+    }
+
+    fn is_installed(&self, service: &ServiceConfig) -> bool {
+        process::output(Command::new("s6-svstat").arg(&service.unit)).is_ok_and(|o| o.status.success())
+    }
+}
+
+// Runs the command in its own session (so stopping it reaches whatever it
+// started) with its output going to the log, and prints its PID.
+const LAUNCH: &str = r#"setsid sh -c "$1" </dev/null >>"$2" 2>&1 & echo $!"#;
+
+struct Process;
+
+impl ServiceBackend for Process {
+    fn start(&self, service: &ServiceConfig) -> Result<()> {
+        if let Some(pid) = running_pid(&service.unit) {
+            info!("→ {} already running (pid {pid})", service.unit);
+            return Ok(());
+        }
+        let command = service.command.as_deref().unwrap_or_default();
+        let log = persist::path().with_file_name(format!("{}.log", service.unit));
+        if let Some(dir) = log.parent() {
+            fs::create_dir_all(dir).map_err(|e| VitaminkError::io(dir, e))?;
+        }
+        let output = process::run(Command::new("sh").args(["-c", LAUNCH, "sh", command]).arg(&log))?;
+        if !output.status.success() {
+            return Err(VitaminkError::command_failed(format!("sh -c {command}"), &output));
+        }
+        if process::is_dry_run() {
+            return Ok(());
+        }
+        let pid: u32 = String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .parse()
+            .map_err(|_| VitaminkError::CommandFailed { command: command.to_string(), stderr: "didn't report a PID".to_string() })?;
+        let path = pid_path(&service.unit);
+        persist::write_atomic(&path, pid.to_string().as_bytes())?;
+
+        // Catches a command that fails straight away, like a typo.
+        thread::sleep(Duration::from_millis(200));
+        if !is_alive(pid) {
+            let _ = fs::remove_file(&path);
+            return Err(VitaminkError::CommandFailed {
+                command: command.to_string(),
+                stderr: format!("exited right after starting (see {})", log.display()),
+            });
+        }
+        debug!("{} started (pid {pid})", service.unit);
+        Ok(())
+    }
+
+    fn stop(&self, service: &ServiceConfig) -> Result<()> {
+        let path = pid_path(&service.unit);
+        let Some(pid) = running_pid(&service.unit) else {
+            let _ = fs::remove_file(&path);
+            return Ok(());
+        };
+        // A negative PID signals the whole session (its process group).
+        run_checked(Command::new("kill").args(["-TERM", "--", &format!("-{pid}")]))?;
+        if process::is_dry_run() {
+            return Ok(());
+        }
+        let start = Instant::now();
+        while is_alive(pid) {
+            if start.elapsed() >= STOP_TIMEOUT {
+                warn!("{} (pid {pid}) ignored SIGTERM, killing it", service.unit);
+                run_checked(Command::new("kill").args(["-KILL", "--", &format!("-{pid}")]))?;
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        fs::remove_file(&path).map_err(|e| VitaminkError::io(&path, e))
+    }
+
+    fn is_active(&self, service: &ServiceConfig) -> bool {
+        running_pid(&service.unit).is_some()
+    }
+
+    // Whether the command's program can be found.
+    fn is_installed(&self, service: &ServiceConfig) -> bool {
+        let program = service.command.as_deref().and_then(|c| c.split_whitespace().next()).unwrap_or_default();
+        let program = match program.strip_prefix("~/") {
+            Some(rest) => env::var_os("HOME").map(|home| Path::new(&home).join(rest)),
+            None => Some(PathBuf::from(program)),
+        };
+        program.is_some_and(|program| match program.components().count() {
+            0 => false,
+            1 => env::var_os("PATH").is_some_and(|path| env::split_paths(&path).any(|dir| dir.join(&program).is_file())),
+            _ => program.is_file(),
+        })
+    }
+}
+
+fn pid_path(unit: &str) -> PathBuf {
+    status::path().with_file_name(format!("{unit}.pid"))
+}
+
+// The recorded PID, if that process is still there.
+fn running_pid(unit: &str) -> Option<u32> {
+    let pid = fs::read_to_string(pid_path(unit)).ok()?.trim().parse().ok()?;
+    is_alive(pid).then_some(pid)
+}
+
+// Exited processes that haven't been reaped yet don't count.
+fn is_alive(pid: u32) -> bool {
+    fs::read_to_string(format!("/proc/{pid}/stat")).is_ok_and(|stat| process_state(&stat).is_some_and(|state| state != 'Z'))
+}
+
+// The state letter from /proc/PID/stat, which comes after the command
+// name in parentheses (itself free to contain spaces and parentheses).
+fn process_state(stat: &str) -> Option<char> {
+    stat.rsplit_once(')')?.1.trim_start().chars().next()
+}
+
+fn run_checked(cmd: &mut Command) -> Result<()> {
+    let output = process::run(cmd)?;
+    if !output.status.success() {
+        return Err(VitaminkError::command_failed(process::describe(cmd), &output));
+    }
+    Ok(())
+}
+
+fn control(service: &ServiceConfig, action: &str) -> Result<()> {
+    let unit = service.unit.as_str();
+    let output = process::run(systemctl(service.scope).args([action, unit]))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    if service.scope == Scope::System && access_denied(&output.stderr) {
+        warn!("Not allowed to {action} {unit} without a password, asking through pkexec");
+        let output = process::run(Command::new("pkexec").args(["systemctl", action, unit]))?;
+        if output.status.success() {
+            return Ok(());
+        }
+        return Err(VitaminkError::command_failed(format!("pkexec systemctl {action} {unit}"), &output));
+    }
+    Err(VitaminkError::command_failed(format!("systemctl {action} {unit}"), &output))
+}
+
+// ---- Tuning ----
+
+// Writes (or with None, removes) the unit's drop-in and reloads the user
+// manager so the next start sees it.
+fn set_drop_in(service: &ServiceConfig, text: Option<&str>) -> Result<()> {
+    let path = drop_in_path(&service.unit)?;
+    if process::is_dry_run() {
+        info!("[dry-run] {} {}", if text.is_some() { "write" } else { "remove" }, path.display());
+        return Ok(());
+    }
+
+    match text {
+        // Unchanged: no need to make systemd reload everything.
+        Some(text) if fs::read_to_string(&path).is_ok_and(|old| old == text) => return Ok(()),
+        Some(text) => {
+            let dir = path.parent().expect("drop-in path has a parent");
+            fs::create_dir_all(dir).map_err(|e| VitaminkError::io(dir, e))?;
+            fs::write(&path, text).map_err(|e| VitaminkError::io(&path, e))?;
+        }
+        None => match fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(VitaminkError::io(&path, e)),
+        },
+    }
+
+    let output = process::run(Command::new("systemctl").args(["--user", "daemon-reload"]))?;
+    if !output.status.success() {
+        return Err(VitaminkError::command_failed("systemctl --user daemon-reload", &output));
+    }
+    Ok(())
+}
+
+fn drop_in_path(unit: &str) -> Result<PathBuf> {
+    let runtime = std::env::var_os("XDG_RUNTIME_DIR")
+        .ok_or_else(|| VitaminkError::Parse("XDG_RUNTIME_DIR is not set, can't tune services".to_string()))?;
+    let unit = if unit.contains('.') { unit.to_string() } else { format!("{unit}.service") };
+    Ok(PathBuf::from(runtime).join("systemd/user").join(format!("{unit}.d")).join("50-vitamink.conf"))
+}
+
+fn drop_in(tuning: &ServiceTuning, slice: Option<&str>) -> String {
+    let mut text = "# Written by VitaminK while Away; removed when the unit stops.\n[Service]\n".to_string();
+    if let Some(slice) = slice {
+        text += &format!("Slice={slice}\n");
+    }
+    if let Some(cpus) = &tuning.cpu_affinity {
+        text += &format!("CPUAffinity={cpus}\n");
+    }
+    if let Some(nice) = tuning.nice {
+        text += &format!("Nice={nice}\n");
+    }
+    if let Some(class) = tuning.io_class {
+        let class = match class {
+            IoClass::Realtime => "realtime",
+            IoClass::BestEffort => "best-effort",
+            IoClass::Idle => "idle",
+        };
+        text += &format!("IOSchedulingClass={class}\n");
+    }
+    if let Some(priority) = tuning.io_priority {
+        text += &format!("IOSchedulingPriority={priority}\n");
+    }
+    if let Some(policy) = tuning.cpu_scheduling {
+        let policy = match policy {
+            SchedPolicy::Other => "other",
+            SchedPolicy::Batch => "batch",
+            SchedPolicy::Idle => "idle",
+            SchedPolicy::Fifo => "fifo",
+            SchedPolicy::Rr => "rr",
+        };
+        text += &format!("CPUSchedulingPolicy={policy}\n");
+    }
+    if let Some(priority) = tuning.cpu_scheduling_priority {
+        text += &format!("CPUSchedulingPriority={priority}\n");
+    }
+    text
+}
+
+// ---- Slice ----
+
+fn slice_properties(slice: &SliceConfig) -> Vec<String> {
+    let mut properties = Vec::new();
+    if let Some(weight) = slice.cpu_weight {
+        properties.push(format!("CPUWeight={weight}"));
+    }
+    if let Some(weight) = slice.io_weight {
+        properties.push(format!("IOWeight={weight}"));
+    }
+    if let Some(low) = &slice.memory_low {
+        properties.push(format!("MemoryLow={low}"));
+    }
+    if let Some(high) = &slice.memory_high {
+        properties.push(format!("MemoryHigh={high}"));
+    }
+    properties
+}
+
+fn set_slice_properties(name: &str, properties: &[String]) -> Result<()> {
+    if properties.is_empty() {
+        return Ok(());
+    }
+    let output = process::run(Command::new("systemctl").args(["--user", "set-property", "--runtime", name]).args(properties))?;
+    if !output.status.success() {
+        return Err(VitaminkError::command_failed(format!("systemctl --user set-property {name}"), &output));
+    }
+    Ok(())
+}
+
+// systemctl's message when polkit says no and it wasn't allowed to ask.
+fn access_denied(stderr: &[u8]) -> bool {
+    let stderr = String::from_utf8_lossy(stderr);
+    stderr.contains("Access denied") || stderr.contains("Interactive authentication required")
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::mock::FakeRunner;
+
+    fn service(unit: &str, order: i32) -> ServiceConfig {
+        ServiceConfig {
+            unit: unit.to_string(),
+            scope: Scope::User,
+            manager: Manager::Systemd,
+            command: None,
+            order,
+            tuning: ServiceTuning::default(),
+        }
+    }
+
+    #[test]
+    fn test_ordered() {
+        let services = [service("wayvnc", 10), service("sunshine", 0), service("obs", 10), service("pre", -1)];
+        let units: Vec<&str> = ordered(&services).iter().map(|s| s.unit.as_str()).collect();
+        assert_eq!(units, vec!["pre", "sunshine", "wayvnc", "obs"]);
+
+        assert_eq!(sunshine(&services).map(|s| s.order), Some(0));
+        assert_eq!(sunshine(&[service("sunshine.service", 3)]).map(|s| s.order), Some(3));
+        assert!(sunshine(&[service("wayvnc", 0)]).is_none());
+        assert!(sunshine(&[service("/run/service/sunshine", 0)]).is_some());
+    }
+
+    #[test]
+    fn test_other_managers() {
+        let fake = Rc::new(FakeRunner::new());
+        fake.respond("sv up", 0, "");
+        fake.respond("sv status", 0, "run: sunshine: (pid 4242) 310s; run: log: (pid 4240) 310s\n");
+        fake.respond("sv down", 0, "");
+        fake.respond("s6-svc", 0, "");
+        fake.respond("s6-svstat -o up", 0, "false\n");
+        let runit = ServiceConfig { manager: Manager::Runit, ..service("sunshine", 0) };
+        let s6 = ServiceConfig { manager: Manager::S6, ..service("/run/service/wayvnc", 10) };
+        let services = [runit, s6];
+
+        process::with_runner(fake.clone(), || {
+            start_all(&services, &SliceConfig::default()).unwrap();
+            assert!(is_active(&services[0]));
+            assert!(!is_active(&services[1]));
+            stop_all(&services, &SliceConfig::default()).unwrap();
+        });
+        // Sunshine was running already, so only wayvnc is started.
+        assert_eq!(
+            fake.calls(),
+            [
+                "sv status sunshine",
+                "s6-svstat -o up /run/service/wayvnc",
+                "s6-svc -u /run/service/wayvnc",
+                "sv status sunshine",
+                "s6-svstat -o up /run/service/wayvnc",
+                "s6-svstat -o up /run/service/wayvnc",
+                "sv status sunshine",
+                "sv down sunshine",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_process_state() {
+        assert_eq!(process_state("4242 (sunshine) S 1 4242 4242 0 -1"), Some('S'));
+        assert_eq!(process_state("4243 (a (weird) name) Z 1"), Some('Z'));
+        assert_eq!(process_state(""), None);
+        // This test itself.
+        assert!(is_alive(std::process::id()));
+    }
+
+    #[test]
+    fn test_start_stop() {
+        let fake = Rc::new(FakeRunner::new());
+        fake.respond("systemctl --user", 0, "");
+        fake.respond("systemctl --user is-active", 3, "");
+        let services = [service("sunshine", 10), service("helper", 0)];
+
+        process::with_runner(fake.clone(), || {
+            start_all(&services, &SliceConfig::default()).unwrap();
+            fake.respond("systemctl --user is-active --quiet sunshine", 0, "");
+            stop_all(&services, &SliceConfig::default()).unwrap();
+        });
+        // helper wasn't running by the time we stopped, so it's left alone.
+        assert_eq!(
+            fake.calls(),
+            [
+                "systemctl --user is-active --quiet helper",
+                "systemctl --user start helper",
+                "systemctl --user is-active --quiet sunshine",
+                "systemctl --user start sunshine",
+                "systemctl --user is-active --quiet sunshine",
+                "systemctl --user stop sunshine",
+                "systemctl --user is-active --quiet helper",
+            ]
+        );
+
+        fake.respond("systemctl --user start sunshine", 1, "");
+        fake.respond("systemctl --user is-active --quiet sunshine", 3, "");
+        let error = process::with_runner(fake, || start_all(&services, &SliceConfig::default())).unwrap_err();
+        assert!(error.to_string().contains("systemctl start sunshine"), "{error}");
+
+        // Only what isn't running yet is started.
+        let fake = Rc::new(FakeRunner::new());
+        fake.respond("systemctl --user", 0, "");
+        fake.respond("systemctl --user is-active --quiet helper", 3, "");
+        process::with_runner(fake.clone(), || start_all(&services, &SliceConfig::default())).unwrap();
+        assert!(!fake.calls().contains(&"systemctl --user start sunshine".to_string()));
+        assert!(fake.calls().contains(&"systemctl --user start helper".to_string()));
+    }
+
+    #[test]
+    fn test_drop_in() {
+        let tuning = ServiceTuning {
+            cpu_affinity: Some("0-3".to_string()),
+            nice: Some(-5),
+            io_class: Some(IoClass::BestEffort),
+            io_priority: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(
+            drop_in(&tuning, Some("vitamink-stream.slice")),
+            "# Written by VitaminK while Away; removed when the unit stops.\n\
+             [Service]\n\
+             Slice=vitamink-stream.slice\n\
+             CPUAffinity=0-3\n\
+             Nice=-5\n\
+             IOSchedulingClass=best-effort\n\
+             IOSchedulingPriority=0\n"
+        );
+    }
+
+    #[test]
+    fn test_slice_properties() {
+        let slice = SliceConfig {
+            name: Some("vitamink-stream.slice".to_string()),
+            cpu_weight: Some(1000),
+            memory_low: Some("4G".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(slice_properties(&slice), vec!["CPUWeight=1000", "MemoryLow=4G"]);
+        assert!(slice.contains("sunshine"));
+        assert!(slice.contains("sunshine.service"));
+        assert!(!slice.contains("wayvnc"));
+    }
+
+    #[test]
+    fn test_access_denied() {
+        assert!(access_denied(b"Failed to start wayvnc.service: Access denied\n"));
+        assert!(access_denied(b"Failed to start wayvnc.service: Interactive authentication required.\n"));
+        assert!(!access_denied(b"Failed to start wayvnc.service: Unit wayvnc.service not found.\n"));
+    }
+}
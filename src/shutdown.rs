@@ -0,0 +1,76 @@
+// src/shutdown.rs — Stopping cleanly on SIGTERM / SIGINT
+//
+// Without a handler, `systemctl --user stop` or Ctrl-C killed the daemon
+// wherever it was, possibly mid-Away with the dummy plug on and Sunshine
+// running. Now the signals become a `Command::Shutdown` on the daemon's
+// command channel, so the main loop finishes what it's doing and then
+// either goes back to AtDesk or just saves its state, as `on_shutdown`
+// says. A second signal while that is under way exits at once.
+//
+// SIGHUP rides the same thread and becomes a `Command::Reload`, and
+// SIGUSR1 and SIGUSR2 a `Command::ForceAway` and `Command::ForceDesk`: a
+// switch by hand for scripts that can't speak D-Bus (`pkill -USR1
+// vitamink`), held like any other (see `override_timeout`).
+//
+// New Rust concepts in this file:
+//
+// - `signal_hook::iterator::Signals`: a signal handler may only do a few
+//   async-signal-safe things, so signal-hook's handler just writes to a
+//   pipe, and a normal thread reads the signals back as an iterator.
+
+use std::io;
+use std::sync::mpsc::Sender;
+use std::thread;
+
+use log::{info, warn};
+use serde::Deserialize;
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM, SIGUSR1, SIGUSR2};
+use signal_hook::iterator::Signals;
+
+use crate::daemon::Command;
+
+// The `on_shutdown` config setting.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShutdownAction {
+    // Save the state; the next start picks up where this one left off
+    // (a restart doesn't interrupt a stream).
+    #[default]
+    Persist,
+    // Go back to AtDesk first: restore the layout, stop Sunshine, ...
+    Restore,
+}
+
+// Starts a thread that turns SIGTERM and SIGINT into `Command::Shutdown`,
+// SIGHUP into `Command::Reload`, and SIGUSR1/SIGUSR2 into
+// `Command::ForceAway`/`Command::ForceDesk`.
+pub fn forward_signals(commands: Sender<Command>) -> io::Result<()> {
+    let mut signals = Signals::new([SIGTERM, SIGINT, SIGHUP, SIGUSR1, SIGUSR2])?;
+
+    thread::Builder::new().name("signals".into()).spawn(move || {
+        let mut received = 0;
+        for signal in signals.forever() {
+            if signal == SIGHUP {
+                info!("SIGHUP received, reloading the config");
+                let _ = commands.send(Command::Reload);
+                continue;
+            }
+            if signal == SIGUSR1 || signal == SIGUSR2 {
+                let (name, command) = if signal == SIGUSR1 { ("SIGUSR1", Command::ForceAway) } else { ("SIGUSR2", Command::ForceDesk) };
+                info!("{name} received, {command:?}");
+                let _ = commands.send(command);
+                continue;
+            }
+            let name = if signal == SIGINT { "SIGINT" } else { "SIGTERM" };
+            received += 1;
+            if received > 1 {
+                warn!("{name} again, exiting without cleaning up");
+                std::process::exit(1);
+            }
+            info!("{name} received, shutting down");
+            let _ = commands.send(Command::Shutdown);
+        }
+    })?;
+
+    Ok(())
+}
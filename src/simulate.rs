@@ -0,0 +1,319 @@
+// src/simulate.rs — `vitamink simulate`: rehearse a day at the desk
+//
+// Feeds a script of readings and events to the same StateMachine the
+// daemon runs, with the configured grace period, cooldown and poll
+// intervals, and prints what the daemon would do and when. Time is
+// simulated, so an afternoon runs in a blink — handy for trying a
+// grace period against the way your monitor actually blinks before
+// putting it in the config. A script is one step per line:
+//
+//   # the monitor sleeps, wakes for a moment, sleeps again
+//   start desk          # or away; desk if left out
+//   0s    dpms off      # also "absent"; "idle" reads the same
+//   12s   dpms on       # also "present" or "active"
+//   15s   dpms off
+//   +2m   stream start  # "+" counts from the step before
+//   20m   stream end
+//   25m   dpms on
+//   40m   end           # otherwise a little past the last step
+//
+// and can also say `unknown`, `force away`, `force desk`, `cancel`,
+// `outputs lost` and `outputs returned`. As in the daemon, readings and
+// streams are only noticed at the next poll (which slows down when
+// nothing changes, see `adaptive_interval`), while forcing and cancelling
+// act at once, like their D-Bus commands. Nothing is read or changed on
+// the machine itself: the presence backend, the schedule and the [seat]
+// checks are left out.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::daemon::{self, State};
+use crate::duration;
+use crate::machine::{Action, Event, StateMachine};
+use crate::presence::Presence;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Input {
+    Reading(Presence),
+    Stream(bool),
+    Force(State),
+    Cancel,
+    OutputsLost,
+    OutputsReturned,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Step {
+    pub at: Duration,
+    pub input: Input,
+    // As written in the script, to print back.
+    pub text: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Script {
+    pub start: State,
+    pub steps: Vec<Step>,
+    pub end: Option<Duration>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Line {
+    pub at: Duration,
+    // A step from the script, or what the daemon did about it.
+    pub step: bool,
+    pub text: String,
+}
+
+pub struct Simulation {
+    pub lines: Vec<Line>,
+    pub end: Duration,
+    pub state: State,
+    pub switches: usize,
+    pub time_in: BTreeMap<State, Duration>,
+}
+
+// ---- Scripts ----
+
+pub fn parse(text: &str) -> Result<Script, String> {
+    let mut script = Script { start: State::AtDesk, steps: Vec::new(), end: None };
+    let mut last = Duration::ZERO;
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |why: String| format!("line {}: {why}", number + 1);
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if let ["start", state] = words[..] {
+            if !script.steps.is_empty() {
+                return Err(error("`start` goes before the first step".to_string()));
+            }
+            script.start = match state {
+                "desk" => State::AtDesk,
+                "away" => State::Away,
+                other => return Err(error(format!("start \"{other}\": expected desk or away"))),
+            };
+            continue;
+        }
+
+        let (when, what) = (words[0], &words[1..]);
+        let at = match when.strip_prefix('+') {
+            Some(offset) => last + duration::parse(offset).map_err(|e| error(e.to_string()))?,
+            None => duration::parse(when).map_err(|e| error(e.to_string()))?,
+        };
+        if at < last {
+            return Err(error(format!("{} comes before the step above it", duration::format(at))));
+        }
+        if script.end.is_some() {
+            return Err(error("nothing can come after `end`".to_string()));
+        }
+        last = at;
+        let input = match what {
+            ["end"] => {
+                script.end = Some(at);
+                continue;
+            }
+            ["absent"] | ["idle"] | ["dpms", "off"] => Input::Reading(Presence::Absent),
+            ["present"] | ["active"] | ["dpms", "on"] => Input::Reading(Presence::Present),
+            ["unknown"] => Input::Reading(Presence::Unknown),
+            ["stream", "start"] => Input::Stream(true),
+            ["stream", "end"] => Input::Stream(false),
+            ["force", "away"] => Input::Force(State::Away),
+            ["force", "desk"] => Input::Force(State::AtDesk),
+            ["cancel"] => Input::Cancel,
+            ["outputs", "lost"] => Input::OutputsLost,
+            ["outputs", "returned"] => Input::OutputsReturned,
+            [] => return Err(error(format!("{when} what? e.g. \"{when} dpms off\""))),
+            _ => return Err(error(format!("don't know \"{}\"", what.join(" ")))),
+        };
+        script.steps.push(Step { at, input, text: what.join(" ") });
+    }
+    if script.steps.is_empty() {
+        return Err("the script has no steps".to_string());
+    }
+    Ok(script)
+}
+
+// ---- Running ----
+
+pub fn run(script: &Script, config: &Config) -> Simulation {
+    let origin = Instant::now();
+    let mut machine = StateMachine::new(script.start, config);
+    let last_step = script.steps.last().map_or(Duration::ZERO, |step| step.at);
+    let end = script.end.unwrap_or(last_step + config.grace_period + config.cooldown + config.max_poll_interval * 2);
+
+    let mut sim = Simulation { lines: Vec::new(), end, state: script.start, switches: 0, time_in: BTreeMap::new() };
+    let mut steps = script.steps.iter().peekable();
+    // Until the script says otherwise the reading agrees with the state
+    // we start in. The first poll is right away, as in the daemon.
+    let mut reading = if script.start == State::Away { Presence::Absent } else { Presence::Present };
+    let mut streaming = false;
+    let (mut seen_reading, mut seen_streaming) = (None, false);
+    let mut quiet_polls = 0;
+    let mut next_poll = Duration::ZERO;
+    let mut since = Duration::ZERO;
+
+    loop {
+        let now = steps.peek().map_or(next_poll, |step| step.at.min(next_poll));
+        if now > end {
+            break;
+        }
+        let mut actions = Vec::new();
+        while let Some(step) = steps.next_if(|step| step.at <= now) {
+            sim.lines.push(Line { at: now, step: true, text: step.text.clone() });
+            let event = match step.input {
+                Input::Reading(presence) => {
+                    reading = presence;
+                    continue;
+                }
+                Input::Stream(on) => {
+                    streaming = on;
+                    continue;
+                }
+                Input::Force(target) => Event::ManualOverride { target, detected: reading },
+                Input::Cancel => Event::CancelPending { detected: reading },
+                Input::OutputsLost => Event::OutputsLost,
+                Input::OutputsReturned => Event::OutputsReturned,
+            };
+            actions.push(machine.handle(event, origin + now));
+        }
+
+        if now >= next_poll {
+            if streaming != seen_streaming {
+                seen_streaming = streaming;
+                let event = if streaming { Event::StreamStarted } else { Event::StreamEnded };
+                actions.push(machine.handle(event, origin + now));
+            }
+            let standby_due = machine.standby_due(origin + now);
+            let event = if seen_reading.replace(reading) != Some(reading) {
+                quiet_polls = 0;
+                Some(Event::Presence { detected: reading, effective: reading })
+            } else if machine.pending().is_some() || standby_due {
+                quiet_polls = 0;
+                Some(Event::Tick)
+            } else {
+                quiet_polls += 1;
+                None
+            };
+            if let Some(event) = event {
+                actions.push(machine.handle(event, origin + now));
+            }
+            let interval = if machine.pending().is_some() {
+                config.poll_interval
+            } else {
+                daemon::adaptive_interval(config.poll_interval, config.max_poll_interval, quiet_polls)
+            };
+            next_poll = now + interval;
+        }
+
+        for action in actions.into_iter().flatten() {
+            if let Action::Transition { from, .. } = action {
+                *sim.time_in.entry(from).or_default() += now - since;
+                since = now;
+                sim.switches += 1;
+            }
+            let text = describe(action, &machine, origin);
+            sim.lines.push(Line { at: now, step: false, text });
+        }
+    }
+    sim.state = machine.state();
+    *sim.time_in.entry(sim.state).or_default() += end - since;
+    sim
+}
+
+fn describe(action: Action, machine: &StateMachine, origin: Instant) -> String {
+    match action {
+        Action::StartGrace { to } => match machine.due() {
+            Some(due) => format!("grace period started: {to} at {}", duration::format(due - origin)),
+            None => format!("grace period started: {to}"),
+        },
+        Action::Transition { from, to } => format!("switch {from} → {to}"),
+        Action::HoldForStream => "held: a stream is in progress".to_string(),
+        Action::HoldForLoad => "held: the machine is busy".to_string(),
+        Action::HoldForCooldown { in_a_row } => match machine.due() {
+            Some(due) => format!("held by the cooldown until {} ({in_a_row} in a row)", duration::format(due - origin)),
+            None => format!("held by the cooldown ({in_a_row} in a row)"),
+        },
+        Action::Hold { from } => format!("no outputs: holding, {from} when they return"),
+        Action::Cancelled => "pending switch cancelled".to_string(),
+    }
+}
+
+pub fn render(config: &Config, sim: &Simulation) -> String {
+    let mut out = format!(
+        "grace_period {}, cooldown {}, poll_interval {} (up to {})\n\n",
+        duration::format(config.grace_period),
+        duration::format(config.cooldown),
+        duration::format(config.poll_interval),
+        duration::format(config.max_poll_interval),
+    );
+    for line in &sim.lines {
+        let marker = if line.step { "▸" } else { " " };
+        out += &format!("{:>8}  {marker} {}\n", duration::format(line.at), line.text);
+    }
+    let time_in: Vec<String> = sim.time_in.iter().map(|(state, time)| format!("{state} {}", duration::format(*time))).collect();
+    out += &format!(
+        "\n{} after {} switch{} in {} ({})\n",
+        sim.state,
+        sim.switches,
+        if sim.switches == 1 { "" } else { "es" },
+        duration::format(sim.end),
+        time_in.join(", ")
+    );
+    out
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(n: u64) -> Duration {
+        Duration::from_secs(n)
+    }
+
+    #[test]
+    fn test_parse() {
+        let script = parse("# blink\nstart away\n0s dpms on\n+5s  absent # back off\n1m stream start\n2m end\n").unwrap();
+        assert_eq!(script.start, State::Away);
+        assert_eq!(script.end, Some(secs(120)));
+        let steps: Vec<(Duration, Input)> = script.steps.iter().map(|s| (s.at, s.input)).collect();
+        assert_eq!(
+            steps,
+            [(secs(0), Input::Reading(Presence::Present)), (secs(5), Input::Reading(Presence::Absent)), (secs(60), Input::Stream(true))]
+        );
+
+        assert_eq!(parse("5s dpms sideways").unwrap_err(), "line 1: don't know \"dpms sideways\"");
+        assert_eq!(parse("10s absent\n5s present").unwrap_err(), "line 2: 5s comes before the step above it");
+        assert!(parse("0s absent\nstart away").is_err());
+        assert!(parse("soon absent").is_err());
+        assert!(parse("# nothing\n").is_err());
+    }
+
+    #[test]
+    fn test_run() {
+        let config = Config { grace_period: secs(10), poll_interval: secs(5), ..Config::default() };
+        // A blink shorter than the grace period, then off for good.
+        let script = parse("0s dpms off\n7s dpms on\n12s dpms off\n1m end").unwrap();
+        let sim = run(&script, &config);
+
+        let actions: Vec<(Duration, &str)> = sim.lines.iter().filter(|l| !l.step).map(|l| (l.at, l.text.as_str())).collect();
+        assert_eq!(
+            actions,
+            [
+                (secs(0), "grace period started: Away at 10s"),
+                (secs(15), "grace period started: Away at 25s"),
+                (secs(25), "switch AtDesk → Away"),
+            ]
+        );
+        assert_eq!(sim.state, State::Away);
+        assert_eq!(sim.switches, 1);
+        assert_eq!(sim.time_in[&State::AtDesk], secs(25));
+        assert_eq!(sim.time_in[&State::Away], secs(35));
+        assert!(render(&config, &sim).ends_with("\nAway after 1 switch in 1m (AtDesk 25s, Away 35s)\n"));
+    }
+}
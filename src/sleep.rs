@@ -0,0 +1,97 @@
+// src/sleep.rs — Noticing system suspend and resume
+//
+// Right after a resume, DPMS and connector state are whatever the kernel
+// and compositor had before waking everything up: the main monitor reads
+// as off for a few seconds, which used to look like the user leaving. The
+// `SleepWatch` thread follows logind's `PrepareForSleep` signal so the
+// daemon can stop deciding anything before the machine goes down, and
+// start again from fresh readings once it's back (see `Daemon::poll`).
+//
+// It holds a "delay" inhibitor lock while awake, so logind waits for us to
+// mark ourselves asleep before suspending (for at most its
+// InhibitDelayMaxSec).
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::thread;
+
+use log::{debug, info, warn};
+use zbus::blocking::{Connection, Proxy};
+use zbus::zvariant::OwnedFd;
+
+use crate::dbus;
+use crate::error::{Result, VitaminkError};
+
+const LOGIN1: &str = "org.freedesktop.login1";
+const MANAGER_PATH: &str = "/org/freedesktop/login1";
+const MANAGER: &str = "org.freedesktop.login1.Manager";
+
+#[derive(Default)]
+struct Shared {
+    asleep: AtomicBool,
+    // Bumped on every resume, so a suspend that starts and ends between
+    // two polls isn't missed.
+    resumes: AtomicU32,
+}
+
+pub struct SleepWatch {
+    shared: Arc<Shared>,
+}
+
+impl SleepWatch {
+    // Subscribes before returning, so a failure (no system bus, no logind)
+    // is reported to the caller instead of the thread.
+    pub fn start() -> Result<Self> {
+        let conn = Connection::system().map_err(|e| VitaminkError::dbus("System bus unavailable", e))?;
+        let proxy = Proxy::new(&conn, LOGIN1, MANAGER_PATH, MANAGER)
+            .map_err(|e| VitaminkError::dbus("logind unavailable", e))?;
+        let signals = proxy
+            .receive_signal("PrepareForSleep")
+            .map_err(|e| VitaminkError::dbus("Couldn't subscribe to PrepareForSleep", e))?;
+
+        let shared = Arc::new(Shared::default());
+        let watched = shared.clone();
+        thread::Builder::new()
+            .name("sleep".to_string())
+            .spawn(move || {
+                let mut lock = delay_lock(&conn);
+                for message in signals {
+                    let Ok(going_down) = message.body().deserialize::<bool>() else {
+                        continue;
+                    };
+                    if going_down {
+                        info!("System is suspending, pausing");
+                        watched.asleep.store(true, Ordering::SeqCst);
+                        // Dropping the lock lets the suspend go ahead.
+                        lock = None;
+                    } else {
+                        info!("System resumed");
+                        watched.resumes.fetch_add(1, Ordering::SeqCst);
+                        watched.asleep.store(false, Ordering::SeqCst);
+                        lock = delay_lock(&conn);
+                    }
+                }
+                drop(lock);
+                warn!("Stopped receiving suspend notifications from logind");
+            })
+            .map_err(|e| VitaminkError::spawn("sleep watcher", e))?;
+
+        debug!("Watching for suspend");
+        Ok(Self { shared })
+    }
+
+    pub fn is_asleep(&self) -> bool {
+        self.shared.asleep.load(Ordering::SeqCst)
+    }
+
+    pub fn resumes(&self) -> u32 {
+        self.shared.resumes.load(Ordering::SeqCst)
+    }
+}
+
+// None if logind won't give us one; suspend then just doesn't wait.
+fn delay_lock(conn: &Connection) -> Option<OwnedFd> {
+    dbus::call(conn, LOGIN1, MANAGER_PATH, MANAGER, "Inhibit", &("sleep", "VitaminK", "Pausing display switching", "delay"))
+        .inspect_err(|e| debug!("No delay lock: {e}"))
+        .ok()
+}
@@ -0,0 +1,328 @@
+// src/smoke.rs — `vitamink smoke-test`: does this build work here?
+//
+// For packagers and anyone without CI: exercises each subsystem once,
+// against the in-memory compositor in mock.rs and a fake `systemctl`, and
+// prints one line per component:
+//
+//   PASS  display   2 outputs; HDMI-A-1 enabled at 1920x1080@120 and disabled
+//   FAIL  hooks     the hook wrote "" instead of "Away"
+//
+// Nothing real is touched: PATH, XDG_RUNTIME_DIR and XDG_STATE_HOME point
+// into a scratch directory that's removed afterwards. What still depends
+// on the machine is `sh` for the fake systemctl and the hooks.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::condition::Term;
+use crate::config::{self, Config};
+use crate::daemon::State;
+use crate::diagram;
+use crate::display::{self, DisplayState, DpmsState};
+use crate::hooks::{self, Transition};
+use crate::layout::Layout;
+use crate::mock::MockBackend;
+use crate::persist::{self, Persisted};
+use crate::schedule::{self, Action, WeekTime};
+use crate::services;
+use crate::status::{self, Status};
+
+pub struct Component {
+    pub name: &'static str,
+    // What was checked, or what went wrong.
+    pub result: Result<String, String>,
+}
+
+type Check = fn(&Config, &Path) -> Result<String, String>;
+
+const CONFIG: &str = r#"
+main_display = "DP-2"
+dummy_plug = "HDMI-A-1"
+dummy_mode = "1920x1080@120"
+away_when = "dpms_off and (locked or idle > 10m)"
+
+[[services]]
+unit = "sunshine"
+order = 10
+tuning = { nice = -5 }
+
+[[services]]
+unit = "smoke-helper"
+
+[slice]
+name = "vitamink-smoke.slice"
+cpu_weight = 500
+
+[[schedule]]
+action = "suppress"
+days = "weekdays"
+from = "09:00"
+to = "17:00"
+"#;
+
+// Units are files in units/; every call is appended to calls.
+const SYSTEMCTL: &str = r#"#!/bin/sh
+dir=$(dirname "$0")
+echo "$*" >> "$dir/calls"
+case "$1" in --user|--no-ask-password) shift ;; esac
+mkdir -p "$dir/units"
+case "$1" in
+    start) touch "$dir/units/$2" ;;
+    stop) rm -f "$dir/units/$2" ;;
+    is-active) [ -e "$dir/units/$3" ] ;;
+    show) echo loaded ;;
+    daemon-reload|set-property|revert) ;;
+    *) echo "fake systemctl: unknown command $1" >&2; exit 1 ;;
+esac
+"#;
+
+// Runs every component. Changes this process's environment, so call it
+// before any other thread exists.
+pub fn run() -> Vec<Component> {
+    let scratch = env::temp_dir().join(format!("vitamink-smoke-{}", std::process::id()));
+    if let Err(e) = prepare(&scratch) {
+        return vec![Component { name: "setup", result: Err(format!("{}: {e}", scratch.display())) }];
+    }
+    display::use_backend(Box::new(MockBackend::desk()));
+
+    let parsed = config::parse(CONFIG);
+    let config = parsed.as_ref().ok();
+    let mut components = vec![Component {
+        name: "config",
+        result: parsed
+            .as_ref()
+            .map(|c| format!("parsed {} services and {} schedule rule", c.services.len(), c.schedule.len()))
+            .map_err(|e| e.clone()),
+    }];
+    let checks: [(&'static str, Check); 9] = [
+        ("display", check_display),
+        ("layout", check_layout),
+        ("condition", check_condition),
+        ("schedule", check_schedule),
+        ("services", check_services),
+        ("hooks", check_hooks),
+        ("state", check_state),
+        ("status", check_status),
+        ("diagram", check_diagram),
+    ];
+    for (name, check) in checks {
+        let result = match config {
+            Some(config) => check(config, &scratch),
+            None => Err("skipped, the config didn't parse".to_string()),
+        };
+        components.push(Component { name, result });
+    }
+
+    let _ = fs::remove_dir_all(&scratch);
+    components
+}
+
+pub fn render(components: &[Component]) -> String {
+    let width = components.iter().map(|c| c.name.len()).max().unwrap_or(0);
+    let mut out = String::new();
+    for component in components {
+        let (verdict, detail) = match &component.result {
+            Ok(detail) => ("PASS", detail),
+            Err(problem) => ("FAIL", problem),
+        };
+        out += &format!("{verdict}  {:width$}  {detail}\n", component.name);
+    }
+    let passed = components.iter().filter(|c| c.result.is_ok()).count();
+    out += &format!("\n{passed} of {} components passed\n", components.len());
+    out
+}
+
+// Makes the scratch directory and points the environment into it.
+fn prepare(scratch: &Path) -> std::io::Result<()> {
+    let bin = scratch.join("bin");
+    fs::create_dir_all(&bin)?;
+    fs::create_dir_all(scratch.join("runtime"))?;
+    let systemctl = bin.join("systemctl");
+    fs::write(&systemctl, SYSTEMCTL)?;
+    fs::set_permissions(&systemctl, fs::Permissions::from_mode(0o755))?;
+
+    let path = env::var_os("PATH").unwrap_or_default();
+    let path = env::join_paths([bin].into_iter().chain(env::split_paths(&path))).map_err(std::io::Error::other)?;
+    // SAFETY: `run` is documented to be called while this is the only
+    // thread, so nothing reads the environment concurrently.
+    unsafe {
+        env::set_var("PATH", path);
+        env::set_var("XDG_RUNTIME_DIR", scratch.join("runtime"));
+        env::set_var("XDG_STATE_HOME", scratch.join("state"));
+    }
+    Ok(())
+}
+
+fn ensure(ok: bool, problem: impl FnOnce() -> String) -> Result<(), String> {
+    if ok { Ok(()) } else { Err(problem()) }
+}
+
+// ---- Components ----
+
+fn check_display(config: &Config, _scratch: &Path) -> Result<String, String> {
+    let dummy = &config.dummy_plug;
+    let count = display::get_displays().map_err(|e| e.to_string())?.len();
+    ensure(count == 2, || format!("listed {count} outputs instead of 2"))?;
+
+    display::enable_output(dummy, config.dummy_mode.as_ref(), config.dummy_scale).map_err(|e| e.to_string())?;
+    let displays = display::get_displays().map_err(|e| e.to_string())?;
+    let plug = displays.iter().find(|d| d.name == *dummy).ok_or_else(|| format!("{dummy} disappeared"))?;
+    let mode = plug.modes.iter().find(|m| m.current).ok_or_else(|| format!("{dummy} has no current mode"))?;
+    let mode = format!("{}x{}@{}", mode.width, mode.height, mode.refresh);
+    ensure(mode == "1920x1080@120", || format!("{dummy} got {mode} instead of 1920x1080@120"))?;
+
+    display::disable_output(dummy).map_err(|e| e.to_string())?;
+    let displays = display::get_displays().map_err(|e| e.to_string())?;
+    ensure(displays.iter().any(|d| d.name == *dummy && d.state == DisplayState::Disabled), || {
+        format!("{dummy} is still enabled")
+    })?;
+    display::set_all_dpms(false).and_then(|()| display::set_all_dpms(true)).map_err(|e| e.to_string())?;
+    Ok(format!("{count} outputs; {dummy} enabled at {mode} and disabled"))
+}
+
+fn check_layout(config: &Config, _scratch: &Path) -> Result<String, String> {
+    let saved = Layout::capture().map_err(|e| e.to_string())?;
+    display::enable_output(&config.dummy_plug, None, None).map_err(|e| e.to_string())?;
+    display::disable_output(config.primary_display()).map_err(|e| e.to_string())?;
+
+    saved.restore(config.primary_display()).map_err(|e| e.to_string())?;
+    let restored = Layout::capture().map_err(|e| e.to_string())?;
+    ensure(restored == saved, || format!("restored {restored:?}, expected {saved:?}"))?;
+    Ok(format!("{} outputs saved and restored", saved.outputs.len()))
+}
+
+fn check_condition(config: &Config, _scratch: &Path) -> Result<String, String> {
+    let condition = config.away_when.as_ref().ok_or("away_when is missing")?;
+    let answer = |dpms_off: bool| {
+        condition.eval(&mut |term| match term {
+            Term::DpmsOff => Some(dpms_off),
+            Term::Locked => Some(false),
+            Term::Idle(_) => Some(true),
+            Term::CameraEmpty | Term::ProcessRunning | Term::BluetoothAway | Term::OnBattery => None,
+        })
+    };
+    ensure(answer(true) == Some(true), || format!("\"{condition}\" wasn't true with the monitor asleep"))?;
+    ensure(answer(false) == Some(false), || format!("\"{condition}\" wasn't false with the monitor on"))?;
+    Ok(format!("\"{condition}\" evaluated"))
+}
+
+fn check_schedule(config: &Config, _scratch: &Path) -> Result<String, String> {
+    let at = |weekday, time: &str| WeekTime::new(weekday, time.parse().expect("valid time"));
+    let tuesday = schedule::active(&config.schedule, at(1, "10:30"));
+    ensure(tuesday == Some(Action::Suppress), || format!("Tuesday 10:30 gave {tuesday:?}, expected Suppress"))?;
+    let saturday = schedule::active(&config.schedule, at(5, "10:30"));
+    ensure(saturday.is_none(), || format!("Saturday 10:30 gave {saturday:?}, expected nothing"))?;
+    Ok("weekday window matched".to_string())
+}
+
+fn check_services(config: &Config, scratch: &Path) -> Result<String, String> {
+    let calls = scratch.join("bin/calls");
+    let started = |verb: &str| -> Vec<String> {
+        let calls = fs::read_to_string(&calls).unwrap_or_default();
+        calls.lines().filter_map(|l| l.strip_prefix(&format!("--user {verb} ")).map(str::to_string)).collect()
+    };
+    let drop_in = PathBuf::from(scratch).join("runtime/systemd/user/sunshine.service.d/50-vitamink.conf");
+
+    services::start_all(&config.services, &config.slice).map_err(|e| e.to_string())?;
+    ensure(config.services.iter().all(services::is_active), || "not every unit is active after starting".to_string())?;
+    ensure(started("start") == ["smoke-helper", "sunshine"], || format!("started in the order {:?}", started("start")))?;
+    let tuned = fs::read_to_string(&drop_in).map_err(|e| format!("{}: {e}", drop_in.display()))?;
+    ensure(tuned.contains("Nice=-5") && tuned.contains("Slice=vitamink-smoke.slice"), || {
+        format!("the drop-in says {tuned:?}")
+    })?;
+
+    services::stop_all(&config.services, &config.slice).map_err(|e| e.to_string())?;
+    ensure(!config.services.iter().any(services::is_active), || "a unit is still active after stopping".to_string())?;
+    ensure(started("stop") == ["sunshine", "smoke-helper"], || format!("stopped in the order {:?}", started("stop")))?;
+    ensure(!drop_in.exists(), || "the drop-in is still there".to_string())?;
+    Ok(format!("{} units started, tuned and stopped in order", config.services.len()))
+}
+
+fn check_hooks(config: &Config, scratch: &Path) -> Result<String, String> {
+    let written = scratch.join("hook");
+    let command = format!("printf %s \"$VITAMINK_STATE\" > '{}'", written.display());
+    let transition = Transition {
+        hook: "on_away",
+        state: State::Away.to_string(),
+        previous: Some(State::AtDesk.to_string()),
+        main_display: config.primary_display(),
+        dummy_plug: Some(&config.dummy_plug),
+    };
+    hooks::run_all(&[command], &transition, Duration::from_secs(5));
+
+    let state = fs::read_to_string(&written).unwrap_or_default();
+    ensure(state == "Away", || format!("the hook wrote {state:?} instead of \"Away\""))?;
+    Ok("ran with the transition in its environment".to_string())
+}
+
+fn check_state(config: &Config, _scratch: &Path) -> Result<String, String> {
+    let layout = Layout::capture().map_err(|e| e.to_string())?;
+    let persisted = Persisted {
+        state: State::Away,
+        dummy_plug: Some(config.dummy_plug.clone()),
+        profile: None,
+        saved_layout: Some(layout.clone()),
+        ..Persisted::default()
+    };
+    persist::save(&persisted).map_err(|e| e.to_string())?;
+    let loaded = persist::load().map_err(|e| e.to_string())?.ok_or("the state file wasn't written")?;
+    ensure(loaded.state == State::Away && loaded.saved_layout == Some(layout), || {
+        format!("read back {loaded:?}")
+    })?;
+    Ok(format!("written to and read from {}", persist::path().display()))
+}
+
+fn check_status(config: &Config, _scratch: &Path) -> Result<String, String> {
+    let written = Status {
+        state: State::Away,
+        pid: std::process::id(),
+        main_display: config.primary_display().to_string(),
+        dpms: DpmsState::Off,
+        sunshine_running: true,
+        services: config.services.iter().map(|s| (s.unit.clone(), true)).collect(),
+        profile: None,
+        last_transition: Some(status::unix_now()),
+        last_error: None,
+        hotplugs: BTreeMap::new(),
+        updated: status::unix_now(),
+        pending: None,
+        inhibitors: Vec::new(),
+        progress: None,
+        time_in_state: BTreeMap::new(),
+    };
+    status::write(&written).map_err(|e| e.to_string())?;
+    let read = status::read().map_err(|e| e.to_string())?;
+    ensure(read == written, || format!("read back {read:?}"))?;
+    ensure(!status::is_stale(&read), || "our own status counts as stale".to_string())?;
+    Ok(format!("written to and read from {}", status::path().display()))
+}
+
+fn check_diagram(config: &Config, _scratch: &Path) -> Result<String, String> {
+    let machine = diagram::build(config);
+    let dot = diagram::to_dot(&machine);
+    ensure(dot.contains("start smoke-helper, sunshine"), || "the Away box doesn't start the services".to_string())?;
+    Ok(format!("{} states, {} edges", machine.states.len(), machine.edges.len()))
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let components = vec![
+            Component { name: "config", result: Ok("parsed".to_string()) },
+            Component { name: "hooks", result: Err("the hook wrote \"\"".to_string()) },
+        ];
+        assert_eq!(
+            render(&components),
+            "PASS  config  parsed\nFAIL  hooks   the hook wrote \"\"\n\n1 of 2 components passed\n"
+        );
+    }
+}
@@ -0,0 +1,79 @@
+// src/startup.rs — Waiting for the compositor before the daemon starts
+//
+// Started at login, the daemon can be up before KWin is: kscreen-doctor
+// fails, the first apply_state has nothing to work with, and every poll
+// logs an error until the session catches up. So `run_daemon` first
+// waits for
+//
+//   1. the Wayland socket in $XDG_RUNTIME_DIR (`wayland_display`, or
+//      $WAYLAND_DISPLAY, or any `wayland-N`), before the session's
+//      variables are settled for good by `display::init`, and then
+//   2. the display backend listing at least one output,
+//
+// both within `startup_timeout` ("0s" doesn't wait). An X11 session has
+// no socket to wait for. Running out of time isn't fatal: the daemon
+// starts anyway, and polls as it would have before. systemd hears about
+// the wait through the status line and keeps getting watchdog pings.
+
+use std::env;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{debug, info, warn};
+
+use crate::config::Config;
+use crate::display::{self, BackendKind};
+use crate::duration;
+use crate::systemd;
+
+const CHECK_EVERY: Duration = Duration::from_millis(500);
+
+// When waiting has to stop.
+pub fn deadline(config: &Config) -> Instant {
+    Instant::now() + config.startup_timeout
+}
+
+// Step 1. Call before `display::init`.
+pub fn wait_for_socket(config: &Config, deadline: Instant) {
+    let x11 = config.display_backend == BackendKind::Xrandr || env::var("XDG_SESSION_TYPE").is_ok_and(|t| t == "x11");
+    if x11 || env::var_os("XDG_RUNTIME_DIR").is_none() {
+        return;
+    }
+    wait("the Wayland socket", config, deadline, || display::wayland_socket(config.wayland_display.as_deref()).is_some());
+}
+
+// Step 2. Call after `display::init`.
+pub fn wait_for_outputs(config: &Config, deadline: Instant) {
+    wait("the compositor to list outputs", config, deadline, || match display::backend().get_displays() {
+        Ok(displays) => !displays.is_empty(),
+        Err(e) => {
+            debug!("{e}");
+            false
+        }
+    });
+}
+
+fn wait(what: &str, config: &Config, deadline: Instant, mut ready: impl FnMut() -> bool) {
+    let started = Instant::now();
+    if ready() {
+        return;
+    }
+    if Instant::now() >= deadline {
+        debug!("Not waiting for {what}");
+        return;
+    }
+    info!("Waiting up to {} for {what}", duration::format(deadline.saturating_duration_since(started)));
+    systemd::status(&format!("Waiting for {what}"));
+    let keepalive = systemd::watchdog_interval().is_some();
+    while Instant::now() < deadline {
+        if keepalive {
+            systemd::watchdog_keepalive();
+        }
+        thread::sleep(CHECK_EVERY.min(deadline.saturating_duration_since(Instant::now())));
+        if ready() {
+            info!("Done waiting for {what} after {:.1}s", started.elapsed().as_secs_f64());
+            return;
+        }
+    }
+    warn!("Gave up waiting for {what} after {}, starting anyway", duration::format(config.startup_timeout));
+}
@@ -0,0 +1,185 @@
+// src/status.rs — Machine-readable status for bars and scripts
+//
+// The daemon rewrites `$XDG_RUNTIME_DIR/vitamink/status.json` after every
+// poll and transition; `vitamink status --json` prints it. A Waybar or
+// Polybar module can poll that command (or read the file) without talking
+// D-Bus. The file is replaced atomically, so readers never see half of it.
+//
+// A transition can take half a minute (DRM framebuffers, Sunshine
+// starting), so it's also rewritten before each of its steps: `progress`
+// says which one is running, so nothing looks hung meanwhile.
+//
+// During a grace period `pending` counts down from one poll to the next.
+// D-Bus (the `Countdown` signal) and control socket subscribers get the
+// same countdown as it changes, for a "Switching to Away in 7s" widget
+// with a cancel button.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::daemon::State;
+use crate::display::DpmsState;
+use crate::error::{Result, VitaminkError};
+use crate::inhibit::Held;
+use crate::persist;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Status {
+    pub state: State,
+    pub pid: u32,
+    pub main_display: String,
+    pub dpms: DpmsState,
+    pub sunshine_running: bool,
+    // Every `[[services]]` unit and whether it's active.
+    #[serde(default)]
+    pub services: BTreeMap<String, bool>,
+    pub profile: Option<String>,
+    // Unix seconds.
+    pub last_transition: Option<u64>,
+    pub last_error: Option<String>,
+    // Kernel-level connect/disconnect flips per connector since startup.
+    pub hotplugs: BTreeMap<String, u32>,
+    // When this was written, in Unix seconds.
+    pub updated: u64,
+    // A switch waiting out the grace period, if one is.
+    #[serde(default)]
+    pub pending: Option<Pending>,
+    // Inhibitors holding the state (see inhibit.rs).
+    #[serde(default)]
+    pub inhibitors: Vec<Held>,
+    // The transition under way, if one is.
+    #[serde(default)]
+    pub progress: Option<Progress>,
+    // Seconds spent in each state, over every run (see accounting.rs).
+    #[serde(default)]
+    pub time_in_state: BTreeMap<State, u64>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Progress {
+    pub to: State,
+    // The step running now, e.g. "enable the Away outputs".
+    pub step: String,
+    // Counting from 1.
+    pub number: usize,
+    pub total: usize,
+    // When the transition started, in Unix seconds.
+    pub started: u64,
+    // The steps finished so far.
+    pub done: Vec<StepTime>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct StepTime {
+    pub step: String,
+    pub ms: u64,
+}
+
+impl Progress {
+    // "enable the Away outputs (3/10), 4s in".
+    pub fn describe(&self, now: u64) -> String {
+        format!("{} ({}/{}), {}s in", self.step, self.number, self.total, now.saturating_sub(self.started))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Pending {
+    pub to: State,
+    // When the grace period ends, in Unix seconds.
+    pub due: u64,
+    // Seconds left of it as of `updated`, for readers that would rather
+    // not do the sum.
+    #[serde(default)]
+    pub remaining: u64,
+    // Due already, but someone is streaming (block_desk_while_streaming).
+    pub held_by_stream: bool,
+    // Due already, but the machine is busy (`[load]`): why.
+    #[serde(default)]
+    pub held_by_load: Option<String>,
+}
+
+// `$XDG_RUNTIME_DIR/vitamink/status.json`; /tmp only if there's no runtime
+// dir at all (not a login session).
+pub fn path() -> PathBuf {
+    let base = env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from).unwrap_or_else(env::temp_dir);
+    base.join("vitamink").join("status.json")
+}
+
+pub fn unix_now() -> u64 {
+    unix_secs(SystemTime::now())
+}
+
+pub fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+// Plain JSON (no checksum envelope) so bars can read it directly.
+pub fn write(status: &Status) -> Result<()> {
+    let json = serde_json::to_string_pretty(status).map_err(|e| VitaminkError::Parse(format!("status: {e}")))?;
+    persist::write_atomic(&path(), json.as_bytes())
+}
+
+// Rewrites just `progress` (and `updated`) in the file the last `write`
+// left, which is much quicker than asking every service again.
+pub fn write_progress(progress: Option<&Progress>) -> Result<()> {
+    let mut status = read()?;
+    status.progress = progress.cloned();
+    status.updated = unix_now();
+    write(&status)
+}
+
+pub fn read() -> Result<Status> {
+    let path = path();
+    let text = fs::read_to_string(&path).map_err(|e| VitaminkError::io(&path, e))?;
+    serde_json::from_str(&text).map_err(|e| VitaminkError::Parse(format!("{}: {e}", path.display())))
+}
+
+// A status file left behind by a daemon that's gone.
+pub fn is_stale(status: &Status) -> bool {
+    !Path::new(&format!("/proc/{}", status.pid)).exists()
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let status = Status {
+            state: State::Away,
+            pid: 42,
+            main_display: "DP-2".into(),
+            dpms: DpmsState::Off,
+            sunshine_running: true,
+            services: BTreeMap::from([("sunshine".to_string(), true), ("wayvnc".to_string(), false)]),
+            profile: None,
+            last_transition: Some(1_790_000_000),
+            last_error: Some("kscreen-doctor failed".into()),
+            hotplugs: BTreeMap::from([("HDMI-A-1".to_string(), 3)]),
+            updated: 1_790_000_005,
+            pending: Some(Pending { to: State::AtDesk, due: 1_790_000_010, remaining: 5, held_by_stream: false, held_by_load: None }),
+            inhibitors: vec![Held { name: "movie".into(), reason: "watching movie".into(), until: Some(1_790_007_200) }],
+            progress: Some(Progress {
+                to: State::Away,
+                step: "start services".into(),
+                number: 7,
+                total: 10,
+                started: 1_790_000_001,
+                done: vec![StepTime { step: "enable the Away outputs".into(), ms: 2100 }],
+            }),
+            time_in_state: BTreeMap::from([(State::AtDesk, 7_200), (State::Away, 50_400)]),
+        };
+
+        let json = serde_json::to_string(&status).unwrap();
+        assert!(json.contains("\"dpms\":\"off\""));
+        assert_eq!(serde_json::from_str::<Status>(&json).unwrap(), status);
+        assert_eq!(status.progress.unwrap().describe(1_790_000_005), "start services (7/10), 4s in");
+    }
+}
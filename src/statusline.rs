@@ -0,0 +1,234 @@
+// src/statusline.rs — `vitamink statusline`: one line for a status bar
+//
+// Prints the daemon's state as the JSON a bar module reads, from
+// status.json like `vitamink status --json`:
+//
+//   vitamink statusline --format waybar   {"text":"Away","alt":"away","tooltip":"...","class":["away","sunshine"]}
+//   vitamink statusline --format i3bar    {"name":"vitamink","full_text":"Away","short_text":"Away"}
+//
+// With `--follow` it keeps running and prints a new line whenever that
+// one would change (the state, a countdown, Sunshine starting), for a
+// Waybar custom module without an `interval`:
+//
+//   "custom/vitamink": {
+//       "exec": "vitamink statusline --format waybar --follow",
+//       "return-type": "json"
+//   }
+//
+// `alt` is the state in lowercase, for `format-icons`, and `class` becomes
+// CSS classes: the state, plus "sunshine" while it runs, "switching", "pending", "inhibited" and "error" as they apply, or "stopped"
+// when the daemon isn't running. The i3bar block is for i3status-rust's
+// custom blocks and i3blocks; it's marked urgent while there's an error.
+
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{Value, json};
+
+use crate::daemon::State;
+use crate::status::{self, Status};
+
+// How often `--follow` looks at status.json.
+const FOLLOW_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Format {
+    Waybar,
+    I3bar,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "waybar" => Ok(Format::Waybar),
+            "i3bar" | "i3status" => Ok(Format::I3bar),
+            other => Err(format!("unknown statusline format \"{other}\" (expected waybar or i3bar)")),
+        }
+    }
+}
+
+// Prints the line once, or with `follow` each time it changes, until
+// stdout goes away (the bar exiting).
+pub fn run(format: Format, follow: bool) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    let mut last = String::new();
+    loop {
+        let line = render(&current(), status::unix_now(), format);
+        if line != last {
+            writeln!(stdout, "{line}")?;
+            stdout.flush()?;
+            last = line;
+        }
+        if !follow {
+            return Ok(());
+        }
+        thread::sleep(FOLLOW_INTERVAL);
+    }
+}
+
+// The daemon's status, or why there's none.
+fn current() -> Result<Status, String> {
+    match status::read() {
+        Ok(s) if status::is_stale(&s) => Err(format!("not running (pid {} is gone)", s.pid)),
+        Ok(s) => Ok(s),
+        Err(e) => Err(format!("not running ({e})")),
+    }
+}
+
+pub fn render(status: &Result<Status, String>, now: u64, format: Format) -> String {
+    let line = match format {
+        Format::Waybar => waybar(status, now),
+        Format::I3bar => i3bar(status, now),
+    };
+    line.to_string()
+}
+
+fn waybar(status: &Result<Status, String>, now: u64) -> Value {
+    let Ok(s) = status else {
+        let why = status.as_ref().err().cloned().unwrap_or_default();
+        return json!({ "text": "VitaminK off", "alt": "stopped", "tooltip": format!("VitaminK: {why}"), "class": ["stopped"] });
+    };
+    let mut class = vec![state_class(s.state)];
+    for (applies, name) in [
+        (s.sunshine_running, "sunshine"),
+        (s.progress.is_some(), "switching"),
+        (s.pending.is_some(), "pending"),
+        (!s.inhibitors.is_empty(), "inhibited"),
+        (s.last_error.is_some(), "error"),
+    ] {
+        if applies {
+            class.push(name);
+        }
+    }
+    json!({ "text": text(s, now), "alt": state_class(s.state), "tooltip": tooltip(s, now), "class": class })
+}
+
+fn i3bar(status: &Result<Status, String>, now: u64) -> Value {
+    match status {
+        Ok(s) => json!({
+            "name": "vitamink",
+            "full_text": text(s, now),
+            "short_text": s.state.to_string(),
+            "urgent": s.last_error.is_some(),
+        }),
+        Err(_) => json!({ "name": "vitamink", "full_text": "VitaminK off", "short_text": "off" }),
+    }
+}
+
+// "Away", "AtDesk → Away 7s" while a switch is counting down, or
+// "Away 3/10…" while it's being made.
+fn text(s: &Status, now: u64) -> String {
+    if let Some(progress) = &s.progress {
+        return format!("{} {}/{}…", progress.to, progress.number, progress.total);
+    }
+    match &s.pending {
+        Some(pending) if pending.held_by_stream => format!("{} (streaming)", s.state),
+        Some(pending) => format!("{} → {} {}s", s.state, pending.to, pending.due.saturating_sub(now)),
+        None => s.state.to_string(),
+    }
+}
+
+fn tooltip(s: &Status, now: u64) -> String {
+    let mut lines = vec![format!("VitaminK: {}", s.state)];
+    if let Some(profile) = &s.profile {
+        lines.push(format!("Profile: {profile}"));
+    }
+    lines.push(format!("Sunshine: {}", if s.sunshine_running { "running" } else { "stopped" }));
+    if let Some(progress) = &s.progress {
+        lines.push(format!("Switching to {}: {}", progress.to, progress.describe(now)));
+    }
+    if let Some(pending) = &s.pending {
+        lines.push(if pending.held_by_stream {
+            format!("{} once the stream ends", pending.to)
+        } else {
+            format!("{} in {}s", pending.to, pending.due.saturating_sub(now))
+        });
+    }
+    for held in &s.inhibitors {
+        lines.push(format!("Inhibited by {}: {}", held.name, held.reason));
+    }
+    if let Some(error) = &s.last_error {
+        lines.push(format!("Error: {error}"));
+    }
+    lines.join("\n")
+}
+
+// Lowercase, for CSS and `format-icons`.
+fn state_class(state: State) -> &'static str {
+    match state {
+        State::AtDesk => "atdesk",
+        State::Away => "away",
+        State::HoldingPattern => "holdingpattern",
+        State::Standby => "standby",
+    }
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::display::DpmsState;
+    use crate::status::{Pending, Progress};
+
+    fn status() -> Status {
+        Status {
+            state: State::Away,
+            pid: 4242,
+            main_display: "DP-2".to_string(),
+            dpms: DpmsState::Off,
+            sunshine_running: true,
+            services: BTreeMap::from([("sunshine".to_string(), true)]),
+            profile: Some("tv".to_string()),
+            last_transition: None,
+            last_error: None,
+            hotplugs: BTreeMap::new(),
+            updated: 1000,
+            pending: None,
+            inhibitors: Vec::new(),
+            progress: None,
+            time_in_state: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_waybar() {
+        let line = render(&Ok(status()), 1000, Format::Waybar);
+        assert_eq!(
+            serde_json::from_str::<Value>(&line).unwrap(),
+            json!({ "text": "Away", "alt": "away", "tooltip": "VitaminK: Away\nProfile: tv\nSunshine: running", "class": ["away", "sunshine"] })
+        );
+        assert!(!line.contains('\n'));
+
+        let mut s = status();
+        s.pending = Some(Pending { to: State::AtDesk, due: 1007, remaining: 7, held_by_stream: false, held_by_load: None });
+        s.last_error = Some("boom".to_string());
+        let line: Value = serde_json::from_str(&render(&Ok(s), 1000, Format::Waybar)).unwrap();
+        assert_eq!(line["text"], "Away → AtDesk 7s");
+        assert_eq!(line["class"], json!(["away", "sunshine", "pending", "error"]));
+
+        let mut s = status();
+        s.progress = Some(Progress { to: State::Away, step: "start services".to_string(), number: 7, total: 10, started: 996, done: Vec::new() });
+        let line: Value = serde_json::from_str(&render(&Ok(s), 1000, Format::Waybar)).unwrap();
+        assert_eq!(line["text"], "Away 7/10…");
+        assert_eq!(line["class"], json!(["away", "sunshine", "switching"]));
+        assert!(line["tooltip"].as_str().unwrap().ends_with("Switching to Away: start services (7/10), 4s in"));
+
+        let line: Value = serde_json::from_str(&render(&Err("not running".to_string()), 1000, Format::Waybar)).unwrap();
+        assert_eq!(line["class"], json!(["stopped"]));
+    }
+
+    #[test]
+    fn test_i3bar() {
+        let line: Value = serde_json::from_str(&render(&Ok(status()), 1000, Format::I3bar)).unwrap();
+        assert_eq!(line, json!({ "name": "vitamink", "full_text": "Away", "short_text": "Away", "urgent": false }));
+        assert_eq!("i3status".parse(), Ok(Format::I3bar));
+        assert!("polybar".parse::<Format>().is_err());
+    }
+}
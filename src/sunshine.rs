@@ -1,33 +1,229 @@
-// src/sunshine.rs — Sunshine systemd service control
+// src/sunshine.rs — Sunshine-specific knowledge: is anyone streaming?
+//
+// Starting and stopping the unit is done by services.rs like any other;
+// the web API client lives in sunshine/api.rs, what the installed
+// version supports in sunshine/compat.rs, sunshine.conf templating in
+// sunshine/conf.rs, the hardware encoder check in sunshine/encoder.rs,
+// watching its journal for errors while Away in sunshine/journal.rs, and
+// launching one of its apps for Away in sunshine/apps.rs.
 
-use std::process::Command;
+#[cfg(feature = "sunshine-api")]
+pub mod api;
+pub mod apps;
+pub mod compat;
+pub mod conf;
+pub mod encoder;
+pub mod journal;
 
-pub fn start() -> Result<(), String> {
-    control("start")
+use std::env;
+use std::fs;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::ServiceConfig;
+use crate::display::{self, ModeTarget};
+use crate::error::{Result, VitaminkError};
+use crate::services;
+
+// Whether the Sunshine unit from `[[services]]` is active; false if none
+// is configured.
+pub fn is_running(services: &[ServiceConfig]) -> bool {
+    services::sunshine(services).is_some_and(services::is_active)
+}
+
+// ---- Readiness ----
+//
+// `systemctl start` returns long before Sunshine can take a stream, and a
+// client that connects before the Away outputs have a framebuffer gets a
+// black screen. Going Away isn't done until both are ready: each output's
+// DRM connector is active, then the unit is running and its web port
+// (from `api_url`) accepts connections — Sunshine opens it once it has
+// set up capture.
+
+// Waits for the stages in order within one overall `timeout`, calling
+// `report` as each begins. Returns how long it took.
+pub fn wait_until_ready(
+    outputs: &[String],
+    services: &[ServiceConfig],
+    api_url: &str,
+    timeout: Duration,
+    report: impl Fn(&str),
+) -> Result<Duration> {
+    let start = Instant::now();
+    let remaining = || timeout.saturating_sub(start.elapsed());
+
+    for name in outputs {
+        report(&format!("waiting for {name} DRM"));
+        display::wait_for_drm_active(name, remaining())?;
+    }
+
+    let address = api_address(api_url).ok_or_else(|| VitaminkError::Parse(format!("Invalid Sunshine api_url {api_url:?}")))?;
+    report(&format!("waiting for Sunshine on port {}", address.1));
+    while !remaining().is_zero() {
+        if is_running(services) && accepts(&address, remaining().min(Duration::from_secs(1))) {
+            return Ok(start.elapsed());
+        }
+        thread::sleep(remaining().min(Duration::from_millis(500)));
+    }
+    Err(VitaminkError::Timeout(format!("Sunshine wasn't ready after {}", crate::duration::format(timeout))))
+}
+
+fn accepts((host, port): &(String, u16), timeout: Duration) -> bool {
+    if timeout.is_zero() {
+        return false;
+    }
+    let Ok(addresses) = (host.as_str(), *port).to_socket_addrs() else {
+        return false;
+    };
+    addresses.into_iter().any(|address| TcpStream::connect_timeout(&address, timeout).is_ok())
+}
+
+// "https://localhost:47990/" → ("localhost", 47990). The port defaults
+// to Sunshine's 47990 rather than the scheme's.
+fn api_address(url: &str) -> Option<(String, u16)> {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split('/').next()?;
+    let (host, port) = match authority.strip_prefix('[') {
+        Some(v6) => v6.split_once(']').map(|(host, rest)| (host, rest.strip_prefix(':')))?,
+        None => authority.rsplit_once(':').map_or((authority, None), |(host, port)| (host, Some(port))),
+    };
+    let port = match port {
+        Some(port) => port.parse().ok()?,
+        None => 47990,
+    };
+    (!host.is_empty()).then(|| (host.to_string(), port))
 }
 
-pub fn stop() -> Result<(), String> {
-    control("stop")
+// ---- Client Mode ----
+//
+// Sunshine tells prep commands what the client asked for through
+// SUNSHINE_CLIENT_WIDTH, _HEIGHT and _FPS. With `match_client_mode` on,
+// a global prep command in sunshine.conf hands that to the daemon, which
+// switches the dummy plug to the closest mode — 120 Hz for the phone,
+// 60 Hz for the TV:
+//
+//   global_prep_cmd = [{"do":"vitamink ctl client-mode","undo":"vitamink ctl client-mode --reset"}]
+//
+// Each client that launches an app gets its own mode. The undo only runs
+// when the app is quit, so the daemon also switches back by itself once
+// `has_active_session` says the last client has disconnected.
+
+// What the connecting client asked for, from Sunshine's environment.
+pub fn client_mode() -> Option<ModeTarget> {
+    let var = |name| env::var(name).ok();
+    client_mode_from(var("SUNSHINE_CLIENT_WIDTH")?, var("SUNSHINE_CLIENT_HEIGHT")?, var("SUNSHINE_CLIENT_FPS"))
+}
+
+fn client_mode_from(width: String, height: String, fps: Option<String>) -> Option<ModeTarget> {
+    Some(ModeTarget {
+        width: width.trim().parse().ok()?,
+        height: height.trim().parse().ok()?,
+        refresh: fps.and_then(|fps| fps.trim().parse().ok()).filter(|&fps: &f64| fps > 0.0),
+    })
 }
 
-pub fn is_running() -> bool {
-    Command::new("systemctl")
-        .args(["--user", "is-active", "--quiet", "sunshine"])
-        .status()
-        .map(|s| s.success())
-        .unwrap_or(false)
+// ---- Session Detection ----
+
+// Whether a Moonlight client is connected right now.
+//
+// Sunshine has no "who's streaming" query, so we look for ESTABLISHED
+// sockets on its ports in /proc/net — the same thing `ss -tun` shows.
+// This is a heuristic: a client that just paired or is browsing the app
+// list briefly counts too, which errs on the side of not killing streams.
+pub fn has_active_session(ports: &[u16]) -> bool {
+    ["/proc/net/tcp", "/proc/net/tcp6", "/proc/net/udp", "/proc/net/udp6"]
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .any(|table| count_established(&table, ports) > 0)
 }
 
-fn control(action: &str) -> Result<(), String> {
-    let output = Command::new("systemctl")
-        .args(["--user", action, "sunshine"])
-        .output()
-        .map_err(|e| format!("Failed to run systemctl: {e}"))?;
+// Lines look like:
+//   sl  local_address rem_address   st tx_queue ...
+//    0: 0100007F:BB80 0200A8C0:D431 01 00000000:00000000 ...
+// Ports are hex; state 01 is ESTABLISHED (for UDP: a connected socket).
+fn count_established(table: &str, ports: &[u16]) -> usize {
+    table
+        .lines()
+        .skip(1)
+        .filter(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 4 || fields[3] != "01" {
+                return false;
+            }
+            fields[1]
+                .rsplit_once(':')
+                .and_then(|(_, port)| u16::from_str_radix(port, 16).ok())
+                .is_some_and(|port| ports.contains(&port))
+        })
+        .count()
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("systemctl {action} sunshine failed: {stderr}"));
+    use super::*;
+    use crate::config::ServiceTuning;
+    use crate::mock::FakeRunner;
+    use crate::process;
+    use crate::services::{Manager, Scope};
+
+    #[test]
+    fn test_is_running() {
+        let service = |unit: &str| ServiceConfig {
+            unit: unit.into(),
+            scope: Scope::User,
+            manager: Manager::Systemd,
+            command: None,
+            order: 0,
+            tuning: ServiceTuning::default(),
+        };
+        let fake = Rc::new(FakeRunner::new());
+        fake.respond("systemctl --user is-active --quiet sunshine", 0, "");
+        fake.respond("systemctl --user is-active --quiet sunshine-beta", 3, "");
+
+        process::with_runner(fake.clone(), || {
+            assert!(is_running(&[service("wayvnc"), service("sunshine")]));
+            assert!(!is_running(&[service("wayvnc")]));
+        });
+        assert_eq!(fake.calls(), ["systemctl --user is-active --quiet sunshine"]);
+    }
+
+    #[test]
+    fn test_api_address() {
+        assert_eq!(api_address("https://localhost:47990"), Some(("localhost".into(), 47990)));
+        assert_eq!(api_address("https://192.168.1.5:48990/api"), Some(("192.168.1.5".into(), 48990)));
+        assert_eq!(api_address("https://[::1]:47990"), Some(("::1".into(), 47990)));
+        assert_eq!(api_address("https://[::1]"), Some(("::1".into(), 47990)));
+        assert_eq!(api_address("https://sunshine.lan"), Some(("sunshine.lan".into(), 47990)));
+        assert_eq!(api_address("https://:47990"), None);
+        assert_eq!(api_address("https://localhost:http"), None);
     }
 
-    Ok(())
+    #[test]
+    fn test_client_mode_from() {
+        let mode = |w: &str, h: &str, fps: Option<&str>| client_mode_from(w.into(), h.into(), fps.map(Into::into));
+        assert_eq!(mode("2400", "1080", Some("120")).unwrap().to_string(), "2400x1080@120");
+        assert_eq!(mode("3840", "2160", None).unwrap().to_string(), "3840x2160");
+        assert_eq!(mode("1920", "1080", Some("0")).unwrap().to_string(), "1920x1080");
+        assert!(mode("", "1080", Some("60")).is_none());
+    }
+
+    #[test]
+    fn test_count_established() {
+        let table = "\
+  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000:BB80 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 1
+   1: 0100A8C0:BB80 0200A8C0:D431 01 00000000:00000000 00:00000000 00000000  1000        0 2
+   2: 0100A8C0:0016 0200A8C0:D432 01 00000000:00000000 00:00000000 00000000     0        0 3
+";
+        // 0xBB80 = 48000: one listener (0A) and one established connection
+        assert_eq!(count_established(table, &[48000]), 1);
+        // 0x0016 = 22 (ssh) is established but not a Sunshine port
+        assert_eq!(count_established(table, &[47989]), 0);
+        assert_eq!(count_established("", &[48000]), 0);
+    }
 }
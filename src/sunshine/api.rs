@@ -0,0 +1,169 @@
+// src/sunshine/api.rs — Sunshine's web API, for more than start/stop
+//
+// Sunshine serves its web UI and a JSON API on https://localhost:47990,
+// behind the username/password set in that UI. Through it we can list the
+// apps and paired clients, and close the running app — which ends the
+// stream cleanly and runs the app's "undo" commands, unlike stopping the
+// whole service.
+//
+// Requests go through curl. The certificate is Sunshine's own self-signed
+// one, so it isn't verified (the API is only reachable on localhost by
+// default), and the credentials are handed to curl on stdin rather than on
+// the command line, where any user could read them from `ps`.
+//
+// The API has no "who is streaming right now" query; has_active_session
+// in sunshine.rs still answers that.
+
+use std::process::Command;
+
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+
+use crate::config::SunshineConfig;
+use crate::error::{Result, VitaminkError};
+use crate::process;
+
+// An entry of Sunshine's apps.json; sunshine/apps.rs runs these commands.
+#[derive(Debug, Default, PartialEq, Deserialize)]
+pub struct App {
+    pub name: String,
+    #[serde(default)]
+    pub cmd: String,
+    #[serde(default)]
+    pub detached: Vec<String>,
+    #[serde(default, rename = "prep-cmd")]
+    pub prep: Vec<PrepCommand>,
+    #[serde(default, rename = "working-dir")]
+    pub working_dir: String,
+}
+
+// Run before the app, and undone after it.
+#[derive(Debug, Default, PartialEq, Deserialize)]
+pub struct PrepCommand {
+    #[serde(default, rename = "do")]
+    pub run: String,
+    #[serde(default)]
+    pub undo: String,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct PairedClient {
+    pub name: String,
+    pub uuid: String,
+}
+
+#[derive(Deserialize)]
+struct AppList {
+    #[serde(default)]
+    apps: Vec<App>,
+}
+
+#[derive(Deserialize)]
+struct ServerConfig {
+    #[serde(default)]
+    version: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ClientList {
+    #[serde(default)]
+    named_certs: Vec<PairedClient>,
+}
+
+pub struct Client {
+    base_url: String,
+    username: String,
+    password: String,
+}
+
+impl Client {
+    // None unless credentials are configured.
+    pub fn from_config(config: &SunshineConfig) -> Option<Self> {
+        Some(Self {
+            base_url: config.api_url.trim_end_matches('/').to_string(),
+            username: config.api_username.clone()?,
+            password: config.api_password.clone()?,
+        })
+    }
+
+    pub fn apps(&self) -> Result<Vec<App>> {
+        Ok(self.get::<AppList>("/api/apps")?.apps)
+    }
+
+    pub fn paired_clients(&self) -> Result<Vec<PairedClient>> {
+        Ok(self.get::<ClientList>("/api/clients/list")?.named_certs)
+    }
+
+    // The version string /api/config reports, if it has one.
+    pub fn version(&self) -> Result<Option<String>> {
+        Ok(self.get::<ServerConfig>("/api/config")?.version)
+    }
+
+    // Quits whatever app is being streamed. Fine to call when none is.
+    pub fn close_app(&self) -> Result<()> {
+        self.request("POST", "/api/apps/close", true).map(|_| ())
+    }
+
+    fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let body = self.request("GET", path, false)?;
+        serde_json::from_str(&body).map_err(|e| VitaminkError::Parse(format!("Unexpected Sunshine reply from {path}: {e}")))
+    }
+
+    // Changes go through process::run so `--dry-run` only logs them.
+    fn request(&self, method: &str, path: &str, changes_something: bool) -> Result<String> {
+        let url = format!("{}{path}", self.base_url);
+        let mut cmd = Command::new("curl");
+        cmd.args(["--silent", "--show-error", "--fail", "--insecure", "--max-time", "10", "--config", "-"])
+            .args(["--request", method, &url]);
+        if method == "POST" {
+            cmd.args(["--header", "Content-Type: application/json", "--data", "{}"]);
+        }
+
+        let input = curl_config(&self.username, &self.password);
+        let output = if changes_something {
+            process::run_with_input(&mut cmd, input.as_bytes())?
+        } else {
+            process::output_with_input(&mut cmd, input.as_bytes())?
+        };
+
+        if !output.status.success() {
+            return Err(VitaminkError::command_failed(format!("Sunshine API {method} {path}"), &output));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+}
+
+// A curl config file (read from stdin with `--config -`) holding the login.
+fn curl_config(username: &str, password: &str) -> String {
+    let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("user = \"{}:{}\"\n", escape(username), escape(password))
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_curl_config() {
+        assert_eq!(curl_config("admin", "p\"w\\d"), "user = \"admin:p\\\"w\\\\d\"\n");
+    }
+
+    #[test]
+    fn test_parse_replies() {
+        let apps: AppList = serde_json::from_str(
+            r#"{"env":{},"apps":[{"name":"Desktop","image-path":"desktop.png"},{"name":"Steam Big Picture","detached":["setsid steam steam://open/bigpicture"],"prep-cmd":[{"do":"","undo":"setsid steam steam://close/bigpicture"}]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(apps.apps[0], App { name: "Desktop".into(), ..App::default() });
+        assert_eq!(apps.apps[1].detached, ["setsid steam steam://open/bigpicture"]);
+        assert_eq!(apps.apps[1].prep, [PrepCommand { run: String::new(), undo: "setsid steam steam://close/bigpicture".into() }]);
+
+        let clients: ClientList = serde_json::from_str(r#"{"named_certs":[{"name":"phone","uuid":"ABC"}],"status":"true"}"#).unwrap();
+        assert_eq!(clients.named_certs, vec![PairedClient { name: "phone".into(), uuid: "ABC".into() }]);
+
+        let config: ServerConfig = serde_json::from_str(r#"{"platform":"linux","status":"true","version":"0.23.1"}"#).unwrap();
+        assert_eq!(config.version.as_deref(), Some("0.23.1"));
+    }
+}
@@ -0,0 +1,214 @@
+// src/sunshine/apps.rs — Launching one of Sunshine's apps for Away
+//
+// Only a Moonlight client can ask Sunshine to start an app, so a client
+// normally lands on the desktop and has to pick one. With
+//
+//   [apps]
+//   launch = "Steam Big Picture"   # as `vitamink sunshine apps` lists it
+//   close_on_desk = true
+//
+// going Away looks the app up through the web API (so `[sunshine]
+// api_username` and `api_password` are needed) and starts it the way
+// Sunshine would: the `do` of each prep command in order, the detached
+// commands left to run on their own, and the app's command as the
+// transient user unit `vitamink-app` (like gamescope.rs, so a daemon
+// restart while Away doesn't start it twice). Moonlight then connects
+// straight into it. At the desk the unit is stopped and the prep
+// commands' `undo` run in reverse — they're kept in state.json, so this
+// doesn't need Sunshine to still be running.
+
+use std::process::Command;
+
+#[cfg(feature = "sunshine-api")]
+use log::info;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{AppsConfig, SunshineConfig};
+use crate::error::{Result, VitaminkError};
+use crate::process;
+
+const UNIT: &str = "vitamink-app";
+
+// What closing the app takes.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Restore {
+    pub app: String,
+    // Prep commands' `undo`, in the order they're run.
+    undo: Vec<String>,
+    working_dir: Option<String>,
+}
+
+// Starts `[apps] launch`. None when nothing is to be launched, or the app
+// is to be left running at the desk.
+#[cfg(feature = "sunshine-api")]
+pub fn launch(config: &AppsConfig, sunshine: &SunshineConfig) -> Result<Option<Restore>> {
+    let Some(name) = &config.launch else {
+        return Ok(None);
+    };
+    let api = super::api::Client::from_config(sunshine)
+        .ok_or_else(|| VitaminkError::Unavailable("[apps] launch needs [sunshine] api_username and api_password".to_string()))?;
+    let app = api.apps()?.into_iter().find(|app| app.name == *name).ok_or_else(|| {
+        VitaminkError::Unavailable(format!("Sunshine has no app \"{name}\"; `vitamink sunshine apps` lists them"))
+    })?;
+    let restore = start(&app)?;
+    Ok(config.close_on_desk.then_some(restore))
+}
+
+#[cfg(not(feature = "sunshine-api"))]
+pub fn launch(config: &AppsConfig, _sunshine: &SunshineConfig) -> Result<Option<Restore>> {
+    match &config.launch {
+        Some(_) => Err(VitaminkError::Unavailable("[apps] launch needs a build with the sunshine-api feature".to_string())),
+        None => Ok(None),
+    }
+}
+
+// Prep commands first; if one fails, those before it are undone again.
+#[cfg(feature = "sunshine-api")]
+fn start(app: &super::api::App) -> Result<Restore> {
+    let working_dir = Some(app.working_dir.clone()).filter(|dir| !dir.is_empty());
+    let mut restore = Restore { app: app.name.clone(), undo: Vec::new(), working_dir };
+
+    for prep in &app.prep {
+        if !prep.run.trim().is_empty()
+            && let Err(e) = shell(&prep.run, restore.working_dir.as_deref())
+        {
+            undo(&restore);
+            return Err(e);
+        }
+        if !prep.undo.trim().is_empty() {
+            restore.undo.push(prep.undo.clone());
+        }
+    }
+    for detached in app.detached.iter().filter(|cmd| !cmd.trim().is_empty()) {
+        systemd_run(None, detached, restore.working_dir.as_deref())?;
+    }
+    if !app.cmd.trim().is_empty() {
+        if is_running()? {
+            info!("→ {} already running", app.name);
+        } else {
+            systemd_run(Some(UNIT), &app.cmd, restore.working_dir.as_deref())?;
+        }
+    }
+    Ok(restore)
+}
+
+// Stops the app's command and undoes its prep commands. A failing `undo`
+// is only logged, so the ones after it still run.
+pub fn close(restore: &Restore) -> Result<()> {
+    let output = process::run(Command::new("systemctl").args(["--user", "stop", UNIT]))?;
+    // 5: the unit isn't loaded, i.e. already gone (or never started).
+    if !output.status.success() && output.status.code() != Some(5) {
+        return Err(VitaminkError::command_failed(format!("systemctl --user stop {UNIT}"), &output));
+    }
+    undo(restore);
+    Ok(())
+}
+
+fn undo(restore: &Restore) {
+    for cmd in restore.undo.iter().rev() {
+        if let Err(e) = shell(cmd, restore.working_dir.as_deref()) {
+            warn!("{}: undoing a prep command failed: {e}", restore.app);
+        }
+    }
+}
+
+// Changes: only logged in dry-run mode.
+fn shell(cmd: &str, working_dir: Option<&str>) -> Result<()> {
+    let mut sh = Command::new("sh");
+    sh.args(["-c", cmd]);
+    if let Some(dir) = working_dir {
+        sh.current_dir(dir);
+    }
+    let output = process::run(&mut sh)?;
+    if !output.status.success() {
+        return Err(VitaminkError::command_failed(cmd, &output));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "sunshine-api")]
+fn systemd_run(unit: Option<&str>, cmd: &str, working_dir: Option<&str>) -> Result<()> {
+    let mut run = Command::new("systemd-run");
+    run.args(["--user", "--collect"]);
+    if let Some(unit) = unit {
+        run.args(["--unit", unit]);
+    }
+    if let Some(dir) = working_dir {
+        run.arg(format!("--working-directory={dir}"));
+    }
+    let output = process::run(run.args(["sh", "-c", cmd]))?;
+    if !output.status.success() {
+        return Err(VitaminkError::command_failed(format!("systemd-run {cmd}"), &output));
+    }
+    Ok(())
+}
+
+// Queries: always run, even in dry-run mode.
+#[cfg(feature = "sunshine-api")]
+fn is_running() -> Result<bool> {
+    let output = process::output(Command::new("systemctl").args(["--user", "is-active", "--quiet", UNIT]))?;
+    Ok(output.status.success())
+}
+
+// ---- Tests ----
+
+#[cfg(all(test, feature = "sunshine-api"))]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::mock::FakeRunner;
+    use crate::sunshine::api::{App, PrepCommand};
+
+    fn big_picture() -> App {
+        App {
+            name: "Steam Big Picture".into(),
+            cmd: "steam -gamepadui".into(),
+            detached: vec!["notify-send streaming".into()],
+            prep: vec![
+                PrepCommand { run: "pactl set-default-sink hdmi".into(), undo: "pactl set-default-sink desk".into() },
+                PrepCommand { run: String::new(), undo: "steam steam://close/bigpicture".into() },
+            ],
+            working_dir: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_start_and_close() {
+        let fake = Rc::new(FakeRunner::new());
+        fake.respond("", 0, "");
+        fake.respond("systemctl --user is-active", 3, "");
+        let restore = process::with_runner(fake.clone(), || start(&big_picture())).unwrap();
+        assert_eq!(
+            fake.calls(),
+            [
+                "sh -c pactl set-default-sink hdmi",
+                "systemd-run --user --collect sh -c notify-send streaming",
+                "systemctl --user is-active --quiet vitamink-app",
+                "systemd-run --user --collect --unit vitamink-app sh -c steam -gamepadui",
+            ]
+        );
+
+        let fake = Rc::new(FakeRunner::new());
+        fake.respond("", 0, "");
+        process::with_runner(fake.clone(), || close(&restore)).unwrap();
+        assert_eq!(
+            fake.calls(),
+            ["systemctl --user stop vitamink-app", "sh -c steam steam://close/bigpicture", "sh -c pactl set-default-sink desk"]
+        );
+    }
+
+    #[test]
+    fn test_failed_prep_is_undone() {
+        let mut app = big_picture();
+        app.prep.push(PrepCommand { run: "false".into(), undo: "never".into() });
+        let fake = Rc::new(FakeRunner::new());
+        fake.respond("", 0, "");
+        fake.respond("sh -c false", 1, "");
+        assert!(process::with_runner(fake.clone(), || start(&app)).is_err());
+        let calls = fake.calls();
+        assert_eq!(calls.last().unwrap(), "sh -c pactl set-default-sink desk");
+        assert!(!calls.iter().any(|c| c.contains("systemd-run") || c.contains("never")));
+    }
+}
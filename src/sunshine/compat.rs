@@ -0,0 +1,153 @@
+// src/sunshine/compat.rs — Which Sunshine is installed, and what it can do
+//
+// Some of what VitaminK does with Sunshine only works from a certain
+// release on. At startup `probe` asks `sunshine --version` (or, failing
+// that, the web API's /api/config) and warns once about every configured
+// feature the installed version is too old for; those features are then
+// skipped instead of failing halfway through a transition.
+//
+// If the version can't be found out at all, everything is attempted and
+// the calls fail the ordinary way.
+
+use std::fmt;
+use std::process::Command;
+use std::sync::OnceLock;
+
+use log::{debug, info, warn};
+
+use crate::config::SunshineConfig;
+use crate::process;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub struct Version(u32, u32, u32);
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Feature {
+    // POST /api/apps/close before stopping the service.
+    CloseApp,
+    // GET /api/clients/list.
+    ClientList,
+    // SUNSHINE_CLIENT_* in prep commands, for `match_client_mode`.
+    ClientMode,
+}
+
+impl Feature {
+    // The first release known to have it.
+    fn minimum(self) -> Version {
+        match self {
+            Feature::CloseApp => Version(0, 21, 0),
+            Feature::ClientList => Version(0, 23, 0),
+            Feature::ClientMode => Version(0, 20, 0),
+        }
+    }
+
+    // What goes missing without it.
+    fn fallback(self) -> &'static str {
+        match self {
+            Feature::CloseApp => "Sunshine will be stopped without closing the app first",
+            Feature::ClientList => "`vitamink sunshine clients` won't work",
+            Feature::ClientMode => "match_client_mode will keep the configured mode",
+        }
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Feature::CloseApp => write!(f, "closing the app through the API"),
+            Feature::ClientList => write!(f, "listing paired clients"),
+            Feature::ClientMode => write!(f, "match_client_mode"),
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
+
+// None = not probed, or the version couldn't be found out.
+static VERSION: OnceLock<Option<Version>> = OnceLock::new();
+
+// Finds out the installed version and warns about the configured features
+// it's too old for. Call once, at startup.
+pub fn probe(config: &SunshineConfig) {
+    let version = from_binary().or_else(|| from_api(config));
+    match version {
+        Some(v) => info!("Sunshine {v}"),
+        None => debug!("Couldn't tell which Sunshine version is installed"),
+    }
+    // Only the first probe's sticks; that's the one `supports` answers from.
+    let _ = VERSION.set(version);
+    let version = VERSION.get().copied().flatten();
+
+    let mut in_use = Vec::new();
+    if config.api_username.is_some() && cfg!(feature = "sunshine-api") {
+        in_use.push(Feature::CloseApp);
+    }
+    if config.match_client_mode {
+        in_use.push(Feature::ClientMode);
+    }
+    for feature in in_use.into_iter().filter(|&f| !supports(f)) {
+        warn!(
+            "Sunshine {} is older than {}, which {feature} needs — {}",
+            version.map_or("?".to_string(), |v| v.to_string()),
+            feature.minimum(),
+            feature.fallback()
+        );
+    }
+}
+
+// Whether the installed Sunshine has `feature`; true if unknown.
+pub fn supports(feature: Feature) -> bool {
+    VERSION.get().copied().flatten().is_none_or(|v| v >= feature.minimum())
+}
+
+fn from_binary() -> Option<Version> {
+    let output = process::output(Command::new("sunshine").arg("--version")).ok()?;
+    parse_version(&String::from_utf8_lossy(&output.stdout))
+}
+
+#[cfg(feature = "sunshine-api")]
+fn from_api(config: &SunshineConfig) -> Option<Version> {
+    let version = super::api::Client::from_config(config)?.version().ok()??;
+    parse_version(&version)
+}
+
+#[cfg(not(feature = "sunshine-api"))]
+fn from_api(_config: &SunshineConfig) -> Option<Version> {
+    None
+}
+
+// The first "[v]X.Y[.Z]" in `text`, e.g. from "Sunshine version: v0.23.1".
+// Newer releases number by date ("2025.628.4510"), which orders after all
+// of the 0.x ones.
+fn parse_version(text: &str) -> Option<Version> {
+    text.split(|c: char| c.is_whitespace() || c == ':').find_map(|word| {
+        let word = word.strip_prefix('v').unwrap_or(word);
+        let mut parts = word.split(['.', '-']).map(|p| p.parse::<u32>());
+        let (major, minor) = (parts.next()?.ok()?, parts.next()?.ok()?);
+        let patch = parts.next().and_then(|p| p.ok()).unwrap_or(0);
+        Some(Version(major, minor, patch))
+    })
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("Sunshine version: v0.23.1\n"), Some(Version(0, 23, 1)));
+        assert_eq!(parse_version("0.20.0"), Some(Version(0, 20, 0)));
+        assert_eq!(parse_version("v2025.628.4510"), Some(Version(2025, 628, 4510)));
+        assert_eq!(parse_version("0.22.2-dirty"), Some(Version(0, 22, 2)));
+        assert_eq!(parse_version("Sunshine (unknown build)"), None);
+
+        assert!(Version(2025, 628, 4510) >= Feature::ClientList.minimum());
+        assert!(Version(0, 20, 1) < Feature::CloseApp.minimum());
+    }
+}
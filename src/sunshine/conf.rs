@@ -0,0 +1,126 @@
+// src/sunshine/conf.rs — sunshine.conf rendered from a template while Away
+//
+// Sunshine takes the resolution and frame rate it advertises to clients
+// from sunshine.conf, not from the output it captures, so a profile with a
+// different mode needs a different config. With a template:
+//
+//   [sunshine]
+//   config_template = "~/.config/vitamink/sunshine.conf.in"
+//
+//   [profiles.tv]
+//   enable = [{ name = "HDMI-A-1", mode = "1920x1080@60" }]
+//   sunshine_template = "~/.config/vitamink/sunshine-tv.conf.in"
+//
+// going Away writes the rendered template over `config_file` before
+// Sunshine starts, with "{width}", "{height}" and "{fps}" filled in from
+// the dummy plug's mode. Switching profiles while Away renders it again
+// and restarts Sunshine. What the file held before is kept in state.json
+// and put back on AtDesk.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::display::ModeTarget;
+use crate::error::{Result, VitaminkError};
+
+// What AtDesk puts back.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Restore {
+    pub file: PathBuf,
+    // None if there was no file: AtDesk removes ours.
+    pub original: Option<String>,
+}
+
+// "~/..." is relative to $HOME.
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+// "{width}", "{height}" and "{fps}" (60 when the mode has no refresh rate).
+pub fn render(template: &str, mode: &ModeTarget) -> String {
+    template
+        .replace("{width}", &mode.width.to_string())
+        .replace("{height}", &mode.height.to_string())
+        .replace("{fps}", &mode.refresh.unwrap_or(60.0).round().to_string())
+}
+
+// Renders `template` over `file`. `restore` is what an earlier call
+// returned, if any: rendering again keeps the original from before the
+// first one.
+pub fn apply(template: &str, file: &str, mode: &ModeTarget, restore: Option<Restore>) -> Result<Restore> {
+    let template_path = expand_home(template);
+    let text = fs::read_to_string(&template_path).map_err(|e| VitaminkError::io(&template_path, e))?;
+
+    let file = expand_home(file);
+    let restore = match restore {
+        Some(restore) => restore,
+        None => Restore { original: read_optional(&file)?, file: file.clone() },
+    };
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir).map_err(|e| VitaminkError::io(dir, e))?;
+    }
+    fs::write(&file, render(&text, mode)).map_err(|e| VitaminkError::io(&file, e))?;
+    Ok(restore)
+}
+
+pub fn restore(restore: &Restore) -> Result<()> {
+    match &restore.original {
+        Some(text) => fs::write(&restore.file, text).map_err(|e| VitaminkError::io(&restore.file, e)),
+        None => match fs::remove_file(&restore.file) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(VitaminkError::io(&restore.file, e)),
+            _ => Ok(()),
+        },
+    }
+}
+
+fn read_optional(path: &PathBuf) -> Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(Some(text)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(VitaminkError::io(path, e)),
+    }
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mode = ModeTarget { width: 1920, height: 1080, refresh: Some(59.94) };
+        assert_eq!(render("resolutions = [{width}x{height}]\nfps = [{fps}]\n", &mode), "resolutions = [1920x1080]\nfps = [60]\n");
+    }
+
+    #[test]
+    fn test_apply_and_restore() {
+        let dir = env::temp_dir().join(format!("vitamink-conf-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let template = dir.join("sunshine.conf.in");
+        let file = dir.join("sunshine.conf");
+        fs::write(&template, "fps = [{fps}]\n").unwrap();
+        fs::write(&file, "fps = [120]\n").unwrap();
+        let (template, file_str) = (template.to_str().unwrap(), file.to_str().unwrap());
+
+        let mode = ModeTarget { width: 1920, height: 1080, refresh: Some(60.0) };
+        let first = apply(template, file_str, &mode, None).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "fps = [60]\n");
+
+        // A second render (a profile switch) still restores the original.
+        let mode = ModeTarget { width: 3840, height: 2160, refresh: Some(30.0) };
+        let second = apply(template, file_str, &mode, Some(first)).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "fps = [30]\n");
+        restore(&second).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "fps = [120]\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,201 @@
+// src/sunshine/encoder.rs — Is there a hardware encoder left for Sunshine?
+//
+// GeForce cards allow only a few NVENC sessions at once. With OBS or a
+// browser holding them all, Sunshine still starts, but every stream
+// fails without saying why on the client. Before starting Sunshine for
+// Away, `check` looks at the GPUs the kernel lists:
+//
+//   - NVIDIA: `nvidia-smi --query-gpu=encoder.stats.sessionCount` against
+//     `[sunshine] nvenc_sessions`, the driver's limit.
+//   - anything else: `vainfo` has to list an encode entrypoint
+//     (VAEntrypointEncSlice or EncSliceLP) for some profile.
+//
+// `encoder_check` decides what a missing encoder does: "warn" (the
+// default) logs and notifies, then goes Away anyway; "abort" fails the
+// Away transition, so it's rolled back; "off" doesn't look. A check
+// that can't run (no nvidia-smi, no vainfo) is skipped quietly — it's
+// there to explain a failure, not to cause one.
+//
+// On a machine with two GPUs, `check_adapter` also compares the one
+// sunshine.conf's `adapter_name` picks for encoding with the one driving
+// the output Sunshine captures (see drm.rs). Apart, every frame is copied
+// between them, if capture works at all.
+
+use std::fs;
+use std::process::Command;
+
+use log::debug;
+use serde::Deserialize;
+
+use crate::drm::{self, Gpu};
+use crate::process;
+use crate::sunshine::conf;
+
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EncoderCheck {
+    Off,
+    #[default]
+    Warn,
+    Abort,
+}
+
+// Ok with what was found, or Err with why Sunshine won't be able to
+// encode. None if nothing could be checked.
+pub fn check(nvenc_sessions: u32) -> Option<Result<String, String>> {
+    let cards = drm::cards();
+    if cards.iter().any(|card| card.driver == "nvidia")
+        && let Some(result) = nvenc(nvenc_sessions)
+    {
+        return Some(result);
+    }
+    if cards.iter().any(|card| card.driver != "nvidia") {
+        return vaapi();
+    }
+    None
+}
+
+fn nvenc(limit: u32) -> Option<Result<String, String>> {
+    let output = process::output(Command::new("nvidia-smi").args([
+        "--query-gpu=index,name,encoder.stats.sessionCount",
+        "--format=csv,noheader,nounits",
+    ]))
+    .inspect_err(|e| debug!("Encoder check: {e}"))
+    .ok()?;
+    if !output.status.success() {
+        debug!("Encoder check: nvidia-smi failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+        return None;
+    }
+    nvenc_free(&String::from_utf8_lossy(&output.stdout), limit)
+}
+
+// From nvidia-smi's CSV: fine if any GPU has a session to spare.
+fn nvenc_free(csv: &str, limit: u32) -> Option<Result<String, String>> {
+    let gpus: Vec<(String, u32)> = csv
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            match fields[..] {
+                [index, name, sessions] => Some((format!("GPU {index} ({name})"), sessions.parse().ok()?)),
+                _ => None,
+            }
+        })
+        .collect();
+    if gpus.is_empty() {
+        return None;
+    }
+    let described: Vec<String> = gpus.iter().map(|(gpu, sessions)| format!("{gpu}: {sessions}/{limit} NVENC sessions")).collect();
+    Some(if gpus.iter().any(|&(_, sessions)| sessions < limit) {
+        Ok(described.join(", "))
+    } else {
+        Err(format!("no NVENC session free ({}) — close whatever else is encoding (OBS, a browser, another Sunshine)", described.join(", ")))
+    })
+}
+
+fn vaapi() -> Option<Result<String, String>> {
+    let output = process::output(&mut Command::new("vainfo")).inspect_err(|e| debug!("Encoder check: {e}")).ok()?;
+    if !output.status.success() {
+        debug!("Encoder check: vainfo failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+        return None;
+    }
+    Some(vaapi_encoders(&String::from_utf8_lossy(&output.stdout)))
+}
+
+// The profiles `vainfo` lists with an encode entrypoint.
+fn vaapi_encoders(text: &str) -> Result<String, String> {
+    let mut profiles: Vec<&str> = text
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .filter(|(_, entrypoint)| entrypoint.trim().starts_with("VAEntrypointEncSlice"))
+        .map(|(profile, _)| profile.trim().trim_start_matches("VAProfile"))
+        .collect();
+    // Listed once per entrypoint, EncSlice and EncSliceLP.
+    profiles.dedup();
+    if profiles.is_empty() {
+        Err("VA-API lists no hardware encoder — check the driver (mesa-va-drivers, intel-media-driver)".to_string())
+    } else {
+        Ok(format!("VA-API encodes {}", profiles.join(", ")))
+    }
+}
+
+// ---- Capture Adapter ----
+
+// Ok with the GPU, or Err with the mismatch. None without an
+// `adapter_name` in `config_file`, or if either GPU can't be found.
+pub fn check_adapter(config_file: &str, output: &str) -> Option<Result<String, String>> {
+    let text = fs::read_to_string(conf::expand_home(config_file)).ok()?;
+    let adapter = setting(&text, "adapter_name")?;
+    Some(same_gpu(&adapter, &drm::gpu_for_node(&adapter)?, output, &drm::gpu_of(output)?))
+}
+
+fn same_gpu(adapter: &str, encoding: &Gpu, output: &str, driving: &Gpu) -> Result<String, String> {
+    if encoding.card.name == driving.card.name {
+        return Ok(format!("{adapter} is {driving}, which drives {output}"));
+    }
+    let fix = driving.render_node.as_ref().map_or_else(String::new, |node| format!(" — set adapter_name = /dev/dri/{node} in sunshine.conf"));
+    Err(format!("Sunshine encodes on {encoding} ({adapter}), but {output} is on {driving}{fix}"))
+}
+
+// A `key = value` line of sunshine.conf.
+fn setting(text: &str, key: &str) -> Option<String> {
+    text.lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .find(|(k, _)| k.trim() == key)
+        .map(|(_, value)| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nvenc_free() {
+        assert_eq!(nvenc_free("0, NVIDIA GeForce RTX 3080, 2\n", 8), Some(Ok("GPU 0 (NVIDIA GeForce RTX 3080): 2/8 NVENC sessions".to_string())));
+        let busy = nvenc_free("0, NVIDIA GeForce RTX 3080, 8\n", 8).unwrap().unwrap_err();
+        assert!(busy.starts_with("no NVENC session free (GPU 0 (NVIDIA GeForce RTX 3080): 8/8"), "{busy}");
+        // One of two GPUs is enough.
+        assert!(nvenc_free("0, A, 3\n1, B, 1\n", 3).unwrap().is_ok());
+        // "[N/A]" and the like aren't a reading.
+        assert_eq!(nvenc_free("0, NVIDIA GeForce RTX 3080, [N/A]\n", 8), None);
+    }
+
+    #[test]
+    fn test_vaapi_encoders() {
+        let vainfo = "\
+vainfo: VA-API version: 1.20 (libva 2.20.0)
+vainfo: Driver version: Mesa Gallium driver 24.0.5 for AMD Radeon RX 6800 XT
+vainfo: Supported profile and entrypoints
+      VAProfileH264Main               :	VAEntrypointVLD
+      VAProfileH264Main               :	VAEntrypointEncSlice
+      VAProfileHEVCMain               :	VAEntrypointEncSlice
+      VAProfileHEVCMain               :	VAEntrypointEncSliceLP
+      VAProfileAV1Profile0            :	VAEntrypointVLD";
+        assert_eq!(vaapi_encoders(vainfo), Ok("VA-API encodes H264Main, HEVCMain".to_string()));
+        assert!(vaapi_encoders(&vainfo.replace("EncSlice", "VLD")).is_err());
+    }
+
+    #[test]
+    fn test_same_gpu() {
+        let text = "# adapter_name = /dev/dri/renderD128\nadapter_name = /dev/dri/renderD129\noutput_name = 1\n";
+        assert_eq!(setting(text, "adapter_name").as_deref(), Some("/dev/dri/renderD129"));
+        assert_eq!(setting("adapter_name =\n", "adapter_name"), None);
+
+        let gpu = |card: &str, driver: &str, node: &str| Gpu {
+            card: drm::Card { name: card.into(), driver: driver.into(), inode: 0 },
+            device: String::new(),
+            render_node: Some(node.into()),
+            connectors: Vec::new(),
+        };
+        let intel = gpu("card0", "i915", "renderD128");
+        let nvidia = gpu("card1", "nvidia", "renderD129");
+        assert_eq!(same_gpu("/dev/dri/renderD129", &nvidia, "HDMI-A-1", &nvidia), Ok("/dev/dri/renderD129 is card1 (nvidia), which drives HDMI-A-1".to_string()));
+        assert_eq!(
+            same_gpu("/dev/dri/renderD128", &intel, "HDMI-A-1", &nvidia),
+            Err("Sunshine encodes on card0 (i915) (/dev/dri/renderD128), but HDMI-A-1 is on card1 (nvidia) — set adapter_name = /dev/dri/renderD129 in sunshine.conf".to_string())
+        );
+    }
+}
@@ -0,0 +1,129 @@
+// src/sunshine/journal.rs — Noticing Sunshine fail mid-stream
+//
+// The unit staying active doesn't mean Sunshine can still stream: an
+// encoder that dies under it, or a capture that loses the dummy plug's
+// framebuffer, leaves it running and logging errors while every client
+// gets a black screen. While Away, `Watcher` follows the unit's journal
+// (`journalctl -u sunshine -f`, like `mosquitto_sub` in mqtt.rs, one
+// long-lived child) and hands each line matching `[sunshine]
+// error_patterns` to the daemon, which does what `on_error` says:
+//
+//   - "notify" (the default): an error notification and webhook post
+//   - "restart": that, and restart the Sunshine unit
+//   - "reapply": that, and run the Away steps again (outputs, then
+//     services), as after a GPU reset
+//   - "off": don't follow the journal at all
+//
+// Only a systemd-managed Sunshine has a journal to follow.
+
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command as Process, Stdio};
+use std::sync::mpsc::Sender;
+use std::thread::{self, JoinHandle};
+
+use log::{debug, warn};
+use serde::Deserialize;
+
+use crate::config::ServiceConfig;
+use crate::daemon::Command;
+use crate::environment;
+use crate::error::{Result, VitaminkError};
+use crate::services::{Manager, Scope};
+
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnError {
+    Off,
+    #[default]
+    Notify,
+    Restart,
+    Reapply,
+}
+
+// What Sunshine logs when it won't be streaming anything.
+pub const DEFAULT_PATTERNS: &[&str] = &[
+    "Fatal:",
+    "Couldn't find any working encoder",
+    "Failed to initialize video capture/encoding",
+    "Unable to find display or encoder",
+];
+
+// The journalctl child and the thread reading it. Dropping it stops both.
+pub struct Watcher {
+    journalctl: Child,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watcher {
+    // Follows `service`'s journal from `since` (Unix time) on, so lines
+    // logged while going Away aren't missed.
+    pub fn start(service: &ServiceConfig, patterns: &[String], since: u64, commands: Sender<Command>) -> Result<Self> {
+        if service.manager != Manager::Systemd {
+            return Err(VitaminkError::Unavailable(format!("{} isn't run by systemd, so it has no journal", service.unit)));
+        }
+        let mut journalctl = Process::new("journalctl");
+        if service.scope == Scope::User {
+            journalctl.arg("--user");
+        }
+        journalctl.args(["--unit", &service.unit, "--follow", "--output", "cat", "--since", &format!("@{since}")]);
+        environment::apply(&mut journalctl);
+        let mut journalctl = journalctl
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| VitaminkError::spawn("journalctl", e))?;
+        let stdout = journalctl.stdout.take().expect("stdout is piped");
+
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_lowercase()).collect();
+        let thread = thread::Builder::new()
+            .name("sunshine-log".into())
+            .spawn(move || {
+                for line in BufReader::new(stdout).lines().map_while(|line| line.ok()) {
+                    if is_fatal(&line, &patterns) {
+                        let _ = commands.send(Command::SunshineError(line.trim().to_string()));
+                    }
+                }
+                debug!("Sunshine log: journalctl ended");
+            })
+            .map_err(|e| VitaminkError::io("sunshine-log thread", e))?;
+        debug!("Sunshine log: following {}", service.unit);
+        Ok(Self { journalctl, thread: Some(thread) })
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        if let Err(e) = self.journalctl.kill() {
+            warn!("Sunshine log: couldn't stop journalctl: {e}");
+        }
+        let _ = self.journalctl.wait();
+        // Its stdout is closed now, so the thread is about to end.
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// `patterns` are lowercase already; matching ignores case.
+fn is_fatal(line: &str, patterns: &[String]) -> bool {
+    let line = line.to_lowercase();
+    patterns.iter().any(|pattern| line.contains(pattern.as_str()))
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_fatal() {
+        let patterns: Vec<String> = DEFAULT_PATTERNS.iter().map(|p| p.to_lowercase()).collect();
+        assert!(is_fatal("[2024:06:01:21:14:03]: Fatal: Unable to find display or encoder during startup.", &patterns));
+        assert!(is_fatal("[2024:06:01:21:14:03]: Error: Couldn't find any working encoder", &patterns));
+        assert!(!is_fatal("[2024:06:01:21:14:03]: Info: Found H.264 encoder: h264_nvenc [nvenc]", &patterns));
+        // A display name or client that merely contains the word doesn't count.
+        assert!(!is_fatal("[2024:06:01:21:14:03]: Info: New streaming session started [Fatality]", &patterns));
+    }
+}
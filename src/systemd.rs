@@ -0,0 +1,101 @@
+// src/systemd.rs — sd_notify: readiness, watchdog keepalives, status text
+//
+// When the user unit has `Type=notify`, systemd waits for `READY=1` before
+// considering the service started, shows our `STATUS=` line in
+// `systemctl --user status vitamink`, and — with `WatchdogSec=` — restarts
+// us if `WATCHDOG=1` stops arriving. A matching unit:
+//
+//   [Service]
+//   Type=notify
+//   ExecStart=%h/.cargo/bin/vitamink daemon
+//   ExecReload=kill -HUP $MAINPID
+//   WatchdogSec=30
+//   Restart=on-failure
+//
+// The protocol is one datagram per message to the socket named in
+// $NOTIFY_SOCKET, so there's no need for libsystemd. Outside systemd the
+// variable is unset and every call here does nothing.
+//
+// New Rust concepts in this file:
+//
+// - `UnixDatagram`: a connectionless Unix socket. `send_to_addr` delivers
+//   one message without any handshake.
+//
+// - Abstract socket names: a leading '@' in $NOTIFY_SOCKET means a socket
+//   with no file on disk (Linux-only, hence `std::os::linux`).
+
+use std::env;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::Duration;
+
+use log::warn;
+
+// Sends e.g. "READY=1" or "STATUS=Away". Failures are logged, never fatal:
+// a lost status update shouldn't take the daemon down.
+pub fn notify(message: &str) {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let path = path.to_string_lossy();
+
+    let addr = match path.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name.as_bytes()),
+        None => SocketAddr::from_pathname(path.as_ref()),
+    };
+
+    let sent = addr.and_then(|addr| UnixDatagram::unbound()?.send_to_addr(message.as_bytes(), &addr));
+    if let Err(e) = sent {
+        warn!("sd_notify to {path} failed: {e}");
+    }
+}
+
+pub fn ready() {
+    notify("READY=1");
+}
+
+pub fn status(text: &str) {
+    notify(&format!("STATUS={text}"));
+}
+
+pub fn watchdog_keepalive() {
+    notify("WATCHDOG=1");
+}
+
+// How often to send WATCHDOG=1: half of `WatchdogSec`, as sd_watchdog_enabled(3)
+// recommends. None when the unit has no watchdog or it's meant for another pid.
+pub fn watchdog_interval() -> Option<Duration> {
+    watchdog_interval_from(
+        env::var("WATCHDOG_USEC").ok().as_deref(),
+        env::var("WATCHDOG_PID").ok().as_deref(),
+        std::process::id(),
+    )
+}
+
+fn watchdog_interval_from(usec: Option<&str>, pid: Option<&str>, own_pid: u32) -> Option<Duration> {
+    if let Some(pid) = pid
+        && pid.parse::<u32>().ok() != Some(own_pid)
+    {
+        return None;
+    }
+
+    let usec: u64 = usec?.parse().ok().filter(|&us| us > 0)?;
+    Some(Duration::from_micros(usec) / 2)
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watchdog_interval() {
+        assert_eq!(watchdog_interval_from(Some("30000000"), None, 42), Some(Duration::from_secs(15)));
+        assert_eq!(watchdog_interval_from(Some("30000000"), Some("42"), 42), Some(Duration::from_secs(15)));
+        assert_eq!(watchdog_interval_from(Some("30000000"), Some("7"), 42), None);
+        assert_eq!(watchdog_interval_from(Some("0"), None, 42), None);
+        assert_eq!(watchdog_interval_from(Some("soon"), None, 42), None);
+        assert_eq!(watchdog_interval_from(None, None, 42), None);
+    }
+}
@@ -0,0 +1,87 @@
+// src/table.rs — Plain-text tables for CLI output
+//
+// Columns are padded to their widest cell; the last column isn't padded so
+// lines carry no trailing spaces. Widths count chars, not bytes, so "—"
+// and "≤" line up, and leave out ANSI colors (see overview.rs).
+
+pub struct Table {
+    headers: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(headers: &[&str]) -> Self {
+        Self { headers: headers.iter().map(|h| h.to_string()).collect(), rows: Vec::new() }
+    }
+
+    pub fn add_row(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    pub fn render(&self) -> String {
+        let mut widths: Vec<usize> = self.headers.iter().map(|h| width(h)).collect();
+        for row in &self.rows {
+            for (i, cell) in row.iter().enumerate().take(widths.len()) {
+                widths[i] = widths[i].max(width(cell));
+            }
+        }
+
+        let mut out = String::new();
+        for row in std::iter::once(&self.headers).chain(&self.rows) {
+            let mut line = String::new();
+            for (i, cell) in row.iter().enumerate().take(widths.len()) {
+                if i + 1 < widths.len() {
+                    let pad = widths[i] - width(cell);
+                    line.push_str(cell);
+                    line.push_str(&" ".repeat(pad + 2));
+                } else {
+                    line.push_str(cell);
+                }
+            }
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+        out
+    }
+}
+
+// Chars on screen: "\x1b[31m✗\x1b[0m" is one.
+fn width(cell: &str) -> usize {
+    let mut width = 0;
+    let mut chars = cell.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Up to and including the final letter, e.g. the `m` of `\x1b[0m`.
+            chars.by_ref().find(char::is_ascii_alphabetic);
+        } else {
+            width += 1;
+        }
+    }
+    width
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut table = Table::new(&["NAME", "STATE", "MODE"]);
+        table.add_row(vec!["DP-2".into(), "enabled".into(), "3840x2160@60".into()]);
+        table.add_row(vec!["HDMI-A-1".into(), "—".into(), String::new()]);
+
+        assert_eq!(
+            table.render(),
+            "NAME      STATE    MODE\n\
+             DP-2      enabled  3840x2160@60\n\
+             HDMI-A-1  —\n"
+        );
+
+        let mut table = Table::new(&["NAME", "STATE"]);
+        table.add_row(vec!["\x1b[32mDP-2\x1b[0m".into(), "on".into()]);
+        table.add_row(vec!["HDMI-A-1".into(), "off".into()]);
+        assert_eq!(table.render(), "NAME      STATE\n\x1b[32mDP-2\x1b[0m      on\nHDMI-A-1  off\n");
+    }
+}
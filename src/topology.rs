@@ -0,0 +1,274 @@
+// src/topology.rs — The output list, kept until something changes it
+//
+// Listing outputs runs kscreen-doctor (or asks Mutter, or wlr-randr) and
+// parses what comes back: a fork and a parse, over and over, for a list
+// that changes a few times a day. `DisplayManager` keeps the last list
+// and only lists again once it may be out of date:
+//
+//   - the kernel announced a DRM hotplug (a cable, a dock, a monitor
+//     waking up), which `watch` hears as a uevent on a netlink socket
+//   - we changed the outputs ourselves; display.rs invalidates after
+//     every enable, disable and layout change
+//   - it's older than MAX_AGE, for changes made behind our back in the
+//     desktop's display settings, which the kernel doesn't announce
+//
+// `on_change` callbacks get the new list whenever a hotplug changed it;
+// the daemon uses that to poll right away instead of at the next poll
+// interval. Without `watch` (the CLI commands) the list is still cached,
+// just never for longer than MAX_AGE.
+
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{debug, warn};
+
+use crate::display::{self, Display};
+use crate::error::{Result, VitaminkError};
+
+// How long a list is trusted without a hotplug or a change of ours.
+const MAX_AGE: Duration = Duration::from_secs(5);
+
+// Hotplugs come in bursts (every connector of a dock at once); the list
+// is read once they've stopped for this long.
+const HOTPLUG_SETTLE: Duration = Duration::from_millis(300);
+
+// The group the kernel broadcasts uevents on; udev's own re-broadcasts
+// are group 2.
+const KERNEL_UEVENTS: u32 = 1;
+
+type Callback = Box<dyn Fn(&[Display]) + Send>;
+
+pub struct DisplayManager {
+    // Lists the outputs for real: display::list_displays, or a fake in tests.
+    list: fn() -> Result<Vec<Display>>,
+    cached: Mutex<Option<(Instant, Vec<Display>)>>,
+    subscribers: Mutex<Vec<Callback>>,
+    watching: AtomicBool,
+}
+
+static MANAGER: DisplayManager = DisplayManager::new(display::list_displays);
+
+// The one the display.rs functions go through.
+pub fn manager() -> &'static DisplayManager {
+    &MANAGER
+}
+
+impl DisplayManager {
+    pub const fn new(list: fn() -> Result<Vec<Display>>) -> Self {
+        Self { list, cached: Mutex::new(None), subscribers: Mutex::new(Vec::new()), watching: AtomicBool::new(false) }
+    }
+
+    // The cached list, or a fresh one if it may be out of date.
+    pub fn displays(&self) -> Result<Vec<Display>> {
+        let mut cached = self.cached.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((listed, displays)) = cached.as_ref()
+            && listed.elapsed() < MAX_AGE
+        {
+            return Ok(displays.clone());
+        }
+        let displays = (self.list)()?;
+        *cached = Some((Instant::now(), displays.clone()));
+        Ok(displays)
+    }
+
+    // A fresh list, e.g. to save the layout from.
+    pub fn refresh(&self) -> Result<Vec<Display>> {
+        self.invalidate();
+        self.displays()
+    }
+
+    // The outputs changed (or may have): the next `displays` lists again.
+    pub fn invalidate(&self) {
+        *self.cached.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    // Calls `callback` with the new list after each hotplug that changed
+    // it. From the watch thread, so it shouldn't block for long.
+    pub fn on_change(&self, callback: impl Fn(&[Display]) + Send + 'static) {
+        self.subscribers.lock().unwrap_or_else(|e| e.into_inner()).push(Box::new(callback));
+    }
+
+    // Lists again after a hotplug, and tells the subscribers if that
+    // changed anything.
+    pub fn hotplug(&self) {
+        let before = self.cached.lock().unwrap_or_else(|e| e.into_inner()).take().map(|(_, displays)| displays);
+        let after = match self.displays() {
+            Ok(displays) => displays,
+            Err(e) => {
+                warn!("Couldn't list outputs after a hotplug: {e}");
+                return;
+            }
+        };
+        if before.as_ref() == Some(&after) {
+            debug!("Hotplug didn't change the outputs");
+            return;
+        }
+        for callback in self.subscribers.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+            callback(&after);
+        }
+    }
+
+    // Listens for the kernel's DRM hotplugs in a thread of its own. Only
+    // the first call starts one.
+    pub fn watch(&'static self) -> Result<()> {
+        if self.watching.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        let socket = uevent_socket().map_err(|e| {
+            self.watching.store(false, Ordering::SeqCst);
+            VitaminkError::io("uevent socket", e)
+        })?;
+        thread::Builder::new()
+            .name("hotplug".into())
+            .spawn(move || self.listen(socket))
+            .map_err(|e| VitaminkError::io("hotplug thread", e))?;
+        Ok(())
+    }
+
+    fn listen(&self, socket: OwnedFd) {
+        let mut buf = vec![0u8; 8192];
+        loop {
+            let Ok(message) = receive(&socket, &mut buf, 0) else {
+                warn!("Stopped listening for hotplugs");
+                return;
+            };
+            if !is_drm_hotplug(message) {
+                continue;
+            }
+            // Whatever else the burst brings, drm or not.
+            thread::sleep(HOTPLUG_SETTLE);
+            while receive(&socket, &mut buf, libc::MSG_DONTWAIT).is_ok() {}
+            debug!("DRM hotplug, listing outputs again");
+            self.hotplug();
+        }
+    }
+}
+
+// ---- Uevents ----
+
+fn uevent_socket() -> io::Result<OwnedFd> {
+    // SAFETY: plain socket(2); the fd is owned right after the check.
+    let fd = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, libc::NETLINK_KOBJECT_UEVENT) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: `fd` is a fresh descriptor nothing else owns.
+    let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    // SAFETY: sockaddr_nl is plain data; all zeroes is a valid value.
+    let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+    addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+    addr.nl_groups = KERNEL_UEVENTS;
+    // SAFETY: `addr` is a sockaddr_nl and we pass its size.
+    let bound = unsafe {
+        libc::bind(
+            socket.as_raw_fd(),
+            (&raw const addr).cast::<libc::sockaddr>(),
+            mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        )
+    };
+    if bound != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(socket)
+}
+
+fn receive<'a>(socket: &OwnedFd, buf: &'a mut [u8], flags: libc::c_int) -> io::Result<&'a [u8]> {
+    // SAFETY: `buf` is valid for writes of its length.
+    let n = unsafe { libc::recv(socket.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), flags) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(&buf[..n as usize])
+}
+
+// A kernel uevent is "ACTION@DEVPATH" and then KEY=VALUE fields, all
+// NUL-separated. A connector change on a card comes as
+// "change@/devices/.../drm/card1" with SUBSYSTEM=drm and HOTPLUG=1.
+fn is_drm_hotplug(message: &[u8]) -> bool {
+    let (mut drm, mut hotplug) = (false, false);
+    for field in message.split(|&b| b == 0).skip(1) {
+        drm |= field == b"SUBSYSTEM=drm";
+        hotplug |= field == b"HOTPLUG=1";
+    }
+    drm && hotplug
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+    use crate::display::{Color, ConnectionState, DisplayState};
+
+    static LISTED: AtomicUsize = AtomicUsize::new(0);
+
+    fn list() -> Result<Vec<Display>> {
+        let count = LISTED.fetch_add(1, Ordering::SeqCst);
+        // A second output turns up from the third listing on.
+        let names: &[&str] = if count < 2 { &["DP-2"] } else { &["DP-2", "HDMI-A-1"] };
+        Ok(names
+            .iter()
+            .map(|name| Display {
+                index: 0,
+                name: name.to_string(),
+                uuid: None,
+                state: DisplayState::Enabled,
+                connection: ConnectionState::Connected,
+                modes: Vec::new(),
+                geometry: None,
+                scale: None,
+                priority: None,
+                color: Color::default(),
+                vrr: None,
+                rotation: None,
+                overscan: None,
+            })
+            .collect())
+    }
+
+    #[test]
+    fn test_display_manager() {
+        static MANAGER: DisplayManager = DisplayManager::new(list);
+        static CHANGES: AtomicUsize = AtomicUsize::new(0);
+        MANAGER.on_change(|displays| {
+            assert_eq!(displays.len(), 2);
+            CHANGES.fetch_add(1, Ordering::SeqCst);
+        });
+
+        // Cached...
+        assert_eq!(MANAGER.displays().unwrap().len(), 1);
+        assert_eq!(MANAGER.displays().unwrap().len(), 1);
+        assert_eq!(LISTED.load(Ordering::SeqCst), 1);
+        // ...until invalidated.
+        MANAGER.invalidate();
+        assert_eq!(MANAGER.displays().unwrap().len(), 1);
+        assert_eq!(LISTED.load(Ordering::SeqCst), 2);
+
+        // A hotplug that brings an output tells the subscribers; one that
+        // changes nothing doesn't.
+        MANAGER.hotplug();
+        assert_eq!(CHANGES.load(Ordering::SeqCst), 1);
+        MANAGER.hotplug();
+        assert_eq!(CHANGES.load(Ordering::SeqCst), 1);
+        assert_eq!(MANAGER.displays().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_is_drm_hotplug() {
+        let hotplug = b"change@/devices/pci0000:00/0000:00:01.0/0000:01:00.0/drm/card1\0ACTION=change\0\
+DEVPATH=/devices/pci0000:00/0000:00:01.0/0000:01:00.0/drm/card1\0SUBSYSTEM=drm\0HOTPLUG=1\0CONNECTOR=95\0\
+DEVNAME=dri/card1\0DEVTYPE=drm_minor\0SEQNUM=4242\0MAJOR=226\0MINOR=1\0";
+        assert!(is_drm_hotplug(hotplug));
+        // A USB stick, and a DRM event that isn't a hotplug.
+        assert!(!is_drm_hotplug(b"add@/devices/usb1/1-1\0ACTION=add\0SUBSYSTEM=usb\0HOTPLUG=1\0"));
+        assert!(!is_drm_hotplug(b"change@/devices/drm/card1\0ACTION=change\0SUBSYSTEM=drm\0LEASE=1\0"));
+    }
+}
@@ -0,0 +1,228 @@
+// src/tune.rs — `vitamink tune`: measure this machine, recommend timings
+//
+// The defaults (5s poll, 10s grace, 10s DRM timeout) are guesses. How long
+// DPMS takes to reach sysfs, how slow kscreen-doctor is, and how long the
+// dummy plug needs to get a DRM framebuffer all vary a lot between GPUs and
+// Plasma versions. This measures them and derives settings from the results.
+//
+// Measuring DPMS means briefly blanking the monitors, so this is meant to be
+// run interactively, not from the daemon.
+
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::display::{self, DisplayState, DpmsState};
+use crate::dummy;
+use crate::duration;
+use crate::error::{Result, VitaminkError};
+
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(50);
+const MEASURE_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+pub struct Measurements {
+    // Slowest of a few `kscreen-doctor -o` runs.
+    pub enumerate: Duration,
+    // `kscreen-doctor output.X.enable` for the dummy plug.
+    pub apply: Duration,
+    // From the enable command returning until DRM reports the output active.
+    pub drm_activation: Duration,
+    // From `kscreen-doctor --dpms off/on` until sysfs reflects it.
+    // None if the main display's DPMS file never changed.
+    pub dpms_off: Option<Duration>,
+    pub dpms_on: Option<Duration>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Recommendation {
+    pub poll_interval: Duration,
+    pub grace_period: Duration,
+    pub drm_timeout: Duration,
+}
+
+// ---- Measuring ----
+
+pub fn measure(config: &Config) -> Result<Measurements> {
+    let mut enumerate = Duration::ZERO;
+    for _ in 0..3 {
+        let start = Instant::now();
+        display::get_displays()?;
+        enumerate = enumerate.max(start.elapsed());
+    }
+
+    let dummy = &dummy::resolve(config)?;
+    let dummy_was_enabled = display::get_displays()?
+        .iter()
+        .any(|d| d.name == *dummy && d.state == DisplayState::Enabled);
+    if dummy_was_enabled {
+        display::disable_output(dummy)?;
+        wait_until(|| !display::is_drm_active(dummy));
+    }
+
+    let start = Instant::now();
+    display::enable_output(dummy, config.dummy_mode.as_ref(), config.dummy_scale)?;
+    let apply = start.elapsed();
+
+    let drm_activation = wait_until(|| display::is_drm_active(dummy))
+        .ok_or_else(|| VitaminkError::Timeout(format!("{} never became DRM-active", dummy)))?;
+
+    if !dummy_was_enabled {
+        display::disable_output(dummy)?;
+    }
+
+    let main = config.primary_display();
+    display::set_all_dpms(false)?;
+    let dpms_off = wait_until(|| display::read_dpms(main) == DpmsState::Off);
+    display::set_all_dpms(true)?;
+    let dpms_on = wait_until(|| display::read_dpms(main) == DpmsState::On);
+
+    Ok(Measurements { enumerate, apply, drm_activation, dpms_off, dpms_on })
+}
+
+// Returns how long it took for `check` to pass, or None on timeout.
+fn wait_until(mut check: impl FnMut() -> bool) -> Option<Duration> {
+    let start = Instant::now();
+    while start.elapsed() < MEASURE_TIMEOUT {
+        if check() {
+            return Some(start.elapsed());
+        }
+        thread::sleep(SAMPLE_INTERVAL);
+    }
+    None
+}
+
+// ---- Recommending ----
+
+pub fn recommend(m: &Measurements) -> Recommendation {
+    let dpms = m.dpms_off.max(m.dpms_on).unwrap_or(Duration::from_secs(1));
+
+    // Polling faster than DPMS propagates buys nothing, and each poll should
+    // cost well under a tenth of the interval.
+    let poll_secs = ceil_secs(dpms.max(m.enumerate * 10)).clamp(2, 10);
+
+    // At least two polls must agree before we act, plus DPMS settling time,
+    // so a monitor that blinks off and on never triggers a transition.
+    let grace_secs = poll_secs * 2 + ceil_secs(dpms);
+
+    // Generous headroom: activation is slower right after a DPMS wake.
+    let drm_secs = ceil_secs((m.apply + m.drm_activation) * 3).max(5);
+
+    Recommendation {
+        poll_interval: Duration::from_secs(poll_secs),
+        grace_period: Duration::from_secs(grace_secs),
+        drm_timeout: Duration::from_secs(drm_secs),
+    }
+}
+
+fn ceil_secs(d: Duration) -> u64 {
+    d.as_millis().div_ceil(1000) as u64
+}
+
+// ---- Writing ----
+
+// Updates just these keys in the config file, keeping everything else
+// (comments included) as the user wrote it.
+// As a TOML string, e.g. `"9s"`.
+fn quoted(d: Duration) -> String {
+    format!("\"{}\"", duration::format(d))
+}
+
+pub fn write(path: &Path, rec: &Recommendation) -> Result<()> {
+    let existing = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(VitaminkError::io(path, e)),
+    };
+
+    let updated = set_top_level_keys(
+        &existing,
+        &[
+            ("poll_interval", quoted(rec.poll_interval)),
+            ("grace_period", quoted(rec.grace_period)),
+            ("drm_timeout", quoted(rec.drm_timeout)),
+        ],
+    );
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| VitaminkError::io(dir, e))?;
+    }
+    fs::write(path, updated).map_err(|e| VitaminkError::io(path, e))
+}
+
+// Top-level keys must come before the first `[table]` header in TOML, so
+// existing ones are replaced in place and new ones go at the very top.
+fn set_top_level_keys(text: &str, keys: &[(&str, String)]) -> String {
+    let mut lines: Vec<String> = text.lines().map(String::from).collect();
+    let top_level_end = lines
+        .iter()
+        .position(|l| l.trim_start().starts_with('['))
+        .unwrap_or(lines.len());
+
+    let mut missing = Vec::new();
+    for (key, value) in keys {
+        let existing = lines[..top_level_end].iter().position(|l| {
+            l.split_once('=').is_some_and(|(k, _)| k.trim() == *key)
+        });
+        match existing {
+            Some(i) => lines[i] = format!("{key} = {value}"),
+            None => missing.push(format!("{key} = {value}")),
+        }
+    }
+
+    missing.extend(lines);
+    let mut out = missing.join("\n");
+    out.push('\n');
+    out
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn test_recommend() {
+        let rec = recommend(&Measurements {
+            enumerate: ms(120),
+            apply: ms(400),
+            drm_activation: ms(900),
+            dpms_off: Some(ms(1500)),
+            dpms_on: Some(ms(2500)),
+        });
+        assert_eq!(rec.poll_interval, Duration::from_secs(3));
+        assert_eq!(rec.grace_period, Duration::from_secs(9));
+        assert_eq!(rec.drm_timeout, Duration::from_secs(5));
+
+        // Fast machine: clamped to the minimum poll
+        let rec = recommend(&Measurements {
+            enumerate: ms(30),
+            apply: ms(200),
+            drm_activation: ms(3000),
+            dpms_off: None,
+            dpms_on: None,
+        });
+        assert_eq!(rec.poll_interval, Duration::from_secs(2));
+        assert_eq!(rec.grace_period, Duration::from_secs(5));
+        assert_eq!(rec.drm_timeout, Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_set_top_level_keys() {
+        let text = "# my config\ngrace_period = 30\n\n[privacy]\nmute_microphone = true\n";
+        let out = set_top_level_keys(
+            text,
+            &[("grace_period", "9".to_string()), ("poll_interval", "3".to_string())],
+        );
+        assert_eq!(out, "poll_interval = 3\n# my config\ngrace_period = 9\n\n[privacy]\nmute_microphone = true\n");
+
+        assert_eq!(set_top_level_keys("", &[("drm_timeout", "5".to_string())]), "drm_timeout = 5\n");
+    }
+}
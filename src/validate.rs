@@ -0,0 +1,163 @@
+// src/validate.rs — `vitamink config check`: the config against this machine
+//
+// `config::parse` catches what's wrong with the file on its own: bad TOML,
+// unknown keys, settings that contradict each other. This goes on to hold
+// it up against the outputs and services that are actually here, which is
+// where a typo like `dummy_plug = "HDMI-A1"` shows up:
+//
+//   ✓ dummy_plug "HDMI-A-*" — is HDMI-A-1
+//   ✗ Configured outputs exist — main_display: no output named "DP2" (did you mean "DP-2"?)
+//       → Fix the names in the config (see `vitamink displays`) or add them to allow_missing
+//   ✗ dummy_mode 3840x2160@120 on HDMI-A-1 — HDMI-A-1 has no such mode; the closest is 3840x2160@60.00
+//       → `vitamink modes HDMI-A-1` lists the modes it has
+//   ✓ sunshine.service unit installed — loaded
+//
+// Before the checks, the config is printed the way the daemon would see
+// it: every default filled in and every pattern resolved, with passwords,
+// tokens and webhook URLs blanked out. Like `vitamink doctor`, the command
+// exits nonzero if anything failed.
+
+use crate::config::Config;
+use crate::display::{self, Display, Mode, ModeTarget};
+use crate::doctor::{self, Check};
+use crate::pattern;
+
+const HIDDEN: &str = "(hidden)";
+
+// Resolves the config's patterns, then checks outputs, modes and services.
+pub fn run(config: &mut Config) -> Vec<Check> {
+    let mut checks: Vec<Check> = pattern::resolve_config(config)
+        .into_iter()
+        .map(|resolved| {
+            let name = format!("{} \"{}\"", resolved.setting, resolved.pattern);
+            match resolved.connector {
+                Ok(connector) => Check::pass(name, format!("is {connector}")),
+                Err(e) => Check::fail(name, e.to_string(), "Narrow the pattern down, or name the connector; `vitamink displays` lists them"),
+            }
+        })
+        .collect();
+
+    match display::get_displays() {
+        Ok(displays) if !displays.is_empty() => {
+            checks.push(doctor::configured_outputs(config, &displays));
+            checks.extend(modes(config, &displays));
+        }
+        Ok(_) => checks.push(Check::fail("Outputs listed", "the compositor lists none", "Run this from the desktop session")),
+        Err(e) => checks.push(Check::fail("Outputs listed", e.to_string(), "`vitamink doctor` says more")),
+    }
+    checks.extend(doctor::service_units(config));
+    checks
+}
+
+// Each mode the config asks for is one its output has. Outputs that
+// aren't there are left to `configured_outputs`.
+fn modes(config: &Config, displays: &[Display]) -> Vec<Check> {
+    let mut wanted: Vec<(String, &str, &ModeTarget)> = Vec::new();
+    if let Some(mode) = &config.dummy_mode {
+        wanted.push(("dummy_mode".to_string(), &config.dummy_plug, mode));
+    }
+    for (name, profile) in &config.profiles {
+        for output in &profile.enable {
+            if let Some(mode) = &output.mode {
+                wanted.push((format!("[profiles.{name}]"), &output.name, mode));
+            }
+        }
+    }
+
+    wanted
+        .into_iter()
+        .filter_map(|(setting, output, target)| {
+            let display = displays.iter().find(|d| d.name == output)?;
+            let name = format!("{setting} {target} on {output}");
+            Some(match display::select_mode(&display.modes, Some(target)) {
+                Some(mode) if fits(mode, target) => Check::pass(name, format!("mode {}", mode.id)),
+                Some(mode) => Check::fail(
+                    name,
+                    format!("{output} has no such mode; the closest is {}x{}@{:.2}", mode.width, mode.height, mode.refresh),
+                    format!("`vitamink modes {output}` lists the modes it has"),
+                ),
+                None => Check::fail(name, format!("{output} reports no modes"), "Is the plug seated?"),
+            })
+        })
+        .collect()
+}
+
+// The resolution exactly, and the refresh rate to within half a hertz
+// (59.94 is "60").
+fn fits(mode: &Mode, target: &ModeTarget) -> bool {
+    (mode.width, mode.height) == (target.width, target.height) && target.refresh.is_none_or(|hz| (mode.refresh - hz).abs() < 0.5)
+}
+
+// The effective config, without its secrets.
+pub fn dump(mut config: Config) -> String {
+    if config.sunshine.api_password.is_some() {
+        config.sunshine.api_password = Some(HIDDEN.to_string());
+    }
+    if config.http.token.is_some() {
+        config.http.token = Some(HIDDEN.to_string());
+    }
+    // Telegram and Discord URLs carry the bot's token.
+    for webhook in &mut config.webhooks {
+        webhook.url = HIDDEN.to_string();
+    }
+    format!("{config:#?}\n")
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+    use crate::display::{Color, ConnectionState, DisplayState};
+
+    fn output(name: &str, modes: &[(u32, u32, f64)]) -> Display {
+        Display {
+            index: 0,
+            name: name.into(),
+            uuid: None,
+            state: DisplayState::Enabled,
+            connection: ConnectionState::Connected,
+            modes: modes
+                .iter()
+                .zip(1..)
+                .map(|(&(width, height, refresh), id)| Mode { id, width, height, refresh, preferred: false, current: false })
+                .collect(),
+            geometry: None,
+            scale: None,
+            priority: None,
+            color: Color::default(),
+            vrr: None,
+            rotation: None,
+            overscan: None,
+        }
+    }
+
+    #[test]
+    fn test_modes() {
+        let displays = [output("DP-2", &[(2560, 1440, 144.0)]), output("HDMI-A-1", &[(3840, 2160, 59.94), (1920, 1080, 120.0)])];
+        let text = "dummy_mode = \"3840x2160@60\"\n[profiles.tv]\nenable = [{ name = \"HDMI-A-1\", mode = \"3840x2160@120\" }, { name = \"DP-3\", mode = \"1920x1080\" }]";
+        let checks = modes(&config::parse(text).unwrap(), &displays);
+
+        assert_eq!(checks.len(), 2);
+        assert_eq!(checks[0], Check::pass("dummy_mode 3840x2160@60 on HDMI-A-1", "mode 1"));
+        assert_eq!(
+            checks[1],
+            Check::fail(
+                "[profiles.tv] 3840x2160@120 on HDMI-A-1",
+                "HDMI-A-1 has no such mode; the closest is 3840x2160@59.94",
+                "`vitamink modes HDMI-A-1` lists the modes it has"
+            )
+        );
+    }
+
+    #[test]
+    fn test_dump() {
+        let text = "[sunshine]\napi_username = \"admin\"\napi_password = \"pw\"\n[[webhooks]]\nurl = \"https://api.telegram.org/bot123:SECRET/sendMessage\"\nformat = \"telegram\"\nchat_id = \"42\"";
+        let dump = dump(config::parse(text).unwrap());
+        assert!(dump.contains("api_username: Some(\n            \"admin\",\n        )"));
+        assert!(!dump.contains("\"pw\""));
+        assert!(!dump.contains("SECRET"));
+        assert!(dump.contains("poll_interval: "));
+    }
+}
@@ -0,0 +1,124 @@
+// src/virtual_output.rs — a headless output instead of a dummy plug
+//
+// Plasma 6 can stream a desktop to an output that only exists in KWin:
+// whatever asks for one through KWin's screencast protocol gets a fresh
+// "Virtual-NAME" output for as long as it holds the stream open.
+// `krfb-virtualmonitor` is the stock tool that does this:
+//
+//   dummy_plug = "virtual"
+//   dummy_mode = "2560x1440@60"
+//
+//   [virtual_output]
+//   command = "krfb-virtualmonitor --name vitamink --resolution {width}x{height} --port 5910 --password CHANGEME"
+//   output = "Virtual-vitamink"
+//
+// (krfb also serves that output over VNC on `--port`; firewall it if the
+// machine isn't only on a trusted network.)
+//
+// The command runs as a transient systemd user unit rather than as our
+// child, so a daemon restart while Away neither orphans it nor starts a
+// second one, and `systemctl --user stop` takes the output away again.
+
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::info;
+
+use crate::config::VirtualOutputConfig;
+use crate::display::{self, ModeTarget};
+use crate::error::{Result, VitaminkError};
+use crate::process;
+
+// `dummy_plug` value that selects this instead of a connector.
+pub const VIRTUAL: &str = "virtual";
+
+const UNIT: &str = "vitamink-virtual-output";
+
+// Used when `dummy_mode` doesn't say.
+pub const DEFAULT_MODE: ModeTarget = ModeTarget { width: 1920, height: 1080, refresh: None };
+
+// Starts the helper unless the output is already there, and waits up to
+// `timeout` for KWin to list it.
+pub fn create(config: &VirtualOutputConfig, mode: Option<&ModeTarget>, timeout: Duration) -> Result<()> {
+    if exists(&config.output)? {
+        return Ok(());
+    }
+    // Never None; config::parse rejects that with dummy_plug = "virtual".
+    let command = config.command.as_deref().unwrap_or_default();
+
+    info!("→ Creating virtual output {}", config.output);
+    let output = process::run(
+        Command::new("systemd-run")
+            .args(["--user", "--collect", "--unit", UNIT, "sh", "-c"])
+            .arg(expand(command, mode.unwrap_or(&DEFAULT_MODE))),
+    )?;
+    if !output.status.success() {
+        return Err(VitaminkError::command_failed("systemd-run", &output));
+    }
+    if process::is_dry_run() {
+        return Ok(());
+    }
+
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        if exists(&config.output)? {
+            return Ok(());
+        }
+        thread::sleep(Duration::from_millis(250));
+    }
+    // Don't leave a helper running that never produced the output.
+    let _ = destroy();
+    Err(VitaminkError::Timeout(format!("{} didn't appear within {}s", config.output, timeout.as_secs())))
+}
+
+// Stops the helper; fine if it isn't running.
+pub fn destroy() -> Result<()> {
+    let output = process::run(Command::new("systemctl").args(["--user", "stop", UNIT]))?;
+    // 5: the unit isn't loaded, i.e. already gone.
+    if !output.status.success() && output.status.code() != Some(5) {
+        return Err(VitaminkError::command_failed(format!("systemctl --user stop {UNIT}"), &output));
+    }
+    Ok(())
+}
+
+fn exists(name: &str) -> Result<bool> {
+    Ok(display::get_displays()?.iter().any(|d| d.name == name))
+}
+
+// "{width}", "{height}" and "{refresh}" (60 when the mode has none).
+pub fn expand(command: &str, mode: &ModeTarget) -> String {
+    command
+        .replace("{width}", &mode.width.to_string())
+        .replace("{height}", &mode.height.to_string())
+        .replace("{refresh}", &mode.refresh.unwrap_or(60.0).round().to_string())
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::mock::FakeRunner;
+
+    #[test]
+    fn test_expand() {
+        let mode = ModeTarget { width: 2560, height: 1440, refresh: Some(119.88) };
+        assert_eq!(expand("krfb-virtualmonitor --resolution {width}x{height}", &mode), "krfb-virtualmonitor --resolution 2560x1440");
+        assert_eq!(expand("helper {refresh}", &mode), "helper 120");
+        assert_eq!(expand("helper {refresh}", &DEFAULT_MODE), "helper 60");
+    }
+
+    #[test]
+    fn test_destroy() {
+        let fake = Rc::new(FakeRunner::new());
+        fake.respond("systemctl --user stop", 5, "");
+        assert!(process::with_runner(fake.clone(), destroy).is_ok());
+
+        fake.respond("systemctl --user stop", 1, "");
+        assert!(process::with_runner(fake.clone(), destroy).is_err());
+        assert_eq!(fake.calls()[0], "systemctl --user stop vitamink-virtual-output");
+    }
+}
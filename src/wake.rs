@@ -0,0 +1,195 @@
+// src/wake.rs — Going Away as soon as a client tries to wake the PC
+//
+// With `[wake] enabled = true`, while we're AtDesk and Sunshine isn't
+// running the daemon listens for what a client sends to a sleeping host:
+// Wake-on-LAN magic packets on the configured UDP ports, and optionally a
+// connection to Sunshine's HTTP port, which Moonlight opens whenever it
+// lists this PC. Either one goes Away right away instead of after the
+// grace period, so the stream is ready by the time the client connects.
+// The same listener brings the daemon back from Standby (see
+// `[standby]`), where it always watches for Moonlight.
+//
+// The sockets are closed before anything starts Sunshine — it needs some
+// of the same ports — and only a magic packet for one of our own MAC
+// addresses counts.
+
+use std::fs;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, UdpSocket};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use log::{debug, info, warn};
+
+use crate::config::WakeConfig;
+use crate::daemon::Command;
+
+// Sunshine's default HTTP port, which Moonlight polls for server info.
+pub const MOONLIGHT_HTTP_PORT: u16 = 47989;
+
+// How often the thread checks its sockets (and whether to stop).
+const TICK: Duration = Duration::from_millis(200);
+
+// The listening thread. Dropping it closes the sockets.
+pub struct Listener {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Listener {
+    // Binds what it can; fails only if nothing could be bound.
+    pub fn start(config: &WakeConfig, commands: Sender<Command>) -> io::Result<Self> {
+        let mut udp = Vec::new();
+        for &port in &config.ports {
+            match bind_udp(port) {
+                Ok(socket) => udp.push(socket),
+                Err(e) => warn!("Wake: can't listen on UDP port {port}: {e}"),
+            }
+        }
+        let tcp = if config.moonlight_probe {
+            bind_tcp(MOONLIGHT_HTTP_PORT)
+                .inspect_err(|e| warn!("Wake: can't listen on TCP port {MOONLIGHT_HTTP_PORT}: {e}"))
+                .ok()
+        } else {
+            None
+        };
+        if udp.is_empty() && tcp.is_none() {
+            return Err(io::Error::other("no port could be bound"));
+        }
+
+        let macs = local_macs();
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = stop.clone();
+        let thread = thread::Builder::new()
+            .name("wake".to_string())
+            .spawn(move || listen(&udp, tcp.as_ref(), &macs, &flag, &commands))?;
+        debug!("Wake: listening");
+        Ok(Self { stop, thread: Some(thread) })
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        debug!("Wake: stopped listening");
+    }
+}
+
+fn bind_udp(port: u16) -> io::Result<UdpSocket> {
+    let socket = UdpSocket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)))?;
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+fn bind_tcp(port: u16) -> io::Result<TcpListener> {
+    let listener = TcpListener::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)))?;
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+fn listen(udp: &[UdpSocket], tcp: Option<&TcpListener>, macs: &[[u8; 6]], stop: &AtomicBool, commands: &Sender<Command>) {
+    let mut buf = [0u8; 1024];
+    while !stop.load(Ordering::Relaxed) {
+        for socket in udp {
+            while let Ok((len, from)) = socket.recv_from(&mut buf) {
+                match magic_target(&buf[..len]) {
+                    Some(mac) if macs.is_empty() || macs.contains(&mac) => {
+                        info!("Wake: magic packet from {from}");
+                        let _ = commands.send(Command::RemoteWake(format!("magic packet from {from}")));
+                    }
+                    Some(mac) => debug!("Wake: magic packet from {from} for {}, not us", format_mac(&mac)),
+                    None => {}
+                }
+            }
+        }
+        // The connection is dropped unanswered; Moonlight retries once
+        // Sunshine is up.
+        if let Some(listener) = tcp
+            && let Ok((_, from)) = listener.accept()
+        {
+            info!("Wake: Moonlight probe from {from}");
+            let _ = commands.send(Command::RemoteWake(format!("Moonlight probe from {}", from.ip())));
+        }
+        thread::sleep(TICK);
+    }
+}
+
+// A magic packet is six 0xFF bytes followed by the target MAC sixteen
+// times, anywhere in the payload (some senders put a header first).
+fn magic_target(packet: &[u8]) -> Option<[u8; 6]> {
+    (0..packet.len().saturating_sub(101)).find_map(|start| {
+        let (sync, rest) = packet[start..].split_at(6);
+        if sync != [0xFF; 6] {
+            return None;
+        }
+        let mac: [u8; 6] = rest[..6].try_into().ok()?;
+        rest[..96].chunks(6).all(|chunk| chunk == mac).then_some(mac)
+    })
+}
+
+// MAC addresses of this machine's network interfaces, loopback aside.
+fn local_macs() -> Vec<[u8; 6]> {
+    let Ok(entries) = fs::read_dir("/sys/class/net") else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| fs::read_to_string(entry.path().join("address")).ok())
+        .filter_map(|text| parse_mac(text.trim()))
+        .filter(|mac| *mac != [0; 6])
+        .collect()
+}
+
+fn parse_mac(text: &str) -> Option<[u8; 6]> {
+    let bytes: Vec<u8> = text.split(':').map(|b| u8::from_str_radix(b, 16).ok()).collect::<Option<_>>()?;
+    bytes.try_into().ok()
+}
+
+fn format_mac(mac: &[u8; 6]) -> String {
+    mac.iter().map(|b| format!("{b:02x}")).collect::<Vec<_>>().join(":")
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(mac: [u8; 6]) -> Vec<u8> {
+        let mut packet = vec![0xFF; 6];
+        for _ in 0..16 {
+            packet.extend_from_slice(&mac);
+        }
+        packet
+    }
+
+    #[test]
+    fn test_magic_target() {
+        let mac = [0x00, 0x1b, 0x21, 0x3a, 0x4c, 0x5d];
+        assert_eq!(magic_target(&packet(mac)), Some(mac));
+
+        let mut with_header = b"WOL\0".to_vec();
+        with_header.extend(packet(mac));
+        assert_eq!(magic_target(&with_header), Some(mac));
+
+        let mut broken = packet(mac);
+        broken[50] ^= 1;
+        assert_eq!(magic_target(&broken), None);
+        assert_eq!(magic_target(&packet(mac)[..100]), None);
+        assert_eq!(magic_target(b""), None);
+    }
+
+    #[test]
+    fn test_parse_mac() {
+        let mac = parse_mac("00:1b:21:3a:4c:5d").unwrap();
+        assert_eq!(format_mac(&mac), "00:1b:21:3a:4c:5d");
+        assert_eq!(parse_mac("00:1b:21:3a:4c"), None);
+        assert_eq!(parse_mac("zz:1b:21:3a:4c:5d"), None);
+    }
+}
@@ -0,0 +1,213 @@
+// src/watch.rs — `vitamink watch`: the whole picture, redrawn every second
+//
+// For watching a transition happen: the daemon's state and any switch
+// counting down its grace period (from status.json), every output with
+// its DPMS and DRM state and mode (asked directly, like `vitamink
+// displays`), and the services. Plain ANSI: the screen is cleared and
+// redrawn on the terminal's alternate screen, which Ctrl-C leaves again.
+//
+//   VitaminK — 14:02:31 (Ctrl-C to quit)
+//
+//   Daemon:   AtDesk (pid 4242)
+//   Pending:  Away in 7s
+//
+//   (the `vitamink displays` table)
+//
+//   sunshine: stopped
+
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use signal_hook::consts::{SIGINT, SIGTERM};
+
+use crate::config::Config;
+use crate::display;
+use crate::drm;
+use crate::edid;
+use crate::listing;
+use crate::services;
+use crate::status::{self, Status};
+
+// One screenful.
+pub struct Frame {
+    // Unix seconds.
+    pub now: u64,
+    // Local wall-clock time, for the title.
+    pub clock: String,
+    // Why there's no status, if there isn't one.
+    pub status: Result<Status, String>,
+    // The rendered output table, or why it couldn't be listed.
+    pub outputs: Result<String, String>,
+    // Used when the daemon isn't running to say so.
+    pub services: Vec<(String, bool)>,
+}
+
+// Redraws until SIGINT/SIGTERM.
+pub fn run(config: &Config) -> io::Result<()> {
+    let stop = Arc::new(AtomicBool::new(false));
+    for signal in [SIGINT, SIGTERM] {
+        signal_hook::flag::register(signal, Arc::clone(&stop))?;
+    }
+
+    let mut stdout = io::stdout();
+    // Alternate screen, cursor hidden.
+    write!(stdout, "\x1b[?1049h\x1b[?25l")?;
+    while !stop.load(Ordering::Relaxed) {
+        let frame = capture(config);
+        write!(stdout, "\x1b[H\x1b[2J{}", render(&frame).replace('\n', "\r\n"))?;
+        stdout.flush()?;
+        // Short naps, so Ctrl-C doesn't wait out a whole second.
+        for _ in 0..10 {
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+    write!(stdout, "\x1b[?25h\x1b[?1049l")?;
+    stdout.flush()
+}
+
+fn capture(config: &Config) -> Frame {
+    let status = match status::read() {
+        Ok(s) if status::is_stale(&s) => Err(format!("not running (pid {} is gone)", s.pid)),
+        Ok(s) => Ok(s),
+        Err(e) => Err(format!("not running ({e})")),
+    };
+    let outputs = display::get_displays().map_err(|e| e.to_string()).map(|displays| {
+        listing::render(&displays, &listing::Options::default(), |d| listing::Hardware {
+            model: edid::read(&d.name).map(|e| e.name.unwrap_or(e.manufacturer)),
+            dpms: display::read_dpms(&d.name),
+            drm_active: display::is_drm_active(&d.name),
+            link: drm::connector(&d.name).map(|c| c.status),
+        })
+    });
+    // The daemon's view when it's running; otherwise ask systemd.
+    let services = match &status {
+        Ok(s) => s.services.iter().map(|(unit, active)| (unit.clone(), *active)).collect(),
+        Err(_) => config.services.iter().map(|s| (s.unit.clone(), services::is_active(s))).collect(),
+    };
+
+    Frame { now: status::unix_now(), clock: clock(), status, outputs, services }
+}
+
+pub fn render(frame: &Frame) -> String {
+    let mut out = format!("VitaminK — {} (Ctrl-C to quit)\n\n", frame.clock);
+    let mut field = |label: &str, value: String| out.push_str(&format!("{label:<10}{value}\n"));
+
+    match &frame.status {
+        Ok(s) => {
+            let profile = s.profile.as_ref().map(|p| format!(", profile {p}")).unwrap_or_default();
+            field("Daemon:", format!("{} (pid {}{profile})", s.state, s.pid));
+            if let Some(progress) = &s.progress {
+                field("Step:", progress.describe(frame.now));
+            }
+            if let Some(pending) = &s.pending {
+                let left = pending.due.saturating_sub(frame.now);
+                field("Pending:", match (pending.held_by_stream, left) {
+                    (true, _) => format!("{} once the stream ends", pending.to),
+                    (false, 0) => format!("{} now", pending.to),
+                    (false, left) => format!("{} in {left}s", pending.to),
+                });
+            }
+            for held in &s.inhibitors {
+                field("Inhibit:", match held.until {
+                    Some(until) => format!("{} ({}, {}s left)", held.reason, held.name, until.saturating_sub(frame.now)),
+                    None => format!("{} ({}, until released)", held.reason, held.name),
+                });
+            }
+            if let Some(error) = &s.last_error {
+                field("Error:", error.clone());
+            }
+        }
+        Err(why) => field("Daemon:", why.clone()),
+    }
+    out.push('\n');
+
+    match &frame.outputs {
+        Ok(table) => out.push_str(table),
+        Err(e) => out.push_str(&format!("Couldn't list outputs: {e}\n")),
+    }
+    out.push('\n');
+
+    for (unit, active) in &frame.services {
+        out.push_str(&format!("{unit}: {}\n", if *active { "running" } else { "stopped" }));
+    }
+    out
+}
+
+// "14:02:31" in local time.
+fn clock() -> String {
+    // SAFETY: `time` accepts a null pointer, and `localtime_r` only writes
+    // into the zeroed `tm` we hand it.
+    let tm = unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&now, &mut tm);
+        tm
+    };
+    format!("{:02}:{:02}:{:02}", tm.tm_hour, tm.tm_min, tm.tm_sec)
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::daemon::State;
+    use crate::display::DpmsState;
+    use crate::status::Pending;
+
+    fn status(pending: Option<Pending>) -> Status {
+        Status {
+            state: State::AtDesk,
+            pid: 4242,
+            main_display: "DP-2".to_string(),
+            dpms: DpmsState::Off,
+            sunshine_running: false,
+            services: BTreeMap::from([("sunshine".to_string(), false)]),
+            profile: None,
+            last_transition: None,
+            last_error: None,
+            hotplugs: BTreeMap::new(),
+            updated: 1000,
+            pending,
+            inhibitors: Vec::new(),
+            progress: None,
+            time_in_state: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let frame = Frame {
+            now: 1000,
+            clock: "14:02:31".to_string(),
+            status: Ok(status(Some(Pending { to: State::Away, due: 1007, remaining: 7, held_by_stream: false, held_by_load: None }))),
+            outputs: Ok("#  NAME\n1  DP-2\n".to_string()),
+            services: vec![("sunshine".to_string(), false)],
+        };
+        assert_eq!(
+            render(&frame),
+            "VitaminK — 14:02:31 (Ctrl-C to quit)\n\n\
+             Daemon:   AtDesk (pid 4242)\n\
+             Pending:  Away in 7s\n\n\
+             #  NAME\n1  DP-2\n\n\
+             sunshine: stopped\n"
+        );
+
+        let frame = Frame {
+            status: Err("not running (no status file)".to_string()),
+            outputs: Err("kscreen-doctor not found".to_string()),
+            ..frame
+        };
+        let text = render(&frame);
+        assert!(text.contains("Daemon:   not running (no status file)\n"));
+        assert!(text.contains("Couldn't list outputs: kscreen-doctor not found\n"));
+    }
+}
@@ -0,0 +1,105 @@
+// src/watchdog.rs — Detects a main loop that has stopped cycling
+//
+// The daemon loop is single-threaded: if kscreen-doctor or systemctl hangs
+// (wedged compositor, D-Bus timeout), nothing else happens until it
+// returns — possibly never. A separate thread watches a heartbeat that the
+// loop bumps after every cycle. When it goes stale for 3× the poll
+// interval, the watchdog logs what the loop was doing and which external
+// commands are running, then kills the stuck ones so the loop can continue.
+//
+// New Rust concepts in this file:
+//
+// - `AtomicU64`: lock-free shared integer. The loop stores "milliseconds
+//   since start" into it; the watchdog reads it without ever blocking the
+//   loop.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{debug, warn};
+
+use crate::process;
+
+// Shared between the main loop (writer) and the watchdog thread (reader).
+pub struct Heartbeat {
+    epoch: Instant,
+    last_beat_ms: AtomicU64,
+    // What the loop is currently doing, for the diagnostic.
+    phase: Mutex<&'static str>,
+}
+
+impl Heartbeat {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            epoch: Instant::now(),
+            last_beat_ms: AtomicU64::new(0),
+            phase: Mutex::new("starting"),
+        })
+    }
+
+    pub fn beat(&self) {
+        self.last_beat_ms.store(self.epoch.elapsed().as_millis() as u64, Ordering::SeqCst);
+    }
+
+    pub fn set_phase(&self, phase: &'static str) {
+        *self.phase.lock().unwrap_or_else(|e| e.into_inner()) = phase;
+    }
+
+    fn since_last_beat(&self) -> Duration {
+        let now_ms = self.epoch.elapsed().as_millis() as u64;
+        Duration::from_millis(now_ms.saturating_sub(self.last_beat_ms.load(Ordering::SeqCst)))
+    }
+
+    fn phase(&self) -> &'static str {
+        *self.phase.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+pub fn spawn(heartbeat: Arc<Heartbeat>, poll_interval: Duration) {
+    let limit = poll_interval * 3;
+
+    thread::spawn(move || {
+        // Only report each stall once; a new beat re-arms the watchdog.
+        let mut reported = false;
+
+        loop {
+            thread::sleep(poll_interval);
+
+            let stalled_for = heartbeat.since_last_beat();
+            if stalled_for < limit {
+                reported = false;
+                continue;
+            }
+            if reported {
+                continue;
+            }
+            reported = true;
+
+            warn!(
+                "Watchdog: main loop stalled for {:.0}s (limit {:.0}s) in phase \"{}\"",
+                stalled_for.as_secs_f64(),
+                limit.as_secs_f64(),
+                heartbeat.phase()
+            );
+
+            let children = process::running();
+            if children.is_empty() {
+                debug!("Watchdog: no external commands running");
+            }
+            for child in &children {
+                debug!(
+                    "Watchdog:   pid {} running {:.0}s: {}",
+                    child.pid,
+                    child.started.elapsed().as_secs_f64(),
+                    child.command
+                );
+            }
+
+            for child in process::kill_older_than(limit) {
+                warn!("Watchdog: killed stuck pid {} ({})", child.pid, child.command);
+            }
+        }
+    });
+}
@@ -0,0 +1,171 @@
+// src/webhook.rs — Notifications for a PC nobody is sitting at
+//
+// Desktop popups only reach someone at the desk. For a box that's managed
+// remotely, each `[[webhooks]]` entry posts the same events — transitions,
+// errors like a pulled dummy plug, holding — to a URL:
+//
+//   [[webhooks]]
+//   url = "https://example.com/hooks/vitamink"
+//
+//   [[webhooks]]
+//   format = "telegram"
+//   url = "https://api.telegram.org/botTOKEN/sendMessage"
+//   chat_id = "123456789"
+//
+//   [[webhooks]]
+//   format = "discord"
+//   url = "https://discord.com/api/webhooks/ID/TOKEN"
+//   events = ["error"]
+//
+// "generic" posts {"host", "event", "summary", "body", "time"} for
+// whatever is on the other end (Home Assistant, ntfy, a script);
+// "telegram" and "discord" post what their bot APIs expect.
+//
+// Each post runs curl in a thread of its own, so a slow endpoint never
+// holds up a switch; one that fails is logged and dropped, not retried.
+// The URL, which holds the bot token for Telegram and Discord, reaches
+// curl on stdin rather than the command line. Like desktop
+// notifications, an error that repeats on every poll is posted once.
+
+use std::fs;
+use std::process::Command;
+use std::sync::Mutex;
+use std::thread;
+
+use log::{debug, warn};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::config::WebhookConfig;
+use crate::notify::Event;
+use crate::process;
+use crate::status;
+
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Generic,
+    Telegram,
+    Discord,
+}
+
+// The last error posted, so repeats are dropped.
+static LAST_ERROR: Mutex<Option<String>> = Mutex::new(None);
+
+// Posts the event to every webhook that wants it.
+pub fn send(hooks: &[WebhookConfig], event: Event, summary: &str, body: &str) {
+    let hooks: Vec<&WebhookConfig> = hooks.iter().filter(|hook| hook.events.contains(&event)).collect();
+    if hooks.is_empty() {
+        return;
+    }
+    if event == Event::Error {
+        let mut last = LAST_ERROR.lock().unwrap_or_else(|e| e.into_inner());
+        if last.as_deref() == Some(body) {
+            return;
+        }
+        *last = Some(body.to_string());
+    }
+
+    let host = hostname();
+    for hook in hooks {
+        let url = hook.url.clone();
+        let payload = payload(hook, &host, event, summary, body, status::unix_now()).to_string();
+        let spawned = thread::Builder::new().name("webhook".into()).spawn(move || {
+            if let Err(e) = post(&url, &payload) {
+                warn!("Webhook failed: {e}");
+            }
+        });
+        if let Err(e) = spawned {
+            warn!("Couldn't start a thread for the webhook: {e}");
+        }
+    }
+}
+
+// As notify::clear_error: after a successful transition, the next error
+// is posted even if it's the same as the last.
+pub fn clear_error() {
+    *LAST_ERROR.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+// The JSON body `hook` expects.
+fn payload(hook: &WebhookConfig, host: &str, event: Event, summary: &str, body: &str, now: u64) -> Value {
+    match hook.format {
+        Format::Generic => json!({ "host": host, "event": event, "summary": summary, "body": body, "time": now }),
+        // Plain text: Telegram's Markdown would need every '.' and '-' escaped.
+        Format::Telegram => json!({
+            "chat_id": hook.chat_id,
+            "text": format!("{host}: {summary}\n{body}"),
+            "disable_web_page_preview": true,
+        }),
+        Format::Discord => json!({
+            "username": "VitaminK",
+            "content": format!("**{host}: {summary}**\n{body}"),
+            "allowed_mentions": { "parse": [] },
+        }),
+    }
+}
+
+fn post(url: &str, payload: &str) -> crate::error::Result<()> {
+    let mut cmd = Command::new("curl");
+    cmd.args(["--silent", "--show-error", "--fail", "--max-time", "10", "--config", "-"])
+        .args(["--header", "Content-Type: application/json"]);
+    let input = format!("url = {}\ndata = {}\n", quote(url), quote(payload));
+    let output = process::output_with_input(&mut cmd, input.as_bytes())?;
+    if !output.status.success() {
+        return Err(crate::error::VitaminkError::command_failed("webhook POST", &output));
+    }
+    debug!("Webhook posted: {payload}");
+    Ok(())
+}
+
+// A quoted string in a curl config file.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// To tell several PCs apart in one chat.
+fn hostname() -> String {
+    let name = fs::read_to_string("/proc/sys/kernel/hostname").unwrap_or_default();
+    let name = name.trim();
+    if name.is_empty() { "pc".to_string() } else { name.to_string() }
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hook(format: Format, chat_id: Option<&str>) -> WebhookConfig {
+        WebhookConfig {
+            url: "https://example.com/hook".to_string(),
+            format,
+            chat_id: chat_id.map(str::to_string),
+            events: vec![Event::Transition, Event::Error],
+        }
+    }
+
+    #[test]
+    fn test_payload() {
+        let generic = payload(&hook(Format::Generic, None), "den", Event::Error, "Dummy plug unplugged", "HDMI-A-1 disconnected", 1000);
+        assert_eq!(
+            generic,
+            json!({ "host": "den", "event": "error", "summary": "Dummy plug unplugged", "body": "HDMI-A-1 disconnected", "time": 1000 })
+        );
+
+        let telegram = payload(&hook(Format::Telegram, Some("42")), "den", Event::Transition, "Switched to Away mode", "AtDesk → Away", 1000);
+        assert_eq!(telegram["chat_id"], "42");
+        assert_eq!(telegram["text"], "den: Switched to Away mode\nAtDesk → Away");
+
+        let discord = payload(&hook(Format::Discord, None), "den", Event::Transition, "Switched to Away mode", "AtDesk → Away", 1000);
+        assert_eq!(discord["content"], "**den: Switched to Away mode**\nAtDesk → Away");
+        assert_eq!(discord["allowed_mentions"]["parse"], json!([]));
+    }
+
+    #[test]
+    fn test_quote() {
+        let body = json!({ "text": "say \"hi\"\nC:\\" }).to_string();
+        assert_eq!(quote(&body), r#""{\"text\":\"say \\\"hi\\\"\\nC:\\\\\"}""#);
+    }
+}
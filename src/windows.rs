@@ -0,0 +1,194 @@
+// src/windows.rs — Window placements kept across Away, through KWin scripts
+//
+// Turning the dummy plug on makes KWin spread windows onto it, and turning
+// it off again piles them up wherever KWin sees fit. With
+//
+//   [windows]
+//   restore = true
+//   settle = "2s"
+//
+// going Away records where each normal window is, right after the display
+// layout is saved, and AtDesk puts them back once the layout is restored
+// and `settle` has passed, so KWin's own shuffling is over by then.
+//
+// Only KWin's scripts can get at its windows, so both halves are small
+// scripts loaded over D-Bus (org.kde.kwin.Scripting). Each one answers by
+// calling `Report` on an object this module serves on a session bus
+// connection of its own for as long as the script runs. Windows are
+// matched by KWin's internal id: one closed and opened again while Away
+// is left where KWin put it.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::time::Duration;
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::dbus;
+use crate::error::{Result, VitaminkError};
+
+// What the scripts are loaded as.
+const PLUGIN: &str = "vitamink-windows";
+const REPORT_PATH: &str = "/org/vitamink/Windows";
+// How long a script gets to call back.
+const REPORT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Where a window was.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Placement {
+    // KWin's internalId, a UUID.
+    pub id: String,
+    // The window class, e.g. "firefox", for the log.
+    pub class: String,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    pub fullscreen: bool,
+}
+
+// Lists the normal windows, KWin 6 or 5, and passes a value back.
+const PRELUDE: &str = r#"
+function windows() {
+    const all = typeof workspace.windowList === "function" ? workspace.windowList() : workspace.clientList();
+    return all.filter(w => w.normalWindow && !w.skipTaskbar);
+}
+function report(value) {
+    callDBus("@BUS@", "@PATH@", "org.vitamink.Windows", "Report", JSON.stringify(value));
+}
+"#;
+
+const RECORD: &str = r#"
+report(windows().map(w => ({
+    id: String(w.internalId),
+    class: String(w.resourceClass),
+    x: w.frameGeometry.x,
+    y: w.frameGeometry.y,
+    width: w.frameGeometry.width,
+    height: w.frameGeometry.height,
+    fullscreen: w.fullScreen,
+})));
+"#;
+
+const RESTORE: &str = r#"
+const saved = @SAVED@;
+let moved = 0;
+for (const w of windows()) {
+    const p = saved.find(p => p.id === String(w.internalId));
+    if (!p) {
+        continue;
+    }
+    if (w.fullScreen) {
+        w.fullScreen = false;
+    }
+    w.frameGeometry = { x: p.x, y: p.y, width: p.width, height: p.height };
+    if (p.fullscreen) {
+        w.fullScreen = true;
+    }
+    moved++;
+}
+report(moved);
+"#;
+
+// Where each normal window is now.
+pub fn record() -> Result<Vec<Placement>> {
+    let answer = run(RECORD)?;
+    serde_json::from_str(&answer).map_err(|e| VitaminkError::Parse(format!("Unexpected window list from KWin: {e}")))
+}
+
+// Moves the windows in `placements` that are still open back; returns how
+// many that was.
+pub fn restore(placements: &[Placement]) -> Result<u32> {
+    // Serializing plain data can't fail.
+    let saved = serde_json::to_string(placements).expect("placements serialize");
+    let answer = run(&RESTORE.replace("@SAVED@", &saved))?;
+    answer.trim().parse().map_err(|_| VitaminkError::Parse(format!("Unexpected answer from KWin's script: {answer}")))
+}
+
+// ---- Scripting ----
+
+// Receives the script's `report(...)`.
+struct Reporter {
+    answers: Sender<String>,
+}
+
+#[zbus::interface(name = "org.vitamink.Windows")]
+impl Reporter {
+    fn report(&self, json: String) {
+        let _ = self.answers.send(json);
+    }
+}
+
+// Loads `body` into KWin, runs it, and returns what it reported.
+fn run(body: &str) -> Result<String> {
+    let (answers, answer) = mpsc::channel();
+    let own = zbus::blocking::connection::Builder::session()
+        .and_then(|b| b.serve_at(REPORT_PATH, Reporter { answers }))
+        .and_then(|b| b.build())
+        .map_err(|e| VitaminkError::dbus("Couldn't serve the window report", e))?;
+    let bus = own.unique_name().map(|name| name.to_string()).ok_or_else(|| VitaminkError::Unavailable("No unique bus name to report to".to_string()))?;
+
+    let script = script(&bus, body);
+    let path = script_path();
+    fs::write(&path, script).map_err(|e| VitaminkError::io(&path, e))?;
+    let result = load_and_start(&path).and_then(|()| {
+        answer.recv_timeout(REPORT_TIMEOUT).map_err(|_| VitaminkError::Timeout("KWin's window script didn't report back".to_string()))
+    });
+    unload();
+    let _ = fs::remove_file(&path);
+    result
+}
+
+fn script(bus: &str, body: &str) -> String {
+    PRELUDE.replace("@BUS@", bus).replace("@PATH@", REPORT_PATH) + body
+}
+
+// Somewhere KWin, running as the same user, can read it.
+fn script_path() -> PathBuf {
+    let dir = env::var_os("XDG_RUNTIME_DIR").map_or_else(env::temp_dir, PathBuf::from);
+    dir.join(format!("vitamink-windows-{}.js", std::process::id()))
+}
+
+fn load_and_start(path: &Path) -> Result<()> {
+    let conn = dbus::session()?;
+    // One left over from a run that died half way would block the name.
+    unload();
+    let id: i32 = dbus::call(&conn, "org.kde.KWin", "/Scripting", "org.kde.kwin.Scripting", "loadScript", &(path.display().to_string(), PLUGIN))?;
+    if id < 0 {
+        return Err(VitaminkError::Unavailable("KWin wouldn't load the window script".to_string()));
+    }
+    dbus::call::<()>(&conn, "org.kde.KWin", "/Scripting", "org.kde.kwin.Scripting", "start", &())
+}
+
+fn unload() {
+    let unloaded = dbus::session().and_then(|conn| {
+        dbus::call::<bool>(&conn, "org.kde.KWin", "/Scripting", "org.kde.kwin.Scripting", "unloadScript", &(PLUGIN,))
+    });
+    if let Err(e) = unloaded {
+        debug!("Unloading the window script: {e}");
+    }
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_script() {
+        let record = script(":1.42", RECORD);
+        assert!(record.contains(r#"callDBus(":1.42", "/org/vitamink/Windows", "org.vitamink.Windows", "Report""#));
+        assert!(!record.contains('@'));
+
+        // What the record script reports is what restore writes back in.
+        let json = r#"[{"id":"{0d1c}","class":"firefox","x":0,"y":0,"width":1280.5,"height":1440,"fullscreen":false}]"#;
+        let placements: Vec<Placement> = serde_json::from_str(json).unwrap();
+        assert_eq!(placements[0].width, 1280.5);
+        let saved = serde_json::to_string(&placements).unwrap();
+        assert!(RESTORE.replace("@SAVED@", &saved).contains(r#"const saved = [{"id":"{0d1c}","class":"firefox","x":0.0"#));
+    }
+}
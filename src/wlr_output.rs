@@ -0,0 +1,562 @@
+// src/wlr_output.rs — Output control over the Wayland protocol itself
+//
+// The same wlr-output-management protocol wlr-randr speaks (see
+// wlr_randr.rs), spoken directly: no program to install, no fork per
+// call, no text to parse. Hyprland, Sway, river, labwc and recent KWin
+// all implement zwlr_output_manager_v1. Plasma's own kde_output_device
+// protocol isn't covered; on Plasma without it, kscreen-doctor remains
+// the way.
+//
+// Each call opens its own connection to the compositor, reads every head
+// (output) and its modes, and for a change builds one configuration
+// covering all of them — the protocol rejects a configuration that
+// leaves a head out — with the heads we're not touching kept as they
+// are. DPMS goes through wlr-output-power-management, which the same
+// compositors offer, instead of their IPC.
+//
+// `watch_changes` keeps a connection open and tells topology.rs whenever
+// the compositor announces a new output configuration, so changes made
+// in the desktop's settings are noticed without waiting for MAX_AGE.
+//
+// Only built with the `wlr-output` feature (it pulls in wayland-client),
+// and only used with `display_backend = "wlr-output"`.
+
+use std::env;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::thread;
+
+use log::{debug, info, warn};
+use wayland_client::globals::{GlobalList, GlobalListContents, registry_queue_init};
+use wayland_client::protocol::{wl_output, wl_registry};
+use wayland_client::{Connection, Dispatch, EventQueue, Proxy, QueueHandle, WEnum, delegate_noop, event_created_child};
+use wayland_protocols_wlr::output_management::v1::client::{
+    zwlr_output_configuration_head_v1::ZwlrOutputConfigurationHeadV1,
+    zwlr_output_configuration_v1::{self, ZwlrOutputConfigurationV1},
+    zwlr_output_head_v1::{self, ZwlrOutputHeadV1},
+    zwlr_output_manager_v1::{self, ZwlrOutputManagerV1},
+    zwlr_output_mode_v1::{self, ZwlrOutputModeV1},
+};
+use wayland_protocols_wlr::output_power_management::v1::client::{
+    zwlr_output_power_manager_v1::ZwlrOutputPowerManagerV1,
+    zwlr_output_power_v1::{self, ZwlrOutputPowerV1},
+};
+
+use crate::display::{self, Color, CompositorBackend, ConnectionState, Display, DisplayState, Geometry, Mode, OutputChange, Rotation};
+use crate::error::{Result, VitaminkError};
+use crate::layout::Layout;
+use crate::process;
+use crate::topology;
+
+pub struct WlrOutputBackend;
+
+impl CompositorBackend for WlrOutputBackend {
+    fn name(&self) -> &'static str {
+        "wlr-output"
+    }
+
+    fn get_displays(&self) -> Result<Vec<Display>> {
+        Ok(Session::connect()?.displays())
+    }
+
+    fn enable_output(&self, display: &Display, mode: &Mode, scale: Option<f64>) -> Result<()> {
+        let target = Target { mode: Some(mode.id), scale, ..Target::default() };
+        configure(&format!("enable {} at {}x{}@{}", display.name, mode.width, mode.height, mode.refresh), |d| {
+            if d.name == display.name { Setting::On(target) } else { Setting::Keep }
+        })
+    }
+
+    fn disable_output(&self, name: &str) -> Result<()> {
+        configure(&format!("disable {name}"), |d| if d.name == name { Setting::Off } else { Setting::Keep })
+    }
+
+    // All of them in one configuration, which is what the protocol is for.
+    fn apply_changes(&self, changes: &[OutputChange]) -> Result<()> {
+        let mut described = Vec::new();
+        for change in changes {
+            match change {
+                OutputChange::Enable { display, mode, .. } => described.push(format!("enable {} at {}x{}", display.name, mode.width, mode.height)),
+                OutputChange::Disable(name) => described.push(format!("disable {name}")),
+            }
+        }
+        configure(&described.join(", "), |d| {
+            let change = changes.iter().find(|change| match change {
+                OutputChange::Enable { display, .. } => display.name == d.name,
+                OutputChange::Disable(name) => *name == d.name,
+            });
+            match change {
+                Some(OutputChange::Enable { mode, scale, .. }) => Setting::On(Target { mode: Some(mode.id), scale: *scale, ..Target::default() }),
+                Some(OutputChange::Disable(_)) => Setting::Off,
+                None => Setting::Keep,
+            }
+        })
+    }
+
+    fn apply_layout(&self, layout: &Layout) -> Result<()> {
+        configure("restore the layout", |d| match layout.outputs.iter().find(|o| o.name == d.name) {
+            Some(o) if o.enabled => Setting::On(Target {
+                mode: o.find_mode(&d.modes).map(|m| m.id),
+                position: o.position,
+                scale: o.scale,
+                rotation: o.rotation,
+            }),
+            Some(_) => Setting::Off,
+            // Not in the saved layout: plugged in since.
+            None => Setting::Keep,
+        })
+    }
+
+    fn set_all_dpms(&self, on: bool) -> Result<()> {
+        if process::is_dry_run() {
+            info!("[dry-run] wlr-output: turn every output {}", if on { "on" } else { "off" });
+            return Ok(());
+        }
+        let mut session = Session::connect()?;
+        let qh = session.queue.handle();
+        let power = session
+            .globals
+            .bind::<ZwlrOutputPowerManagerV1, _, _>(&qh, 1..=1, ())
+            .map_err(|e| VitaminkError::Unavailable(format!("The compositor has no output power management: {e}")))?;
+
+        let outputs: Vec<wl_output::WlOutput> = session.globals.contents().with_list(|globals| {
+            globals
+                .iter()
+                .filter(|g| g.interface == wl_output::WlOutput::interface().name)
+                .map(|g| session.globals.registry().bind::<wl_output::WlOutput, _, _>(g.name, g.version.min(4), &qh, ()))
+                .collect()
+        });
+        let mode = if on { zwlr_output_power_v1::Mode::On } else { zwlr_output_power_v1::Mode::Off };
+        let controls: Vec<ZwlrOutputPowerV1> = outputs
+            .iter()
+            .map(|output| {
+                let control = power.get_output_power(output, &qh, ());
+                control.set_mode(mode);
+                control
+            })
+            .collect();
+        session.roundtrip()?;
+
+        for control in controls {
+            control.destroy();
+        }
+        power.destroy();
+        for output in outputs {
+            output.release();
+        }
+        session.roundtrip()?;
+        match session.state.power_failed {
+            0 => Ok(()),
+            n => Err(VitaminkError::Unavailable(format!("{n} output(s) refused power management (another client holds it?)"))),
+        }
+    }
+
+    fn raw_output(&self, name: &str) -> Result<Vec<String>> {
+        let session = Session::connect()?;
+        Ok(session.state.heads.iter().filter(|h| h.head.name == name).map(|h| format!("{:#?}", h.head)).collect())
+    }
+
+    fn watch_changes(&self) -> Result<()> {
+        let mut session = Session::connect()?;
+        thread::Builder::new()
+            .name("wlr-output".into())
+            .spawn(move || {
+                let mut seen = session.state.configurations;
+                loop {
+                    if let Err(e) = session.queue.blocking_dispatch(&mut session.state) {
+                        warn!("Stopped following output changes: {e}");
+                        return;
+                    }
+                    if session.state.configurations != seen {
+                        seen = session.state.configurations;
+                        debug!("The compositor changed the outputs");
+                        topology::manager().hotplug();
+                    }
+                }
+            })
+            .map_err(|e| VitaminkError::io("wlr-output thread", e))?;
+        Ok(())
+    }
+}
+
+// ---- Connection ----
+
+// One connection, with every head's state read.
+struct Session {
+    globals: GlobalList,
+    queue: EventQueue<State>,
+    manager: ZwlrOutputManagerV1,
+    state: State,
+}
+
+impl Session {
+    fn connect() -> Result<Self> {
+        let path = socket_path();
+        let stream = UnixStream::connect(&path)
+            .map_err(|e| VitaminkError::Unavailable(format!("Can't reach the compositor at {}: {e}", path.display())))?;
+        let conn = Connection::from_socket(stream).map_err(|e| VitaminkError::Wayland(e.to_string()))?;
+        let (globals, queue) = registry_queue_init::<State>(&conn).map_err(|e| VitaminkError::Wayland(e.to_string()))?;
+        let manager = globals
+            .bind::<ZwlrOutputManagerV1, _, _>(&queue.handle(), 1..=4, ())
+            .map_err(|e| VitaminkError::Unavailable(format!("The compositor doesn't offer wlr-output-management: {e}")))?;
+
+        let mut session = Self { globals, queue, manager, state: State::default() };
+        // Heads, then their modes and properties, then `done`.
+        while session.state.serial.is_none() {
+            session.roundtrip()?;
+        }
+        Ok(session)
+    }
+
+    fn roundtrip(&mut self) -> Result<()> {
+        self.queue.roundtrip(&mut self.state).map(|_| ()).map_err(|e| VitaminkError::Wayland(e.to_string()))
+    }
+
+    fn displays(&self) -> Vec<Display> {
+        self.state.heads.iter().enumerate().map(|(i, h)| into_display(i as u32 + 1, &h.head)).collect()
+    }
+}
+
+// Where WAYLAND_DISPLAY points, as the commands we run would find it.
+fn socket_path() -> PathBuf {
+    let name = display::wayland_env().into_iter().find(|(key, _)| *key == "WAYLAND_DISPLAY").map(|(_, v)| v).unwrap_or_default();
+    let runtime = env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from).unwrap_or_default();
+    runtime.join(name)
+}
+
+// ---- Changes ----
+
+// What to do with one head in a configuration.
+enum Setting {
+    // As it is now.
+    Keep,
+    Off,
+    On(Target),
+}
+
+// Unset fields keep the head's current value (or the compositor's pick,
+// for a head that was off).
+#[derive(Clone, Copy, Default)]
+struct Target {
+    // An index into the head's modes, as in `Display::modes`.
+    mode: Option<u32>,
+    position: Option<(i32, i32)>,
+    scale: Option<f64>,
+    rotation: Option<Rotation>,
+}
+
+// Applies one configuration built from `plan`, which is asked about every
+// head. `what` is for the log.
+fn configure(what: &str, plan: impl Fn(&Display) -> Setting) -> Result<()> {
+    let mut session = Session::connect()?;
+    let displays = session.displays();
+    if process::is_dry_run() {
+        info!("[dry-run] wlr-output: {what}");
+        return Ok(());
+    }
+    debug!("wlr-output: {what}");
+
+    let qh = session.queue.handle();
+    let serial = session.state.serial.unwrap_or_default();
+    let config = session.manager.create_configuration(serial, &qh, ());
+    for (entry, display) in session.state.heads.iter().zip(&displays) {
+        let enabled = display.state == DisplayState::Enabled;
+        let target = match plan(display) {
+            Setting::Off => None,
+            Setting::Keep if !enabled => None,
+            // Spelled out: not every compositor keeps what isn't set.
+            Setting::Keep => Some(Target {
+                mode: None,
+                position: Some(entry.head.position),
+                scale: Some(entry.head.scale),
+                rotation: entry.head.rotation,
+            }),
+            Setting::On(target) => Some(target),
+        };
+        let Some(target) = target else {
+            config.disable_head(&entry.proxy);
+            continue;
+        };
+
+        let head = config.enable_head(&entry.proxy, &qh, ());
+        let mode = target.mode.map(|id| id as usize).or(entry.head.current).or_else(|| entry.head.modes.iter().position(|m| m.preferred));
+        if let Some(mode) = mode.and_then(|i| entry.modes.get(i)) {
+            head.set_mode(mode);
+        }
+        if let Some((x, y)) = target.position.or(enabled.then_some(entry.head.position)) {
+            head.set_position(x, y);
+        }
+        if let Some(scale) = target.scale {
+            head.set_scale(scale);
+        }
+        if let Some(rotation) = target.rotation {
+            head.set_transform(transform(rotation));
+        }
+    }
+    config.apply();
+
+    session.state.outcome = None;
+    while session.state.outcome.is_none() {
+        session.queue.blocking_dispatch(&mut session.state).map_err(|e| VitaminkError::Wayland(e.to_string()))?;
+    }
+    config.destroy();
+    match session.state.outcome {
+        Some(Outcome::Succeeded) => Ok(()),
+        // The outputs changed under us; worth another go.
+        Some(Outcome::Cancelled) => Err(VitaminkError::Wayland(format!("Couldn't {what}: the outputs changed meanwhile"))),
+        _ => Err(VitaminkError::Unavailable(format!("The compositor refused to {what}"))),
+    }
+}
+
+// KScreen's Left is a quarter turn counter-clockwise, which is Wayland's 90.
+fn transform(rotation: Rotation) -> wl_output::Transform {
+    match rotation {
+        Rotation::Normal => wl_output::Transform::Normal,
+        Rotation::Left => wl_output::Transform::_90,
+        Rotation::Inverted => wl_output::Transform::_180,
+        Rotation::Right => wl_output::Transform::_270,
+    }
+}
+
+// ---- Protocol State ----
+
+// What the compositor told us about one head.
+#[derive(Debug, Default)]
+struct Head {
+    name: String,
+    enabled: bool,
+    modes: Vec<HeadMode>,
+    // Index into `modes`.
+    current: Option<usize>,
+    position: (i32, i32),
+    scale: f64,
+    // None for the flipped transforms, which we don't model.
+    rotation: Option<Rotation>,
+}
+
+#[derive(Debug, Default, Clone)]
+struct HeadMode {
+    width: i32,
+    height: i32,
+    // mHz; 0 if the compositor didn't say.
+    refresh: i32,
+    preferred: bool,
+}
+
+struct HeadEntry {
+    proxy: ZwlrOutputHeadV1,
+    // Parallel to `head.modes`.
+    modes: Vec<ZwlrOutputModeV1>,
+    head: Head,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Outcome {
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Default)]
+struct State {
+    heads: Vec<HeadEntry>,
+    // From the last `done`, needed to create a configuration.
+    serial: Option<u32>,
+    // How many `done`s so far: each is a new output configuration.
+    configurations: u32,
+    outcome: Option<Outcome>,
+    power_failed: usize,
+}
+
+impl State {
+    fn head(&mut self, proxy: &ZwlrOutputHeadV1) -> Option<&mut HeadEntry> {
+        self.heads.iter_mut().find(|entry| entry.proxy == *proxy)
+    }
+}
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for State {
+    fn event(_: &mut Self, _: &wl_registry::WlRegistry, _: wl_registry::Event, _: &GlobalListContents, _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<ZwlrOutputManagerV1, ()> for State {
+    fn event(state: &mut Self, _: &ZwlrOutputManagerV1, event: zwlr_output_manager_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        match event {
+            zwlr_output_manager_v1::Event::Head { head } => {
+                state.heads.push(HeadEntry { proxy: head, modes: Vec::new(), head: Head { scale: 1.0, ..Head::default() } });
+            }
+            zwlr_output_manager_v1::Event::Done { serial } => {
+                state.serial = Some(serial);
+                state.configurations += 1;
+            }
+            _ => {}
+        }
+    }
+
+    event_created_child!(State, ZwlrOutputManagerV1, [
+        zwlr_output_manager_v1::EVT_HEAD_OPCODE => (ZwlrOutputHeadV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrOutputHeadV1, ()> for State {
+    fn event(state: &mut Self, proxy: &ZwlrOutputHeadV1, event: zwlr_output_head_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        if let zwlr_output_head_v1::Event::Finished = event {
+            state.heads.retain(|entry| entry.proxy != *proxy);
+            return;
+        }
+        let Some(entry) = state.head(proxy) else {
+            return;
+        };
+        match event {
+            zwlr_output_head_v1::Event::Name { name } => entry.head.name = name,
+            zwlr_output_head_v1::Event::Mode { mode } => {
+                entry.modes.push(mode);
+                entry.head.modes.push(HeadMode::default());
+            }
+            zwlr_output_head_v1::Event::Enabled { enabled } => entry.head.enabled = enabled != 0,
+            zwlr_output_head_v1::Event::CurrentMode { mode } => entry.head.current = entry.modes.iter().position(|m| *m == mode),
+            zwlr_output_head_v1::Event::Position { x, y } => entry.head.position = (x, y),
+            zwlr_output_head_v1::Event::Scale { scale } => entry.head.scale = scale,
+            zwlr_output_head_v1::Event::Transform { transform: WEnum::Value(transform) } => {
+                entry.head.rotation = match transform {
+                    wl_output::Transform::Normal => Some(Rotation::Normal),
+                    wl_output::Transform::_90 => Some(Rotation::Left),
+                    wl_output::Transform::_180 => Some(Rotation::Inverted),
+                    wl_output::Transform::_270 => Some(Rotation::Right),
+                    _ => None,
+                };
+            }
+            _ => {}
+        }
+    }
+
+    event_created_child!(State, ZwlrOutputHeadV1, [
+        zwlr_output_head_v1::EVT_MODE_OPCODE => (ZwlrOutputModeV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrOutputModeV1, ()> for State {
+    fn event(state: &mut Self, proxy: &ZwlrOutputModeV1, event: zwlr_output_mode_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        let Some(mode) = state
+            .heads
+            .iter_mut()
+            .find_map(|entry| entry.modes.iter().position(|m| m == proxy).map(|i| &mut entry.head.modes[i]))
+        else {
+            return;
+        };
+        match event {
+            zwlr_output_mode_v1::Event::Size { width, height } => (mode.width, mode.height) = (width, height),
+            zwlr_output_mode_v1::Event::Refresh { refresh } => mode.refresh = refresh,
+            zwlr_output_mode_v1::Event::Preferred => mode.preferred = true,
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZwlrOutputConfigurationV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrOutputConfigurationV1,
+        event: zwlr_output_configuration_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        state.outcome = match event {
+            zwlr_output_configuration_v1::Event::Succeeded => Some(Outcome::Succeeded),
+            zwlr_output_configuration_v1::Event::Failed => Some(Outcome::Failed),
+            zwlr_output_configuration_v1::Event::Cancelled => Some(Outcome::Cancelled),
+            _ => state.outcome,
+        };
+    }
+}
+
+impl Dispatch<ZwlrOutputPowerV1, ()> for State {
+    fn event(state: &mut Self, _: &ZwlrOutputPowerV1, event: zwlr_output_power_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        if let zwlr_output_power_v1::Event::Failed = event {
+            state.power_failed += 1;
+        }
+    }
+}
+
+delegate_noop!(State: ZwlrOutputConfigurationHeadV1);
+delegate_noop!(State: ZwlrOutputPowerManagerV1);
+delegate_noop!(State: ignore wl_output::WlOutput);
+
+// ---- Conversion ----
+
+fn into_display(index: u32, head: &Head) -> Display {
+    let modes: Vec<Mode> = head
+        .modes
+        .iter()
+        .enumerate()
+        .map(|(id, m)| Mode {
+            id: id as u32,
+            width: m.width.max(0) as u32,
+            height: m.height.max(0) as u32,
+            refresh: f64::from(m.refresh) / 1000.0,
+            preferred: m.preferred,
+            current: head.enabled && head.current == Some(id),
+        })
+        .collect();
+
+    let geometry = modes.iter().find(|m| m.current).map(|mode| {
+        let (width, height) =
+            if head.rotation.is_some_and(Rotation::is_sideways) { (mode.height, mode.width) } else { (mode.width, mode.height) };
+        Geometry {
+            x: head.position.0,
+            y: head.position.1,
+            width: (f64::from(width) / head.scale).round() as u32,
+            height: (f64::from(height) / head.scale).round() as u32,
+        }
+    });
+
+    Display {
+        index,
+        name: head.name.clone(),
+        uuid: None,
+        state: if head.enabled { DisplayState::Enabled } else { DisplayState::Disabled },
+        // Only connected heads are advertised.
+        connection: ConnectionState::Connected,
+        modes,
+        geometry,
+        scale: head.enabled.then_some(head.scale),
+        priority: None,
+        color: Color::default(),
+        vrr: None,
+        rotation: head.rotation,
+        overscan: None,
+    }
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_into_display() {
+        let head = Head {
+            name: "DP-1".to_string(),
+            enabled: true,
+            modes: vec![
+                HeadMode { width: 3840, height: 2160, refresh: 59_997, preferred: true },
+                HeadMode { width: 1920, height: 1080, refresh: 60_000, preferred: false },
+            ],
+            current: Some(0),
+            position: (1920, 0),
+            scale: 1.5,
+            rotation: Some(Rotation::Left),
+        };
+        let display = into_display(1, &head);
+        assert_eq!(display.state, DisplayState::Enabled);
+        assert_eq!(display.modes[0].refresh, 59.997);
+        assert!(display.modes[0].current && !display.modes[1].current);
+        // Turned sideways: the logical size is taller than wide.
+        assert_eq!(display.geometry, Some(Geometry { x: 1920, y: 0, width: 1440, height: 2560 }));
+
+        let off = into_display(2, &Head { enabled: false, ..head });
+        assert_eq!(off.state, DisplayState::Disabled);
+        assert_eq!(off.geometry, None);
+        assert_eq!(off.scale, None);
+    }
+}
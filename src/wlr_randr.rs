@@ -0,0 +1,357 @@
+// src/wlr_randr.rs — Output control on wlroots compositors via `wlr-randr`
+//
+// Hyprland, Sway, river, etc. all implement wlr-output-management, and
+// `wlr-randr` is the standard CLI for it. `wlr-randr --json` gives us the
+// outputs; `wlr-randr --output X --on --mode WxH@RHz ...` changes them,
+// with every `--output` in one call applied as a single configuration.
+//
+// wlr-randr has no mode ids, so a mode's id here is just its index in the
+// output's mode list. Mode ids only need to round-trip within one session.
+//
+// Blanking isn't part of the protocol, so DPMS goes through the
+// compositor's own IPC (`hyprctl` or `swaymsg`) when we recognise it.
+
+use std::env;
+use std::process::Command;
+
+use serde::Deserialize;
+
+use crate::display::{self, Color, CompositorBackend, ConnectionState, Display, DisplayState, Geometry, Mode, Rotation};
+use crate::error::{Result, VitaminkError};
+use crate::layout::Layout;
+use crate::process;
+
+pub struct WlrRandrBackend;
+
+impl CompositorBackend for WlrRandrBackend {
+    fn name(&self) -> &'static str {
+        "wlr-randr"
+    }
+
+    fn get_displays(&self) -> Result<Vec<Display>> {
+        parse(&run(&["--json"])?)
+    }
+
+    fn enable_output(&self, display: &Display, mode: &Mode, scale: Option<f64>) -> Result<()> {
+        let mode = mode_arg(mode);
+        let mut args = vec!["--output", &display.name, "--on", "--mode", &mode];
+        let scale = scale.map(|s| s.to_string());
+        if let Some(scale) = &scale {
+            args.extend(["--scale", scale]);
+        }
+        apply(&args)
+    }
+
+    fn disable_output(&self, name: &str) -> Result<()> {
+        apply(&["--output", name, "--off"])
+    }
+
+    fn apply_layout(&self, layout: &Layout) -> Result<()> {
+        let displays = self.get_displays()?;
+        let args = layout_args(layout, &displays);
+        if args.is_empty() {
+            return Ok(());
+        }
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        apply(&args)
+    }
+
+    fn set_all_dpms(&self, on: bool) -> Result<()> {
+        dpms(None, on)
+    }
+
+    fn set_dpms(&self, name: &str, on: bool) -> Result<()> {
+        dpms(Some(name), on)
+    }
+}
+
+// Blanks or wakes `output`, or all of them.
+fn dpms(output: Option<&str>, on: bool) -> Result<()> {
+    let state = if on { "on" } else { "off" };
+    let mut cmd = if env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+        let mut cmd = Command::new("hyprctl");
+        cmd.args(["dispatch", "dpms", state]);
+        cmd.args(output);
+        cmd
+    } else if env::var_os("SWAYSOCK").is_some() {
+        let mut cmd = Command::new("swaymsg");
+        cmd.args(["output", output.unwrap_or("*"), "power", state]);
+        cmd
+    } else {
+        return Err(VitaminkError::Parse(
+            "DPMS control needs Hyprland or Sway; wlr-randr can't blank outputs".to_string(),
+        ));
+    };
+
+    let output = process::run(&mut cmd)?;
+    if !output.status.success() {
+        return Err(VitaminkError::command_failed(format!("{cmd:?}"), &output));
+    }
+    Ok(())
+}
+
+fn wlr_randr(args: &[&str]) -> Command {
+    let mut cmd = Command::new("wlr-randr");
+    for (key, val) in display::wayland_env() {
+        cmd.env(key, val);
+    }
+    cmd.args(args);
+    cmd
+}
+
+fn run(args: &[&str]) -> Result<String> {
+    let output = process::output(&mut wlr_randr(args))?;
+    if !output.status.success() {
+        return Err(VitaminkError::command_failed(format!("wlr-randr {}", args.join(" ")), &output));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+// Like `run`, for changes — skipped in dry-run mode.
+fn apply(args: &[&str]) -> Result<()> {
+    let output = process::run(&mut wlr_randr(args))?;
+    if !output.status.success() {
+        return Err(VitaminkError::command_failed(format!("wlr-randr {}", args.join(" ")), &output));
+    }
+    Ok(())
+}
+
+fn mode_arg(mode: &Mode) -> String {
+    format!("{}x{}@{}Hz", mode.width, mode.height, mode.refresh)
+}
+
+// `--output` groups that recreate `layout`. Outputs that are gone, and
+// modes that no longer exist, are skipped rather than failing the lot.
+fn layout_args(layout: &Layout, displays: &[Display]) -> Vec<String> {
+    let mut args = Vec::new();
+
+    for o in &layout.outputs {
+        let Some(display) = displays.iter().find(|d| d.name == o.name) else {
+            continue;
+        };
+
+        args.extend(["--output".to_string(), o.name.clone()]);
+        if !o.enabled {
+            args.push("--off".to_string());
+            continue;
+        }
+
+        args.push("--on".to_string());
+        if let Some(mode) = o.find_mode(&display.modes) {
+            args.extend(["--mode".to_string(), mode_arg(mode)]);
+        }
+        if let Some((x, y)) = o.position {
+            args.extend(["--pos".to_string(), format!("{x},{y}")]);
+        }
+        if let Some(scale) = o.scale {
+            args.extend(["--scale".to_string(), scale.to_string()]);
+        }
+        if let Some(rotation) = o.rotation {
+            args.extend(["--transform".to_string(), transform(rotation).to_string()]);
+        }
+    }
+
+    args
+}
+
+// KScreen's Left is a quarter turn counter-clockwise, which is Wayland's "90".
+fn transform(rotation: Rotation) -> &'static str {
+    match rotation {
+        Rotation::Normal => "normal",
+        Rotation::Left => "90",
+        Rotation::Inverted => "180",
+        Rotation::Right => "270",
+    }
+}
+
+// ---- Parsing ----
+
+#[derive(Deserialize)]
+struct Output {
+    name: String,
+    enabled: bool,
+    #[serde(default)]
+    modes: Vec<JsonMode>,
+    // Only present while the output is enabled.
+    position: Option<Position>,
+    scale: Option<f64>,
+    // "normal", "90", ... or a "flipped" variant, which we don't model.
+    transform: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JsonMode {
+    width: u32,
+    height: u32,
+    refresh: f64,
+    #[serde(default)]
+    preferred: bool,
+    #[serde(default)]
+    current: bool,
+}
+
+#[derive(Deserialize)]
+struct Position {
+    x: i32,
+    y: i32,
+}
+
+fn parse(json: &str) -> Result<Vec<Display>> {
+    let outputs: Vec<Output> =
+        serde_json::from_str(json).map_err(|e| VitaminkError::Parse(format!("Invalid wlr-randr JSON: {e}")))?;
+
+    Ok(outputs.into_iter().enumerate().map(|(i, o)| into_display(i as u32 + 1, o)).collect())
+}
+
+fn into_display(index: u32, o: Output) -> Display {
+    let modes: Vec<Mode> = o
+        .modes
+        .iter()
+        .enumerate()
+        .map(|(id, m)| Mode {
+            id: id as u32,
+            width: m.width,
+            height: m.height,
+            refresh: m.refresh,
+            preferred: m.preferred,
+            current: m.current,
+        })
+        .collect();
+
+    let rotation = o.transform.as_deref().and_then(|t| {
+        [Rotation::Normal, Rotation::Left, Rotation::Inverted, Rotation::Right].into_iter().find(|&r| transform(r) == t)
+    });
+    let geometry = match (&o.position, modes.iter().find(|m| m.current)) {
+        (Some(pos), Some(mode)) if o.enabled => {
+            let scale = o.scale.unwrap_or(1.0);
+            let (width, height) =
+                if rotation.is_some_and(Rotation::is_sideways) { (mode.height, mode.width) } else { (mode.width, mode.height) };
+            Some(Geometry {
+                x: pos.x,
+                y: pos.y,
+                width: (width as f64 / scale).round() as u32,
+                height: (height as f64 / scale).round() as u32,
+            })
+        }
+        _ => None,
+    };
+
+    Display {
+        index,
+        name: o.name,
+        uuid: None,
+        state: if o.enabled { DisplayState::Enabled } else { DisplayState::Disabled },
+        // wlr-output-management only advertises connected heads.
+        connection: ConnectionState::Connected,
+        modes,
+        geometry,
+        scale: o.scale,
+        priority: None,
+        // wlr-output-management has no HDR controls.
+        color: Color::default(),
+        vrr: None,
+        rotation,
+        overscan: None,
+    }
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::OutputLayout;
+
+    const SAMPLE: &str = r#"[
+      {
+        "name": "DP-1",
+        "description": "Dell Inc. DELL U2720Q",
+        "enabled": true,
+        "modes": [
+          {"width": 3840, "height": 2160, "refresh": 59.997002, "preferred": true, "current": true},
+          {"width": 1920, "height": 1080, "refresh": 60.0, "preferred": false, "current": false}
+        ],
+        "position": {"x": 0, "y": 0},
+        "transform": "normal",
+        "scale": 1.5,
+        "adaptive_sync": false
+      },
+      {
+        "name": "HDMI-A-1",
+        "enabled": false,
+        "modes": [{"width": 1920, "height": 1080, "refresh": 60.0, "preferred": true, "current": false}]
+      }
+    ]"#;
+
+    #[test]
+    fn test_parse() {
+        let displays = parse(SAMPLE).unwrap();
+        assert_eq!(displays.len(), 2);
+
+        let main = &displays[0];
+        assert_eq!(main.name, "DP-1");
+        assert_eq!(main.state, DisplayState::Enabled);
+        assert_eq!(main.modes[1].id, 1);
+        assert_eq!(main.geometry, Some(Geometry { x: 0, y: 0, width: 2560, height: 1440 }));
+
+        let dummy = &displays[1];
+        assert_eq!(dummy.state, DisplayState::Disabled);
+        assert_eq!(dummy.geometry, None);
+        assert!(parse("not json").is_err());
+    }
+
+    #[test]
+    fn test_layout_args() {
+        let displays = parse(SAMPLE).unwrap();
+        let layout = Layout {
+            outputs: vec![
+                OutputLayout {
+                    name: "DP-1".into(),
+                    enabled: true,
+                    mode_id: Some(1),
+                    mode: None,
+                    position: Some((0, 0)),
+                    scale: Some(1.0),
+                    priority: Some(1),
+                    color: Color::default(),
+                    vrr: None,
+                    rotation: None,
+                    overscan: None,
+                },
+                OutputLayout {
+                    name: "HDMI-A-1".into(),
+                    enabled: false,
+                    mode_id: None,
+                    mode: None,
+                    position: None,
+                    scale: None,
+                    priority: None,
+                    color: Color::default(),
+                    vrr: None,
+                    rotation: None,
+                    overscan: None,
+                },
+                OutputLayout {
+                    name: "DP-9".into(),
+                    enabled: true,
+                    mode_id: None,
+                    mode: None,
+                    position: None,
+                    scale: None,
+                    priority: None,
+                    color: Color::default(),
+                    vrr: None,
+                    rotation: None,
+                    overscan: None,
+                },
+            ],
+            checksum: 0,
+        };
+
+        assert_eq!(
+            layout_args(&layout, &displays).join(" "),
+            "--output DP-1 --on --mode 1920x1080@60Hz --pos 0,0 --scale 1 --output HDMI-A-1 --off"
+        );
+    }
+}
@@ -0,0 +1,469 @@
+// src/xrandr.rs — Output control in X11 sessions via `xrandr` and `xset`
+//
+// Plasma on X11 still has kscreen-doctor, but X does a few things its own
+// way: DPMS is the X server's (a proprietary driver may never tell the
+// kernel, so /sys/class/drm keeps saying "On"), and an output that's
+// switched on lands at 0,0 on top of the desk unless it's given a place.
+// So in an X11 session (`XDG_SESSION_TYPE=x11`) outputs are listed and
+// changed with `xrandr`, every `--output` in one call applied together,
+// and DPMS is read and set with `xset`.
+//
+// `xrandr --query` has no mode ids, so like wlr-randr's a mode's id is
+// its index in the output's list. X has no per-output scale either; a
+// profile's `scale` is ignored here.
+//
+// Custom modes (see custom_mode.rs) are `cvt`'s modelines, added with
+// `--newmode` and `--addmode` under cvt's name for them, like
+// "1920x1080_120.00". xrandr picks modes by name, so that's the name
+// they're switched to by.
+
+use std::process::Command;
+use std::sync::Mutex;
+
+use log::debug;
+
+use crate::config::CustomMode;
+use crate::display::{self, Color, CompositorBackend, ConnectionState, Display, DisplayState, DpmsState, Geometry, Mode, OutputChange, Rotation};
+use crate::error::{Result, VitaminkError};
+use crate::layout::Layout;
+use crate::process;
+
+pub struct XrandrBackend;
+
+// The custom modes added since the daemon started, with their names.
+static ADDED: Mutex<Vec<(CustomMode, String)>> = Mutex::new(Vec::new());
+
+impl CompositorBackend for XrandrBackend {
+    fn name(&self) -> &'static str {
+        "xrandr"
+    }
+
+    fn get_displays(&self) -> Result<Vec<Display>> {
+        parse(&run("xrandr", &["--query"])?)
+    }
+
+    fn enable_output(&self, display: &Display, mode: &Mode, scale: Option<f64>) -> Result<()> {
+        self.apply_changes(&[OutputChange::Enable { display, mode, scale }])
+    }
+
+    fn disable_output(&self, name: &str) -> Result<()> {
+        apply("xrandr", &["--output", name, "--off"])
+    }
+
+    fn apply_layout(&self, layout: &Layout) -> Result<()> {
+        let args = layout_args(layout, &self.get_displays()?);
+        if args.is_empty() {
+            return Ok(());
+        }
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        apply("xrandr", &args)
+    }
+
+    fn apply_changes(&self, changes: &[OutputChange]) -> Result<()> {
+        let args = change_args(changes, &self.get_displays()?);
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        apply("xrandr", &args)
+    }
+
+    fn set_all_dpms(&self, on: bool) -> Result<()> {
+        apply("xset", &["dpms", "force", if on { "on" } else { "off" }])
+    }
+
+    fn set_primary(&self, name: &str) -> Result<()> {
+        apply("xrandr", &["--output", name, "--primary"])
+    }
+
+    fn add_mode(&self, custom: &CustomMode) -> Result<()> {
+        let (name, timings) = modeline(custom)?;
+        let mut newmode = vec!["--newmode", name.as_str()];
+        newmode.extend(timings.iter().map(String::as_str));
+        // Fails if X has it from an earlier Away; attaching it is what counts.
+        if let Err(e) = apply("xrandr", &newmode) {
+            debug!("{e}");
+        }
+        apply("xrandr", &["--addmode", &custom.output, &name])?;
+        ADDED.lock().unwrap_or_else(|e| e.into_inner()).push((custom.clone(), name));
+        Ok(())
+    }
+
+    fn remove_mode(&self, custom: &CustomMode) -> Result<()> {
+        let (name, _) = modeline(custom)?;
+        apply("xrandr", &["--delmode", &custom.output, &name])?;
+        // Another output may still have it.
+        if let Err(e) = apply("xrandr", &["--rmmode", &name]) {
+            debug!("{e}");
+        }
+        ADDED.lock().unwrap_or_else(|e| e.into_inner()).retain(|(added, _)| added != custom);
+        Ok(())
+    }
+
+    // The X server blanks every output together, so its answer goes for
+    // each of them. None with DPMS turned off in X, or no X to ask.
+    fn dpms(&self, _name: &str) -> Option<DpmsState> {
+        match run("xset", &["q"]) {
+            Ok(text) => parse_dpms(&text),
+            Err(e) => {
+                debug!("Can't ask X about DPMS: {e}");
+                None
+            }
+        }
+    }
+}
+
+fn command(program: &str, args: &[&str]) -> Command {
+    let mut cmd = Command::new(program);
+    for (key, val) in display::wayland_env() {
+        cmd.env(key, val);
+    }
+    cmd.args(args);
+    cmd
+}
+
+// Queries: always run, even in dry-run mode.
+fn run(program: &str, args: &[&str]) -> Result<String> {
+    let output = process::output(&mut command(program, args))?;
+    if !output.status.success() {
+        return Err(VitaminkError::command_failed(format!("{program} {}", args.join(" ")), &output));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+// Changes: only logged in dry-run mode.
+fn apply(program: &str, args: &[&str]) -> Result<()> {
+    let output = process::run(&mut command(program, args))?;
+    if !output.status.success() {
+        return Err(VitaminkError::command_failed(format!("{program} {}", args.join(" ")), &output));
+    }
+    Ok(())
+}
+
+fn mode_args(output: &str, mode: &Mode) -> Vec<String> {
+    let added = ADDED.lock().unwrap_or_else(|e| e.into_inner());
+    let custom = added.iter().find(|(custom, _)| {
+        let target = &custom.mode;
+        custom.output == output
+            && (target.width, target.height) == (mode.width, mode.height)
+            && target.refresh.is_some_and(|hz| (mode.refresh - hz).abs() < 0.5)
+    });
+    match custom {
+        Some((_, name)) => vec!["--mode".to_string(), name.clone()],
+        None => vec!["--mode".to_string(), format!("{}x{}", mode.width, mode.height), "--rate".to_string(), format!("{:.2}", mode.refresh)],
+    }
+}
+
+// cvt's name for `custom`'s mode and its timings.
+fn modeline(custom: &CustomMode) -> Result<(String, Vec<String>)> {
+    let mode = &custom.mode;
+    let (width, height, refresh) = (mode.width.to_string(), mode.height.to_string(), mode.refresh.unwrap_or(60.0).to_string());
+    let mut args = vec![width.as_str(), height.as_str(), refresh.as_str()];
+    if custom.reduced_blanking {
+        args.insert(0, "-r");
+    }
+    parse_modeline(&run("cvt", &args)?).ok_or_else(|| VitaminkError::Parse(format!("No modeline in cvt's output for {mode}")))
+}
+
+// `cvt 1920 1080 120`:
+//
+//   # 1920x1080 119.88 Hz (CVT) hsync: 137.14 kHz; pclk: 369.50 MHz
+//   Modeline "1920x1080_120.00"  369.50  1920 2080 2288 2656  1080 1083 1088 1160 -hsync +vsync
+fn parse_modeline(text: &str) -> Option<(String, Vec<String>)> {
+    let line = text.lines().find_map(|line| line.trim().strip_prefix("Modeline "))?;
+    let (name, timings) = line.trim().strip_prefix('"')?.split_once('"')?;
+    Some((name.to_string(), timings.split_whitespace().map(String::from).collect()))
+}
+
+// `--output` groups for `changes`. Outputs switched on go to the right of
+// what's on already, one after the other, instead of X's default of 0,0.
+fn change_args(changes: &[OutputChange], displays: &[Display]) -> Vec<String> {
+    let changed: Vec<&str> = changes
+        .iter()
+        .map(|change| match change {
+            OutputChange::Enable { display, .. } => display.name.as_str(),
+            OutputChange::Disable(name) => name,
+        })
+        .collect();
+    let mut right = displays
+        .iter()
+        .filter(|d| !changed.contains(&d.name.as_str()))
+        .filter_map(|d| d.geometry.map(|g| g.x + g.width as i32))
+        .max()
+        .unwrap_or(0);
+
+    let mut args = Vec::new();
+    for change in changes {
+        match change {
+            OutputChange::Enable { display, mode, scale } => {
+                if scale.is_some_and(|s| s != 1.0) {
+                    debug!("X11 has no per-output scale, leaving {} at 1", display.name);
+                }
+                args.extend(["--output".to_string(), display.name.clone()]);
+                args.extend(mode_args(&display.name, mode));
+                args.extend(["--pos".to_string(), format!("{right}x0")]);
+                right += mode.width as i32;
+            }
+            OutputChange::Disable(name) => args.extend(["--output".to_string(), name.to_string(), "--off".to_string()]),
+        }
+    }
+    args
+}
+
+// `--output` groups that recreate `layout`, skipping outputs that are gone.
+fn layout_args(layout: &Layout, displays: &[Display]) -> Vec<String> {
+    let mut args = Vec::new();
+
+    for o in &layout.outputs {
+        let Some(display) = displays.iter().find(|d| d.name == o.name) else {
+            continue;
+        };
+
+        args.extend(["--output".to_string(), o.name.clone()]);
+        if !o.enabled {
+            args.push("--off".to_string());
+            continue;
+        }
+
+        match o.find_mode(&display.modes) {
+            Some(mode) => args.extend(mode_args(&o.name, mode)),
+            None => args.push("--auto".to_string()),
+        }
+        if let Some((x, y)) = o.position {
+            args.extend(["--pos".to_string(), format!("{x}x{y}")]);
+        }
+        if let Some(rotation) = o.rotation {
+            args.extend(["--rotate".to_string(), rotation_name(rotation).to_string()]);
+        }
+        if o.priority == Some(1) {
+            args.push("--primary".to_string());
+        }
+    }
+
+    args
+}
+
+// xrandr's names are KScreen's: left is a quarter turn counter-clockwise.
+fn rotation_name(rotation: Rotation) -> &'static str {
+    match rotation {
+        Rotation::Normal => "normal",
+        Rotation::Left => "left",
+        Rotation::Inverted => "inverted",
+        Rotation::Right => "right",
+    }
+}
+
+// ---- Parsing ----
+
+// `xrandr --query`:
+//
+//   DP-2 connected primary 2560x1440+0+0 (normal left inverted right x axis y axis) 597mm x 336mm
+//      2560x1440     59.95 + 143.97*
+//      1920x1080     60.00
+//   HDMI-0 connected (normal left inverted right x axis y axis)
+//      3840x2160     60.00 +  30.00
+//
+// An output with a WxH+X+Y is on; `*` marks the current mode, `+` the
+// preferred one, sometimes as a word of their own after the rate.
+fn parse(text: &str) -> Result<Vec<Display>> {
+    let mut displays: Vec<Display> = Vec::new();
+
+    for line in text.lines() {
+        if line.starts_with("Screen ") || line.trim().is_empty() {
+            continue;
+        }
+        if line.starts_with([' ', '\t']) {
+            let Some(display) = displays.last_mut() else {
+                continue;
+            };
+            parse_modes(line, &mut display.modes);
+            continue;
+        }
+
+        let mut words = line.split_whitespace();
+        let (Some(name), Some(connection)) = (words.next(), words.next()) else {
+            continue;
+        };
+        let connection = match connection {
+            "connected" => ConnectionState::Connected,
+            "disconnected" => ConnectionState::Disconnected,
+            // "unknown connection"
+            _ => ConnectionState::Connected,
+        };
+        // Everything before the "(normal left ...)" list of what it can do.
+        let head: Vec<&str> = words.take_while(|w| !w.starts_with('(')).collect();
+        let geometry = head.iter().find_map(|w| parse_geometry(w));
+        let rotation = head.iter().find_map(|&w| {
+            [Rotation::Normal, Rotation::Left, Rotation::Inverted, Rotation::Right].into_iter().find(|&r| rotation_name(r) == w)
+        });
+
+        displays.push(Display {
+            index: displays.len() as u32 + 1,
+            name: name.to_string(),
+            uuid: None,
+            state: if geometry.is_some() { DisplayState::Enabled } else { DisplayState::Disabled },
+            connection,
+            modes: Vec::new(),
+            geometry,
+            scale: None,
+            priority: (geometry.is_some() && head.contains(&"primary")).then_some(1),
+            color: Color::default(),
+            vrr: None,
+            rotation: rotation.or(geometry.map(|_| Rotation::Normal)),
+            overscan: None,
+        });
+    }
+
+    if displays.is_empty() && !text.trim().is_empty() {
+        return Err(VitaminkError::Parse("xrandr listed no outputs".to_string()));
+    }
+    Ok(displays)
+}
+
+// "2560x1440+0+0"
+fn parse_geometry(word: &str) -> Option<Geometry> {
+    let (size, position) = word.split_once('+')?;
+    let (width, height) = size.split_once('x')?;
+    let (x, y) = position.split_once('+')?;
+    Some(Geometry { x: x.parse().ok()?, y: y.parse().ok()?, width: width.parse().ok()?, height: height.parse().ok()? })
+}
+
+// "   2560x1440     59.95 + 143.97*", one mode per rate.
+fn parse_modes(line: &str, modes: &mut Vec<Mode>) {
+    let mut words = line.split_whitespace();
+    // A custom mode's name can be anything, but cvt's and most others
+    // start with the size: "1920x1080_120.00".
+    let Some((width, height)) = words.next().and_then(|size| size.split('_').next()?.trim_end_matches('i').split_once('x')) else {
+        return;
+    };
+    let (Ok(width), Ok(height)) = (width.parse(), height.parse()) else {
+        return;
+    };
+    for word in words {
+        let flags = word.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+        let rate = &word[..word.len() - flags.len()];
+        if rate.is_empty() {
+            // The flags of the rate before, on their own.
+            if let Some(last) = modes.last_mut() {
+                last.current |= flags.contains('*');
+                last.preferred |= flags.contains('+');
+            }
+            continue;
+        }
+        let Ok(refresh) = rate.parse() else {
+            continue;
+        };
+        modes.push(Mode {
+            id: modes.len() as u32,
+            width,
+            height,
+            refresh,
+            preferred: flags.contains('+'),
+            current: flags.contains('*'),
+        });
+    }
+}
+
+// `xset q`'s "Monitor is On"; "in Standby" and "in Suspend" count as off. Without
+// that line DPMS is disabled in X, which says nothing about the screens.
+fn parse_dpms(text: &str) -> Option<DpmsState> {
+    let state = text.lines().find_map(|line| line.trim().strip_prefix("Monitor is "))?;
+    Some(match state.trim() {
+        "On" => DpmsState::On,
+        "Off" | "in Standby" | "in Suspend" => DpmsState::Off,
+        _ => DpmsState::Unknown,
+    })
+}
+
+// ---- Tests ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "\
+Screen 0: minimum 8 x 8, current 4480 x 1440, maximum 32767 x 32767
+DP-0 disconnected (normal left inverted right x axis y axis)
+DP-2 connected primary 2560x1440+0+0 (normal left inverted right x axis y axis) 597mm x 336mm
+   2560x1440     59.95 + 143.97*
+   1920x1080     60.00    59.94
+HDMI-0 connected (normal left inverted right x axis y axis)
+   3840x2160     60.00 +  30.00
+   1920x1080     60.00
+DP-4 connected 1080x1920+2560+0 left (normal left inverted right x axis y axis) 527mm x 296mm
+   1920x1080     60.00*+
+";
+
+    #[test]
+    fn test_parse() {
+        let displays = parse(SAMPLE).unwrap();
+        let names: Vec<&str> = displays.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, ["DP-0", "DP-2", "HDMI-0", "DP-4"]);
+
+        let main = &displays[1];
+        assert_eq!(main.state, DisplayState::Enabled);
+        assert_eq!(main.priority, Some(1));
+        assert_eq!(main.geometry, Some(Geometry { x: 0, y: 0, width: 2560, height: 1440 }));
+        assert_eq!(main.modes.len(), 4);
+        assert!(main.modes[0].preferred && !main.modes[0].current);
+        assert!(main.modes[1].current && main.modes[1].refresh == 143.97);
+        assert_eq!(main.modes[3].id, 3);
+
+        let dummy = &displays[2];
+        assert_eq!(dummy.state, DisplayState::Disabled);
+        assert_eq!(dummy.connection, ConnectionState::Connected);
+        assert!(dummy.modes[0].preferred && dummy.modes[0].width == 3840);
+
+        assert_eq!(displays[0].connection, ConnectionState::Disconnected);
+        assert_eq!(displays[3].rotation, Some(Rotation::Left));
+        assert!(displays[3].modes[0].current && displays[3].modes[0].preferred);
+        assert!(parse("garbage").is_err());
+    }
+
+    #[test]
+    fn test_custom_mode() {
+        let cvt = "# 1920x1080 119.88 Hz (CVT) hsync: 137.14 kHz; pclk: 369.50 MHz\nModeline \"1920x1080_120.00\"  369.50  1920 2080 2288 2656  1080 1083 1088 1160 -hsync +vsync\n";
+        let (name, timings) = parse_modeline(cvt).unwrap();
+        assert_eq!(name, "1920x1080_120.00");
+        assert_eq!(timings.len(), 11);
+        assert_eq!(parse_modeline("cvt: bad refresh"), None);
+
+        let mut modes = Vec::new();
+        parse_modes("   1920x1080_120.00 119.88", &mut modes);
+        assert_eq!((modes[0].width, modes[0].height, modes[0].refresh), (1920, 1080, 119.88));
+
+        // Switched to by cvt's name once added, on that output only.
+        let custom = CustomMode { output: "HDMI-0".into(), mode: "1920x1080@120".parse().unwrap(), reduced_blanking: false };
+        ADDED.lock().unwrap().push((custom, name));
+        assert_eq!(mode_args("HDMI-0", &modes[0]), ["--mode", "1920x1080_120.00"]);
+        assert_eq!(mode_args("DP-2", &modes[0]), ["--mode", "1920x1080", "--rate", "119.88"]);
+    }
+
+    #[test]
+    fn test_change_args() {
+        let displays = parse(SAMPLE).unwrap();
+        let changes = [OutputChange::Enable { display: &displays[2], mode: &displays[2].modes[0], scale: Some(2.0) }, OutputChange::Disable("DP-4")];
+        // Right of DP-2, with DP-4's place freed up.
+        assert_eq!(change_args(&changes, &displays).join(" "), "--output HDMI-0 --mode 3840x2160 --rate 60.00 --pos 2560x0 --output DP-4 --off");
+    }
+
+    #[test]
+    fn test_layout_args() {
+        let displays = parse(SAMPLE).unwrap();
+        let mut layout = Layout::from_displays(&displays);
+        layout.disable("DP-4");
+        assert_eq!(
+            layout_args(&layout, &displays).join(" "),
+            "--output DP-0 --off \
+             --output DP-2 --mode 2560x1440 --rate 143.97 --pos 0x0 --rotate normal --primary \
+             --output HDMI-0 --off --output DP-4 --off"
+        );
+    }
+
+    #[test]
+    fn test_parse_dpms() {
+        let q = "DPMS (Energy Star):\n  Standby: 600    Suspend: 600    Off: 600\n  DPMS is Enabled\n  Monitor is Off\n";
+        assert_eq!(parse_dpms(q), Some(DpmsState::Off));
+        assert_eq!(parse_dpms(&q.replace("is Off", "is On")), Some(DpmsState::On));
+        assert_eq!(parse_dpms(&q.replace("is Off", "is in Standby")), Some(DpmsState::Off));
+        assert_eq!(parse_dpms(&q.replace("Monitor is Off", "")), None);
+        assert_eq!(parse_dpms("DPMS (Energy Star):\n  Server does not have the DPMS Extension\n"), None);
+    }
+}
@@ -0,0 +1,327 @@
+// tests/common/mod.rs — A fake desk to run the real daemon against
+//
+// `Desk` builds a scratch directory that stands in for everything the
+// daemon touches outside itself, then runs the `vitamink` binary in it:
+//
+//   sys/class/drm/card1-DP-2/        the main display, connected, DPMS On
+//   sys/class/drm/card1-HDMI-A-1/    the dummy plug, connected, disabled
+//   bin/kscreen-doctor               prints Plasma 5.27-style `-o` output
+//                                    from outputs/ and applies enable/disable
+//                                    there and to sysfs, like KWin would
+//   bin/systemctl                    keeps units' active state in units/
+//   runtime/wayland-0                stands in for KWin's socket
+//   calls.log                        every shim invocation, one per line
+//   daemon.log                       the daemon's stderr
+//
+// The shims are `sh` scripts first on PATH; XDG_* and HOME point into the
+// scratch directory and VITAMINK_SYSFS at its sys/ (see drm.rs), and the
+// D-Bus addresses lead nowhere, so a test never reaches the real session.
+// Tests change the "hardware" (`set_dpms`), wait for the daemon to react
+// (`wait_for_state`), then look at what it ran (`changes`).
+//
+// The directory is removed when the Desk is dropped, unless the test
+// failed: then it's kept and its path printed, daemon.log and all.
+
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::{Child, Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Fast enough that a cycle takes a couple of seconds.
+pub const CONFIG: &str = r#"
+main_display = "DP-2"
+dummy_plug = "HDMI-A-1"
+dummy_mode = "1920x1080@60"
+display_backend = "kscreen"
+poll_interval = "200ms"
+max_poll_interval = "200ms"
+grace_period = "1s"
+
+[sunshine]
+ready_timeout = "0s"
+# The machine's own journal has nothing to do with the shims.
+on_error = "off"
+"#;
+
+// How long the daemon gets to react before a test gives up.
+const WAIT: Duration = Duration::from_secs(20);
+
+// Queries the shims answer; everything else changes something.
+const QUERIES: [&str; 5] =
+    ["kscreen-doctor -o", "kscreen-doctor -j", "kscreen-doctor --version", "systemctl --user is-active", "systemctl --user show"];
+
+const KSCREEN_DOCTOR: &str = r#"#!/bin/sh
+echo "kscreen-doctor $*" >> "$VITAMINK_TEST_ROOT/calls.log"
+outputs="$VITAMINK_TEST_ROOT/outputs"
+case "$1" in
+    --version) echo "kscreen-doctor 5.27.11"; exit 0 ;;
+    -j) echo "JSON output isn't supported here" >&2; exit 1 ;;
+    -o)
+        for dir in "$outputs"/*; do
+            printf "Output: $(cat "$dir/line")\n" "$(cat "$dir/state")"
+        done
+        exit 0 ;;
+    --dpms) exit 0 ;;
+esac
+for arg in "$@"; do
+    name=$(echo "$arg" | cut -d. -f2)
+    case "$arg" in
+        output.*.enable) state=enabled ;;
+        output.*.disable) state=disabled ;;
+        *) continue ;;
+    esac
+    [ -d "$outputs/$name" ] || { echo "no output $name" >&2; exit 1; }
+    echo "$state" > "$outputs/$name/state"
+    for connector in "$VITAMINK_SYSFS"/class/drm/card*-"$name"; do
+        echo "$state" > "$connector/enabled"
+    done
+done
+"#;
+
+const SYSTEMCTL: &str = r#"#!/bin/sh
+echo "systemctl $*" >> "$VITAMINK_TEST_ROOT/calls.log"
+units="$VITAMINK_TEST_ROOT/units"
+[ "$1" = --user ] && shift
+case "$1" in
+    start|restart) touch "$units/$2" ;;
+    stop) rm -f "$units/$2" ;;
+    is-active) [ -e "$units/$3" ] ;;
+    show) echo loaded ;;
+esac
+"#;
+
+pub struct Desk {
+    pub root: PathBuf,
+    daemon: Option<Child>,
+}
+
+impl Desk {
+    // An empty desk: main display on, dummy plug connected but off, no
+    // units running. `name` keeps parallel tests apart.
+    pub fn new(name: &str, config: &str) -> Self {
+        let root = env::temp_dir().join(format!("vitamink-test-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for dir in ["bin", "units", "config/vitamink", "runtime", "state", "data"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        // The compositor's socket, which the daemon waits for at startup.
+        fs::write(root.join("runtime/wayland-0"), "").unwrap();
+        let desk = Self { root, daemon: None };
+
+        desk.add_output(1, "DP-2", "On", "1 DisplayPort Modes: 1:3840x2160@60*! 2:2560x1440@144 Geometry: 0,0 3840x2160 Scale: 1.5");
+        desk.add_output(2, "HDMI-A-1", "Off", "2 HDMI Modes: 1:1920x1080@60! 2:3840x2160@60 Geometry: 3840,0 1920x1080 Scale: 1");
+        desk.set_enabled("DP-2", true);
+        desk.set_enabled("HDMI-A-1", false);
+
+        desk.add_shim("kscreen-doctor", KSCREEN_DOCTOR);
+        desk.add_shim("systemctl", SYSTEMCTL);
+        fs::write(desk.root.join("calls.log"), "").unwrap();
+        fs::write(desk.root.join("config/vitamink/config.toml"), config).unwrap();
+        desk
+    }
+
+    // A connected connector on card1 and the matching kscreen-doctor
+    // output. `rest` is the `-o` line after the state and connection.
+    fn add_output(&self, index: u32, name: &str, dpms: &str, rest: &str) {
+        let connector = self.connector(name);
+        fs::create_dir_all(&connector).unwrap();
+        fs::write(connector.join("status"), "connected\n").unwrap();
+        fs::write(connector.join("dpms"), format!("{dpms}\n")).unwrap();
+
+        let output = self.root.join("outputs").join(name);
+        fs::create_dir_all(&output).unwrap();
+        fs::write(output.join("line"), format!("{index} {name} %s connected priority {rest}")).unwrap();
+    }
+
+    fn add_shim(&self, program: &str, script: &str) {
+        let path = self.root.join("bin").join(program);
+        fs::write(&path, script).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    fn connector(&self, name: &str) -> PathBuf {
+        self.root.join("sys/class/drm").join(format!("card1-{name}"))
+    }
+
+    // What both KWin and the kernel would say after a mode set.
+    fn set_enabled(&self, name: &str, enabled: bool) {
+        let state = if enabled { "enabled" } else { "disabled" };
+        fs::write(self.connector(name).join("enabled"), format!("{state}\n")).unwrap();
+        fs::write(self.root.join("outputs").join(name).join("state"), state).unwrap();
+    }
+
+    // The monitor going to sleep (false) or waking up.
+    pub fn set_dpms(&self, name: &str, on: bool) {
+        fs::write(self.connector(name).join("dpms"), if on { "On\n" } else { "Off\n" }).unwrap();
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        fs::read_to_string(self.connector(name).join("enabled")).unwrap().trim() == "enabled"
+    }
+
+    pub fn is_unit_active(&self, unit: &str) -> bool {
+        self.root.join("units").join(unit).exists()
+    }
+
+    // Runs `vitamink daemon` with `args` in the scratch directory.
+    pub fn start(&mut self, args: &[&str]) {
+        let log = fs::File::create(self.root.join("daemon.log")).unwrap();
+        let child = self
+            .vitamink("daemon", args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(log)
+            .spawn()
+            .unwrap();
+        self.daemon = Some(child);
+    }
+
+    // Runs another command, e.g. `vitamink reconcile`, to the end; its
+    // log goes to daemon.log too.
+    pub fn run(&self, command: &str, args: &[&str]) -> Output {
+        let log = fs::File::create(self.root.join("daemon.log")).unwrap();
+        self.vitamink(command, args).stdin(Stdio::null()).stderr(log).output().unwrap()
+    }
+
+    fn vitamink(&self, command: &str, args: &[&str]) -> Command {
+        let path = env::join_paths(std::iter::once(self.root.join("bin")).chain(env::split_paths(&env::var_os("PATH").unwrap_or_default())))
+            .unwrap();
+        let no_bus = format!("unix:path={}", self.root.join("no-bus").display());
+        let mut vitamink = Command::new(env!("CARGO_BIN_EXE_vitamink"));
+        vitamink
+            .arg(command)
+            .args(args)
+            .args(["--log-level", "debug"])
+            .env("PATH", path)
+            .env("HOME", &self.root)
+            .env("XDG_CONFIG_HOME", self.root.join("config"))
+            .env("XDG_RUNTIME_DIR", self.root.join("runtime"))
+            .env("XDG_STATE_HOME", self.root.join("state"))
+            .env("XDG_DATA_HOME", self.root.join("data"))
+            .env("XDG_CURRENT_DESKTOP", "KDE")
+            .env("VITAMINK_SYSFS", self.root.join("sys"))
+            .env("VITAMINK_TEST_ROOT", &self.root)
+            .env("DBUS_SESSION_BUS_ADDRESS", &no_bus)
+            .env("DBUS_SYSTEM_BUS_ADDRESS", &no_bus)
+            .env_remove("WAYLAND_DISPLAY")
+            .env_remove("DISPLAY")
+            .env_remove("NOTIFY_SOCKET")
+            .env_remove("WATCHDOG_USEC")
+            .env_remove("JOURNAL_STREAM");
+        vitamink
+    }
+
+    // Sends the running daemon `signal`, e.g. libc::SIGUSR1.
+    pub fn signal(&self, signal: libc::c_int) {
+        let child = self.daemon.as_ref().expect("the daemon is running");
+        unsafe { libc::kill(child.id() as libc::pid_t, signal) };
+    }
+
+    // SIGTERM, then waits for the daemon to clean up and exit.
+    pub fn stop(&mut self) {
+        let Some(mut child) = self.daemon.take() else {
+            return;
+        };
+        unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM) };
+        let start = Instant::now();
+        while child.try_wait().unwrap().is_none() {
+            if start.elapsed() > WAIT {
+                let _ = child.kill();
+                panic!("the daemon didn't exit after SIGTERM\n{}", self.daemon_log());
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    // The state in status.json, once it's been written.
+    pub fn state(&self) -> Option<String> {
+        let text = fs::read_to_string(self.root.join("runtime/vitamink/status.json")).ok()?;
+        let status: serde_json::Value = serde_json::from_str(&text).ok()?;
+        status["state"].as_str().map(str::to_string)
+    }
+
+    // Waits for the daemon to report `state` with nothing pending.
+    pub fn wait_for_state(&self, state: &str) {
+        let start = Instant::now();
+        while start.elapsed() < WAIT {
+            if self.state().as_deref() == Some(state) && self.settled() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        panic!("still {:?} after {WAIT:?}, expected {state}\n{}", self.state(), self.daemon_log());
+    }
+
+    // Waits for `condition`, e.g. a unit stopping on its own time.
+    pub fn wait_until(&self, what: &str, condition: impl Fn(&Desk) -> bool) {
+        let start = Instant::now();
+        while start.elapsed() < WAIT {
+            if condition(self) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        panic!("{what}: not after {WAIT:?}\n{}", self.daemon_log());
+    }
+
+    fn settled(&self) -> bool {
+        let text = fs::read_to_string(self.root.join("runtime/vitamink/status.json")).unwrap_or_default();
+        serde_json::from_str::<serde_json::Value>(&text).is_ok_and(|status| status["pending"].is_null())
+    }
+
+    // One request on the control socket (see control.rs), and its answer.
+    pub fn control(&self, request: &str) -> serde_json::Value {
+        let mut stream = UnixStream::connect(self.root.join("runtime/vitamink.sock")).unwrap();
+        writeln!(stream, "{request}").unwrap();
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+
+    // Every shim call so far, e.g. "systemctl --user start sunshine".
+    pub fn calls(&self) -> Vec<String> {
+        fs::read_to_string(self.root.join("calls.log")).unwrap().lines().map(str::to_string).collect()
+    }
+
+    // The calls that change something, in order.
+    pub fn changes(&self) -> Vec<String> {
+        self.calls().into_iter().filter(|call| !QUERIES.iter().any(|query| call.starts_with(query))).collect()
+    }
+
+    // Forgets the calls so far, so the next `changes` is one step's worth.
+    pub fn clear_calls(&self) {
+        fs::write(self.root.join("calls.log"), "").unwrap();
+    }
+
+    pub fn daemon_log(&self) -> String {
+        let log = fs::read_to_string(self.root.join("daemon.log")).unwrap_or_default();
+        format!("---- daemon.log ({}) ----\n{log}", self.root.display())
+    }
+}
+
+impl Drop for Desk {
+    fn drop(&mut self) {
+        if let Some(mut child) = self.daemon.take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        if thread::panicking() {
+            eprintln!("Keeping {} for a look", self.root.display());
+        } else {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+}
+
+// Whether `earlier` was called before `later` (each at least once).
+pub fn in_order(calls: &[String], earlier: &str, later: &str) -> bool {
+    let position = |wanted: &str| calls.iter().position(|call| call == wanted);
+    matches!((position(earlier), position(later)), (Some(a), Some(b)) if a < b)
+}
@@ -0,0 +1,245 @@
+// tests/cycle.rs — The daemon, end to end, through Away and back
+//
+// Each test runs the real binary against a fake desk (see common/mod.rs)
+// and checks what it ran as the main display sleeps and wakes.
+
+mod common;
+
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use common::{CONFIG, Desk, in_order};
+
+const ENABLE_DUMMY: &str = "kscreen-doctor output.HDMI-A-1.enable output.HDMI-A-1.mode.1920x1080@60";
+const START_SUNSHINE: &str = "systemctl --user start sunshine";
+const STOP_SUNSHINE: &str = "systemctl --user stop sunshine";
+const DISABLE_DUMMY: &str = "kscreen-doctor output.HDMI-A-1.disable";
+const CLIENT_MODE: &str = "kscreen-doctor output.HDMI-A-1.enable output.HDMI-A-1.mode.3840x2160@60";
+
+#[test]
+fn test_away_and_back() {
+    let mut desk = Desk::new("cycle", CONFIG);
+    desk.start(&[]);
+    desk.wait_for_state("AtDesk");
+    assert!(!desk.is_unit_active("sunshine"));
+
+    // Twice, so whatever the first cycle leaves behind gets used.
+    for _ in 0..2 {
+        desk.clear_calls();
+        desk.set_dpms("DP-2", false);
+        desk.wait_for_state("Away");
+        let changes = desk.changes();
+        assert!(in_order(&changes, ENABLE_DUMMY, START_SUNSHINE), "{changes:#?}");
+        assert!(desk.is_enabled("HDMI-A-1"));
+        assert!(desk.is_unit_active("sunshine"));
+
+        desk.clear_calls();
+        desk.set_dpms("DP-2", true);
+        desk.wait_for_state("AtDesk");
+        let changes = desk.changes();
+        assert!(in_order(&changes, STOP_SUNSHINE, DISABLE_DUMMY), "{changes:#?}");
+        assert!(!changes.contains(&START_SUNSHINE.to_string()), "{changes:#?}");
+        assert!(!desk.is_enabled("HDMI-A-1"));
+        assert!(!desk.is_unit_active("sunshine"));
+    }
+    desk.stop();
+}
+
+#[test]
+fn test_restart_while_away() {
+    let mut desk = Desk::new("restart", CONFIG);
+    desk.start(&[]);
+    desk.wait_for_state("AtDesk");
+    desk.set_dpms("DP-2", false);
+    desk.wait_for_state("Away");
+
+    // A restart (an upgrade, say) leaves the stream alone...
+    desk.clear_calls();
+    desk.stop();
+    assert_eq!(desk.changes(), Vec::<String>::new());
+    assert!(desk.is_unit_active("sunshine"));
+
+    // ...and the next daemon picks up from Away, so coming back to the
+    // desk still undoes it.
+    desk.set_dpms("DP-2", true);
+    desk.start(&[]);
+    desk.wait_for_state("AtDesk");
+    let changes = desk.changes();
+    assert!(in_order(&changes, STOP_SUNSHINE, DISABLE_DUMMY), "{changes:#?}");
+    assert!(!desk.is_enabled("HDMI-A-1"));
+    desk.stop();
+}
+
+#[test]
+fn test_forced_away() {
+    let mut desk = Desk::new("forced", CONFIG);
+    desk.start(&[]);
+    desk.wait_for_state("AtDesk");
+
+    // With the main display still on.
+    assert_eq!(desk.control(r#"{"command":"force-state","state":"away"}"#)["ok"], true);
+    desk.wait_for_state("Away");
+    assert!(desk.is_unit_active("sunshine"));
+
+    assert_eq!(desk.control(r#"{"command":"force-state","state":"desk"}"#)["ok"], true);
+    desk.wait_for_state("AtDesk");
+    assert!(!desk.is_unit_active("sunshine"));
+    desk.stop();
+}
+
+#[test]
+fn test_signals() {
+    let mut desk = Desk::new("signals", CONFIG);
+    desk.start(&[]);
+    desk.wait_for_state("AtDesk");
+
+    desk.signal(libc::SIGUSR1);
+    desk.wait_for_state("Away");
+    assert!(desk.is_unit_active("sunshine"));
+
+    desk.signal(libc::SIGUSR2);
+    desk.wait_for_state("AtDesk");
+    assert!(!desk.is_unit_active("sunshine"));
+    desk.stop();
+}
+
+#[test]
+fn test_dry_run() {
+    let mut desk = Desk::new("dry-run", CONFIG);
+    desk.start(&["--dry-run"]);
+    desk.wait_for_state("AtDesk");
+    desk.set_dpms("DP-2", false);
+    desk.wait_for_state("Away");
+    desk.set_dpms("DP-2", true);
+    desk.wait_for_state("AtDesk");
+    desk.stop();
+
+    // Only queries: every change was logged instead.
+    assert_eq!(desk.changes(), Vec::<String>::new());
+    assert!(desk.daemon_log().contains(&format!("[dry-run] {START_SUNSHINE}")), "{}", desk.daemon_log());
+    assert!(!desk.is_enabled("HDMI-A-1"));
+}
+
+#[test]
+fn test_drain_at_desk() {
+    use std::net::{TcpListener, TcpStream};
+
+    // A "client" streaming from a port of our own, standing in for Sunshine's.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let config = CONFIG.replace(
+        "[sunshine]\n",
+        &format!("[sunshine]\nblock_desk_while_streaming = false\nsession_ports = [{port}]\ndrain_timeout = \"1m\"\n"),
+    );
+    let mut desk = Desk::new("drain", &config);
+    desk.start(&[]);
+    desk.wait_for_state("AtDesk");
+    desk.set_dpms("DP-2", false);
+    desk.wait_for_state("Away");
+
+    let client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    let (server, _) = listener.accept().unwrap();
+    desk.clear_calls();
+    desk.set_dpms("DP-2", true);
+    desk.wait_for_state("AtDesk");
+
+    // The desk is back, the stream carries on...
+    assert!(desk.changes().contains(&DISABLE_DUMMY.to_string()), "{:#?}", desk.changes());
+    assert!(!desk.changes().contains(&STOP_SUNSHINE.to_string()), "{:#?}", desk.changes());
+    assert!(desk.is_unit_active("sunshine"));
+
+    // ...until it ends.
+    drop((client, server));
+    desk.wait_until("sunshine stopped after the stream", |desk| !desk.is_unit_active("sunshine"));
+    desk.stop();
+}
+
+#[test]
+fn test_reconcile() {
+    let desk = Desk::new("reconcile", CONFIG);
+    let reconcile = || {
+        let output = desk.run("reconcile", &[]);
+        assert!(output.status.success(), "{}", desk.daemon_log());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    // At the desk already: nothing to do.
+    assert_eq!(reconcile(), "AtDesk\n");
+    assert_eq!(desk.changes(), Vec::<String>::new());
+
+    // No grace period to wait out.
+    desk.set_dpms("DP-2", false);
+    assert_eq!(reconcile(), "Away\n");
+    let changes = desk.changes();
+    assert!(in_order(&changes, ENABLE_DUMMY, START_SUNSHINE), "{changes:#?}");
+    assert!(desk.is_unit_active("sunshine"));
+
+    // Still Away: the outputs are set again, but nothing is started twice.
+    desk.clear_calls();
+    assert_eq!(reconcile(), "Away\n");
+    let changes = desk.changes();
+    assert!(!changes.contains(&START_SUNSHINE.to_string()), "{changes:#?}");
+    assert!(desk.is_unit_active("sunshine"));
+
+    desk.set_dpms("DP-2", true);
+    assert_eq!(reconcile(), "AtDesk\n");
+    let changes = desk.changes();
+    assert!(in_order(&changes, STOP_SUNSHINE, DISABLE_DUMMY), "{changes:#?}");
+    assert!(!desk.is_enabled("HDMI-A-1"));
+    assert!(!desk.is_unit_active("sunshine"));
+}
+
+#[test]
+fn test_reconcile_busy() {
+    // No disk has this much free.
+    let desk = Desk::new("busy", &format!("{CONFIG}\n[load]\nmin_free_gb = 1e12\ndisk_path = \"/\"\n"));
+    desk.set_dpms("DP-2", false);
+
+    let output = desk.run("reconcile", &[]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "AtDesk\n");
+    assert!(desk.daemon_log().contains("the machine is busy"), "{}", desk.daemon_log());
+    assert!(!desk.is_unit_active("sunshine"));
+
+    let output = desk.run("reconcile", &["--force"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Away\n");
+    assert!(desk.is_unit_active("sunshine"));
+}
+
+#[test]
+fn test_client_mode_restored() {
+    for matching in [true, false] {
+        // Sunshine's stream, played by a connection to a port of our own.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let config = format!("{CONFIG}match_client_mode = {matching}\nsession_ports = [{port}]\n");
+        let mut desk = Desk::new(&format!("client-mode-{matching}"), &config);
+        desk.start(&[]);
+        desk.wait_for_state("AtDesk");
+        desk.set_dpms("DP-2", false);
+        desk.wait_for_state("Away");
+
+        // A client connects, and Sunshine's prep command passes on its mode.
+        desk.clear_calls();
+        let client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        assert_eq!(desk.control(r#"{"command":"client-mode","mode":"3840x2160@60"}"#)["ok"], true);
+        if matching {
+            desk.wait_until("the client's mode", |d| d.changes().contains(&CLIENT_MODE.to_string()));
+            desk.wait_until("the stream", |d| d.daemon_log().contains("Stream started"));
+        } else {
+            thread::sleep(Duration::from_secs(1));
+        }
+
+        // The last client hangs up.
+        drop((client, server));
+        if matching {
+            desk.wait_until("the dummy plug's own mode", |d| d.changes().contains(&ENABLE_DUMMY.to_string()));
+            assert!(in_order(&desk.changes(), CLIENT_MODE, ENABLE_DUMMY), "{:#?}", desk.changes());
+        } else {
+            thread::sleep(Duration::from_secs(1));
+            assert_eq!(desk.changes(), Vec::<String>::new(), "{}", desk.daemon_log());
+        }
+        desk.stop();
+    }
+}